@@ -0,0 +1,408 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Counting which components and resources each system uses, for tuning schedules.
+//!
+//! Systems that write the same component can't share a stage of a schedule (see the
+//! [`schedule`](../schedule/index.html) module), so a component that half the systems write is a
+//! bottleneck, and often a sign that it should be split. With the `access-stats` feature, the
+//! world counts, for every system, how many times it has read and written each component and
+//! resource, in a side table, [`AccessStats`](struct.AccessStats.html).
+//! [`AccessStats::matrix`](struct.AccessStats.html#method.matrix) gives the counts as a matrix of
+//! systems by types, and [`AccessMatrix::contention`](struct.AccessMatrix.html#method.contention)
+//! ranks the types by how many pairs of systems they stop from running together.
+//!
+//! A use is counted each time a system's dependencies are fetched, which is once per run, plus
+//! once for its `setup`. Dependencies fetched outside a system, with a world's `fetch` method,
+//! aren't counted. A `RestrictedWriteComponent` counts as a write. Writes that were never
+//! actually borrowed mutably are counted too, as the difference between `AccessCount::writes`
+//! and `AccessCount::mutations`; the [`lint`](../lint/index.html) module reports them.
+//!
+//! Without the feature, `AccessStats` is zero-sized, nothing is counted, and the matrix is always
+//! empty.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Position(i32);
+//! #[derive(Default)]
+//! pub struct Velocity(i32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!             velocities: BasicVecStorage<Velocity>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! struct Move;
+//! impl<'a> System<'a> for Move {
+//!     type Dependencies = (ReadComponent<'a, Velocity>, WriteComponent<'a, Position>);
+//!     fn run(&'a mut self, (vel, mut pos): Self::Dependencies) {
+//!         (&vel, &mut pos).for_each(|_, (v, p)| p.0 += v.0);
+//!     }
+//! }
+//!
+//! struct Report;
+//! impl<'a> System<'a> for Report {
+//!     type Dependencies = (ReadComponent<'a, Position>,);
+//!     fn run(&'a mut self, _: Self::Dependencies) {}
+//! }
+//!
+//! let mut w = World::default();
+//! let mut dispatcher = Dispatcher::new().with(&mut w, Move).with(&mut w, Report);
+//! for _ in 0..3 {
+//!     dispatcher.run(&mut w).unwrap();
+//! }
+//!
+//! let matrix = w.access_stats().matrix();
+//! if cfg!(feature = "access-stats") {
+//!     let position = AccessedType::component::<Position>();
+//!     // Three runs, plus `setup`.
+//!     assert_eq!(matrix.count::<Move>(position).writes, 4);
+//!     assert_eq!(matrix.count::<Report>(position).reads, 4);
+//!     assert_eq!(matrix.contention(), vec![(position, 1)]);
+//!     println!("{}", matrix);
+//! } else {
+//!     assert!(matrix.systems().is_empty());
+//! }
+//! ```
+
+use alloc::vec::Vec;
+#[cfg(feature = "access-stats")]
+use core::cell::Cell;
+use core::fmt;
+
+#[cfg(feature = "access-stats")]
+use crate::cell::WorldCell;
+use crate::Usage;
+
+/// A component or resource type, identified by its name. The columns of an
+/// [`AccessMatrix`](struct.AccessMatrix.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AccessedType {
+    /// The component type with the given name.
+    Component(&'static str),
+    /// The resource type with the given name.
+    Resource(&'static str),
+}
+
+impl AccessedType {
+    /// The component type `T`.
+    pub fn component<T: ?Sized>() -> Self {
+        AccessedType::Component(core::any::type_name::<T>())
+    }
+
+    /// The resource type `T`.
+    pub fn resource<T: ?Sized>() -> Self {
+        AccessedType::Resource(core::any::type_name::<T>())
+    }
+
+    /// The type's name.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            AccessedType::Component(name) | AccessedType::Resource(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for AccessedType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessedType::Component(name) => write!(f, "component `{}`", name),
+            AccessedType::Resource(name) => write!(f, "resource `{}`", name),
+        }
+    }
+}
+
+/// How many times a system has read and written a component or resource.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AccessCount {
+    /// The number of times it was only read.
+    pub reads: u64,
+    /// The number of times it was fetched for writing.
+    pub writes: u64,
+    /// The number of those times it was actually borrowed mutably, e.g. by a join over
+    /// `&mut` the component, or by assigning to the resource.
+    pub mutations: u64,
+}
+
+impl AccessCount {
+    /// Reads and writes together.
+    pub fn total(&self) -> u64 {
+        self.reads + self.writes
+    }
+}
+
+// Everything counted for one system.
+#[cfg_attr(not(feature = "access-stats"), allow(dead_code))]
+#[derive(Clone, Debug)]
+struct SystemRow {
+    name: &'static str,
+    fetches: u64,
+    counts: Vec<(AccessedType, AccessCount)>,
+}
+
+/// The world's record of which components and resources each system uses. See the
+/// [module-level documentation](index.html).
+#[derive(Clone, Debug, Default)]
+pub struct AccessStats {
+    // The row of the system whose dependencies are being fetched, if any.
+    #[cfg(feature = "access-stats")]
+    current: Cell<Option<usize>>,
+    #[cfg(feature = "access-stats")]
+    rows: WorldCell<Vec<SystemRow>>,
+}
+
+impl AccessStats {
+    // Start counting uses for system type `S`, returning the system that was being counted
+    // before, which should be restored with `leave`. `WorldInterface::fetch_for` does this.
+    #[inline]
+    #[cfg_attr(
+        not(feature = "access-stats"),
+        allow(clippy::extra_unused_type_parameters)
+    )]
+    pub(crate) fn enter<S: ?Sized>(&self) -> Option<usize> {
+        #[cfg(feature = "access-stats")]
+        {
+            let mut rows = self.rows.borrow_mut();
+            // Systems are told apart by name rather than `TypeId`, so that they don't have to be
+            // `'static`.
+            let name = core::any::type_name::<S>();
+            let row = match rows.iter().position(|r| r.name == name) {
+                Some(row) => row,
+                None => {
+                    rows.push(SystemRow {
+                        name,
+                        fetches: 0,
+                        counts: Vec::new(),
+                    });
+                    rows.len() - 1
+                }
+            };
+            rows[row].fetches += 1;
+            self.current.replace(Some(row))
+        }
+        #[cfg(not(feature = "access-stats"))]
+        None
+    }
+
+    // Undo a call to `enter`.
+    #[inline]
+    pub(crate) fn leave(&self, _previous: Option<usize>) {
+        #[cfg(feature = "access-stats")]
+        self.current.set(_previous);
+    }
+
+    // Count a use of `accessed` by the system being counted, if any. Write views keep the
+    // returned probe, to count the first time they're borrowed mutably.
+    #[inline]
+    pub(crate) fn record(&self, _accessed: AccessedType, _usage: Usage) -> MutationProbe<'_> {
+        #[cfg(feature = "access-stats")]
+        if let Some(row) = self.current.get() {
+            let counts = &mut self.rows.borrow_mut()[row].counts;
+            let i = match counts.iter().position(|(t, _)| *t == _accessed) {
+                Some(i) => i,
+                None => {
+                    counts.push((_accessed, AccessCount::default()));
+                    counts.len() - 1
+                }
+            };
+            if _usage.is_write() {
+                counts[i].1.writes += 1;
+            } else {
+                counts[i].1.reads += 1;
+            }
+            return MutationProbe {
+                target: Some((self, row, i)),
+                done: Cell::new(false),
+            };
+        }
+        MutationProbe::default()
+    }
+
+    /// Everything counted so far, as a matrix of systems by types.
+    pub fn matrix(&self) -> AccessMatrix {
+        #[cfg(feature = "access-stats")]
+        {
+            AccessMatrix::new(&self.rows.borrow())
+        }
+        #[cfg(not(feature = "access-stats"))]
+        AccessMatrix::new(&[])
+    }
+
+    /// Forget everything counted so far, e.g. to leave out a game's loading screen.
+    pub fn clear(&self) {
+        #[cfg(feature = "access-stats")]
+        self.rows.borrow_mut().clear();
+    }
+}
+
+// Kept by write views to count, in `AccessStats`, whether they were borrowed mutably.
+#[derive(Default)]
+pub(crate) struct MutationProbe<'a> {
+    // The stats, and the system's row and the type's entry in them.
+    #[cfg(feature = "access-stats")]
+    target: Option<(&'a AccessStats, usize, usize)>,
+    #[cfg(feature = "access-stats")]
+    done: Cell<bool>,
+    #[cfg(not(feature = "access-stats"))]
+    target: core::marker::PhantomData<&'a AccessStats>,
+}
+
+impl MutationProbe<'_> {
+    // Count a mutation, the first time this is called.
+    #[inline]
+    pub(crate) fn touch(&self) {
+        #[cfg(feature = "access-stats")]
+        if let Some((stats, row, i)) = self.target {
+            if !self.done.replace(true) {
+                // The stats may have been cleared since the view was fetched.
+                if let Some(r) = stats.rows.borrow_mut().get_mut(row) {
+                    r.counts[i].1.mutations += 1;
+                }
+            }
+        }
+    }
+}
+
+/// The number of times each system has read and written each component and resource, returned by
+/// `AccessStats::matrix`. Systems are in the order they first ran, and types are sorted, with
+/// components before resources.
+///
+/// Displays as a list of the systems, each followed by the types it used, e.g.
+///
+/// ```text
+/// game::Move (4 runs)
+///     component `game::Position`: 4 writes
+///     component `game::Velocity`: 4 reads
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessMatrix {
+    systems: Vec<&'static str>,
+    fetches: Vec<u64>,
+    columns: Vec<AccessedType>,
+    // Row-major: `cells[row * columns.len() + column]`.
+    cells: Vec<AccessCount>,
+}
+
+impl AccessMatrix {
+    fn new(rows: &[SystemRow]) -> Self {
+        let mut columns: Vec<AccessedType> = rows
+            .iter()
+            .flat_map(|r| r.counts.iter().map(|(t, _)| *t))
+            .collect();
+        columns.sort_unstable();
+        columns.dedup();
+        let mut cells = vec![AccessCount::default(); rows.len() * columns.len()];
+        for (row, r) in rows.iter().enumerate() {
+            for (t, count) in &r.counts {
+                if let Ok(column) = columns.binary_search(t) {
+                    cells[row * columns.len() + column] = *count;
+                }
+            }
+        }
+        AccessMatrix {
+            systems: rows.iter().map(|r| r.name).collect(),
+            fetches: rows.iter().map(|r| r.fetches).collect(),
+            columns,
+            cells,
+        }
+    }
+
+    /// The names of the systems, one per row.
+    pub fn systems(&self) -> &[&'static str] {
+        &self.systems
+    }
+
+    /// The types, one per column.
+    pub fn columns(&self) -> &[AccessedType] {
+        &self.columns
+    }
+
+    /// The number of times the system in `row` had its dependencies fetched.
+    pub fn runs(&self, row: usize) -> u64 {
+        self.fetches[row]
+    }
+
+    /// The counts for the system in `row` and the type in `column`.
+    pub fn get(&self, row: usize, column: usize) -> AccessCount {
+        self.cells[row * self.columns.len() + column]
+    }
+
+    /// The counts for system type `S` and `accessed`, or zeros if either hasn't been seen.
+    pub fn count<S: ?Sized>(&self, accessed: AccessedType) -> AccessCount {
+        let system = core::any::type_name::<S>();
+        match (
+            self.systems.iter().position(|&s| s == system),
+            self.columns.binary_search(&accessed),
+        ) {
+            (Some(row), Ok(column)) => self.get(row, column),
+            _ => AccessCount::default(),
+        }
+    }
+
+    /// The types that keep systems from running at the same time, each with the number of pairs
+    /// of systems that use it where at least one of the pair writes it, most contended first.
+    /// Types that no system writes are left out.
+    pub fn contention(&self) -> Vec<(AccessedType, usize)> {
+        let mut contended = Vec::new();
+        for (column, &accessed) in self.columns.iter().enumerate() {
+            let (mut writers, mut users) = (0usize, 0usize);
+            for row in 0..self.systems.len() {
+                let count = self.get(row, column);
+                if count.writes > 0 {
+                    writers += 1;
+                }
+                if count.total() > 0 {
+                    users += 1;
+                }
+            }
+            // Pairs of writers, plus pairs of a writer and a reader.
+            let pairs = writers * writers.saturating_sub(1) / 2 + writers * (users - writers);
+            if pairs > 0 {
+                contended.push((accessed, pairs));
+            }
+        }
+        contended.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        contended
+    }
+}
+
+impl fmt::Display for AccessMatrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (row, system) in self.systems.iter().enumerate() {
+            writeln!(f, "{} ({} runs)", system, self.fetches[row])?;
+            for (column, accessed) in self.columns.iter().enumerate() {
+                let count = self.get(row, column);
+                match (count.reads, count.writes) {
+                    (0, 0) => continue,
+                    (reads, 0) => writeln!(f, "    {}: {} reads", accessed, reads)?,
+                    (0, writes) => writeln!(f, "    {}: {} writes", accessed, writes)?,
+                    (reads, writes) => {
+                        writeln!(f, "    {}: {} reads, {} writes", accessed, reads, writes)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
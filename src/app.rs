@@ -0,0 +1,203 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Plugins: reusable bundles of systems, schedules and resource values.
+//!
+//! An [`EcsApp`](struct.EcsApp.html) is a world together with the `Dispatcher` that runs against
+//! it. A [`Plugin`](trait.Plugin.html) adds whatever a gameplay module needs to one (lighting,
+//! inventory, AI, ...) in a single call, so the game doesn't need to know what's inside it.
+//!
+//! Component and resource types still have to be declared in the world's `define_world!`, since
+//! a world's layout is fixed when it's defined. A plugin states which ones it needs in its bounds
+//! on the world type, so leaving one out is a compile error naming the missing type.
+//!
+//! Plugins can add other plugins they depend on. Each plugin type is only built once per app, so
+//! two plugins can share a dependency without its systems being added twice.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! // In the lighting crate:
+//! #[derive(Default)]
+//! pub struct Light(pub u32);
+//! #[derive(Default)]
+//! pub struct Ambient(pub u32);
+//!
+//! pub struct Falloff;
+//! impl<'a> System<'a> for Falloff {
+//!     type Dependencies = (ReadResource<'a, Ambient>, WriteComponent<'a, Light>);
+//!     fn run(&'a mut self, (ambient, mut lights): Self::Dependencies) {
+//!         (&mut lights,).for_each(|_, (l,)| l.0 = l.0.saturating_sub(1).max(ambient.0));
+//!     }
+//! }
+//!
+//! pub struct Lighting {
+//!     pub ambient: u32,
+//! }
+//! impl<W> Plugin<W> for Lighting
+//! where
+//!     W: GetResource<Ambient> + for<'a> WorldInterface<'a>,
+//!     Falloff: RunSystem<W, Input = ()>,
+//!     <Falloff as RunSystem<W>>::Output: IntoSystemResult,
+//! {
+//!     fn build(&self, app: &mut EcsApp<W>) {
+//!         app.insert_resource(Ambient(self.ambient));
+//!         app.add_system(Falloff).label("lighting");
+//!     }
+//! }
+//!
+//! // In the game:
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             lights: BasicVecStorage<Light>,
+//!         }
+//!         resources {
+//!             ambient: Ambient,
+//!         }
+//!     }
+//! );
+//!
+//! let mut app = EcsApp::new(World::default());
+//! app.add_plugin(Lighting { ambient: 2 });
+//! let e = app.world_mut().new_entity().with(Light(5)).build();
+//! app.run().unwrap();
+//! app.run().unwrap();
+//! app.run().unwrap();
+//! assert_eq!(app.world().component::<Light>().get(e).unwrap().0, 2);
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::any::TypeId;
+use core::time::Duration;
+
+use crate::*;
+
+/// A bundle of systems, schedules and resource values that can be added to an `EcsApp` in one go.
+/// See the [module-level documentation](index.html).
+pub trait Plugin<W> {
+    /// Add the plugin's systems, schedules and resource values to `app`.
+    fn build(&self, app: &mut EcsApp<W>);
+}
+
+/// A world and the `Dispatcher` that runs its systems, assembled from plugins.
+pub struct EcsApp<W> {
+    world: W,
+    dispatcher: Dispatcher<W>,
+    // The plugin types that have been built.
+    plugins: Vec<TypeId>,
+}
+
+impl<W: Default> Default for EcsApp<W> {
+    fn default() -> Self {
+        EcsApp::new(W::default())
+    }
+}
+
+impl<W> EcsApp<W> {
+    /// Create an app for `world`, with no systems.
+    pub fn new(world: W) -> Self {
+        EcsApp {
+            world,
+            dispatcher: Dispatcher::new(),
+            plugins: Vec::new(),
+        }
+    }
+
+    /// Build `plugin` into the app, unless a plugin of the same type already has been.
+    pub fn add_plugin<P: Plugin<W> + 'static>(&mut self, plugin: P) -> &mut Self {
+        let id = TypeId::of::<P>();
+        if !self.plugins.contains(&id) {
+            self.plugins.push(id);
+            plugin.build(self);
+        }
+        self
+    }
+
+    /// Whether a plugin of type `P` has been added.
+    pub fn has_plugin<P: 'static>(&self) -> bool {
+        self.plugins.contains(&TypeId::of::<P>())
+    }
+
+    /// Add a system to the dispatcher, setting it up on the app's world, and returning a handle
+    /// that can be used to configure it. A schedule declared with `define_schedule!` can be added
+    /// the same way, and runs as a unit.
+    pub fn add_system<S>(&mut self, system: S) -> &mut ScheduledSystem<W>
+    where
+        S: RunSystem<W, Input = ()> + 'static,
+        S::Output: IntoSystemResult,
+        W: for<'a> WorldInterface<'a>,
+    {
+        self.dispatcher.add(&mut self.world, system)
+    }
+
+    /// Get the ordering constraints for every system with the given label, for modification.
+    pub fn configure_set<L: Into<String>>(&mut self, label: L) -> &mut SetConfig {
+        self.dispatcher.configure_set(label)
+    }
+
+    /// Set the resource of type `T`.
+    pub fn insert_resource<T>(&mut self, value: T) -> &mut Self
+    where
+        W: GetResource<T>,
+    {
+        self.world.set(value);
+        self
+    }
+
+    /// The world.
+    pub fn world(&self) -> &W {
+        &self.world
+    }
+
+    /// The world, mutably.
+    pub fn world_mut(&mut self) -> &mut W {
+        &mut self.world
+    }
+
+    /// The dispatcher.
+    pub fn dispatcher(&self) -> &Dispatcher<W> {
+        &self.dispatcher
+    }
+
+    /// The dispatcher, mutably.
+    pub fn dispatcher_mut(&mut self) -> &mut Dispatcher<W> {
+        &mut self.dispatcher
+    }
+
+    /// Run every enabled system once. See `Dispatcher::run`.
+    pub fn run(&mut self) -> Result<(), SystemError>
+    where
+        W: for<'a> WorldInterface<'a>,
+    {
+        self.dispatcher.run(&mut self.world)
+    }
+
+    /// Start a new frame and run every enabled system once. See `Dispatcher::run_frame`.
+    pub fn run_frame(&mut self, delta: Duration) -> Result<(), SystemError>
+    where
+        W: GetResource<FrameInfo> + for<'a> WorldInterface<'a>,
+    {
+        self.dispatcher.run_frame(&mut self.world, delta)
+    }
+
+    /// Take the app apart.
+    pub fn into_parts(self) -> (W, Dispatcher<W>) {
+        (self.world, self.dispatcher)
+    }
+}
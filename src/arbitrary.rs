@@ -0,0 +1,135 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Arbitrary worlds for property tests, using `quickcheck`.
+//!
+//! `define_world!` implements [`GenerateWorld`](trait.GenerateWorld.html) for worlds whose
+//! components all implement `quickcheck::Arbitrary`. Each generated entity gets a random subset
+//! of the components, chosen so that it satisfies the world's `constraints`, with random values.
+//! [`ArbitraryWorld`](struct.ArbitraryWorld.html) wraps such a world so that it can be taken as an
+//! argument by a `quickcheck` property, and shrinks a failing world by deleting its entities one
+//! at a time.
+//!
+//! This module is only available with the `quickcheck` feature.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! use ecstatic::arbitrary::*;
+//!
+//! #[derive(Clone, Debug, Default)]
+//! pub struct Health(u8);
+//! impl Arbitrary for Health {
+//!     fn arbitrary(g: &mut Gen) -> Self {
+//!         Health(u8::arbitrary(g))
+//!     }
+//! }
+//!
+//! define_world!(
+//!     #[derive(Clone, Default)]
+//!     pub world {
+//!         components {
+//!             health: BasicVecStorage<Health>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! fn heal(w: &World) {
+//!     for h in <World as GetComponent<'_, Health>>::get_mut(w).iter_mut().flatten() {
+//!         h.0 = h.0.saturating_add(1);
+//!     }
+//! }
+//!
+//! fn healing_never_hurts(w: ArbitraryWorld<World>) -> bool {
+//!     let before = w.0.clone();
+//!     heal(&w.0);
+//!     let health = <World as GetComponent<'_, Health>>::get(&w.0);
+//!     let old = <World as GetComponent<'_, Health>>::get(&before);
+//!     w.0.live_entities()
+//!         .into_iter()
+//!         .all(|e| health.get(e).map(|h| h.0) >= old.get(e).map(|h| h.0))
+//! }
+//!
+//! quickcheck::quickcheck(healing_never_hurts as fn(ArbitraryWorld<World>) -> bool);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Entity, WorldInterface};
+
+pub use quickcheck::{Arbitrary, Gen};
+
+/// Worlds whose entities can be generated at random. Implemented by `define_world!` for worlds
+/// whose components all implement `Arbitrary`.
+pub trait GenerateWorld {
+    /// Add an entity with a random subset of the world's components.
+    fn arbitrary_entity(&mut self, g: &mut Gen) -> Entity;
+
+    /// Every live entity, in id order.
+    fn live_entities(&self) -> Vec<Entity>;
+}
+
+/// Generate a world with up to `g.size()` entities.
+pub fn arbitrary_world<W: Default + GenerateWorld>(g: &mut Gen) -> W {
+    let mut world = W::default();
+    // `usize::arbitrary` favors extreme values, which would make empty worlds common.
+    let sizes = (0..=g.size()).collect::<Vec<_>>();
+    let n = *g.choose(&sizes).unwrap();
+    for _ in 0..n {
+        world.arbitrary_entity(g);
+    }
+    world
+}
+
+/// A world that implements `Arbitrary`, for use as an argument to `quickcheck` properties.
+#[derive(Clone, Default)]
+pub struct ArbitraryWorld<W>(pub W);
+
+impl<W> Arbitrary for ArbitraryWorld<W>
+where
+    W: Clone + Default + GenerateWorld + for<'a> WorldInterface<'a> + 'static,
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        ArbitraryWorld(arbitrary_world(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let world = self.0.clone();
+        Box::new(world.live_entities().into_iter().map(move |entity| {
+            let mut smaller = world.clone();
+            smaller.delete_entity(entity);
+            ArbitraryWorld(smaller)
+        }))
+    }
+}
+
+impl<W: GenerateWorld> fmt::Debug for ArbitraryWorld<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArbitraryWorld")
+            .field("entities", &self.0.live_entities())
+            .finish()
+    }
+}
+
+/// Expands to its input only if the `quickcheck` feature is enabled, for use in `define_world!`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_quickcheck {
+    ($($t:tt)*) => { $($t)* };
+}
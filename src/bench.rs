@@ -0,0 +1,125 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Entry points for micro-benchmarks.
+//!
+//! These functions exercise the library's hot paths (joins, storage inserts and removals, and
+//! entity creation and deletion) in isolation, so that downstream crates can benchmark them
+//! against their own component types and storages, and catch regressions in CI. They return
+//! something computed from their work, to hand to the benchmark harness's `black_box`. Their
+//! signatures are kept stable between releases.
+//!
+//! The `bench_support` feature turns off checks that are only there to catch bugs, so that they
+//! don't skew the measurements. At the moment, that's the order check enabled by the
+//! `deterministic` feature.
+//!
+//! # Example
+//!
+//! With `criterion`, in `benches/`:
+//!
+//! ```ignore
+//! fn joins(c: &mut Criterion) {
+//!     let mut w = World::default();
+//!     for i in 0..10_000 {
+//!         w.new_entity().with(Position(i, 0)).with(Velocity(1, 0)).build();
+//!     }
+//!     c.bench_function("join 10k", |b| {
+//!         b.iter(|| {
+//!             let (p, v): (ReadComponent<Position>, ReadComponent<Velocity>) =
+//!                 ComponentProvider::fetch(&w);
+//!             black_box(ecstatic::bench::join_count((&p, &v)))
+//!         })
+//!     });
+//!     c.bench_function("churn 1k", |b| {
+//!         b.iter(|| black_box(ecstatic::bench::entity_churn(&mut w, 1_000, 1)))
+//!     });
+//! }
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::{ComponentStorage, Entity, Join, WorldInterface};
+
+/// Visit every entity in `join`, returning how many there were.
+#[inline(never)]
+pub fn join_count<J: Join>(join: J) -> usize {
+    let mut count = 0;
+    join.for_each(|_, _| count += 1);
+    count
+}
+
+/// Fold over every entity in `join`, in id order.
+#[inline(never)]
+pub fn join_fold<J, A, F>(join: J, init: A, mut f: F) -> A
+where
+    J: Join,
+    F: FnMut(A, Entity, J::Output) -> A,
+{
+    let mut acc = Some(init);
+    join.for_each(|e, v| acc = acc.take().map(|a| f(a, e, v)));
+    acc.expect("the accumulator is always put back")
+}
+
+/// Iterate over every component in `storage`, returning how many there were.
+#[inline(never)]
+pub fn storage_iter<'a, S: ComponentStorage<'a>>(storage: &'a S) -> usize {
+    storage.iter().flatten().count()
+}
+
+/// Give each of `entities` a component made by `make`, returning how many replaced an existing
+/// component.
+#[inline(never)]
+pub fn storage_insert<'a, S, F>(storage: &mut S, entities: &[Entity], mut make: F) -> usize
+where
+    S: ComponentStorage<'a>,
+    F: FnMut(Entity) -> S::Component,
+{
+    let mut replaced = 0;
+    for &e in entities {
+        if storage.get(e).is_some() {
+            replaced += 1;
+        }
+        storage.set(e, Some(make(e)));
+    }
+    replaced
+}
+
+/// Take the component of each of `entities` out of `storage`, returning how many there were.
+#[inline(never)]
+pub fn storage_remove<'a, S: ComponentStorage<'a>>(storage: &mut S, entities: &[Entity]) -> usize {
+    entities
+        .iter()
+        .filter(|&&e| storage.take(e).is_some())
+        .count()
+}
+
+/// Create `n` entities with no components and delete them again, `rounds` times over. Returns the
+/// last entity created, if any.
+#[inline(never)]
+pub fn entity_churn<W>(world: &mut W, n: usize, rounds: usize) -> Option<Entity>
+where
+    W: for<'a> WorldInterface<'a>,
+    for<'a> <W as WorldInterface<'a>>::ComponentSet: Default,
+{
+    let mut entities = Vec::with_capacity(n);
+    let mut last = None;
+    for _ in 0..rounds {
+        entities.extend((0..n).map(|_| world.build_entity(Default::default())));
+        last = entities.last().copied().or(last);
+        for e in entities.drain(..) {
+            world.delete_entity(e);
+        }
+    }
+    last
+}
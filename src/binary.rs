@@ -0,0 +1,530 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compact binary encoding of saved worlds.
+//!
+//! `World::save_binary` and `World::load_binary` use this instead of a `Value` tree. Compared to
+//! writing the tree out as text, the binary form avoids repeating the entity handle and field
+//! names for every component:
+//!
+//! - Integers, including entity ids and generations, are LEB128 varints (zigzag-encoded when
+//!   signed).
+//! - Which entity slots are alive, which of them are disabled, and which of them have each
+//!   component, are stored as bitsets.
+//! - Each storage's components are stored one after another, in entity id order, so their
+//!   entities don't need to be stored at all.
+//!
+//! [`encode_value`](fn.encode_value.html) and [`decode_value`](fn.decode_value.html) are also
+//! available for saving other data in the same encoding.
+//!
+//! ```
+//! # use ecstatic::*;
+//! let value = Value::List(vec![Value::UInt(300), Value::Str("hi".to_string())]);
+//! let mut bytes = Vec::new();
+//! encode_value(&value, &mut bytes);
+//! assert_eq!(bytes.len(), 9);
+//! assert_eq!(decode_value(&mut &bytes[..]), Ok(value));
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::persist::*;
+use crate::Entity;
+
+// For `IncrementalSave`.
+#[cfg(feature = "std")]
+use alloc::{boxed::Box, collections::VecDeque};
+#[cfg(feature = "std")]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+const MAGIC: &[u8; 4] = b"ECSB";
+// Format 1 didn't record disabled entities; it can still be loaded.
+const FORMAT: u8 = 2;
+
+const UNIT: u8 = 0;
+const FALSE: u8 = 1;
+const TRUE: u8 = 2;
+const INT: u8 = 3;
+const UINT: u8 = 4;
+const FLOAT: u8 = 5;
+const STR: u8 = 6;
+const LIST: u8 = 7;
+const MAP: u8 = 8;
+
+fn malformed(what: &str) -> PersistError {
+    PersistError::Malformed(what.to_string())
+}
+
+fn write_varint(mut n: u64, out: &mut Vec<u8>) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_byte(input: &mut &[u8]) -> Result<u8, PersistError> {
+    let (&b, rest) = input
+        .split_first()
+        .ok_or_else(|| malformed("unexpected end of data"))?;
+    *input = rest;
+    Ok(b)
+}
+
+fn read_bytes<'b>(input: &mut &'b [u8], n: usize) -> Result<&'b [u8], PersistError> {
+    if input.len() < n {
+        return Err(malformed("unexpected end of data"));
+    }
+    let (bytes, rest) = input.split_at(n);
+    *input = rest;
+    Ok(bytes)
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64, PersistError> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let b = read_byte(input)?;
+        n |= u64::from(b & 0x7f) << shift;
+        if b & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(malformed("varint too long"))
+}
+
+fn read_len(input: &mut &[u8]) -> Result<usize, PersistError> {
+    let n = read_varint(input)? as usize;
+    // Every element takes at least a byte, so this rules out absurd lengths before anything is
+    // allocated for them.
+    if n > input.len() {
+        return Err(malformed("length longer than the data"));
+    }
+    Ok(n)
+}
+
+fn write_str(s: &str, out: &mut Vec<u8>) {
+    write_varint(s.len() as u64, out);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn read_str(input: &mut &[u8]) -> Result<String, PersistError> {
+    let n = read_len(input)?;
+    String::from_utf8(read_bytes(input, n)?.to_vec()).map_err(|_| malformed("invalid UTF-8"))
+}
+
+fn write_bits(bits: &[bool], out: &mut Vec<u8>) {
+    for chunk in bits.chunks(8) {
+        out.push(
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |b, (i, &bit)| b | (u8::from(bit) << i)),
+        );
+    }
+}
+
+fn read_bits(input: &mut &[u8], n: usize) -> Result<Vec<bool>, PersistError> {
+    let bytes = read_bytes(input, n.div_ceil(8))?;
+    Ok((0..n).map(|i| bytes[i / 8] & (1 << (i % 8)) != 0).collect())
+}
+
+/// Append the binary encoding of `value` to `out`.
+pub fn encode_value(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Unit => out.push(UNIT),
+        Value::Bool(false) => out.push(FALSE),
+        Value::Bool(true) => out.push(TRUE),
+        Value::Int(n) => {
+            out.push(INT);
+            write_varint(((n << 1) ^ (n >> 63)) as u64, out);
+        }
+        Value::UInt(n) => {
+            out.push(UINT);
+            write_varint(*n, out);
+        }
+        Value::Float(x) => {
+            out.push(FLOAT);
+            out.extend_from_slice(&x.to_le_bytes());
+        }
+        Value::Str(s) => {
+            out.push(STR);
+            write_str(s, out);
+        }
+        Value::List(items) => {
+            out.push(LIST);
+            write_varint(items.len() as u64, out);
+            for item in items {
+                encode_value(item, out);
+            }
+        }
+        Value::Map(entries) => {
+            out.push(MAP);
+            write_varint(entries.len() as u64, out);
+            for (name, item) in entries {
+                write_str(name, out);
+                encode_value(item, out);
+            }
+        }
+    }
+}
+
+/// Decode a value encoded by `encode_value` from the start of `input`, advancing `input` past it.
+/// Lists and maps may be nested up to 128 deep.
+pub fn decode_value(input: &mut &[u8]) -> Result<Value, PersistError> {
+    decode_nested(input, 0)
+}
+
+// How deeply lists and maps may be nested.
+const MAX_DEPTH: usize = 128;
+
+// Decode a value inside `depth` lists and maps.
+fn decode_nested(input: &mut &[u8], depth: usize) -> Result<Value, PersistError> {
+    Ok(match read_byte(input)? {
+        UNIT => Value::Unit,
+        FALSE => Value::Bool(false),
+        TRUE => Value::Bool(true),
+        INT => {
+            let n = read_varint(input)?;
+            Value::Int((n >> 1) as i64 ^ -((n & 1) as i64))
+        }
+        UINT => Value::UInt(read_varint(input)?),
+        FLOAT => {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(read_bytes(input, 8)?);
+            Value::Float(f64::from_le_bytes(bytes))
+        }
+        STR => Value::Str(read_str(input)?),
+        LIST | MAP if depth == MAX_DEPTH => return Err(malformed("nested too deeply")),
+        LIST => {
+            let n = read_len(input)?;
+            Value::List(
+                (0..n)
+                    .map(|_| decode_nested(input, depth + 1))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        MAP => {
+            let n = read_len(input)?;
+            Value::Map(
+                (0..n)
+                    .map(|_| Ok((read_str(input)?, decode_nested(input, depth + 1)?)))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        _ => return Err(malformed("unknown value tag")),
+    })
+}
+
+fn generation(entity: Entity) -> u64 {
+    entity.to_bits() >> 32
+}
+
+fn entity(id: usize, generation: u64) -> Result<Entity, PersistError> {
+    if id > u32::MAX as usize || generation > u64::from(u32::MAX) {
+        return Err(malformed("entity out of range"));
+    }
+    Entity::from_bits(generation << 32 | id as u64).ok_or_else(|| malformed("zero generation"))
+}
+
+// The live entity in each slot, if any.
+fn alive_table(entities: &[Entity], free: &[Entity]) -> Vec<Option<Entity>> {
+    let slots = entities
+        .iter()
+        .chain(free.iter())
+        .map(|e| e.id() + 1)
+        .max()
+        .unwrap_or(0);
+    let mut alive = vec![None; slots];
+    for &e in entities.iter() {
+        alive[e.id()] = Some(e);
+    }
+    alive
+}
+
+// Which slots hold one of the `disabled` entities. Entities that aren't alive are skipped.
+fn disabled_bits(alive: &[Option<Entity>], disabled: &[Entity]) -> Vec<bool> {
+    let mut bits = vec![false; alive.len()];
+    for &e in disabled.iter() {
+        if alive.get(e.id()) == Some(&Some(e)) {
+            bits[e.id()] = true;
+        }
+    }
+    bits
+}
+
+fn encode_header(
+    version: u32,
+    alive: &[Option<Entity>],
+    free: &[Entity],
+    disabled: &[bool],
+    components: usize,
+    out: &mut Vec<u8>,
+) {
+    out.extend_from_slice(MAGIC);
+    out.push(FORMAT);
+    write_varint(u64::from(version), out);
+    write_varint(alive.len() as u64, out);
+    write_bits(&alive.iter().map(Option::is_some).collect::<Vec<_>>(), out);
+    for e in alive.iter().flatten() {
+        write_varint(generation(*e), out);
+    }
+    write_varint(free.len() as u64, out);
+    for e in free.iter() {
+        write_varint(e.id() as u64, out);
+        write_varint(generation(*e), out);
+    }
+    write_bits(disabled, out);
+    write_varint(components as u64, out);
+}
+
+fn encode_component<I>(
+    name: &str,
+    alive: &[Option<Entity>],
+    items: I,
+    out: &mut Vec<u8>,
+) -> Result<(), PersistError>
+where
+    I: IntoIterator<Item = (Entity, Value)>,
+{
+    let mut values = vec![None; alive.len()];
+    for (e, value) in items {
+        match values.get_mut(e.id()) {
+            Some(slot) if alive[e.id()] == Some(e) => *slot = Some(value),
+            _ => return Err(malformed("component saved for a dead entity")),
+        }
+    }
+    write_str(name, out);
+    write_bits(&values.iter().map(Option::is_some).collect::<Vec<_>>(), out);
+    for value in values.iter().flatten() {
+        encode_value(value, out);
+    }
+    Ok(())
+}
+
+fn encode_resources(resources: &[(String, Value)], out: &mut Vec<u8>) {
+    write_varint(resources.len() as u64, out);
+    for (name, value) in resources {
+        write_str(name, out);
+        encode_value(value, out);
+    }
+}
+
+/// Convert a save made by `World::save` to the binary format.
+pub fn encode_world(save: &Value) -> Result<Vec<u8>, PersistError> {
+    let version = u32::load(save.field("version")?)?;
+    let entities = Vec::<Entity>::load(save.field("entities")?)?;
+    let free = Vec::<Entity>::load(save.field("free")?)?;
+    let alive = alive_table(&entities, &free);
+    let disabled = match save.optional_field("disabled")? {
+        Some(disabled) => Vec::<Entity>::load(disabled)?,
+        None => Vec::new(),
+    };
+    let components = save.field("components")?.as_map()?;
+
+    let mut out = Vec::new();
+    let disabled = disabled_bits(&alive, &disabled);
+    encode_header(
+        version,
+        &alive,
+        &free,
+        &disabled,
+        components.len(),
+        &mut out,
+    );
+    for (name, items) in components {
+        let items = items
+            .as_list()?
+            .iter()
+            .map(<(Entity, Value)>::load)
+            .collect::<Result<Vec<_>, _>>()?;
+        encode_component(name, &alive, items, &mut out)?;
+    }
+    encode_resources(save.field("resources")?.as_map()?, &mut out);
+    Ok(out)
+}
+
+#[cfg(feature = "std")]
+type ComponentDump = Box<dyn FnOnce() -> Vec<(Entity, Value)>>;
+
+/// A binary world save that's written out a piece at a time, so that saving a large world can be
+/// spread over several frames. Made by `World::save_incremental`.
+///
+/// The world's storages are copied when the save is started, which is much quicker than
+/// converting them, so the save is of the world as it was at that moment, no matter what happens
+/// to the world afterwards. Each call to `step` then converts and writes out one storage. The
+/// output is the same as `World::save_binary`'s, and is loaded with `World::load_binary`.
+///
+/// Only available with the `std` feature.
+#[cfg(feature = "std")]
+pub struct IncrementalSave {
+    version: u32,
+    alive: Vec<Option<Entity>>,
+    free: Vec<Entity>,
+    disabled: Vec<bool>,
+    components: VecDeque<(&'static str, ComponentDump)>,
+    resources: Option<Vec<(String, Value)>>,
+    started: bool,
+    buffer: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl IncrementalSave {
+    #[doc(hidden)]
+    pub fn new(
+        version: u32,
+        entities: &[Entity],
+        free: &[Entity],
+        disabled: &[Entity],
+        resources: Vec<(String, Value)>,
+    ) -> Self {
+        let alive = alive_table(entities, free);
+        IncrementalSave {
+            version,
+            disabled: disabled_bits(&alive, disabled),
+            alive,
+            free: free.to_vec(),
+            components: VecDeque::new(),
+            resources: Some(resources),
+            started: false,
+            buffer: Vec::new(),
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn push_component<F>(&mut self, name: &'static str, dump: F)
+    where
+        F: FnOnce() -> Vec<(Entity, Value)> + 'static,
+    {
+        self.components.push_back((name, Box::new(dump)));
+    }
+
+    /// The number of calls to `step` left before the save is finished.
+    pub fn remaining(&self) -> usize {
+        usize::from(!self.started) + self.components.len() + usize::from(self.resources.is_some())
+    }
+
+    /// Whether the whole save has been written.
+    pub fn is_done(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Write the next piece of the save to `out`: the entity table first, then each storage in
+    /// turn, then the resources. Returns whether the save is finished.
+    pub fn step<W: Write>(&mut self, out: &mut W) -> io::Result<bool> {
+        self.buffer.clear();
+        if !self.started {
+            self.started = true;
+            let components = self.components.len();
+            encode_header(
+                self.version,
+                &self.alive,
+                &self.free,
+                &self.disabled,
+                components,
+                &mut self.buffer,
+            );
+        } else if let Some((name, dump)) = self.components.pop_front() {
+            encode_component(name, &self.alive, dump(), &mut self.buffer)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        } else if let Some(resources) = self.resources.take() {
+            encode_resources(&resources, &mut self.buffer);
+        }
+        out.write_all(&self.buffer)?;
+        Ok(self.is_done())
+    }
+
+    /// Write the rest of the save to `out`.
+    pub fn finish<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        while !self.step(out)? {}
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for IncrementalSave {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncrementalSave")
+            .field("version", &self.version)
+            .field("remaining", &self.remaining())
+            .finish()
+    }
+}
+
+/// Convert the binary format back to a save that `World::load` accepts.
+pub fn decode_world(mut input: &[u8]) -> Result<Value, PersistError> {
+    let input = &mut input;
+    if read_bytes(input, 4)? != MAGIC {
+        return Err(malformed("not a binary world save"));
+    }
+    let format = read_byte(input)?;
+    if format == 0 || format > FORMAT {
+        return Err(malformed("unsupported binary format"));
+    }
+    let version = read_varint(input)?;
+    let slots = read_varint(input)? as usize;
+    let alive_bits = read_bits(input, slots)?;
+    let mut alive = vec![None; slots];
+    for (id, _) in alive_bits.iter().enumerate().filter(|(_, &bit)| bit) {
+        alive[id] = Some(entity(id, read_varint(input)?)?);
+    }
+    let free = (0..read_len(input)?)
+        .map(|_| {
+            let id = read_varint(input)? as usize;
+            entity(id, read_varint(input)?)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut disabled = Vec::new();
+    if format >= 2 {
+        for (id, _) in read_bits(input, slots)?
+            .iter()
+            .enumerate()
+            .filter(|(_, &bit)| bit)
+        {
+            disabled.push(alive[id].ok_or_else(|| malformed("dead entity disabled"))?);
+        }
+    }
+
+    let mut components = Vec::new();
+    for _ in 0..read_len(input)? {
+        let name = read_str(input)?;
+        let present = read_bits(input, slots)?;
+        let mut items = Vec::new();
+        for (id, _) in present.iter().enumerate().filter(|(_, &bit)| bit) {
+            let e = alive[id].ok_or_else(|| malformed("component saved for a dead entity"))?;
+            items.push(Value::List(vec![e.save(), decode_value(input)?]));
+        }
+        components.push((name, Value::List(items)));
+    }
+
+    let mut resources = Vec::new();
+    for _ in 0..read_len(input)? {
+        resources.push((read_str(input)?, decode_value(input)?));
+    }
+    if !input.is_empty() {
+        return Err(malformed("trailing data"));
+    }
+
+    let entities = alive.into_iter().flatten().collect::<Vec<_>>();
+    Ok(Value::Map(vec![
+        ("version".to_string(), Value::UInt(version)),
+        ("entities".to_string(), entities.save()),
+        ("free".to_string(), free.save()),
+        ("disabled".to_string(), disabled.save()),
+        ("components".to_string(), Value::Map(components)),
+        ("resources".to_string(), Value::Map(resources)),
+    ]))
+}
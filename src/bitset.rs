@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use alloc::vec::Vec;
+
 /// Trait for implementing bit sets on top of unsigned integer types.
+#[allow(dead_code)]
 pub trait BitSet {
     /// Number of bits stored in this bitset.
     const SIZE: usize;
@@ -103,6 +106,7 @@ bitset_impl!(u32, 32);
 bitset_impl!(u64, 64);
 bitset_impl!(u128, 128);
 
+#[allow(dead_code)]
 pub trait BitVec {
     type Rep: BitSet;
     /// Return `true` iff bit `i` is set.
@@ -117,7 +121,7 @@ pub trait BitVec {
 
 impl<T> BitVec for Vec<T>
 where
-    T: BitSet + Copy + std::ops::BitAnd<Output = T>,
+    T: BitSet + Copy + core::ops::BitAnd<Output = T>,
 {
     type Rep = T;
     #[inline]
@@ -160,32 +164,32 @@ mod tests {
         let mut x: u32 = 0;
         // Should default to unset (0).
         for i in 0..32 {
-            assert!(x.get_bit(i) == false);
+            assert!(!x.get_bit(i));
         }
         // Setting one bit shouldn't have any effect on the others.
         x.set_bit(12);
         assert!(x.get_bit(12));
         for i in 0..32 {
             if i != 12 {
-                assert!(x.get_bit(i) == false);
+                assert!(!x.get_bit(i));
             }
         }
         // Same for clearing one bit.
         x.clear_bit(12);
         for i in 0..32 {
-            assert!(x.get_bit(i) == false);
+            assert!(!x.get_bit(i));
         }
 
         x = 0xffffffff;
         for i in 0..32 {
-            assert!(x.get_bit(i) == true);
+            assert!(x.get_bit(i));
         }
 
         x.clear_bit(14);
-        assert!(x.get_bit(14) == false);
+        assert!(!x.get_bit(14));
         for i in 0..32 {
             if i != 14 {
-                assert!(x.get_bit(i) == true);
+                assert!(x.get_bit(i));
             }
         }
     }
@@ -0,0 +1,251 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed, change-tracked data shared between AI systems.
+//!
+//! A [`Blackboard`](struct.Blackboard.html) holds at most one value of each type: one system
+//! computes a `ThreatMap` and `set`s it, and any number of others `get::<ThreatMap>()` it later.
+//! Every write bumps the blackboard's tick, so a system that remembers the tick it last looked at
+//! can skip its work when the slots it reads haven't `changed_since` then.
+//!
+//! Slot types that implement [`Persist`](../persist/trait.Persist.html) can be `register`ed under
+//! a name, after which `save` and `load` carry them in and out of a save file.
+//!
+//! # Example
+//!
+//! ```
+//! # use ecstatic::*;
+//! #[derive(Clone, Debug, PartialEq)]
+//! pub struct ThreatMap(Vec<u32>);
+//! # impl Persist for ThreatMap {
+//! #     fn save(&self) -> Value { self.0.save() }
+//! #     fn load(value: &Value) -> Result<Self, PersistError> { Vec::load(value).map(ThreatMap) }
+//! # }
+//!
+//! let mut board = Blackboard::new();
+//! board.register::<ThreatMap>("threat_map");
+//! let seen = board.tick();
+//!
+//! board.set(ThreatMap(vec![0, 3, 1]));
+//! assert!(board.changed_since::<ThreatMap>(seen));
+//! assert_eq!(board.get::<ThreatMap>(), Some(&ThreatMap(vec![0, 3, 1])));
+//!
+//! let mut restored = Blackboard::new();
+//! restored.register::<ThreatMap>("threat_map");
+//! restored.load(&board.save()).unwrap();
+//! assert_eq!(restored.get::<ThreatMap>(), Some(&ThreatMap(vec![0, 3, 1])));
+//! ```
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::any::{Any, TypeId};
+use core::fmt;
+
+use crate::hash::HashMap;
+use crate::persist::*;
+
+struct Slot {
+    value: Box<dyn Any>,
+    changed: u64,
+    clone: fn(&dyn Any) -> Box<dyn Any>,
+}
+
+#[derive(Clone, Copy)]
+struct Registration {
+    type_id: TypeId,
+    save: fn(&dyn Any) -> Value,
+    load: fn(&Value) -> Result<Slot, PersistError>,
+}
+
+fn clone_slot<T: Any + Clone>(value: &dyn Any) -> Box<dyn Any> {
+    Box::new(downcast::<T>(value).clone())
+}
+
+fn save_slot<T: Any + Persist>(value: &dyn Any) -> Value {
+    downcast::<T>(value).save()
+}
+
+fn load_slot<T: Any + Clone + Persist>(value: &Value) -> Result<Slot, PersistError> {
+    Ok(Slot {
+        value: Box::new(T::load(value)?),
+        changed: 0,
+        clone: clone_slot::<T>,
+    })
+}
+
+fn downcast<T: Any>(value: &dyn Any) -> &T {
+    value
+        .downcast_ref::<T>()
+        .expect("slot type is determined by its key")
+}
+
+/// Typed slots shared between systems, meant to be kept as a resource. See the
+/// [module documentation](index.html).
+#[derive(Default)]
+pub struct Blackboard {
+    slots: HashMap<TypeId, Slot>,
+    tick: u64,
+    registry: BTreeMap<&'static str, Registration>,
+}
+
+impl Blackboard {
+    /// Create an empty blackboard.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The blackboard's tick, which goes up by one on every write.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// The value of type `T`, if there is one.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.slots
+            .get(&TypeId::of::<T>())
+            .map(|slot| downcast(&*slot.value))
+    }
+
+    /// Mutable version of `get`. This counts as a write, whether or not the value is modified.
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        let slot = self.slots.get_mut(&TypeId::of::<T>())?;
+        self.tick += 1;
+        slot.changed = self.tick;
+        slot.value.downcast_mut::<T>()
+    }
+
+    /// Store `value`, returning the previous value of the same type.
+    pub fn set<T: Any + Clone>(&mut self, value: T) -> Option<T> {
+        self.tick += 1;
+        let old = self.slots.insert(
+            TypeId::of::<T>(),
+            Slot {
+                value: Box::new(value),
+                changed: self.tick,
+                clone: clone_slot::<T>,
+            },
+        );
+        old.and_then(|slot| slot.value.downcast::<T>().ok())
+            .map(|v| *v)
+    }
+
+    /// Remove the value of type `T`, returning it. Removing a value doesn't count as a change.
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        self.slots
+            .remove(&TypeId::of::<T>())
+            .and_then(|slot| slot.value.downcast::<T>().ok())
+            .map(|v| *v)
+    }
+
+    /// Whether there is a value of type `T`.
+    pub fn contains<T: Any>(&self) -> bool {
+        self.slots.contains_key(&TypeId::of::<T>())
+    }
+
+    /// The tick at which the value of type `T` was last written, if there is one.
+    pub fn change_tick<T: Any>(&self) -> Option<u64> {
+        self.slots.get(&TypeId::of::<T>()).map(|slot| slot.changed)
+    }
+
+    /// Whether the value of type `T` has been written after `tick`.
+    pub fn changed_since<T: Any>(&self, tick: u64) -> bool {
+        self.change_tick::<T>().is_some_and(|t| t > tick)
+    }
+
+    /// Remove every value.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+    }
+
+    /// Include values of type `T` in `save` and `load`, under `name`. Registering another type
+    /// with the same name replaces the earlier registration.
+    pub fn register<T: Any + Clone + Persist>(&mut self, name: &'static str) {
+        self.registry.insert(
+            name,
+            Registration {
+                type_id: TypeId::of::<T>(),
+                save: save_slot::<T>,
+                load: load_slot::<T>,
+            },
+        );
+    }
+
+    /// Save every registered value as a map from name to value, in name order.
+    pub fn save(&self) -> Value {
+        Value::Map(
+            self.registry
+                .iter()
+                .filter_map(|(name, r)| {
+                    let slot = self.slots.get(&r.type_id)?;
+                    Some((name.to_string(), (r.save)(&*slot.value)))
+                })
+                .collect(),
+        )
+    }
+
+    /// Load values saved by `save`. Each one loaded counts as a write. Names that haven't been
+    /// registered are ignored, and values that aren't in `value` are left alone.
+    ///
+    /// If any value fails to load, none of them are stored.
+    pub fn load(&mut self, value: &Value) -> Result<(), PersistError> {
+        let mut loaded = Vec::new();
+        for (name, v) in value.as_map()? {
+            if let Some(r) = self.registry.get(name.as_str()) {
+                loaded.push((r.type_id, (r.load)(v)?));
+            }
+        }
+        for (type_id, mut slot) in loaded {
+            self.tick += 1;
+            slot.changed = self.tick;
+            self.slots.insert(type_id, slot);
+        }
+        Ok(())
+    }
+}
+
+impl Clone for Blackboard {
+    fn clone(&self) -> Self {
+        Blackboard {
+            slots: self
+                .slots
+                .iter()
+                .map(|(type_id, slot)| {
+                    let value = (slot.clone)(&*slot.value);
+                    (
+                        *type_id,
+                        Slot {
+                            value,
+                            changed: slot.changed,
+                            clone: slot.clone,
+                        },
+                    )
+                })
+                .collect(),
+            tick: self.tick,
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for Blackboard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Blackboard")
+            .field("slots", &self.slots.len())
+            .field("tick", &self.tick)
+            .field("registered", &self.registry.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
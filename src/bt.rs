@@ -0,0 +1,522 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Behavior trees for AI, ticked by a provided system.
+//!
+//! A [`BehaviorTree`](struct.BehaviorTree.html) is assembled from
+//! [`Behavior`](struct.Behavior.html) nodes: actions and conditions at the leaves, and sequences,
+//! selectors and inverters above them. Each entity's tree lives in a component, and a
+//! [`BehaviorRunner`](struct.BehaviorRunner.html) ticks every tree in the world once per run.
+//! A sequence or selector whose child is `Running` picks up from that child on the next tick,
+//! rather than starting over.
+//!
+//! Leaves see the world through a [`Context`](struct.Context.html), which can read anything, but
+//! only write to the ticking entity's own components and to resources. Leaves can't create or
+//! delete entities; queue a command for that instead.
+//!
+//! Like systems, leaves declare what they use as a tuple of dependencies, and the `Context` panics
+//! if a leaf uses anything else. The runner declares the dependencies of all the leaves it might
+//! tick, which make up its [`DependencyAccess`](../trait.DependencyAccess.html) along with the
+//! tree components, and it panics when it gets to a leaf that uses something it didn't declare.
+//!
+//! Like other component types, the tree component has to be defined in the crate that calls
+//! `define_world!`, so wrap the tree in a type of your own that implements
+//! `AsMut<BehaviorTree<World>>`.
+//!
+//! This module is only available with the `bt` feature.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! use ecstatic::bt::*;
+//!
+//! #[derive(Clone, Default)]
+//! pub struct Brain(BehaviorTree<World>);
+//! impl AsMut<BehaviorTree<World>> for Brain {
+//!     fn as_mut(&mut self) -> &mut BehaviorTree<World> {
+//!         &mut self.0
+//!     }
+//! }
+//!
+//! #[derive(Default)]
+//! pub struct Health(u32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             brains: BasicVecStorage<Brain>,
+//!             health: BasicVecStorage<Health>,
+//!         }
+//!         resources {
+//!             log: Vec<String>,
+//!         }
+//!     }
+//! );
+//!
+//! type Flee = (ReadComponent<'static, Health>, WriteResource<'static, Vec<String>>);
+//! type Fight = (RestrictedWriteComponent<'static, Health>,);
+//!
+//! let tree = BehaviorTree::new(Behavior::selector(vec![
+//!     Behavior::sequence(vec![
+//!         Behavior::condition::<Flee, _>(|ctx: &Context<World>| {
+//!             ctx.get::<Health>().unwrap().0 < 5
+//!         }),
+//!         Behavior::action::<Flee, _>(|ctx: &Context<World>| {
+//!             ctx.resource_mut::<Vec<String>>().push(format!("{:?} flees", ctx.entity()));
+//!             Status::Success
+//!         }),
+//!     ]),
+//!     Behavior::action::<Fight, _>(|ctx: &Context<World>| {
+//!         ctx.get_mut::<Health>().unwrap().0 -= 1;
+//!         Status::Success
+//!     }),
+//! ]));
+//!
+//! let mut w = World::default();
+//! let e = w.new_entity().with(Brain(tree)).with(Health(6)).build();
+//!
+//! // The runner writes the brains, and covers what every leaf uses.
+//! type Leaves = (WriteComponent<'static, Health>, WriteResource<'static, Vec<String>>);
+//! let mut runner = BehaviorRunner::<Brain, Leaves>::new();
+//! let mut uses = Vec::new();
+//! <BehaviorRunner<Brain, Leaves> as DependencyAccess>::uses(&mut uses);
+//! assert_eq!(uses.len(), 3);
+//! for _ in 0..3 {
+//!     runner.run_on(&mut w, ());
+//! }
+//! assert_eq!(*<World as GetResource<Vec<String>>>::get(&w), vec![format!("{:?} flees", e)]);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::TypeId;
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::cell::{Ref, RefMut};
+use crate::*;
+
+/// The result of ticking a behavior.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Status {
+    /// The behavior is done, and it worked.
+    Success,
+    /// The behavior is done, and it didn't work.
+    Failure,
+    /// The behavior isn't done yet; tick it again next time.
+    Running,
+}
+
+/// What a leaf behavior can see of the world while it's being ticked.
+///
+/// Each accessor panics if the leaf didn't declare the component or resource in its
+/// dependencies: `get`, `component` and `resource` need it declared as read or written, and
+/// `get_mut` and `resource_mut` need it declared as written.
+pub struct Context<'w, W> {
+    world: &'w W,
+    entity: Entity,
+    // What the leaf being ticked declared.
+    uses: &'w [DependencyUse],
+    // What the runner declared, which every leaf's dependencies have to be part of.
+    runner_uses: &'w [DependencyUse],
+}
+
+impl<'w, W> Context<'w, W> {
+    /// The entity whose tree is being ticked.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// The ticking entity's `T` component, if it has one.
+    pub fn get<T>(&self) -> Option<Ref<'w, T>>
+    where
+        T: 'static + StorageSpec<'w, Component = T>,
+        W: GetComponent<'w, T>,
+    {
+        self.check::<T>(AccessKey::Component, false);
+        let entity = self.entity;
+        Ref::filter_map(self.world.get(), |s| s.get(entity)).ok()
+    }
+
+    /// Mutable version of `get`.
+    pub fn get_mut<T>(&self) -> Option<RefMut<'w, T>>
+    where
+        T: 'static + StorageSpec<'w, Component = T>,
+        T::Storage: MutableComponentStorage<'w>,
+        W: GetComponent<'w, T>,
+    {
+        self.check::<T>(AccessKey::Component, true);
+        let entity = self.entity;
+        RefMut::filter_map(self.world.get_mut(), |s| s.get_mut(entity)).ok()
+    }
+
+    /// The storage of every entity's `T` component, for looking at other entities. Joins over it
+    /// include disabled entities.
+    pub fn component<T>(&self) -> ReadComponent<'w, T>
+    where
+        T: 'static + StorageSpec<'w>,
+        W: GetComponent<'w, T>,
+    {
+        self.check::<T>(AccessKey::Component, false);
+        ReadComponent {
+            storage: self.world.get(),
+            disabled: None,
+        }
+    }
+
+    /// The resource of type `R`.
+    pub fn resource<R: 'static>(&self) -> ReadResource<'w, R>
+    where
+        W: GetResource<R>,
+    {
+        self.check::<R>(AccessKey::Resource, false);
+        ReadResource {
+            resource: self.world.get(),
+        }
+    }
+
+    /// Mutable version of `resource`.
+    pub fn resource_mut<R: 'static>(&self) -> WriteResource<'w, R>
+    where
+        W: GetResource<R>,
+    {
+        self.check::<R>(AccessKey::Resource, true);
+        WriteResource {
+            resource: self.world.get_mut(),
+            probe: Default::default(),
+        }
+    }
+
+    // Panic unless the leaf declared `T`, written if `write`.
+    #[track_caller]
+    fn check<T: 'static>(&self, key: fn(TypeId) -> AccessKey, write: bool) {
+        let key = key(TypeId::of::<T>());
+        if !self
+            .uses
+            .iter()
+            .any(|u| u.key == key && (u.usage.is_write() || !write))
+        {
+            panic!(
+                "behavior tree leaf {} `{}` without declaring it in its dependencies",
+                if write { "writes" } else { "reads" },
+                core::any::type_name::<T>()
+            );
+        }
+    }
+}
+
+type LeafFn<W> = Arc<dyn Fn(&Context<'_, W>) -> Status + Send + Sync>;
+
+// A leaf, and what it declared it uses.
+struct Leaf<W> {
+    f: LeafFn<W>,
+    uses: Arc<[DependencyUse]>,
+}
+
+impl<W> Leaf<W> {
+    fn tick(&self, ctx: &Context<'_, W>) -> Status {
+        if let Some(u) = self.uses.iter().find(|u| {
+            !ctx.runner_uses
+                .iter()
+                .any(|r| r.key == u.key && (r.usage.is_write() || !u.usage.is_write()))
+        }) {
+            panic!(
+                "behavior tree leaf uses `{}`, which isn't in the behavior runner's dependencies",
+                u.name
+            );
+        }
+        (self.f)(&Context {
+            world: ctx.world,
+            entity: ctx.entity,
+            uses: &self.uses,
+            runner_uses: ctx.runner_uses,
+        })
+    }
+}
+
+enum Node<W> {
+    Leaf(Leaf<W>),
+    // Composites remember which child they got up to, so a `Running` child is resumed rather
+    // than starting over.
+    Sequence(Vec<Behavior<W>>, usize),
+    Selector(Vec<Behavior<W>>, usize),
+    Invert(Box<Behavior<W>>),
+}
+
+/// A node of a behavior tree. The constructors make up a small DSL for building trees; see the
+/// [module documentation](index.html).
+pub struct Behavior<W> {
+    node: Node<W>,
+}
+
+impl<W> Behavior<W> {
+    /// A leaf that runs `f`, which uses the components and resources in `D`, a tuple of
+    /// dependencies like a system's, e.g. `(ReadComponent<'static, Position>,)`.
+    pub fn action<D, F>(f: F) -> Self
+    where
+        D: Nest,
+        D::Nested: DependencyAccess,
+        F: Fn(&Context<'_, W>) -> Status + Send + Sync + 'static,
+    {
+        let mut uses = Vec::new();
+        <D::Nested as DependencyAccess>::uses(&mut uses);
+        Behavior {
+            node: Node::Leaf(Leaf {
+                f: Arc::new(f),
+                uses: uses.into(),
+            }),
+        }
+    }
+
+    /// A leaf that succeeds if `f` returns `true`, and fails otherwise. `D` is as for `action`.
+    pub fn condition<D, F>(f: F) -> Self
+    where
+        D: Nest,
+        D::Nested: DependencyAccess,
+        F: Fn(&Context<'_, W>) -> bool + Send + Sync + 'static,
+    {
+        Self::action::<D, _>(move |ctx| {
+            if f(ctx) {
+                Status::Success
+            } else {
+                Status::Failure
+            }
+        })
+    }
+
+    /// Ticks `children` in order until one fails. Succeeds if they all succeed.
+    pub fn sequence<I: IntoIterator<Item = Behavior<W>>>(children: I) -> Self {
+        Behavior {
+            node: Node::Sequence(children.into_iter().collect(), 0),
+        }
+    }
+
+    /// Ticks `children` in order until one succeeds. Fails if they all fail.
+    pub fn selector<I: IntoIterator<Item = Behavior<W>>>(children: I) -> Self {
+        Behavior {
+            node: Node::Selector(children.into_iter().collect(), 0),
+        }
+    }
+
+    /// Swaps this behavior's `Success` and `Failure`.
+    pub fn invert(self) -> Self {
+        Behavior {
+            node: Node::Invert(Box::new(self)),
+        }
+    }
+
+    fn tick(&mut self, ctx: &Context<'_, W>) -> Status {
+        match &mut self.node {
+            Node::Leaf(leaf) => leaf.tick(ctx),
+            Node::Sequence(children, cursor) => {
+                tick_composite(children, cursor, ctx, Status::Success)
+            }
+            Node::Selector(children, cursor) => {
+                tick_composite(children, cursor, ctx, Status::Failure)
+            }
+            Node::Invert(child) => match child.tick(ctx) {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            },
+        }
+    }
+
+    fn reset(&mut self) {
+        match &mut self.node {
+            Node::Leaf(_) => {}
+            Node::Sequence(children, cursor) | Node::Selector(children, cursor) => {
+                *cursor = 0;
+                children.iter_mut().for_each(Behavior::reset);
+            }
+            Node::Invert(child) => child.reset(),
+        }
+    }
+}
+
+// Ticks children from `cursor` on for as long as they return `proceed`.
+fn tick_composite<W>(
+    children: &mut [Behavior<W>],
+    cursor: &mut usize,
+    ctx: &Context<'_, W>,
+    proceed: Status,
+) -> Status {
+    while let Some(child) = children.get_mut(*cursor) {
+        match child.tick(ctx) {
+            Status::Running => return Status::Running,
+            status if status == proceed => *cursor += 1,
+            status => {
+                *cursor = 0;
+                return status;
+            }
+        }
+    }
+    *cursor = 0;
+    proceed
+}
+
+impl<W> Clone for Behavior<W> {
+    fn clone(&self) -> Self {
+        let node = match &self.node {
+            Node::Leaf(leaf) => Node::Leaf(Leaf {
+                f: leaf.f.clone(),
+                uses: leaf.uses.clone(),
+            }),
+            Node::Sequence(children, cursor) => Node::Sequence(children.clone(), *cursor),
+            Node::Selector(children, cursor) => Node::Selector(children.clone(), *cursor),
+            Node::Invert(child) => Node::Invert(child.clone()),
+        };
+        Behavior { node }
+    }
+}
+
+impl<W> fmt::Debug for Behavior<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.node {
+            Node::Leaf(_) => f.write_str("Leaf"),
+            Node::Sequence(children, _) => f.debug_tuple("Sequence").field(children).finish(),
+            Node::Selector(children, _) => f.debug_tuple("Selector").field(children).finish(),
+            Node::Invert(child) => f.debug_tuple("Invert").field(child).finish(),
+        }
+    }
+}
+
+/// A behavior tree for worlds of type `W`. See the [module documentation](index.html).
+///
+/// The default tree is an empty sequence, which does nothing and succeeds.
+pub struct BehaviorTree<W> {
+    root: Behavior<W>,
+    status: Option<Status>,
+}
+
+impl<W> BehaviorTree<W> {
+    /// Create a tree with `root` at the top.
+    pub fn new(root: Behavior<W>) -> Self {
+        BehaviorTree { root, status: None }
+    }
+
+    /// The status returned by the last tick, or `None` if the tree hasn't been ticked.
+    pub fn status(&self) -> Option<Status> {
+        self.status
+    }
+
+    /// Tick the tree for `ctx.entity()`. `BehaviorRunner` calls this for each entity.
+    pub fn tick(&mut self, ctx: &Context<'_, W>) -> Status {
+        let status = self.root.tick(ctx);
+        self.status = Some(status);
+        status
+    }
+
+    /// Forget where any running behaviors got up to, so the next tick starts from the top.
+    pub fn reset(&mut self) {
+        self.root.reset();
+        self.status = None;
+    }
+}
+
+impl<W> Clone for BehaviorTree<W> {
+    fn clone(&self) -> Self {
+        BehaviorTree {
+            root: self.root.clone(),
+            status: self.status,
+        }
+    }
+}
+
+impl<W> fmt::Debug for BehaviorTree<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BehaviorTree")
+            .field("root", &self.root)
+            .field("status", &self.status)
+            .finish()
+    }
+}
+
+impl<W> Default for BehaviorTree<W> {
+    fn default() -> Self {
+        Self::new(Behavior::sequence(Vec::new()))
+    }
+}
+
+/// System that ticks the behavior tree of every entity with a `B` component, in ascending
+/// entity id order.
+///
+/// `D` is a tuple of dependencies covering everything the trees' leaves use; the runner panics
+/// when it gets to a leaf that uses anything else. The runner's `DependencyAccess` is a write to
+/// the `B` storage plus `D`.
+///
+/// The `B` storage is borrowed mutably for the whole run, so leaves can't look at `B`
+/// components.
+pub struct BehaviorRunner<B, D = ()> {
+    _tree: PhantomData<fn() -> (B, D)>,
+}
+
+impl<B, D> BehaviorRunner<B, D> {
+    /// Create the system.
+    pub fn new() -> Self {
+        BehaviorRunner { _tree: PhantomData }
+    }
+}
+
+impl<B, D> Default for BehaviorRunner<B, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B, D> DependencyAccess for BehaviorRunner<B, D>
+where
+    B: 'static,
+    D: Nest,
+    D::Nested: DependencyAccess,
+{
+    fn uses(uses: &mut Vec<DependencyUse>) {
+        uses.push(DependencyUse::of::<B>(AccessKey::Component, Usage::Write));
+        <D::Nested as DependencyAccess>::uses(uses);
+    }
+}
+
+impl<W, B, D> RunSystem<W> for BehaviorRunner<B, D>
+where
+    B: 'static + for<'a> StorageSpec<'a, Component = B> + AsMut<BehaviorTree<W>>,
+    for<'a> <B as StorageSpec<'a>>::Storage: MutableComponentStorage<'a>,
+    D: Nest,
+    D::Nested: DependencyAccess,
+    W: for<'a> GetComponent<'a, B>,
+{
+    type Input = ();
+    type Output = ();
+    fn run_on(&mut self, world: &mut W, _: ()) {
+        let mut runner_uses = Vec::new();
+        <Self as DependencyAccess>::uses(&mut runner_uses);
+        let world = &*world;
+        let mut trees = <W as GetComponent<'_, B>>::get_mut(world);
+        for id in 0..trees.size() {
+            if let Some(entity) = trees.entity(id) {
+                if let Some(tree) = trees.get_mut(entity) {
+                    tree.as_mut().tick(&Context {
+                        world,
+                        entity,
+                        uses: &[],
+                        runner_uses: &runner_uses,
+                    });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,446 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The cells that worlds keep their storages and resources in.
+//!
+//! By default, [`WorldCell`](type.WorldCell.html) is `core::cell::RefCell`, and
+//! [`Ref`](type.Ref.html) and [`RefMut`](type.RefMut.html) are its guards, so every borrow
+//! updates a counter and a conflicting borrow panics.
+//!
+//! The `single_thread` feature replaces them with a plain `UnsafeCell`, a borrow flag and
+//! reference wrappers, for targets like `wasm32-unknown-unknown` where every instruction on the
+//! borrow path counts. The world's API is unchanged. The flag is kept in every build, so
+//! conflicting dependencies in a system (say, a `ReadComponent<T>` and a `WriteComponent<T>`)
+//! still panic rather than aliasing; the checks are a compare and an increment inline, with the
+//! panics kept out of line.
+//!
+//! Worlds themselves are single-threaded, but state that is shared between threads goes behind
+//! [`RwLock`](struct.RwLock.html) instead. It's the standard library's lock, without poisoning, or
+//! `parking_lot`'s with the `parking_lot` feature; the two have the same API, so switching between
+//! them doesn't require any other changes.
+
+#[cfg(not(feature = "single_thread"))]
+pub use core::cell::{BorrowError, BorrowMutError, Ref, RefCell as WorldCell, RefMut};
+
+#[cfg(feature = "single_thread")]
+pub use unchecked::*;
+
+#[cfg(feature = "parking_lot")]
+pub use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(all(feature = "std", not(feature = "parking_lot")))]
+pub use self::lock::*;
+
+#[cfg(all(feature = "std", not(feature = "parking_lot")))]
+mod lock {
+    use std::sync::{self, PoisonError, TryLockError};
+
+    pub use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+    /// A reader-writer lock. This wraps `std::sync::RwLock`, but ignores poisoning: a thread that
+    /// panics while holding the lock doesn't stop others from using it afterwards.
+    #[derive(Debug, Default)]
+    pub struct RwLock<T: ?Sized>(sync::RwLock<T>);
+
+    impl<T> RwLock<T> {
+        /// Wrap `value`.
+        pub const fn new(value: T) -> Self {
+            RwLock(sync::RwLock::new(value))
+        }
+
+        /// Unwrap the value.
+        pub fn into_inner(self) -> T {
+            self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    impl<T: ?Sized> RwLock<T> {
+        /// Lock for reading, blocking until no thread holds the write lock.
+        pub fn read(&self) -> RwLockReadGuard<'_, T> {
+            self.0.read().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Lock for writing, blocking until no other thread holds the lock.
+        pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+            self.0.write().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        /// Lock for reading if that can be done without blocking.
+        pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+            match self.0.try_read() {
+                Ok(guard) => Some(guard),
+                Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            }
+        }
+
+        /// Lock for writing if that can be done without blocking.
+        pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+            match self.0.try_write() {
+                Ok(guard) => Some(guard),
+                Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            }
+        }
+
+        /// Get a mutable reference to the value. No locking is needed, since `self` is borrowed
+        /// mutably.
+        pub fn get_mut(&mut self) -> &mut T {
+            self.0.get_mut().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+}
+
+#[cfg(feature = "single_thread")]
+mod unchecked {
+    use core::cell::UnsafeCell;
+    use core::fmt;
+    use core::marker::PhantomData;
+    use core::ops::{Deref, DerefMut};
+    use core::ptr::NonNull;
+
+    use core::cell::Cell;
+
+    // The borrow state: the number of shared borrows, or -1 while mutably borrowed.
+    type Flag<'b> = &'b Cell<isize>;
+
+    #[cold]
+    #[inline(never)]
+    #[track_caller]
+    fn already_mutably_borrowed() -> ! {
+        panic!("already mutably borrowed")
+    }
+
+    #[cold]
+    #[inline(never)]
+    #[track_caller]
+    fn already_borrowed() -> ! {
+        panic!("already borrowed")
+    }
+
+    /// A mutable memory location with a minimal borrow flag.
+    pub struct WorldCell<T: ?Sized> {
+        borrows: Cell<isize>,
+        value: UnsafeCell<T>,
+    }
+
+    impl<T> WorldCell<T> {
+        /// Wrap `value`.
+        #[inline]
+        pub const fn new(value: T) -> Self {
+            WorldCell {
+                borrows: Cell::new(0),
+                value: UnsafeCell::new(value),
+            }
+        }
+
+        /// Unwrap the value.
+        #[inline]
+        pub fn into_inner(self) -> T {
+            self.value.into_inner()
+        }
+
+        /// Replace the value, returning the old one.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the value is borrowed.
+        #[inline]
+        #[track_caller]
+        pub fn replace(&self, value: T) -> T {
+            core::mem::replace(&mut *self.borrow_mut(), value)
+        }
+    }
+
+    impl<T: ?Sized> WorldCell<T> {
+        /// Immutably borrow the value.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the value is mutably borrowed.
+        #[inline]
+        #[track_caller]
+        pub fn borrow(&self) -> Ref<'_, T> {
+            let n = self.borrows.get();
+            if n < 0 {
+                already_mutably_borrowed();
+            }
+            self.borrows.set(n + 1);
+            Ref {
+                // Shared borrows only coexist with other shared borrows, as checked above.
+                value: unsafe { &*self.value.get() },
+                flag: &self.borrows,
+            }
+        }
+
+        /// Mutably borrow the value.
+        ///
+        /// # Panics
+        ///
+        /// Panics if the value is borrowed.
+        #[inline]
+        #[track_caller]
+        pub fn borrow_mut(&self) -> RefMut<'_, T> {
+            if self.borrows.get() != 0 {
+                already_borrowed();
+            }
+            self.borrows.set(-1);
+            // A mutable borrow is exclusive, as checked above.
+            RefMut::new(
+                unsafe { NonNull::new_unchecked(self.value.get()) },
+                &self.borrows,
+            )
+        }
+
+        /// Immutably borrow the value, or fail if it's mutably borrowed.
+        #[inline]
+        pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+            if self.borrows.get() < 0 {
+                return Err(BorrowError);
+            }
+            Ok(self.borrow())
+        }
+
+        /// Mutably borrow the value, or fail if it's borrowed.
+        #[inline]
+        pub fn try_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowMutError> {
+            if self.borrows.get() != 0 {
+                return Err(BorrowMutError);
+            }
+            Ok(self.borrow_mut())
+        }
+
+        /// Get a mutable reference to the value. No checks are needed, since `self` is borrowed
+        /// mutably.
+        #[inline]
+        pub fn get_mut(&mut self) -> &mut T {
+            self.value.get_mut()
+        }
+    }
+
+    impl<T: Clone> Clone for WorldCell<T> {
+        fn clone(&self) -> Self {
+            WorldCell::new(self.borrow().clone())
+        }
+    }
+
+    impl<T: Default> Default for WorldCell<T> {
+        fn default() -> Self {
+            WorldCell::new(T::default())
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for WorldCell<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("WorldCell")
+                .field("value", &&*self.borrow())
+                .finish()
+        }
+    }
+
+    /// The error returned by `WorldCell::try_borrow`.
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub struct BorrowError;
+
+    impl fmt::Display for BorrowError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("already mutably borrowed")
+        }
+    }
+
+    impl core::error::Error for BorrowError {}
+
+    /// The error returned by `WorldCell::try_borrow_mut`.
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub struct BorrowMutError;
+
+    impl fmt::Display for BorrowMutError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("already borrowed")
+        }
+    }
+
+    impl core::error::Error for BorrowMutError {}
+
+    /// A shared borrow of a `WorldCell`.
+    pub struct Ref<'b, T: ?Sized> {
+        value: &'b T,
+        flag: Flag<'b>,
+    }
+
+    impl<'b, T: ?Sized> Ref<'b, T> {
+        /// Copy the borrow. An associated function, like `core::cell::Ref::clone`.
+        #[allow(clippy::should_implement_trait)]
+        #[inline]
+        pub fn clone(orig: &Ref<'b, T>) -> Ref<'b, T> {
+            orig.flag.set(orig.flag.get() + 1);
+            Ref {
+                value: orig.value,
+                flag: orig.flag,
+            }
+        }
+
+        /// Borrow a part of the value.
+        #[inline]
+        pub fn map<U: ?Sized, F>(orig: Ref<'b, T>, f: F) -> Ref<'b, U>
+        where
+            F: FnOnce(&T) -> &U,
+        {
+            let orig = core::mem::ManuallyDrop::new(orig);
+            Ref {
+                value: f(orig.value),
+                flag: orig.flag,
+            }
+        }
+
+        /// Borrow a part of the value that may not be there, giving back the original borrow if
+        /// it isn't.
+        #[inline]
+        pub fn filter_map<U: ?Sized, F>(orig: Ref<'b, T>, f: F) -> Result<Ref<'b, U>, Self>
+        where
+            F: FnOnce(&T) -> Option<&U>,
+        {
+            match f(orig.value) {
+                Some(value) => {
+                    let orig = core::mem::ManuallyDrop::new(orig);
+                    Ok(Ref {
+                        value,
+                        flag: orig.flag,
+                    })
+                }
+                None => Err(orig),
+            }
+        }
+    }
+
+    impl<T: ?Sized> Deref for Ref<'_, T> {
+        type Target = T;
+
+        #[inline]
+        fn deref(&self) -> &T {
+            self.value
+        }
+    }
+
+    impl<T: ?Sized> Drop for Ref<'_, T> {
+        #[inline]
+        fn drop(&mut self) {
+            self.flag.set(self.flag.get() - 1);
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for Ref<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.value.fmt(f)
+        }
+    }
+
+    impl<T: ?Sized + fmt::Display> fmt::Display for Ref<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.value.fmt(f)
+        }
+    }
+
+    /// A mutable borrow of a `WorldCell`.
+    pub struct RefMut<'b, T: ?Sized> {
+        // A pointer rather than a `&'b mut T`, like `core::cell::RefMut`, so that `filter_map`
+        // can hand out a borrow derived from it and still use it afterwards.
+        value: NonNull<T>,
+        flag: Flag<'b>,
+        marker: PhantomData<&'b mut T>,
+    }
+
+    impl<'b, T: ?Sized> RefMut<'b, T> {
+        // `value` must be valid and exclusively borrowed for `'b`, as recorded by `flag`.
+        #[inline]
+        fn new(value: NonNull<T>, flag: Flag<'b>) -> Self {
+            RefMut {
+                value,
+                flag,
+                marker: PhantomData,
+            }
+        }
+
+        /// Borrow a part of the value.
+        #[inline]
+        pub fn map<U: ?Sized, F>(orig: RefMut<'b, T>, f: F) -> RefMut<'b, U>
+        where
+            F: FnOnce(&mut T) -> &mut U,
+        {
+            let (mut value, flag) = orig.into_parts();
+            // The borrow is exclusive for `'b`, and `orig` is gone.
+            RefMut::new(NonNull::from(f(unsafe { value.as_mut() })), flag)
+        }
+
+        /// Borrow a part of the value that may not be there, giving back the original borrow if
+        /// it isn't.
+        #[inline]
+        pub fn filter_map<U: ?Sized, F>(orig: RefMut<'b, T>, f: F) -> Result<RefMut<'b, U>, Self>
+        where
+            F: FnOnce(&mut T) -> Option<&mut U>,
+        {
+            let (value, flag) = orig.into_parts();
+            // The reference given to `f` is derived from `value`, so once `f` has returned
+            // without keeping it, `value` can be used again.
+            match f(unsafe { &mut *value.as_ptr() }) {
+                Some(part) => Ok(RefMut::new(NonNull::from(part), flag)),
+                None => Err(RefMut::new(value, flag)),
+            }
+        }
+
+        // Take the borrow apart without releasing it.
+        fn into_parts(self) -> (NonNull<T>, Flag<'b>) {
+            let this = core::mem::ManuallyDrop::new(self);
+            (this.value, this.flag)
+        }
+    }
+
+    impl<T: ?Sized> Deref for RefMut<'_, T> {
+        type Target = T;
+
+        #[inline]
+        fn deref(&self) -> &T {
+            // The borrow is exclusive, so the value is only reached through `self`.
+            unsafe { self.value.as_ref() }
+        }
+    }
+
+    impl<T: ?Sized> DerefMut for RefMut<'_, T> {
+        #[inline]
+        fn deref_mut(&mut self) -> &mut T {
+            unsafe { self.value.as_mut() }
+        }
+    }
+
+    impl<T: ?Sized> Drop for RefMut<'_, T> {
+        #[inline]
+        fn drop(&mut self) {
+            self.flag.set(0);
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for RefMut<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            (**self).fmt(f)
+        }
+    }
+
+    impl<T: ?Sized + fmt::Display> fmt::Display for RefMut<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            (**self).fmt(f)
+        }
+    }
+}
@@ -0,0 +1,455 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Change detection for resources and components.
+//!
+//! Every `World` keeps a change tick counter. Whenever a resource is borrowed mutably (including
+//! being fetched as a `WriteResource` for a system, or replaced via `GetResource::set`), the
+//! counter is advanced and the new value is stamped on the resource. Comparing stamps tells you
+//! whether a resource has been touched since you last looked.
+//!
+//! Component storages are stamped the same way, when they're borrowed mutably (with
+//! `GetComponent::get_mut`, say) and when the world adds components to them or removes components
+//! from them, which includes building and deleting entities. A `WriteComponent` or
+//! `RestrictedWriteComponent` only stamps its storage once it's actually written to, so systems
+//! that declare a write but don't make one in a frame don't count as changing anything. `GetComponent::change_tick` gives the
+//! stamp. `WorldInterface::flip` doesn't count as a change, even for storages like
+//! `DoubleBufferedStorage` whose contents it swaps.
+//!
+//! [`ResourceChanged`](struct.ResourceChanged.html) packages this up as a run condition, so that
+//! expensive systems only run when their inputs have changed, and
+//! [`Cached`](struct.Cached.html) is a resource holding a value derived from other resources and
+//! components, which is only recomputed when they change.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # #[derive(Default)]
+//! # pub struct Widget;
+//! #[derive(Default)]
+//! pub struct ScreenSize(u32, u32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             widgets: BasicVecStorage<Widget>,
+//!         }
+//!         resources {
+//!             screen_size: ScreenSize,
+//!         }
+//!     }
+//! );
+//!
+//! let w = World::default();
+//! let mut resized = ResourceChanged::<ScreenSize>::new();
+//!
+//! // The first check always succeeds, so the layout gets computed at least once.
+//! assert!(resized.check(&w));
+//! assert!(!resized.check(&w));
+//!
+//! w.set(ScreenSize(640, 480));
+//! assert!(resized.check(&w));
+//! assert!(!resized.check(&w));
+//! ```
+
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::cell::{Ref, RefMut, WorldCell};
+use crate::*;
+
+/// Container for a resource that remembers when it was last borrowed mutably.
+///
+/// This is what `define_world!` stores resources in. The `tick` arguments are the world's change
+/// tick counter.
+pub struct ResourceCell<T> {
+    value: WorldCell<T>,
+    changed: Cell<u64>,
+}
+
+impl<T> ResourceCell<T> {
+    /// Wrap `value`. It is considered unchanged until it is first borrowed mutably.
+    pub fn new(value: T) -> Self {
+        ResourceCell {
+            value: WorldCell::new(value),
+            changed: Cell::new(0),
+        }
+    }
+
+    /// Immutably borrow the resource.
+    #[inline]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.value.borrow()
+    }
+
+    /// Mutably borrow the resource, marking it as changed.
+    #[inline]
+    pub fn borrow_mut(&self, tick: &Cell<u64>) -> RefMut<'_, T> {
+        let value = self.value.borrow_mut();
+        self.mark_changed(tick);
+        value
+    }
+
+    /// Whether the resource could be borrowed right now, mutably if `write`, without borrowing
+    /// it.
+    #[inline]
+    pub fn can_borrow(&self, write: bool) -> bool {
+        if write {
+            self.value.try_borrow_mut().is_ok()
+        } else {
+            self.value.try_borrow().is_ok()
+        }
+    }
+
+    /// Get a mutable reference to the resource without any runtime borrow checking, marking it as
+    /// changed.
+    #[inline]
+    pub fn get_mut(&mut self, tick: &Cell<u64>) -> &mut T {
+        self.mark_changed(tick);
+        self.value.get_mut()
+    }
+
+    /// Replace the resource, marking it as changed.
+    #[inline]
+    pub fn replace(&self, value: T, tick: &Cell<u64>) -> T {
+        let old = self.value.replace(value);
+        self.mark_changed(tick);
+        old
+    }
+
+    /// The world change tick at which the resource was last borrowed mutably, or 0 if it never
+    /// has been.
+    #[inline]
+    pub fn change_tick(&self) -> u64 {
+        self.changed.get()
+    }
+
+    #[inline]
+    fn mark_changed(&self, tick: &Cell<u64>) {
+        let t = tick.get() + 1;
+        tick.set(t);
+        self.changed.set(t);
+    }
+}
+
+impl<T: Default> Default for ResourceCell<T> {
+    fn default() -> Self {
+        ResourceCell::new(T::default())
+    }
+}
+
+impl<T: Clone> Clone for ResourceCell<T> {
+    fn clone(&self) -> Self {
+        ResourceCell {
+            value: self.value.clone(),
+            changed: self.changed.clone(),
+        }
+    }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for ResourceCell<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ResourceCell")
+            .field("value", &self.value)
+            .field("changed", &self.changed)
+            .finish()
+    }
+}
+
+/// Run condition that holds whenever the resource `T` has been borrowed mutably since the last
+/// time it was checked.
+pub struct ResourceChanged<T> {
+    last_seen: Option<u64>,
+    _t: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for ResourceChanged<T> {
+    fn default() -> Self {
+        ResourceChanged {
+            last_seen: None,
+            _t: PhantomData,
+        }
+    }
+}
+
+impl<T> ResourceChanged<T> {
+    /// Create a new condition. The first call to `check` always returns `true`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if `T` has changed since the last call to `check` (or if this is the first
+    /// call).
+    pub fn check<W: GetResource<T>>(&mut self, world: &W) -> bool {
+        let tick = world.change_tick();
+        let changed = self.last_seen != Some(tick);
+        self.last_seen = Some(tick);
+        changed
+    }
+}
+
+/// The change ticks of a world's component storages, indexed by the order the components are
+/// declared in. `define_world!` keeps one of these to implement `GetComponent::change_tick`.
+#[derive(Clone, Debug, Default)]
+pub struct ComponentTicks {
+    ticks: WorldCell<Vec<u64>>,
+}
+
+impl ComponentTicks {
+    /// Create an empty set of ticks. Every component is considered unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark `component` as changed, advancing the world change tick `tick`.
+    #[inline]
+    pub fn mark(&self, component: usize, tick: &Cell<u64>) {
+        let t = tick.get() + 1;
+        tick.set(t);
+        let mut ticks = self.ticks.borrow_mut();
+        if component >= ticks.len() {
+            ticks.resize(component + 1, 0);
+        }
+        ticks[component] = t;
+    }
+
+    /// The world change tick at which `component` was last changed, or 0 if it never has been.
+    #[inline]
+    pub fn get(&self, component: usize) -> u64 {
+        self.ticks.borrow().get(component).copied().unwrap_or(0)
+    }
+}
+
+/// Marks a component storage as changed the first time a view of it is written to, so that
+/// fetching a `WriteComponent` without writing to it doesn't count as a change. See
+/// `GetComponent::get_mut_unstamped`.
+#[derive(Debug, Default)]
+pub struct ChangeStamp<'a> {
+    target: Option<(&'a ComponentTicks, usize, &'a Cell<u64>)>,
+}
+
+impl<'a> ChangeStamp<'a> {
+    /// Create a stamp that marks `component` in `ticks` as changed, advancing the world change
+    /// tick `tick`.
+    pub fn new(ticks: &'a ComponentTicks, component: usize, tick: &'a Cell<u64>) -> Self {
+        ChangeStamp {
+            target: Some((ticks, component, tick)),
+        }
+    }
+
+    /// Mark the storage as changed, the first time this is called.
+    #[inline]
+    pub fn mark(&mut self) {
+        if let Some((ticks, component, tick)) = self.target.take() {
+            ticks.mark(component, tick);
+        }
+    }
+}
+
+/// The resource `T`, as an input to a [`Cached`](struct.Cached.html) value.
+pub struct ResourceInput<T>(PhantomData<fn() -> T>);
+
+/// The `T` components, as an input to a [`Cached`](struct.Cached.html) value.
+pub struct ComponentInput<T>(PhantomData<fn() -> T>);
+
+/// Resources and components whose changes can be detected, used to declare what a
+/// [`Cached`](struct.Cached.html) value is derived from. Implemented for `ResourceInput<T>`,
+/// `ComponentInput<T>` and tuples of up to 8 of them.
+pub trait ChangeInputs<W> {
+    /// The latest world change tick at which any of the inputs changed.
+    fn last_changed(world: &W) -> u64;
+}
+
+impl<W: GetResource<T>, T> ChangeInputs<W> for ResourceInput<T> {
+    fn last_changed(world: &W) -> u64 {
+        world.change_tick()
+    }
+}
+
+impl<W, T> ChangeInputs<W> for ComponentInput<T>
+where
+    T: for<'a> StorageSpec<'a>,
+    W: for<'a> GetComponent<'a, T>,
+{
+    fn last_changed(world: &W) -> u64 {
+        <W as GetComponent<'_, T>>::change_tick(world)
+    }
+}
+
+macro_rules! impl_change_inputs {
+    ($($t:ident),*) => {
+        impl<W, $($t: ChangeInputs<W>),*> ChangeInputs<W> for ($($t,)*) {
+            fn last_changed(_world: &W) -> u64 {
+                0 $(.max($t::last_changed(_world)))*
+            }
+        }
+    };
+}
+
+impl_change_inputs!();
+impl_change_inputs!(A);
+impl_change_inputs!(A, B);
+impl_change_inputs!(A, B, C);
+impl_change_inputs!(A, B, C, D);
+impl_change_inputs!(A, B, C, D, E);
+impl_change_inputs!(A, B, C, D, E, F);
+impl_change_inputs!(A, B, C, D, E, F, G);
+impl_change_inputs!(A, B, C, D, E, F, G, H);
+
+/// How a [`Cached`](struct.Cached.html) value is computed, and what from.
+pub trait Derive<W> {
+    /// The value.
+    type Output;
+    /// The resources and components the value is computed from, e.g.
+    /// `(ResourceInput<Map>, ComponentInput<Wall>)`.
+    type Inputs: ChangeInputs<W>;
+    /// Compute the value. This must only read from the world; anything it borrows mutably counts
+    /// as a change, and would make the value stale straight away if it's one of the inputs.
+    fn derive(world: &W) -> Self::Output;
+}
+
+/// Resource holding a value derived from other resources and components, such as a flow field
+/// computed from the map, which is only recomputed when one of them has changed.
+///
+/// `F` says how the value is computed, and from what, by implementing
+/// [`Derive`](trait.Derive.html). The value is computed the first time it's asked for, and again
+/// whenever one of the inputs has been stamped with a newer change tick, i.e. borrowed mutably;
+/// see the [module-level documentation](index.html).
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// #[derive(Default)]
+/// pub struct Wall;
+/// #[derive(Default)]
+/// pub struct MapSize(u32);
+///
+/// // The number of open tiles.
+/// pub struct OpenTiles;
+/// impl Derive<World> for OpenTiles {
+///     type Output = usize;
+///     type Inputs = (ResourceInput<MapSize>, ComponentInput<Wall>);
+///     fn derive(w: &World) -> usize {
+///         let walls = w.component::<Wall>().iter().flatten().count();
+///         <World as GetResource<MapSize>>::get(w).0 as usize - walls
+///     }
+/// }
+///
+/// define_world!(
+///     #[derive(Default)]
+///     pub world {
+///         components {
+///             walls: VoidStorage<Wall>,
+///         }
+///         resources {
+///             map_size: MapSize = MapSize(100),
+///             open: Cached<usize, OpenTiles>,
+///         }
+///     }
+/// );
+///
+/// type Open = Cached<usize, OpenTiles>;
+/// let mut w = World::default();
+/// assert_eq!(*<World as GetResource<Open>>::get_mut(&w).get(&w), 100);
+/// assert_eq!(<World as GetResource<Open>>::get(&w).computed(), 1);
+///
+/// w.new_entity().with(Wall).build();
+/// assert_eq!(*<World as GetResource<Open>>::get_mut(&w).get(&w), 99);
+/// assert_eq!(*<World as GetResource<Open>>::get_mut(&w).get(&w), 99);
+/// assert_eq!(<World as GetResource<Open>>::get(&w).computed(), 2);
+/// ```
+pub struct Cached<T, F> {
+    value: Option<T>,
+    // The latest change tick of the inputs when the value was computed.
+    seen: u64,
+    computed: usize,
+    _f: PhantomData<fn() -> F>,
+}
+
+impl<T, F> Default for Cached<T, F> {
+    fn default() -> Self {
+        Cached {
+            value: None,
+            seen: 0,
+            computed: 0,
+            _f: PhantomData,
+        }
+    }
+}
+
+impl<T: Clone, F> Clone for Cached<T, F> {
+    fn clone(&self) -> Self {
+        Cached {
+            value: self.value.clone(),
+            seen: self.seen,
+            computed: self.computed,
+            _f: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Cached<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cached")
+            .field("value", &self.value)
+            .field("seen", &self.seen)
+            .finish()
+    }
+}
+
+impl<T, F> Cached<T, F> {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value, recomputing it first if it has never been computed or any of its inputs have
+    /// changed since it was.
+    pub fn get<W>(&mut self, world: &W) -> &T
+    where
+        F: Derive<W, Output = T>,
+    {
+        let latest = F::Inputs::last_changed(world);
+        if self.value.is_none() || latest != self.seen {
+            self.value = Some(F::derive(world));
+            self.seen = latest;
+            self.computed += 1;
+        }
+        match self.value {
+            Some(ref value) => value,
+            None => unreachable!("the value was just computed"),
+        }
+    }
+
+    /// The value as of the last time it was computed, if it has been, without checking whether
+    /// it's stale.
+    pub fn last(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    /// Forget the value, so it's recomputed the next time it's asked for.
+    pub fn invalidate(&mut self) {
+        self.value = None;
+    }
+
+    /// The number of times the value has been computed.
+    pub fn computed(&self) -> usize {
+        self.computed
+    }
+}
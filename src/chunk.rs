@@ -0,0 +1,202 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Streamed worlds, keyed by chunk coordinate.
+//!
+//! Large overworlds don't need to live in one gigantic entity space. [`Chunks`](struct.Chunks.html)
+//! keeps a separate `World` for each loaded chunk of the map, creating them through a
+//! [`ChunkHooks`](trait.ChunkHooks.html) implementation when they're loaded (from a save file or a
+//! generator, say) and handing them back to it when they're unloaded. `stream` keeps the chunks
+//! around a point loaded, and `migrate` moves an entity that has wandered over a chunk border into
+//! the neighbouring chunk's world.
+//!
+//! Loaded chunks are always visited in coordinate order, so iterating over them is deterministic.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # #[derive(Default)]
+//! # pub struct Monster;
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             monsters: BasicVecStorage<Monster>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut saved = Vec::new();
+//! let mut chunks = Chunks::new((
+//!     |_| World::default(),
+//!     |coord, _world: World| saved.push(coord),
+//! ));
+//!
+//! chunks.stream((0, 0), 1);
+//! assert_eq!(chunks.len(), 9);
+//!
+//! let monster = chunks.load((0, 0)).new_entity().with(Monster).build();
+//! let monster = chunks.migrate((0, 0), monster, (1, 0)).unwrap();
+//! assert!(chunks.get((1, 0)).unwrap().is_alive(monster));
+//!
+//! chunks.stream((1, 0), 1);
+//! drop(chunks);
+//! assert_eq!(saved, vec![(-1, -1), (-1, 0), (-1, 1)]);
+//! ```
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// Coordinates of a chunk.
+pub type ChunkCoord = (i32, i32);
+
+/// Creates and disposes of the worlds in a `Chunks`.
+///
+/// This is implemented for pairs of closures, `(load, unload)`.
+pub trait ChunkHooks<W> {
+    /// Create the world for a chunk that's being loaded.
+    fn load(&mut self, coord: ChunkCoord) -> W;
+    /// Dispose of the world for a chunk that's being unloaded, e.g. by saving it.
+    fn unload(&mut self, coord: ChunkCoord, world: W);
+}
+
+impl<W, L, U> ChunkHooks<W> for (L, U)
+where
+    L: FnMut(ChunkCoord) -> W,
+    U: FnMut(ChunkCoord, W),
+{
+    fn load(&mut self, coord: ChunkCoord) -> W {
+        (self.0)(coord)
+    }
+    fn unload(&mut self, coord: ChunkCoord, world: W) {
+        (self.1)(coord, world)
+    }
+}
+
+/// A set of loaded chunks, each with its own world. See the [module documentation](index.html).
+///
+/// Chunks that are still loaded when this is dropped are not passed to `ChunkHooks::unload`;
+/// call `unload_all` first if they need to be saved.
+pub struct Chunks<W, H> {
+    worlds: BTreeMap<ChunkCoord, W>,
+    hooks: H,
+}
+
+impl<W, H: ChunkHooks<W>> Chunks<W, H> {
+    /// Create an empty set of chunks.
+    pub fn new(hooks: H) -> Self {
+        Chunks {
+            worlds: BTreeMap::new(),
+            hooks,
+        }
+    }
+
+    /// The number of loaded chunks.
+    pub fn len(&self) -> usize {
+        self.worlds.len()
+    }
+
+    /// Whether no chunks are loaded.
+    pub fn is_empty(&self) -> bool {
+        self.worlds.is_empty()
+    }
+
+    /// Whether the chunk at `coord` is loaded.
+    pub fn is_loaded(&self, coord: ChunkCoord) -> bool {
+        self.worlds.contains_key(&coord)
+    }
+
+    /// The world of the chunk at `coord`, if it's loaded.
+    pub fn get(&self, coord: ChunkCoord) -> Option<&W> {
+        self.worlds.get(&coord)
+    }
+
+    /// Mutable version of `get`.
+    pub fn get_mut(&mut self, coord: ChunkCoord) -> Option<&mut W> {
+        self.worlds.get_mut(&coord)
+    }
+
+    /// Iterate over the loaded chunks, in coordinate order.
+    pub fn iter(&self) -> impl Iterator<Item = (ChunkCoord, &W)> + '_ {
+        self.worlds.iter().map(|(c, w)| (*c, w))
+    }
+
+    /// Mutable version of `iter`.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (ChunkCoord, &mut W)> + '_ {
+        self.worlds.iter_mut().map(|(c, w)| (*c, w))
+    }
+
+    /// The world of the chunk at `coord`, loading it first if necessary.
+    pub fn load(&mut self, coord: ChunkCoord) -> &mut W {
+        let hooks = &mut self.hooks;
+        self.worlds
+            .entry(coord)
+            .or_insert_with(|| hooks.load(coord))
+    }
+
+    /// Unload the chunk at `coord`. Returns whether it was loaded.
+    pub fn unload(&mut self, coord: ChunkCoord) -> bool {
+        match self.worlds.remove(&coord) {
+            Some(world) => {
+                self.hooks.unload(coord, world);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Unload every chunk, in coordinate order.
+    pub fn unload_all(&mut self) {
+        for (coord, world) in core::mem::take(&mut self.worlds) {
+            self.hooks.unload(coord, world);
+        }
+    }
+
+    /// Make the loaded chunks exactly the ones within `radius` chunks of `center` (counting
+    /// diagonals as 1). Chunks are unloaded before new ones are loaded, in coordinate order.
+    pub fn stream(&mut self, center: ChunkCoord, radius: i32) {
+        let in_range =
+            |c: ChunkCoord| (c.0 - center.0).abs() <= radius && (c.1 - center.1).abs() <= radius;
+        let far = self
+            .worlds
+            .keys()
+            .copied()
+            .filter(|&c| !in_range(c))
+            .collect::<Vec<_>>();
+        for coord in far {
+            self.unload(coord);
+        }
+        for x in center.0 - radius..=center.0 + radius {
+            for y in center.1 - radius..=center.1 + radius {
+                self.load((x, y));
+            }
+        }
+    }
+
+    /// Move `entity` and all of its components from the chunk at `from` to the chunk at `to`,
+    /// loading `to` if necessary. Returns the entity's handle in its new world, or `None` if
+    /// `from` isn't loaded or the entity isn't alive there.
+    pub fn migrate(&mut self, from: ChunkCoord, entity: Entity, to: ChunkCoord) -> Option<Entity>
+    where
+        W: for<'a> WorldInterface<'a>,
+    {
+        let components = self.worlds.get_mut(&from)?.take_entity(entity)?;
+        Some(self.load(to).build_entity(components))
+    }
+}
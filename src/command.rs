@@ -0,0 +1,263 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deferred mutation of a world.
+//!
+//! Code that can't borrow the world mutably (because it's running inside a system, or because it
+//! finishes at some later point, like an [`AsyncSystem`](../traits/trait.AsyncSystem.html)) can
+//! instead queue up changes in a [`Commands`](struct.Commands.html) buffer, which is applied to
+//! the world later on.
+//!
+//! Systems can also queue changes in the world's own buffer, through a
+//! [`Deferred`](struct.Deferred.html) dependency. The buffer is applied at the end of each stage
+//! of a schedule, at the end of `Dispatcher::run`, or by `WorldInterface::apply_deferred`, so
+//! systems running alongside each other don't see each other's queued changes. In particular, a system that only reads a resource can
+//! still change it with `Deferred::mutate_resource`, without declaring write access that would
+//! stop it from sharing a stage with the resource's other readers.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Health(i32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             health: BasicVecStorage<Health>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let e = w.new_entity().with(Health(3)).build();
+//!
+//! let mut commands = Commands::new();
+//! commands.push(move |w: &mut World| w.delete_entity(e));
+//! assert!(<World as GetComponent<'_, Health>>::get(&w).get(e).is_some());
+//!
+//! commands.apply(&mut w);
+//! assert!(<World as GetComponent<'_, Health>>::get(&w).get(e).is_none());
+//! ```
+//!
+//! Deferring a change to a resource from a system:
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # #[derive(Default)]
+//! # pub struct Health(i32);
+//! # define_world!(
+//! #     #[derive(Default)]
+//! #     pub world {
+//! #         components {
+//! #             health: BasicVecStorage<Health>,
+//! #         }
+//! #         resources {
+//! #             deaths: u32,
+//! #         }
+//! #     }
+//! # );
+//! struct CountDeaths;
+//! impl<'a> System<'a> for CountDeaths {
+//!     type Dependencies = (ReadComponent<'a, Health>, Deferred<'a, World>);
+//!     fn run(&'a mut self, (health, deferred): Self::Dependencies) {
+//!         let dead = health.iter().flatten().filter(|h| h.0 <= 0).count() as u32;
+//!         deferred.mutate_resource(move |deaths: &mut u32| *deaths += dead);
+//!     }
+//! }
+//!
+//! let mut w = World::default();
+//! w.new_entity().with(Health(0)).build();
+//! w.new_entity().with(Health(3)).build();
+//! let mut dispatcher = Dispatcher::new().with(&mut w, CountDeaths);
+//! dispatcher.run(&mut w).unwrap();
+//! dispatcher.run(&mut w).unwrap();
+//! assert_eq!(*<World as GetResource<u32>>::get(&w), 2);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::cell::WorldCell;
+use crate::GetResource;
+
+type Command<W> = Box<dyn FnOnce(&mut W)>;
+
+/// A queue of deferred operations on a world of type `W`.
+pub struct Commands<W> {
+    queue: Vec<Command<W>>,
+}
+
+impl<W> Default for Commands<W> {
+    fn default() -> Self {
+        Commands { queue: Vec::new() }
+    }
+}
+
+impl<W> Commands<W> {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue an operation.
+    pub fn push<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut W) + 'static,
+    {
+        self.queue.push(Box::new(f));
+    }
+
+    /// Queue replacing the world's resource of type `T` with `value`.
+    pub fn insert_resource<T>(&mut self, value: T)
+    where
+        W: GetResource<T>,
+        T: 'static,
+    {
+        self.push(move |w: &mut W| w.set(value));
+    }
+
+    /// Queue a change to the world's resource of type `T`, made by calling `f` on it.
+    pub fn mutate_resource<T, F>(&mut self, f: F)
+    where
+        W: GetResource<T>,
+        F: FnOnce(&mut T) + 'static,
+    {
+        self.push(move |w: &mut W| f(&mut *w.get_mut()));
+    }
+
+    /// Move all of the operations in `other` to the end of this queue.
+    pub fn append(&mut self, other: &mut Commands<W>) {
+        self.queue.append(&mut other.queue);
+    }
+
+    /// Apply all of the queued operations to `world`, in the order they were queued, leaving the
+    /// queue empty.
+    pub fn apply(&mut self, world: &mut W) {
+        for f in self.queue.drain(..) {
+            f(world);
+        }
+    }
+
+    /// Number of queued operations.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether there are no queued operations.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+impl<W> core::fmt::Debug for Commands<W> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Commands")
+            .field("len", &self.queue.len())
+            .finish()
+    }
+}
+
+/// The world's own buffer of changes, queued by systems' `Deferred` dependencies. See the
+/// [module-level documentation](index.html).
+pub struct DeferredCommands<W> {
+    queue: WorldCell<Commands<W>>,
+}
+
+impl<W> Default for DeferredCommands<W> {
+    fn default() -> Self {
+        DeferredCommands {
+            queue: WorldCell::new(Commands::new()),
+        }
+    }
+}
+
+// Queued changes belong to the world they were queued for, so clones start out empty.
+impl<W> Clone for DeferredCommands<W> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<W> DeferredCommands<W> {
+    /// Take the queued changes, leaving the buffer empty.
+    pub fn take(&self) -> Commands<W> {
+        core::mem::take(&mut *self.queue.borrow_mut())
+    }
+
+    /// Number of queued changes.
+    pub fn len(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    /// Whether there are no queued changes.
+    pub fn is_empty(&self) -> bool {
+        self.queue.borrow().is_empty()
+    }
+}
+
+impl<W> core::fmt::Debug for DeferredCommands<W> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DeferredCommands")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// System dependency for queueing changes in the world's `DeferredCommands`, which are applied
+/// once the system has finished. See the [module-level documentation](index.html).
+pub struct Deferred<'a, W> {
+    pub(crate) commands: &'a DeferredCommands<W>,
+}
+
+impl<'a, W> Deferred<'a, W> {
+    /// Queue an operation.
+    pub fn push<F>(&self, f: F)
+    where
+        F: FnOnce(&mut W) + 'static,
+    {
+        self.commands.queue.borrow_mut().push(f);
+    }
+
+    /// Queue replacing the world's resource of type `T` with `value`.
+    pub fn insert_resource<T>(&self, value: T)
+    where
+        W: GetResource<T>,
+        T: 'static,
+    {
+        self.commands.queue.borrow_mut().insert_resource(value);
+    }
+
+    /// Queue a change to the world's resource of type `T`, made by calling `f` on it.
+    pub fn mutate_resource<T, F>(&self, f: F)
+    where
+        W: GetResource<T>,
+        F: FnOnce(&mut T) + 'static,
+    {
+        self.commands.queue.borrow_mut().mutate_resource(f);
+    }
+}
+
+impl<'a, W> core::fmt::Debug for Deferred<'a, W> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Deferred")
+            .field("len", &self.commands.len())
+            .finish()
+    }
+}
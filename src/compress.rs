@@ -0,0 +1,129 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pluggable compression for saves.
+//!
+//! A [`Compression`](trait.Compression.html) wraps a `Write` in an encoder and a `Read` in a
+//! decoder. The world's `save_to` and `load_from` take one, and since the encoder is itself a
+//! `Write`, an `IncrementalSave` or any other byte stream can be written through it as well.
+//!
+//! [`Uncompressed`](struct.Uncompressed.html) passes bytes through as they are. With the `deflate`
+//! feature enabled, [`Deflate`](struct.Deflate.html) compresses them with DEFLATE.
+//!
+//! # Example
+//!
+//! ```
+//! # use ecstatic::*;
+//! use std::io::{Read, Write};
+//!
+//! let mut file = Vec::new();
+//! let mut out = Uncompressed.compress(&mut file);
+//! out.write_all(b"save data").unwrap();
+//! out.finish().unwrap();
+//!
+//! let mut input = &file[..];
+//! let mut bytes = Vec::new();
+//! Uncompressed.decompress(&mut input).read_to_end(&mut bytes).unwrap();
+//! assert_eq!(bytes, b"save data");
+//! ```
+
+use alloc::boxed::Box;
+use std::io::{self, Read, Write};
+
+/// A writer that compresses what's written to it. `finish` must be called once everything has
+/// been written, so that the compressed stream is complete.
+pub trait CompressWrite: Write {
+    /// Write out anything still buffered, along with whatever the format needs to end the stream.
+    fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// A compression scheme, as a pair of adapters.
+pub trait Compression {
+    /// Wrap `out` so that everything written to the result is compressed into it.
+    fn compress<'w>(&self, out: &'w mut dyn Write) -> Box<dyn CompressWrite + 'w>;
+
+    /// Wrap `input`, which holds data written through `compress`, so that reading from the result
+    /// gives the original bytes.
+    fn decompress<'r>(&self, input: &'r mut dyn Read) -> Box<dyn Read + 'r>;
+}
+
+/// No compression at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Uncompressed;
+
+struct Passthrough<'w>(&'w mut dyn Write);
+
+impl<'w> Write for Passthrough<'w> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<'w> CompressWrite for Passthrough<'w> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Compression for Uncompressed {
+    fn compress<'w>(&self, out: &'w mut dyn Write) -> Box<dyn CompressWrite + 'w> {
+        Box::new(Passthrough(out))
+    }
+
+    fn decompress<'r>(&self, input: &'r mut dyn Read) -> Box<dyn Read + 'r> {
+        Box::new(input)
+    }
+}
+
+/// DEFLATE compression (RFC 1951), with a level from 0 (none) to 9 (smallest). The default level
+/// is 6.
+#[cfg(feature = "deflate")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deflate {
+    /// The compression level, from 0 to 9.
+    pub level: u32,
+}
+
+#[cfg(feature = "deflate")]
+impl Default for Deflate {
+    fn default() -> Self {
+        Deflate { level: 6 }
+    }
+}
+
+#[cfg(feature = "deflate")]
+impl CompressWrite for flate2::write::DeflateEncoder<&mut dyn Write> {
+    fn finish(self: Box<Self>) -> io::Result<()> {
+        let out = flate2::write::DeflateEncoder::finish(*self)?;
+        out.flush()
+    }
+}
+
+#[cfg(feature = "deflate")]
+impl Compression for Deflate {
+    fn compress<'w>(&self, out: &'w mut dyn Write) -> Box<dyn CompressWrite + 'w> {
+        Box::new(flate2::write::DeflateEncoder::new(
+            out,
+            flate2::Compression::new(self.level.min(9)),
+        ))
+    }
+
+    fn decompress<'r>(&self, input: &'r mut dyn Read) -> Box<dyn Read + 'r> {
+        Box::new(flate2::read::DeflateDecoder::new(input))
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Components computed from other components and resources.
+//!
+//! Some components are pure functions of others: the total weight of what's in an entity's
+//! inventory, the bounding box of a multi-tile entity's tiles. A
+//! [`Computed`](trait.Computed.html) component declares what it's computed from, and the
+//! [`UpdateComputed<C>`](struct.UpdateComputed.html) system keeps it up to date, recomputing it
+//! only when one of those inputs has changed since the system last ran, as detected by the
+//! world's change ticks (see the [`change`](../change/index.html) module). Computed components
+//! whose inputs haven't changed are left alone, so a world can have many of them without paying
+//! for all of them every frame.
+//!
+//! Change ticks are kept per storage and per resource, not per entity, so invalidation is all or
+//! nothing: writing to a component input, even to change one entity's component, recomputes `C`
+//! for every entity with the `Source` component. Only writes count, so systems that fetch an input
+//! as a `WriteComponent` but leave it alone in a frame don't cause a recompute; resources count as
+//! changed whenever they're borrowed mutably, though. Inputs that are written to every frame make
+//! the computed component no cheaper than a plain system that recomputes it every frame.
+//!
+//! Every entity with the computed component's `Source` component gets one, and entities that lose
+//! their `Source` component lose it too. For values derived from the whole world rather than one
+//! entity, use a [`Cached`](../change/struct.Cached.html) resource instead.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Inventory(Vec<Entity>);
+//! #[derive(Default)]
+//! pub struct Weight(u32);
+//! #[derive(Debug, Default, PartialEq)]
+//! pub struct TotalWeight(u32);
+//!
+//! impl Computed<World> for TotalWeight {
+//!     type Source = Inventory;
+//!     type Inputs = (ComponentInput<Inventory>, ComponentInput<Weight>);
+//!     fn compute(w: &World, entity: Entity) -> Option<Self> {
+//!         let inventories = w.component::<Inventory>();
+//!         let weights = w.component::<Weight>();
+//!         let items = &inventories.get(entity)?.0;
+//!         Some(TotalWeight(
+//!             items.iter().filter_map(|&i| weights.get(i)).map(|w| w.0).sum(),
+//!         ))
+//!     }
+//! }
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             inventories: BasicVecStorage<Inventory>,
+//!             weights: BasicVecStorage<Weight>,
+//!             total_weights: BasicVecStorage<TotalWeight>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let sword = w.new_entity().with(Weight(3)).build();
+//! let shield = w.new_entity().with(Weight(5)).build();
+//! let hero = w.new_entity().with(Inventory(vec![sword, shield])).build();
+//!
+//! let mut dispatcher = Dispatcher::new().with(&mut w, UpdateComputed::<TotalWeight>::new());
+//! dispatcher.run(&mut w).unwrap();
+//! assert_eq!(w.component::<TotalWeight>().get(hero), Some(&TotalWeight(8)));
+//!
+//! w.delete_entity(shield);
+//! dispatcher.run(&mut w).unwrap();
+//! assert_eq!(w.component::<TotalWeight>().get(hero), Some(&TotalWeight(3)));
+//! ```
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::*;
+
+/// A component computed from other components and resources. See the
+/// [module-level documentation](index.html).
+pub trait Computed<W>: Sized {
+    /// The component whose entities get this one.
+    type Source;
+    /// The resources and components this one is computed from, e.g.
+    /// `(ComponentInput<Inventory>, ComponentInput<Weight>)`. Changes to the `Source` storage
+    /// always count, whether or not it's listed. The computed component itself mustn't be listed,
+    /// or it would always be out of date.
+    type Inputs: ChangeInputs<W>;
+    /// Compute `entity`'s component, or `None` if it shouldn't have one. This must only read from
+    /// the world.
+    fn compute(world: &W, entity: Entity) -> Option<Self>;
+}
+
+/// System that keeps the computed component `C` up to date, recomputing it for every entity with
+/// its `Source` component when any of its inputs have changed since the system last ran. A change
+/// to one entity's input recomputes every entity's `C`. See the
+/// [module-level documentation](index.html).
+pub struct UpdateComputed<C> {
+    // The latest change tick of the inputs when `C` was last computed.
+    seen: Option<u64>,
+    passes: usize,
+    tag: PhantomData<fn() -> C>,
+}
+
+impl<C> UpdateComputed<C> {
+    /// Create the system.
+    pub fn new() -> Self {
+        UpdateComputed {
+            seen: None,
+            passes: 0,
+            tag: PhantomData,
+        }
+    }
+
+    /// Make the next run recompute `C` whether or not its inputs have changed, e.g. after
+    /// changing something `Computed::compute` reads that isn't one of its declared inputs.
+    pub fn invalidate(&mut self) {
+        self.seen = None;
+    }
+
+    /// The number of times the system has recomputed `C`.
+    pub fn passes(&self) -> usize {
+        self.passes
+    }
+}
+
+impl<C> Default for UpdateComputed<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W, C> RunSystem<W> for UpdateComputed<C>
+where
+    C: Computed<W> + for<'a> StorageSpec<'a, Component = C>,
+    C::Source: for<'a> StorageSpec<'a>,
+    W: for<'a> GetComponent<'a, C>
+        + for<'a> GetComponent<'a, C::Source>
+        + for<'a> WorldInterface<'a>
+        + 'static,
+{
+    type Input = ();
+    type Output = ();
+    fn run_on(&mut self, world: &mut W, _: ()) {
+        let latest = C::Inputs::last_changed(world)
+            .max(<W as GetComponent<'_, C::Source>>::change_tick(world));
+        if self.seen == Some(latest) {
+            return;
+        }
+        // Compute everything before changing anything, so `compute` sees a consistent world.
+        let mut values = Vec::new();
+        let mut stale = Vec::new();
+        {
+            let sources = <W as GetComponent<'_, C::Source>>::get(world);
+            for id in 0..sources.size() {
+                if let Some(e) = sources.entity(id) {
+                    values.push((e, C::compute(world, e)));
+                }
+            }
+            let computed = <W as GetComponent<'_, C>>::get(world);
+            for id in 0..computed.size() {
+                if let Some(e) = computed.entity(id) {
+                    if sources.get(e).is_none() {
+                        stale.push(e);
+                    }
+                }
+            }
+        }
+        for e in stale {
+            <W as GetComponent<'_, C>>::remove(world, e);
+        }
+        for (e, value) in values {
+            match value {
+                Some(v) => {
+                    <W as GetComponent<'_, C>>::insert(world, e, v);
+                }
+                None => {
+                    <W as GetComponent<'_, C>>::remove(world, e);
+                }
+            }
+        }
+        self.seen = Some(latest);
+        self.passes += 1;
+    }
+}
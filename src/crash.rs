@@ -0,0 +1,251 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Writing the world to disk when the game crashes.
+//!
+//! A bug report that comes with the state the game was in when it crashed can be reproduced. A
+//! [`CrashReporter`](struct.CrashReporter.html) writes that state out: wrap the game loop (or
+//! each frame) in [`catch`](struct.CrashReporter.html#method.catch), and if it panics, the
+//! reporter writes a dump file to its directory before letting the panic carry on. The dump holds,
+//! compressed with the reporter's `Compression`:
+//!
+//! * the panic message and where it happened, if the hook from
+//!   [`install_hook`](struct.CrashReporter.html#method.install_hook) is installed;
+//! * the system that panicked, as recorded by the world's
+//!   [`Poison`](../poison/struct.Poison.html);
+//! * the last entries in the world's `Journal` (with the `journal` feature), to show how the
+//!   world got there;
+//! * the world itself, as the text from `World::snapshot`.
+//!
+//! The world is dumped after the panic has unwound out of the systems, so none of its storages
+//! are borrowed any more. `World` implements [`CrashDump`](trait.CrashDump.html) as long as every
+//! component and resource implements `Debug`, as for `snapshot`.
+//!
+//! Because the dump is written while the panic unwinds, nothing is written in builds that abort
+//! on panic (`panic = "abort"` in the Cargo profile): the process ends as soon as the panic hook
+//! returns, before `catch` gets control back. The hook still records the panic message, but
+//! games that need dumps should keep unwinding enabled.
+//!
+//! This module needs the `std` feature.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Debug, Default)]
+//! pub struct Position(i32, i32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! struct Explode;
+//! impl<'a> System<'a> for Explode {
+//!     type Dependencies = (ReadComponent<'a, Position>,);
+//!     fn run(&'a mut self, _: Self::Dependencies) {
+//!         panic!("kaboom");
+//!     }
+//! }
+//!
+//! let dir = std::env::temp_dir().join("ecstatic-crash-example");
+//! let reporter = CrashReporter::new(&dir, Uncompressed);
+//! CrashReporter::install_hook();
+//!
+//! let mut w = World::default();
+//! w.new_entity().with(Position(1, 2)).build();
+//! let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+//!     reporter.catch(&mut w, |w| w.run_system(&mut Explode));
+//! }));
+//! assert!(result.is_err());
+//!
+//! let dump = reporter.read(reporter.last_dump().unwrap()).unwrap();
+//! assert!(dump.contains("kaboom"));
+//! assert!(dump.contains("Position(1, 2)"));
+//! # std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::cell::RefCell;
+use core::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cell::RwLock;
+use crate::*;
+
+std::thread_local! {
+    // The last panic on this thread, as recorded by the hook.
+    static LAST_PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// A world that can be written into a crash dump. See the [module-level
+/// documentation](index.html).
+pub trait CrashDump {
+    /// Write a dump of the world with `reporter`, returning the file's path.
+    fn write_crash_dump(&self, reporter: &CrashReporter) -> io::Result<PathBuf>;
+}
+
+/// Writes crash dumps of a world to a directory. See the [module-level
+/// documentation](index.html).
+pub struct CrashReporter {
+    dir: PathBuf,
+    compression: Arc<dyn Compression + Send + Sync>,
+    journal_tail: usize,
+    last_dump: RwLock<Option<PathBuf>>,
+}
+
+impl CrashReporter {
+    /// Create a reporter that writes dumps to `dir`, creating it if necessary, compressed with
+    /// `compression`.
+    pub fn new<P, C>(dir: P, compression: C) -> Self
+    where
+        P: Into<PathBuf>,
+        C: Compression + Send + Sync + 'static,
+    {
+        CrashReporter {
+            dir: dir.into(),
+            compression: Arc::new(compression),
+            journal_tail: 256,
+            last_dump: RwLock::new(None),
+        }
+    }
+
+    /// Include the last `n` journal entries in dumps, instead of the default 256, builder style.
+    pub fn journal_tail(mut self, n: usize) -> Self {
+        self.journal_tail = n;
+        self
+    }
+
+    /// Install a panic hook that records each panic's message and location for the dump, before
+    /// passing the panic on to the hook that was installed before it. Call this once, at
+    /// startup.
+    pub fn install_hook() {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let report = format!("{}", info);
+            // A panic while the record is borrowed is a panic in the hook, which aborts anyway.
+            let _ = LAST_PANIC.try_with(|last| {
+                if let Ok(mut last) = last.try_borrow_mut() {
+                    *last = Some(report);
+                }
+            });
+            previous(info);
+        }));
+    }
+
+    /// Run `f` on `world`, and if it panics, write a dump of the world before carrying on with
+    /// the panic. Where the dump went is printed to standard error, and available from
+    /// `last_dump`.
+    ///
+    /// No dump is written if panics abort instead of unwinding; see the [module-level
+    /// documentation](index.html).
+    pub fn catch<W, R, F>(&self, world: &mut W, f: F) -> R
+    where
+        W: CrashDump,
+        F: FnOnce(&mut W) -> R,
+    {
+        let _ = LAST_PANIC.try_with(|last| last.borrow_mut().take());
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(&mut *world))) {
+            Ok(result) => result,
+            Err(payload) => {
+                match world.write_crash_dump(self) {
+                    Ok(path) => std::eprintln!("wrote crash dump to {}", path.display()),
+                    Err(e) => std::eprintln!("couldn't write crash dump: {}", e),
+                }
+                std::panic::resume_unwind(payload)
+            }
+        }
+    }
+
+    /// The path of the last dump this reporter wrote, if any.
+    pub fn last_dump(&self) -> Option<PathBuf> {
+        self.last_dump.read().clone()
+    }
+
+    /// Write a dump made of a world's `snapshot`, journal and poison record, returning the file's
+    /// path. This is what `CrashDump::write_crash_dump` does for `World`.
+    pub fn write(&self, snapshot: &str, journal: &Journal, poison: &Poison) -> io::Result<PathBuf> {
+        let mut text = String::new();
+        text.push_str("# panic\n");
+        let report = LAST_PANIC
+            .try_with(|last| last.borrow().clone())
+            .ok()
+            .flatten()
+            .or_else(|| poison.message());
+        let _ = writeln!(text, "{}", report.as_deref().unwrap_or("(unknown)"));
+        let _ = writeln!(text, "\n# system\n{}", poison.system().unwrap_or("(none)"));
+        text.push_str("\n# journal\n");
+        #[cfg(feature = "journal")]
+        {
+            let entries = journal.entries();
+            for entry in &entries[entries.len().saturating_sub(self.journal_tail)..] {
+                let _ = writeln!(text, "{:?}", entry);
+            }
+        }
+        #[cfg(not(feature = "journal"))]
+        {
+            let _ = (journal, self.journal_tail);
+            text.push_str("(not recorded without the `journal` feature)\n");
+        }
+        text.push_str("\n# world\n");
+        text.push_str(snapshot);
+
+        std::fs::create_dir_all(&self.dir)?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        let mut path = self.dir.join(format!("crash-{}.dump", millis));
+        let mut n = 1;
+        while path.exists() {
+            path = self.dir.join(format!("crash-{}-{}.dump", millis, n));
+            n += 1;
+        }
+        let mut file = std::fs::File::create(&path)?;
+        let mut out = self.compression.compress(&mut file);
+        out.write_all(text.as_bytes())?;
+        out.finish()?;
+        *self.last_dump.write() = Some(path.clone());
+        Ok(path)
+    }
+
+    /// Read back a dump written by this reporter, e.g. one attached to a bug report.
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> io::Result<String> {
+        let mut file = std::fs::File::open(path)?;
+        let mut text = String::new();
+        self.compression
+            .decompress(&mut file)
+            .read_to_string(&mut text)?;
+        Ok(text)
+    }
+}
+
+impl core::fmt::Debug for CrashReporter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CrashReporter")
+            .field("dir", &self.dir)
+            .field("journal_tail", &self.journal_tail)
+            .field("last_dump", &self.last_dump())
+            .finish()
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Dirty-region tracking for tile maps.
+//!
+//! [`DirtyRegion2D`](struct.DirtyRegion2D.html) is a bit grid over map coordinates, meant to be
+//! kept as a resource. Systems that change the map (digging, opening doors, moving light sources)
+//! mark the tiles they touch, and systems that derive data from the map (FOV, lighting, pathing
+//! costs) recompute just the marked tiles and then clear the region.
+//!
+//! Movement can be marked automatically: if positions are kept in an `InterpolatedStorage`, which
+//! remembers each component's value as of the last `WorldInterface::flip`, `mark_moved` marks the
+//! old and new tiles of everything that has moved since then.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Clone, Default)]
+//! pub struct Position(usize, usize);
+//!
+//! impl TilePosition for Position {
+//!     fn tile(&self) -> (usize, usize) {
+//!         (self.0, self.1)
+//!     }
+//! }
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: InterpolatedStorage<Position>,
+//!         }
+//!         resources {
+//!             dirty: DirtyRegion2D,
+//!         }
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! w.set(DirtyRegion2D::new(80, 25));
+//! let torch = w.new_entity().with(Position(3, 4)).build();
+//!
+//! w.flip();
+//! {
+//!     let (mut positions,): (WriteComponent<Position>,) = ComponentProvider::fetch(&w);
+//!     positions.get_mut(torch).unwrap().0 += 1;
+//! }
+//!
+//! let (positions, mut dirty): (ReadComponent<Position>, WriteResource<DirtyRegion2D>) =
+//!     ComponentProvider::fetch(&w);
+//! dirty.mark_moved(&positions);
+//! assert_eq!(dirty.iter().collect::<Vec<_>>(), vec![(3, 4), (4, 4)]);
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::bitset::BitVec;
+use crate::*;
+
+/// A component that has a position on a tile map.
+pub trait TilePosition {
+    /// The `(x, y)` coordinates of the tile.
+    fn tile(&self) -> (usize, usize);
+}
+
+/// Set of tiles on a `width` by `height` map that need to be recomputed. See the
+/// [module documentation](index.html).
+///
+/// Coordinates outside of the map are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DirtyRegion2D {
+    width: usize,
+    height: usize,
+    bits: Vec<u32>,
+}
+
+impl DirtyRegion2D {
+    /// Create a region for a map of the given size, with no tiles marked.
+    pub fn new(width: usize, height: usize) -> Self {
+        DirtyRegion2D {
+            width,
+            height,
+            bits: vec![0; (width * height).div_ceil(32)],
+        }
+    }
+
+    /// Width of the map.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height of the map.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y * self.width + x)
+        } else {
+            None
+        }
+    }
+
+    /// Mark the tile at `(x, y)`.
+    pub fn mark(&mut self, x: usize, y: usize) {
+        if let Some(i) = self.index(x, y) {
+            self.bits.set_bit(i);
+        }
+    }
+
+    /// Mark every tile in the `w` by `h` rectangle whose top left corner is at `(x, y)`.
+    pub fn mark_rect(&mut self, x: usize, y: usize, w: usize, h: usize) {
+        for ty in y..(y + h).min(self.height) {
+            for tx in x..(x + w).min(self.width) {
+                self.mark(tx, ty);
+            }
+        }
+    }
+
+    /// Mark the whole map, e.g. after loading a level.
+    pub fn mark_all(&mut self) {
+        self.mark_rect(0, 0, self.width, self.height);
+    }
+
+    /// Mark the old and new tiles of every component in `positions` that has moved to a different
+    /// tile since the last `WorldInterface::flip`.
+    ///
+    /// Components that were added since then haven't moved, as far as the storage is concerned,
+    /// and aren't marked; neither are the tiles of components that have been removed.
+    pub fn mark_moved<T: TilePosition>(&mut self, positions: &InterpolatedStorage<T>) {
+        for (_, prev, current) in positions.iter_with_prev() {
+            let (from, to) = (prev.tile(), current.tile());
+            if from != to {
+                self.mark(from.0, from.1);
+                self.mark(to.0, to.1);
+            }
+        }
+    }
+
+    /// Whether the tile at `(x, y)` is marked.
+    pub fn is_dirty(&self, x: usize, y: usize) -> bool {
+        self.index(x, y).is_some_and(|i| self.bits.get_bit(i))
+    }
+
+    /// Whether no tiles are marked.
+    pub fn is_clean(&self) -> bool {
+        self.bits.iter().all(|&b| b == 0)
+    }
+
+    /// Iterate over the coordinates of the marked tiles, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.width * self.height)
+            .filter(move |&i| self.bits.get_bit(i))
+            .map(move |i| (i % self.width, i / self.width))
+    }
+
+    /// Unmark every tile.
+    pub fn clear(&mut self) {
+        for b in self.bits.iter_mut() {
+            *b = 0;
+        }
+    }
+}
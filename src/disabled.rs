@@ -0,0 +1,126 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Disabling entities without deleting them.
+//!
+//! `WorldInterface::disable` puts an entity in stasis: it keeps all of its components, but joins
+//! skip it, so systems act as if it wasn't there until it's `enable`d again. This suits monsters
+//! on levels the player isn't on, entities far off-screen, and things an editor has hidden.
+//!
+//! Every world keeps the set of disabled entities, [`Disabled`](struct.Disabled.html), and the
+//! `ReadComponent`, `WriteComponent` and `RestrictedWriteComponent` views it hands out refer to
+//! it. A join skips an entity if any of the views in it would; wrapping the join with
+//! `Join::including_disabled` visits disabled entities too. Looking components up by entity
+//! (`storage.get(e)`) isn't affected, and neither are joins of views that don't come from a
+//! world, like a `MultiView` on its own.
+//!
+//! `World::save` records which entities are disabled, and `World::load` disables them again.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Position(i32, i32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let near = w.new_entity().with(Position(1, 1)).build();
+//! let far = w.new_entity().with(Position(500, 500)).build();
+//! w.disable(far);
+//!
+//! let (positions,) = w.fetch::<(ReadComponent<Position>,)>().unwrap();
+//! let mut seen = Vec::new();
+//! (&positions,).for_each(|e, _| seen.push(e));
+//! assert_eq!(seen, vec![near]);
+//!
+//! seen.clear();
+//! (&positions,).including_disabled().for_each(|e, _| seen.push(e));
+//! assert_eq!(seen, vec![near, far]);
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::Entity;
+
+/// The set of a world's disabled entities. See the [module-level documentation](index.html).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Disabled {
+    // Indexed by entity id.
+    disabled: Vec<Option<Entity>>,
+    count: usize,
+}
+
+impl Disabled {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `entity` to the set. Use `WorldInterface::disable`, which checks that the entity is
+    /// alive, rather than calling this directly.
+    pub fn insert(&mut self, entity: Entity) {
+        if entity.id() >= self.disabled.len() {
+            self.disabled.resize(entity.id() + 1, None);
+        }
+        if self.disabled[entity.id()].replace(entity).is_none() {
+            self.count += 1;
+        }
+    }
+
+    /// Remove `entity` from the set.
+    pub fn remove(&mut self, entity: Entity) {
+        if self.contains(entity) {
+            self.disabled[entity.id()] = None;
+            self.count -= 1;
+        }
+    }
+
+    /// Whether `entity` is disabled.
+    #[inline]
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.disabled.get(entity.id()) == Some(&Some(entity))
+    }
+
+    /// The number of disabled entities.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether no entities are disabled.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Iterate over the disabled entities, in ascending id order.
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.disabled.iter().flatten().copied()
+    }
+
+    /// Empty the set.
+    pub fn clear(&mut self) {
+        self.disabled.clear();
+        self.count = 0;
+    }
+}
@@ -0,0 +1,1105 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Running collections of systems against a world.
+//!
+//! The building block is [`RunSystem<W>`](trait.RunSystem.html), which is implemented by
+//! anything that can be run against a world of type `W`: every `System`, `TrySystem` and
+//! `OutputSystem` whose dependencies `W` can provide, as well as combinations of those, like
+//! [`Chain`](struct.Chain.html).
+//!
+//! A [`Dispatcher`](struct.Dispatcher.html) owns a list of such systems and runs them in order,
+//! stopping at the first one that fails. Systems are added with the world they'll run against, and
+//! the dispatcher calls each one's `setup` right away, so that systems can give the resources
+//! they use their initial values, or spawn the entities they need, themselves instead of relying
+//! on the game to do it.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Position(i32, i32);
+//! #[derive(Default)]
+//! pub struct Goal(i32, i32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!             goals: BasicVecStorage<Goal>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! /// Works out which way each entity with a `Goal` should step.
+//! struct Plan;
+//! impl<'a> OutputSystem<'a> for Plan {
+//!     type Dependencies = (ReadComponent<'a, Position>, ReadComponent<'a, Goal>);
+//!     type Input = ();
+//!     type Output = Vec<(Entity, (i32, i32))>;
+//!     fn run_with(&'a mut self, _: (), (pos, goal): Self::Dependencies) -> Self::Output {
+//!         let mut steps = vec![];
+//!         (&pos, &goal).for_each(|e, (p, g)| {
+//!             steps.push((e, ((g.0 - p.0).signum(), (g.1 - p.1).signum())));
+//!         });
+//!         steps
+//!     }
+//! }
+//!
+//! /// Applies the steps produced by `Plan`.
+//! struct Move;
+//! impl<'a> OutputSystem<'a> for Move {
+//!     type Dependencies = (WriteComponent<'a, Position>,);
+//!     type Input = Vec<(Entity, (i32, i32))>;
+//!     type Output = ();
+//!     fn run_with(&'a mut self, steps: Self::Input, (mut pos,): Self::Dependencies) {
+//!         for (e, (dx, dy)) in steps {
+//!             if let Some(p) = pos.get_mut(e) {
+//!                 p.0 += dx;
+//!                 p.1 += dy;
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! let mut w = World::default();
+//! let e = w.new_entity().with(Position(0, 0)).with(Goal(5, -5)).build();
+//!
+//! let mut dispatcher = Dispatcher::new().with(&mut w, Chain::new(Plan, Move));
+//! dispatcher.run(&mut w).unwrap();
+//! dispatcher.run(&mut w).unwrap();
+//!
+//! let pos = <World as GetComponent<'_, Position>>::get(&w);
+//! assert_eq!((pos.get(e).unwrap().0, pos.get(e).unwrap().1), (2, -2));
+//! ```
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+
+use crate::*;
+
+/// Something that can be run against a world of type `W`.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be run on `{W}`",
+    note = "every component and resource a system depends on must be declared in the world's \
+            `define_world!`"
+)]
+pub trait RunSystem<W> {
+    /// Extra input passed in when running.
+    type Input;
+    /// The value produced by running.
+    type Output;
+    /// Run against `world`.
+    fn run_on(&mut self, world: &mut W, input: Self::Input) -> Self::Output;
+    /// Prepare `world` for the system, when it's added to a `Dispatcher`. See `System::setup`.
+    /// The default implementation does nothing.
+    fn setup_on(&mut self, _world: &mut W) {}
+    /// A name to identify this system by in diagnostics.
+    fn name(&self) -> &'static str {
+        core::any::type_name::<Self>()
+    }
+}
+
+impl<W, S, I, O> RunSystem<W> for S
+where
+    S: for<'a> OutputSystem<'a, Input = I, Output = O> + 'static,
+    W: for<'a> WorldInterface<'a>
+        + for<'a> ComponentProviderRec<'a, <<S as OutputSystem<'a>>::Dependencies as Nest>::Nested>,
+{
+    type Input = I;
+    type Output = O;
+    #[inline]
+    fn run_on(&mut self, world: &mut W, input: I) -> O {
+        world.run_output_system(self, input)
+    }
+    fn setup_on(&mut self, world: &mut W) {
+        let dependencies = world.fetch_for::<S, _>();
+        <S as OutputSystem>::setup(self, dependencies);
+        world.apply_deferred();
+    }
+}
+
+/// Runs `A`, then feeds its output into `B`.
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A, B> Chain<A, B> {
+    /// Chain `first` and `second` together.
+    pub fn new(first: A, second: B) -> Self {
+        Chain { first, second }
+    }
+}
+
+impl<W, A, B> RunSystem<W> for Chain<A, B>
+where
+    A: RunSystem<W>,
+    B: RunSystem<W, Input = A::Output>,
+{
+    type Input = A::Input;
+    type Output = B::Output;
+    #[inline]
+    fn run_on(&mut self, world: &mut W, input: A::Input) -> B::Output {
+        let intermediate = self.first.run_on(world, input);
+        self.second.run_on(world, intermediate)
+    }
+    fn setup_on(&mut self, world: &mut W) {
+        self.first.setup_on(world);
+        self.second.setup_on(world);
+    }
+}
+
+/// Conversion from a system's output to the result the `Dispatcher` inspects.
+pub trait IntoSystemResult {
+    /// Perform the conversion.
+    fn into_system_result(self) -> Result<(), Box<dyn Error>>;
+}
+
+impl IntoSystemResult for () {
+    #[inline]
+    fn into_system_result(self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+impl<E> IntoSystemResult for Result<(), E>
+where
+    E: Into<Box<dyn Error>>,
+{
+    #[inline]
+    fn into_system_result(self) -> Result<(), Box<dyn Error>> {
+        self.map_err(Into::into)
+    }
+}
+
+/// Error returned by `Dispatcher::run` when a system fails.
+#[derive(Debug)]
+pub struct SystemError {
+    system: &'static str,
+    error: Box<dyn Error>,
+}
+
+impl SystemError {
+    pub(crate) fn new(system: &'static str, error: Box<dyn Error>) -> Self {
+        SystemError { system, error }
+    }
+
+    /// The name of the system that failed.
+    pub fn system(&self) -> &'static str {
+        self.system
+    }
+
+    /// The error the system returned.
+    pub fn error(&self) -> &(dyn Error + 'static) {
+        &*self.error
+    }
+
+    /// Unwrap the error the system returned.
+    pub fn into_inner(self) -> Box<dyn Error> {
+        self.error
+    }
+}
+
+impl fmt::Display for SystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "system {} failed: {}", self.system, self.error)
+    }
+}
+
+impl Error for SystemError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+// Adapts a `RunSystem` with any acceptable output so it can be stored in a `Dispatcher`.
+struct Erased<S>(S);
+
+impl<W, S> RunSystem<W> for Erased<S>
+where
+    S: RunSystem<W, Input = ()>,
+    S::Output: IntoSystemResult,
+{
+    type Input = ();
+    type Output = Result<(), Box<dyn Error>>;
+    #[inline]
+    fn run_on(&mut self, world: &mut W, _input: ()) -> Self::Output {
+        self.0.run_on(world, ()).into_system_result()
+    }
+    fn setup_on(&mut self, world: &mut W) {
+        self.0.setup_on(world)
+    }
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+}
+
+// Drives an `AsyncSystem` from a `Dispatcher`.
+struct AsyncRunner<S, F> {
+    system: S,
+    in_flight: Option<Pin<Box<F>>>,
+}
+
+impl<W, S, F> RunSystem<W> for AsyncRunner<S, F>
+where
+    S: for<'a> AsyncSystem<'a, W, Future = F> + 'static,
+    F: Future<Output = Commands<W>> + 'static,
+    W: for<'a> WorldInterface<'a>
+        + for<'a> ComponentProviderRec<'a, <<S as AsyncSystem<'a, W>>::Dependencies as Nest>::Nested>,
+{
+    type Input = ();
+    type Output = ();
+    fn run_on(&mut self, world: &mut W, _input: ()) {
+        let in_flight = match self.in_flight {
+            Some(ref mut f) => f,
+            None => {
+                let dependencies = world.fetch_for::<S, _>();
+                self.in_flight
+                    .insert(Box::pin(self.system.run(dependencies)))
+            }
+        };
+        // Futures are polled once per run, rather than when they are woken up, so there's no need
+        // for a real waker.
+        let mut cx = Context::from_waker(Waker::noop());
+        if let Poll::Ready(mut commands) = in_flight.as_mut().poll(&mut cx) {
+            self.in_flight = None;
+            commands.apply(world);
+        }
+    }
+    fn name(&self) -> &'static str {
+        core::any::type_name::<S>()
+    }
+}
+
+// Runs systems as the instance `id` (see `Locals::instance`) while it's alive, without keeping the
+// world borrowed.
+struct RunningAs<'w, W: for<'a> WorldInterface<'a>> {
+    world: &'w mut W,
+    previous: Option<(SystemId, usize)>,
+}
+
+impl<'w, W: for<'a> WorldInterface<'a>> RunningAs<'w, W> {
+    fn new(world: &'w mut W, id: SystemId) -> Self {
+        let previous = world.locals().begin(id);
+        RunningAs { world, previous }
+    }
+}
+
+impl<W: for<'a> WorldInterface<'a>> core::ops::Deref for RunningAs<'_, W> {
+    type Target = W;
+    fn deref(&self) -> &W {
+        self.world
+    }
+}
+
+impl<W: for<'a> WorldInterface<'a>> core::ops::DerefMut for RunningAs<'_, W> {
+    fn deref_mut(&mut self) -> &mut W {
+        self.world
+    }
+}
+
+impl<W: for<'a> WorldInterface<'a>> Drop for RunningAs<'_, W> {
+    fn drop(&mut self) {
+        self.world.locals().end(self.previous);
+    }
+}
+
+type BoxedSystem<W> = Box<dyn RunSystem<W, Input = (), Output = Result<(), Box<dyn Error>>>>;
+
+/// A system registered with a `Dispatcher`, along with its configuration.
+pub struct ScheduledSystem<W> {
+    system: BoxedSystem<W>,
+    // Identifies the system's `Local`s.
+    id: SystemId,
+    labels: Vec<String>,
+    before: Vec<String>,
+    after: Vec<String>,
+    enabled: bool,
+    tasks: usize,
+    #[cfg(feature = "std")]
+    budget: Option<Duration>,
+}
+
+impl<W> ScheduledSystem<W> {
+    fn new(system: BoxedSystem<W>) -> Self {
+        ScheduledSystem {
+            system,
+            id: SystemId::new(),
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            enabled: true,
+            tasks: 0,
+            #[cfg(feature = "std")]
+            budget: None,
+        }
+    }
+
+    /// Add a label to the system. Labels are used to refer to systems after they have been added
+    /// to the dispatcher; several systems may share the same label, in which case the label
+    /// names a set of systems that can be ordered and toggled as a unit.
+    pub fn label<L: Into<String>>(&mut self, label: L) -> &mut Self {
+        self.labels.push(label.into());
+        self
+    }
+
+    /// Run this system before every system with the given label.
+    pub fn before<L: Into<String>>(&mut self, label: L) -> &mut Self {
+        self.before.push(label.into());
+        self
+    }
+
+    /// Run this system after every system with the given label.
+    pub fn after<L: Into<String>>(&mut self, label: L) -> &mut Self {
+        self.after.push(label.into());
+        self
+    }
+
+    /// Whether the system has the given label.
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels.iter().any(|l| l == label)
+    }
+
+    /// The name of the system.
+    pub fn name(&self) -> &'static str {
+        self.system.name()
+    }
+
+    /// Whether the system will run when the dispatcher does.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable the system.
+    pub fn set_enabled(&mut self, enabled: bool) -> &mut Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// The number of tasks the system ran through its `Tasks` dependency the last time it ran.
+    /// Always zero without the `std` feature.
+    pub fn tasks(&self) -> usize {
+        self.tasks
+    }
+
+    /// Give the system its own time budget, instead of the `Watchdog`'s, e.g. for a pathfinding
+    /// system that's expected to take longer than the rest. Has no effect unless the dispatcher
+    /// has a watchdog.
+    #[cfg(feature = "std")]
+    pub fn budget(&mut self, budget: Duration) -> &mut Self {
+        self.budget = Some(budget);
+        self
+    }
+}
+
+/// A run of a system that took longer than its budget, as reported by a `Watchdog`.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BudgetOverrun {
+    /// The name of the system.
+    pub system: &'static str,
+    /// The frame it ran in: the world's `FrameInfo` tick if the dispatcher was run with
+    /// `run_frame`, or else the number of times the dispatcher has run.
+    pub tick: u64,
+    /// How long the system took.
+    pub elapsed: Duration,
+    /// How long it was allowed.
+    pub budget: Duration,
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for BudgetOverrun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "system {} took {:?} in frame {}, over its budget of {:?}",
+            self.system, self.elapsed, self.tick, self.budget
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+enum OverrunAction {
+    Log,
+    Panic,
+    Callback(Box<dyn FnMut(&BudgetOverrun)>),
+}
+
+/// Times every system a `Dispatcher` runs, and flags the ones that take longer than a budget, to
+/// catch pathological frames in playtests. Needs the `std` feature.
+///
+/// By default, overruns are printed to standard error. `panicking` turns them into panics, for
+/// automated playtests, and `on_overrun` passes them to a callback instead, e.g. to record them
+/// in the game's own log. Individual systems can be given their own budget with
+/// `ScheduledSystem::budget`.
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// # #[derive(Default)]
+/// # pub struct Position(i32, i32);
+/// # define_world!(
+/// #     #[derive(Default)]
+/// #     pub world {
+/// #         components {
+/// #             positions: BasicVecStorage<Position>,
+/// #         }
+/// #         resources {
+/// #             frame: FrameInfo,
+/// #         }
+/// #     }
+/// # );
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+/// use std::time::Duration;
+///
+/// struct Pathfind;
+/// impl<'a> System<'a> for Pathfind {
+///     type Dependencies = (ReadComponent<'a, Position>,);
+///     fn run(&'a mut self, _: Self::Dependencies) {
+///         std::thread::sleep(Duration::from_millis(5));
+///     }
+/// }
+///
+/// let overruns = Rc::new(RefCell::new(Vec::new()));
+/// let log = overruns.clone();
+/// let mut w = World::default();
+/// let mut dispatcher = Dispatcher::new().with_watchdog(
+///     Watchdog::new(Duration::from_millis(1)).on_overrun(move |o| log.borrow_mut().push(*o)),
+/// );
+/// dispatcher.add(&mut w, Pathfind);
+///
+/// dispatcher.run_frame(&mut w, Duration::from_millis(16)).unwrap();
+/// let overruns = overruns.borrow();
+/// assert_eq!(overruns.len(), 1);
+/// assert_eq!(overruns[0].system, std::any::type_name::<Pathfind>());
+/// assert_eq!(overruns[0].tick, 1);
+/// assert!(overruns[0].elapsed >= Duration::from_millis(5));
+/// ```
+#[cfg(feature = "std")]
+pub struct Watchdog {
+    budget: Duration,
+    action: OverrunAction,
+    tick: u64,
+    // The tick `run_frame` is about to run, if it's been called.
+    next_tick: Option<u64>,
+    overruns: u64,
+}
+
+#[cfg(feature = "std")]
+impl Watchdog {
+    /// Create a watchdog allowing each system `budget` per run, which prints overruns to standard
+    /// error.
+    pub fn new(budget: Duration) -> Self {
+        Watchdog {
+            budget,
+            action: OverrunAction::Log,
+            tick: 0,
+            next_tick: None,
+            overruns: 0,
+        }
+    }
+
+    /// Panic on an overrun instead, builder style.
+    pub fn panicking(mut self) -> Self {
+        self.action = OverrunAction::Panic;
+        self
+    }
+
+    /// Call `f` with each overrun instead, builder style.
+    pub fn on_overrun<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&BudgetOverrun) + 'static,
+    {
+        self.action = OverrunAction::Callback(Box::new(f));
+        self
+    }
+
+    /// The budget for systems that don't have their own.
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// The number of overruns so far.
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    fn check(&mut self, system: &'static str, elapsed: Duration, budget: Option<Duration>) {
+        let budget = budget.unwrap_or(self.budget);
+        if elapsed <= budget {
+            return;
+        }
+        self.overruns += 1;
+        let overrun = BudgetOverrun {
+            system,
+            tick: self.tick,
+            elapsed,
+            budget,
+        };
+        match self.action {
+            OverrunAction::Log => std::eprintln!("warning: {}", overrun),
+            OverrunAction::Panic => panic!("{}", overrun),
+            OverrunAction::Callback(ref mut f) => f(&overrun),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("budget", &self.budget)
+            .field("tick", &self.tick)
+            .field("overruns", &self.overruns)
+            .finish()
+    }
+}
+
+/// Ordering constraints for every system with a given label. See `Dispatcher::configure_set`.
+#[derive(Clone, Debug)]
+pub struct SetConfig {
+    label: String,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+impl SetConfig {
+    /// Run the systems in this set before every system with the given label.
+    pub fn before<L: Into<String>>(&mut self, label: L) -> &mut Self {
+        self.before.push(label.into());
+        self
+    }
+
+    /// Run the systems in this set after every system with the given label.
+    pub fn after<L: Into<String>>(&mut self, label: L) -> &mut Self {
+        self.after.push(label.into());
+        self
+    }
+}
+
+/// Error returned by `Dispatcher::build` when the ordering constraints can't be satisfied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// The constraints form a cycle. Contains the names of the systems in the cycle, each of which
+    /// must run before the next (and the last before the first).
+    Cycle(Vec<&'static str>),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::Cycle(systems) => {
+                write!(f, "system ordering constraints form a cycle: ")?;
+                for system in systems.iter() {
+                    write!(f, "{} -> ", system)?;
+                }
+                write!(f, "{}", systems[0])
+            }
+        }
+    }
+}
+
+impl Error for ScheduleError {}
+
+/// Runs a list of systems in order.
+///
+/// By default, systems run in the order they were added. Systems can also be constrained to run
+/// `before` or `after` the systems with a given label, and `configure_set` constrains every system
+/// with a given label at once. The dispatcher sorts its systems so that all of the constraints
+/// are satisfied, otherwise keeping them in the order they were added. Constraints that refer to
+/// labels no system has are ignored.
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// # #[derive(Default)]
+/// # pub struct Position(i32, i32);
+/// # define_world!(
+/// #     #[derive(Default)]
+/// #     pub world {
+/// #         components {
+/// #             positions: BasicVecStorage<Position>,
+/// #         }
+/// #         resources {
+/// #             log: Vec<&'static str>,
+/// #         }
+/// #     }
+/// # );
+/// struct Log(&'static str);
+/// impl<'a> System<'a> for Log {
+///     type Dependencies = (WriteResource<'a, Vec<&'static str>>,);
+///     fn run(&'a mut self, (mut log,): Self::Dependencies) {
+///         log.push(self.0);
+///     }
+/// }
+///
+/// let mut w = World::default();
+/// let mut dispatcher = Dispatcher::new();
+/// dispatcher.add(&mut w, Log("render")).label("render");
+/// dispatcher.add(&mut w, Log("collision")).label("physics").after("movement");
+/// dispatcher.add(&mut w, Log("movement")).label("physics").label("movement");
+/// dispatcher.add(&mut w, Log("input")).label("input").before("physics");
+/// dispatcher.configure_set("physics").before("render");
+///
+/// dispatcher.build().unwrap();
+/// dispatcher.run(&mut w).unwrap();
+/// assert_eq!(
+///     *<World as GetResource<Vec<&'static str>>>::get(&w),
+///     vec!["input", "movement", "collision", "render"]
+/// );
+/// ```
+///
+/// Systems can be labeled when they are added, and enabled or disabled by label at runtime:
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// # #[derive(Default)]
+/// # pub struct Position(i32, i32);
+/// # define_world!(
+/// #     #[derive(Default)]
+/// #     pub world {
+/// #         components {
+/// #             positions: BasicVecStorage<Position>,
+/// #         }
+/// #         resources {}
+/// #     }
+/// # );
+/// struct DebugOverlay;
+/// impl<'a> System<'a> for DebugOverlay {
+///     type Dependencies = (ReadComponent<'a, Position>,);
+///     fn run(&'a mut self, (positions,): Self::Dependencies) {
+///         // Draw a marker at each position...
+///     }
+/// }
+///
+/// let mut w = World::default();
+/// let mut dispatcher = Dispatcher::new();
+/// dispatcher.add(&mut w, DebugOverlay).label("debug_overlay");
+///
+/// assert!(dispatcher.set_enabled("debug_overlay", false));
+/// assert_eq!(dispatcher.is_enabled("debug_overlay"), Some(false));
+/// // Does not run `DebugOverlay`.
+/// dispatcher.run(&mut w).unwrap();
+/// ```
+pub struct Dispatcher<W> {
+    systems: Vec<ScheduledSystem<W>>,
+    sets: Vec<SetConfig>,
+    // Indices into `systems`, in the order they should run. Only valid if `!dirty`.
+    order: Vec<usize>,
+    dirty: bool,
+    observers: Vec<Box<dyn crate::observer::Trigger<W>>>,
+    batches: crate::observer::Batches,
+    #[cfg(feature = "std")]
+    watchdog: Option<Watchdog>,
+}
+
+impl<W> Default for Dispatcher<W> {
+    fn default() -> Self {
+        Dispatcher {
+            systems: Vec::new(),
+            sets: Vec::new(),
+            order: Vec::new(),
+            dirty: false,
+            observers: Vec::new(),
+            batches: Default::default(),
+            #[cfg(feature = "std")]
+            watchdog: None,
+        }
+    }
+}
+
+impl<W> Dispatcher<W> {
+    /// Create an empty dispatcher.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a system, builder style. See `add`.
+    pub fn with<S>(mut self, world: &mut W, system: S) -> Self
+    where
+        S: RunSystem<W, Input = ()> + 'static,
+        S::Output: IntoSystemResult,
+        W: for<'a> WorldInterface<'a>,
+    {
+        self.add(world, system);
+        self
+    }
+
+    /// Append a system, returning a handle that can be used to configure it.
+    ///
+    /// The system is set up on `world` straight away (see `System::setup`), so whatever it puts
+    /// in the world is in place before the dispatcher first runs, and the game can still
+    /// override it afterwards, e.g. by loading a save.
+    pub fn add<S>(&mut self, world: &mut W, system: S) -> &mut ScheduledSystem<W>
+    where
+        S: RunSystem<W, Input = ()> + 'static,
+        S::Output: IntoSystemResult,
+        W: for<'a> WorldInterface<'a>,
+    {
+        self.dirty = true;
+        let mut entry = ScheduledSystem::new(Box::new(Erased(system)));
+        entry.system.setup_on(&mut RunningAs::new(world, entry.id));
+        self.systems.push(entry);
+        self.systems.last_mut().unwrap()
+    }
+
+    /// Register an observer of events of type `E`, which runs with each batch of them sent while
+    /// the dispatcher is running. See the [`observer`](../observer/index.html) module.
+    pub fn add_observer<E, O>(&mut self, observer: O) -> &mut Self
+    where
+        E: 'static,
+        O: for<'a> Observer<'a, E> + 'static,
+        W: for<'a> WorldInterface<'a>
+            + GetResource<Events<E>>
+            + for<'a> ComponentProviderRec<'a, <<O as Observer<'a, E>>::Dependencies as Nest>::Nested>,
+    {
+        self.observers
+            .push(Box::new(crate::observer::ObserverRunner::new(observer)));
+        self
+    }
+
+    /// Register an observer, builder style. See `add_observer`.
+    pub fn with_observer<E, O>(mut self, observer: O) -> Self
+    where
+        E: 'static,
+        O: for<'a> Observer<'a, E> + 'static,
+        W: for<'a> WorldInterface<'a>
+            + GetResource<Events<E>>
+            + for<'a> ComponentProviderRec<'a, <<O as Observer<'a, E>>::Dependencies as Nest>::Nested>,
+    {
+        self.add_observer::<E, O>(observer);
+        self
+    }
+
+    // Run observers until there are no events left for any of them. All the observers of an event
+    // type see the same batch, and events sent while they run are left for the next pass.
+    fn observe(&mut self, world: &mut W) {
+        let mut triggered = true;
+        while triggered {
+            triggered = false;
+            for observer in self.observers.iter() {
+                observer.take(world, &mut self.batches);
+            }
+            for observer in self.observers.iter_mut() {
+                triggered |= observer.trigger(world, &mut self.batches);
+            }
+            for observer in self.observers.iter() {
+                observer.clear(&mut self.batches);
+            }
+        }
+    }
+
+    /// Get the ordering constraints for every system with the given label, for modification.
+    pub fn configure_set<L: Into<String>>(&mut self, label: L) -> &mut SetConfig {
+        self.dirty = true;
+        let label = label.into();
+        match self.sets.iter().position(|s| s.label == label) {
+            Some(i) => &mut self.sets[i],
+            None => {
+                self.sets.push(SetConfig {
+                    label,
+                    before: Vec::new(),
+                    after: Vec::new(),
+                });
+                self.sets.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Work out the order to run the systems in. This happens automatically the first time the
+    /// dispatcher runs after being modified, but calling it directly lets you handle
+    /// unsatisfiable constraints gracefully instead of panicking.
+    pub fn build(&mut self) -> Result<(), ScheduleError> {
+        let n = self.systems.len();
+        let systems = &self.systems;
+        let with_label = |label: &str| -> Vec<usize> {
+            systems
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.has_label(label))
+                .map(|(i, _)| i)
+                .collect()
+        };
+        // successors[i] contains the systems that must run after system i.
+        let mut successors = vec![Vec::new(); n];
+        for (i, system) in self.systems.iter().enumerate() {
+            for label in system.before.iter() {
+                successors[i].extend(with_label(label));
+            }
+            for label in system.after.iter() {
+                for j in with_label(label) {
+                    successors[j].push(i);
+                }
+            }
+        }
+        for set in self.sets.iter() {
+            for i in with_label(&set.label) {
+                for label in set.before.iter() {
+                    successors[i].extend(with_label(label));
+                }
+                for label in set.after.iter() {
+                    for j in with_label(label) {
+                        successors[j].push(i);
+                    }
+                }
+            }
+        }
+        let mut predecessors = vec![0; n];
+        for (i, succ) in successors.iter_mut().enumerate() {
+            // A system labeled `x` that runs after `x` is constrained only by its peers.
+            succ.retain(|&j| j != i);
+            succ.sort_unstable();
+            succ.dedup();
+            for &j in succ.iter() {
+                predecessors[j] += 1;
+            }
+        }
+
+        // Kahn's algorithm, always picking the earliest-added system that's ready so that
+        // unconstrained systems keep their relative order.
+        let mut ready: alloc::collections::BTreeSet<usize> =
+            (0..n).filter(|&i| predecessors[i] == 0).collect();
+        let mut order = Vec::with_capacity(n);
+        while let Some(&i) = ready.iter().next() {
+            ready.remove(&i);
+            order.push(i);
+            for &j in successors[i].iter() {
+                predecessors[j] -= 1;
+                if predecessors[j] == 0 {
+                    ready.insert(j);
+                }
+            }
+        }
+
+        if order.len() < n {
+            // Every system that didn't get scheduled has an unscheduled predecessor, so walking
+            // backwards from any of them must eventually revisit a system.
+            let mut predecessor = vec![None; n];
+            for (i, succ) in successors.iter().enumerate() {
+                if predecessors[i] > 0 {
+                    for &j in succ.iter() {
+                        predecessor[j] = Some(i);
+                    }
+                }
+            }
+            let mut visited = vec![false; n];
+            let mut i = (0..n).find(|&i| predecessors[i] > 0).unwrap();
+            while !visited[i] {
+                visited[i] = true;
+                i = predecessor[i].unwrap();
+            }
+            let mut cycle = vec![i];
+            let mut j = predecessor[i].unwrap();
+            while j != i {
+                cycle.push(j);
+                j = predecessor[j].unwrap();
+            }
+            cycle.reverse();
+            return Err(ScheduleError::Cycle(
+                cycle.into_iter().map(|i| self.systems[i].name()).collect(),
+            ));
+        }
+
+        self.order = order;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Append an `AsyncSystem`, builder style.
+    pub fn with_async<S, F>(mut self, world: &mut W, system: S) -> Self
+    where
+        S: for<'a> AsyncSystem<'a, W, Future = F> + 'static,
+        F: Future<Output = Commands<W>> + 'static,
+        W: for<'a> WorldInterface<'a>
+            + for<'a> ComponentProviderRec<
+                'a,
+                <<S as AsyncSystem<'a, W>>::Dependencies as Nest>::Nested,
+            > + 'static,
+    {
+        self.add(
+            world,
+            AsyncRunner {
+                system,
+                in_flight: None,
+            },
+        );
+        self
+    }
+
+    /// Append an `AsyncSystem`, returning a handle that can be used to configure it.
+    ///
+    /// Each time the dispatcher runs, it starts the system if it isn't already in flight, and
+    /// then polls the in-flight future. When the future completes, the `Commands` it resolves to
+    /// are applied to the world immediately. Disabling the system pauses any in-flight future.
+    pub fn add_async<S, F>(&mut self, world: &mut W, system: S) -> &mut ScheduledSystem<W>
+    where
+        S: for<'a> AsyncSystem<'a, W, Future = F> + 'static,
+        F: Future<Output = Commands<W>> + 'static,
+        W: for<'a> WorldInterface<'a>
+            + for<'a> ComponentProviderRec<
+                'a,
+                <<S as AsyncSystem<'a, W>>::Dependencies as Nest>::Nested,
+            > + 'static,
+    {
+        self.add(
+            world,
+            AsyncRunner {
+                system,
+                in_flight: None,
+            },
+        )
+    }
+
+    /// Enable or disable every system with the given label. Returns `false` if there are no such
+    /// systems.
+    pub fn set_enabled(&mut self, label: &str, enabled: bool) -> bool {
+        let mut found = false;
+        for system in self.systems.iter_mut().filter(|s| s.has_label(label)) {
+            system.set_enabled(enabled);
+            found = true;
+        }
+        found
+    }
+
+    /// Whether the systems with the given label are enabled: `Some(true)` if any of them are,
+    /// `Some(false)` if none of them are, or `None` if there are no such systems.
+    pub fn is_enabled(&self, label: &str) -> Option<bool> {
+        let mut labeled = self
+            .systems
+            .iter()
+            .filter(|s| s.has_label(label))
+            .peekable();
+        labeled.peek()?;
+        Some(labeled.any(|s| s.is_enabled()))
+    }
+
+    /// Time every system with `watchdog`, builder style. See `Watchdog`.
+    #[cfg(feature = "std")]
+    pub fn with_watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Replace the watchdog, or remove it with `None`, returning the old one.
+    #[cfg(feature = "std")]
+    pub fn set_watchdog(&mut self, watchdog: Option<Watchdog>) -> Option<Watchdog> {
+        core::mem::replace(&mut self.watchdog, watchdog)
+    }
+
+    /// The watchdog, if there is one.
+    #[cfg(feature = "std")]
+    pub fn watchdog(&self) -> Option<&Watchdog> {
+        self.watchdog.as_ref()
+    }
+
+    /// Iterate over the registered systems, in the order they were added.
+    pub fn systems(&self) -> impl Iterator<Item = &ScheduledSystem<W>> {
+        self.systems.iter()
+    }
+
+    /// Run every enabled system once. If a system fails, the remaining systems are skipped and
+    /// the error is returned.
+    ///
+    /// Changes that systems and observers queue with a `Deferred` dependency are applied once
+    /// they've all run (or one has failed), so every system sees the world as it was at the start
+    /// of the run, apart from its direct changes. Observers of events sent by those changes run
+    /// straight afterwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the dispatcher has been modified since it was last built, and its ordering
+    /// constraints can't be satisfied. Call `build` first to handle that case yourself.
+    pub fn run(&mut self, world: &mut W) -> Result<(), SystemError>
+    where
+        W: for<'a> WorldInterface<'a>,
+    {
+        if self.dirty {
+            if let Err(e) = self.build() {
+                panic!("{}", e);
+            }
+        }
+        #[cfg(feature = "std")]
+        if let Some(ref mut watchdog) = self.watchdog {
+            watchdog.tick = watchdog.next_tick.take().unwrap_or(watchdog.tick + 1);
+        }
+        self.observe(world);
+        for n in 0..self.order.len() {
+            let entry = &mut self.systems[self.order[n]];
+            if !entry.enabled {
+                continue;
+            }
+            crate::executor::take_tasks();
+            let mut running = RunningAs::new(world, entry.id);
+            #[cfg(feature = "std")]
+            let result = match self.watchdog {
+                Some(ref mut watchdog) => {
+                    let start = std::time::Instant::now();
+                    let result = entry.system.run_on(&mut running, ());
+                    watchdog.check(entry.system.name(), start.elapsed(), entry.budget);
+                    result
+                }
+                None => entry.system.run_on(&mut running, ()),
+            };
+            #[cfg(not(feature = "std"))]
+            let result = entry.system.run_on(&mut running, ());
+            drop(running);
+            entry.tasks = crate::executor::take_tasks();
+            if let Err(error) = result {
+                world.apply_deferred();
+                return Err(SystemError {
+                    system: entry.system.name(),
+                    error,
+                });
+            }
+            self.observe(world);
+        }
+        world.apply_deferred();
+        self.observe(world);
+        world.apply_deferred();
+        Ok(())
+    }
+
+    /// Start a new frame `delta` after the last one, by advancing the world's `FrameInfo`, and
+    /// then `run`. See the [`frame`](../frame/index.html) module.
+    pub fn run_frame(&mut self, world: &mut W, delta: Duration) -> Result<(), SystemError>
+    where
+        W: GetResource<FrameInfo> + for<'a> WorldInterface<'a>,
+    {
+        let tick = {
+            let mut frame = <W as GetResource<FrameInfo>>::get_mut(world);
+            frame.advance(delta);
+            frame.tick()
+        };
+        world.debug_origins_mut().set_tick(tick);
+        #[cfg(feature = "std")]
+        if let Some(ref mut watchdog) = self.watchdog {
+            watchdog.next_tick = Some(tick);
+        }
+        self.run(world)
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Handles for working with one entity at a time.
+//!
+//! `WorldInterface::entity` and `entity_mut` return an [`EntityRef`](struct.EntityRef.html) or
+//! [`EntityMut`](struct.EntityMut.html), which pair an entity with its world, so code that deals
+//! with a single entity (moving an item into an inventory, a scripted cutscene) can name the
+//! component it wants with a type parameter rather than going through `GetComponent` for each
+//! storage.
+//!
+//! A handle to an entity that isn't alive acts as if the entity had no components: `get` returns
+//! `None`, and `insert` does nothing.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Debug, Default, PartialEq)]
+//! pub struct Gold(u32);
+//! #[derive(Debug, Default, PartialEq)]
+//! pub struct Sword;
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             gold: BasicVecStorage<Gold>,
+//!             swords: BasicVecStorage<Sword>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let hero = w.new_entity().with(Gold(100)).build();
+//!
+//! let mut h = w.entity_mut(hero);
+//! h.get_mut::<Gold>().unwrap().0 -= 40;
+//! h.insert(Sword);
+//! assert_eq!(*h.get::<Gold>().unwrap(), Gold(60));
+//!
+//! assert!(w.entity(hero).has::<Sword>());
+//! w.entity_mut(hero).despawn();
+//! assert!(w.entity(hero).get::<Gold>().is_none());
+//! ```
+
+use crate::cell::{Ref, RefMut};
+use crate::*;
+
+/// A read-only handle to an entity and its world, returned by `WorldInterface::entity`.
+pub struct EntityRef<'w, W> {
+    world: &'w W,
+    entity: Entity,
+}
+
+impl<'w, W> Clone for EntityRef<'w, W> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'w, W> Copy for EntityRef<'w, W> {}
+
+impl<'w, W> EntityRef<'w, W> {
+    /// Create a handle to `entity` in `world`.
+    pub fn new(world: &'w W, entity: Entity) -> Self {
+        EntityRef { world, entity }
+    }
+
+    /// The entity.
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
+
+    /// The world.
+    pub fn world(&self) -> &'w W {
+        self.world
+    }
+
+    /// Whether the entity is alive.
+    pub fn is_alive(&self) -> bool
+    where
+        W: WorldInterface<'w>,
+    {
+        self.world.is_alive(self.entity)
+    }
+
+    /// Get the entity's `T` component, if it has one. This borrows `T`'s storage until the
+    /// result is dropped.
+    pub fn get<T>(&self) -> Option<Ref<'w, T::Component>>
+    where
+        T: StorageSpec<'w> + 'w,
+        W: GetComponent<'w, T>,
+    {
+        get::<W, T>(self.world, self.entity)
+    }
+
+    /// Whether the entity has a `T` component.
+    pub fn has<T>(&self) -> bool
+    where
+        T: StorageSpec<'w> + 'w,
+        W: GetComponent<'w, T>,
+    {
+        self.get::<T>().is_some()
+    }
+}
+
+/// A handle to an entity and its world that can change the entity's components, returned by
+/// `WorldInterface::entity_mut`.
+pub struct EntityMut<'w, W> {
+    world: &'w mut W,
+    entity: Entity,
+}
+
+impl<'w, W> EntityMut<'w, W> {
+    /// Create a handle to `entity` in `world`.
+    pub fn new(world: &'w mut W, entity: Entity) -> Self {
+        EntityMut { world, entity }
+    }
+
+    /// The entity.
+    pub fn id(&self) -> Entity {
+        self.entity
+    }
+
+    /// The world.
+    pub fn world(&self) -> &W {
+        self.world
+    }
+
+    /// The world, mutably.
+    pub fn world_mut(&mut self) -> &mut W {
+        self.world
+    }
+
+    /// A read-only handle to the entity.
+    pub fn as_ref(&self) -> EntityRef<'_, W> {
+        EntityRef::new(self.world, self.entity)
+    }
+
+    /// Whether the entity is alive.
+    pub fn is_alive(&self) -> bool
+    where
+        W: WorldInterface<'w>,
+    {
+        self.world.is_alive(self.entity)
+    }
+
+    /// Get the entity's `T` component, if it has one. This borrows `T`'s storage until the
+    /// result is dropped.
+    pub fn get<'r, T>(&'r self) -> Option<Ref<'r, T::Component>>
+    where
+        T: StorageSpec<'r> + 'r,
+        W: GetComponent<'r, T>,
+    {
+        get::<W, T>(self.world, self.entity)
+    }
+
+    /// Get the entity's `T` component mutably, if it has one. This borrows `T`'s storage until
+    /// the result is dropped.
+    pub fn get_mut<'r, T>(&'r mut self) -> Option<RefMut<'r, T::Component>>
+    where
+        T: StorageSpec<'r> + 'r,
+        T::Storage: MutableComponentStorage<'r>,
+        W: GetComponent<'r, T>,
+    {
+        let storage = <W as GetComponent<'r, T>>::get_mut(self.world);
+        RefMut::filter_map(storage, |s| s.get_mut(self.entity)).ok()
+    }
+
+    /// Whether the entity has a `T` component.
+    pub fn has<'r, T>(&'r self) -> bool
+    where
+        T: StorageSpec<'r> + 'r,
+        W: GetComponent<'r, T>,
+    {
+        self.get::<T>().is_some()
+    }
+
+    /// Give the entity a `T` component, returning the one it replaces, if any. Does nothing if
+    /// the entity isn't alive. See `GetComponent::insert`.
+    pub fn insert<T>(&mut self, value: T) -> Option<T>
+    where
+        T: StorageSpec<'w, Component = T>,
+        W: GetComponent<'w, T>,
+    {
+        <W as GetComponent<'w, T>>::insert(self.world, self.entity, value)
+    }
+
+    /// Remove the entity's `T` component and return it, if it has one.
+    pub fn remove<T>(&mut self) -> Option<T::Component>
+    where
+        T: StorageSpec<'w>,
+        W: GetComponent<'w, T>,
+    {
+        <W as GetComponent<'w, T>>::remove(self.world, self.entity)
+    }
+
+    /// Delete the entity. See `WorldInterface::delete_entity`.
+    pub fn despawn(self)
+    where
+        W: WorldInterface<'w>,
+    {
+        self.world.delete_entity(self.entity);
+    }
+}
+
+fn get<'r, W, T>(world: &'r W, entity: Entity) -> Option<Ref<'r, T::Component>>
+where
+    T: StorageSpec<'r> + 'r,
+    W: GetComponent<'r, T>,
+{
+    let storage = <W as GetComponent<'r, T>>::get(world);
+    Ref::filter_map(storage, |s| s.get(entity)).ok()
+}
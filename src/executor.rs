@@ -0,0 +1,369 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pluggable thread pools.
+//!
+//! Anything in the crate that spreads work over threads does it through an
+//! [`Executor`](trait.Executor.html), rather than a particular thread pool, so engines that
+//! already have a job system can hand it over instead of running a second one. Implementing the
+//! trait takes `spawn`, for fire-and-forget jobs, and `scope`, for jobs that borrow from the
+//! caller and must all finish before it carries on. The crate provides:
+//!
+//! * [`Inline`](struct.Inline.html), which runs every job on the calling thread straight away.
+//!   This is the default, and the only one without the `std` feature.
+//! * [`ThreadPool`](struct.ThreadPool.html), a small fixed-size pool with the `std` feature.
+//! * [`Rayon`](struct.Rayon.html), which hands jobs to rayon, with the `rayon` feature.
+//!
+//! Worlds are single-threaded (they aren't `Sync`), so a `Dispatcher` always runs its systems one
+//! at a time on the calling thread, and doesn't use an executor itself. Systems fan their own work
+//! out instead, usually through a [`Tasks`](../task/struct.Tasks.html) dependency, on the executor
+//! [`current`](fn.current.html) returns: the one given to [`set_current`](fn.set_current.html) on
+//! the thread running them.
+//!
+//! # Example
+//!
+//! ```
+//! # use ecstatic::*;
+//! let pool = ThreadPool::new(4);
+//! let mut chunks = vec![vec![1u32; 1000]; 8];
+//! pool.scope(
+//!     chunks
+//!         .iter_mut()
+//!         .map(|chunk| -> Job<'_> { Box::new(move || chunk.iter_mut().for_each(|x| *x *= 2)) })
+//!         .collect(),
+//! );
+//! assert!(chunks.iter().flatten().all(|&x| x == 2));
+//! ```
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// A unit of work for an `Executor`, which may borrow anything that lives for `'a`.
+pub type Job<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+/// Something that runs jobs, possibly in parallel. See the [module-level
+/// documentation](index.html).
+pub trait Executor: Send + Sync {
+    /// Run `job` at some point, on some thread.
+    fn spawn(&self, job: Job<'static>);
+
+    /// Run every job in `jobs`, in any order and possibly in parallel, returning once they've
+    /// all finished. If any of them panics, the panic carries on from here once the rest have
+    /// finished (or straight away without the `std` feature, where panics can't be caught).
+    fn scope<'a>(&self, jobs: Vec<Job<'a>>);
+
+    /// Run `a` and `b`, possibly in parallel, returning once both have finished.
+    fn join<'a>(&self, a: Job<'a>, b: Job<'a>) {
+        self.scope(vec![a, b]);
+    }
+
+    /// The number of jobs that can usefully run at once, as a guide for how finely to split work
+    /// up.
+    fn parallelism(&self) -> usize {
+        1
+    }
+}
+
+/// An `Executor` that runs every job on the calling thread, as soon as it's given.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Inline;
+
+impl Executor for Inline {
+    fn spawn(&self, job: Job<'static>) {
+        job();
+    }
+
+    fn scope<'a>(&self, jobs: Vec<Job<'a>>) {
+        // Without `std`, a panic can't be held back until the rest have run.
+        #[cfg(feature = "std")]
+        {
+            let mut panic = None;
+            for job in jobs {
+                if let Err(payload) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job)) {
+                    panic.get_or_insert(payload);
+                }
+            }
+            if let Some(payload) = panic {
+                std::panic::resume_unwind(payload);
+            }
+        }
+        #[cfg(not(feature = "std"))]
+        for job in jobs {
+            job();
+        }
+    }
+}
+
+/// The executor set with `set_current` on this thread, or `Inline` if there isn't one. Without the
+/// `std` feature, this is always `Inline`.
+pub fn current() -> Arc<dyn Executor> {
+    #[cfg(feature = "std")]
+    if let Ok(Some(executor)) = CURRENT.try_with(|current| current.borrow().clone()) {
+        return executor;
+    }
+    Arc::new(Inline)
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static CURRENT: core::cell::RefCell<Option<Arc<dyn Executor>>> =
+        const { core::cell::RefCell::new(None) };
+    // The number of tasks run through `Tasks` on this thread since the dispatcher last checked.
+    static TASKS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+
+// Count `n` tasks towards the system running on this thread.
+pub(crate) fn record_tasks(n: usize) {
+    #[cfg(feature = "std")]
+    let _ = TASKS.try_with(|tasks| tasks.set(tasks.get() + n));
+    #[cfg(not(feature = "std"))]
+    let _ = n;
+}
+
+// The number of tasks counted since the last call, for `Dispatcher::run`.
+pub(crate) fn take_tasks() -> usize {
+    #[cfg(feature = "std")]
+    {
+        TASKS.try_with(|tasks| tasks.replace(0)).unwrap_or(0)
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        0
+    }
+}
+
+/// Make `executor` the one `current` returns on this thread, or go back to `Inline` with `None`,
+/// returning the previous one. Without the `std` feature, this does nothing, and returns `None`.
+pub fn set_current(executor: Option<Arc<dyn Executor>>) -> Option<Arc<dyn Executor>> {
+    #[cfg(feature = "std")]
+    {
+        CURRENT
+            .try_with(|current| current.replace(executor))
+            .ok()
+            .flatten()
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = executor;
+        None
+    }
+}
+
+#[cfg(feature = "std")]
+pub use self::pool::ThreadPool;
+
+#[cfg(feature = "std")]
+mod pool {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+    use std::thread::JoinHandle;
+
+    /// A fixed-size pool of worker threads, as an `Executor`. See the [module-level
+    /// documentation](index.html). Needs the `std` feature.
+    ///
+    /// A thread waiting for a `scope` to finish works through the pool's queue in the meantime,
+    /// so scopes can be nested, e.g. inside jobs, without running out of threads. Dropping the
+    /// pool waits for the jobs that have been spawned to finish.
+    pub struct ThreadPool {
+        shared: Arc<Shared>,
+        workers: Vec<JoinHandle<()>>,
+    }
+
+    struct Shared {
+        state: Mutex<State>,
+        ready: Condvar,
+    }
+
+    struct State {
+        jobs: VecDeque<Job<'static>>,
+        shutdown: bool,
+    }
+
+    // Counts down the unfinished jobs of a scope, and keeps the first panic among them.
+    struct Latch {
+        remaining: Mutex<(usize, Option<Box<dyn core::any::Any + Send>>)>,
+        done: Condvar,
+    }
+
+    fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+        // Jobs run outside the locks, so nothing can panic while holding one.
+        mutex.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    impl Shared {
+        fn push(&self, jobs: impl IntoIterator<Item = Job<'static>>) {
+            lock(&self.state).jobs.extend(jobs);
+            self.ready.notify_all();
+        }
+
+        fn try_pop(&self) -> Option<Job<'static>> {
+            lock(&self.state).jobs.pop_front()
+        }
+    }
+
+    impl ThreadPool {
+        /// Start a pool of `threads` worker threads. Panics if `threads` is zero.
+        pub fn new(threads: usize) -> Self {
+            assert!(threads > 0, "a thread pool needs at least one thread");
+            let shared = Arc::new(Shared {
+                state: Mutex::new(State {
+                    jobs: VecDeque::new(),
+                    shutdown: false,
+                }),
+                ready: Condvar::new(),
+            });
+            let workers = (0..threads)
+                .map(|i| {
+                    let shared = shared.clone();
+                    std::thread::Builder::new()
+                        .name(format!("ecstatic-worker-{}", i))
+                        .spawn(move || work(&shared))
+                        .expect("couldn't start a worker thread")
+                })
+                .collect();
+            ThreadPool { shared, workers }
+        }
+
+        /// Start a pool with a worker thread for each CPU.
+        pub fn with_available_parallelism() -> Self {
+            Self::new(std::thread::available_parallelism().map_or(1, |n| n.get()))
+        }
+    }
+
+    fn work(shared: &Shared) {
+        loop {
+            let job = {
+                let mut state = lock(&shared.state);
+                loop {
+                    if let Some(job) = state.jobs.pop_front() {
+                        break job;
+                    }
+                    if state.shutdown {
+                        return;
+                    }
+                    state = shared
+                        .ready
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+            };
+            job();
+        }
+    }
+
+    impl Executor for ThreadPool {
+        fn spawn(&self, job: Job<'static>) {
+            // A panicking job takes down neither the worker nor the pool. The panic hook has
+            // already reported it.
+            self.shared.push(Some(Box::new(move || {
+                let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+            }) as Job<'static>));
+        }
+
+        fn scope<'a>(&self, jobs: Vec<Job<'a>>) {
+            let latch = Arc::new(Latch {
+                remaining: Mutex::new((jobs.len(), None)),
+                done: Condvar::new(),
+            });
+            let jobs = jobs.into_iter().map(|job| {
+                let latch = latch.clone();
+                let job: Job<'a> = Box::new(move || {
+                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+                    let mut remaining = lock(&latch.remaining);
+                    remaining.0 -= 1;
+                    if let Err(payload) = result {
+                        remaining.1.get_or_insert(payload);
+                    }
+                    if remaining.0 == 0 {
+                        latch.done.notify_all();
+                    }
+                });
+                // This doesn't return until every job has run, whether or not any of them
+                // panic, so nothing they borrow can go away while they're queued or running.
+                unsafe { core::mem::transmute::<Job<'a>, Job<'static>>(job) }
+            });
+            self.shared.push(jobs);
+
+            // Help out until there's nothing left to start, then wait for the rest to finish.
+            while let Some(job) = self.shared.try_pop() {
+                job();
+            }
+            let mut remaining = lock(&latch.remaining);
+            while remaining.0 > 0 {
+                remaining = latch
+                    .done
+                    .wait(remaining)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            if let Some(payload) = remaining.1.take() {
+                drop(remaining);
+                std::panic::resume_unwind(payload);
+            }
+        }
+
+        fn parallelism(&self) -> usize {
+            self.workers.len()
+        }
+    }
+
+    impl Drop for ThreadPool {
+        fn drop(&mut self) {
+            lock(&self.shared.state).shutdown = true;
+            self.shared.ready.notify_all();
+            for worker in self.workers.drain(..) {
+                let _ = worker.join();
+            }
+        }
+    }
+
+    impl core::fmt::Debug for ThreadPool {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.debug_struct("ThreadPool")
+                .field("threads", &self.workers.len())
+                .field("queued", &lock(&self.shared.state).jobs.len())
+                .finish()
+        }
+    }
+}
+
+/// An `Executor` that hands jobs to rayon's current thread pool: the global one, or the one
+/// whose `install` it's called from. Needs the `rayon` feature.
+#[cfg(feature = "rayon")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rayon;
+
+#[cfg(feature = "rayon")]
+impl Executor for Rayon {
+    fn spawn(&self, job: Job<'static>) {
+        rayon::spawn(job);
+    }
+
+    fn scope<'a>(&self, jobs: Vec<Job<'a>>) {
+        rayon::scope(|s| {
+            for job in jobs {
+                s.spawn(move |_| job());
+            }
+        });
+    }
+
+    fn join<'a>(&self, a: Job<'a>, b: Job<'a>) {
+        rayon::join(a, b);
+    }
+
+    fn parallelism(&self) -> usize {
+        rayon::current_num_threads()
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Frame counting and timing.
+//!
+//! A world with a [`FrameInfo`](struct.FrameInfo.html) resource can be run with
+//! `Dispatcher::run_frame`, which advances it before running the systems, so that systems can
+//! read the frame number, the time since the last frame and the total time elapsed. Turn-based
+//! games can pass a zero `delta` and just use the frame number as a turn counter.
+//!
+//! With the `std` feature, a [`FrameClock`](struct.FrameClock.html) measures the time between
+//! frames.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! use core::time::Duration;
+//!
+//! # #[derive(Default)]
+//! # pub struct Sprite;
+//! #[derive(Default)]
+//! pub struct Blink {
+//!     visible: bool,
+//! }
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             sprites: VoidStorage<Sprite>,
+//!         }
+//!         resources {
+//!             frame: FrameInfo,
+//!             blink: Blink,
+//!         }
+//!     }
+//! );
+//!
+//! struct Animate;
+//! impl<'a> System<'a> for Animate {
+//!     type Dependencies = (ReadResource<'a, FrameInfo>, WriteResource<'a, Blink>);
+//!     fn run(&'a mut self, (frame, mut blink): Self::Dependencies) {
+//!         blink.visible = frame.elapsed().as_millis() % 1000 < 500;
+//!     }
+//! }
+//!
+//! let mut w = World::default();
+//! let mut dispatcher = Dispatcher::new().with(&mut w, Animate);
+//! for _ in 0..3 {
+//!     dispatcher.run_frame(&mut w, Duration::from_millis(250)).unwrap();
+//! }
+//! let frame = *<World as GetResource<FrameInfo>>::get(&w);
+//! assert_eq!(frame.tick(), 3);
+//! assert_eq!(frame.elapsed(), Duration::from_millis(750));
+//! assert!(!<World as GetResource<Blink>>::get(&w).visible);
+//! ```
+
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// The current frame's number and timing. See the [module-level documentation](index.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameInfo {
+    tick: u64,
+    delta: Duration,
+    elapsed: Duration,
+}
+
+impl FrameInfo {
+    /// The number of frames that have started, counting this one. Zero before the first frame.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// The time between the start of the last frame and this one.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// `delta` in seconds, for scaling speeds by.
+    pub fn delta_secs(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// The total of every frame's `delta` so far.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Start a new frame, `delta` after the last one.
+    pub fn advance(&mut self, delta: Duration) {
+        self.tick += 1;
+        self.delta = delta;
+        self.elapsed += delta;
+    }
+}
+
+/// Measures the time between frames, for passing to `Dispatcher::run_frame`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub struct FrameClock {
+    last: Option<Instant>,
+}
+
+#[cfg(feature = "std")]
+impl FrameClock {
+    /// Create a clock that hasn't started.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The time since the last call, or zero for the first.
+    pub fn delta(&mut self) -> Duration {
+        let now = Instant::now();
+        let delta = self.last.map_or(Duration::ZERO, |last| now - last);
+        self.last = Some(now);
+        delta
+    }
+}
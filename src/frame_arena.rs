@@ -0,0 +1,185 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A bump allocator for temporary, per-frame allocations.
+//!
+//! Hot systems like field of view and AI scoring build scratch collections every frame (the
+//! tiles to check, the candidate actions and their scores) and throw them away at the end of
+//! their run. Allocating those on the heap each time adds up. A
+//! [`FrameArena`](struct.FrameArena.html) resource hands out [`FrameVec`](type.FrameVec.html)s
+//! and [`FrameString`](type.FrameString.html)s from a bump allocator instead: allocating is a
+//! pointer increment, nothing is freed individually, and the
+//! [`ResetFrameArena`](struct.ResetFrameArena.html) system, run once per frame, releases
+//! everything at once while keeping the memory for the next frame.
+//!
+//! Systems only need to read the resource to allocate from it, and the collections they get
+//! borrow it, so they can't outlive the system's run. Values put in the arena with `alloc` are
+//! never dropped, so only use that for types without a meaningful `Drop`; the collections drop
+//! their contents as usual.
+//!
+//! This module needs the `frame-arena` feature, which uses the `bumpalo` crate.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Position(i32, i32);
+//! #[derive(Default)]
+//! pub struct Nearby(usize);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!         }
+//!         resources {
+//!             arena: FrameArena,
+//!             nearby: Nearby,
+//!         }
+//!     }
+//! );
+//!
+//! struct CountNearby;
+//! impl<'a> System<'a> for CountNearby {
+//!     type Dependencies = (
+//!         ReadComponent<'a, Position>,
+//!         ReadResource<'a, FrameArena>,
+//!         WriteResource<'a, Nearby>,
+//!     );
+//!     fn run(&'a mut self, (positions, arena, mut nearby): Self::Dependencies) {
+//!         let mut close = arena.vec();
+//!         (&positions,).for_each(|e, (p,)| {
+//!             if p.0.abs() + p.1.abs() < 5 {
+//!                 close.push(e);
+//!             }
+//!         });
+//!         nearby.0 = close.len();
+//!     }
+//! }
+//!
+//! let mut w = World::default();
+//! w.new_entity().with(Position(1, 2)).build();
+//! w.new_entity().with(Position(10, 2)).build();
+//!
+//! let mut dispatcher = Dispatcher::new()
+//!     .with(&mut w, ResetFrameArena)
+//!     .with(&mut w, CountNearby);
+//! dispatcher.run(&mut w).unwrap();
+//! assert_eq!(<World as GetResource<Nearby>>::get(&w).0, 1);
+//! ```
+
+use core::fmt;
+
+use bumpalo::Bump;
+
+use crate::*;
+
+/// A `Vec` allocated in a [`FrameArena`](struct.FrameArena.html).
+pub type FrameVec<'a, T> = bumpalo::collections::Vec<'a, T>;
+
+/// A `String` allocated in a [`FrameArena`](struct.FrameArena.html).
+pub type FrameString<'a> = bumpalo::collections::String<'a>;
+
+/// Resource that allocates temporary collections for systems, and frees them all at once each
+/// frame. See the [module-level documentation](index.html).
+#[derive(Default)]
+pub struct FrameArena {
+    bump: Bump,
+}
+
+impl FrameArena {
+    /// Create an empty arena. It allocates its first chunk of memory when it's first used.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an arena with room for `bytes` bytes of allocations before it needs more memory.
+    pub fn with_capacity(bytes: usize) -> Self {
+        FrameArena {
+            bump: Bump::with_capacity(bytes),
+        }
+    }
+
+    /// An empty vector in the arena.
+    #[inline]
+    pub fn vec<T>(&self) -> FrameVec<'_, T> {
+        FrameVec::new_in(&self.bump)
+    }
+
+    /// An empty vector in the arena with room for `n` elements.
+    #[inline]
+    pub fn vec_with_capacity<T>(&self, n: usize) -> FrameVec<'_, T> {
+        FrameVec::with_capacity_in(n, &self.bump)
+    }
+
+    /// An empty string in the arena.
+    #[inline]
+    pub fn string(&self) -> FrameString<'_> {
+        FrameString::new_in(&self.bump)
+    }
+
+    /// A copy of `s` in the arena.
+    #[inline]
+    pub fn alloc_str(&self, s: &str) -> &mut str {
+        self.bump.alloc_str(s)
+    }
+
+    /// Move `value` into the arena. It's never dropped.
+    #[inline]
+    pub fn alloc<T>(&self, value: T) -> &mut T {
+        self.bump.alloc(value)
+    }
+
+    /// The number of bytes the arena has taken from the heap, whether or not they're in use.
+    pub fn allocated_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+
+    /// Free everything allocated in the arena. It keeps its largest chunk of memory for reuse,
+    /// so a steady per-frame workload stops touching the heap after the first few frames.
+    pub fn reset(&mut self) {
+        self.bump.reset();
+    }
+}
+
+/// Cloning an arena gives an empty one, since nothing allocated in it can be borrowed while it's
+/// being cloned anyway.
+impl Clone for FrameArena {
+    fn clone(&self) -> Self {
+        FrameArena::new()
+    }
+}
+
+impl fmt::Debug for FrameArena {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FrameArena")
+            .field("allocated_bytes", &self.allocated_bytes())
+            .finish()
+    }
+}
+
+/// System that resets the world's `FrameArena`. Run it once per frame, before or after every
+/// system that uses the arena.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ResetFrameArena;
+
+impl<'a> System<'a> for ResetFrameArena {
+    type Dependencies = (WriteResource<'a, FrameArena>,);
+    fn run(&'a mut self, (mut arena,): Self::Dependencies) {
+        arena.reset();
+    }
+}
@@ -0,0 +1,412 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Keyboard, button and axis state, and mapping it to named actions.
+//!
+//! Windowing and terminal libraries (winit, crossterm, SDL and so on) each report input in their
+//! own way. The game's event loop translates what it gets into
+//! [`InputEvent`](enum.InputEvent.html)s over whatever type `I` it uses to identify keys, buttons
+//! and axes (the backend's own key code type, or an enum of the game's), and pushes them to an
+//! `InputEvents<InputEvent<I>>` resource each frame. Because that's the same resource `Replay`
+//! records, sessions driven this way can be replayed.
+//!
+//! The [`UpdateInput`](struct.UpdateInput.html) system folds each frame's events into an
+//! [`InputState`](struct.InputState.html) resource, which remembers which buttons are held, which
+//! were pressed or released this frame, and where each axis is. Run it before any system that
+//! reads the state. As with other `InputEvents`, clearing the events at the end of the frame is
+//! up to the game.
+//!
+//! Rather than checking particular keys, systems usually ask an
+//! [`ActionMap`](struct.ActionMap.html) resource about named actions, like `"jump"`, each bound
+//! to any number of buttons and axes. `ActionMap` implements `Persist`, so the bindings can be
+//! loaded from a settings file in whatever format the game reads into a `Value`: a map from each
+//! action to a list of bindings, each of which is either an input, or a map with an `input` and a
+//! `scale` to multiply it by (e.g. `-1` for the left arrow in a horizontal `"move"` action).
+//!
+//! This module needs the `input` feature.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Position(f32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!         }
+//!         resources {
+//!             events: InputEvents<InputEvent<String>>,
+//!             input: InputState<String>,
+//!             actions: ActionMap<String>,
+//!         }
+//!     }
+//! );
+//!
+//! struct Walk;
+//! impl<'a> System<'a> for Walk {
+//!     type Dependencies = (
+//!         ReadResource<'a, InputState<String>>,
+//!         ReadResource<'a, ActionMap<String>>,
+//!         WriteComponent<'a, Position>,
+//!     );
+//!     fn run(&'a mut self, (input, actions, mut positions): Self::Dependencies) {
+//!         let dx = actions.value(&input, "move");
+//!         (&mut positions,).for_each(|_, (p,)| p.0 += dx);
+//!     }
+//! }
+//!
+//! // As read from a settings file.
+//! let bindings = Value::Map(vec![(
+//!     "move".to_string(),
+//!     Value::List(vec![
+//!         Value::Str("Right".to_string()),
+//!         Value::Map(vec![
+//!             ("input".to_string(), Value::Str("Left".to_string())),
+//!             ("scale".to_string(), Value::Float(-1.0)),
+//!         ]),
+//!         Value::Str("StickX".to_string()),
+//!     ]),
+//! )]);
+//!
+//! let mut w = World::default();
+//! *<World as GetResource<ActionMap<String>>>::get_mut(&w) = ActionMap::load(&bindings).unwrap();
+//! let e = w.new_entity().with(Position(0.0)).build();
+//! let mut dispatcher = Dispatcher::new()
+//!     .with(&mut w, UpdateInput::<String>::new())
+//!     .with(&mut w, Walk);
+//!
+//! // What the event loop does with each frame's events, once translated from the backend's.
+//! let mut frame = |w: &mut World, events: Vec<InputEvent<String>>| {
+//!     for event in events {
+//!         <World as GetResource<InputEvents<InputEvent<String>>>>::get_mut(w).push(event);
+//!     }
+//!     dispatcher.run(w).unwrap();
+//!     <World as GetResource<InputEvents<InputEvent<String>>>>::get_mut(w).clear();
+//! };
+//! frame(&mut w, vec![InputEvent::Pressed("Left".to_string())]);
+//! frame(&mut w, vec![]);
+//! frame(&mut w, vec![InputEvent::Released("Left".to_string())]);
+//! frame(&mut w, vec![InputEvent::Axis("StickX".to_string(), 0.5)]);
+//! assert_eq!(w.component::<Position>().get(e).unwrap().0, -1.5);
+//! ```
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::marker::PhantomData;
+
+use crate::hash::{HashMap, HashSet};
+use crate::*;
+
+/// A change in an input, as reported by a backend. See the [module-level
+/// documentation](index.html).
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent<I> {
+    /// A key or button went down. Repeats while it's held are ignored.
+    Pressed(I),
+    /// A key or button came back up.
+    Released(I),
+    /// An axis, like a gamepad stick or trigger, moved to the given position, usually between
+    /// -1 and 1.
+    Axis(I, f32),
+}
+
+/// Resource holding the state of every key, button and axis. See the [module-level
+/// documentation](index.html).
+#[derive(Clone, Debug)]
+pub struct InputState<I: Eq + Hash> {
+    down: HashSet<I>,
+    pressed: HashSet<I>,
+    released: HashSet<I>,
+    axes: HashMap<I, f32>,
+}
+
+impl<I: Eq + Hash> Default for InputState<I> {
+    fn default() -> Self {
+        InputState {
+            down: Default::default(),
+            pressed: Default::default(),
+            released: Default::default(),
+            axes: Default::default(),
+        }
+    }
+}
+
+impl<I: Eq + Hash + Clone> InputState<I> {
+    /// Apply an event.
+    pub fn apply(&mut self, event: &InputEvent<I>) {
+        match event {
+            InputEvent::Pressed(input) => {
+                if self.down.insert(input.clone()) {
+                    self.pressed.insert(input.clone());
+                }
+            }
+            InputEvent::Released(input) => {
+                if self.down.remove(input) {
+                    self.released.insert(input.clone());
+                }
+            }
+            InputEvent::Axis(input, value) => {
+                self.axes.insert(input.clone(), *value);
+            }
+        }
+    }
+
+    /// Forget which inputs were pressed and released, at the start of a new frame. `UpdateInput`
+    /// does this before applying the frame's events.
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    /// Release every key and button and center every axis, e.g. when the window loses focus and
+    /// stops receiving the events that would do so.
+    pub fn release_all(&mut self) {
+        self.released.extend(self.down.drain());
+        self.pressed.clear();
+        self.axes.clear();
+    }
+}
+
+impl<I: Eq + Hash> InputState<I> {
+    /// Whether `input` is held down.
+    pub fn is_down(&self, input: &I) -> bool {
+        self.down.contains(input)
+    }
+
+    /// Whether `input` went down this frame.
+    pub fn just_pressed(&self, input: &I) -> bool {
+        self.pressed.contains(input)
+    }
+
+    /// Whether `input` came up this frame.
+    pub fn just_released(&self, input: &I) -> bool {
+        self.released.contains(input)
+    }
+
+    /// The position of the axis `input`, or 0 if it hasn't moved.
+    pub fn axis(&self, input: &I) -> f32 {
+        self.axes.get(input).copied().unwrap_or(0.0)
+    }
+
+    /// Iterate over the keys and buttons that are held down, in no particular order.
+    pub fn down(&self) -> impl Iterator<Item = &I> + '_ {
+        self.down.iter()
+    }
+}
+
+/// An input bound to an action, with what to multiply its value by.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Binding<I> {
+    /// The key, button or axis.
+    pub input: I,
+    /// What to multiply the input's value by: 1 for a key or button that's held down, or the
+    /// axis's position.
+    pub scale: f32,
+}
+
+impl<I: Eq + Hash> Binding<I> {
+    /// The binding's contribution to its action's value.
+    pub fn value(&self, state: &InputState<I>) -> f32 {
+        let value = if state.is_down(&self.input) {
+            1.0
+        } else {
+            state.axis(&self.input)
+        };
+        value * self.scale
+    }
+}
+
+/// Saved as the input alone if its scale is 1, otherwise as a map with `input` and `scale`.
+impl<I: Persist> Persist for Binding<I> {
+    fn save(&self) -> Value {
+        if self.scale == 1.0 {
+            self.input.save()
+        } else {
+            Value::Map(alloc::vec![
+                (String::from("input"), self.input.save()),
+                (String::from("scale"), self.scale.save()),
+            ])
+        }
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        if let Value::Map(_) = value {
+            if let Some(input) = value.optional_field("input")? {
+                let scale = match value.optional_field("scale")? {
+                    Some(scale) => f32::load(scale)?,
+                    None => 1.0,
+                };
+                return Ok(Binding {
+                    input: I::load(input)?,
+                    scale,
+                });
+            }
+        }
+        Ok(Binding {
+            input: I::load(value)?,
+            scale: 1.0,
+        })
+    }
+}
+
+impl<I: Persist> Migrate for Binding<I> {}
+
+/// Resource mapping named actions to the inputs bound to them. See the [module-level
+/// documentation](index.html).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ActionMap<I> {
+    actions: BTreeMap<String, Vec<Binding<I>>>,
+}
+
+impl<I> Default for ActionMap<I> {
+    fn default() -> Self {
+        ActionMap {
+            actions: BTreeMap::new(),
+        }
+    }
+}
+
+impl<I> ActionMap<I> {
+    /// Create an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind `input` to `action`.
+    pub fn bind<S: Into<String>>(&mut self, action: S, input: I) -> &mut Self {
+        self.bind_scaled(action, input, 1.0)
+    }
+
+    /// Bind `input` to `action`, multiplying its value by `scale`.
+    pub fn bind_scaled<S: Into<String>>(&mut self, action: S, input: I, scale: f32) -> &mut Self {
+        self.actions
+            .entry(action.into())
+            .or_default()
+            .push(Binding { input, scale });
+        self
+    }
+
+    /// Remove every binding of `action`.
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    /// The inputs bound to `action`.
+    pub fn bindings(&self, action: &str) -> &[Binding<I>] {
+        self.actions.get(action).map_or(&[], |b| &b[..])
+    }
+
+    /// Iterate over the actions with bindings, in alphabetical order.
+    pub fn actions(&self) -> impl Iterator<Item = &str> + '_ {
+        self.actions.keys().map(String::as_str)
+    }
+}
+
+impl<I: Eq + Hash> ActionMap<I> {
+    /// The value of `action`: the sum of its bindings' values, clamped to between -1 and 1. 0 if
+    /// the action has no bindings.
+    pub fn value(&self, state: &InputState<I>, action: &str) -> f32 {
+        self.bindings(action)
+            .iter()
+            .map(|b| b.value(state))
+            .sum::<f32>()
+            .clamp(-1.0, 1.0)
+    }
+
+    /// Whether any of `action`'s bindings is held down or away from the center.
+    pub fn is_active(&self, state: &InputState<I>, action: &str) -> bool {
+        self.bindings(action).iter().any(|b| b.value(state) != 0.0)
+    }
+
+    /// Whether any key or button bound to `action` went down this frame.
+    pub fn just_activated(&self, state: &InputState<I>, action: &str) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|b| state.just_pressed(&b.input))
+    }
+
+    /// Whether any key or button bound to `action` came up this frame.
+    pub fn just_deactivated(&self, state: &InputState<I>, action: &str) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|b| state.just_released(&b.input))
+    }
+}
+
+/// Saved as a map from each action to the list of its bindings.
+impl<I: Persist> Persist for ActionMap<I> {
+    fn save(&self) -> Value {
+        Value::Map(
+            self.actions
+                .iter()
+                .map(|(action, bindings)| (action.clone(), bindings.save()))
+                .collect(),
+        )
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        let mut actions = BTreeMap::new();
+        for (action, bindings) in value.as_map()? {
+            actions.insert(action.clone(), Vec::load(bindings)?);
+        }
+        Ok(ActionMap { actions })
+    }
+}
+
+impl<I: Persist> Migrate for ActionMap<I> {}
+
+/// System that applies each frame's `InputEvents<InputEvent<I>>` to the `InputState<I>`. See the
+/// [module-level documentation](index.html).
+pub struct UpdateInput<I> {
+    marker: PhantomData<fn() -> I>,
+}
+
+impl<I> UpdateInput<I> {
+    /// Create the system.
+    pub fn new() -> Self {
+        UpdateInput {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<I> Default for UpdateInput<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I> core::fmt::Debug for UpdateInput<I> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("UpdateInput").finish()
+    }
+}
+
+impl<'a, I: Eq + Hash + Clone + 'static> System<'a> for UpdateInput<I> {
+    type Dependencies = (
+        ReadResource<'a, InputEvents<InputEvent<I>>>,
+        WriteResource<'a, InputState<I>>,
+    );
+    fn run(&'a mut self, (events, mut state): Self::Dependencies) {
+        state.end_frame();
+        for event in events.iter() {
+            state.apply(event);
+        }
+    }
+}
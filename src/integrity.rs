@@ -0,0 +1,316 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checking a world's internal invariants.
+//!
+//! `World::validate` walks the entity table, the free list, every storage and the component masks,
+//! and returns an [`IntegrityReport`](struct.IntegrityReport.html) listing everything that doesn't
+//! add up. A world that has only been changed through its own API always passes; the check is
+//! meant for debug builds of code that writes to storages directly.
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # #[derive(Debug, Default)]
+//! # pub struct Health(u32);
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             health: BasicVecStorage<Health>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let e = w.new_entity().with(Health(3)).build();
+//! w.delete_entity(e);
+//! assert!(w.validate().is_ok());
+//!
+//! // Bypassing the world leaves a component behind on a dead entity.
+//! <World as GetComponent<'_, Health>>::get_mut(&w).set(e, Some(Health(1)));
+//! let report = w.validate();
+//! assert_eq!(
+//!     report.problems(),
+//!     &[IntegrityProblem::DeadComponent {
+//!         component: std::any::type_name::<Health>(),
+//!         entity: e,
+//!     }]
+//! );
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::hash::HashMap;
+use crate::{ComponentMasks, ComponentStorage, Entity};
+
+/// Something wrong with a world, found by `World::validate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IntegrityProblem {
+    /// The entity table holds an entity in a slot other than its own id.
+    MisplacedEntity {
+        /// The slot.
+        slot: usize,
+        /// The entity found there.
+        entity: Entity,
+    },
+    /// An entity on the free list has an id past the end of the entity table.
+    FreeOutOfRange(Entity),
+    /// A slot is on the free list more than once, so it would be handed out twice.
+    DuplicateFree(Entity),
+    /// A slot is on the free list but is still occupied.
+    FreeButAlive {
+        /// The free list entry.
+        freed: Entity,
+        /// The entity occupying the slot.
+        alive: Entity,
+    },
+    /// A slot is empty but isn't on the free list, so it will never be reused.
+    LeakedSlot(usize),
+    /// A storage says the component in a slot belongs to an entity with a different id.
+    MisplacedComponent {
+        /// The component's type name.
+        component: &'static str,
+        /// The slot.
+        slot: usize,
+        /// The entity the storage reported for it.
+        entity: Entity,
+    },
+    /// A storage has a component for an entity that isn't alive.
+    DeadComponent {
+        /// The component's type name.
+        component: &'static str,
+        /// The dead entity.
+        entity: Entity,
+    },
+    /// A storage has a component for a generation of a free slot that is newer than the one on
+    /// the free list, so the slot's generation has gone backwards. The next entity in the slot
+    /// would pick the component up.
+    GenerationRegressed {
+        /// The component's type name.
+        component: &'static str,
+        /// The free list entry for the slot.
+        freed: Entity,
+        /// The entity the component belongs to.
+        found: Entity,
+    },
+    /// The component mask for a slot disagrees with the component's storage.
+    MaskMismatch {
+        /// The component's type name.
+        component: &'static str,
+        /// The slot.
+        slot: usize,
+        /// Whether the mask has the component.
+        masked: bool,
+    },
+}
+
+impl fmt::Display for IntegrityProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityProblem::MisplacedEntity { slot, entity } => {
+                write!(f, "slot {} holds {:?}", slot, entity)
+            }
+            IntegrityProblem::FreeOutOfRange(entity) => {
+                write!(
+                    f,
+                    "free list entry {:?} is past the end of the entities",
+                    entity
+                )
+            }
+            IntegrityProblem::DuplicateFree(entity) => {
+                write!(f, "slot {} is on the free list more than once", entity.id())
+            }
+            IntegrityProblem::FreeButAlive { freed, alive } => write!(
+                f,
+                "free list entry {:?} is for a slot occupied by {:?}",
+                freed, alive
+            ),
+            IntegrityProblem::LeakedSlot(slot) => {
+                write!(f, "slot {} is empty but not on the free list", slot)
+            }
+            IntegrityProblem::MisplacedComponent {
+                component,
+                slot,
+                entity,
+            } => write!(
+                f,
+                "`{}` storage reports {:?} in slot {}",
+                component, entity, slot
+            ),
+            IntegrityProblem::DeadComponent { component, entity } => {
+                write!(f, "dead entity {:?} has a `{}`", entity, component)
+            }
+            IntegrityProblem::GenerationRegressed {
+                component,
+                freed,
+                found,
+            } => write!(
+                f,
+                "`{}` for {:?} is newer than free list entry {:?}",
+                component, found, freed
+            ),
+            IntegrityProblem::MaskMismatch {
+                component,
+                slot,
+                masked,
+            } => write!(
+                f,
+                "mask for slot {} says it {} a `{}`, but its storage disagrees",
+                slot,
+                if *masked { "has" } else { "doesn't have" },
+                component
+            ),
+        }
+    }
+}
+
+/// The result of `World::validate`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    problems: Vec<IntegrityProblem>,
+}
+
+impl IntegrityReport {
+    /// Whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// The problems found, entity table and free list first, then each component in declaration
+    /// order.
+    pub fn problems(&self) -> &[IntegrityProblem] {
+        &self.problems
+    }
+}
+
+impl fmt::Display for IntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "no problems found");
+        }
+        write!(f, "{} problem(s) found:", self.problems.len())?;
+        for problem in self.problems.iter() {
+            write!(f, "\n  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds an `IntegrityReport`. Used by `World::validate`; not meant to be used directly.
+#[doc(hidden)]
+pub struct IntegrityCheck<'w> {
+    entities: &'w [Option<Entity>],
+    masks: &'w ComponentMasks,
+    // The free list entry for each free slot.
+    free: HashMap<usize, Entity>,
+    problems: Vec<IntegrityProblem>,
+}
+
+impl<'w> IntegrityCheck<'w> {
+    /// Check the entity table and free list.
+    pub fn new(
+        entities: &'w [Option<Entity>],
+        free_list: &[Entity],
+        masks: &'w ComponentMasks,
+    ) -> Self {
+        let mut problems = Vec::new();
+        for (slot, entity) in entities.iter().enumerate() {
+            if let Some(entity) = entity {
+                if entity.id() != slot {
+                    problems.push(IntegrityProblem::MisplacedEntity {
+                        slot,
+                        entity: *entity,
+                    });
+                }
+            }
+        }
+        let mut free = HashMap::new();
+        for &freed in free_list {
+            match entities.get(freed.id()) {
+                None => problems.push(IntegrityProblem::FreeOutOfRange(freed)),
+                Some(&Some(alive)) => {
+                    problems.push(IntegrityProblem::FreeButAlive { freed, alive })
+                }
+                Some(None) => {
+                    if free.insert(freed.id(), freed).is_some() {
+                        problems.push(IntegrityProblem::DuplicateFree(freed));
+                    }
+                }
+            }
+        }
+        for (slot, entity) in entities.iter().enumerate() {
+            if entity.is_none() && !free.contains_key(&slot) {
+                problems.push(IntegrityProblem::LeakedSlot(slot));
+            }
+        }
+        IntegrityCheck {
+            entities,
+            masks,
+            free,
+            problems,
+        }
+    }
+
+    /// Check the storage for component number `index`.
+    pub fn component<'a, S: ComponentStorage<'a>>(&mut self, index: usize, storage: &S) {
+        let component = core::any::type_name::<S::Component>();
+        let synced = self.masks.is_synced(index);
+        for slot in 0..storage.size().max(self.entities.len()) {
+            let alive = self.entities.get(slot).copied().flatten();
+            if let Some(entity) = storage.entity(slot) {
+                if entity.id() != slot {
+                    self.problems.push(IntegrityProblem::MisplacedComponent {
+                        component,
+                        slot,
+                        entity,
+                    });
+                } else if alive != Some(entity) {
+                    match self.free.get(&slot) {
+                        Some(&freed) if entity.generation() > freed.generation() => {
+                            self.problems.push(IntegrityProblem::GenerationRegressed {
+                                component,
+                                freed,
+                                found: entity,
+                            })
+                        }
+                        _ => self
+                            .problems
+                            .push(IntegrityProblem::DeadComponent { component, entity }),
+                    }
+                }
+            }
+            // Masks are cleared when an entity is deleted, so a dead slot should never have a
+            // bit set. For a live entity, the mask is only exact if the storage is synced.
+            let masked = self.masks.contains(slot, index);
+            let stored = alive.is_some_and(|entity| storage.get(entity).is_some());
+            if masked != stored && (alive.is_none() || synced) {
+                self.problems.push(IntegrityProblem::MaskMismatch {
+                    component,
+                    slot,
+                    masked,
+                });
+            }
+        }
+    }
+
+    /// Finish the check.
+    pub fn finish(self) -> IntegrityReport {
+        IntegrityReport {
+            problems: self.problems,
+        }
+    }
+}
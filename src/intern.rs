@@ -0,0 +1,221 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Interned values, shared between every entity that has a copy.
+//!
+//! Names, descriptions and dialogue keys tend to repeat across many entities, and storing a
+//! `String` for each costs an allocation per entity. An [`Interner`](struct.Interner.html), kept
+//! as a resource, hands out [`Interned`](struct.Interned.html) handles instead: interning a value
+//! that has been interned before returns a handle to the existing copy, and cloning a handle only
+//! bumps a reference count. Handles can be stored in any storage, and compare equal quickly when
+//! they point at the same copy.
+//!
+//! Unlike `SharedStorage`, which deduplicates the values within one storage, an interner can be
+//! shared by several components (an entity's name and its nameplate's text, say), and the values
+//! can't be changed in place.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Debug, Default)]
+//! pub struct Name(pub Interned);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             names: BasicVecStorage<Name>,
+//!         }
+//!         resources {
+//!             interner: Interner,
+//!         }
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let mut goblins = Vec::new();
+//! for _ in 0..100 {
+//!     let name = <World as GetResource<Interner>>::get_mut(&w).intern("goblin");
+//!     goblins.push(w.new_entity().with(Name(name)).build());
+//! }
+//! let names = <World as GetComponent<'_, Name>>::get(&w);
+//! assert_eq!(&*names.get(goblins[0]).unwrap().0, "goblin");
+//! assert!(Interned::ptr_eq(
+//!     &names.get(goblins[0]).unwrap().0,
+//!     &names.get(goblins[99]).unwrap().0
+//! ));
+//! assert_eq!(<World as GetResource<Interner>>::get(&w).len(), 1);
+//! ```
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+
+use crate::hash::HashSet;
+use crate::persist::{Migrate, Persist, PersistError, Value};
+
+/// A handle to an interned value. Dereferences to the value, and is cheap to clone.
+pub struct Interned<T: ?Sized = str>(Arc<T>);
+
+impl<T: ?Sized> Interned<T> {
+    /// Whether `a` and `b` are handles to the same copy of a value. Handles from the same
+    /// `Interner` are equal exactly when they are.
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Arc::ptr_eq(&a.0, &b.0)
+    }
+}
+
+impl<T: ?Sized> Clone for Interned<T> {
+    fn clone(&self) -> Self {
+        Interned(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> Deref for Interned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for Interned<T> {
+    fn as_ref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized> Borrow<T> for Interned<T> {
+    fn borrow(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for Interned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Interned::ptr_eq(self, other) || *self.0 == *other.0
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Interned<T> {}
+
+impl<T: ?Sized + Hash> Hash for Interned<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Interned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for Interned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The empty string, which isn't shared with any interner.
+impl Default for Interned<str> {
+    fn default() -> Self {
+        Interned(Arc::from(""))
+    }
+}
+
+/// Saved as a string. Loading doesn't know about any interner, so each loaded handle has its own
+/// copy of the string until it's passed to `Interner::intern`.
+impl Persist for Interned<str> {
+    fn save(&self) -> Value {
+        Value::Str(String::from(&*self.0))
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        String::load(value).map(|s| Interned(Arc::from(s)))
+    }
+}
+
+impl Migrate for Interned<str> {}
+
+/// The set of values that have been interned. Usually kept as a resource; see the
+/// [module-level documentation](index.html).
+///
+/// `T` is `str` by default, but can be any type that can be hashed, like `[u8]` or an enum.
+pub struct Interner<T: ?Sized = str> {
+    values: HashSet<Interned<T>>,
+}
+
+impl<T: ?Sized> Default for Interner<T> {
+    fn default() -> Self {
+        Interner {
+            values: HashSet::default(),
+        }
+    }
+}
+
+impl<T: ?Sized + Eq + Hash> Interner<T> {
+    /// Create an empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a handle to the interned copy of `value`, interning it if it hasn't been already.
+    pub fn intern(&mut self, value: &T) -> Interned<T>
+    where
+        T: ToOwned,
+        Arc<T>: From<T::Owned>,
+    {
+        if let Some(interned) = self.values.get(value) {
+            return interned.clone();
+        }
+        let interned = Interned(Arc::from(value.to_owned()));
+        self.values.insert(interned.clone());
+        interned
+    }
+
+    /// Get a handle to the interned copy of `value`, if it's been interned.
+    pub fn get(&self, value: &T) -> Option<Interned<T>> {
+        self.values.get(value).cloned()
+    }
+
+    /// The number of distinct values interned.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether nothing has been interned.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Forget every value that nothing outside the interner has a handle to any more, freeing
+    /// its memory. Returns the number of values forgotten.
+    pub fn collect_garbage(&mut self) -> usize {
+        let before = self.values.len();
+        self.values.retain(|v| Arc::strong_count(&v.0) > 1);
+        before - self.values.len()
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Interner<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.values.iter()).finish()
+    }
+}
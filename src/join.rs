@@ -14,6 +14,17 @@
 
 //! Support for iterating over a joined view of different components.
 //!
+//! # Ordering
+//! Joins always visit entities in ascending id order, whatever storages are involved and however
+//! the components were inserted, so anything computed by a join (sums, "first match" searches,
+//! the order events are emitted in) is the same on every platform and every run. Replays and
+//! lockstep multiplayer depend on this. Joins run on a single thread, so reductions over them are
+//! never reordered.
+//!
+//! This relies on storages keeping to the `ComponentStorage` contract; the `deterministic`
+//! feature checks it at runtime, unless `bench_support` is also enabled. (`Join::for_each_in` visits entities in the order they're
+//! listed instead.)
+//!
 //! # Soundness
 //! This part of the library contains some unsafe code, which could set of some alarm bells.
 //! However, this usage should be safe and free of undefined behavior.
@@ -68,14 +79,29 @@
 //! possible for client code to violate this soundness by implementing this trait and doing
 //! something funky with the closure.
 
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use crate::hash::HashMap;
 use crate::*;
 
 mod private {
     pub trait Sealed {}
-    use crate::{ReadComponent, StorageSpec, WriteComponent};
-    impl<'a, 'b, H, T> Sealed for (&'a ReadComponent<'b, H>, T) where H: StorageSpec<'b> {}
-    impl<'a, 'b, H, T> Sealed for (&'a WriteComponent<'b, H>, T) where H: StorageSpec<'b> {}
-    impl<'a, 'b, H, T> Sealed for (&'a mut WriteComponent<'b, H>, T) where H: StorageSpec<'b> {}
+    use crate::{
+        InLayers, MultiView, MultiViewMut, OnScreen, Prev, ReadComponent, RestrictedWriteComponent,
+        StorageSpec, Visibility, WriteComponent,
+    };
+    impl<'b, H, T> Sealed for (&ReadComponent<'b, H>, T) where H: StorageSpec<'b> {}
+    impl<'b, H, T> Sealed for (&WriteComponent<'b, H>, T) where H: StorageSpec<'b> {}
+    impl<'b, H, T> Sealed for (&mut WriteComponent<'b, H>, T) where H: StorageSpec<'b> {}
+    impl<'b, H, T> Sealed for (&RestrictedWriteComponent<'b, H>, T) where H: StorageSpec<'b> {}
+    impl<'b, H, T> Sealed for (&mut RestrictedWriteComponent<'b, H>, T) where H: StorageSpec<'b> {}
+    impl<'b, H, T> Sealed for (&MultiView<'b, H>, T) {}
+    impl<'b, H, T> Sealed for (&mut MultiViewMut<'b, H>, T) {}
+    impl<'b, H, T> Sealed for (&Prev<'b, H>, T) {}
+    impl<T> Sealed for (&Visibility, T) {}
+    impl<T, U> Sealed for (InLayers<'_, U>, T) {}
+    impl<T, S> Sealed for (OnScreen<'_, S>, T) {}
     impl Sealed for () {}
 }
 
@@ -89,6 +115,10 @@ pub trait Joinable: private::Sealed {
         F: FnOnce(Self::Output);
     /// HACK: return the number of entities in the underlying storage.
     fn size(&self) -> usize;
+    /// The entity stored in slot `id` of the first storage in the list.
+    fn entity(&self, id: usize) -> Option<Entity>;
+    /// Whether `e` is disabled in the world the first view from a world came from.
+    fn is_disabled(&self, e: Entity) -> bool;
 }
 
 impl<'a, 'b, H, T> Joinable for (&'a ReadComponent<'b, H>, T)
@@ -109,6 +139,15 @@ where
     fn size(&self) -> usize {
         self.0.size()
     }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        match self.0.disabled {
+            Some(disabled) => disabled.contains(e),
+            None => self.1.is_disabled(e),
+        }
+    }
 }
 
 impl<'a, 'b, H, T> Joinable for (&'a WriteComponent<'b, H>, T)
@@ -129,6 +168,15 @@ where
     fn size(&self) -> usize {
         self.0.size()
     }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        match self.0.disabled {
+            Some(disabled) => disabled.contains(e),
+            None => self.1.is_disabled(e),
+        }
+    }
 }
 
 impl<'a, 'b, H, T> Joinable for (&'a mut WriteComponent<'b, H>, T)
@@ -139,6 +187,123 @@ where
 {
     type Output = (&'a mut H, T::Output);
     fn process<F>(&mut self, e: Entity, f: F)
+    where
+        F: FnOnce(Self::Output),
+    {
+        let v = self.0.get_raw_mut(e);
+        if !v.is_null() {
+            self.0.probe.touch();
+            self.0.stamp.mark();
+            self.1.process(e, move |tail| f((unsafe { &mut *v }, tail)))
+        }
+    }
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        match self.0.disabled {
+            Some(disabled) => disabled.contains(e),
+            None => self.1.is_disabled(e),
+        }
+    }
+}
+
+impl<'a, 'b, H, T> Joinable for (&'a RestrictedWriteComponent<'b, H>, T)
+where
+    H: StorageSpec<'b, Component = H>,
+    T: Joinable,
+{
+    type Output = (&'a H, T::Output);
+    fn process<F>(&mut self, e: Entity, f: F)
+    where
+        F: FnOnce(Self::Output),
+    {
+        let v = self.0.get_raw(e);
+        if !v.is_null() {
+            self.1.process(e, move |tail| f((unsafe { &*v }, tail)))
+        }
+    }
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        match self.0.disabled {
+            Some(disabled) => disabled.contains(e),
+            None => self.1.is_disabled(e),
+        }
+    }
+}
+
+// This is the only way to mutate through a `RestrictedWriteComponent`.
+impl<'a, 'b, H, T> Joinable for (&'a mut RestrictedWriteComponent<'b, H>, T)
+where
+    H: StorageSpec<'b, Component = H>,
+    H::Storage: MutableComponentStorage<'b>,
+    T: Joinable,
+{
+    type Output = (&'a mut H, T::Output);
+    fn process<F>(&mut self, e: Entity, f: F)
+    where
+        F: FnOnce(Self::Output),
+    {
+        self.0.probe.touch();
+        let v = self.0.storage.get_raw_mut(e);
+        if !v.is_null() {
+            self.0.stamp.mark();
+            self.1.process(e, move |tail| f((unsafe { &mut *v }, tail)))
+        }
+    }
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        match self.0.disabled {
+            Some(disabled) => disabled.contains(e),
+            None => self.1.is_disabled(e),
+        }
+    }
+}
+
+impl<'a, 'b, H, T> Joinable for (&'a MultiView<'b, H>, T)
+where
+    T: Joinable,
+{
+    type Output = (&'a [H], T::Output);
+    fn process<F>(&mut self, e: Entity, f: F)
+    where
+        F: FnOnce(Self::Output),
+    {
+        let v = self.0.get_raw(e);
+        if !v.is_null() {
+            self.1.process(e, move |tail| f((unsafe { &*v }, tail)))
+        }
+    }
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        self.1.is_disabled(e)
+    }
+}
+
+impl<'a, 'b, H, T> Joinable for (&'a mut MultiViewMut<'b, H>, T)
+where
+    T: Joinable,
+{
+    type Output = (&'a mut [H], T::Output);
+    fn process<F>(&mut self, e: Entity, f: F)
     where
         F: FnOnce(Self::Output),
     {
@@ -150,6 +315,112 @@ where
     fn size(&self) -> usize {
         self.0.size()
     }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        self.1.is_disabled(e)
+    }
+}
+
+impl<'a, 'b, H, T> Joinable for (&'a Prev<'b, H>, T)
+where
+    T: Joinable,
+{
+    type Output = (&'a H, T::Output);
+    fn process<F>(&mut self, e: Entity, f: F)
+    where
+        F: FnOnce(Self::Output),
+    {
+        let v = self.0.get_raw(e);
+        if !v.is_null() {
+            self.1.process(e, move |tail| f((unsafe { &*v }, tail)))
+        }
+    }
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        self.1.is_disabled(e)
+    }
+}
+
+impl<T> Joinable for (&Visibility, T)
+where
+    T: Joinable,
+{
+    type Output = ((), T::Output);
+    fn process<F>(&mut self, e: Entity, f: F)
+    where
+        F: FnOnce(Self::Output),
+    {
+        if self.0.contains(e) {
+            self.1.process(e, move |tail| f(((), tail)))
+        }
+    }
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        self.1.is_disabled(e)
+    }
+}
+
+impl<T, U> Joinable for (InLayers<'_, U>, T)
+where
+    T: Joinable,
+    U: AsRef<Layers>,
+{
+    type Output = ((), T::Output);
+    fn process<F>(&mut self, e: Entity, f: F)
+    where
+        F: FnOnce(Self::Output),
+    {
+        if self.0.contains(e) {
+            self.1.process(e, move |tail| f(((), tail)))
+        }
+    }
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        self.1.is_disabled(e)
+    }
+}
+
+impl<'a, T, S> Joinable for (OnScreen<'_, S>, T)
+where
+    T: Joinable,
+    S: ComponentStorage<'a>,
+    S::Component: GridPosition,
+{
+    type Output = ((), T::Output);
+    fn process<F>(&mut self, e: Entity, f: F)
+    where
+        F: FnOnce(Self::Output),
+    {
+        if self.0.contains(e) {
+            self.1.process(e, move |tail| f(((), tail)))
+        }
+    }
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.entity(id)
+    }
+    fn is_disabled(&self, e: Entity) -> bool {
+        self.1.is_disabled(e)
+    }
 }
 
 impl Joinable for () {
@@ -163,16 +434,115 @@ impl Joinable for () {
     fn size(&self) -> usize {
         0
     }
+    fn entity(&self, _id: usize) -> Option<Entity> {
+        None
+    }
+    fn is_disabled(&self, _e: Entity) -> bool {
+        false
+    }
 }
 
 /// Trait for joining different component types together.
 pub trait Join {
     /// Output type of the join.
     type Output;
-    /// Call `f` on each entity that contains all of the components in `Output`.
+    /// Iterator type returned by `iter`.
+    type Iter: Iterator<Item = (Entity, Self::Output)>;
+    /// Iterate over the entities that contain all of the components in `Output`, in ascending id
+    /// order, for use with the standard iterator adapters:
+    ///
+    /// ```ignore
+    /// let total = (&health,).iter().map(|(_, (h,))| h.current).sum::<u32>();
+    /// ```
+    fn iter(self) -> Self::Iter;
+    /// The entity whose components give the smallest key, along with those components. If several
+    /// are equally small, the one with the lowest id is returned.
+    fn min_by_key<K, F>(self, mut f: F) -> Option<(Entity, Self::Output)>
+    where
+        Self: Sized,
+        K: Ord,
+        F: FnMut(Entity, &Self::Output) -> K,
+    {
+        self.iter().min_by_key(|(e, v)| f(*e, v))
+    }
+    /// The entity whose components give the largest key, along with those components. If several
+    /// are equally large, the one with the highest id is returned.
+    fn max_by_key<K, F>(self, mut f: F) -> Option<(Entity, Self::Output)>
+    where
+        Self: Sized,
+        K: Ord,
+        F: FnMut(Entity, &Self::Output) -> K,
+    {
+        self.iter().max_by_key(|(e, v)| f(*e, v))
+    }
+    /// Partition the entities that contain all of the components in `Output` by the key `f`
+    /// computes for them, e.g. to batch rendering by sprite sheet. Each group lists its entities
+    /// in ascending id order.
+    fn group_by<K, F>(self, mut f: F) -> HashMap<K, Vec<Entity>>
+    where
+        Self: Sized,
+        K: Eq + Hash,
+        F: FnMut(Entity, Self::Output) -> K,
+    {
+        let mut groups: HashMap<K, Vec<Entity>> = HashMap::new();
+        for (e, v) in self.iter() {
+            groups.entry(f(e, v)).or_default().push(e);
+        }
+        groups
+    }
+    /// Call `f` on each entity that contains all of the components in `Output`, in ascending id
+    /// order.
     fn for_each<F>(self, f: F)
     where
         F: FnMut(Entity, Self::Output);
+    /// Call `f` on each of `entities` that contains all of the components in `Output`, in the
+    /// order they're listed. Entities that are listed more than once are only visited the first
+    /// time.
+    fn for_each_in<F>(self, entities: &[Entity], f: F)
+    where
+        F: FnMut(Entity, Self::Output);
+    /// The only entity that contains all of the components in `Output`, with its components.
+    /// Returns `None` if there are none or more than one.
+    fn single(self) -> Option<(Entity, Self::Output)>;
+    /// Like `single`, but panics, with the number of entities that matched, unless exactly one
+    /// does. Handy for things there can only be one of, like the player:
+    ///
+    /// ```
+    /// # #[macro_use] extern crate ecstatic;
+    /// # use ecstatic::*;
+    /// # #[derive(Default)]
+    /// # pub struct Player;
+    /// # #[derive(Default)]
+    /// # pub struct Position(i32, i32);
+    /// # define_world!(
+    /// #     #[derive(Default)]
+    /// #     pub world {
+    /// #         components {
+    /// #             players: VoidStorage<Player>,
+    /// #             positions: BasicVecStorage<Position>,
+    /// #         }
+    /// #         resources {}
+    /// #     }
+    /// # );
+    /// let mut w = World::default();
+    /// w.new_entity().with(Position(0, 0)).build();
+    /// let p = w.new_entity().with(Player).with(Position(3, 4)).build();
+    ///
+    /// let (players, mut positions): (ReadComponent<Player>, WriteComponent<Position>) =
+    ///     ComponentProvider::fetch(&w);
+    /// let (e, (_, pos)) = (&players, &mut positions).expect_one();
+    /// pos.0 += 1;
+    /// assert_eq!(e, p);
+    /// ```
+    fn expect_one(self) -> (Entity, Self::Output);
+    /// Visit disabled entities too, which joins skip by default. See the
+    /// [`disabled`](../disabled/index.html) module.
+    fn including_disabled(self) -> IncludingDisabled<Self>
+    where
+        Self: Sized,
+    {
+        IncludingDisabled(self)
+    }
 }
 
 impl<T> Join for T
@@ -182,17 +552,178 @@ where
     <T::Nested as Joinable>::Output: Flatten,
 {
     type Output = <<T::Nested as Joinable>::Output as Flatten>::Flattened;
-    fn for_each<F>(self, mut f: F)
+    type Iter = JoinIter<T::Nested>;
+    fn iter(self) -> Self::Iter {
+        JoinIter::new(self.nest(), true)
+    }
+    fn for_each<F>(self, f: F)
+    where
+        F: FnMut(Entity, Self::Output),
+    {
+        for_each(self.nest(), true, f)
+    }
+    fn single(self) -> Option<(Entity, Self::Output)> {
+        match find_one(self) {
+            (1, found) => found,
+            _ => None,
+        }
+    }
+    fn expect_one(self) -> (Entity, Self::Output) {
+        expect_one(self)
+    }
+    fn for_each_in<F>(self, entities: &[Entity], f: F)
+    where
+        F: FnMut(Entity, Self::Output),
+    {
+        for_each_in(self.nest(), true, entities, f)
+    }
+}
+
+/// A join that visits disabled entities too. See `Join::including_disabled`.
+pub struct IncludingDisabled<T>(T);
+
+impl<T> Join for IncludingDisabled<T>
+where
+    T: Nest,
+    T::Nested: Joinable,
+    <T::Nested as Joinable>::Output: Flatten,
+{
+    type Output = <<T::Nested as Joinable>::Output as Flatten>::Flattened;
+    type Iter = JoinIter<T::Nested>;
+    fn iter(self) -> Self::Iter {
+        JoinIter::new(self.0.nest(), false)
+    }
+    fn for_each<F>(self, f: F)
     where
         F: FnMut(Entity, Self::Output),
     {
-        let mut storage = self.nest();
-        for i in 0..storage.size() {
-            let e = Entity {
-                id: i,
-                generation: 0,
-            };
+        for_each(self.0.nest(), false, f)
+    }
+    fn single(self) -> Option<(Entity, Self::Output)> {
+        match find_one(self) {
+            (1, found) => found,
+            _ => None,
+        }
+    }
+    fn expect_one(self) -> (Entity, Self::Output) {
+        expect_one(self)
+    }
+    fn for_each_in<F>(self, entities: &[Entity], f: F)
+    where
+        F: FnMut(Entity, Self::Output),
+    {
+        for_each_in(self.0.nest(), false, entities, f)
+    }
+}
+
+fn for_each<J, F>(mut storage: J, skip_disabled: bool, mut f: F)
+where
+    J: Joinable,
+    J::Output: Flatten,
+    F: FnMut(Entity, <J::Output as Flatten>::Flattened),
+{
+    for i in 0..storage.size() {
+        // Every storage checks the generation, so this only visits entities whose components
+        // are all current.
+        if let Some(e) = storage.entity(i) {
+            #[cfg(all(feature = "deterministic", not(feature = "bench_support")))]
+            assert_eq!(e.id(), i, "storage returned an entity out of id order");
+            if skip_disabled && storage.is_disabled(e) {
+                continue;
+            }
             storage.process(e, |v| f(e, v.flatten()));
         }
     }
 }
+
+fn for_each_in<J, F>(mut storage: J, skip_disabled: bool, entities: &[Entity], mut f: F)
+where
+    J: Joinable,
+    J::Output: Flatten,
+    F: FnMut(Entity, <J::Output as Flatten>::Flattened),
+{
+    // Visiting an entity twice could hand out two mutable references to the same component.
+    let mut seen = crate::hash::HashSet::with_capacity(entities.len());
+    for &e in entities {
+        if skip_disabled && storage.is_disabled(e) {
+            continue;
+        }
+        if seen.insert(e) {
+            storage.process(e, |v| f(e, v.flatten()));
+        }
+    }
+}
+
+fn expect_one<T: Join>(join: T) -> (Entity, T::Output) {
+    match find_one(join) {
+        (1, Some(found)) => found,
+        (n, _) => panic!(
+            "expected exactly one entity with {}, found {}",
+            core::any::type_name::<T::Output>(),
+            n
+        ),
+    }
+}
+
+/// Iterator over the entities in a join. See `Join::iter`.
+pub struct JoinIter<J> {
+    storage: J,
+    next: usize,
+    size: usize,
+    skip_disabled: bool,
+}
+
+impl<J: Joinable> JoinIter<J> {
+    fn new(storage: J, skip_disabled: bool) -> Self {
+        JoinIter {
+            size: storage.size(),
+            next: 0,
+            storage,
+            skip_disabled,
+        }
+    }
+}
+
+impl<J> Iterator for JoinIter<J>
+where
+    J: Joinable,
+    J::Output: Flatten,
+{
+    type Item = (Entity, <J::Output as Flatten>::Flattened);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next < self.size {
+            let i = self.next;
+            self.next += 1;
+            if let Some(e) = self.storage.entity(i) {
+                #[cfg(all(feature = "deterministic", not(feature = "bench_support")))]
+                assert_eq!(e.id(), i, "storage returned an entity out of id order");
+                if self.skip_disabled && self.storage.is_disabled(e) {
+                    continue;
+                }
+                let mut item = None;
+                self.storage.process(e, |v| item = Some((e, v.flatten())));
+                if item.is_some() {
+                    return item;
+                }
+            }
+        }
+        None
+    }
+}
+
+// The number of entities that match the join, and the first one.
+fn find_one<T>(join: T) -> (usize, Option<(Entity, <T as Join>::Output)>)
+where
+    T: Join,
+{
+    let mut count = 0;
+    let mut first = None;
+    join.for_each(|e, v| {
+        count += 1;
+        if first.is_none() {
+            first = Some((e, v));
+        }
+    });
+    (count, first)
+}
@@ -0,0 +1,160 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Append-only log of structural changes made to a `World`.
+//!
+//! Every `World` generated by [`define_world!`](../macro.define_world.html) carries a `Journal`,
+//! which is available to systems as a resource (`ReadResource<'a, Journal>`). When the `journal`
+//! feature is enabled, the world records every spawn, despawn, component addition/removal and
+//! resource `set` into it, in the order they happened. Frames are delimited by calling
+//! [`mark_frame`](struct.Journal.html#method.mark_frame), so a crashed session can be stepped
+//! through frame by frame when debugging.
+//!
+//! Components added or removed through a storage borrowed mutably, like a system's
+//! `WriteComponent`, are recorded when the world next syncs its component masks (see
+//! [`WorldInterface::sync_masks`](../traits/trait.WorldInterface.html#tymethod.sync_masks)):
+//! before its next structural change, and whenever deferred changes are applied, as at the end of
+//! `Dispatcher::run`. Only the difference is recorded, so a component that was added and removed
+//! again in between doesn't show up.
+//!
+//! Without the `journal` feature, `Journal` is zero-sized and recording compiles down to nothing.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # #[derive(Default)]
+//! # pub struct Position(i32, i32);
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! <World as GetResource<Journal>>::get_mut(&w).mark_frame();
+//! let e = w.new_entity().with(Position(1, 2)).build();
+//! w.delete_entity(e);
+//!
+//! # #[cfg(feature = "journal")]
+//! assert_eq!(
+//!     <World as GetResource<Journal>>::get(&w).entries(),
+//!     &[
+//!         JournalEntry::Frame(0),
+//!         JournalEntry::Spawn(e),
+//!         JournalEntry::ComponentAdded {
+//!             entity: e,
+//!             component: std::any::type_name::<Position>(),
+//!         },
+//!         JournalEntry::ComponentRemoved {
+//!             entity: e,
+//!             component: std::any::type_name::<Position>(),
+//!         },
+//!         JournalEntry::Despawn(e),
+//!     ][..]
+//! );
+//! ```
+
+#[cfg(feature = "journal")]
+use alloc::vec::Vec;
+
+use crate::Entity;
+
+/// A single structural change recorded in a `Journal`.
+///
+/// Component and resource types are identified by their `std::any::type_name`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JournalEntry {
+    /// Start of a new frame.
+    Frame(u64),
+    /// An entity was created.
+    Spawn(Entity),
+    /// An entity was deleted.
+    Despawn(Entity),
+    /// Every entity was deleted at once, via `WorldInterface::clear`.
+    Clear,
+    /// A component was attached to an entity.
+    ComponentAdded {
+        /// The entity the component was attached to.
+        entity: Entity,
+        /// The type name of the component.
+        component: &'static str,
+    },
+    /// A component was removed from an entity.
+    ComponentRemoved {
+        /// The entity the component was removed from.
+        entity: Entity,
+        /// The type name of the component.
+        component: &'static str,
+    },
+    /// A resource was replaced via `GetResource::set`.
+    ResourceSet {
+        /// The type name of the resource.
+        resource: &'static str,
+    },
+}
+
+/// Append-only log of `JournalEntry`s.
+#[derive(Clone, Debug, Default)]
+pub struct Journal {
+    #[cfg(feature = "journal")]
+    entries: Vec<JournalEntry>,
+    #[cfg(feature = "journal")]
+    frame: u64,
+}
+
+impl Journal {
+    /// Append an entry to the log. This is a no-op unless the `journal` feature is enabled.
+    #[inline]
+    pub fn record(&mut self, _entry: JournalEntry) {
+        #[cfg(feature = "journal")]
+        self.entries.push(_entry);
+    }
+
+    /// Record the start of a new frame. Frames are numbered sequentially, starting at 0.
+    #[inline]
+    pub fn mark_frame(&mut self) {
+        #[cfg(feature = "journal")]
+        {
+            let frame = self.frame;
+            self.frame += 1;
+            self.record(JournalEntry::Frame(frame));
+        }
+    }
+
+    /// All entries recorded so far, oldest first.
+    #[cfg(feature = "journal")]
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// The entries recorded since the start of frame `frame`, or an empty slice if that frame
+    /// hasn't been marked.
+    #[cfg(feature = "journal")]
+    pub fn since_frame(&self, frame: u64) -> &[JournalEntry] {
+        match self
+            .entries
+            .iter()
+            .position(|e| *e == JournalEntry::Frame(frame))
+        {
+            Some(i) => &self.entries[i..],
+            None => &[],
+        }
+    }
+}
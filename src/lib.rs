@@ -30,7 +30,8 @@
 //!    along with trait implementations necessary for the library to interact with it
 //! 2. Implement one or more [`System`s](traits/trait.System.html)
 //! 3. Run your `System`s on the World using the
-//!    (`run_system`)[traits/trait.WorldInterface.html#method.run_system] method.
+//!    (`run_system`)[traits/trait.WorldInterface.html#method.run_system] method, or collect them
+//!    in a [`Dispatcher`](dispatch/struct.Dispatcher.html) and run them all at once.
 //!
 //! # Peculiarities
 //!
@@ -127,10 +128,7 @@
 //! w.run_system(&mut system);
 //!
 //! assert_eq!(system.total, 20);
-//! assert_eq!(
-//!     <World as GetComponent<'_, MoreData>>::get(&w).get(md),
-//!     Some(&MoreData { y: 84 })
-//! );
+//! assert_eq!(w.component::<MoreData>().get(md), Some(&MoreData { y: 84 }));
 //! ```
 //!
 //! Components accessed via `ReadComponent` cannot be iterated over mutably:
@@ -167,6 +165,21 @@
 //! }
 //! ```
 //!
+//! # `no_std`
+//!
+//! The crate only needs `alloc`. Turning off the default `std` feature drops the parts that need
+//! the standard library (the `compress`, `crash`, `sync` and `testing` modules,
+//! `IncrementalSave`, and the world's `save_to`, `load_from`, `save_incremental` and `snapshot`
+//! methods), and then the `hashbrown` feature has to be turned on to provide hash maps.
+
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+#[macro_use]
+extern crate alloc;
+
 #[macro_use]
 pub mod typelist;
 
@@ -178,20 +191,440 @@ pub mod storage;
 
 pub mod join;
 
+/// Handles for working with one entity at a time
+pub mod entity_ref;
+
+/// Structural change journaling
+pub mod journal;
+
+/// The cells that worlds keep their storages and resources in
+pub mod cell;
+
+/// Deterministic session record/playback
+pub mod replay;
+
+/// Snapshot-based undo/redo
+pub mod undo;
+
+/// Resource and component change detection
+pub mod change;
+
+/// Components computed from other components and resources
+pub mod computed;
+
+/// Per-system state
+pub mod local;
+
+/// Per-entity component presence
+pub mod mask;
+
+/// Entity names for debugging
+pub mod names;
+
+/// Disabling entities without deleting them
+pub mod disabled;
+
+/// Where and when entities were created, for debugging
+pub mod origins;
+
+/// Per-system component access statistics
+pub mod access_stats;
+
+/// Recording which system panicked
+pub mod poison;
+
+/// Warnings about suspicious access declarations and components
+#[cfg(feature = "lints")]
+pub mod lint;
+
+/// Interned values shared between entities
+pub mod intern;
+
+/// Dirty-region tracking for tile maps
+pub mod dirty;
+
+/// Streamed worlds keyed by chunk coordinate
+pub mod chunk;
+
+/// Visible-entity filter for joins
+pub mod visibility;
+
+/// Scrolling viewports onto tile maps
+pub mod viewport;
+
+/// Entities kept sorted by a key component
+pub mod sorted;
+
+/// Pathfinding requests and solver
+pub mod pathfinding;
+
+/// Typed, change-tracked data shared between AI systems
+pub mod blackboard;
+
+/// Behavior trees for AI
+#[cfg(feature = "bt")]
+pub mod bt;
+
+/// Converting data to and from a save-data value tree
+pub mod persist;
+
+/// Compact binary world saves
+pub mod binary;
+
+/// Pluggable compression for saves
+#[cfg(feature = "std")]
+pub mod compress;
+
+/// Writing the world to disk when the game crashes
+#[cfg(feature = "std")]
+pub mod crash;
+
+/// Synchronization primitives for running systems on more than one thread
+#[cfg(feature = "std")]
+pub mod sync;
+
+/// Checking a world's internal invariants
+pub mod integrity;
+
+/// Helpers for testing systems
+#[cfg(feature = "std")]
+pub mod testing;
+
+/// Arbitrary worlds for property tests
+#[cfg(feature = "quickcheck")]
+pub mod arbitrary;
+
+/// Entry points for micro-benchmarks
+pub mod bench;
+
+/// Reloading resources from files while the game runs
+#[cfg(feature = "watch")]
+pub mod watch;
+
+/// A bump allocator for temporary, per-frame allocations
+#[cfg(feature = "frame-arena")]
+pub mod frame_arena;
+
+/// Input state and action mapping
+#[cfg(feature = "input")]
+pub mod input;
+
+/// Drawing entities as characters on a grid
+#[cfg(feature = "term")]
+pub mod term;
+
+#[cfg(not(feature = "quickcheck"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_quickcheck {
+    ($($t:tt)*) => {};
+}
+
+/// Expands to its input only if the `std` feature is enabled, for use in `define_world!`.
+#[cfg(feature = "std")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_std {
+    ($($t:tt)*) => { $($t)* };
+}
+
+#[cfg(not(feature = "std"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_std {
+    ($($t:tt)*) => {};
+}
+
+/// Expands to its input only if the `lints` feature is enabled, for use in `define_world!`.
+#[cfg(feature = "lints")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_lints {
+    ($($t:tt)*) => { $($t)* };
+}
+
+#[cfg(not(feature = "lints"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_lints {
+    ($($t:tt)*) => {};
+}
+
+/// Expands to its input only if the `resource-accessors` feature is enabled, for use in
+/// `define_world!`.
+#[cfg(feature = "resource-accessors")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_resource_accessors {
+    ($($t:tt)*) => { $($t)* };
+}
+
+#[cfg(not(feature = "resource-accessors"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_resource_accessors {
+    ($($t:tt)*) => {};
+}
+
+/// System scheduling
+pub mod dispatch;
+
+/// Pluggable thread pools
+pub mod executor;
+
+/// Fanning a system's work out over threads
+pub mod task;
+
+/// Frame counting and timing
+pub mod frame;
+
+/// Timers and cooldowns
+pub mod timer;
+
+/// Animating component fields over time
+pub mod tween;
+
+/// Entities that despawn after a number of ticks
+pub mod lifetime;
+
+/// Deferred world mutation
+pub mod command;
+
+/// Events, and systems triggered by them
+pub mod observer;
+
+/// Static schedules with stage inference
+pub mod schedule;
+
+/// Plugins and the apps they're added to
+pub mod app;
+
 mod bitset;
 
+// Hash maps and sets, from `std` if it's available, otherwise from `hashbrown`.
+mod hash {
+    #[cfg(feature = "std")]
+    pub use std::collections::hash_map::DefaultHasher;
+    #[cfg(feature = "std")]
+    pub use std::collections::{HashMap, HashSet};
+
+    #[cfg(all(not(feature = "std"), feature = "hashbrown"))]
+    pub use hashbrown::{HashMap, HashSet};
+
+    #[cfg(not(any(feature = "std", feature = "hashbrown")))]
+    compile_error!("ecstatic needs either the `std` or the `hashbrown` feature");
+
+    /// FNV-1a, standing in for `std`'s `DefaultHasher` where values only need hashing the same
+    /// way every time.
+    #[cfg(not(feature = "std"))]
+    pub struct DefaultHasher(u64);
+
+    #[cfg(not(feature = "std"))]
+    impl DefaultHasher {
+        pub fn new() -> Self {
+            DefaultHasher(0xcbf2_9ce4_8422_2325)
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    impl core::hash::Hasher for DefaultHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+    }
+}
+
+// Paths used by the code that macros generate, which has to work in `no_std` crates that don't
+// declare `alloc`.
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::boxed::Box;
+    pub use alloc::string::String;
+    pub use alloc::vec::Vec;
+    pub use alloc::{format, vec};
+    #[cfg(feature = "resource-accessors")]
+    pub use paste::paste;
+
+    #[cfg(feature = "std")]
+    pub use std::sync::OnceLock;
+
+    /// A minimal `OnceLock` for `no_std`, which spins while another thread initializes it. The
+    /// value is never dropped, which is fine for the statics it's used for.
+    #[cfg(not(feature = "std"))]
+    pub struct OnceLock<T> {
+        state: core::sync::atomic::AtomicU8,
+        value: core::cell::UnsafeCell<core::mem::MaybeUninit<T>>,
+    }
+
+    #[cfg(not(feature = "std"))]
+    unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+
+    #[cfg(not(feature = "std"))]
+    impl<T> Default for OnceLock<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    impl<T> OnceLock<T> {
+        const EMPTY: u8 = 0;
+        const BUSY: u8 = 1;
+        const READY: u8 = 2;
+
+        pub const fn new() -> Self {
+            OnceLock {
+                state: core::sync::atomic::AtomicU8::new(Self::EMPTY),
+                value: core::cell::UnsafeCell::new(core::mem::MaybeUninit::uninit()),
+            }
+        }
+
+        pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+            use core::sync::atomic::Ordering;
+            if self
+                .state
+                .compare_exchange(
+                    Self::EMPTY,
+                    Self::BUSY,
+                    Ordering::Acquire,
+                    Ordering::Acquire,
+                )
+                .is_ok()
+            {
+                // Only one caller gets here, and nobody reads the value until it's `READY`.
+                unsafe { (*self.value.get()).write(f()) };
+                self.state.store(Self::READY, Ordering::Release);
+            }
+            while self.state.load(Ordering::Acquire) != Self::READY {
+                core::hint::spin_loop();
+            }
+            // The value was initialized before `READY` was stored, and is never written again.
+            unsafe { (*self.value.get()).assume_init_ref() }
+        }
+    }
+}
+
+pub use crate::access_stats::*;
+pub use crate::app::*;
+pub use crate::binary::*;
+pub use crate::blackboard::*;
+pub use crate::change::*;
+pub use crate::chunk::*;
+pub use crate::command::*;
+#[cfg(feature = "std")]
+pub use crate::compress::*;
+pub use crate::computed::*;
+#[cfg(feature = "std")]
+pub use crate::crash::*;
+pub use crate::dirty::*;
+pub use crate::disabled::*;
+pub use crate::dispatch::*;
+pub use crate::entity_ref::*;
+pub use crate::executor::*;
+pub use crate::frame::*;
+#[cfg(feature = "frame-arena")]
+pub use crate::frame_arena::*;
+#[cfg(feature = "input")]
+pub use crate::input::*;
+pub use crate::integrity::*;
+pub use crate::intern::*;
 pub use crate::join::*;
+pub use crate::journal::*;
+pub use crate::lifetime::*;
+#[cfg(feature = "lints")]
+pub use crate::lint::*;
+pub use crate::local::*;
+pub use crate::mask::*;
+pub use crate::names::*;
+pub use crate::observer::*;
+pub use crate::origins::*;
+pub use crate::pathfinding::*;
+pub use crate::persist::*;
+pub use crate::poison::*;
+pub use crate::replay::*;
+pub use crate::schedule::*;
+pub use crate::sorted::*;
 pub use crate::storage::*;
+pub use crate::task::*;
+#[cfg(feature = "term")]
+pub use crate::term::*;
+pub use crate::timer::*;
 pub use crate::traits::*;
+pub use crate::tween::*;
+pub use crate::undo::*;
+pub use crate::viewport::*;
+pub use crate::visibility::*;
 
 /// `Entity` is an opaque identifier that can be used to look up associated components in a
 /// `World`.
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+///
+/// The generation is never zero, so `Option<Entity>` is the same size as `Entity` (64 bits).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Entity {
-    /// The id of this entity within the world.
-    pub id: usize,
-    /// The generation of this entity.
-    pub generation: usize,
+    id: u32,
+    generation: core::num::NonZeroU32,
+}
+
+impl Entity {
+    /// The handle for the first entity to occupy slot `id`.
+    pub fn new(id: u32) -> Entity {
+        Entity {
+            id,
+            generation: core::num::NonZeroU32::MIN,
+        }
+    }
+
+    /// The handle for the next entity to occupy this entity's slot, once it has been deleted.
+    pub fn next_generation(self) -> Entity {
+        Entity {
+            id: self.id,
+            generation: core::num::NonZeroU32::new(self.generation.get().wrapping_add(1))
+                .unwrap_or(core::num::NonZeroU32::MIN),
+        }
+    }
+
+    /// The id of this entity within the world. Ids of deleted entities are reused.
+    #[inline]
+    pub fn id(self) -> usize {
+        self.id as usize
+    }
+
+    /// The generation of this entity, which distinguishes it from other entities that have had
+    /// the same id. Starts at 1.
+    #[inline]
+    pub fn generation(self) -> u32 {
+        self.generation.get()
+    }
+
+    /// Pack this entity into 64 bits, with the generation in the high half and the id in the low
+    /// half.
+    ///
+    /// ```
+    /// # use ecstatic::Entity;
+    /// let e = Entity::new(7).next_generation();
+    /// assert_eq!(e.to_bits(), (2 << 32) | 7);
+    /// assert_eq!(Entity::from_bits(e.to_bits()), Some(e));
+    /// assert_eq!(Entity::from_bits(7), None);
+    /// ```
+    #[inline]
+    pub fn to_bits(self) -> u64 {
+        (u64::from(self.generation.get()) << 32) | u64::from(self.id)
+    }
+
+    /// Unpack an entity produced by `to_bits`. Returns `None` if the generation is zero.
+    #[inline]
+    pub fn from_bits(bits: u64) -> Option<Entity> {
+        Some(Entity {
+            id: bits as u32,
+            generation: core::num::NonZeroU32::new((bits >> 32) as u32)?,
+        })
+    }
 }
 
 /// Defines the set of data structures necessary for using `ecstatic`.
@@ -202,9 +635,36 @@ pub struct Entity {
 /// - `World`
 ///   - Wraps `Resources` and contains entity metadata
 /// - `EntityBuilder`
-///   - Helper for `World::new_entity()`
+///   - Helper for `World::new_entity()`. Each component can only be given once; see `BuildWith`.
 /// - `ComponentSet`
-///   - Used by `EntityBuilder`. Basically just all of the components wrapped in an `Option`.
+///   - Used by `EntityBuilder` and `WorldInterface::spawn_batch`. Basically just all of the
+///     components wrapped in an `Option`, in fields named after the world's component fields.
+///
+/// An optional `constraints` section lists components that only make sense together: with
+/// `velocity requires position;`, building an entity with a `velocity` but no `position` fails.
+/// So does giving an entity a `velocity` with `GetComponent::insert` before it has a `position`,
+/// taking the `position` away with `GetComponent::remove` while it has a `velocity`, and loading
+/// a save with such an entity. Setting components directly through a storage isn't checked.
+/// Components are referred to by field name here.
+///
+/// Any component or resource field can be given an initializer, like `seed: u64 = 42`; for a
+/// component, the initializer builds the storage. `World::default()` uses it instead of the type's `Default`
+/// implementation, so resources that don't have one, or that need setting up, can still be used.
+///
+/// `World::component::<T>()` and `component_mut::<T>()` borrow the storage for component `T`,
+/// without having to name the `GetComponent` trait, and `World::fetch` borrows several storages
+/// and resources at once, checking first that they don't conflict with each other or with
+/// borrows that are still alive.
+///
+/// With the `resource-accessors` feature, each resource also gets a pair of accessors named
+/// after its field: for `turn: u64`, `World::resource_turn()` and `World::resource_turn_mut()`
+/// borrow the resource, like `GetResource::get` and `get_mut`. This is handy when several
+/// resources are newtypes of one another, or when the type is long.
+///
+/// `World` also gets `save` and `load` methods, for worlds whose components and resources can
+/// be persisted. See the [`persist`](persist/index.html) module.
+/// `World::snapshot` dumps a world as text for golden-file tests; see the
+/// [`testing`](testing/index.html) module.
 ///
 /// # Example
 /// ```
@@ -217,7 +677,8 @@ pub struct Entity {
 ///
 /// define_world!(
 ///     // You can apply trait derivations to the output structs. Whatever is specified here will
-///     // apply to both the `World` struct and the `Resources` struct.
+///     // apply to both the `World` struct and the `Resources` struct, except that `Resources`
+///     // always implements `Default` itself, using the fields' initializers.
 ///     #[derive(Default, Debug)]
 ///     // The visibility specifier is optional. It applies to all of the types defined by the
 ///     // macro.
@@ -236,42 +697,127 @@ pub struct Entity {
 ///     }
 /// );
 /// ```
+///
+/// Initializers:
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// # #[derive(Default)]
+/// # pub struct Position(i32, i32);
+/// // No `Default`: a dungeon has to be given a size.
+/// pub struct Dungeon {
+///     width: u32,
+///     height: u32,
+/// }
+///
+/// define_world!(
+///     #[derive(Default)]
+///     pub world {
+///         components {
+///             positions: BasicVecStorage<Position>,
+///         }
+///         resources {
+///             dungeon: Dungeon = Dungeon { width: 80, height: 25 },
+///             turn: u64,
+///         }
+///     }
+/// );
+///
+/// let w = World::default();
+/// assert_eq!(<World as GetResource<Dungeon>>::get(&w).width, 80);
+/// *<World as GetResource<u64>>::get_mut(&w) += 1;
+/// assert_eq!(*<World as GetResource<u64>>::get(&w), 1);
+/// ```
+///
+/// Constraints:
+/// ```should_panic
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// #[derive(Debug, Default)]
+/// pub struct Position(i32, i32);
+/// #[derive(Debug, Default)]
+/// pub struct Velocity(i32, i32);
+/// #[derive(Debug, Default)]
+/// pub struct Sprite(char);
+///
+/// define_world!(
+///     #[derive(Default)]
+///     pub world {
+///         components {
+///             positions: BasicVecStorage<Position>,
+///             velocities: BasicVecStorage<Velocity>,
+///             sprites: BasicVecStorage<Sprite>,
+///         }
+///         resources {}
+///         constraints {
+///             velocities requires positions;
+///             sprites requires positions;
+///         }
+///     }
+/// );
+///
+/// let mut w = World::default();
+/// let err = w.new_entity().with(Sprite('@')).try_build().unwrap_err();
+/// assert_eq!(err.required_by, std::any::type_name::<Sprite>());
+///
+/// // Panics: "an entity with a `...::Velocity` must also have a `...::Position`".
+/// w.new_entity().with(Velocity(1, 0)).build();
+/// ```
 #[macro_export(local_inner_macros)]
 macro_rules! define_world {
-    ($(#[$meta:meta])*
+    ($(#[$($meta:tt)*])*
      $v:vis world {
         components {
-            $($component:ident : $($component_storage:ident) :: + < $component_type:ty >),* $(,)*
+            $($component:ident : $($component_storage:ident) :: + < $component_type:ty $(, $storage_param:tt)* >
+              $(= $component_init:expr)?),* $(,)*
         }
         resources {
-            $($resource:ident : $resource_type:ty),* $(,)*
+            $($resource:ident : $resource_type:ty $(= $resource_init:expr)?),* $(,)*
         }
+        $(constraints {
+            $($dependent:ident requires $($required:ident),+);* $(;)*
+        })?
     }) => {
-        __define_world_internal!{@impl_storage_spec {$($component_type; $($component_storage)::*)*}}
+        __define_world_internal!{@impl_storage_spec
+            {$($component_type; $($component_storage)::*; [$(, $storage_param)*])*}}
+        __define_world_internal!{@define_component_index $($component)*}
+        __define_world_internal!{@impl_access_index
+            {$($component $component_type)*} {$($resource $resource_type)*}}
         __define_world_internal!{@impl_get_component $({$component $component_type})*}
         __define_world_internal!{@impl_get_resource $({$resource $resource_type})*}
+        __define_world_internal!{@impl_resource_accessors {$($resource $resource_type)*} $v}
         __define_world_internal!{@define_world_struct
-            $(#[$meta])* $v ($($component: $component_type)*)}
-        __define_world_internal!{@define_builder_struct $v $($component:$component_type)*}
-        $(
-            __define_world_internal!{@impl_build_with $component $component_type}
-        )*
-        __define_world_internal!{@define_resource_struct $(#[$meta])* $v
+            $(#[$($meta)*])* $v ($($component: $component_type)*)}
+        __define_world_internal!{@impl_persist
+            {$($component $component_type)*} {$($resource $resource_type)*} $v}
+        __define_world_internal!{@impl_snapshot
+            {$($component $component_type)*} {$($resource $resource_type)*} $v}
+        __define_world_internal!{@define_builder_struct
+            [$($($dependent requires $($required),+);*)?] $v $($component:$component_type)*}
+        __define_world_internal!{@impl_generate_world $($component $component_type)*}
+        __define_world_internal!{@impl_build_with [] $($component:$component_type,)*}
+        __define_world_internal!{@define_resource_struct [] [$(#[$($meta)*])*] $v
             (
-                {$($component:($($component_storage)::*; $component_type))*}
+                {$($component:($($component_storage)::*; $component_type; [$(, $storage_param)*]))*}
                 {$($resource : $resource_type)*}
             )
         }
+        __define_world_internal!{@impl_resources_default [] []
+            $({$component
+               $crate::cell::WorldCell<$($component_storage)::*<$component_type $(, $storage_param)*>>
+               [$($component_init)?]})*
+            $({$resource $crate::ResourceCell<$resource_type> [$($resource_init)?]})*
+        }
     };
 }
 
 #[doc(hidden)]
 #[macro_export]
 macro_rules! __define_world_internal {
-    (@impl_storage_spec {$($component_type:ty; $($component_storage:ident)::+ )*}) => {
+    (@impl_storage_spec {$($component_type:ty; $($component_storage:ident)::+; [$($storage_param:tt)*])*}) => {
         $(
             impl<'a> $crate::StorageSpec<'a> for $component_type {
-                type Storage = $($component_storage)::* <$component_type>;
+                type Storage = $($component_storage)::* <$component_type $($storage_param)*>;
                 type Component = $component_type;
             }
         )*
@@ -280,45 +826,278 @@ macro_rules! __define_world_internal {
     (@impl_get_resource $({$resource:ident $resource_type:ty})*) => {
         $(
             impl GetResource<$resource_type> for World {
-                fn get(&self) -> std::cell::Ref<$resource_type> {
+                fn get(&self) -> $crate::cell::Ref<'_, $resource_type> {
                     self.resources.$resource.borrow()
                 }
-                fn get_mut(&self) -> std::cell::RefMut<$resource_type> {
-                    self.resources.$resource.borrow_mut()
+                fn get_mut(&self) -> $crate::cell::RefMut<'_, $resource_type> {
+                    self.resources.$resource.borrow_mut(&self.change_tick)
                 }
                 fn set(&self, t: $resource_type) {
-                    self.resources.$resource.replace(t);
+                    self.resources.$resource.replace(t, &self.change_tick);
+                    self.journal.borrow_mut(&self.change_tick).record(
+                        $crate::JournalEntry::ResourceSet {
+                            resource: ::core::any::type_name::<$resource_type>(),
+                        });
+                }
+                fn change_tick(&self) -> u64 {
+                    self.resources.$resource.change_tick()
+                }
+            }
+        )*
+    };
+
+    (@impl_resource_accessors {$($resource:ident $resource_type:ty)*} $v:vis) => {
+        $crate::__if_resource_accessors! {
+            $crate::__private::paste! {
+                #[allow(dead_code)]
+                impl World {
+                    $(
+                        #[doc = "Get the `" $resource "` resource."]
+                        $v fn [<resource_ $resource>](&self)
+                            -> $crate::cell::Ref<'_, $resource_type> {
+                            self.resources.$resource.borrow()
+                        }
+
+                        #[doc = "Get the `" $resource "` resource mutably."]
+                        $v fn [<resource_ $resource _mut>](&self)
+                            -> $crate::cell::RefMut<'_, $resource_type> {
+                            self.resources.$resource.borrow_mut(&self.change_tick)
+                        }
+                    )*
                 }
             }
+        }
+    };
+
+    (@define_component_index $($component:ident)*) => {
+        // Numbers the components, for `ComponentMasks`.
+        #[doc(hidden)]
+        #[allow(non_camel_case_types, dead_code)]
+        enum __ComponentIndex {
+            $($component,)*
+            __Count,
+        }
+    };
+
+    (@impl_access_index {$($component:ident $component_type:ty)*}
+                        {$($resource:ident $resource_type:ty)*}) => {
+        // Numbers the resources, for `define_schedule!`.
+        #[doc(hidden)]
+        #[allow(non_camel_case_types, dead_code)]
+        enum __ResourceIndex {
+            $($resource,)*
+            __journal,
+        }
+
+        $(
+            impl $crate::ComponentIndex<$component_type> for World {
+                const INDEX: usize = __ComponentIndex::$component as usize;
+            }
         )*
+        $(
+            impl $crate::ResourceIndex<$resource_type> for World {
+                const INDEX: usize = __ResourceIndex::$resource as usize;
+            }
+        )*
+        impl $crate::ResourceIndex<$crate::Journal> for World {
+            const INDEX: usize = __ResourceIndex::__journal as usize;
+        }
     };
 
     (@impl_get_component $({$component:ident $component_type:ty})*) => {
         $(
             impl<'a> GetComponent<'a, $component_type> for World {
-                fn get(&self) -> std::cell::Ref<<$component_type as StorageSpec<'a>>::Storage> {
+                fn get(&self) -> $crate::cell::Ref<'_, <$component_type as StorageSpec<'a>>::Storage> {
                     self.resources.$component.borrow()
                 }
-                fn get_mut(&self) -> std::cell::RefMut<<$component_type as StorageSpec<'a>>::Storage> {
-                    self.resources.$component.borrow_mut()
+                fn get_mut(&self) -> $crate::cell::RefMut<'_, <$component_type as StorageSpec<'a>>::Storage> {
+                    self.masks.mark_unsynced(__ComponentIndex::$component as usize);
+                    let storage = self.resources.$component.borrow_mut();
+                    self.component_ticks.mark(__ComponentIndex::$component as usize, &self.change_tick);
+                    storage
+                }
+                fn get_mut_unstamped(&self) -> (
+                    $crate::cell::RefMut<'_, <$component_type as StorageSpec<'a>>::Storage>,
+                    $crate::ChangeStamp<'_>,
+                ) {
+                    let index = __ComponentIndex::$component as usize;
+                    self.masks.mark_unsynced(index);
+                    let storage = self.resources.$component.borrow_mut();
+                    (storage, $crate::ChangeStamp::new(&self.component_ticks, index, &self.change_tick))
+                }
+                #[track_caller]
+                fn insert(&mut self, entity: Entity, value: $component_type) -> Option<$component_type> {
+                    use $crate::ComponentStorage;
+                    if !<Self as $crate::WorldInterface<'a>>::is_alive(self, entity) {
+                        return None;
+                    }
+                    self.flush_reserved();
+                    self.sync_masks();
+                    let index = __ComponentIndex::$component as usize;
+                    if let Err(e) = World::check_constraints_by_index(
+                        |i| i == index || self.has_component(entity, i), Some(index)) {
+                        ::core::panic!("{}", e);
+                    }
+                    let storage = self.resources.$component.get_mut();
+                    let replaced = storage.get(entity).is_some();
+                    let old = storage.take(entity);
+                    storage.set(entity, Some(value));
+                    self.masks.insert(entity.id(), __ComponentIndex::$component as usize);
+                    self.component_ticks.mark(__ComponentIndex::$component as usize, &self.change_tick);
+                    if !replaced {
+                        let journal = self.journal.get_mut(&self.change_tick);
+                        journal.record($crate::JournalEntry::ComponentAdded {
+                            entity,
+                            component: ::core::any::type_name::<$component_type>(),
+                        });
+                    }
+                    old
+                }
+                #[track_caller]
+                fn remove(&mut self, entity: Entity) -> Option<$component_type> {
+                    use $crate::ComponentStorage;
+                    self.sync_masks();
+                    let index = __ComponentIndex::$component as usize;
+                    let storage = self.resources.$component.get_mut();
+                    let old = storage.take(entity)?;
+                    if storage.get(entity).is_none() {
+                        if let Err(e) = World::check_constraints_by_index(
+                            |i| i != index && self.has_component(entity, i), Some(index)) {
+                            self.resources.$component.get_mut().set(entity, Some(old));
+                            ::core::panic!("{}", e);
+                        }
+                        self.masks.remove(entity.id(), index);
+                    }
+                    self.component_ticks.mark(__ComponentIndex::$component as usize, &self.change_tick);
+                    let journal = self.journal.get_mut(&self.change_tick);
+                    journal.record($crate::JournalEntry::ComponentRemoved {
+                        entity,
+                        component: ::core::any::type_name::<$component_type>(),
+                    });
+                    Some(old)
+                }
+                fn change_tick(&self) -> u64 {
+                    self.component_ticks.get(__ComponentIndex::$component as usize)
                 }
             }
         )*
     };
 
-    (@define_resource_struct $(#[$meta:meta])* $v:vis (
-                             {$($component:ident : ($($component_storage:ident) :: +; $component_type:ty))*}
+    // The world's attributes are applied to `Resources` too, except that `Default` is taken out
+    // of any derives, since `@impl_resources_default` implements it.
+    (@define_resource_struct [$($done:tt)*] [#[derive($($derive:tt)*)] $($attr:tt)*]
+                             $($rest:tt)*) => {
+        $crate::__define_world_internal!{@strip_default [$($done)*] [] ($($derive)*)
+            [$($attr)*] $($rest)*}
+    };
+
+    (@define_resource_struct [$($done:tt)*] [#[$($meta:tt)*] $($attr:tt)*] $($rest:tt)*) => {
+        $crate::__define_world_internal!{@define_resource_struct [$($done)* #[$($meta)*]]
+            [$($attr)*] $($rest)*}
+    };
+
+    (@define_resource_struct [$($done:tt)*] [] $v:vis (
+                             {$($component:ident : ($($component_storage:ident) :: +; $component_type:ty; [$($storage_param:tt)*]))*}
                              {$($resource:ident : $resource_type:ty)*})) => {
-        $(#[$meta])*
+        $($done)*
         $v struct Resources {
             $(
-                $component: std::cell::RefCell<$($component_storage)::*<$component_type>>,
+                $component: $crate::cell::WorldCell<$($component_storage)::*<$component_type $($storage_param)*>>,
             )*
 
             $(
-                $resource: std::cell::RefCell<$resource_type>,
+                $resource: $crate::ResourceCell<$resource_type>,
             )*
         }
+
+        impl Resources {
+            // Whether the storage or resource `key` could be borrowed right now, mutably if
+            // `write`. Anything else can always be.
+            #[allow(dead_code)]
+            fn can_borrow(&self, key: $crate::AccessKey, write: bool) -> bool {
+                $(
+                    if key == $crate::AccessKey::Component(
+                        ::core::any::TypeId::of::<$component_type>())
+                    {
+                        return if write {
+                            self.$component.try_borrow_mut().is_ok()
+                        } else {
+                            self.$component.try_borrow().is_ok()
+                        };
+                    }
+                )*
+                $(
+                    if key == $crate::AccessKey::Resource(
+                        ::core::any::TypeId::of::<$resource_type>())
+                    {
+                        return self.$resource.can_borrow(write);
+                    }
+                )*
+                true
+            }
+        }
+    };
+
+    // Copies the derives in the parentheses into the brackets, leaving out `Default`.
+    (@strip_default [$($done:tt)*] [$($kept:tt)*]
+                    ($(::)? core :: default :: Default $(, $($derive:tt)*)?) $($rest:tt)*) => {
+        $crate::__define_world_internal!{@strip_default [$($done)*] [$($kept)*]
+            ($($($derive)*)?) $($rest)*}
+    };
+
+    (@strip_default [$($done:tt)*] [$($kept:tt)*]
+                    ($(::)? std :: default :: Default $(, $($derive:tt)*)?) $($rest:tt)*) => {
+        $crate::__define_world_internal!{@strip_default [$($done)*] [$($kept)*]
+            ($($($derive)*)?) $($rest)*}
+    };
+
+    (@strip_default [$($done:tt)*] [$($kept:tt)*] (:: $segment:tt $($derive:tt)*)
+                    $($rest:tt)*) => {
+        $crate::__define_world_internal!{@strip_default [$($done)*] [$($kept)* :: $segment]
+            ($($derive)*) $($rest)*}
+    };
+
+    (@strip_default [$($done:tt)*] [$($kept:tt)*] (Default $(, $($derive:tt)*)?)
+                    $($rest:tt)*) => {
+        $crate::__define_world_internal!{@strip_default [$($done)*] [$($kept)*]
+            ($($($derive)*)?) $($rest)*}
+    };
+
+    (@strip_default [$($done:tt)*] [$($kept:tt)*] ($token:tt $($derive:tt)*) $($rest:tt)*) => {
+        $crate::__define_world_internal!{@strip_default [$($done)*] [$($kept)* $token]
+            ($($derive)*) $($rest)*}
+    };
+
+    (@strip_default [$($done:tt)*] [$($kept:tt)*] () $($rest:tt)*) => {
+        $crate::__define_world_internal!{@define_resource_struct
+            [$($done)* #[derive($($kept)*)]] $($rest)*}
+    };
+
+    // Fields with an initializer are built from it, and the rest with `Default`. The `Default`
+    // bounds are higher-ranked so that a field type without a default is only an error if
+    // something uses `Resources::default`.
+    (@impl_resources_default [$($bound:tt)*] [$($init:tt)*]
+                             {$field:ident $cell:ty []} $($rest:tt)*) => {
+        $crate::__define_world_internal!{@impl_resources_default
+            [$($bound)* for<'x> $cell: ::core::default::Default,]
+            [$($init)* $field: ::core::default::Default::default(),]
+            $($rest)*}
+    };
+
+    (@impl_resources_default [$($bound:tt)*] [$($init:tt)*]
+                             {$field:ident $cell:ty [$value:expr]} $($rest:tt)*) => {
+        $crate::__define_world_internal!{@impl_resources_default
+            [$($bound)*]
+            [$($init)* $field: <$cell>::new($value),]
+            $($rest)*}
+    };
+
+    (@impl_resources_default [$($bound:tt)*] [$($init:tt)*]) => {
+        impl ::core::default::Default for Resources where $($bound)* {
+            fn default() -> Self {
+                Resources { $($init)* }
+            }
+        }
     };
 
     (@define_world_struct $(#[$meta:meta])* $v:vis
@@ -328,8 +1107,306 @@ macro_rules! __define_world_internal {
         $(#[$meta])*
         $v struct World {
             resources: Resources,
-            num_entities: usize,
-            free_list: Vec<Entity>,
+            // The handle of the live entity in each slot, if any.
+            entities: $crate::__private::Vec<Option<Entity>>,
+            free_list: $crate::__private::Vec<Entity>,
+            // The number of entities handed out by `reserve_entity`, in the slots after the last
+            // one in `entities`, that haven't been added to it yet.
+            reserved: ::core::cell::Cell<u32>,
+            masks: $crate::ComponentMasks,
+            journal: $crate::ResourceCell<$crate::Journal>,
+            change_tick: ::core::cell::Cell<u64>,
+            component_ticks: $crate::ComponentTicks,
+            locals: $crate::Locals,
+            names: $crate::DebugNames,
+            origins: $crate::DebugOrigins,
+            access_stats: $crate::AccessStats,
+            poison: $crate::Poison,
+            disabled: $crate::Disabled,
+            deferred: $crate::DeferredCommands<World>,
+        }
+
+        impl GetResource<$crate::Journal> for World {
+            fn get(&self) -> $crate::cell::Ref<'_, $crate::Journal> {
+                self.journal.borrow()
+            }
+            fn get_mut(&self) -> $crate::cell::RefMut<'_, $crate::Journal> {
+                self.journal.borrow_mut(&self.change_tick)
+            }
+            fn set(&self, t: $crate::Journal) {
+                self.journal.replace(t, &self.change_tick);
+            }
+            fn change_tick(&self) -> u64 {
+                self.journal.change_tick()
+            }
+        }
+
+        impl World {
+            // Allocate a slot for a new entity, reusing a deleted one if possible.
+            fn alloc_entity(&mut self) -> Entity {
+                self.flush_reserved();
+                let entity = if let Some(e) = self.free_list.pop() {
+                    e.next_generation()
+                } else {
+                    let id = ::core::convert::TryFrom::try_from(self.entities.len())
+                        .expect("too many entities");
+                    self.entities.push(None);
+                    Entity::new(id)
+                };
+                self.entities[entity.id()] = Some(entity);
+                entity
+            }
+
+            // Add the entities handed out by `reserve_entity` to the entity table. Everything
+            // that changes the table or looks entities up in it does this first.
+            #[track_caller]
+            fn flush_reserved(&mut self) {
+                let reserved = self.reserved.replace(0);
+                if reserved == 0 {
+                    return;
+                }
+                let journal = self.journal.get_mut(&self.change_tick);
+                for _ in 0..reserved {
+                    let entity = Entity::new(
+                        ::core::convert::TryFrom::try_from(self.entities.len())
+                            .expect("too many entities"),
+                    );
+                    self.entities.push(Some(entity));
+                    self.origins.record(entity);
+                    journal.record($crate::JournalEntry::Spawn(entity));
+                }
+            }
+
+            // Store an entity's components, without checking the world's `constraints`.
+            #[track_caller]
+            fn store_entity(&mut self, components: ComponentSet) -> Entity {
+                use $crate::ComponentStorage;
+                let entity = self.alloc_entity();
+                self.origins.record(entity);
+                let journal = self.journal.get_mut(&self.change_tick);
+                journal.record($crate::JournalEntry::Spawn(entity));
+                $(
+                    if components.$component.is_some() {
+                        self.masks.insert(entity.id(), __ComponentIndex::$component as usize);
+                        self.component_ticks.mark(__ComponentIndex::$component as usize, &self.change_tick);
+                        journal.record($crate::JournalEntry::ComponentAdded {
+                            entity,
+                            component: ::core::any::type_name::<$type>(),
+                        });
+                    }
+                    // Should never panic, since having a mutable reference to `self` implies that
+                    // there are no extant immutable references.
+                    self.resources.$component.borrow_mut().set(entity, components.$component);
+                )*
+                entity
+            }
+
+            // The disabled entities that are still alive, for saves.
+            #[allow(dead_code)]
+            fn saved_disabled(&self) -> $crate::__private::Vec<Entity> {
+                self.disabled
+                    .iter()
+                    .filter(|&e| <Self as $crate::WorldInterface<'_>>::is_alive(self, e))
+                    .collect()
+            }
+
+            // Rebuild the masks of the components whose storages have been borrowed mutably since
+            // they were last synced, so that they can be relied on, recording the components that
+            // were added or removed in the meantime in the journal.
+            fn sync_masks(&mut self) {
+                use $crate::ReadStorage;
+                $(
+                    let index = __ComponentIndex::$component as usize;
+                    if !self.masks.is_synced(index) {
+                        let storage = self.resources.$component.get_mut();
+                        let journal = self.journal.get_mut(&self.change_tick);
+                        for &entity in self.entities.iter().flatten() {
+                            let present = storage.get(entity).is_some();
+                            if present == self.masks.contains(entity.id(), index) {
+                                continue;
+                            }
+                            let component = ::core::any::type_name::<$type>();
+                            journal.record(if present {
+                                $crate::JournalEntry::ComponentAdded { entity, component }
+                            } else {
+                                $crate::JournalEntry::ComponentRemoved { entity, component }
+                            });
+                        }
+                        let ids = self
+                            .entities
+                            .iter()
+                            .flatten()
+                            .filter(|&&e| storage.get(e).is_some())
+                            .map(|e| e.id());
+                        self.masks.sync(index, ids);
+                    }
+                )*
+            }
+
+            // Whether `entity` has a component in the storage at `index`.
+            fn has_component(&self, entity: Entity, index: usize) -> bool {
+                use $crate::ReadStorage;
+                $(
+                    if index == __ComponentIndex::$component as usize {
+                        return self.resources.$component.borrow().get(entity).is_some();
+                    }
+                )*
+                false
+            }
+
+            // Whether `entity` was handed out by `reserve_entity` and hasn't been flushed yet.
+            fn is_reserved(&self, entity: Entity) -> bool {
+                let first = self.entities.len();
+                entity.generation() == 1
+                    && entity.id() >= first
+                    && entity.id() < first + self.reserved.get() as usize
+            }
+
+            // The live entities in id order, including reserved ones that haven't been flushed yet,
+            // for the methods that only borrow the world.
+            #[allow(dead_code)]
+            fn alive_entities(&self) -> $crate::__private::Vec<Entity> {
+                let first = self.entities.len();
+                let reserved = (first..first + self.reserved.get() as usize).map(|id| {
+                    Entity::new(::core::convert::TryFrom::try_from(id).expect("too many entities"))
+                });
+                self.entities.iter().flatten().copied().chain(reserved).collect()
+            }
+
+            /// Check the world's internal invariants: that the entity table, free list, storages
+            /// and component masks all agree. See the `integrity` module.
+            #[allow(dead_code)]
+            $v fn validate(&self) -> $crate::IntegrityReport {
+                let mut check = $crate::IntegrityCheck::new(
+                    &self.entities,
+                    &self.free_list,
+                    &self.masks,
+                );
+                $(
+                    check.component(
+                        __ComponentIndex::$component as usize,
+                        &*self.resources.$component.borrow(),
+                    );
+                )*
+                check.finish()
+            }
+
+            $crate::__if_lints! {
+                /// Look for write access systems don't use and components no entity has. See the
+                /// `lint` module.
+                #[allow(dead_code)]
+                $v fn lint(&self) -> $crate::__private::Vec<$crate::Lint> {
+                    use $crate::ComponentStorage;
+                    let mut lints = $crate::lint::unused_writes(&self.access_stats.matrix());
+                    $(
+                        if self.component_ticks.get(__ComponentIndex::$component as usize) == 0 {
+                            let storage = self.resources.$component.borrow();
+                            if (0..storage.size()).all(|id| storage.entity(id).is_none()) {
+                                lints.push($crate::Lint::UnusedComponent {
+                                    component: ::core::any::type_name::<$type>(),
+                                });
+                            }
+                        }
+                    )*
+                    lints
+                }
+            }
+
+            /// Everything recorded about `entity` for debugging: its name, and where and when it
+            /// was created. These are only recorded with the `debug-names` and `debug-origins`
+            /// features. Returns `None` if the entity isn't alive.
+            #[allow(dead_code)]
+            $v fn debug_entity(&self, entity: Entity) -> Option<$crate::EntityDebug<'_>> {
+                if !<Self as $crate::WorldInterface<'_>>::is_alive(self, entity) {
+                    return None;
+                }
+                Some($crate::EntityDebug {
+                    entity: self.names.describe(entity),
+                    origin: self.origins.get(entity),
+                })
+            }
+
+            /// Create a new entity with clones of `entity`'s components, returning it, or `None`
+            /// if `entity` isn't alive. Only the storages `entity` has components in are looked
+            /// at. Storages that can hold several components per entity, like `MultiStorage`, only
+            /// clone the first.
+            #[allow(dead_code)]
+            $v fn clone_entity(&mut self, entity: Entity) -> Option<Entity>
+            where
+                $(for<'x> $type: Clone,)*
+            {
+                use $crate::ReadStorage;
+                if !<Self as $crate::WorldInterface<'_>>::is_alive(self, entity) {
+                    return None;
+                }
+                self.flush_reserved();
+                self.sync_masks();
+                let components = ComponentSet {
+                    $(
+                        $component: if self.masks.contains(
+                            entity.id(), __ComponentIndex::$component as usize) {
+                            self.resources.$component.get_mut().get(entity).cloned()
+                        } else {
+                            None
+                        },
+                    )*
+                };
+                Some(<Self as $crate::WorldInterface<'_>>::build_entity(self, components))
+            }
+
+            /// Get the storage for component `T`. Shorthand for
+            /// `<World as GetComponent<'_, T>>::get(&world)`.
+            #[allow(dead_code)]
+            $v fn component<'a, T>(&'a self) -> $crate::cell::Ref<'a, T::Storage>
+            where
+                T: $crate::StorageSpec<'a> + 'a,
+                Self: $crate::GetComponent<'a, T>,
+            {
+                <Self as $crate::GetComponent<'a, T>>::get(self)
+            }
+
+            /// Get the storage for component `T` mutably. Shorthand for
+            /// `<World as GetComponent<'_, T>>::get_mut(&world)`.
+            #[allow(dead_code)]
+            $v fn component_mut<'a, T>(&'a self) -> $crate::cell::RefMut<'a, T::Storage>
+            where
+                T: $crate::StorageSpec<'a> + 'a,
+                Self: $crate::GetComponent<'a, T>,
+            {
+                <Self as $crate::GetComponent<'a, T>>::get_mut(self)
+            }
+
+            /// Borrow several storages and resources at once, outside of a system, e.g.
+            /// `world.fetch::<(WriteComponent<A>, ReadComponent<B>, WriteResource<C>)>()`.
+            ///
+            /// The dependencies are checked before anything is borrowed, against each other and
+            /// against borrows made outside the fetch that are still alive. If one of them borrows
+            /// something mutably that another also uses, or something can't be borrowed the way a
+            /// dependency needs, no borrows are made and the conflict is returned instead of
+            /// panicking partway through.
+            #[allow(dead_code)]
+            $v fn fetch<'a, T>(&'a self) -> ::core::result::Result<T, $crate::FetchConflict>
+            where
+                T: $crate::Nest,
+                T::Nested: $crate::DependencyAccess,
+                Self: $crate::ComponentProviderRec<'a, T::Nested>,
+            {
+                let journal = $crate::AccessKey::Resource(
+                    ::core::any::TypeId::of::<$crate::Journal>());
+                let can_borrow = |key, write| {
+                    if key == journal {
+                        self.journal.can_borrow(write)
+                    } else {
+                        self.resources.can_borrow(key, write)
+                    }
+                };
+                let conflict =
+                    <T::Nested as $crate::DependencyAccess>::fetch_conflict(can_borrow);
+                if let ::core::option::Option::Some(conflict) = conflict {
+                    return ::core::result::Result::Err(conflict);
+                }
+                ::core::result::Result::Ok(<Self as $crate::ComponentProvider<'a, T>>::fetch(self))
+            }
         }
 
         impl $crate::ResourceProvider for World {
@@ -352,72 +1429,785 @@ macro_rules! __define_world_internal {
                     )*
                     },
                     world: self,
+                    name: ::core::default::Default::default(),
+                    state: ::core::marker::PhantomData,
                 }
             }
 
+            #[track_caller]
             fn build_entity(&mut self, components: Self::ComponentSet) -> Entity {
+                match self.try_build_entity(components) {
+                    Ok(entity) => entity,
+                    Err(e) => ::core::panic!("{}", e),
+                }
+            }
+
+            #[track_caller]
+            fn spawn_batch<F>(
+                &mut self,
+                template: &Self::ComponentSet,
+                n: usize,
+                mut f: F,
+            ) -> $crate::__private::Vec<Entity>
+            where
+                <Self as $crate::WorldInterface<'a>>::ComponentSet: Clone,
+                F: FnMut(usize, &mut Self::ComponentSet),
+            {
                 use $crate::ComponentStorage;
-                let mut entity;
-                if let Some(e) = self.free_list.pop() {
-                    entity = e;
-                    entity.generation += 1;
-                } else {
-                    entity = Entity{
-                        id:self.num_entities,
-                        generation: 0,
-                    };
-                    self.num_entities += 1;
+                let mut sets = (0..n)
+                    .map(|i| {
+                        let mut components = template.clone();
+                        f(i, &mut components);
+                        if let Err(e) = components.check_constraints() {
+                            ::core::panic!("{}", e);
+                        }
+                        components
+                    })
+                    .collect::<$crate::__private::Vec<_>>();
+                let entities = (0..n).map(|_| self.alloc_entity()).collect::<$crate::__private::Vec<_>>();
+                for &entity in entities.iter() {
+                    self.origins.record(entity);
+                }
+                let journal = self.journal.get_mut(&self.change_tick);
+                for &entity in entities.iter() {
+                    journal.record($crate::JournalEntry::Spawn(entity));
                 }
                 $(
-                    // Should never panic, since having a mutable reference to `self` implies that
-                    // there are no extant immutable references.
-                    self.resources.$component.borrow_mut().set(entity, components.$component);
+                    let storage = self.resources.$component.get_mut();
+                    for (&entity, components) in entities.iter().zip(sets.iter_mut()) {
+                        if let Some(c) = components.$component.take() {
+                            self.masks.insert(entity.id(), __ComponentIndex::$component as usize);
+                            self.component_ticks.mark(__ComponentIndex::$component as usize, &self.change_tick);
+                            journal.record($crate::JournalEntry::ComponentAdded {
+                                entity,
+                                component: ::core::any::type_name::<$type>(),
+                            });
+                            storage.set(entity, Some(c));
+                        }
+                    }
                 )*
-                entity
+                entities
             }
 
             fn delete_entity(&mut self, entity: Entity) {
                 use $crate::ComponentStorage;
-                if entity.id < self.num_entities {
+                self.flush_reserved();
+                if <Self as $crate::WorldInterface<'a>>::is_alive(self, entity) {
+                    self.sync_masks();
+                    let journal = self.journal.get_mut(&self.change_tick);
                     $(
-                        self.resources.$component.borrow_mut().set(entity, None);
+                        let index = __ComponentIndex::$component as usize;
+                        if self.masks.contains(entity.id(), index) {
+                            let mut storage = self.resources.$component.borrow_mut();
+                            if storage.get(entity).is_some() {
+                                journal.record($crate::JournalEntry::ComponentRemoved {
+                                    entity,
+                                    component: ::core::any::type_name::<$type>(),
+                                });
+                                storage.set(entity, None);
+                                self.component_ticks.mark(index, &self.change_tick);
+                            }
+                        }
                     )*
+                    self.masks.clear(entity.id());
+                    journal.record($crate::JournalEntry::Despawn(entity));
+                    self.entities[entity.id()] = None;
                     self.free_list.push(entity);
+                    self.names.remove(entity);
+                    self.origins.remove(entity);
+                    self.disabled.remove(entity);
+                }
+            }
+
+            fn take_entity(&mut self, entity: Entity) -> Option<Self::ComponentSet> {
+                use $crate::ComponentStorage;
+                self.flush_reserved();
+                if !<Self as $crate::WorldInterface<'a>>::is_alive(self, entity) {
+                    return None;
+                }
+                self.sync_masks();
+                let journal = self.journal.get_mut(&self.change_tick);
+                let components = ComponentSet {
+                    $(
+                        $component: if !self.masks.contains(
+                            entity.id(), __ComponentIndex::$component as usize) {
+                            None
+                        } else {
+                            let storage = self.resources.$component.get_mut();
+                            let component = storage.take(entity);
+                            // `take` may leave more behind, e.g. in a `MultiStorage`.
+                            storage.set(entity, None);
+                            if component.is_some() {
+                                journal.record($crate::JournalEntry::ComponentRemoved {
+                                    entity,
+                                    component: ::core::any::type_name::<$type>(),
+                                });
+                                self.component_ticks.mark(
+                                    __ComponentIndex::$component as usize, &self.change_tick);
+                            }
+                            component
+                        },
+                    )*
+                };
+                self.masks.clear(entity.id());
+                journal.record($crate::JournalEntry::Despawn(entity));
+                self.entities[entity.id()] = None;
+                self.free_list.push(entity);
+                self.names.remove(entity);
+                self.origins.remove(entity);
+                self.disabled.remove(entity);
+                Some(components)
+            }
+
+            fn is_alive(&self, entity: Entity) -> bool {
+                self.entities.get(entity.id()) == Some(&Some(entity)) || self.is_reserved(entity)
+            }
+
+            fn reserve_entity(&self) -> Entity {
+                let reserved = self.reserved.get();
+                let id = <u32 as ::core::convert::TryFrom<usize>>::try_from(self.entities.len())
+                    .ok()
+                    .and_then(|first| first.checked_add(reserved))
+                    .filter(|&id| id != u32::MAX)
+                    .expect("too many entities");
+                self.reserved.set(reserved + 1);
+                Entity::new(id)
+            }
+
+            fn clear(&mut self) {
+                use $crate::ComponentStorage;
+                self.flush_reserved();
+                $(
+                    self.resources.$component.get_mut().clear();
+                    self.component_ticks.mark(__ComponentIndex::$component as usize, &self.change_tick);
+                )*
+                for slot in self.entities.iter_mut() {
+                    if let Some(entity) = slot.take() {
+                        self.free_list.push(entity);
+                    }
+                }
+                self.masks = $crate::ComponentMasks::new();
+                self.names.clear();
+                self.origins.clear();
+                self.disabled.clear();
+                self.journal.get_mut(&self.change_tick).record($crate::JournalEntry::Clear);
+            }
+
+            fn flip(&mut self) {
+                use $crate::ComponentStorage;
+                $(
+                    self.resources.$component.get_mut().flip();
+                )*
+            }
+
+            fn reserve(&mut self, n: usize) {
+                use $crate::ComponentStorage;
+                self.entities.reserve(n);
+                self.free_list.reserve(n);
+                self.masks.reserve(n, __ComponentIndex::__Count as usize);
+                $(
+                    self.resources.$component.get_mut().reserve(n);
+                )*
+            }
+
+            fn locals(&self) -> &$crate::Locals {
+                &self.locals
+            }
+
+            fn deferred(&self) -> &$crate::DeferredCommands<World> {
+                &self.deferred
+            }
+
+            fn component_masks(&self) -> &$crate::ComponentMasks {
+                &self.masks
+            }
+
+            fn sync_masks(&mut self) {
+                World::sync_masks(self);
+            }
+
+            fn disabled(&self) -> &$crate::Disabled {
+                &self.disabled
+            }
+
+            fn disabled_mut(&mut self) -> &mut $crate::Disabled {
+                &mut self.disabled
+            }
+
+            fn debug_names(&self) -> &$crate::DebugNames {
+                &self.names
+            }
+
+            fn debug_names_mut(&mut self) -> &mut $crate::DebugNames {
+                &mut self.names
+            }
+
+            fn debug_origins(&self) -> &$crate::DebugOrigins {
+                &self.origins
+            }
+
+            fn debug_origins_mut(&mut self) -> &mut $crate::DebugOrigins {
+                &mut self.origins
+            }
+
+            fn access_stats(&self) -> &$crate::AccessStats {
+                &self.access_stats
+            }
+
+            fn poison(&self) -> &$crate::Poison {
+                &self.poison
+            }
+        }
+    };
+
+    (@impl_persist {$($component:ident $type:ty)*} {$($resource:ident $resource_type:ty)*} $v:vis) => {
+        // Only usable if every component and resource can be saved. The bounds are higher-ranked
+        // so that they aren't rejected outright when one can't.
+        #[allow(dead_code)]
+        impl World {
+            /// Save the world's entities, components and resources, along with `version`, which
+            /// is passed on to `Migrate::migrate` when the save is loaded.
+            ///
+            /// Storages that can hold several components per entity, like `MultiStorage`, only
+            /// save the first.
+            $v fn save(&self, version: u32) -> $crate::Value
+            where
+                $(for<'x> $type: $crate::Persist,)*
+                $(for<'x> $resource_type: $crate::Persist,)*
+            {
+                use $crate::{ComponentStorage, Persist, Value};
+                let entities = self.alive_entities();
+                let disabled = self.saved_disabled();
+                Value::Map($crate::__private::vec![
+                    ($crate::__private::String::from("version"), version.save()),
+                    ($crate::__private::String::from("entities"), entities.save()),
+                    ($crate::__private::String::from("free"), self.free_list.save()),
+                    ($crate::__private::String::from("disabled"), disabled.save()),
+                    ($crate::__private::String::from("components"), Value::Map($crate::__private::vec![$(
+                        ($crate::__private::String::from(::core::stringify!($component)), {
+                            let storage = self.resources.$component.borrow();
+                            let mut items = $crate::__private::Vec::new();
+                            for id in 0..storage.size() {
+                                if let Some(entity) = storage.entity(id) {
+                                    if let Some(c) = storage.get(entity) {
+                                        items.push(Value::List($crate::__private::vec![entity.save(), c.save()]));
+                                    }
+                                }
+                            }
+                            Value::List(items)
+                        }),
+                    )*])),
+                    ($crate::__private::String::from("resources"), Value::Map($crate::__private::vec![$(
+                        (
+                            $crate::__private::String::from(::core::stringify!($resource)),
+                            self.resources.$resource.borrow().save(),
+                        ),
+                    )*])),
+                ])
+            }
+
+            /// Save `entity`'s components, along with `version`, or return `None` if it isn't
+            /// alive. Only the storages `entity` has components in are looked at. Load the result
+            /// with `load_entity`.
+            $v fn save_entity(&mut self, entity: Entity, version: u32) -> Option<$crate::Value>
+            where
+                $(for<'x> $type: $crate::Persist,)*
+            {
+                use $crate::{Persist, ReadStorage, Value};
+                if !<Self as $crate::WorldInterface<'_>>::is_alive(self, entity) {
+                    return None;
+                }
+                self.flush_reserved();
+                self.sync_masks();
+                let mut components = $crate::__private::Vec::new();
+                $(
+                    if self.masks.contains(entity.id(), __ComponentIndex::$component as usize) {
+                        if let Some(c) = self.resources.$component.get_mut().get(entity) {
+                            components.push((
+                                $crate::__private::String::from(::core::stringify!($component)),
+                                c.save(),
+                            ));
+                        }
+                    }
+                )*
+                Some(Value::Map($crate::__private::vec![
+                    ($crate::__private::String::from("version"), version.save()),
+                    ($crate::__private::String::from("components"), Value::Map(components)),
+                ]))
+            }
+
+            /// Create a new entity with the components saved by `save_entity`, returning it.
+            $v fn load_entity(&mut self, value: &$crate::Value) -> Result<Entity, $crate::PersistError>
+            where
+                $(for<'x> $type: $crate::Migrate,)*
+            {
+                use $crate::{Migrate, Persist};
+                let version = u32::load(value.field("version")?)?;
+                let components = value.field("components")?;
+                let components = ComponentSet {
+                    $(
+                        $component: match components.optional_field(::core::stringify!($component))? {
+                            Some(c) => Some(<$type as Migrate>::migrate(version, c)?),
+                            None => None,
+                        },
+                    )*
+                };
+                self.try_build_entity(components)
+                    .map_err(|e| $crate::PersistError::Custom($crate::__private::format!("{}", e)))
+            }
+
+            /// Like `save`, but in the compact binary format described in the
+            /// [`binary`](binary/index.html) module.
+            $v fn save_binary(&self, version: u32) -> $crate::__private::Vec<u8>
+            where
+                $(for<'x> $type: $crate::Persist,)*
+                $(for<'x> $resource_type: $crate::Persist,)*
+            {
+                $crate::encode_world(&self.save(version)).expect("saves are always encodable")
+            }
+
+            /// Like `load`, but for a save made by `save_binary`.
+            $v fn load_binary(&mut self, bytes: &[u8]) -> Result<(), $crate::PersistError>
+            where
+                $(for<'x> $type: $crate::Migrate,)*
+                $(for<'x> $resource_type: $crate::Migrate,)*
+            {
+                self.load(&$crate::decode_world(bytes)?)
+            }
+
+            /// Replace the world's contents with a save made by `save`, loading each component
+            /// and resource with `Migrate::migrate`. Components and resources the save doesn't
+            /// have are left empty and unchanged, respectively. Nothing is changed if the save
+            /// can't be loaded.
+            ///
+            /// The journal records this as a `Clear`, followed by a `ResourceSet` for each
+            /// resource.
+            $v fn load(&mut self, value: &$crate::Value) -> Result<(), $crate::PersistError>
+            where
+                $(for<'x> $type: $crate::Migrate,)*
+                $(for<'x> $resource_type: $crate::Migrate,)*
+            {
+                use $crate::{ComponentStorage, Migrate, Persist, PersistError, Value};
+                let __version = u32::load(value.field("version")?)?;
+                let __entities = $crate::__private::Vec::<Entity>::load(value.field("entities")?)?;
+                let __free = $crate::__private::Vec::<Entity>::load(value.field("free")?)?;
+                let __disabled = match value.optional_field("disabled")? {
+                    Some(disabled) => $crate::__private::Vec::<Entity>::load(disabled)?,
+                    None => $crate::__private::Vec::new(),
+                };
+                let __components = value.field("components")?;
+                let __resources = value.field("resources")?;
+                let __slots = __entities
+                    .iter()
+                    .chain(__free.iter())
+                    .map(|e| e.id() + 1)
+                    .max()
+                    .unwrap_or(0);
+                let mut __alive = $crate::__private::vec![None; __slots];
+                for &e in __entities.iter() {
+                    __alive[e.id()] = Some(e);
+                }
+                if let Some(e) = __disabled.iter().find(|e| __alive.get(e.id()) != Some(&Some(**e))) {
+                    return Err(PersistError::Custom($crate::__private::format!(
+                        "dead entity {:?} saved as disabled", e)));
+                }
+                $(
+                    let $component = match __components.optional_field(::core::stringify!($component))? {
+                        Some(items) => items
+                            .as_list()?
+                            .iter()
+                            .map(|item| {
+                                let (entity, c) = <(Entity, Value)>::load(item)?;
+                                if __alive.get(entity.id()) != Some(&Some(entity)) {
+                                    return Err(PersistError::Custom($crate::__private::format!(
+                                        "`{}` component saved for dead entity {:?}",
+                                        ::core::stringify!($component),
+                                        entity,
+                                    )));
+                                }
+                                Ok((entity, <$type as Migrate>::migrate(__version, &c)?))
+                            })
+                            .collect::<Result<$crate::__private::Vec<_>, _>>()?,
+                        None => $crate::__private::Vec::new(),
+                    };
+                )*
+                $(
+                    let $resource = match __resources.optional_field(::core::stringify!($resource))? {
+                        Some(r) => Some(<$resource_type as Migrate>::migrate(__version, r)?),
+                        None => None,
+                    };
+                )*
+                let mut __masks = $crate::ComponentMasks::new();
+                $(
+                    for (entity, _) in $component.iter() {
+                        __masks.insert(entity.id(), __ComponentIndex::$component as usize);
+                    }
+                )*
+                for entity in __entities.iter() {
+                    World::check_constraints_by_index(|i| __masks.contains(entity.id(), i), None)
+                        .map_err(|e| PersistError::Custom(
+                            $crate::__private::format!("{} (entity {:?})", e, entity)))?;
+                }
+
+                <Self as $crate::WorldInterface<'_>>::clear(self);
+                self.entities = __alive;
+                self.free_list = __free;
+                for e in __disabled {
+                    self.disabled.insert(e);
+                }
+                $(
+                    let storage = self.resources.$component.get_mut();
+                    for (entity, c) in $component {
+                        self.masks.insert(entity.id(), __ComponentIndex::$component as usize);
+                        storage.set(entity, Some(c));
+                    }
+                )*
+                $(
+                    if let Some(r) = $resource {
+                        <Self as GetResource<$resource_type>>::set(self, r);
+                    }
+                )*
+                Ok(())
+            }
+        }
+
+        $crate::__if_std! {
+            #[allow(dead_code)]
+            impl World {
+                /// Write a binary save to `out`, compressed with `compression`.
+                $v fn save_to(
+                    &self,
+                    version: u32,
+                    out: &mut dyn ::std::io::Write,
+                    compression: &dyn $crate::Compression,
+                ) -> ::std::io::Result<()>
+                where
+                    $(for<'x> $type: $crate::Persist,)*
+                    $(for<'x> $resource_type: $crate::Persist,)*
+                {
+                    use ::std::io::Write;
+                    let mut out = compression.compress(out);
+                    out.write_all(&self.save_binary(version))?;
+                    out.finish()
+                }
+
+                /// Like `load_binary`, but reading a save written by `save_to` with the same
+                /// `compression`.
+                $v fn load_from(
+                    &mut self,
+                    input: &mut dyn ::std::io::Read,
+                    compression: &dyn $crate::Compression,
+                ) -> Result<(), $crate::PersistError>
+                where
+                    $(for<'x> $type: $crate::Migrate,)*
+                    $(for<'x> $resource_type: $crate::Migrate,)*
+                {
+                    use ::std::io::Read;
+                    let mut bytes = $crate::__private::Vec::new();
+                    compression
+                        .decompress(input)
+                        .read_to_end(&mut bytes)
+                        .map_err(|e| $crate::PersistError::Io($crate::__private::format!("{}", e)))?;
+                    self.load_binary(&bytes)
+                }
+
+                /// Start a binary save that's written out a storage at a time. See
+                /// `IncrementalSave`.
+                $v fn save_incremental(&self, version: u32) -> $crate::IncrementalSave
+                where
+                    $(
+                        for<'x> $type: $crate::Persist + 'static,
+                        for<'x> <$type as $crate::StorageSpec<'x>>::Storage: Clone,
+                    )*
+                    $(for<'x> $resource_type: $crate::Persist,)*
+                {
+                    use $crate::{ComponentStorage, Persist};
+                    let entities = self.alive_entities();
+                    let mut save = $crate::IncrementalSave::new(
+                        version,
+                        &entities,
+                        &self.free_list,
+                        &self.saved_disabled(),
+                        $crate::__private::vec![$(
+                            (
+                                $crate::__private::String::from(::core::stringify!($resource)),
+                                self.resources.$resource.borrow().save(),
+                            ),
+                        )*],
+                    );
+                    $(
+                        let storage = self.resources.$component.borrow().clone();
+                        save.push_component(::core::stringify!($component), move || {
+                            let mut items = $crate::__private::Vec::new();
+                            for id in 0..storage.size() {
+                                if let Some(entity) = storage.entity(id) {
+                                    if let Some(c) = storage.get(entity) {
+                                        items.push((entity, c.save()));
+                                    }
+                                }
+                            }
+                            items
+                        });
+                    )*
+                    save
                 }
             }
         }
     };
 
-    (@define_builder_struct $v:vis $($field:ident:$type:ty)*) => {
+    (@define_builder_struct [$($dependent:ident requires $($required:ident),+);*]
+                            $v:vis $($field:ident:$type:ty)*) => {
         #[derive(Default)]
         /// ComponentSet is roughly equivalent to a tuple containing Option<T> for all types the
         /// World stores.
         $v struct ComponentSet {
             $(
-                $field: Option<$type>,
+                $v $field: Option<$type>,
             )*
         }
+        // Only usable if every component is `Clone`. The bounds are higher-ranked so that they
+        // aren't rejected outright when one isn't.
+        impl Clone for ComponentSet where $(for<'x> $type: Clone,)* {
+            fn clone(&self) -> Self {
+                ComponentSet {
+                    $(
+                        $field: self.$field.clone(),
+                    )*
+                }
+            }
+        }
+        impl ComponentSet {
+            /// Check that the components satisfy the world's `constraints`.
+            $v fn check_constraints(&self) -> Result<(), $crate::MissingComponent> {
+                $($(
+                    if self.$dependent.is_some() && self.$required.is_none() {
+                        return Err($crate::MissingComponent {
+                            component: $crate::MissingComponent::type_name_of(&self.$required),
+                            required_by: $crate::MissingComponent::type_name_of(&self.$dependent),
+                        });
+                    }
+                )+)*
+                Ok(())
+            }
+        }
+        impl World {
+            // Like `build_entity`, but returns an error instead of panicking if the components
+            // don't satisfy the world's `constraints`. Nothing is stored in that case.
+            #[track_caller]
+            fn try_build_entity(
+                &mut self,
+                components: ComponentSet,
+            ) -> Result<Entity, $crate::MissingComponent> {
+                components.check_constraints()?;
+                Ok(self.store_entity(components))
+            }
+
+            // Check the world's `constraints` for an entity, given whether it has the component
+            // at each index. If `changed` is given, only the constraints involving that index
+            // are checked.
+            #[allow(dead_code, unused_variables)]
+            fn check_constraints_by_index(
+                has: impl Fn(usize) -> bool,
+                changed: Option<usize>,
+            ) -> Result<(), $crate::MissingComponent> {
+                $($(
+                    let (dependent, required) = (
+                        __ComponentIndex::$dependent as usize,
+                        __ComponentIndex::$required as usize,
+                    );
+                    if changed.map_or(true, |i| i == dependent || i == required)
+                        && has(dependent)
+                        && !has(required)
+                    {
+                        return Err($crate::MissingComponent {
+                            component: $crate::MissingComponent::type_name_of_field(
+                                |s: &ComponentSet| &s.$required),
+                            required_by: $crate::MissingComponent::type_name_of_field(
+                                |s: &ComponentSet| &s.$dependent),
+                        });
+                    }
+                )+)*
+                Ok(())
+            }
+        }
         /// Builder pattern for creating new entities.
-        $v struct EntityBuilder<'a> {
+        ///
+        /// `S` records which components have been provided so far, as a tuple with a
+        /// `Provided`/`Unprovided` for each component, in declaration order.
+        $v struct EntityBuilder<'a, S = ($(__define_world_internal!(@unprovided $field),)*)> {
             components: ComponentSet,
             world: &'a mut World,
+            name: $crate::PendingName,
+            state: ::core::marker::PhantomData<S>,
         }
-        impl<'a> EntityBuilder<'a> {
+        impl<'a, S> EntityBuilder<'a, S> {
             /// Finalize this entity and all of its components by storing them in the `World`.
+            ///
+            /// Panics if the components don't satisfy the world's `constraints`.
+            #[track_caller]
             $v fn build(self) -> Entity {
-                use $crate::WorldInterface;
-                self.world.build_entity(self.components)
+                match self.world.try_build_entity(self.components) {
+                    Ok(entity) => {
+                        self.world.names.assign(entity, self.name);
+                        entity
+                    }
+                    Err(e) => match self.name.get() {
+                        Some(name) => ::core::panic!("{} (building `{}`)", e, name),
+                        None => ::core::panic!("{}", e),
+                    },
+                }
+            }
+
+            /// Like `build`, but returns an error instead of panicking if the components don't
+            /// satisfy the world's `constraints`. Nothing is stored in that case.
+            #[track_caller]
+            #[allow(dead_code)]
+            $v fn try_build(self) -> Result<Entity, $crate::MissingComponent> {
+                let entity = self.world.try_build_entity(self.components)?;
+                self.world.names.assign(entity, self.name);
+                Ok(entity)
+            }
+
+            /// Give the entity a name, for debugging. Does nothing without the `debug-names`
+            /// feature. See the `names` module.
+            #[allow(dead_code)]
+            $v fn named(mut self, name: &str) -> Self {
+                self.name.set(name);
+                self
+            }
+
+            /// Set the component of type `T` to `T::default()`.
+            $v fn with_default<T: Default>(self) -> <Self as $crate::BuildWith<T>>::Output
+            where
+                Self: $crate::BuildWith<T>,
+            {
+                $crate::BuildWith::with(self, T::default())
+            }
+
+            /// Set the component of type `T` only if `cond` is true.
+            $v fn with_if<T>(self, cond: bool, data: T) -> <Self as $crate::BuildWith<T>>::Output
+            where
+                Self: $crate::BuildWith<T>,
+            {
+                $crate::BuildWith::with_option(self, if cond { Some(data) } else { None })
             }
         }
     };
 
-    (@impl_build_with $field:ident $type:ty) => {
-        impl<'a> $crate::BuildWith<$type> for EntityBuilder<'a> {
-            fn with(mut self, data: $type) -> Self {
-                self.components.$field = Some(data);
-                self
+    (@impl_snapshot {$($component:ident $type:ty)*} {$($resource:ident $resource_type:ty)*} $v:vis) => {
+        $crate::__if_std! {
+            #[allow(dead_code)]
+            impl World {
+                /// A text dump of the world, for comparing against a golden file. See the
+                /// `testing` module.
+                $v fn snapshot(&self) -> $crate::__private::String
+                where
+                    $(for<'x> $type: ::core::fmt::Debug,)*
+                    $(for<'x> $resource_type: ::core::fmt::Debug,)*
+                {
+                    use $crate::ComponentStorage;
+                    let mut snapshot =
+                        $crate::testing::Snapshot::new(self.alive_entities());
+                    for (entity, name) in self.names.iter() {
+                        snapshot.name(entity, name);
+                    }
+                    $(
+                        let storage = self.resources.$component.borrow();
+                        for id in 0..storage.size() {
+                            if let Some(entity) = storage.entity(id) {
+                                if let Some(c) = storage.get(entity) {
+                                    snapshot.component(
+                                        entity,
+                                        ::core::stringify!($component),
+                                        $crate::__private::format!("{:?}", c),
+                                    );
+                                }
+                            }
+                        }
+                    )*
+                    $(
+                        snapshot.resource(
+                            ::core::stringify!($resource),
+                            $crate::__private::format!("{:?}", *self.resources.$resource.borrow()),
+                        );
+                    )*
+                    snapshot.finish()
+                }
+            }
+
+            impl $crate::CrashDump for World
+            where
+                $(for<'x> $type: ::core::fmt::Debug,)*
+                $(for<'x> $resource_type: ::core::fmt::Debug,)*
+            {
+                fn write_crash_dump(
+                    &self,
+                    reporter: &$crate::CrashReporter,
+                ) -> ::std::io::Result<::std::path::PathBuf> {
+                    reporter.write(&self.snapshot(), &self.journal.borrow(), &self.poison)
+                }
+            }
+        }
+    };
+
+    (@impl_generate_world $($component:ident $type:ty)*) => {
+        $crate::__if_quickcheck! {
+            impl $crate::arbitrary::GenerateWorld for World
+            where
+                $(for<'x> $type: $crate::arbitrary::Arbitrary,)*
+            {
+                fn arbitrary_entity(&mut self, g: &mut $crate::arbitrary::Gen) -> Entity {
+                    use $crate::arbitrary::Arbitrary;
+                    // Retry until the subset satisfies the constraints. The empty set always
+                    // does, so this finishes.
+                    loop {
+                        let components = ComponentSet {
+                            $(
+                                $component: if bool::arbitrary(g) {
+                                    Some(<$type as Arbitrary>::arbitrary(g))
+                                } else {
+                                    None
+                                },
+                            )*
+                        };
+                        if let Ok(entity) = self.try_build_entity(components) {
+                            break entity;
+                        }
+                    }
+                }
+
+                fn live_entities(&self) -> $crate::__private::Vec<Entity> {
+                    self.alive_entities()
+                }
+            }
+        }
+    };
+
+    (@unprovided $field:ident) => { $crate::Unprovided };
+
+    // Implement `BuildWith` for each component, for builder states where that component (but
+    // not necessarily any other) hasn't been provided yet. The state's type parameters are named
+    // after the fields they stand for.
+    (@impl_build_with [$($before:ident)*]) => {};
+
+    (@impl_build_with [$($before:ident)*]
+                      $field:ident : $type:ty, $($after:ident : $after_type:ty,)*) => {
+        #[allow(non_camel_case_types)]
+        impl<'a, $($before,)* $($after,)*> $crate::BuildWith<$type>
+            for EntityBuilder<'a, ($($before,)* $crate::Unprovided, $($after,)*)>
+        {
+            type Output = EntityBuilder<'a, ($($before,)* $crate::Provided, $($after,)*)>;
+            fn with_option(mut self, data: Option<$type>) -> Self::Output {
+                self.components.$field = data;
+                EntityBuilder {
+                    components: self.components,
+                    world: self.world,
+                    name: self.name,
+                    state: ::core::marker::PhantomData,
+                }
             }
         }
+        __define_world_internal!{@impl_build_with [$($before)* $field] $($after : $after_type,)*}
     };
 }
 
@@ -0,0 +1,145 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Entities that despawn themselves after a number of ticks.
+//!
+//! Particles, floating combat text and temporary hazards only live for a short while. Give them a
+//! component holding a [`Lifetime`](struct.Lifetime.html), and the
+//! [`DespawnExpired`](struct.DespawnExpired.html) system counts it down by one each time it runs,
+//! despawning the entity once it reaches zero. Since `define_world!` can't pick a storage for a
+//! type from another crate, the component is one of the game's own, which implements
+//! `AsMut<Lifetime>`; it can hold whatever else the entity needs, too.
+//!
+//! The despawns are queued in a [`Commands`](../command/struct.Commands.html) buffer while the
+//! component storage is borrowed, and applied once it has been released, so they go through
+//! `WorldInterface::delete_entity` like any other.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Spark {
+//!     life: Lifetime,
+//!     brightness: f32,
+//! }
+//! impl AsMut<Lifetime> for Spark {
+//!     fn as_mut(&mut self) -> &mut Lifetime {
+//!         &mut self.life
+//!     }
+//! }
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             sparks: BasicVecStorage<Spark>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let spark = w
+//!     .new_entity()
+//!     .with(Spark { life: Lifetime(2), brightness: 1.0 })
+//!     .build();
+//! let mut dispatcher = Dispatcher::new().with(&mut w, DespawnExpired::<Spark>::new());
+//! dispatcher.run(&mut w).unwrap();
+//! assert!(w.is_alive(spark));
+//! dispatcher.run(&mut w).unwrap();
+//! assert!(!w.is_alive(spark));
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::*;
+
+/// The number of ticks an entity has left to live. See the
+/// [module-level documentation](index.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lifetime(pub u32);
+
+impl Lifetime {
+    /// Count down one tick, returning whether the lifetime has reached zero. A lifetime that is
+    /// already zero stays there.
+    pub fn tick(&mut self) -> bool {
+        self.0 = self.0.saturating_sub(1);
+        self.0 == 0
+    }
+}
+
+impl AsRef<Lifetime> for Lifetime {
+    fn as_ref(&self) -> &Lifetime {
+        self
+    }
+}
+
+impl AsMut<Lifetime> for Lifetime {
+    fn as_mut(&mut self) -> &mut Lifetime {
+        self
+    }
+}
+
+/// System that counts down the lifetime in every `C` component, and despawns the entities whose
+/// lifetimes reach zero. An entity spawned with a lifetime of zero is despawned the next time the
+/// system runs. Disabled entities' lifetimes are left alone.
+pub struct DespawnExpired<C> {
+    _component: PhantomData<fn() -> C>,
+}
+
+impl<C> DespawnExpired<C> {
+    /// Create the system.
+    pub fn new() -> Self {
+        DespawnExpired {
+            _component: PhantomData,
+        }
+    }
+}
+
+impl<C> Default for DespawnExpired<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W, C> RunSystem<W> for DespawnExpired<C>
+where
+    C: for<'a> StorageSpec<'a, Component = C> + AsMut<Lifetime>,
+    for<'a> <C as StorageSpec<'a>>::Storage: MutableComponentStorage<'a>,
+    W: for<'a> GetComponent<'a, C> + for<'a> WorldInterface<'a> + 'static,
+{
+    type Input = ();
+    type Output = ();
+    fn run_on(&mut self, world: &mut W, _: ()) {
+        let mut commands = Commands::<W>::new();
+        {
+            let mut lifetimes = <W as GetComponent<'_, C>>::get_mut(world);
+            for id in 0..lifetimes.size() {
+                if let Some(entity) = lifetimes.entity(id) {
+                    if world.is_disabled(entity) {
+                        continue;
+                    }
+                    if let Some(c) = lifetimes.get_mut(entity) {
+                        if c.as_mut().tick() {
+                            commands.push(move |w: &mut W| w.delete_entity(entity));
+                        }
+                    }
+                }
+            }
+        }
+        commands.apply(world);
+    }
+}
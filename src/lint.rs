@@ -0,0 +1,154 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Warnings about access declarations and components that look like mistakes.
+//!
+//! A system that declares a `WriteComponent<T>` it doesn't need can't share a stage with any
+//! other system using `T`, and a component no entity ever gets is dead weight in every world.
+//! With the `lints` feature (which turns on `access-stats`), `World::lint` looks for both, and
+//! returns a [`Lint`](enum.Lint.html) for each, which displays as a warning to log:
+//!
+//! * [`Lint::UnusedWrite`](enum.Lint.html#variant.UnusedWrite): a system was given write access
+//!   to a component or resource (by a `WriteComponent`, `RestrictedWriteComponent` or
+//!   `WriteResource`), but in all the runs counted by the world's
+//!   [`AccessStats`](../access_stats/struct.AccessStats.html), it never borrowed it mutably.
+//!   A read would do, unless the system only writes in rare circumstances.
+//! * [`Lint::UnusedComponent`](enum.Lint.html#variant.UnusedComponent): no entity has the
+//!   component, and it's never been added through the world.
+//!
+//! Call it after the game has run for a while, e.g. when it exits. Clearing the access stats
+//! each frame and calling it at the end of the frame narrows the unused write checks down to
+//! that frame.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Position(i32);
+//! #[derive(Default)]
+//! pub struct Velocity(i32);
+//! #[derive(Default)]
+//! pub struct Mana(u32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!             velocities: BasicVecStorage<Velocity>,
+//!             mana: BasicVecStorage<Mana>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! struct Move;
+//! impl<'a> System<'a> for Move {
+//!     // `Velocity` is only read, so this should be a `ReadComponent`.
+//!     type Dependencies = (WriteComponent<'a, Velocity>, WriteComponent<'a, Position>);
+//!     fn run(&'a mut self, (vel, mut pos): Self::Dependencies) {
+//!         (&vel, &mut pos).for_each(|_, (v, p)| p.0 += v.0);
+//!     }
+//! }
+//!
+//! let mut w = World::default();
+//! w.new_entity().with(Position(0)).with(Velocity(1)).build();
+//! let mut dispatcher = Dispatcher::new().with(&mut w, Move);
+//! dispatcher.run(&mut w).unwrap();
+//!
+//! let lints = w.lint();
+//! for lint in &lints {
+//!     println!("warning: {}", lint);
+//! }
+//! assert_eq!(
+//!     lints,
+//!     vec![
+//!         Lint::UnusedWrite {
+//!             system: std::any::type_name::<Move>(),
+//!             accessed: AccessedType::component::<Velocity>(),
+//!             runs: 2,
+//!         },
+//!         Lint::UnusedComponent {
+//!             component: std::any::type_name::<Mana>(),
+//!         },
+//!     ]
+//! );
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::*;
+
+/// A suspicious pattern found by `World::lint`. See the [module-level documentation](index.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lint {
+    /// `system` was given write access to `accessed` but never borrowed it mutably.
+    UnusedWrite {
+        /// The name of the system's type.
+        system: &'static str,
+        /// What it could have read instead.
+        accessed: AccessedType,
+        /// The number of times it was fetched for writing.
+        runs: u64,
+    },
+    /// No entity has the component, and it's never been added through the world.
+    UnusedComponent {
+        /// The name of the component's type.
+        component: &'static str,
+    },
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::UnusedWrite {
+                system,
+                accessed,
+                runs,
+            } => write!(
+                f,
+                "system `{}` requested write access to {} but never mutated it in {} runs; \
+                 reading it instead would let it run alongside more systems",
+                system, accessed, runs
+            ),
+            Lint::UnusedComponent { component } => write!(
+                f,
+                "component `{}` has never been attached to any entity",
+                component
+            ),
+        }
+    }
+}
+
+/// The `UnusedWrite` lints for the access counts in `matrix`, in the order of its systems and
+/// then its types.
+pub fn unused_writes(matrix: &AccessMatrix) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    for (row, &system) in matrix.systems().iter().enumerate() {
+        for (column, &accessed) in matrix.columns().iter().enumerate() {
+            let count = matrix.get(row, column);
+            if count.writes > 0 && count.mutations == 0 {
+                lints.push(Lint::UnusedWrite {
+                    system,
+                    accessed,
+                    runs: count.writes,
+                });
+            }
+        }
+    }
+    lints
+}
@@ -0,0 +1,235 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-system scratch state.
+//!
+//! Requesting a [`Local<'a, T>`](struct.Local.html) in a system's `Dependencies` gives the system
+//! mutable access to a `T` that persists between runs. The slot is created with `T::default()`
+//! the first time it's requested, and is owned by the world, keyed by the
+//! [`SystemId`](struct.SystemId.html) the system was given when it was added to a `Dispatcher`;
+//! two instances of the same system type each get their own `T`. A system run on its own, with
+//! `run_system` and the like, isn't registered anywhere, so its locals only last for that run.
+//!
+//! Locals are scratch state, so they are not carried over when a `World` is cloned.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # #[derive(Default)]
+//! # pub struct Position(i32, i32);
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! struct CountRuns;
+//! impl<'a> System<'a> for CountRuns {
+//!     type Dependencies = (Local<'a, u32>,);
+//!     fn run(&'a mut self, (mut runs,): Self::Dependencies) {
+//!         *runs += 1;
+//!         println!("run #{}", *runs);
+//!     }
+//! }
+//!
+//! let mut w = World::default();
+//! let mut dispatcher = Dispatcher::new().with(&mut w, CountRuns);
+//! dispatcher.run(&mut w).unwrap();
+//! dispatcher.run(&mut w).unwrap();
+//! ```
+
+use alloc::boxed::Box;
+use core::any::{Any, TypeId};
+use core::cell::Cell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cell::{RefMut, WorldCell};
+use crate::hash::HashMap;
+
+/// Per-system state of type `T`.
+pub struct Local<'a, T> {
+    pub(crate) value: RefMut<'a, T>,
+}
+
+impl<'a, T> Deref for Local<'a, T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &T {
+        Deref::deref(&self.value)
+    }
+}
+
+impl<'a, T> DerefMut for Local<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        DerefMut::deref_mut(&mut self.value)
+    }
+}
+
+/// Identifies a system instance, e.g. one added to a `Dispatcher`, so that it can be given its
+/// own `Local`s. Every id is different from every other one created in the same process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemId(usize);
+
+impl SystemId {
+    /// Create a new id.
+    pub fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        SystemId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for SystemId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A slot's key: the instance, the position of the system within it (e.g. in a `Chain`), and the
+// type of the local.
+type SlotKey = (SystemId, usize, TypeId);
+
+/// Storage for every system's `Local`s. Each `World` owns one of these.
+#[derive(Default)]
+pub struct Locals {
+    // Each slot is a boxed `WorldCell<T>`. Slots are only removed when the instance they belong
+    // to is done with them (see `Instance`), so the address of a slot's `WorldCell` is stable for
+    // as long as it can be borrowed.
+    slots: WorldCell<HashMap<SlotKey, Box<dyn Any>>>,
+    // The instance whose systems are running, and how many of them have fetched their
+    // dependencies so far.
+    instance: Cell<Option<(SystemId, usize)>>,
+    current: Cell<Option<(SystemId, usize)>>,
+}
+
+impl Locals {
+    /// Run systems as the instance `id`, until the returned guard is dropped. Each system that
+    /// fetches its dependencies while the guard is alive gets the next of `id`'s sets of slots, so
+    /// the systems should be run in the same order every time.
+    pub fn instance(&self, id: SystemId) -> Instance<'_> {
+        Instance {
+            locals: self,
+            previous: self.begin(id),
+            forget: None,
+        }
+    }
+
+    // Start running systems as the instance `id`, returning the previous instance, which should
+    // be restored with `end`. For when the guard returned by `instance` would keep the world
+    // borrowed.
+    pub(crate) fn begin(&self, id: SystemId) -> Option<(SystemId, usize)> {
+        self.instance.replace(Some((id, 0)))
+    }
+
+    // Undo a call to `begin`.
+    pub(crate) fn end(&self, previous: Option<(SystemId, usize)>) {
+        self.instance.set(previous);
+    }
+
+    // Run a system that isn't part of any instance as a new, temporary one, whose slots are
+    // removed when the guard is dropped. Does nothing if an instance is already running.
+    pub(crate) fn ad_hoc(&self) -> Option<Instance<'_>> {
+        if self.instance.get().is_some() {
+            return None;
+        }
+        let id = SystemId::new();
+        let mut instance = self.instance(id);
+        instance.forget = Some(id);
+        Some(instance)
+    }
+
+    /// Make the next set of slots belonging to the running instance available to `Local`
+    /// dependencies, returning the previously active set (if any), which should be restored with
+    /// `leave` afterwards.
+    pub fn enter(&self) -> Option<(SystemId, usize)> {
+        let next = self.instance.get();
+        if let Some((id, n)) = next {
+            self.instance.set(Some((id, n + 1)));
+        }
+        self.current.replace(next)
+    }
+
+    /// Undo a call to `enter`.
+    pub fn leave(&self, previous: Option<(SystemId, usize)>) {
+        self.current.set(previous);
+    }
+
+    /// Borrow the active system's slot for `T`, creating it if necessary.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no system is active, or if the slot is already borrowed (i.e., a system
+    /// requested the same `Local<T>` twice).
+    pub fn fetch<T: 'static + Default>(&self) -> Local<'_, T> {
+        let (id, n) = self
+            .current
+            .get()
+            .expect("Local<T> can only be fetched while a system is running");
+        let mut slots = self.slots.borrow_mut();
+        let slot = slots
+            .entry((id, n, TypeId::of::<T>()))
+            .or_insert_with(|| Box::new(WorldCell::new(T::default())));
+        let cell = slot
+            .downcast_ref::<WorldCell<T>>()
+            .expect("slot type is determined by its key") as *const WorldCell<T>;
+        // This unsafe block should be sound: the `WorldCell` lives in its own heap allocation that
+        // is never freed or moved while `self` is alive, other than by an `Instance` guard for a
+        // system that has finished running, and the returned borrow is tied to the borrow of
+        // `&self`.
+        Local {
+            value: unsafe { &*cell }.borrow_mut(),
+        }
+    }
+}
+
+/// Guard returned by `Locals::instance`, which ends the instance when dropped.
+pub struct Instance<'l> {
+    locals: &'l Locals,
+    previous: Option<(SystemId, usize)>,
+    forget: Option<SystemId>,
+}
+
+impl Drop for Instance<'_> {
+    fn drop(&mut self) {
+        self.locals.end(self.previous);
+        if let Some(id) = self.forget {
+            self.locals
+                .slots
+                .borrow_mut()
+                .retain(|&(i, _, _), _| i != id);
+        }
+    }
+}
+
+// Locals are scratch state, so clones start out empty.
+impl Clone for Locals {
+    fn clone(&self) -> Self {
+        Locals::default()
+    }
+}
+
+impl core::fmt::Debug for Locals {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Locals")
+            .field("slots", &self.slots.borrow().len())
+            .finish()
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-entity component presence masks.
+//!
+//! The world keeps a bitmask for each entity recording which component storages it occupies, so
+//! operations on a single entity (like deletion) only have to touch those storages. Components are
+//! numbered in the order they are declared in `define_world!`.
+//!
+//! The world can't see components being added or removed directly through a storage (e.g. by a
+//! system calling `set` on a `WriteComponent`), so every mutable borrow of a storage marks that
+//! component as *unsynced*. The world rebuilds the masks for unsynced components from their
+//! storages the next time it needs them, e.g. when an entity is deleted, so a system that borrows
+//! a storage mutably costs one pass over the entities rather than one per deletion.
+
+use alloc::vec::Vec;
+
+use crate::cell::WorldCell;
+
+/// Bitmasks recording which components each entity has.
+#[derive(Clone, Debug, Default)]
+pub struct ComponentMasks {
+    // Number of `u64`s per entity.
+    words: usize,
+    bits: Vec<u64>,
+    unsynced: WorldCell<Vec<u64>>,
+}
+
+impl ComponentMasks {
+    /// Create an empty set of masks.
+    pub fn new() -> Self {
+        ComponentMasks::default()
+    }
+
+    /// Reserve space for `n` more entities with up to `components` components.
+    pub fn reserve(&mut self, n: usize, components: usize) {
+        let words = components.div_ceil(64);
+        if words > self.words {
+            self.grow_words(words);
+        }
+        self.bits.reserve(n * self.words);
+    }
+
+    /// Record that entity `id` has component `component`.
+    pub fn insert(&mut self, id: usize, component: usize) {
+        if component >= self.words * 64 {
+            self.grow_words(component / 64 + 1);
+        }
+        let i = id * self.words + component / 64;
+        if i >= self.bits.len() {
+            self.bits.resize((id + 1) * self.words, 0);
+        }
+        self.bits[i] |= 1 << (component % 64);
+    }
+
+    /// Record that entity `id` doesn't have component `component`.
+    pub fn remove(&mut self, id: usize, component: usize) {
+        if component < self.words * 64 {
+            if let Some(word) = self.bits.get_mut(id * self.words + component / 64) {
+                *word &= !(1 << (component % 64));
+            }
+        }
+    }
+
+    /// Whether entity `id` is recorded as having component `component`.
+    pub fn contains(&self, id: usize, component: usize) -> bool {
+        component < self.words * 64
+            && self
+                .bits
+                .get(id * self.words + component / 64)
+                .is_some_and(|word| word & (1 << (component % 64)) != 0)
+    }
+
+    /// Clear the mask for entity `id`.
+    pub fn clear(&mut self, id: usize) {
+        if let Some(words) = self.bits.get_mut(id * self.words..(id + 1) * self.words) {
+            for word in words {
+                *word = 0;
+            }
+        }
+    }
+
+    /// Iterate over the components entity `id` is recorded as having.
+    pub fn components(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        let words = self
+            .bits
+            .get(id * self.words..(id + 1) * self.words)
+            .unwrap_or(&[]);
+        words.iter().enumerate().flat_map(|(i, &word)| {
+            (0..64)
+                .filter(move |b| word & (1 << b) != 0)
+                .map(move |b| i * 64 + b)
+        })
+    }
+
+    /// Note that the storage for `component` has been borrowed mutably, so it may have changed
+    /// without the masks being updated.
+    pub fn mark_unsynced(&self, component: usize) {
+        let mut unsynced = self.unsynced.borrow_mut();
+        if component / 64 >= unsynced.len() {
+            unsynced.resize(component / 64 + 1, 0);
+        }
+        unsynced[component / 64] |= 1 << (component % 64);
+    }
+
+    /// Replace the record of which entities have `component` with `ids`, and mark it as synced.
+    pub fn sync<I: IntoIterator<Item = usize>>(&mut self, component: usize, ids: I) {
+        if component < self.words * 64 {
+            let entities = self.bits.len() / self.words;
+            for id in 0..entities {
+                self.remove(id, component);
+            }
+        }
+        for id in ids {
+            self.insert(id, component);
+        }
+        if let Some(word) = self.unsynced.get_mut().get_mut(component / 64) {
+            *word &= !(1 << (component % 64));
+        }
+    }
+
+    /// Whether the masks are known to be accurate for `component`.
+    pub fn is_synced(&self, component: usize) -> bool {
+        self.unsynced
+            .borrow()
+            .get(component / 64)
+            .is_none_or(|word| word & (1 << (component % 64)) == 0)
+    }
+
+    // Change the number of words per entity, preserving the existing masks.
+    fn grow_words(&mut self, words: usize) {
+        let entities = self.bits.len().checked_div(self.words).unwrap_or(0);
+        let mut bits = vec![0; entities * words];
+        for id in 0..entities {
+            bits[id * words..id * words + self.words]
+                .copy_from_slice(&self.bits[id * self.words..(id + 1) * self.words]);
+        }
+        self.bits = bits;
+        self.words = words;
+    }
+}
@@ -0,0 +1,190 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Names for entities, for debugging.
+//!
+//! With the `debug-names` feature, entities can be given a name when they're built, with
+//! `new_entity().named("boss_dragon")`, or later on with `WorldInterface::debug_names_mut`. The
+//! names are kept in a side table in the world, [`DebugNames`](struct.DebugNames.html), rather
+//! than in a component, and show up in `World::snapshot` and in the panic from
+//! `EntityBuilder::build`. [`DebugNames::describe`](struct.DebugNames.html#method.describe)
+//! formats an entity with its name, for log messages and panics of your own.
+//!
+//! Without the feature, `DebugNames` is zero-sized, naming an entity does nothing, and every
+//! entity is unnamed, so calls to `named` can be left in release builds.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # #[derive(Default)]
+//! # pub struct Health(u32);
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             health: BasicVecStorage<Health>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let boss = w.new_entity().named("boss_dragon").with(Health(500)).build();
+//! let description = w.debug_names().describe(boss).to_string();
+//! if cfg!(feature = "debug-names") {
+//!     assert_eq!(description, "`boss_dragon` (entity 0, generation 1)");
+//! } else {
+//!     assert_eq!(description, "entity 0 (generation 1)");
+//! }
+//! ```
+
+#[cfg(feature = "debug-names")]
+use alloc::string::String;
+use core::fmt;
+
+#[cfg(feature = "debug-names")]
+use crate::hash::HashMap;
+use crate::Entity;
+
+/// The names of a world's entities. See the [module-level documentation](index.html).
+#[derive(Clone, Debug, Default)]
+pub struct DebugNames {
+    #[cfg(feature = "debug-names")]
+    names: HashMap<Entity, String>,
+}
+
+impl DebugNames {
+    /// Name `entity`, replacing any name it had. Does nothing without the `debug-names` feature.
+    #[inline]
+    pub fn set(&mut self, _entity: Entity, _name: &str) {
+        #[cfg(feature = "debug-names")]
+        self.names.insert(_entity, String::from(_name));
+    }
+
+    /// Give `entity` the name in `pending`, if there is one. Used by `EntityBuilder::build`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn assign(&mut self, _entity: Entity, _pending: PendingName) {
+        #[cfg(feature = "debug-names")]
+        {
+            if let Some(name) = _pending.name {
+                self.names.insert(_entity, name);
+            }
+        }
+    }
+
+    /// `entity`'s name, if it has one. Always `None` without the `debug-names` feature.
+    #[inline]
+    pub fn get(&self, _entity: Entity) -> Option<&str> {
+        #[cfg(feature = "debug-names")]
+        {
+            self.names.get(&_entity).map(String::as_str)
+        }
+        #[cfg(not(feature = "debug-names"))]
+        None
+    }
+
+    /// Forget `entity`'s name. The world does this when the entity is deleted.
+    #[inline]
+    pub fn remove(&mut self, _entity: Entity) {
+        #[cfg(feature = "debug-names")]
+        self.names.remove(&_entity);
+    }
+
+    /// Forget every name.
+    #[inline]
+    pub fn clear(&mut self) {
+        #[cfg(feature = "debug-names")]
+        self.names.clear();
+    }
+
+    /// Every named entity and its name, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &str)> + '_ {
+        #[cfg(feature = "debug-names")]
+        {
+            self.names.iter().map(|(e, name)| (*e, name.as_str()))
+        }
+        #[cfg(not(feature = "debug-names"))]
+        core::iter::empty()
+    }
+
+    /// Format `entity` along with its name, if it has one: "`boss_dragon` (entity 3, generation
+    /// 1)", or "entity 3 (generation 1)".
+    pub fn describe(&self, entity: Entity) -> Described<'_> {
+        Described {
+            entity,
+            name: self.get(entity),
+        }
+    }
+}
+
+/// An entity and its name, formatted by `DebugNames::describe`.
+#[derive(Clone, Copy, Debug)]
+pub struct Described<'a> {
+    entity: Entity,
+    name: Option<&'a str>,
+}
+
+impl fmt::Display for Described<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(
+                f,
+                "`{}` (entity {}, generation {})",
+                name,
+                self.entity.id(),
+                self.entity.generation()
+            ),
+            None => write!(
+                f,
+                "entity {} (generation {})",
+                self.entity.id(),
+                self.entity.generation()
+            ),
+        }
+    }
+}
+
+/// The name given to an `EntityBuilder`, until the entity is built. Zero-sized without the
+/// `debug-names` feature.
+#[doc(hidden)]
+#[derive(Clone, Debug, Default)]
+pub struct PendingName {
+    #[cfg(feature = "debug-names")]
+    name: Option<String>,
+}
+
+impl PendingName {
+    /// Set the name.
+    #[inline]
+    pub fn set(&mut self, _name: &str) {
+        #[cfg(feature = "debug-names")]
+        {
+            self.name = Some(String::from(_name));
+        }
+    }
+
+    /// The name, if one has been set.
+    #[inline]
+    pub fn get(&self) -> Option<&str> {
+        #[cfg(feature = "debug-names")]
+        {
+            self.name.as_deref()
+        }
+        #[cfg(not(feature = "debug-names"))]
+        None
+    }
+}
@@ -0,0 +1,397 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Systems that run when events are sent, rather than every frame.
+//!
+//! Events of type `E` are sent to an [`Events<E>`](struct.Events.html) resource, declared in
+//! `define_world!` like any other, e.g. by a system that depends on
+//! `WriteResource<'a, Events<E>>`. An [`Observer`](trait.Observer.html) of `E` is registered
+//! with a `Dispatcher` by
+//! [`add_observer`](../dispatch/struct.Dispatcher.html#method.add_observer), instead of `add`,
+//! and doesn't run as part of the dispatcher's order. Instead, after each system, the dispatcher
+//! checks for events, and runs each observer whose events have been sent with the whole batch,
+//! which it takes out of the resource. Every observer of the same event type sees the same batch.
+//! So observers always run straight after the system that
+//! sent their events, without being ordered after it, and don't cost anything in frames without
+//! events.
+//!
+//! Observers can send events in turn; the dispatcher keeps running observers until there are no
+//! events left (so observers that keep sending each other events never finish). Events sent
+//! before the dispatcher runs, e.g. by input handling between frames, are observed before its
+//! first system. Like systems, observers can depend on anything in the world, and have their
+//! `Deferred` changes applied at the end of the dispatcher's run.
+//!
+//! # Events for particular entities
+//!
+//! Events that are addressed to an entity, like damage, go in an
+//! [`EntityEvents<E>`](struct.EntityEvents.html) resource instead, sent with
+//! [`WorldInterface::send_to`](../traits/trait.WorldInterface.html#method.send_to) or
+//! `EntityEvents::send`. They're kept grouped by entity, so a system can visit just the entities
+//! that have events and match one of its joins, with
+//! [`for_each_matching`](struct.EntityEvents.html#method.for_each_matching), rather than checking
+//! every event against every entity. Like `InputEvents`, they're kept until they're cleared, so
+//! any number of systems can read them; clear them once a frame.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Health(u32);
+//!
+//! pub struct Died(Entity);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             health: BasicVecStorage<Health>,
+//!         }
+//!         resources {
+//!             deaths: Events<Died>,
+//!             graveyard: Vec<Entity>,
+//!         }
+//!     }
+//! );
+//!
+//! struct Poison;
+//! impl<'a> System<'a> for Poison {
+//!     type Dependencies = (WriteComponent<'a, Health>, WriteResource<'a, Events<Died>>);
+//!     fn run(&'a mut self, (mut health, mut deaths): Self::Dependencies) {
+//!         (&mut health,).for_each(|e, (h,)| {
+//!             if h.0 > 0 {
+//!                 h.0 -= 1;
+//!                 if h.0 == 0 {
+//!                     deaths.send(Died(e));
+//!                 }
+//!             }
+//!         });
+//!     }
+//! }
+//!
+//! struct Bury;
+//! impl<'a> Observer<'a, Died> for Bury {
+//!     type Dependencies = (WriteResource<'a, Vec<Entity>>,);
+//!     fn observe(&'a mut self, deaths: &[Died], (mut graveyard,): Self::Dependencies) {
+//!         graveyard.extend(deaths.iter().map(|d| d.0));
+//!     }
+//! }
+//!
+//! let mut w = World::default();
+//! let a = w.new_entity().with(Health(1)).build();
+//! let b = w.new_entity().with(Health(2)).build();
+//! let mut dispatcher = Dispatcher::new().with(&mut w, Poison);
+//! dispatcher.add_observer(Bury);
+//!
+//! dispatcher.run(&mut w).unwrap();
+//! assert_eq!(*<World as GetResource<Vec<Entity>>>::get(&w), vec![a]);
+//! dispatcher.run(&mut w).unwrap();
+//! assert_eq!(*<World as GetResource<Vec<Entity>>>::get(&w), vec![a, b]);
+//! assert!(<World as GetResource<Events<Died>>>::get(&w).is_empty());
+//! ```
+//!
+//! Entity events:
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Health(u32);
+//! #[derive(Default)]
+//! pub struct Armor(u32);
+//!
+//! pub struct Damage(u32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             health: BasicVecStorage<Health>,
+//!             armor: BasicVecStorage<Armor>,
+//!         }
+//!         resources {
+//!             damage: EntityEvents<Damage>,
+//!         }
+//!     }
+//! );
+//!
+//! struct ApplyDamage;
+//! impl<'a> System<'a> for ApplyDamage {
+//!     type Dependencies = (
+//!         ReadResource<'a, EntityEvents<Damage>>,
+//!         ReadComponent<'a, Armor>,
+//!         WriteComponent<'a, Health>,
+//!     );
+//!     fn run(&'a mut self, (damage, armor, mut health): Self::Dependencies) {
+//!         // Only visits entities that were sent damage and have both components.
+//!         damage.for_each_matching((&armor, &mut health), |_, hits, (armor, health)| {
+//!             for hit in hits {
+//!                 health.0 = health.0.saturating_sub(hit.0.saturating_sub(armor.0));
+//!             }
+//!         });
+//!     }
+//! }
+//!
+//! let mut w = World::default();
+//! let knight = w.new_entity().with(Health(10)).with(Armor(2)).build();
+//! let ghost = w.new_entity().with(Health(10)).build();
+//! w.send_to(knight, Damage(5));
+//! w.send_to(knight, Damage(1));
+//! w.send_to(ghost, Damage(5));
+//! w.run_system(&mut ApplyDamage);
+//! <World as GetResource<EntityEvents<Damage>>>::get_mut(&w).clear();
+//!
+//! assert_eq!(w.component::<Health>().get(knight).unwrap().0, 7);
+//! assert_eq!(w.component::<Health>().get(ghost).unwrap().0, 10);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::any::{Any, TypeId};
+use core::marker::PhantomData;
+
+use crate::*;
+
+/// Resource holding events of type `E` that have been sent but not yet observed. See the
+/// [module-level documentation](index.html).
+#[derive(Clone, Debug)]
+pub struct Events<E> {
+    pending: Vec<E>,
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Events {
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<E> Events<E> {
+    /// Send an event.
+    #[inline]
+    pub fn send(&mut self, event: E) {
+        self.pending.push(event);
+    }
+
+    /// Iterate over the events that haven't been observed yet, in the order they were sent.
+    #[inline]
+    pub fn iter(&self) -> core::slice::Iter<'_, E> {
+        self.pending.iter()
+    }
+
+    /// Number of events that haven't been observed yet.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Whether every event has been observed.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Take the events that haven't been observed yet, e.g. to handle them without an observer.
+    pub fn drain(&mut self) -> alloc::vec::Drain<'_, E> {
+        self.pending.drain(..)
+    }
+}
+
+impl<E> Extend<E> for Events<E> {
+    fn extend<T: IntoIterator<Item = E>>(&mut self, iter: T) {
+        self.pending.extend(iter);
+    }
+}
+
+/// Resource holding events of type `E` addressed to particular entities. See the
+/// [module-level documentation](index.html).
+#[derive(Clone, Debug)]
+pub struct EntityEvents<E> {
+    // The entities that have been sent events, in ascending id order, and each one's events in
+    // the order they were sent.
+    targets: Vec<Entity>,
+    events: Vec<Vec<E>>,
+}
+
+impl<E> Default for EntityEvents<E> {
+    fn default() -> Self {
+        EntityEvents {
+            targets: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+}
+
+impl<E> EntityEvents<E> {
+    /// Send an event to `entity`.
+    pub fn send(&mut self, entity: Entity, event: E) {
+        let key = |e: &Entity| (e.id(), e.generation());
+        match self.targets.binary_search_by_key(&key(&entity), key) {
+            Ok(i) => self.events[i].push(event),
+            Err(i) => {
+                self.targets.insert(i, entity);
+                self.events.insert(i, alloc::vec![event]);
+            }
+        }
+    }
+
+    /// The events sent to `entity`, in the order they were sent.
+    pub fn get(&self, entity: Entity) -> &[E] {
+        let key = |e: &Entity| (e.id(), e.generation());
+        match self.targets.binary_search_by_key(&key(&entity), key) {
+            Ok(i) => &self.events[i],
+            Err(_) => &[],
+        }
+    }
+
+    /// The entities that have been sent events, in ascending id order.
+    pub fn targets(&self) -> &[Entity] {
+        &self.targets
+    }
+
+    /// Iterate over the entities that have been sent events, in ascending id order, along with
+    /// their events.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &[E])> + '_ {
+        self.targets
+            .iter()
+            .copied()
+            .zip(self.events.iter().map(|e| &e[..]))
+    }
+
+    /// Call `f` on each entity that has been sent events and matches `join`, in ascending id
+    /// order, with its events and its components from the join. Only the entities with events
+    /// are looked up, however many the join would otherwise visit.
+    pub fn for_each_matching<J, F>(&self, join: J, mut f: F)
+    where
+        J: Join,
+        F: FnMut(Entity, &[E], J::Output),
+    {
+        join.for_each_in(&self.targets, |e, components| f(e, self.get(e), components));
+    }
+
+    /// Total number of events.
+    pub fn len(&self) -> usize {
+        self.events.iter().map(Vec::len).sum()
+    }
+
+    /// Whether there are no events.
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// Remove every event. Call this at the end of each frame.
+    pub fn clear(&mut self) {
+        self.targets.clear();
+        self.events.clear();
+    }
+}
+
+/// A system that runs with each batch of events of type `E`, rather than every frame. See the
+/// [module-level documentation](index.html).
+pub trait Observer<'a, E> {
+    /// The components and resources this observer needs to run.
+    type Dependencies: Nest;
+    /// Handle a batch of events, in the order they were sent.
+    fn observe(&'a mut self, events: &[E], dependencies: Self::Dependencies);
+}
+
+// The batches of events being observed, one per event type, shared by all the observers of that
+// type. The `Dispatcher` keeps them between runs to reuse their allocations.
+#[derive(Default)]
+pub(crate) struct Batches(Vec<(TypeId, Box<dyn Any>)>);
+
+impl Batches {
+    fn get_mut<E: 'static>(&mut self) -> &mut Vec<E> {
+        let id = TypeId::of::<E>();
+        let i = match self.0.iter().position(|(t, _)| *t == id) {
+            Some(i) => i,
+            None => {
+                self.0.push((id, Box::new(Vec::<E>::new())));
+                self.0.len() - 1
+            }
+        };
+        match self.0[i].1.downcast_mut() {
+            Some(batch) => batch,
+            None => unreachable!("batches are keyed by their type"),
+        }
+    }
+}
+
+// An observer, type-erased for the `Dispatcher`, which triggers observers in three passes so that
+// every observer of an event type sees the same batch.
+pub(crate) trait Trigger<W> {
+    // Move the events sent since the last pass into the observer's batch, unless another
+    // observer of the same type already has.
+    fn take(&self, world: &mut W, batches: &mut Batches);
+    // Run the observer if its batch isn't empty, returning whether it wasn't.
+    fn trigger(&mut self, world: &mut W, batches: &mut Batches) -> bool;
+    // Empty the observer's batch, once every observer has seen it.
+    fn clear(&self, batches: &mut Batches);
+}
+
+pub(crate) struct ObserverRunner<E, O> {
+    observer: O,
+    // Identifies the observer's `Local`s.
+    id: SystemId,
+    tag: PhantomData<fn(&E)>,
+}
+
+impl<E, O> ObserverRunner<E, O> {
+    pub(crate) fn new(observer: O) -> Self {
+        ObserverRunner {
+            observer,
+            id: SystemId::new(),
+            tag: PhantomData,
+        }
+    }
+}
+
+impl<W, E, O> Trigger<W> for ObserverRunner<E, O>
+where
+    E: 'static,
+    O: for<'a> Observer<'a, E> + 'static,
+    W: for<'a> WorldInterface<'a>
+        + GetResource<Events<E>>
+        + for<'a> ComponentProviderRec<'a, <<O as Observer<'a, E>>::Dependencies as Nest>::Nested>,
+{
+    fn take(&self, world: &mut W, batches: &mut Batches) {
+        let batch = batches.get_mut::<E>();
+        if batch.is_empty() && !<W as GetResource<Events<E>>>::get(world).is_empty() {
+            core::mem::swap(
+                batch,
+                &mut <W as GetResource<Events<E>>>::get_mut(world).pending,
+            );
+        }
+    }
+
+    fn trigger(&mut self, world: &mut W, batches: &mut Batches) -> bool {
+        let batch = batches.get_mut::<E>();
+        if batch.is_empty() {
+            return false;
+        }
+        let (observer, batch) = (&mut self.observer, &*batch);
+        let world: &W = world;
+        let _instance = world.locals().instance(self.id);
+        world.poison().guard::<O, _, _>(move || {
+            observer.observe(batch, world.fetch_for::<O, _>());
+        });
+        true
+    }
+
+    fn clear(&self, batches: &mut Batches) {
+        batches.get_mut::<E>().clear();
+    }
+}
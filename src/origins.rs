@@ -0,0 +1,170 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Where and when entities were created, for debugging.
+//!
+//! With the `debug-origins` feature, the world records the source location that built each
+//! entity (the call to `EntityBuilder::build`, `WorldInterface::spawn_batch` or
+//! `WorldInterface::build_entity`) and the frame it was built in, as counted by
+//! `Dispatcher::run_frame`, in a side table, [`DebugOrigins`](struct.DebugOrigins.html). When an
+//! entity turns up that shouldn't be there, `World::debug_entity` says what spawned it, along
+//! with its debug name if it has one (see the [`names`](../names/index.html) module).
+//!
+//! Without the feature, `DebugOrigins` is zero-sized and nothing is recorded.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # #[derive(Default)]
+//! # pub struct Health(u32);
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             health: BasicVecStorage<Health>,
+//!         }
+//!         resources {
+//!             frame: FrameInfo,
+//!         }
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! Dispatcher::new().run_frame(&mut w, Default::default()).unwrap();
+//! let rogue = w.new_entity().with(Health(1)).build();
+//!
+//! let origin = w.debug_origins().get(rogue);
+//! if cfg!(feature = "debug-origins") {
+//!     let origin = origin.unwrap();
+//!     assert_eq!(origin.location().line(), line!() - 5);
+//!     assert_eq!(origin.tick(), 1);
+//!     println!("{}", w.debug_entity(rogue).unwrap());
+//! } else {
+//!     assert!(origin.is_none());
+//! }
+//! ```
+
+use core::fmt;
+use core::panic::Location;
+
+#[cfg(feature = "debug-origins")]
+use crate::hash::HashMap;
+use crate::Entity;
+
+/// Where and when an entity was created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Origin {
+    location: &'static Location<'static>,
+    tick: u64,
+}
+
+impl Origin {
+    /// The source location that built the entity.
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// The frame the entity was built in, or zero if it was built before the first frame.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "spawned at {} in frame {}", self.location, self.tick)
+    }
+}
+
+/// The origins of a world's entities. See the [module-level documentation](index.html).
+#[derive(Clone, Debug, Default)]
+pub struct DebugOrigins {
+    #[cfg(feature = "debug-origins")]
+    tick: u64,
+    #[cfg(feature = "debug-origins")]
+    origins: HashMap<Entity, Origin>,
+}
+
+impl DebugOrigins {
+    /// Record that `entity` was created by the caller (or by its caller, and so on, for as long
+    /// as the callers are `#[track_caller]`) in the current frame. Does nothing without the
+    /// `debug-origins` feature.
+    #[inline]
+    #[track_caller]
+    pub fn record(&mut self, _entity: Entity) {
+        #[cfg(feature = "debug-origins")]
+        self.origins.insert(
+            _entity,
+            Origin {
+                location: Location::caller(),
+                tick: self.tick,
+            },
+        );
+    }
+
+    /// Set the frame that entities are being created in. `Dispatcher::run_frame` does this.
+    #[inline]
+    pub fn set_tick(&mut self, _tick: u64) {
+        #[cfg(feature = "debug-origins")]
+        {
+            self.tick = _tick;
+        }
+    }
+
+    /// Where and when `entity` was created. Always `None` without the `debug-origins` feature.
+    #[inline]
+    pub fn get(&self, _entity: Entity) -> Option<Origin> {
+        #[cfg(feature = "debug-origins")]
+        {
+            self.origins.get(&_entity).copied()
+        }
+        #[cfg(not(feature = "debug-origins"))]
+        None
+    }
+
+    /// Forget `entity`'s origin. The world does this when the entity is deleted.
+    #[inline]
+    pub fn remove(&mut self, _entity: Entity) {
+        #[cfg(feature = "debug-origins")]
+        self.origins.remove(&_entity);
+    }
+
+    /// Forget every origin.
+    #[inline]
+    pub fn clear(&mut self) {
+        #[cfg(feature = "debug-origins")]
+        self.origins.clear();
+    }
+}
+
+/// Everything known about an entity for debugging, returned by `World::debug_entity`. Displays
+/// as e.g. "`boss_dragon` (entity 3, generation 1), spawned at src/main.rs:40:10 in frame 12".
+#[derive(Clone, Copy, Debug)]
+pub struct EntityDebug<'a> {
+    /// The entity, and its name if it has one.
+    pub entity: crate::Described<'a>,
+    /// Where and when it was created, if that was recorded.
+    pub origin: Option<Origin>,
+}
+
+impl fmt::Display for EntityDebug<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.origin {
+            Some(origin) => write!(f, "{}, {}", self.entity, origin),
+            None => self.entity.fmt(f),
+        }
+    }
+}
@@ -0,0 +1,308 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pathfinding requests and a solver system.
+//!
+//! Systems that want a path (AI deciding where to go, the player clicking on a distant tile)
+//! submit a request to the [`PathRequests`](struct.PathRequests.html) resource. A
+//! [`PathSolver`](struct.PathSolver.html) system, run later in the frame, finds the paths with A*
+//! over a grid cost function and gives each requesting entity the result as a component.
+//!
+//! The result component is a type of your own that can be made from a
+//! [`Path`](enum.Path.html), since component types have to be defined in the crate that calls
+//! `define_world!`.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Route(Option<Path>);
+//!
+//! impl From<Path> for Route {
+//!     fn from(path: Path) -> Self {
+//!         Route(Some(path))
+//!     }
+//! }
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             routes: BasicVecStorage<Route>,
+//!         }
+//!         resources {
+//!             path_requests: PathRequests,
+//!         }
+//!     }
+//! );
+//!
+//! // A wall at x == 1, with a gap at y == 2.
+//! let cost = |(x, y): (i32, i32)| {
+//!     if x < 0 || y < 0 || x > 4 || y > 4 || (x == 1 && y != 2) {
+//!         None
+//!     } else {
+//!         Some(1)
+//!     }
+//! };
+//!
+//! let mut w = World::default();
+//! let goblin = w.new_entity().build();
+//! <World as GetResource<PathRequests>>::get_mut(&w).submit(goblin, (0, 0), (2, 0));
+//!
+//! let mut dispatcher = Dispatcher::new();
+//! dispatcher.add(&mut w, PathSolver::<_, Route>::new(cost));
+//! dispatcher.run(&mut w).unwrap();
+//!
+//! let routes = <World as GetComponent<'_, Route>>::get(&w);
+//! let path = routes.get(goblin).unwrap().0.as_ref().unwrap();
+//! assert_eq!(
+//!     path.steps(),
+//!     Some(&[(0, 1), (0, 2), (1, 2), (2, 2), (2, 1), (2, 0)][..])
+//! );
+//! ```
+
+use alloc::collections::{BinaryHeap, VecDeque};
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::marker::PhantomData;
+
+use crate::hash::HashMap;
+use crate::*;
+
+/// Gives the cost of entering each tile of a grid.
+///
+/// This is implemented for closures of type `Fn((i32, i32)) -> Option<u32>`.
+pub trait GridCost {
+    /// The cost of entering the tile at `(x, y)`, or `None` if it can't be entered. Costs should
+    /// be at least 1 for the paths found to be the cheapest.
+    fn cost(&self, tile: (i32, i32)) -> Option<u32>;
+}
+
+impl<F> GridCost for F
+where
+    F: Fn((i32, i32)) -> Option<u32>,
+{
+    fn cost(&self, tile: (i32, i32)) -> Option<u32> {
+        self(tile)
+    }
+}
+
+/// The result of a path request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Path {
+    /// The tiles to step through, in order, ending at the destination. The starting tile isn't
+    /// included, so this is empty if the destination is the start.
+    Found(Vec<(i32, i32)>),
+    /// The destination can't be reached (within the solver's search limit).
+    Unreachable,
+}
+
+impl Path {
+    /// The steps of the path, if one was found.
+    pub fn steps(&self) -> Option<&[(i32, i32)]> {
+        match self {
+            Path::Found(steps) => Some(steps),
+            Path::Unreachable => None,
+        }
+    }
+}
+
+/// A request for a path from `from` to `to`, to be given to `entity`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathRequest {
+    /// The entity that will be given the result.
+    pub entity: Entity,
+    /// The starting tile.
+    pub from: (i32, i32),
+    /// The destination tile.
+    pub to: (i32, i32),
+}
+
+/// Queue of pending path requests, meant to be kept as a resource. See the
+/// [module documentation](index.html).
+#[derive(Clone, Debug, Default)]
+pub struct PathRequests {
+    queue: VecDeque<PathRequest>,
+}
+
+impl PathRequests {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request a path for `entity`. A later request for the same entity doesn't replace an
+    /// earlier one; both are solved, in order.
+    pub fn submit(&mut self, entity: Entity, from: (i32, i32), to: (i32, i32)) {
+        self.queue.push_back(PathRequest { entity, from, to });
+    }
+
+    /// The number of pending requests.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Whether there are no pending requests.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Drop any pending requests for `entity`. Call this when deleting an entity that might have
+    /// some, so that the solver doesn't spend time on them; it skips deleted entities, but only
+    /// once it gets to their requests.
+    pub fn cancel(&mut self, entity: Entity) {
+        self.queue.retain(|r| r.entity != entity);
+    }
+
+    /// Remove the oldest pending request.
+    pub fn pop(&mut self) -> Option<PathRequest> {
+        self.queue.pop_front()
+    }
+}
+
+/// System that solves the requests in `PathRequests` and gives each requesting entity a `P`
+/// component made from the result.
+///
+/// Requests are solved in the order they were submitted, on the thread the system runs on.
+/// Requests for entities that have been deleted since are dropped, but still count towards the
+/// budget.
+pub struct PathSolver<C, P> {
+    cost: C,
+    diagonal: bool,
+    max_expanded: usize,
+    budget: usize,
+    _output: PhantomData<fn() -> P>,
+}
+
+impl<C: GridCost, P> PathSolver<C, P> {
+    /// Create a solver that moves in the four cardinal directions, explores at most 10,000 tiles
+    /// per request, and solves every pending request each time it runs.
+    pub fn new(cost: C) -> Self {
+        PathSolver {
+            cost,
+            diagonal: false,
+            max_expanded: 10_000,
+            budget: usize::MAX,
+            _output: PhantomData,
+        }
+    }
+
+    /// Whether paths may move diagonally.
+    pub fn diagonal(mut self, diagonal: bool) -> Self {
+        self.diagonal = diagonal;
+        self
+    }
+
+    /// The number of tiles to explore before giving up on a request as `Path::Unreachable`.
+    pub fn max_expanded(mut self, n: usize) -> Self {
+        self.max_expanded = n;
+        self
+    }
+
+    /// The number of requests to solve each time the system runs. The rest are left in the queue
+    /// for next time.
+    pub fn budget(mut self, n: usize) -> Self {
+        self.budget = n;
+        self
+    }
+
+    /// Find a path from `from` to `to` straight away.
+    pub fn solve(&self, from: (i32, i32), to: (i32, i32)) -> Path {
+        let heuristic = |(x, y): (i32, i32)| {
+            let (dx, dy) = (x.abs_diff(to.0), y.abs_diff(to.1));
+            if self.diagonal {
+                dx.max(dy)
+            } else {
+                dx.saturating_add(dy)
+            }
+        };
+        let mut open = BinaryHeap::new();
+        let mut best: HashMap<(i32, i32), (u32, (i32, i32))> = HashMap::new();
+        open.push(Reverse((heuristic(from), 0, from)));
+        best.insert(from, (0, from));
+        let mut expanded = 0;
+        while let Some(Reverse((_, g, tile))) = open.pop() {
+            if tile == to {
+                let mut steps = Vec::new();
+                let mut t = to;
+                while t != from {
+                    steps.push(t);
+                    t = best[&t].1;
+                }
+                steps.reverse();
+                return Path::Found(steps);
+            }
+            if g > best[&tile].0 {
+                // Already reached more cheaply.
+                continue;
+            }
+            expanded += 1;
+            if expanded > self.max_expanded {
+                break;
+            }
+            for &(dx, dy) in NEIGHBOURS[..if self.diagonal { 8 } else { 4 }].iter() {
+                // Tiles past the edges of `i32`, and paths costing more than a `u32` can count,
+                // are out of reach.
+                let next = match (tile.0.checked_add(dx), tile.1.checked_add(dy)) {
+                    (Some(x), Some(y)) => (x, y),
+                    _ => continue,
+                };
+                let g = match self.cost.cost(next).and_then(|cost| g.checked_add(cost)) {
+                    Some(g) => g,
+                    None => continue,
+                };
+                if best.get(&next).is_none_or(|&(old, _)| g < old) {
+                    best.insert(next, (g, tile));
+                    open.push(Reverse((g.saturating_add(heuristic(next)), g, next)));
+                }
+            }
+        }
+        Path::Unreachable
+    }
+}
+
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (0, -1),
+    (-1, 0),
+    (1, 0),
+    (0, 1),
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+];
+
+impl<W, C, P> RunSystem<W> for PathSolver<C, P>
+where
+    C: GridCost,
+    P: for<'a> StorageSpec<'a, Component = P> + From<Path>,
+    W: GetResource<PathRequests> + for<'a> GetComponent<'a, P> + for<'a> WorldInterface<'a>,
+{
+    type Input = ();
+    type Output = ();
+    fn run_on(&mut self, world: &mut W, _: ()) {
+        for _ in 0..self.budget {
+            let request = match <W as GetResource<PathRequests>>::get_mut(world).pop() {
+                Some(request) => request,
+                None => break,
+            };
+            if world.is_alive(request.entity) {
+                let path = self.solve(request.from, request.to);
+                <W as GetComponent<'_, P>>::insert(world, request.entity, P::from(path));
+            }
+        }
+    }
+}
@@ -0,0 +1,482 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Converting game data to and from a self-describing value tree.
+//!
+//! Types that implement [`Persist`](trait.Persist.html) can be turned into a
+//! [`Value`](enum.Value.html) and back. `Value` is deliberately simple (numbers, strings, lists
+//! and string-keyed maps), so that it can be written out in whatever format a game needs without
+//! this crate depending on a serialization framework.
+//!
+//! `Persist` is implemented for the primitive types, `String`, `Option`, `Vec`, `Box`, `BTreeMap`,
+//! small tuples and `Entity`. For structs with named fields, the
+//! [`impl_persist!`](../macro.impl_persist.html) macro writes the implementation.
+//!
+//! # Saving worlds
+//!
+//! `define_world!` gives `World` a `save` method, usable if every component and resource
+//! implements `Persist`, and a `load` method, usable if they all implement
+//! [`Migrate`](trait.Migrate.html). `save` records a version number of the game's choosing, and
+//! `load` passes it to each type's `Migrate::migrate`, so component layouts can change without
+//! breaking old saves.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Debug, PartialEq)]
+//! pub struct Stats {
+//!     hp: u32,
+//!     name: String,
+//! }
+//! impl_persist!(Stats { hp, name });
+//!
+//! let stats = Stats { hp: 12, name: "kobold".to_string() };
+//! let value = stats.save();
+//! assert_eq!(value.field("hp"), Ok(&Value::UInt(12)));
+//! assert_eq!(Stats::load(&value), Ok(stats));
+//! ```
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::error::Error;
+use core::fmt;
+
+use crate::Entity;
+
+/// A self-describing tree of data. See the [module documentation](index.html).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// No data, e.g. `()` or `None`.
+    Unit,
+    /// A boolean.
+    Bool(bool),
+    /// A signed integer.
+    Int(i64),
+    /// An unsigned integer.
+    UInt(u64),
+    /// A floating point number.
+    Float(f64),
+    /// A string.
+    Str(String),
+    /// A sequence of values.
+    List(Vec<Value>),
+    /// A sequence of named values, in order. Names are unique.
+    Map(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// A short description of the kind of value, for error messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Unit => "unit",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::UInt(_) => "uint",
+            Value::Float(_) => "float",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+        }
+    }
+
+    /// Look up the field called `name`, if this is a map.
+    pub fn field(&self, name: &str) -> Result<&Value, PersistError> {
+        self.as_map()?
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v)
+            .ok_or_else(|| PersistError::MissingField(name.to_string()))
+    }
+
+    /// Like `field`, but returns `None` instead of an error if the map has no such field.
+    pub fn optional_field(&self, name: &str) -> Result<Option<&Value>, PersistError> {
+        Ok(self
+            .as_map()?
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v))
+    }
+
+    /// The elements of a list.
+    pub fn as_list(&self) -> Result<&[Value], PersistError> {
+        match self {
+            Value::List(items) => Ok(items),
+            _ => Err(PersistError::mismatch("list", self)),
+        }
+    }
+
+    /// The entries of a map.
+    pub fn as_map(&self) -> Result<&[(String, Value)], PersistError> {
+        match self {
+            Value::Map(entries) => Ok(entries),
+            _ => Err(PersistError::mismatch("map", self)),
+        }
+    }
+}
+
+/// Error produced when a `Value` doesn't have the shape a type expects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PersistError {
+    /// The value was of the wrong kind.
+    TypeMismatch {
+        /// The kind of value that was expected.
+        expected: &'static str,
+        /// The kind of value that was found.
+        found: &'static str,
+    },
+    /// A number didn't fit in the type being loaded.
+    OutOfRange,
+    /// A map was missing a field.
+    MissingField(String),
+    /// Binary data couldn't be decoded.
+    Malformed(String),
+    /// Reading or writing the save failed.
+    Io(String),
+    /// Some other problem.
+    Custom(String),
+}
+
+impl PersistError {
+    /// A `TypeMismatch` for when `expected` was wanted but `found` was there.
+    pub fn mismatch(expected: &'static str, found: &Value) -> Self {
+        PersistError::TypeMismatch {
+            expected,
+            found: found.kind(),
+        }
+    }
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistError::TypeMismatch { expected, found } => {
+                write!(f, "expected a {} value, found a {}", expected, found)
+            }
+            PersistError::OutOfRange => write!(f, "number out of range"),
+            PersistError::MissingField(name) => write!(f, "missing field `{}`", name),
+            PersistError::Malformed(what) => write!(f, "malformed data: {}", what),
+            PersistError::Io(what) => write!(f, "i/o error: {}", what),
+            PersistError::Custom(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for PersistError {}
+
+/// Types that can be converted to and from a `Value`. See the [module documentation](index.html).
+pub trait Persist: Sized {
+    /// Convert to a `Value`.
+    fn save(&self) -> Value;
+    /// Convert back from a `Value` produced by `save`.
+    fn load(value: &Value) -> Result<Self, PersistError>;
+}
+
+/// Types that can be loaded from data saved by an older version of a game.
+///
+/// `World::load` loads every component and resource through this trait, passing the version
+/// number the world was saved with, so that a type whose layout has changed can convert data in
+/// its old layout. Types whose layout hasn't changed can use the default implementation, which
+/// ignores the version:
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// pub struct Gold(u32);
+/// # impl Persist for Gold {
+/// #     fn save(&self) -> Value { self.0.save() }
+/// #     fn load(value: &Value) -> Result<Self, PersistError> { u32::load(value).map(Gold) }
+/// # }
+/// impl Migrate for Gold {}
+/// ```
+pub trait Migrate: Persist {
+    /// Load a value saved by version `version`.
+    fn migrate(version: u32, value: &Value) -> Result<Self, PersistError> {
+        let _ = version;
+        Self::load(value)
+    }
+}
+
+/// Implements `Persist` for a struct with named fields, saving it as a map with an entry per
+/// field. Every field's type must implement `Persist`.
+#[macro_export]
+macro_rules! impl_persist {
+    ($name:ident { $($field:ident),* $(,)* }) => {
+        impl $crate::Persist for $name {
+            fn save(&self) -> $crate::Value {
+                $crate::Value::Map($crate::__private::vec![
+                    $((
+                        $crate::__private::String::from(::core::stringify!($field)),
+                        $crate::Persist::save(&self.$field),
+                    )),*
+                ])
+            }
+            fn load(value: &$crate::Value) -> Result<Self, $crate::PersistError> {
+                Ok($name {
+                    $(
+                        $field: $crate::Persist::load(value.field(::core::stringify!($field))?)?,
+                    )*
+                })
+            }
+        }
+    };
+}
+
+macro_rules! persist_int {
+    ($variant:ident, $repr:ty, $($t:ty)*) => {
+        $(
+            impl Persist for $t {
+                fn save(&self) -> Value {
+                    Value::$variant(*self as $repr)
+                }
+                fn load(value: &Value) -> Result<Self, PersistError> {
+                    let n = match *value {
+                        Value::Int(n) => <$t>::try_from(n).ok(),
+                        Value::UInt(n) => <$t>::try_from(n).ok(),
+                        _ => return Err(PersistError::mismatch("integer", value)),
+                    };
+                    n.ok_or(PersistError::OutOfRange)
+                }
+            }
+        )*
+    };
+}
+
+persist_int!(Int, i64, i8 i16 i32 i64 isize);
+persist_int!(UInt, u64, u8 u16 u32 u64 usize);
+
+macro_rules! migrate_as_is {
+    ($($t:ident)*) => {
+        $(
+            impl Migrate for $t {}
+        )*
+    };
+}
+
+migrate_as_is!(i8 i16 i32 i64 isize u8 u16 u32 u64 usize f32 f64 bool char String Entity Value);
+
+impl Migrate for () {}
+
+impl Persist for f64 {
+    fn save(&self) -> Value {
+        Value::Float(*self)
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        match *value {
+            Value::Float(x) => Ok(x),
+            Value::Int(n) => Ok(n as f64),
+            Value::UInt(n) => Ok(n as f64),
+            _ => Err(PersistError::mismatch("float", value)),
+        }
+    }
+}
+
+impl Persist for f32 {
+    fn save(&self) -> Value {
+        Value::Float(f64::from(*self))
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        f64::load(value).map(|x| x as f32)
+    }
+}
+
+impl Persist for bool {
+    fn save(&self) -> Value {
+        Value::Bool(*self)
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        match *value {
+            Value::Bool(b) => Ok(b),
+            _ => Err(PersistError::mismatch("bool", value)),
+        }
+    }
+}
+
+impl Persist for char {
+    fn save(&self) -> Value {
+        Value::Str(self.to_string())
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        let s = String::load(value)?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(PersistError::Custom(format!(
+                "expected a single character, found {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl Persist for String {
+    fn save(&self) -> Value {
+        Value::Str(self.clone())
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        match value {
+            Value::Str(s) => Ok(s.clone()),
+            _ => Err(PersistError::mismatch("string", value)),
+        }
+    }
+}
+
+impl Persist for () {
+    fn save(&self) -> Value {
+        Value::Unit
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        match value {
+            Value::Unit => Ok(()),
+            _ => Err(PersistError::mismatch("unit", value)),
+        }
+    }
+}
+
+impl<T: Persist> Persist for Option<T> {
+    fn save(&self) -> Value {
+        match self {
+            Some(x) => Value::List(vec![x.save()]),
+            None => Value::Unit,
+        }
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        match value {
+            Value::Unit => Ok(None),
+            Value::List(items) if items.len() == 1 => T::load(&items[0]).map(Some),
+            _ => Err(PersistError::mismatch("option", value)),
+        }
+    }
+}
+
+impl<T: Migrate> Migrate for Option<T> {
+    fn migrate(version: u32, value: &Value) -> Result<Self, PersistError> {
+        match value {
+            Value::List(items) if items.len() == 1 => T::migrate(version, &items[0]).map(Some),
+            _ => Self::load(value),
+        }
+    }
+}
+
+impl<T: Persist> Persist for Box<T> {
+    fn save(&self) -> Value {
+        (**self).save()
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        T::load(value).map(Box::new)
+    }
+}
+
+impl<T: Migrate> Migrate for Box<T> {
+    fn migrate(version: u32, value: &Value) -> Result<Self, PersistError> {
+        T::migrate(version, value).map(Box::new)
+    }
+}
+
+impl<T: Persist> Persist for Vec<T> {
+    fn save(&self) -> Value {
+        Value::List(self.iter().map(Persist::save).collect())
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        value.as_list()?.iter().map(T::load).collect()
+    }
+}
+
+impl<T: Migrate> Migrate for Vec<T> {
+    fn migrate(version: u32, value: &Value) -> Result<Self, PersistError> {
+        value
+            .as_list()?
+            .iter()
+            .map(|v| T::migrate(version, v))
+            .collect()
+    }
+}
+
+/// Saved as a list of `[key, value]` pairs, in key order.
+impl<K: Persist + Ord, V: Persist> Persist for BTreeMap<K, V> {
+    fn save(&self) -> Value {
+        Value::List(
+            self.iter()
+                .map(|(k, v)| Value::List(vec![k.save(), v.save()]))
+                .collect(),
+        )
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        value.as_list()?.iter().map(<(K, V)>::load).collect()
+    }
+}
+
+impl<K: Migrate + Ord, V: Migrate> Migrate for BTreeMap<K, V> {
+    fn migrate(version: u32, value: &Value) -> Result<Self, PersistError> {
+        value
+            .as_list()?
+            .iter()
+            .map(|v| <(K, V)>::migrate(version, v))
+            .collect()
+    }
+}
+
+macro_rules! persist_tuple {
+    ($n:expr; $($t:ident $i:tt),*) => {
+        impl<$($t: Persist),*> Persist for ($($t,)*) {
+            fn save(&self) -> Value {
+                Value::List(vec![$(self.$i.save()),*])
+            }
+            fn load(value: &Value) -> Result<Self, PersistError> {
+                match value.as_list()? {
+                    items if items.len() == $n => Ok(($($t::load(&items[$i])?,)*)),
+                    _ => Err(PersistError::Custom(format!("expected a list of {} values", $n))),
+                }
+            }
+        }
+        impl<$($t: Migrate),*> Migrate for ($($t,)*) {
+            fn migrate(version: u32, value: &Value) -> Result<Self, PersistError> {
+                match value.as_list()? {
+                    items if items.len() == $n => Ok(($($t::migrate(version, &items[$i])?,)*)),
+                    _ => Self::load(value),
+                }
+            }
+        }
+    };
+}
+
+persist_tuple!(1; A 0);
+persist_tuple!(2; A 0, B 1);
+persist_tuple!(3; A 0, B 1, C 2);
+persist_tuple!(4; A 0, B 1, C 2, D 3);
+
+/// Saved as the result of `Entity::to_bits`.
+impl Persist for Entity {
+    fn save(&self) -> Value {
+        Value::UInt(self.to_bits())
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        Entity::from_bits(u64::load(value)?)
+            .ok_or_else(|| PersistError::Custom("entity generation is zero".to_string()))
+    }
+}
+
+/// A `Value` is saved as itself, which is handy for data that's loaded in stages.
+impl Persist for Value {
+    fn save(&self) -> Value {
+        self.clone()
+    }
+    fn load(value: &Value) -> Result<Self, PersistError> {
+        Ok(value.clone())
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recording which system panicked, so a world can still be inspected afterwards.
+//!
+//! When a system panics, the borrows of the storages and resources it was given are released as
+//! the panic unwinds, and the world's own bookkeeping (which system's `Local`s and access counts
+//! are in use) is put back, so the world stays usable. What the system had already done isn't
+//! undone, though: it might have been halfway through updating a component. So the world is
+//! marked as poisoned, with the system's name and (with the `std` feature) the panic message, and
+//! the panic carries on.
+//!
+//! A poisoned world can be read as usual, so a crash handler that catches the panic can look at
+//! [`WorldInterface::poison`](../traits/trait.WorldInterface.html#tymethod.poison) and save the
+//! world for a bug report. Running more systems on it works too, but may not make much sense.
+//! [`Poison::clear`](struct.Poison.html#method.clear) unmarks it, e.g. after a debug console has
+//! patched up the damage.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Debug, Default)]
+//! pub struct Health(u32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             health: BasicVecStorage<Health>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! struct Bleed;
+//! impl<'a> System<'a> for Bleed {
+//!     type Dependencies = (WriteComponent<'a, Health>,);
+//!     fn run(&'a mut self, (mut health,): Self::Dependencies) {
+//!         (&mut health,).for_each(|_, (h,)| {
+//!             h.0 = h.0.checked_sub(5).expect("health underflow");
+//!         });
+//!     }
+//! }
+//!
+//! let mut w = World::default();
+//! w.new_entity().with(Health(10)).build();
+//! w.new_entity().with(Health(3)).build();
+//!
+//! let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+//!     w.run_system(&mut Bleed);
+//! }));
+//! assert!(result.is_err());
+//!
+//! let poison = w.poison();
+//! assert_eq!(poison.system(), Some(std::any::type_name::<Bleed>()));
+//! assert_eq!(poison.message().as_deref(), Some("health underflow"));
+//! // The world can still be read, e.g. to save it for a bug report.
+//! let health: Vec<_> = w.component::<Health>().iter().flatten().map(|h| h.0).collect();
+//! assert_eq!(health, vec![5, 3]);
+//! ```
+
+use alloc::string::String;
+use core::cell::Cell;
+
+use crate::cell::WorldCell;
+
+/// The world's record of a system that panicked. See the
+/// [module-level documentation](index.html).
+#[derive(Clone, Debug, Default)]
+pub struct Poison {
+    system: Cell<Option<&'static str>>,
+    message: WorldCell<Option<String>>,
+}
+
+impl Poison {
+    /// Whether a system has panicked since the world was created or the record was cleared.
+    pub fn is_poisoned(&self) -> bool {
+        self.system.get().is_some()
+    }
+
+    /// The name of the system that panicked, if any. If more than one has, this is the first,
+    /// since the others may just be tripping over what it left behind.
+    pub fn system(&self) -> Option<&'static str> {
+        self.system.get()
+    }
+
+    /// The message the system panicked with, if it was a string. Only recorded with the `std`
+    /// feature.
+    pub fn message(&self) -> Option<String> {
+        self.message.borrow().clone()
+    }
+
+    /// Forget the panic, unmarking the world.
+    pub fn clear(&self) {
+        self.system.set(None);
+        *self.message.borrow_mut() = None;
+    }
+
+    fn set(&self, system: &'static str, message: Option<String>) {
+        if !self.is_poisoned() {
+            self.system.set(Some(system));
+            *self.message.borrow_mut() = message;
+        }
+    }
+
+    // Run the system `S` with `f`, recording it if it panics, then carrying on with the panic.
+    // The `run_*` methods of `WorldInterface` do this.
+    #[inline]
+    pub(crate) fn guard<S, R, F>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let system = core::any::type_name::<S>();
+        #[cfg(feature = "std")]
+        {
+            match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+                Ok(result) => result,
+                Err(payload) => {
+                    let message = payload
+                        .downcast_ref::<&str>()
+                        .map(|&s| String::from(s))
+                        .or_else(|| payload.downcast_ref::<String>().cloned());
+                    self.set(system, message);
+                    std::panic::resume_unwind(payload)
+                }
+            }
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            // Without `std` there's no catching the panic, but a guard that's still around when
+            // it's dropped can only be being dropped by one.
+            struct Armed<'p>(&'p Poison, &'static str);
+            impl Drop for Armed<'_> {
+                fn drop(&mut self) {
+                    self.0.set(self.1, None);
+                }
+            }
+            let armed = Armed(self, system);
+            let result = f();
+            core::mem::forget(armed);
+            result
+        }
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deterministic record/playback of a session.
+//!
+//! Systems are deterministic functions of the world state, so the only thing needed to reproduce a
+//! session is the sequence of external inputs that were fed into it. Inputs are injected via an
+//! [`InputEvents`](struct.InputEvents.html) resource declared in `define_world!`; a
+//! [`Replay`](struct.Replay.html) captures the contents of that resource once per frame, and can
+//! later re-drive a fresh world through the same frames.
+//!
+//! Anything nondeterministic (random number generators, wall clock time) must live in the world
+//! and be seeded/driven from inputs for playback to be exact.
+//!
+//! Both `record` and `playback` mark a frame in the world's [`Journal`](../journal/index.html), so
+//! with the `journal` feature enabled, the journal of a replayed session can be compared entry
+//! for entry against the original.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # #[derive(Default)]
+//! # pub struct Position(i32, i32);
+//! #[derive(Default)]
+//! pub struct Counter(u32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!         }
+//!         resources {
+//!             inputs: InputEvents<u32>,
+//!             counter: Counter,
+//!         }
+//!     }
+//! );
+//!
+//! struct Accumulate;
+//! impl<'a> System<'a> for Accumulate {
+//!     type Dependencies = (ReadResource<'a, InputEvents<u32>>, WriteResource<'a, Counter>);
+//!     fn run(&'a mut self, (inputs, mut counter): Self::Dependencies) {
+//!         counter.0 += inputs.iter().sum::<u32>();
+//!     }
+//! }
+//!
+//! let mut replay = Replay::new();
+//! let mut w = World::default();
+//! for i in 0..4 {
+//!     <World as GetResource<InputEvents<u32>>>::get_mut(&w).push(i);
+//!     replay.record(&w);
+//!     w.run_system(&mut Accumulate);
+//!     <World as GetResource<InputEvents<u32>>>::get_mut(&w).clear();
+//! }
+//!
+//! let mut w2 = World::default();
+//! replay.playback(&mut w2, |w| w.run_system(&mut Accumulate));
+//! assert_eq!(
+//!     <World as GetResource<Counter>>::get(&w).0,
+//!     <World as GetResource<Counter>>::get(&w2).0
+//! );
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// Resource holding the external inputs (key presses, network messages, ...) for the current
+/// frame.
+#[derive(Clone, Debug)]
+pub struct InputEvents<I> {
+    events: Vec<I>,
+}
+
+impl<I> Default for InputEvents<I> {
+    fn default() -> Self {
+        InputEvents { events: Vec::new() }
+    }
+}
+
+impl<I> InputEvents<I> {
+    /// Add an input to the current frame.
+    #[inline]
+    pub fn push(&mut self, event: I) {
+        self.events.push(event);
+    }
+
+    /// Remove all inputs. Call this at the end of each frame.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Iterate over the inputs for the current frame, in the order they were pushed.
+    #[inline]
+    pub fn iter(&self) -> core::slice::Iter<'_, I> {
+        self.events.iter()
+    }
+
+    /// Number of inputs in the current frame.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether there are no inputs in the current frame.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// A recording of the inputs a world received, one entry per frame.
+#[derive(Clone, Debug)]
+pub struct Replay<I> {
+    frames: Vec<Vec<I>>,
+}
+
+impl<I> Default for Replay<I> {
+    fn default() -> Self {
+        Replay { frames: Vec::new() }
+    }
+}
+
+impl<I: Clone> Replay<I> {
+    /// Create an empty recording.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Capture the world's current `InputEvents<I>` as the next frame.
+    ///
+    /// Call this once per frame, after the frame's inputs have been injected and before any
+    /// systems run.
+    pub fn record<W>(&mut self, world: &W)
+    where
+        W: GetResource<InputEvents<I>> + GetResource<Journal>,
+    {
+        <W as GetResource<Journal>>::get_mut(world).mark_frame();
+        let inputs = <W as GetResource<InputEvents<I>>>::get(world);
+        self.frames.push(inputs.events.clone());
+    }
+
+    /// Re-drive `world` through every recorded frame.
+    ///
+    /// Before each call to `frame`, the world's `InputEvents<I>` resource is replaced with the
+    /// inputs that were captured for that frame. `frame` should run the same systems, in the same
+    /// order, as the recorded session did.
+    pub fn playback<W, F>(&self, world: &mut W, mut frame: F)
+    where
+        W: GetResource<InputEvents<I>> + GetResource<Journal>,
+        F: FnMut(&mut W),
+    {
+        for inputs in self.frames.iter() {
+            <W as GetResource<Journal>>::get_mut(world).mark_frame();
+            <W as GetResource<InputEvents<I>>>::get_mut(world)
+                .events
+                .clone_from(inputs);
+            frame(world);
+        }
+    }
+
+    /// Number of recorded frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether no frames have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The inputs captured for frame `i`, if it was recorded.
+    pub fn frame(&self, i: usize) -> Option<&[I]> {
+        self.frames.get(i).map(|f| &f[..])
+    }
+}
@@ -0,0 +1,1063 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Static schedules, partitioned into stages of systems that don't conflict.
+//!
+//! [`define_schedule!`](../macro.define_schedule.html) declares a struct holding a fixed list of
+//! systems. The systems' `Dependencies` determine what each of them reads and writes, and from
+//! that the schedule is split into stages: no system in a stage conflicts with the others in the
+//! same stage, and a system that conflicts with an earlier one is always placed in a later stage
+//! than it. Running the stages in order gives the same result as running the systems in the order
+//! they were declared. Worlds are single-threaded, so `run` still runs the systems in a stage one
+//! after another; the stages say which systems are independent of each other.
+//!
+//! The stages depend only on the system types and the world, so they are computed at compile time.
+//! Stable Rust can't compare types in constant expressions, so `define_world!` numbers each
+//! world's components and resources (see [`ComponentIndex`](trait.ComponentIndex.html)), and the
+//! stages for a world come from the systems' [`ConstAccess`](trait.ConstAccess.html) in it. A
+//! world-independent copy, computed from the systems' `TypeId`s the first time it's needed, is
+//! available for inspecting the schedule without a world.
+//!
+//! To see why the stages came out the way they did, a schedule's `to_dot()` describes it as a
+//! Graphviz graph: the systems in their stages, the components and resources each reads and
+//! writes, and the conflicts between systems. Render it with e.g. `dot -Tsvg`.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Position(i32);
+//! #[derive(Default)]
+//! pub struct Velocity(i32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!             velocities: BasicVecStorage<Velocity>,
+//!         }
+//!         resources {
+//!             gravity: i32,
+//!         }
+//!     }
+//! );
+//!
+//! pub struct Fall;
+//! impl<'a> System<'a> for Fall {
+//!     type Dependencies = (ReadResource<'a, i32>, WriteComponent<'a, Velocity>);
+//!     fn run(&'a mut self, (gravity, mut vel): Self::Dependencies) {
+//!         (&mut vel,).for_each(|_, (v,)| v.0 -= *gravity);
+//!     }
+//! }
+//!
+//! pub struct Move;
+//! impl<'a> System<'a> for Move {
+//!     type Dependencies = (ReadComponent<'a, Velocity>, WriteComponent<'a, Position>);
+//!     fn run(&'a mut self, (vel, mut pos): Self::Dependencies) {
+//!         (&vel, &mut pos).for_each(|_, (v, p)| p.0 += v.0);
+//!     }
+//! }
+//!
+//! pub struct Lowest(i32);
+//! impl<'a> System<'a> for Lowest {
+//!     type Dependencies = (ReadComponent<'a, Position>,);
+//!     fn run(&'a mut self, (pos,): Self::Dependencies) {
+//!         (&pos,).for_each(|_, (p,)| self.0 = self.0.min(p.0));
+//!     }
+//! }
+//!
+//! pub struct Count(usize);
+//! impl<'a> System<'a> for Count {
+//!     type Dependencies = (ReadComponent<'a, Velocity>,);
+//!     fn run(&'a mut self, (vel,): Self::Dependencies) {
+//!         self.0 = 0;
+//!         (&vel,).for_each(|_, _| self.0 += 1);
+//!     }
+//! }
+//!
+//! define_schedule!(
+//!     pub schedule Physics {
+//!         fall: Fall,
+//!         movement: Move,
+//!         lowest: Lowest,
+//!         count: Count,
+//!     }
+//! );
+//!
+//! // `Move` and `Count` both need `Fall` to have finished, but not each other.
+//! assert_eq!(Physics::stages(), &[vec![0], vec![1, 3], vec![2]][..]);
+//! // The stage of each system, computed at compile time for `World`.
+//! const STAGE_OF: [usize; 4] = Physics::stage_of::<World>();
+//! assert_eq!(STAGE_OF, [0, 1, 2, 1]);
+//!
+//! let mut w = World::default();
+//! w.set(1);
+//! let e = w.new_entity().with(Position(10)).with(Velocity(0)).build();
+//!
+//! let mut physics = Physics {
+//!     fall: Fall,
+//!     movement: Move,
+//!     lowest: Lowest(0),
+//!     count: Count(0),
+//! };
+//! physics.run(&mut w).unwrap();
+//! physics.run(&mut w).unwrap();
+//! assert_eq!(physics.lowest.0, 0);
+//! assert_eq!(physics.count.0, 1);
+//! # let pos = <World as GetComponent<'_, Position>>::get(&w);
+//! # assert_eq!(pos.get(e).unwrap().0, 7);
+//! ```
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::any::TypeId;
+use core::fmt;
+
+use crate::*;
+
+/// Something a system can read or write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AccessKey {
+    /// The storage for the component type with the given id.
+    Component(TypeId),
+    /// The resource type with the given id.
+    Resource(TypeId),
+}
+
+/// The set of things a system reads and writes.
+///
+/// The keys are kept sorted, so the order dependencies are declared in makes no difference:
+/// systems depending on `(A, B)` and `(B, A)` have equal access sets.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    reads: Vec<AccessKey>,
+    writes: Vec<AccessKey>,
+    restricted_writes: Vec<AccessKey>,
+}
+
+impl Access {
+    /// Create an empty access set.
+    pub fn new() -> Self {
+        Access::default()
+    }
+
+    /// Work out what `S` accesses from its `Dependencies`.
+    pub fn of<S>() -> Self
+    where
+        S: OutputSystem<'static>,
+        <S::Dependencies as Nest>::Nested: DependencyAccess,
+    {
+        let mut access = Access::new();
+        <<S::Dependencies as Nest>::Nested as DependencyAccess>::record(&mut access);
+        access
+    }
+
+    /// Record a read of `key`.
+    pub fn read(&mut self, key: AccessKey) {
+        insert_sorted(&mut self.reads, key);
+    }
+
+    /// Record a write to `key`.
+    pub fn write(&mut self, key: AccessKey) {
+        insert_sorted(&mut self.writes, key);
+    }
+
+    /// Record a write to `key` that only touches the entities the system joins over. See
+    /// `RestrictedWriteComponent`.
+    pub fn write_restricted(&mut self, key: AccessKey) {
+        insert_sorted(&mut self.restricted_writes, key);
+    }
+
+    /// Everything read (but not necessarily written), in sorted order.
+    pub fn reads(&self) -> &[AccessKey] {
+        &self.reads
+    }
+
+    /// Everything written without restriction, in sorted order.
+    pub fn writes(&self) -> &[AccessKey] {
+        &self.writes
+    }
+
+    /// Everything written only for the entities being joined over, in sorted order.
+    pub fn restricted_writes(&self) -> &[AccessKey] {
+        &self.restricted_writes
+    }
+
+    /// Whether a system with this access set can't run at the same time as one with `other`,
+    /// i.e., whether either writes something the other uses.
+    pub fn conflicts_with(&self, other: &Access) -> bool {
+        self.conflicts(other, false)
+    }
+
+    /// Like `conflicts_with`, but for systems whose joins are known never to visit the same
+    /// entity, so restricted writes to the same component don't conflict with each other.
+    pub fn conflicts_with_disjoint(&self, other: &Access) -> bool {
+        self.conflicts(other, true)
+    }
+
+    fn uses(&self, key: &AccessKey) -> bool {
+        self.reads.contains(key)
+            || self.writes.contains(key)
+            || self.restricted_writes.contains(key)
+    }
+
+    fn conflicts(&self, other: &Access, disjoint: bool) -> bool {
+        self.writes.iter().any(|k| other.uses(k))
+            || other.writes.iter().any(|k| self.uses(k))
+            || self.restricted_writes.iter().any(|k| {
+                other.reads.contains(k) || (!disjoint && other.restricted_writes.contains(k))
+            })
+            || other
+                .restricted_writes
+                .iter()
+                .any(|k| self.reads.contains(k))
+    }
+}
+
+fn insert_sorted(keys: &mut Vec<AccessKey>, key: AccessKey) {
+    if let Err(i) = keys.binary_search(&key) {
+        keys.insert(i, key);
+    }
+}
+
+/// How a dependency uses a component or resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Usage {
+    /// It's only read.
+    Read,
+    /// It's written.
+    Write,
+    /// It's written, but only for the entities being joined over. See `RestrictedWriteComponent`.
+    RestrictedWrite,
+}
+
+impl Usage {
+    /// Whether the storage or resource has to be borrowed mutably.
+    pub fn is_write(self) -> bool {
+        self != Usage::Read
+    }
+}
+
+/// A component or resource used by one of the dependencies in a tuple.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DependencyUse {
+    /// What's used.
+    pub key: AccessKey,
+    /// How it's used.
+    pub usage: Usage,
+    /// The name of the component or resource type, for error messages.
+    pub name: &'static str,
+}
+
+impl DependencyUse {
+    pub(crate) fn of<H: 'static>(key: fn(TypeId) -> AccessKey, usage: Usage) -> Self {
+        DependencyUse {
+            key: key(TypeId::of::<H>()),
+            usage,
+            name: core::any::type_name::<H>(),
+        }
+    }
+}
+
+/// Describes what a (nested) dependency tuple accesses.
+///
+/// This is implemented for every kind of dependency a system can declare.
+pub trait DependencyAccess {
+    /// List the components and resources each dependency uses, in the order they're declared.
+    /// Something used by more than one dependency is listed once for each.
+    fn uses(uses: &mut Vec<DependencyUse>);
+
+    /// Add the accesses to `access`.
+    fn record(access: &mut Access) {
+        let mut uses = Vec::new();
+        Self::uses(&mut uses);
+        for u in uses {
+            match u.usage {
+                Usage::Read => access.read(u.key),
+                Usage::Write => access.write(u.key),
+                Usage::RestrictedWrite => access.write_restricted(u.key),
+            }
+        }
+    }
+
+    /// Find the first component or resource that one of the dependencies borrows mutably and
+    /// another uses at the same time, which would make fetching them all at once panic.
+    fn conflict() -> Option<FetchConflict> {
+        Self::fetch_conflict(|_, _| true)
+    }
+
+    /// Like `conflict`, but also finds the first component or resource that `can_borrow` says
+    /// can't be borrowed the way a dependency uses it (mutably if the `bool` is `true`), e.g.
+    /// because it's already borrowed outside the fetch.
+    fn fetch_conflict(can_borrow: impl Fn(AccessKey, bool) -> bool) -> Option<FetchConflict> {
+        let mut uses = Vec::new();
+        Self::uses(&mut uses);
+        let twice = uses.iter().enumerate().find_map(|(i, u)| {
+            uses[..i]
+                .iter()
+                .find(|v| v.key == u.key && (u.usage.is_write() || v.usage.is_write()))
+                .map(|_| FetchConflict {
+                    key: u.key,
+                    name: u.name,
+                    already_borrowed: false,
+                })
+        });
+        twice.or_else(|| {
+            uses.iter()
+                .find(|u| !can_borrow(u.key, u.usage.is_write()))
+                .map(|u| FetchConflict {
+                    key: u.key,
+                    name: u.name,
+                    already_borrowed: true,
+                })
+        })
+    }
+}
+
+/// Error returned by the `fetch` method of worlds defined with `define_world!` when the
+/// dependencies asked for can't all be borrowed at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FetchConflict {
+    /// The component or resource that can't be borrowed.
+    pub key: AccessKey,
+    /// The name of its type.
+    pub name: &'static str,
+    /// Whether it's already borrowed outside the fetch, rather than asked for by two of the
+    /// dependencies.
+    pub already_borrowed: bool,
+}
+
+impl fmt::Display for FetchConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.key {
+            AccessKey::Component(_) => "component",
+            AccessKey::Resource(_) => "resource",
+        };
+        if self.already_borrowed {
+            write!(f, "{} `{}` is already borrowed", kind, self.name)
+        } else {
+            write!(
+                f,
+                "{} `{}` is borrowed mutably by one dependency while another uses it",
+                kind, self.name
+            )
+        }
+    }
+}
+
+impl core::error::Error for FetchConflict {}
+
+impl DependencyAccess for () {
+    fn uses(_uses: &mut Vec<DependencyUse>) {}
+}
+
+impl<'a, H, T> DependencyAccess for (ReadComponent<'a, H>, T)
+where
+    H: 'static + StorageSpec<'a>,
+    T: DependencyAccess,
+{
+    fn uses(uses: &mut Vec<DependencyUse>) {
+        uses.push(DependencyUse::of::<H>(AccessKey::Component, Usage::Read));
+        T::uses(uses);
+    }
+}
+
+impl<'a, H, T> DependencyAccess for (WriteComponent<'a, H>, T)
+where
+    H: 'static + StorageSpec<'a>,
+    T: DependencyAccess,
+{
+    fn uses(uses: &mut Vec<DependencyUse>) {
+        uses.push(DependencyUse::of::<H>(AccessKey::Component, Usage::Write));
+        T::uses(uses);
+    }
+}
+
+impl<'a, H, T> DependencyAccess for (RestrictedWriteComponent<'a, H>, T)
+where
+    H: 'static + StorageSpec<'a>,
+    T: DependencyAccess,
+{
+    fn uses(uses: &mut Vec<DependencyUse>) {
+        uses.push(DependencyUse::of::<H>(
+            AccessKey::Component,
+            Usage::RestrictedWrite,
+        ));
+        T::uses(uses);
+    }
+}
+
+impl<'a, H, T> DependencyAccess for (ReadResource<'a, H>, T)
+where
+    H: 'static,
+    T: DependencyAccess,
+{
+    fn uses(uses: &mut Vec<DependencyUse>) {
+        uses.push(DependencyUse::of::<H>(AccessKey::Resource, Usage::Read));
+        T::uses(uses);
+    }
+}
+
+impl<'a, H, T> DependencyAccess for (WriteResource<'a, H>, T)
+where
+    H: 'static,
+    T: DependencyAccess,
+{
+    fn uses(uses: &mut Vec<DependencyUse>) {
+        uses.push(DependencyUse::of::<H>(AccessKey::Resource, Usage::Write));
+        T::uses(uses);
+    }
+}
+
+// Locals belong to a single system, so they can't conflict with anything.
+impl<'a, H, T> DependencyAccess for (Local<'a, H>, T)
+where
+    T: DependencyAccess,
+{
+    fn uses(uses: &mut Vec<DependencyUse>) {
+        T::uses(uses);
+    }
+}
+
+// Deferred changes are applied after the system has finished, when nothing else is running.
+impl<'a, W, T> DependencyAccess for (Deferred<'a, W>, T)
+where
+    T: DependencyAccess,
+{
+    fn uses(uses: &mut Vec<DependencyUse>) {
+        T::uses(uses);
+    }
+}
+
+// Tasks only borrow what the system hands them, which it has already declared.
+impl<T> DependencyAccess for (Tasks, T)
+where
+    T: DependencyAccess,
+{
+    fn uses(uses: &mut Vec<DependencyUse>) {
+        T::uses(uses);
+    }
+}
+
+// A group of dependencies accesses everything in it.
+impl<G, T> DependencyAccess for (Group<G>, T)
+where
+    G: Nest,
+    G::Nested: DependencyAccess,
+    T: DependencyAccess,
+{
+    fn uses(uses: &mut Vec<DependencyUse>) {
+        G::Nested::uses(uses);
+        T::uses(uses);
+    }
+}
+
+/// Numbers the component types of a world, so that what systems access can be compared in constant
+/// expressions. Implemented by `define_world!` for each component.
+pub trait ComponentIndex<T> {
+    /// The component's index, counting from zero in the order the components were declared.
+    const INDEX: usize;
+}
+
+/// Numbers the resource types of a world. Implemented by `define_world!` for each resource. See
+/// `ComponentIndex`.
+pub trait ResourceIndex<T> {
+    /// The resource's index, counting from zero in the order the resources were declared.
+    const INDEX: usize;
+}
+
+/// The set of things a system reads and writes in a particular world, as bit sets indexed by
+/// `ComponentIndex` and `ResourceIndex`. Unlike `Access`, it can be worked with in constant
+/// expressions, which is how `define_schedule!` computes a schedule's stages at compile time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AccessBits {
+    /// The components read.
+    pub component_reads: u128,
+    /// The components written without restriction.
+    pub component_writes: u128,
+    /// The components written only for the entities being joined over.
+    pub component_restricted_writes: u128,
+    /// The resources read.
+    pub resource_reads: u128,
+    /// The resources written.
+    pub resource_writes: u128,
+}
+
+impl AccessBits {
+    /// Nothing accessed.
+    pub const EMPTY: AccessBits = AccessBits {
+        component_reads: 0,
+        component_writes: 0,
+        component_restricted_writes: 0,
+        resource_reads: 0,
+        resource_writes: 0,
+    };
+
+    /// Everything in `self` or `other`.
+    pub const fn union(self, other: AccessBits) -> AccessBits {
+        AccessBits {
+            component_reads: self.component_reads | other.component_reads,
+            component_writes: self.component_writes | other.component_writes,
+            component_restricted_writes: self.component_restricted_writes
+                | other.component_restricted_writes,
+            resource_reads: self.resource_reads | other.resource_reads,
+            resource_writes: self.resource_writes | other.resource_writes,
+        }
+    }
+
+    /// Whether a system with this access set can't run at the same time as one with `other`.
+    /// See `Access::conflicts_with` and `Access::conflicts_with_disjoint`.
+    pub const fn conflicts_with(&self, other: &AccessBits, disjoint: bool) -> bool {
+        let (a, b) = (self, other);
+        let a_uses = a.component_reads | a.component_writes | a.component_restricted_writes;
+        let b_uses = b.component_reads | b.component_writes | b.component_restricted_writes;
+        let restricted = if disjoint {
+            0
+        } else {
+            a.component_restricted_writes & b.component_restricted_writes
+        };
+        a.component_writes & b_uses != 0
+            || b.component_writes & a_uses != 0
+            || a.component_restricted_writes & b.component_reads != 0
+            || b.component_restricted_writes & a.component_reads != 0
+            || restricted != 0
+            || a.resource_writes & (b.resource_reads | b.resource_writes) != 0
+            || b.resource_writes & (a.resource_reads | a.resource_writes) != 0
+    }
+}
+
+// The bit for index `i` in an `AccessBits` set.
+const fn bit(i: usize) -> u128 {
+    assert!(
+        i < 128,
+        "schedules can only be computed for worlds with at most 128 components and 128 resources"
+    );
+    1 << i
+}
+
+/// What a (nested) dependency tuple accesses in the world `W`. This is the compile-time
+/// counterpart of `DependencyAccess`, and is implemented for the same dependencies.
+pub trait ConstAccess<W> {
+    /// Everything the dependencies access.
+    const ACCESS: AccessBits;
+}
+
+impl<W> ConstAccess<W> for () {
+    const ACCESS: AccessBits = AccessBits::EMPTY;
+}
+
+impl<'a, W, H, T> ConstAccess<W> for (ReadComponent<'a, H>, T)
+where
+    H: StorageSpec<'a>,
+    W: ComponentIndex<H>,
+    T: ConstAccess<W>,
+{
+    const ACCESS: AccessBits = AccessBits {
+        component_reads: T::ACCESS.component_reads | bit(W::INDEX),
+        ..T::ACCESS
+    };
+}
+
+impl<'a, W, H, T> ConstAccess<W> for (WriteComponent<'a, H>, T)
+where
+    H: StorageSpec<'a>,
+    W: ComponentIndex<H>,
+    T: ConstAccess<W>,
+{
+    const ACCESS: AccessBits = AccessBits {
+        component_writes: T::ACCESS.component_writes | bit(W::INDEX),
+        ..T::ACCESS
+    };
+}
+
+impl<'a, W, H, T> ConstAccess<W> for (RestrictedWriteComponent<'a, H>, T)
+where
+    H: StorageSpec<'a>,
+    W: ComponentIndex<H>,
+    T: ConstAccess<W>,
+{
+    const ACCESS: AccessBits = AccessBits {
+        component_restricted_writes: T::ACCESS.component_restricted_writes | bit(W::INDEX),
+        ..T::ACCESS
+    };
+}
+
+impl<'a, W, H, T> ConstAccess<W> for (ReadResource<'a, H>, T)
+where
+    W: ResourceIndex<H>,
+    T: ConstAccess<W>,
+{
+    const ACCESS: AccessBits = AccessBits {
+        resource_reads: T::ACCESS.resource_reads | bit(W::INDEX),
+        ..T::ACCESS
+    };
+}
+
+impl<'a, W, H, T> ConstAccess<W> for (WriteResource<'a, H>, T)
+where
+    W: ResourceIndex<H>,
+    T: ConstAccess<W>,
+{
+    const ACCESS: AccessBits = AccessBits {
+        resource_writes: T::ACCESS.resource_writes | bit(W::INDEX),
+        ..T::ACCESS
+    };
+}
+
+impl<'a, W, H, T> ConstAccess<W> for (Local<'a, H>, T)
+where
+    T: ConstAccess<W>,
+{
+    const ACCESS: AccessBits = T::ACCESS;
+}
+
+impl<'a, W, V, T> ConstAccess<W> for (Deferred<'a, V>, T)
+where
+    T: ConstAccess<W>,
+{
+    const ACCESS: AccessBits = T::ACCESS;
+}
+
+impl<W, T> ConstAccess<W> for (Tasks, T)
+where
+    T: ConstAccess<W>,
+{
+    const ACCESS: AccessBits = T::ACCESS;
+}
+
+impl<W, G, T> ConstAccess<W> for (Group<G>, T)
+where
+    G: Nest,
+    G::Nested: ConstAccess<W>,
+    T: ConstAccess<W>,
+{
+    const ACCESS: AccessBits = G::Nested::ACCESS.union(T::ACCESS);
+}
+
+// Whether two strings are equal, in a constant expression.
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// `partition_with`, in a constant expression: the stage each of the `N` systems with the given
+/// access sets is placed in. `names` are the systems' names, and `disjoint` the pairs of names
+/// declared disjoint. Used by `define_schedule!`.
+pub const fn stage_indices<const N: usize>(
+    accesses: &[AccessBits; N],
+    names: &[&str; N],
+    disjoint: &[(&str, &str)],
+) -> [usize; N] {
+    let mut stage_of = [0; N];
+    let mut i = 0;
+    while i < N {
+        let mut j = 0;
+        while j < i {
+            let mut is_disjoint = false;
+            let mut k = 0;
+            while k < disjoint.len() {
+                let (l, r) = disjoint[k];
+                if (str_eq(l, names[i]) && str_eq(r, names[j]))
+                    || (str_eq(l, names[j]) && str_eq(r, names[i]))
+                {
+                    is_disjoint = true;
+                }
+                k += 1;
+            }
+            if accesses[i].conflicts_with(&accesses[j], is_disjoint)
+                && stage_of[j] + 1 > stage_of[i]
+            {
+                stage_of[i] = stage_of[j] + 1;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    stage_of
+}
+
+/// Split systems into stages. Each system is placed in the stage after the last one containing an
+/// earlier system it conflicts with. Returns, for each stage, the indices of the systems in it.
+pub fn partition(accesses: &[Access]) -> Vec<Vec<usize>> {
+    partition_with(accesses, |_, _| false)
+}
+
+/// Like `partition`, but `disjoint(i, j)` says whether the joins of systems `i` and `j` (with
+/// `j < i`) never visit the same entity.
+pub fn partition_with<F>(accesses: &[Access], disjoint: F) -> Vec<Vec<usize>>
+where
+    F: Fn(usize, usize) -> bool,
+{
+    let mut stages: Vec<Vec<usize>> = Vec::new();
+    let mut stage_of = Vec::with_capacity(accesses.len());
+    for (i, access) in accesses.iter().enumerate() {
+        let stage = (0..i)
+            .filter(|&j| {
+                if disjoint(i, j) {
+                    access.conflicts_with_disjoint(&accesses[j])
+                } else {
+                    access.conflicts_with(&accesses[j])
+                }
+            })
+            .map(|j| stage_of[j] + 1)
+            .max()
+            .unwrap_or(0);
+        if stage == stages.len() {
+            stages.push(Vec::new());
+        }
+        stages[stage].push(i);
+        stage_of.push(stage);
+    }
+    stages
+}
+
+/// List the components and resources `S` uses, from its `Dependencies`, in the order they're
+/// declared. See `DependencyAccess::uses`.
+pub fn uses_of<S>() -> Vec<DependencyUse>
+where
+    S: OutputSystem<'static>,
+    <S::Dependencies as Nest>::Nested: DependencyAccess,
+{
+    let mut uses = Vec::new();
+    <<S::Dependencies as Nest>::Nested as DependencyAccess>::uses(&mut uses);
+    uses
+}
+
+/// Describe a schedule as a [Graphviz](https://graphviz.org) graph in the DOT language. This is
+/// what the `to_dot` function generated by `define_schedule!` returns.
+///
+/// `systems` names the systems, `uses` lists what each of them uses (see `uses_of`) and
+/// `disjoint` is as for `partition_with`. The systems are grouped into their stages, with an edge
+/// from each system to the components and resources it writes, an edge to each system from the
+/// ones it reads, and a bold edge from each system to every later one it conflicts with, which is
+/// what forces them into different stages.
+pub fn to_dot<F>(name: &str, systems: &[&str], uses: &[Vec<DependencyUse>], disjoint: F) -> String
+where
+    F: Fn(usize, usize) -> bool,
+{
+    let accesses: Vec<Access> = uses
+        .iter()
+        .map(|uses| {
+            let mut access = Access::new();
+            for u in uses {
+                match u.usage {
+                    Usage::Read => access.read(u.key),
+                    Usage::Write => access.write(u.key),
+                    Usage::RestrictedWrite => access.write_restricted(u.key),
+                }
+            }
+            access
+        })
+        .collect();
+    let stages = partition_with(&accesses, &disjoint);
+
+    // Each component and resource gets one node, in the order they're first used.
+    let mut data: Vec<&DependencyUse> = Vec::new();
+    for u in uses.iter().flatten() {
+        if !data.iter().any(|d| d.key == u.key) {
+            data.push(u);
+        }
+    }
+
+    let mut dot = String::new();
+    // Writing to a `String` can't fail.
+    let _ = write_dot(
+        &mut dot, name, systems, uses, &accesses, &stages, &data, disjoint,
+    );
+    dot
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_dot<F>(
+    dot: &mut String,
+    name: &str,
+    systems: &[&str],
+    uses: &[Vec<DependencyUse>],
+    accesses: &[Access],
+    stages: &[Vec<usize>],
+    data: &[&DependencyUse],
+    disjoint: F,
+) -> fmt::Result
+where
+    F: Fn(usize, usize) -> bool,
+{
+    use core::fmt::Write;
+
+    writeln!(dot, "digraph \"{}\" {{", escape(name))?;
+    writeln!(dot, "    rankdir=LR;")?;
+    for (n, stage) in stages.iter().enumerate() {
+        writeln!(dot, "    subgraph cluster_stage{} {{", n)?;
+        writeln!(dot, "        label=\"stage {}\";", n)?;
+        for &i in stage {
+            writeln!(
+                dot,
+                "        system{} [label=\"{}\", shape=box];",
+                i,
+                escape(systems[i])
+            )?;
+        }
+        writeln!(dot, "    }}")?;
+    }
+    for (n, d) in data.iter().enumerate() {
+        let shape = match d.key {
+            AccessKey::Component(_) => "ellipse",
+            AccessKey::Resource(_) => "diamond",
+        };
+        writeln!(
+            dot,
+            "    data{} [label=\"{}\", shape={}];",
+            n,
+            escape(d.name),
+            shape
+        )?;
+    }
+    for (i, uses) in uses.iter().enumerate() {
+        for u in uses {
+            let n = data.iter().position(|d| d.key == u.key).unwrap_or(0);
+            match u.usage {
+                Usage::Read => writeln!(dot, "    data{} -> system{};", n, i)?,
+                Usage::Write => writeln!(dot, "    system{} -> data{};", i, n)?,
+                Usage::RestrictedWrite => writeln!(
+                    dot,
+                    "    system{} -> data{} [style=dashed, label=\"joined\"];",
+                    i, n
+                )?,
+            }
+        }
+    }
+    for (i, access) in accesses.iter().enumerate() {
+        for (j, earlier) in accesses[..i].iter().enumerate() {
+            let conflicts = if disjoint(i, j) {
+                access.conflicts_with_disjoint(earlier)
+            } else {
+                access.conflicts_with(earlier)
+            };
+            if conflicts {
+                writeln!(
+                    dot,
+                    "    system{} -> system{} [style=bold, color=red, label=\"before\"];",
+                    j, i
+                )?;
+            }
+        }
+    }
+    writeln!(dot, "}}")
+}
+
+// Escape `s` for a quoted DOT string.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Used by `define_schedule!`.
+#[doc(hidden)]
+pub fn run_scheduled<W, S>(system: &mut S, world: &mut W) -> Result<(), SystemError>
+where
+    S: RunSystem<W, Input = ()>,
+    S::Output: IntoSystemResult,
+{
+    system
+        .run_on(world, ())
+        .into_system_result()
+        .map_err(|error| SystemError::new(system.name(), error))
+}
+
+/// Declare a schedule: a struct holding a fixed list of systems, along with the stages they can
+/// be run in. See the [`schedule`](schedule/index.html) module.
+///
+/// ```ignore
+/// define_schedule!(
+///     pub schedule Name {
+///         field_name: SystemType,
+///         ...
+///     }
+///     // Optional: pairs of systems whose joins never visit the same entity.
+///     disjoint {
+///         field_name, other_field_name;
+///         ...
+///     }
+/// );
+/// ```
+///
+/// Systems listed as disjoint can share a stage even if both have a `RestrictedWriteComponent`
+/// for the same component. Nothing checks that their joins really are disjoint, so only list
+/// systems that, e.g., require mutually exclusive marker components.
+///
+/// The generated struct has a public field for each system, and provides:
+///
+/// * `SYSTEMS`, the field names, in the order they were declared.
+/// * `stage_of::<W>()`, a `const fn` giving the stage of each system when run on a `W`.
+/// * `stages()`, the indices into `SYSTEMS` of the systems in each stage, computed on first use.
+/// * `to_dot()`, a Graphviz graph of the systems, what they use, and the stages they're in.
+/// * `run(&mut self, world)`, which runs every system, stage by stage, and stops at the first one
+///   that fails, like `Dispatcher::run`. The systems in a stage run one after another, and the
+///   changes they queue with a `Deferred` dependency are applied at the end of the stage.
+///
+/// It also implements `RunSystem`, so the whole schedule can be added to a `Dispatcher` as a
+/// single system.
+#[macro_export]
+macro_rules! define_schedule {
+    (
+        $(#[$meta:meta])*
+        $v:vis schedule $name:ident {
+            $($field:ident : $system:ty),* $(,)*
+        }
+        $(disjoint {
+            $($left:ident, $right:ident);* $(;)*
+        })*
+    ) => {
+        $(#[$meta])*
+        $v struct $name {
+            $(
+                #[allow(missing_docs)]
+                pub $field: $system,
+            )*
+        }
+
+        impl $name {
+            /// The names of the systems in this schedule, in the order they were declared.
+            #[allow(dead_code)]
+            pub const SYSTEMS: &'static [&'static str] = &[$(stringify!($field)),*];
+
+            /// The stage each system in `SYSTEMS` is in when the schedule runs on a `W`. This is a
+            /// `const fn`, which `run` evaluates at compile time; the stages are the same as the
+            /// ones `stages()` lists.
+            #[allow(dead_code)]
+            pub const fn stage_of<W>() -> [usize; Self::SYSTEMS.len()]
+            where
+                $(
+                    <<$system as $crate::OutputSystem<'static>>::Dependencies as $crate::Nest>::Nested:
+                        $crate::ConstAccess<W>,
+                )*
+            {
+                $crate::schedule::stage_indices(
+                    &[$(
+                        <<<$system as $crate::OutputSystem<'static>>::Dependencies as $crate::Nest>::Nested
+                            as $crate::ConstAccess<W>>::ACCESS
+                    ),*],
+                    &[$(stringify!($field)),*],
+                    &[$($((stringify!($left), stringify!($right))),*),*],
+                )
+            }
+
+            /// The stages of this schedule, for any world. Each stage lists the indices into
+            /// `SYSTEMS` of the systems in it. They're worked out from the systems' types the
+            /// first time they're needed, rather than at compile time like `stage_of`, because
+            /// that needs the world's numbering of its components and resources.
+            #[allow(dead_code)]
+            pub fn stages() -> &'static [$crate::__private::Vec<usize>] {
+                static STAGES: $crate::__private::OnceLock<
+                    $crate::__private::Vec<$crate::__private::Vec<usize>>,
+                > = $crate::__private::OnceLock::new();
+                STAGES.get_or_init(|| {
+                    $crate::schedule::partition_with(
+                        &[$($crate::Access::of::<$system>()),*],
+                        Self::__disjoint,
+                    )
+                })
+            }
+
+            /// This schedule as a Graphviz graph of its systems, stage by stage, the components
+            /// and resources they read and write, and the conflicts that order them. See
+            /// `schedule::to_dot`.
+            #[allow(dead_code)]
+            pub fn to_dot() -> $crate::__private::String {
+                $crate::schedule::to_dot(
+                    stringify!($name),
+                    Self::SYSTEMS,
+                    &[$($crate::schedule::uses_of::<$system>()),*],
+                    Self::__disjoint,
+                )
+            }
+
+            // Whether systems `i` and `j` were declared disjoint.
+            #[allow(dead_code)]
+            fn __disjoint(i: usize, j: usize) -> bool {
+                let disjoint: &[(&str, &str)] =
+                    &[$($((stringify!($left), stringify!($right))),*),*];
+                let (a, b) = (Self::SYSTEMS[i], Self::SYSTEMS[j]);
+                disjoint.iter().any(|&p| p == (a, b) || p == (b, a))
+            }
+
+            /// Run every system in the schedule, stage by stage, using the stages computed at
+            /// compile time for `W` (see `stage_of`). Worlds are single-threaded, so the systems
+            /// in a stage run one after another, in the order they were declared. Changes queued
+            /// with a `Deferred` dependency are applied at the end of each stage, so the systems in
+            /// a stage don't see each other's.
+            #[allow(dead_code)]
+            pub fn run<W>(&mut self, world: &mut W) -> Result<(), $crate::SystemError>
+            where
+                W: for<'a> $crate::WorldInterface<'a>,
+                $(
+                    $system: $crate::RunSystem<W, Input = ()>,
+                    <$system as $crate::RunSystem<W>>::Output: $crate::IntoSystemResult,
+                    <<$system as $crate::OutputSystem<'static>>::Dependencies as $crate::Nest>::Nested:
+                        $crate::ConstAccess<W>,
+                )*
+            {
+                let stage_of = const { Self::stage_of::<W>() };
+                let $name { $($field),* } = self;
+                $(
+                    let mut $field = |world: &mut W| $crate::schedule::run_scheduled($field, world);
+                )*
+                let systems: &mut [&mut dyn FnMut(&mut W) -> Result<(), $crate::SystemError>] =
+                    &mut [$(&mut $field),*];
+                let stages = stage_of.iter().max().map_or(0, |&last| last + 1);
+                for stage in 0..stages {
+                    let result = (0..systems.len())
+                        .filter(|&i| stage_of[i] == stage)
+                        .try_for_each(|i| (systems[i])(world));
+                    world.apply_deferred();
+                    result?;
+                }
+                Ok(())
+            }
+        }
+
+        impl<W> $crate::RunSystem<W> for $name
+        where
+            W: for<'a> $crate::WorldInterface<'a>,
+            $(
+                $system: $crate::RunSystem<W, Input = ()>,
+                <$system as $crate::RunSystem<W>>::Output: $crate::IntoSystemResult,
+                <<$system as $crate::OutputSystem<'static>>::Dependencies as $crate::Nest>::Nested:
+                    $crate::ConstAccess<W>,
+            )*
+        {
+            type Input = ();
+            type Output = ::core::result::Result<(), $crate::SystemError>;
+            fn run_on(&mut self, world: &mut W, _input: ()) -> Self::Output {
+                self.run(world)
+            }
+            fn setup_on(&mut self, world: &mut W) {
+                $(
+                    $crate::RunSystem::<W>::setup_on(&mut self.$field, world);
+                )*
+            }
+        }
+    };
+}
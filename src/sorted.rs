@@ -0,0 +1,247 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Entities kept sorted by a key component.
+//!
+//! Drawing in the right order means visiting sprites sorted by their `ZOrder` (or depth, or
+//! layer), and sorting every entity every frame is wasteful when only a few of them move. A
+//! [`SortedIndex<C>`](struct.SortedIndex.html) resource keeps the entities with a `C` component
+//! in order of the key `C` gives through [`SortKey`](trait.SortKey.html), and the
+//! [`UpdateSortedIndex<C>`](struct.UpdateSortedIndex.html) system brings it up to date once per
+//! run: it compares each component's key with the one the entity was filed under, and only moves
+//! the entities whose keys changed, or that gained or lost the component.
+//!
+//! Entities with equal keys are kept in ascending id order, so the order is the same on every run.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct ZOrder(i32);
+//! impl SortKey for ZOrder {
+//!     type Key = i32;
+//!     fn sort_key(&self) -> i32 {
+//!         self.0
+//!     }
+//! }
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             z: BasicVecStorage<ZOrder>,
+//!         }
+//!         resources {
+//!             draw_order: SortedIndex<ZOrder>,
+//!         }
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let hud = w.new_entity().with(ZOrder(10)).build();
+//! let floor = w.new_entity().with(ZOrder(-5)).build();
+//! let player = w.new_entity().with(ZOrder(0)).build();
+//!
+//! let mut dispatcher = Dispatcher::new().with(&mut w, UpdateSortedIndex::<ZOrder>::new());
+//! dispatcher.run(&mut w).unwrap();
+//! let order: Vec<_> = <World as GetResource<SortedIndex<ZOrder>>>::get(&w).iter().collect();
+//! assert_eq!(order, vec![floor, player, hud]);
+//!
+//! w.component_mut::<ZOrder>().get_mut(player).unwrap().0 = 20;
+//! dispatcher.run(&mut w).unwrap();
+//! let order: Vec<_> = <World as GetResource<SortedIndex<ZOrder>>>::get(&w).iter().collect();
+//! assert_eq!(order, vec![floor, hud, player]);
+//! ```
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::*;
+
+/// A component that entities can be sorted by.
+pub trait SortKey {
+    /// The key the component sorts by.
+    type Key: Ord + Clone;
+    /// The component's key.
+    fn sort_key(&self) -> Self::Key;
+}
+
+/// Resource listing the entities with a `C` component, sorted by its key. See the
+/// [module-level documentation](index.html).
+pub struct SortedIndex<C: SortKey> {
+    // Entity ids, ordered by key and then id.
+    order: BTreeSet<(C::Key, usize)>,
+    // Indexed by entity id: each entity in the index, and the key it's filed under.
+    filed: Vec<Option<(Entity, C::Key)>>,
+}
+
+impl<C: SortKey> Default for SortedIndex<C> {
+    fn default() -> Self {
+        SortedIndex {
+            order: BTreeSet::new(),
+            filed: Vec::new(),
+        }
+    }
+}
+
+impl<C: SortKey> Clone for SortedIndex<C> {
+    fn clone(&self) -> Self {
+        SortedIndex {
+            order: self.order.clone(),
+            filed: self.filed.clone(),
+        }
+    }
+}
+
+impl<C: SortKey> fmt::Debug for SortedIndex<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<C: SortKey> SortedIndex<C> {
+    /// Create an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bring the index up to date with `storage`, moving the entities whose keys have changed,
+    /// adding the ones that have gained the component and dropping the ones that have lost it.
+    /// Takes time linear in the size of the storage, plus logarithmic time for each entity that
+    /// moves.
+    pub fn update<'a, S>(&mut self, storage: &S)
+    where
+        S: ComponentStorage<'a, Component = C>,
+    {
+        if storage.size() > self.filed.len() {
+            self.filed.resize_with(storage.size(), || None);
+        }
+        for id in 0..self.filed.len() {
+            let current = storage
+                .entity(id)
+                .and_then(|e| storage.get(e).map(|c| (e, c.sort_key())));
+            let unchanged = match (&self.filed[id], &current) {
+                (Some((e, k)), Some((ce, ck))) => e == ce && k == ck,
+                (None, None) => true,
+                _ => false,
+            };
+            if unchanged {
+                continue;
+            }
+            if let Some((_, old)) = self.filed[id].take() {
+                self.order.remove(&(old, id));
+            }
+            if let Some((e, key)) = current {
+                self.order.insert((key.clone(), id));
+                self.filed[id] = Some((e, key));
+            }
+        }
+    }
+
+    /// File `entity` under `key`, moving it if it was already in the index. For keeping the index
+    /// up to date by hand, instead of with `update`.
+    pub fn insert(&mut self, entity: Entity, key: C::Key) {
+        self.remove_id(entity.id());
+        if entity.id() >= self.filed.len() {
+            self.filed.resize_with(entity.id() + 1, || None);
+        }
+        self.order.insert((key.clone(), entity.id()));
+        self.filed[entity.id()] = Some((entity, key));
+    }
+
+    /// Drop `entity` from the index.
+    pub fn remove(&mut self, entity: Entity) {
+        if self.contains(entity) {
+            self.remove_id(entity.id());
+        }
+    }
+
+    fn remove_id(&mut self, id: usize) {
+        if let Some((_, old)) = self.filed.get_mut(id).and_then(Option::take) {
+            self.order.remove(&(old, id));
+        }
+    }
+
+    /// Whether `entity` is in the index.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.key(entity).is_some()
+    }
+
+    /// The key `entity` is filed under, if it's in the index.
+    pub fn key(&self, entity: Entity) -> Option<&C::Key> {
+        match self.filed.get(entity.id()) {
+            Some(Some((e, key))) if *e == entity => Some(key),
+            _ => None,
+        }
+    }
+
+    /// The entities, in ascending key order, with ties in ascending id order. Reverse it for
+    /// descending order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = Entity> + '_ {
+        self.order.iter().map(move |(_, id)| match self.filed[*id] {
+            Some((e, _)) => e,
+            None => unreachable!("every entity in the order is filed"),
+        })
+    }
+
+    /// The number of entities in the index.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Empty the index.
+    pub fn clear(&mut self) {
+        self.order.clear();
+        self.filed.clear();
+    }
+}
+
+/// System that brings the `SortedIndex<C>` resource up to date with the `C` components, with
+/// `SortedIndex::update`. Run it before the systems that read the index, e.g. rendering.
+pub struct UpdateSortedIndex<C> {
+    tag: PhantomData<fn() -> C>,
+}
+
+impl<C> UpdateSortedIndex<C> {
+    /// Create the system.
+    pub fn new() -> Self {
+        UpdateSortedIndex { tag: PhantomData }
+    }
+}
+
+impl<C> Default for UpdateSortedIndex<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, C> System<'a> for UpdateSortedIndex<C>
+where
+    C: 'static + SortKey + StorageSpec<'a, Component = C>,
+    C::Storage: ComponentStorage<'a> + 'a,
+{
+    type Dependencies = (ReadComponent<'a, C>, WriteResource<'a, SortedIndex<C>>);
+    fn run(&'a mut self, (components, mut index): Self::Dependencies) {
+        index.update(&*components);
+    }
+}
@@ -12,16 +12,49 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+use crate::access_stats::MutationProbe;
 use crate::bitset::*;
+use crate::cell::{Ref, RefMut};
 use crate::*;
 
-use std::cell::{Ref, RefMut, UnsafeCell};
-use std::ops::{Deref, DerefMut};
+mod arena;
+mod btree;
+mod dense;
+mod double;
+mod intent;
+mod interpolated;
+mod layer;
+mod multi;
+mod shared;
+mod small;
+
+pub use self::arena::*;
+pub use self::btree::*;
+pub use self::dense::*;
+pub use self::double::*;
+pub use self::intent::*;
+pub use self::interpolated::*;
+pub use self::layer::*;
+pub use self::multi::*;
+pub use self::shared::*;
+pub use self::small::*;
+
+/// Checking that a storage keeps to the storage contracts.
+pub mod conformance;
 
 /// Specifies how a component is stored.
 ///
 /// This is automatically implemented for component types by `define_world!`; you shouldn't ever
 /// need to implement it manually.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a component of any world",
+    label = "not a component",
+    note = "add a storage for `{Self}` to the `components` block of `define_world!`"
+)]
 pub trait StorageSpec<'a> {
     /// The component type.
     type Component: 'a;
@@ -33,12 +66,58 @@ pub trait StorageSpec<'a> {
 pub struct ReadComponent<'a, T: StorageSpec<'a>> {
     // TODO: This probably doesn't need to be crate public.
     pub(crate) storage: Ref<'a, T::Storage>,
+    // The world's disabled entities, which joins skip.
+    pub(crate) disabled: Option<&'a Disabled>,
 }
 
 /// Read/write view of a Component storage.
 pub struct WriteComponent<'a, T: 'a + StorageSpec<'a>> {
     // TODO: This probably doesn't need to be crate public.
     pub(crate) storage: RefMut<'a, T::Storage>,
+    // Counts whether the storage was borrowed mutably, with the `access-stats` feature.
+    pub(crate) probe: MutationProbe<'a>,
+    // Marks the storage as changed once it's written to.
+    pub(crate) stamp: ChangeStamp<'a>,
+    pub(crate) disabled: Option<&'a Disabled>,
+}
+
+/// View of a Component storage that only allows mutating the component of the entity currently
+/// being visited by a join.
+///
+/// Everything else about the storage can be read, but components can't be added, removed, or
+/// modified by entity id. In exchange, a schedule can run two systems with restricted writes to
+/// the same component in the same stage, if it's told that their joins never visit the same
+/// entity. See `define_schedule!`.
+///
+/// ```compile_fail
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// # #[derive(Default)]
+/// # pub struct Health(u32);
+/// # define_world!(
+/// #     pub world {
+/// #         components {
+/// #             health: BasicVecStorage<Health>,
+/// #         }
+/// #         resources {}
+/// #     }
+/// # );
+/// struct Heal(Entity);
+/// impl<'a> System<'a> for Heal {
+///     type Dependencies = (RestrictedWriteComponent<'a, Health>,);
+///     fn run(&'a mut self, (mut health,): Self::Dependencies) {
+///         // Fine: only touches the entity being visited.
+///         (&mut health,).for_each(|_, (h,)| h.0 += 1);
+///         // Error: can't mutate by entity id.
+///         health.get_mut(self.0).unwrap().0 += 1;
+///     }
+/// }
+/// ```
+pub struct RestrictedWriteComponent<'a, T: 'a + StorageSpec<'a>> {
+    pub(crate) storage: RefMut<'a, T::Storage>,
+    pub(crate) probe: MutationProbe<'a>,
+    pub(crate) stamp: ChangeStamp<'a>,
+    pub(crate) disabled: Option<&'a Disabled>,
 }
 
 /// Read-only view of a resource.
@@ -49,6 +128,7 @@ pub struct ReadResource<'a, T> {
 /// Read/write view of a resource.
 pub struct WriteResource<'a, T> {
     pub(crate) resource: RefMut<'a, T>,
+    pub(crate) probe: MutationProbe<'a>,
 }
 
 // ReadComponent is cloneable; WriteComponent is not.
@@ -60,6 +140,7 @@ where
     fn clone(&self) -> Self {
         ReadComponent {
             storage: Ref::clone(&self.storage),
+            disabled: self.disabled,
         }
     }
 }
@@ -75,6 +156,20 @@ where
     pub fn get(v: &Self) -> &T::Storage {
         Deref::deref(&v.storage)
     }
+
+    /// Iterate over the stored components, in ascending entity id order. Shorthand for a join
+    /// over just this component; unlike `ComponentStorage::iter`, it skips empty slots.
+    pub fn components(&self) -> impl Iterator<Item = &T::Component> + '_ {
+        self.iter_with_entities().map(|(_, c)| c)
+    }
+
+    /// Like `components`, but also yields the entity each component belongs to.
+    pub fn iter_with_entities(&self) -> impl Iterator<Item = (Entity, &T::Component)> + '_ {
+        let storage = Deref::deref(&self.storage);
+        (0..storage.size())
+            .filter_map(move |i| storage.entity(i))
+            .filter_map(move |e| storage.get(e).map(|c| (e, c)))
+    }
 }
 
 impl<'a, T> Deref for ReadComponent<'a, T>
@@ -98,8 +193,52 @@ where
     /// `WriteComponent` implements `Deref`/`DerefMut`.
     #[inline]
     pub fn get_mut(v: &mut Self) -> &mut T::Storage {
+        v.probe.touch();
+        v.stamp.mark();
         DerefMut::deref_mut(&mut v.storage)
     }
+
+    /// Iterate over the stored components, in ascending entity id order. See
+    /// `ReadComponent::components`.
+    pub fn components(&self) -> impl Iterator<Item = &T::Component> + '_ {
+        self.iter_with_entities().map(|(_, c)| c)
+    }
+
+    /// Like `components`, but also yields the entity each component belongs to.
+    pub fn iter_with_entities(&self) -> impl Iterator<Item = (Entity, &T::Component)> + '_ {
+        let storage = Deref::deref(&self.storage);
+        (0..storage.size())
+            .filter_map(move |i| storage.entity(i))
+            .filter_map(move |e| storage.get(e).map(|c| (e, c)))
+    }
+}
+
+impl<'a, T> WriteComponent<'a, T>
+where
+    T: StorageSpec<'a>,
+    T::Storage: MutableComponentStorage<'a>,
+{
+    /// Mutably iterate over the stored components, in ascending entity id order, skipping empty
+    /// slots.
+    pub fn components_mut(&mut self) -> impl Iterator<Item = &mut T::Component> + '_ {
+        self.iter_mut_with_entities().map(|(_, c)| c)
+    }
+
+    /// Like `components_mut`, but also yields the entity each component belongs to.
+    pub fn iter_mut_with_entities(
+        &mut self,
+    ) -> impl Iterator<Item = (Entity, &mut T::Component)> + '_ {
+        self.probe.touch();
+        self.stamp.mark();
+        let storage = DerefMut::deref_mut(&mut self.storage);
+        (0..storage.size()).filter_map(move |i| {
+            let e = storage.entity(i)?;
+            // This unsafe block should be sound, because each entity is only visited once, and
+            // mutable storages keep each entity's component separately, so the returned
+            // references don't alias. Their borrows are tied to `&mut self`.
+            unsafe { storage.get_raw_mut(e).as_mut() }.map(|c| (e, c))
+        })
+    }
 }
 
 impl<'a, T> Deref for WriteComponent<'a, T>
@@ -121,10 +260,24 @@ where
 {
     #[inline]
     fn deref_mut(&mut self) -> &mut T::Storage {
+        self.probe.touch();
+        self.stamp.mark();
         DerefMut::deref_mut(&mut self.storage)
     }
 }
 
+impl<'a, T> Deref for RestrictedWriteComponent<'a, T>
+where
+    T: StorageSpec<'a>,
+    T::Storage: ComponentStorage<'a>,
+{
+    type Target = T::Storage;
+    #[inline]
+    fn deref(&self) -> &T::Storage {
+        Deref::deref(&self.storage)
+    }
+}
+
 impl<'a, T> ReadResource<'a, T> {
     /// Get a reference to the underlying `Storage`. This is an associated method because
     /// `ReadResource` implements `Deref`.
@@ -157,6 +310,7 @@ impl<'a, T> WriteResource<'a, T> {
     /// `WriteResource` implements `Deref`/`DerefMut`.
     #[inline]
     pub fn get_mut(v: &mut Self) -> &mut T {
+        v.probe.touch();
         DerefMut::deref_mut(&mut v.resource)
     }
 }
@@ -172,26 +326,79 @@ impl<'a, T> Deref for WriteResource<'a, T> {
 impl<'a, T> DerefMut for WriteResource<'a, T> {
     #[inline]
     fn deref_mut(&mut self) -> &mut T {
+        self.probe.touch();
         DerefMut::deref_mut(&mut self.resource)
     }
 }
 
-/// Trait that all component storage types must implement.
-pub trait ComponentStorage<'a> {
+/// Reading components from a storage, one entity at a time.
+///
+/// This and [`WriteStorage`](trait.WriteStorage.html) are the minimal interface to a storage:
+/// code that only looks up and changes individual components should be written against them
+/// rather than `ComponentStorage`.
+pub trait ReadStorage<'a> {
     /// The individual type of the Component in this storage
     type Component: 'a;
+    /// Get the component corresponding to the given entity, if it exists.
+    ///
+    /// **This *must* return `None` if the component was stored for a different generation of
+    /// the entity.** The same goes for every other accessor that takes an `Entity`.
+    fn get(&self, entity: Entity) -> Option<&Self::Component>;
+}
+
+/// Changing components in a storage, one entity at a time. See
+/// [`ReadStorage`](trait.ReadStorage.html).
+///
+/// Like `ReadStorage::get`, these only see a component if it was stored for the same generation
+/// of the entity.
+pub trait WriteStorage<'a>: ReadStorage<'a> {
+    /// Get a mutable reference to the component corresponding to the given entity, if it exists.
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut Self::Component>;
+    /// Give the entity a component, returning the one it replaces, if any. A component stored for
+    /// a different generation of the entity is dropped rather than returned.
+    fn insert(&mut self, entity: Entity, value: Self::Component) -> Option<Self::Component>;
+    /// Remove the entity's component and return it, if it has one.
+    fn remove(&mut self, entity: Entity) -> Option<Self::Component>;
+}
+
+/// Trait that all component storage types must implement.
+pub trait ComponentStorage<'a>: ReadStorage<'a> {
     /// Immutable iterator type.
     type Iter: Iterator<Item = Option<&'a Self::Component>>;
-    /// Get the component corresponding to the given entity, if it exists.
-    fn get<'b>(&'b self, entity: Entity) -> Option<&'b Self::Component>;
     /// Get a raw pointer to the component corresponding to the given entity, if it exists. Must
     /// return `std::ptr::null()` if the component doesn't exist for the given entity.
     fn get_raw(&self, entity: Entity) -> *const Self::Component;
-    /// Set the component for the given entity.
+    /// The entity whose component is stored in slot `id`, if there is one. Its `id()` must be
+    /// `id`; joins rely on this to visit entities in ascending id order.
+    fn entity(&self, id: usize) -> Option<Entity>;
+    /// Set the component for the given entity. Setting `Some` replaces whatever was stored for
+    /// any generation of the entity; setting `None` only removes the component if it was stored
+    /// for this generation.
     fn set(&mut self, entity: Entity, item: Option<Self::Component>);
+    /// Remove the component for the given entity and return it, if it exists.
+    ///
+    /// A storage can't in general move a component out through `get_raw` and `set`, so the
+    /// default implementation returns `None` and leaves the component where it is. Storages
+    /// should override it, so that `WorldInterface::move_component`, `swap_components` and
+    /// `take_entity` can hand components back.
+    fn take(&mut self, _entity: Entity) -> Option<Self::Component> {
+        None
+    }
     /// Reserve `n` additional slots without affecting the size of the storage. The default
     /// implementation is a no-op; only implement if it makes sense for your storage type.
     fn reserve(&mut self, _n: usize) {}
+    /// Remove every component. The default implementation removes them one at a time; storages
+    /// that can free their memory in one go should override it.
+    fn clear(&mut self) {
+        for id in 0..self.size() {
+            if let Some(entity) = self.entity(id) {
+                self.set(entity, None);
+            }
+        }
+    }
+    /// Called by `WorldInterface::flip`, between frames. The default implementation is a no-op;
+    /// `DoubleBufferedStorage` uses it to make its next buffer current.
+    fn flip(&mut self) {}
     /// Get the number of components currently stored.
     fn size(&self) -> usize;
     /// Iterate over the components in this storage.
@@ -201,64 +408,111 @@ pub trait ComponentStorage<'a> {
 }
 
 /// Trait that component storage may optionally implement if it supports in-place modification.
-pub trait MutableComponentStorage<'a>: ComponentStorage<'a> {
-    /// Get a mutable reference to the component corresponding to the given entity, if it exists.
-    fn get_mut<'b>(&'b mut self, entity: Entity) -> Option<&'b mut Self::Component>;
+pub trait MutableComponentStorage<'a>: ComponentStorage<'a> + WriteStorage<'a> {
     /// Get a mutable raw pointer to the component corresponding to the given entity, if it exists.
     /// Must return `std::ptr::null()` if the component doesn't exist for the given entity.
     fn get_raw_mut(&mut self, entity: Entity) -> *mut Self::Component;
     /// Mutable iterator type.
-    type IterMut: Iterator<Item = Option<&'a mut <Self as ComponentStorage<'a>>::Component>>;
+    type IterMut: Iterator<Item = Option<&'a mut <Self as ReadStorage<'a>>::Component>>;
     /// Mutably iterate over the components in this storage.
     ///
     /// **This *must* output a value for every entity it knows about, in `id` order.**
     fn iter_mut(&'a mut self) -> Self::IterMut;
 }
 
-/// `ComponentStorage` that is just `Vec<Option<T>>`.
+/// `ComponentStorage` that is just `Vec<Option<T>>` (plus the entity each component belongs to).
 #[derive(Debug, Default)]
-pub struct BasicVecStorage<T>(Vec<Option<UnsafeCell<T>>>);
+pub struct BasicVecStorage<T>(Vec<Option<(Entity, UnsafeCell<T>)>>);
+
+impl<T: Clone> Clone for BasicVecStorage<T> {
+    fn clone(&self) -> Self {
+        // This unsafe block should be sound, because `&self` guarantees there are no outstanding
+        // mutable references into the storage.
+        BasicVecStorage(
+            self.0
+                .iter()
+                .map(|v| {
+                    v.as_ref()
+                        .map(|(e, u)| (*e, UnsafeCell::new(unsafe { &*u.get() }.clone())))
+                })
+                .collect(),
+        )
+    }
+}
+
+impl<'a, T: 'a> ReadStorage<'a> for BasicVecStorage<T> {
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        // This unsafe block should be sound, because the borrow of the returned reference is
+        // tied to the borrow of `&self`.
+        self.slot(entity).map(|v| unsafe { &*v.get() })
+    }
+}
+
+impl<'a, T: 'a> WriteStorage<'a> for BasicVecStorage<T> {
+    #[inline]
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        // This unsafe block should be sound, because the borrow of the returned references is
+        // tied to the borrow of `&mut self`.
+        self.slot(entity).map(|v| unsafe { &mut *v.get() })
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
 
 impl<'a, T> ComponentStorage<'a> for BasicVecStorage<T>
 where
     T: 'a,
 {
-    type Component = T;
-    type Iter = std::iter::Map<
-        std::slice::Iter<'a, Option<UnsafeCell<T>>>,
-        fn(&'a Option<UnsafeCell<T>>) -> Option<&'a T>,
+    type Iter = core::iter::Map<
+        core::slice::Iter<'a, Option<(Entity, UnsafeCell<T>)>>,
+        fn(&'a Option<(Entity, UnsafeCell<T>)>) -> Option<&'a T>,
     >;
     #[inline]
-    fn get<'b>(&'b self, entity: Entity) -> Option<&'b T> {
-        if entity.id < self.0.len() {
-            // This unsafe block should be sound, because the borrow of the returned reference is
-            // tied to the borrow of `&self`.
-            self.0[entity.id].as_ref().map(|v| unsafe { &*v.get() })
-        } else {
-            None
-        }
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.slot(entity).map_or(core::ptr::null(), |v| v.get())
     }
     #[inline]
-    fn get_raw(&self, entity: Entity) -> *const T {
-        self.0[entity.id]
-            .as_ref()
-            .map_or(std::ptr::null(), |v| v.get())
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.get(id)?.as_ref().map(|(e, _)| *e)
     }
     #[inline]
     fn set(&mut self, entity: Entity, item: Option<T>) {
-        if entity.id >= self.0.len() {
-            let n = entity.id - self.0.len() + 1;
-            self.0.reserve(n);
-            for _ in 0..n {
-                self.0.push(None);
+        match item {
+            Some(x) => {
+                if entity.id() >= self.0.len() {
+                    self.0.resize_with(entity.id() + 1, || None);
+                }
+                self.0[entity.id()] = Some((entity, UnsafeCell::new(x)));
+            }
+            None => {
+                if self.slot(entity).is_some() {
+                    self.0[entity.id()] = None;
+                }
             }
         }
-        self.0[entity.id] = item.map(|x| UnsafeCell::new(x));
+    }
+    #[inline]
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        self.slot(entity)?;
+        self.0[entity.id()].take().map(|(_, v)| v.into_inner())
     }
     #[inline]
     fn reserve(&mut self, n: usize) {
         self.0.reserve(n);
     }
+    fn clear(&mut self) {
+        self.0 = Vec::new();
+    }
     #[inline]
     fn size(&self) -> usize {
         self.0.len()
@@ -269,14 +523,25 @@ where
         // tied to the borrow of `&self`.
         self.0
             .iter()
-            .map(|v| v.as_ref().map(|u| unsafe { &*(u.get()) }))
+            .map(|v| v.as_ref().map(|(_, u)| unsafe { &*(u.get()) }))
+    }
+}
+
+impl<T> BasicVecStorage<T> {
+    // The cell for `entity`'s component, if it's stored for the right generation.
+    #[inline]
+    fn slot(&self, entity: Entity) -> Option<&UnsafeCell<T>> {
+        match self.0.get(entity.id()) {
+            Some(Some((e, v))) if *e == entity => Some(v),
+            _ => None,
+        }
     }
 }
 
 impl<'a, T: 'a> MutableComponentStorage<'a> for BasicVecStorage<T> {
-    type IterMut = std::iter::Map<
-        std::slice::IterMut<'a, Option<UnsafeCell<T>>>,
-        fn(&mut Option<UnsafeCell<T>>) -> Option<&'a mut T>,
+    type IterMut = core::iter::Map<
+        core::slice::IterMut<'a, Option<(Entity, UnsafeCell<T>)>>,
+        fn(&mut Option<(Entity, UnsafeCell<T>)>) -> Option<&'a mut T>,
     >;
     #[inline]
     fn iter_mut(&'a mut self) -> Self::IterMut {
@@ -284,27 +549,11 @@ impl<'a, T: 'a> MutableComponentStorage<'a> for BasicVecStorage<T> {
         // tied to the borrow of `&mut self`.
         self.0
             .iter_mut()
-            .map(|v| v.as_ref().map(|u| unsafe { &mut *(u.get()) }))
-    }
-    #[inline]
-    fn get_mut<'b>(&'b mut self, entity: Entity) -> Option<&'b mut T> {
-        if entity.id < self.0.len() {
-            // This unsafe block should be sound, because the borrow of the returned references is
-            // tied to the borrow of `&mut self`.
-            self.0[entity.id].as_ref().map(|v| unsafe { &mut *v.get() })
-        } else {
-            None
-        }
+            .map(|v| v.as_ref().map(|(_, u)| unsafe { &mut *(u.get()) }))
     }
     #[inline]
     fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
-        if entity.id < self.0.len() {
-            self.0[entity.id]
-                .as_ref()
-                .map_or(std::ptr::null_mut(), |v| unsafe { &mut *v.get() })
-        } else {
-            std::ptr::null_mut()
-        }
+        self.slot(entity).map_or(core::ptr::null_mut(), |v| v.get())
     }
 }
 
@@ -312,60 +561,90 @@ impl<'a, T: 'a> MutableComponentStorage<'a> for BasicVecStorage<T> {
 /// `Default`, but you will always get the output of `default()` when you iterate over it. Also,
 /// this storage does not implement `MutableComponentStorage`, since there would be no point in
 /// iterating over it in a mutable fashion.
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct VoidStorage<T: Default> {
     storage: Vec<u32>,
+    // The generation of the entity in each set slot.
+    generations: Vec<u32>,
     // Store an actual instance since we need to be able to return it by reference.
     instance: T,
 }
 
-impl<'a, T: 'a + Default> ComponentStorage<'a> for VoidStorage<T> {
+impl<'a, T: 'a + Default> ReadStorage<'a> for VoidStorage<T> {
     type Component = T;
-    type Iter = VoidStorageIter<'a, T>;
 
     #[inline]
     fn get(&self, entity: Entity) -> Option<&T> {
-        if entity.id / 32 < self.storage.len()
-            && self.storage[entity.id / 32].get_bit(entity.id % 32)
-        {
+        if self.contains(entity) {
             Some(&self.instance)
         } else {
             None
         }
     }
+}
+
+impl<'a, T: 'a + Default> ComponentStorage<'a> for VoidStorage<T> {
+    type Iter = VoidStorageIter<'a, T>;
 
     #[inline]
     fn get_raw(&self, entity: Entity) -> *const T {
-        if entity.id / 32 < self.storage.len()
-            && self.storage[entity.id / 32].get_bit(entity.id % 32)
-        {
+        if self.contains(entity) {
             &self.instance as *const T
         } else {
-            std::ptr::null()
+            core::ptr::null()
+        }
+    }
+
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        if id / 32 < self.storage.len() && self.storage[id / 32].get_bit(id % 32) {
+            Entity::from_bits((u64::from(self.generations[id]) << 32) | id as u64)
+        } else {
+            None
         }
     }
 
     #[inline]
     fn set(&mut self, entity: Entity, item: Option<T>) {
-        if entity.id / 32 >= self.storage.len() {
-            let n = self.storage.len() - entity.id / 32 + 1;
+        if entity.id() / 32 >= self.storage.len() {
+            let n = entity.id() / 32 + 1 - self.storage.len();
             for _ in 0..n {
                 self.storage.push(0);
             }
+            self.generations.resize(self.storage.len() * 32, 0);
         }
         match item {
             Some(_) => {
-                self.storage[entity.id / 32].set_bit(entity.id % 32);
+                self.storage[entity.id() / 32].set_bit(entity.id() % 32);
+                self.generations[entity.id()] = entity.generation();
             }
             None => {
-                self.storage[entity.id / 32].clear_bit(entity.id % 32);
+                if self.contains(entity) {
+                    self.storage[entity.id() / 32].clear_bit(entity.id() % 32);
+                }
             }
         }
     }
 
+    #[inline]
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        if self.contains(entity) {
+            self.storage[entity.id() / 32].clear_bit(entity.id() % 32);
+            Some(T::default())
+        } else {
+            None
+        }
+    }
+
     #[inline]
     fn reserve(&mut self, n: usize) {
-        self.storage.reserve((n + 1) / 32);
+        self.storage.reserve(n.div_ceil(32));
+        self.generations.reserve(n.div_ceil(32) * 32);
+    }
+
+    fn clear(&mut self) {
+        self.storage = Vec::new();
+        self.generations = Vec::new();
     }
 
     #[inline]
@@ -379,9 +658,18 @@ impl<'a, T: 'a + Default> ComponentStorage<'a> for VoidStorage<T> {
     }
 }
 
+impl<T: Default> VoidStorage<T> {
+    #[inline]
+    fn contains(&self, entity: Entity) -> bool {
+        entity.id() / 32 < self.storage.len()
+            && self.storage[entity.id() / 32].get_bit(entity.id() % 32)
+            && self.generations[entity.id()] == entity.generation()
+    }
+}
+
 /// Iterator for `VoidStorage<T>`.
 pub struct VoidStorageIter<'a, T> {
-    iter: std::slice::Iter<'a, u32>,
+    iter: core::slice::Iter<'a, u32>,
     cur_bits: u32,
     cur: usize,
     instance: &'a T,
@@ -389,19 +677,19 @@ pub struct VoidStorageIter<'a, T> {
 
 impl<'a, T> VoidStorageIter<'a, T> {
     #[inline]
-    fn new(mut iter: std::slice::Iter<'a, u32>, instance: &'a T) -> Self {
+    fn new(mut iter: core::slice::Iter<'a, u32>, instance: &'a T) -> Self {
         match iter.next() {
             Some(v) => VoidStorageIter {
-                iter: iter,
+                iter,
                 cur_bits: *v,
                 cur: 0,
-                instance: instance,
+                instance,
             },
             None => VoidStorageIter {
-                iter: iter,
+                iter,
                 cur_bits: 0,
-                cur: 31,
-                instance: instance,
+                cur: 32,
+                instance,
             },
         }
     }
@@ -412,7 +700,7 @@ impl<'a, T> Iterator for VoidStorageIter<'a, T> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cur == 31 {
+        if self.cur == 32 {
             match self.iter.next() {
                 Some(v) => {
                     self.cur_bits = *v;
@@ -0,0 +1,259 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// Number of components in each page of an `ArenaStorage`.
+pub const ARENA_PAGE_SIZE: usize = 256;
+
+#[derive(Clone, Debug)]
+struct Page<T> {
+    cells: Vec<Option<(Entity, T)>>,
+    occupied: usize,
+}
+
+/// `ComponentStorage` that allocates memory in pages of `ARENA_PAGE_SIZE` components.
+///
+/// Pages are only allocated for ranges of entity ids that have the component, and are freed as
+/// soon as they're empty. Components never move once stored, and `clear` releases all of the
+/// storage's memory at once, which makes it a good fit for data that is thrown away in bulk, like
+/// everything on a level that's being left (see `WorldInterface::clear`).
+///
+/// ```
+/// # use ecstatic::*;
+/// let mut storage = ArenaStorage::default();
+/// let far = Entity::new(10 * ARENA_PAGE_SIZE as u32);
+/// storage.set(Entity::new(0), Some(1));
+/// storage.set(far, Some(2));
+/// assert_eq!(storage.pages(), 2);
+///
+/// storage.set(far, None);
+/// assert_eq!(storage.pages(), 1);
+/// storage.clear();
+/// assert_eq!(storage.pages(), 0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ArenaStorage<T> {
+    pages: Vec<Option<Box<Page<T>>>>,
+}
+
+impl<T> Default for ArenaStorage<T> {
+    fn default() -> Self {
+        ArenaStorage { pages: Vec::new() }
+    }
+}
+
+impl<T> ArenaStorage<T> {
+    /// The number of pages currently allocated.
+    pub fn pages(&self) -> usize {
+        self.pages.iter().filter(|p| p.is_some()).count()
+    }
+
+    #[inline]
+    fn cell(&self, entity: Entity) -> Option<&T> {
+        let page = self.pages.get(entity.id() / ARENA_PAGE_SIZE)?.as_ref()?;
+        match page.cells[entity.id() % ARENA_PAGE_SIZE] {
+            Some((e, ref v)) if e == entity => Some(v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn cell_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        let page = self
+            .pages
+            .get_mut(entity.id() / ARENA_PAGE_SIZE)?
+            .as_mut()?;
+        match page.cells[entity.id() % ARENA_PAGE_SIZE] {
+            Some((e, ref mut v)) if e == entity => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, T: 'a> ReadStorage<'a> for ArenaStorage<T> {
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.cell(entity)
+    }
+}
+
+impl<'a, T: 'a> WriteStorage<'a> for ArenaStorage<T> {
+    #[inline]
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.cell_mut(entity)
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
+
+impl<'a, T: 'a> ComponentStorage<'a> for ArenaStorage<T> {
+    type Iter = ArenaStorageIter<'a, T>;
+    #[inline]
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.cell(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        let page = self.pages.get(id / ARENA_PAGE_SIZE)?.as_ref()?;
+        page.cells[id % ARENA_PAGE_SIZE].as_ref().map(|(e, _)| *e)
+    }
+    fn set(&mut self, entity: Entity, item: Option<T>) {
+        let (p, i) = (entity.id() / ARENA_PAGE_SIZE, entity.id() % ARENA_PAGE_SIZE);
+        match item {
+            Some(x) => {
+                if p >= self.pages.len() {
+                    self.pages.resize_with(p + 1, || None);
+                }
+                let page = self.pages[p].get_or_insert_with(|| {
+                    Box::new(Page {
+                        cells: (0..ARENA_PAGE_SIZE).map(|_| None).collect(),
+                        occupied: 0,
+                    })
+                });
+                if page.cells[i].is_none() {
+                    page.occupied += 1;
+                }
+                page.cells[i] = Some((entity, x));
+            }
+            None => {
+                if self.cell(entity).is_some() {
+                    let page = self.pages[p].as_mut().unwrap();
+                    page.cells[i] = None;
+                    page.occupied -= 1;
+                    if page.occupied == 0 {
+                        self.pages[p] = None;
+                    }
+                }
+            }
+        }
+    }
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        let (p, i) = (entity.id() / ARENA_PAGE_SIZE, entity.id() % ARENA_PAGE_SIZE);
+        self.cell(entity)?;
+        let page = self.pages[p].as_mut().unwrap();
+        let (_, v) = page.cells[i].take().unwrap();
+        page.occupied -= 1;
+        if page.occupied == 0 {
+            self.pages[p] = None;
+        }
+        Some(v)
+    }
+    fn clear(&mut self) {
+        self.pages = Vec::new();
+    }
+    #[inline]
+    fn size(&self) -> usize {
+        self.pages.len() * ARENA_PAGE_SIZE
+    }
+    #[inline]
+    fn iter(&'a self) -> Self::Iter {
+        ArenaStorageIter {
+            pages: self.pages.iter(),
+            cells: None,
+            blanks: 0,
+        }
+    }
+}
+
+impl<'a, T: 'a> MutableComponentStorage<'a> for ArenaStorage<T> {
+    type IterMut = ArenaStorageIterMut<'a, T>;
+    #[inline]
+    fn iter_mut(&'a mut self) -> Self::IterMut {
+        ArenaStorageIterMut {
+            pages: self.pages.iter_mut(),
+            cells: None,
+            blanks: 0,
+        }
+    }
+    #[inline]
+    fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
+        self.cell_mut(entity)
+            .map_or(core::ptr::null_mut(), |v| v as *mut T)
+    }
+}
+
+/// Iterator for `ArenaStorage<T>`.
+pub struct ArenaStorageIter<'a, T> {
+    pages: core::slice::Iter<'a, Option<Box<Page<T>>>>,
+    cells: Option<core::slice::Iter<'a, Option<(Entity, T)>>>,
+    // Number of `None`s left to yield for a missing page.
+    blanks: usize,
+}
+
+impl<'a, T> Iterator for ArenaStorageIter<'a, T> {
+    type Item = Option<&'a T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.blanks > 0 {
+                self.blanks -= 1;
+                return Some(None);
+            }
+            if let Some(cell) = self.cells.as_mut().and_then(|c| c.next()) {
+                return Some(cell.as_ref().map(|(_, v)| v));
+            }
+            match self.pages.next()? {
+                Some(page) => self.cells = Some(page.cells.iter()),
+                None => {
+                    self.cells = None;
+                    self.blanks = ARENA_PAGE_SIZE;
+                }
+            }
+        }
+    }
+}
+
+/// Mutable iterator for `ArenaStorage<T>`.
+pub struct ArenaStorageIterMut<'a, T> {
+    pages: core::slice::IterMut<'a, Option<Box<Page<T>>>>,
+    cells: Option<core::slice::IterMut<'a, Option<(Entity, T)>>>,
+    blanks: usize,
+}
+
+impl<'a, T> Iterator for ArenaStorageIterMut<'a, T> {
+    type Item = Option<&'a mut T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.blanks > 0 {
+                self.blanks -= 1;
+                return Some(None);
+            }
+            if let Some(cell) = self.cells.as_mut().and_then(|c| c.next()) {
+                return Some(cell.as_mut().map(|(_, v)| v));
+            }
+            match self.pages.next()? {
+                Some(page) => self.cells = Some(page.cells.iter_mut()),
+                None => {
+                    self.cells = None;
+                    self.blanks = ARENA_PAGE_SIZE;
+                }
+            }
+        }
+    }
+}
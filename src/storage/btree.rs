@@ -0,0 +1,197 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::collections::BTreeMap;
+
+use crate::*;
+
+/// `ComponentStorage` backed by a `BTreeMap` from entity id to component.
+///
+/// Memory use is proportional to the number of components rather than the largest entity id, and
+/// iteration always goes in ascending entity id order, no matter what order the components were
+/// inserted or removed in. `entries` walks just the entities that have the component, without
+/// visiting the gaps in between.
+///
+/// ```
+/// # use ecstatic::*;
+/// let mut storage = BTreeMapStorage::default();
+/// storage.set(Entity::new(7), Some('b'));
+/// storage.set(Entity::new(2), Some('a'));
+/// let ids = storage.entries().map(|(e, _)| e.id()).collect::<Vec<_>>();
+/// assert_eq!(ids, vec![2, 7]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct BTreeMapStorage<T>(BTreeMap<usize, (Entity, T)>);
+
+impl<T> Default for BTreeMapStorage<T> {
+    fn default() -> Self {
+        BTreeMapStorage(BTreeMap::new())
+    }
+}
+
+impl<T> BTreeMapStorage<T> {
+    /// Iterate over the entities that have the component, in ascending id order.
+    pub fn entries(&self) -> impl Iterator<Item = (Entity, &T)> + '_ {
+        self.0.values().map(|(e, v)| (*e, v))
+    }
+
+    /// Mutable version of `entries`.
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> + '_ {
+        self.0.values_mut().map(|(e, v)| (*e, v))
+    }
+
+    #[inline]
+    fn cell(&self, entity: Entity) -> Option<&T> {
+        match self.0.get(&entity.id()) {
+            Some((e, v)) if *e == entity => Some(v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn cell_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.0.get_mut(&entity.id()) {
+            Some((e, v)) if *e == entity => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, T: 'a> ReadStorage<'a> for BTreeMapStorage<T> {
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.cell(entity)
+    }
+}
+
+impl<'a, T: 'a> WriteStorage<'a> for BTreeMapStorage<T> {
+    #[inline]
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.cell_mut(entity)
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
+
+impl<'a, T: 'a> ComponentStorage<'a> for BTreeMapStorage<T> {
+    type Iter = BTreeMapStorageIter<'a, T>;
+    #[inline]
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.cell(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.get(&id).map(|(e, _)| *e)
+    }
+    fn set(&mut self, entity: Entity, item: Option<T>) {
+        match item {
+            Some(x) => {
+                self.0.insert(entity.id(), (entity, x));
+            }
+            None => {
+                if self.cell(entity).is_some() {
+                    self.0.remove(&entity.id());
+                }
+            }
+        }
+    }
+    #[inline]
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        self.cell(entity)?;
+        self.0.remove(&entity.id()).map(|(_, v)| v)
+    }
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+    #[inline]
+    fn size(&self) -> usize {
+        self.0.keys().next_back().map_or(0, |id| id + 1)
+    }
+    #[inline]
+    fn iter(&'a self) -> Self::Iter {
+        BTreeMapStorageIter {
+            entries: self.0.iter().peekable(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<'a, T: 'a> MutableComponentStorage<'a> for BTreeMapStorage<T> {
+    type IterMut = BTreeMapStorageIterMut<'a, T>;
+    #[inline]
+    fn iter_mut(&'a mut self) -> Self::IterMut {
+        BTreeMapStorageIterMut {
+            entries: self.0.iter_mut().peekable(),
+            next_id: 0,
+        }
+    }
+    #[inline]
+    fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
+        self.cell_mut(entity)
+            .map_or(core::ptr::null_mut(), |v| v as *mut T)
+    }
+}
+
+/// Iterator for `BTreeMapStorage<T>`. Like the other storages' iterators, it yields one item per
+/// entity id, so ids without the component come out as `None`.
+pub struct BTreeMapStorageIter<'a, T> {
+    entries: core::iter::Peekable<alloc::collections::btree_map::Iter<'a, usize, (Entity, T)>>,
+    next_id: usize,
+}
+
+impl<'a, T> Iterator for BTreeMapStorageIter<'a, T> {
+    type Item = Option<&'a T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = *self.entries.peek()?.0;
+        let item = if id == self.next_id {
+            self.entries.next().map(|(_, (_, v))| v)
+        } else {
+            None
+        };
+        self.next_id += 1;
+        Some(item)
+    }
+}
+
+/// Mutable iterator for `BTreeMapStorage<T>`.
+pub struct BTreeMapStorageIterMut<'a, T> {
+    entries: core::iter::Peekable<alloc::collections::btree_map::IterMut<'a, usize, (Entity, T)>>,
+    next_id: usize,
+}
+
+impl<'a, T> Iterator for BTreeMapStorageIterMut<'a, T> {
+    type Item = Option<&'a mut T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = *self.entries.peek()?.0;
+        let item = if id == self.next_id {
+            self.entries.next().map(|(_, (_, v))| v)
+        } else {
+            None
+        };
+        self.next_id += 1;
+        Some(item)
+    }
+}
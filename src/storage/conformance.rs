@@ -0,0 +1,342 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conformance tests for storages.
+//!
+//! Joins, component masks and `World::validate` all rely on storages keeping to the contracts
+//! documented on `ReadStorage`, `WriteStorage`, `ComponentStorage` and `MutableComponentStorage`.
+//! The functions here check a storage type against them, so that a crate implementing its own
+//! storage can test it the same way the built-in ones are tested. They run a fixed sequence of
+//! inserts, overwrites and removals (including of stale generations of an entity) against both
+//! the storage and a simple model of it, and panic, naming the check, at the first difference.
+//!
+//! Components are made with `From<u32>`, and compared with `PartialEq`. For storages that don't
+//! keep the value they're given, like `VoidStorage`, use a component whose values are all equal.
+//! Changes made through mutable references are only expected to be visible after a `flip`, as
+//! with `DoubleBufferedStorage`.
+//!
+//! ```
+//! use ecstatic::storage::conformance::*;
+//! use ecstatic::*;
+//!
+//! assert_mutable_storage_conformance::<BTreeMapStorage<u64>>();
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::{ComponentStorage, Entity, MutableComponentStorage, ReadStorage};
+
+// Ids on both sides of the boundaries that bit-packed and paged storages are likely to have.
+const IDS: [u32; 10] = [0, 1, 7, 31, 32, 33, 63, 64, 65, 300];
+
+// What the storage should contain, indexed by id.
+struct Model(Vec<Option<(Entity, u32)>>);
+
+impl Model {
+    fn new() -> Self {
+        Model(Vec::new())
+    }
+
+    fn set(&mut self, entity: Entity, value: Option<u32>) {
+        if entity.id() >= self.0.len() {
+            self.0.resize(entity.id() + 1, None);
+        }
+        match value {
+            Some(v) => self.0[entity.id()] = Some((entity, v)),
+            None => {
+                if self.get(entity).is_some() {
+                    self.0[entity.id()] = None;
+                }
+            }
+        }
+    }
+
+    fn get(&self, entity: Entity) -> Option<u32> {
+        match self.0.get(entity.id()) {
+            Some(Some((e, v))) if *e == entity => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn take(&mut self, entity: Entity) -> Option<u32> {
+        let v = self.get(entity);
+        self.set(entity, None);
+        v
+    }
+}
+
+/// Check that `S` keeps to the `ComponentStorage` contract.
+pub fn assert_storage_conformance<S>()
+where
+    S: Default + for<'a> ComponentStorage<'a>,
+    for<'a> <S as ReadStorage<'a>>::Component: From<u32> + PartialEq + Debug,
+{
+    let mut storage = S::default();
+    let mut model = Model::new();
+    check(&storage, &model, "a new storage");
+
+    for (i, &id) in IDS.iter().enumerate() {
+        set(&mut storage, &mut model, Entity::new(id), Some(i as u32));
+    }
+    check(&storage, &model, "inserting");
+
+    for (i, &id) in IDS.iter().enumerate().step_by(2) {
+        set(
+            &mut storage,
+            &mut model,
+            Entity::new(id),
+            Some(100 + i as u32),
+        );
+    }
+    check(&storage, &model, "overwriting");
+
+    for &id in IDS.iter().skip(1).step_by(3) {
+        let e = Entity::new(id);
+        let taken = storage.take(e);
+        assert_eq!(taken, model.take(e).map(From::from), "taking {:?}", e);
+        assert_eq!(storage.take(e), None, "taking {:?} a second time", e);
+    }
+    check(&storage, &model, "taking");
+
+    for &id in IDS.iter().step_by(4) {
+        set(&mut storage, &mut model, Entity::new(id), None);
+    }
+    check(&storage, &model, "removing with `set`");
+
+    // Nothing stored for one generation of an entity is visible through another, but storing
+    // a component for the new generation replaces the old one.
+    for (i, &id) in IDS.iter().enumerate() {
+        let stale = Entity::new(id);
+        let next = stale.next_generation();
+        assert_eq!(
+            storage.get(next),
+            None,
+            "getting {:?} after {:?}",
+            next,
+            stale
+        );
+        assert_eq!(
+            storage.take(next),
+            None,
+            "taking {:?} after {:?}",
+            next,
+            stale
+        );
+        set(&mut storage, &mut model, next, None);
+        check(&storage, &model, "removing a newer generation");
+        if i % 2 == 0 {
+            set(&mut storage, &mut model, next, Some(200 + i as u32));
+            assert_eq!(
+                storage.get(stale),
+                None,
+                "getting {:?} after {:?}",
+                stale,
+                next
+            );
+        }
+    }
+    check(&storage, &model, "reusing ids for a new generation");
+
+    storage.clear();
+    check(&storage, &Model::new(), "clearing");
+    set(&mut storage, &mut model, Entity::new(IDS[3]), Some(300));
+    storage.clear();
+    check(
+        &storage,
+        &Model::new(),
+        "clearing a storage that had been cleared",
+    );
+}
+
+/// Check that `S` keeps to the `MutableComponentStorage` and `WriteStorage` contracts, as well as
+/// the `ComponentStorage` one.
+pub fn assert_mutable_storage_conformance<S>()
+where
+    S: Default + for<'a> MutableComponentStorage<'a>,
+    for<'a> <S as ReadStorage<'a>>::Component: From<u32> + PartialEq + Debug,
+{
+    assert_storage_conformance::<S>();
+
+    let mut storage = S::default();
+    let mut model = Model::new();
+    for (i, &id) in IDS.iter().enumerate() {
+        let e = Entity::new(id);
+        assert_eq!(
+            storage.insert(e, From::from(i as u32)),
+            None,
+            "inserting {:?}",
+            e
+        );
+        model.set(e, Some(i as u32));
+    }
+    check(&storage, &model, "inserting with `insert`");
+
+    for (i, &id) in IDS.iter().enumerate().step_by(2) {
+        let e = Entity::new(id);
+        *storage.get_mut(e).expect("`get_mut` found nothing") = From::from(400 + i as u32);
+        model.set(e, Some(400 + i as u32));
+        assert!(
+            !storage.get_raw_mut(e).is_null(),
+            "`get_raw_mut` for {:?}",
+            e
+        );
+    }
+    storage.flip();
+    check(&storage, &model, "changing components with `get_mut`");
+
+    for (i, &id) in IDS.iter().enumerate().skip(1).step_by(2) {
+        let e = Entity::new(id);
+        let old = storage.insert(e, From::from(500 + i as u32));
+        assert_eq!(old, model.get(e).map(From::from), "replacing {:?}", e);
+        model.set(e, Some(500 + i as u32));
+    }
+    check(&storage, &model, "replacing with `insert`");
+
+    let mut count = 0;
+    for (id, v) in storage.iter_mut().enumerate() {
+        assert_eq!(
+            v.is_some(),
+            model.0.get(id).is_some_and(Option::is_some),
+            "`iter_mut` at {}",
+            id
+        );
+        count += 1;
+    }
+    assert!(count >= model.0.len(), "`iter_mut` stopped at {}", count);
+
+    for (i, &id) in IDS.iter().enumerate() {
+        let stale = Entity::new(id);
+        let next = stale.next_generation();
+        assert!(storage.get_mut(next).is_none(), "`get_mut` for {:?}", next);
+        assert!(
+            storage.get_raw_mut(next).is_null(),
+            "`get_raw_mut` for {:?}",
+            next
+        );
+        assert_eq!(storage.remove(next), None, "removing {:?}", next);
+        if i % 3 == 0 {
+            // The component for the old generation is dropped rather than returned.
+            assert_eq!(
+                storage.insert(next, From::from(600)),
+                None,
+                "inserting {:?}",
+                next
+            );
+            model.set(next, Some(600));
+        }
+    }
+    check(&storage, &model, "inserting a newer generation");
+
+    for &id in IDS.iter() {
+        for e in [Entity::new(id), Entity::new(id).next_generation()] {
+            assert_eq!(
+                storage.remove(e),
+                model.take(e).map(From::from),
+                "removing {:?}",
+                e
+            );
+        }
+    }
+    check(&storage, &model, "removing with `remove`");
+}
+
+fn set<S>(storage: &mut S, model: &mut Model, entity: Entity, value: Option<u32>)
+where
+    S: for<'a> ComponentStorage<'a>,
+    for<'a> <S as ReadStorage<'a>>::Component: From<u32>,
+{
+    storage.set(entity, value.map(From::from));
+    model.set(entity, value);
+}
+
+// Check everything the join machinery, masks and integrity checks rely on.
+fn check<S>(storage: &S, model: &Model, after: &str)
+where
+    S: for<'a> ComponentStorage<'a>,
+    for<'a> <S as ReadStorage<'a>>::Component: From<u32> + PartialEq + Debug,
+{
+    let size = storage.size();
+    let last = model.0.iter().rposition(Option::is_some);
+    assert!(
+        last.is_none_or(|id| id < size),
+        "after {}: `size` is {}, but slot {:?} is used",
+        after,
+        size,
+        last
+    );
+    let mut iter = storage.iter();
+    for id in 0..size.max(model.0.len()) {
+        let expected = model.0.get(id).copied().flatten();
+        let entity = storage.entity(id);
+        assert_eq!(
+            entity,
+            expected.map(|(e, _)| e),
+            "after {}: `entity` for slot {}",
+            after,
+            id
+        );
+        let item = if id < size { iter.next() } else { None };
+        match expected {
+            Some((e, v)) => {
+                let v = From::from(v);
+                assert_eq!(
+                    storage.get(e),
+                    Some(&v),
+                    "after {}: `get` for {:?}",
+                    after,
+                    e
+                );
+                assert_eq!(
+                    storage.get_raw(e),
+                    storage.get(e).unwrap() as *const _,
+                    "after {}: `get_raw` for {:?}",
+                    after,
+                    e
+                );
+                assert_eq!(item, Some(Some(&v)), "after {}: `iter` at {}", after, id);
+                let other = e.next_generation();
+                assert_eq!(
+                    storage.get(other),
+                    None,
+                    "after {}: `get` for {:?}",
+                    after,
+                    other
+                );
+                assert!(
+                    storage.get_raw(other).is_null(),
+                    "after {}: `get_raw` for {:?}",
+                    after,
+                    other
+                );
+            }
+            None => {
+                let e = Entity::new(id as u32);
+                assert_eq!(storage.get(e), None, "after {}: `get` for {:?}", after, e);
+                assert!(
+                    storage.get_raw(e).is_null(),
+                    "after {}: `get_raw` for {:?}",
+                    after,
+                    e
+                );
+                assert!(
+                    item.is_none_or(|v| v.is_none()),
+                    "after {}: `iter` at {}",
+                    after,
+                    id
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,318 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// `ComponentStorage` that packs its components into one contiguous array, in ascending entity id
+/// order, with a second array of the entities they belong to.
+///
+/// [`iter_chunks`](#method.iter_chunks) and [`iter_chunks_mut`](#method.iter_chunks_mut) hand
+/// out the arrays in slices of up to `n` components, so code that does the same arithmetic on
+/// every component (lighting falloff, stat recomputation) can work through them in batches the
+/// compiler can vectorize, without looking each one up by entity.
+///
+/// For handing the components to code outside the ECS (SIMD kernels, GPU uploads) in one go,
+/// [`as_slice`](#method.as_slice) and [`as_mut_slice`](#method.as_mut_slice) give the whole
+/// array, and [`as_mut_slices`](#method.as_mut_slices) gives the entity array alongside it. With
+/// the `bytemuck` feature, [`as_bytes`](#method.as_bytes) gives `Pod` components as raw bytes, to
+/// copy straight into a vertex buffer.
+///
+/// Keeping the arrays sorted makes adding or removing a component anywhere but at the end take
+/// time linear in the number of components, so this suits components that are mostly updated in
+/// place. Entities are usually created in ascending id order, so building them is cheap.
+///
+/// ```
+/// # use ecstatic::*;
+/// let mut light = DenseVecStorage::<f32>::default();
+/// for id in 0..10 {
+///     light.set(Entity::new(id), Some(1.0));
+/// }
+/// light.set(Entity::new(4), None);
+///
+/// for (_, levels) in light.iter_chunks_mut(4) {
+///     for level in levels {
+///         *level *= 0.5;
+///     }
+/// }
+/// let chunks: Vec<_> = light.iter_chunks(4).collect();
+/// assert_eq!(chunks.len(), 3);
+/// assert_eq!(chunks[1].0[0], Entity::new(5));
+/// assert_eq!(chunks[2].1, &[0.5]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct DenseVecStorage<T> {
+    // Indexed by entity id: where the entity's component is in `dense`.
+    sparse: Vec<Option<u32>>,
+    entities: Vec<Entity>,
+    dense: Vec<T>,
+}
+
+impl<T> Default for DenseVecStorage<T> {
+    fn default() -> Self {
+        DenseVecStorage {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            dense: Vec::new(),
+        }
+    }
+}
+
+impl<T> DenseVecStorage<T> {
+    /// The number of components.
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    /// Whether there are no components.
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    /// The components, in ascending entity id order, e.g. for copying them wholesale into a GPU
+    /// buffer.
+    pub fn as_slice(&self) -> &[T] {
+        &self.dense
+    }
+
+    /// The components, in ascending entity id order, mutably, e.g. for handing them to SIMD code
+    /// or filling them from a buffer the GPU wrote.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.dense
+    }
+
+    /// The entities the components belong to, in the same order as `as_slice`.
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// The entities and their components, as two slices of the same length.
+    pub fn as_slices(&self) -> (&[Entity], &[T]) {
+        (&self.entities, &self.dense)
+    }
+
+    /// The entities and their components, with the components mutable, as two slices of the same
+    /// length. The entities can't be changed, since the storage relies on their order.
+    pub fn as_mut_slices(&mut self) -> (&[Entity], &mut [T]) {
+        (&self.entities, &mut self.dense)
+    }
+
+    /// The components' bytes, in ascending entity id order, e.g. for copying sprite instance data
+    /// straight into a vertex buffer. Needs the `bytemuck` feature.
+    ///
+    /// ```
+    /// # use ecstatic::*;
+    /// let mut instances = DenseVecStorage::<[f32; 2]>::default();
+    /// instances.set(Entity::new(1), Some([1.0, 2.0]));
+    /// instances.set(Entity::new(0), Some([3.0, 4.0]));
+    /// let bytes = instances.as_bytes();
+    /// assert_eq!(bytes.len(), 16);
+    /// assert_eq!(&bytes[..4], &3.0f32.to_ne_bytes());
+    /// ```
+    #[cfg(feature = "bytemuck")]
+    pub fn as_bytes(&self) -> &[u8]
+    where
+        T: bytemuck::Pod,
+    {
+        bytemuck::cast_slice(&self.dense)
+    }
+
+    /// The components' bytes, mutably, e.g. for filling them from a buffer read back from the
+    /// GPU. Needs the `bytemuck` feature.
+    #[cfg(feature = "bytemuck")]
+    pub fn as_bytes_mut(&mut self) -> &mut [u8]
+    where
+        T: bytemuck::Pod,
+    {
+        bytemuck::cast_slice_mut(&mut self.dense)
+    }
+
+    /// Iterate over the components in slices of `n` (the last one may be shorter), each with the
+    /// matching slice of entities. Panics if `n` is zero.
+    pub fn iter_chunks(&self, n: usize) -> impl Iterator<Item = (&[Entity], &[T])> {
+        self.entities.chunks(n).zip(self.dense.chunks(n))
+    }
+
+    /// Like `iter_chunks`, but with mutable slices of components.
+    pub fn iter_chunks_mut(&mut self, n: usize) -> impl Iterator<Item = (&[Entity], &mut [T])> {
+        self.entities.chunks(n).zip(self.dense.chunks_mut(n))
+    }
+
+    // Where `entity`'s component is in `dense`, if it's stored for the right generation.
+    #[inline]
+    fn index(&self, entity: Entity) -> Option<usize> {
+        match self.sparse.get(entity.id()) {
+            Some(&Some(i)) if self.entities[i as usize] == entity => Some(i as usize),
+            _ => None,
+        }
+    }
+
+    fn remove_at(&mut self, i: usize) -> T {
+        self.sparse[self.entities[i].id()] = None;
+        self.entities.remove(i);
+        for e in &self.entities[i..] {
+            if let Some(j) = self.sparse[e.id()].as_mut() {
+                *j -= 1;
+            }
+        }
+        self.dense.remove(i)
+    }
+}
+
+impl<'a, T: 'a> ReadStorage<'a> for DenseVecStorage<T> {
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.index(entity).map(|i| &self.dense[i])
+    }
+}
+
+impl<'a, T: 'a> WriteStorage<'a> for DenseVecStorage<T> {
+    #[inline]
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.index(entity).map(move |i| &mut self.dense[i])
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
+
+impl<'a, T: 'a> ComponentStorage<'a> for DenseVecStorage<T> {
+    type Iter = DenseVecStorageIter<'a, T>;
+    #[inline]
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.get(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        let i = (*self.sparse.get(id)?)?;
+        Some(self.entities[i as usize])
+    }
+    fn set(&mut self, entity: Entity, item: Option<T>) {
+        match item {
+            Some(x) => {
+                if entity.id() >= self.sparse.len() {
+                    self.sparse.resize(entity.id() + 1, None);
+                }
+                if let Some(i) = self.sparse[entity.id()] {
+                    // Replace whatever was stored for any generation of the entity.
+                    self.entities[i as usize] = entity;
+                    self.dense[i as usize] = x;
+                    return;
+                }
+                let i = self.entities.partition_point(|e| e.id() < entity.id());
+                for e in &self.entities[i..] {
+                    if let Some(j) = self.sparse[e.id()].as_mut() {
+                        *j += 1;
+                    }
+                }
+                self.entities.insert(i, entity);
+                self.dense.insert(i, x);
+                self.sparse[entity.id()] = Some(i as u32);
+            }
+            None => {
+                if let Some(i) = self.index(entity) {
+                    self.remove_at(i);
+                }
+            }
+        }
+    }
+    #[inline]
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        let i = self.index(entity)?;
+        Some(self.remove_at(i))
+    }
+    #[inline]
+    fn reserve(&mut self, n: usize) {
+        self.sparse.reserve(n);
+        self.entities.reserve(n);
+        self.dense.reserve(n);
+    }
+    fn clear(&mut self) {
+        *self = DenseVecStorage::default();
+    }
+    #[inline]
+    fn size(&self) -> usize {
+        self.sparse.len()
+    }
+    #[inline]
+    fn iter(&'a self) -> Self::Iter {
+        DenseVecStorageIter {
+            sparse: self.sparse.iter(),
+            dense: self.dense.iter(),
+        }
+    }
+}
+
+impl<'a, T: 'a> MutableComponentStorage<'a> for DenseVecStorage<T> {
+    type IterMut = DenseVecStorageIterMut<'a, T>;
+    #[inline]
+    fn iter_mut(&'a mut self) -> Self::IterMut {
+        DenseVecStorageIterMut {
+            sparse: self.sparse.iter(),
+            dense: self.dense.iter_mut(),
+        }
+    }
+    #[inline]
+    fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
+        self.get_mut(entity)
+            .map_or(core::ptr::null_mut(), |v| v as *mut T)
+    }
+}
+
+/// Iterator for `DenseVecStorage<T>`.
+pub struct DenseVecStorageIter<'a, T> {
+    sparse: core::slice::Iter<'a, Option<u32>>,
+    // The components are in id order, so each one is the next one that's needed.
+    dense: core::slice::Iter<'a, T>,
+}
+
+impl<'a, T> Iterator for DenseVecStorageIter<'a, T> {
+    type Item = Option<&'a T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.sparse.next()? {
+            Some(_) => Some(self.dense.next()),
+            None => Some(None),
+        }
+    }
+}
+
+/// Mutable iterator for `DenseVecStorage<T>`.
+pub struct DenseVecStorageIterMut<'a, T> {
+    sparse: core::slice::Iter<'a, Option<u32>>,
+    dense: core::slice::IterMut<'a, T>,
+}
+
+impl<'a, T> Iterator for DenseVecStorageIterMut<'a, T> {
+    type Item = Option<&'a mut T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.sparse.next()? {
+            Some(_) => Some(self.dense.next()),
+            None => Some(None),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// `ComponentStorage` that keeps two copies of each component: the "current" one, which every
+/// read sees, and the "next" one, which every write goes to. `WorldInterface::flip` makes the next
+/// values current and starts a new next buffer from them.
+///
+/// This is for cellular-automaton style systems (fire spreading, fluids) that compute each
+/// entity's new state from its neighbours' old state. Joins read the current buffer through
+/// `&storage` and write the next one through `&mut storage`; to do both at once, use `split_mut`.
+///
+/// `set` and `take` act on both buffers, so new components are visible straight away.
+///
+/// ```
+/// # use ecstatic::*;
+/// let mut storage = DoubleBufferedStorage::default();
+/// let (a, b) = (Entity::new(0), Entity::new(1));
+/// storage.set(a, Some(true));
+/// storage.set(b, Some(false));
+///
+/// // Spread to `b` from `a`.
+/// let (current, mut next) = storage.split_mut();
+/// *next.get_mut(b).unwrap() = *current.get(a).unwrap();
+/// assert_eq!(storage.get(b), Some(&false));
+///
+/// storage.flip();
+/// assert_eq!(storage.get(b), Some(&true));
+/// ```
+#[derive(Clone, Debug)]
+pub struct DoubleBufferedStorage<T> {
+    current: Vec<Option<(Entity, T)>>,
+    next: Vec<Option<(Entity, T)>>,
+}
+
+impl<T> Default for DoubleBufferedStorage<T> {
+    fn default() -> Self {
+        DoubleBufferedStorage {
+            current: Vec::new(),
+            next: Vec::new(),
+        }
+    }
+}
+
+impl<T> DoubleBufferedStorage<T> {
+    /// Borrow the current buffer for reading and the next buffer for writing at the same time.
+    pub fn split_mut(&mut self) -> (CurrentBuffer<'_, T>, NextBuffer<'_, T>) {
+        (CurrentBuffer(&self.current), NextBuffer(&mut self.next))
+    }
+
+    /// The value that will become current at the next flip.
+    pub fn get_next(&self, entity: Entity) -> Option<&T> {
+        cell(&self.next, entity)
+    }
+}
+
+/// The current buffer of a `DoubleBufferedStorage`. See `DoubleBufferedStorage::split_mut`.
+pub struct CurrentBuffer<'a, T>(&'a [Option<(Entity, T)>]);
+
+impl<'a, T> CurrentBuffer<'a, T> {
+    /// Get the current value of `entity`'s component.
+    pub fn get(&self, entity: Entity) -> Option<&'a T> {
+        cell(self.0, entity)
+    }
+}
+
+/// The next buffer of a `DoubleBufferedStorage`. See `DoubleBufferedStorage::split_mut`.
+pub struct NextBuffer<'a, T>(&'a mut [Option<(Entity, T)>]);
+
+impl<'a, T> NextBuffer<'a, T> {
+    /// Get the next value of `entity`'s component, for writing.
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        cell_mut(self.0, entity)
+    }
+}
+
+#[inline]
+fn cell<T>(buffer: &[Option<(Entity, T)>], entity: Entity) -> Option<&T> {
+    match buffer.get(entity.id()) {
+        Some(Some((e, v))) if *e == entity => Some(v),
+        _ => None,
+    }
+}
+
+#[inline]
+fn cell_mut<T>(buffer: &mut [Option<(Entity, T)>], entity: Entity) -> Option<&mut T> {
+    match buffer.get_mut(entity.id()) {
+        Some(Some((e, v))) if *e == entity => Some(v),
+        _ => None,
+    }
+}
+
+impl<'a, T: 'a + Clone> ReadStorage<'a> for DoubleBufferedStorage<T> {
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        cell(&self.current, entity)
+    }
+}
+
+impl<'a, T: 'a + Clone> WriteStorage<'a> for DoubleBufferedStorage<T> {
+    /// Returns the next value, not the current one.
+    #[inline]
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        cell_mut(&mut self.next, entity)
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
+
+impl<'a, T: 'a + Clone> ComponentStorage<'a> for DoubleBufferedStorage<T> {
+    type Iter = core::iter::Map<
+        core::slice::Iter<'a, Option<(Entity, T)>>,
+        fn(&'a Option<(Entity, T)>) -> Option<&'a T>,
+    >;
+    #[inline]
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.get(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.current.get(id)?.as_ref().map(|(e, _)| *e)
+    }
+    fn set(&mut self, entity: Entity, item: Option<T>) {
+        match item {
+            Some(x) => {
+                if entity.id() >= self.current.len() {
+                    self.current.resize_with(entity.id() + 1, || None);
+                    self.next.resize_with(entity.id() + 1, || None);
+                }
+                self.current[entity.id()] = Some((entity, x.clone()));
+                self.next[entity.id()] = Some((entity, x));
+            }
+            None => {
+                if self.get(entity).is_some() {
+                    self.current[entity.id()] = None;
+                    self.next[entity.id()] = None;
+                }
+            }
+        }
+    }
+    /// Returns the next value, since it's the most recently written.
+    #[inline]
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        self.get(entity)?;
+        self.current[entity.id()] = None;
+        self.next[entity.id()].take().map(|(_, v)| v)
+    }
+    #[inline]
+    fn reserve(&mut self, n: usize) {
+        self.current.reserve(n);
+        self.next.reserve(n);
+    }
+    fn clear(&mut self) {
+        self.current.clear();
+        self.next.clear();
+    }
+    fn flip(&mut self) {
+        core::mem::swap(&mut self.current, &mut self.next);
+        self.next.clone_from(&self.current);
+    }
+    #[inline]
+    fn size(&self) -> usize {
+        self.current.len()
+    }
+    #[inline]
+    fn iter(&'a self) -> Self::Iter {
+        self.current.iter().map(|v| v.as_ref().map(|(_, v)| v))
+    }
+}
+
+impl<'a, T: 'a + Clone> MutableComponentStorage<'a> for DoubleBufferedStorage<T> {
+    type IterMut = core::iter::Map<
+        core::slice::IterMut<'a, Option<(Entity, T)>>,
+        fn(&'a mut Option<(Entity, T)>) -> Option<&'a mut T>,
+    >;
+    /// Iterates over the next buffer.
+    #[inline]
+    fn iter_mut(&'a mut self) -> Self::IterMut {
+        self.next.iter_mut().map(|v| v.as_mut().map(|(_, v)| v))
+    }
+    #[inline]
+    fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
+        self.get_mut(entity)
+            .map_or(core::ptr::null_mut(), |v| v as *mut T)
+    }
+}
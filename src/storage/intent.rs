@@ -0,0 +1,211 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::*;
+
+/// `ComponentStorage` for intents: components that actors add to declare what they want to do
+/// this turn ("move north", "attack that goblin"), for a resolver system to carry out.
+///
+/// A resolver consumes the intents with `resolve`, which removes each one as it's handed over, in
+/// ascending entity id order. Intents that no resolver consumed can be dropped by running
+/// `ClearIntents` after the resolution stage, so they don't carry over into the next turn.
+///
+/// ```
+/// # use ecstatic::*;
+/// let mut intents = IntentStorage::default();
+/// intents.set(Entity::new(4), Some("wait"));
+/// intents.set(Entity::new(1), Some("attack"));
+///
+/// let mut resolved = Vec::new();
+/// intents.resolve(|e, intent| resolved.push((e.id(), intent)));
+/// assert_eq!(resolved, vec![(1, "attack"), (4, "wait")]);
+/// assert_eq!(intents.pending(), 0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct IntentStorage<T> {
+    slots: Vec<Option<(Entity, T)>>,
+    pending: usize,
+}
+
+impl<T> Default for IntentStorage<T> {
+    fn default() -> Self {
+        IntentStorage {
+            slots: Vec::new(),
+            pending: 0,
+        }
+    }
+}
+
+impl<T> IntentStorage<T> {
+    /// The number of intents that haven't been resolved.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Remove every intent, passing each to `f` along with its entity, in ascending id order.
+    pub fn resolve<F: FnMut(Entity, T)>(&mut self, mut f: F) {
+        for slot in self.slots.iter_mut() {
+            if let Some((e, intent)) = slot.take() {
+                f(e, intent);
+            }
+        }
+        self.pending = 0;
+    }
+
+    #[inline]
+    fn cell(&self, entity: Entity) -> Option<&T> {
+        match self.slots.get(entity.id()) {
+            Some(Some((e, v))) if *e == entity => Some(v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn cell_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(entity.id()) {
+            Some(Some((e, v))) if *e == entity => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, T: 'a> ReadStorage<'a> for IntentStorage<T> {
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.cell(entity)
+    }
+}
+
+impl<'a, T: 'a> WriteStorage<'a> for IntentStorage<T> {
+    #[inline]
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.cell_mut(entity)
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
+
+impl<'a, T: 'a> ComponentStorage<'a> for IntentStorage<T> {
+    type Iter = core::iter::Map<
+        core::slice::Iter<'a, Option<(Entity, T)>>,
+        fn(&'a Option<(Entity, T)>) -> Option<&'a T>,
+    >;
+    #[inline]
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.cell(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.slots.get(id)?.as_ref().map(|(e, _)| *e)
+    }
+    fn set(&mut self, entity: Entity, item: Option<T>) {
+        match item {
+            Some(x) => {
+                if entity.id() >= self.slots.len() {
+                    self.slots.resize_with(entity.id() + 1, || None);
+                }
+                if self.slots[entity.id()].replace((entity, x)).is_none() {
+                    self.pending += 1;
+                }
+            }
+            None => {
+                self.take(entity);
+            }
+        }
+    }
+    #[inline]
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        self.cell(entity)?;
+        self.pending -= 1;
+        self.slots[entity.id()].take().map(|(_, v)| v)
+    }
+    #[inline]
+    fn reserve(&mut self, n: usize) {
+        self.slots.reserve(n);
+    }
+    /// Keeps the storage's memory, since it'll be needed again next turn.
+    fn clear(&mut self) {
+        if self.pending > 0 {
+            for slot in self.slots.iter_mut() {
+                *slot = None;
+            }
+            self.pending = 0;
+        }
+    }
+    #[inline]
+    fn size(&self) -> usize {
+        self.slots.len()
+    }
+    #[inline]
+    fn iter(&'a self) -> Self::Iter {
+        self.slots.iter().map(|v| v.as_ref().map(|(_, v)| v))
+    }
+}
+
+impl<'a, T: 'a> MutableComponentStorage<'a> for IntentStorage<T> {
+    type IterMut = core::iter::Map<
+        core::slice::IterMut<'a, Option<(Entity, T)>>,
+        fn(&'a mut Option<(Entity, T)>) -> Option<&'a mut T>,
+    >;
+    #[inline]
+    fn iter_mut(&'a mut self) -> Self::IterMut {
+        self.slots.iter_mut().map(|v| v.as_mut().map(|(_, v)| v))
+    }
+    #[inline]
+    fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
+        self.cell_mut(entity)
+            .map_or(core::ptr::null_mut(), |v| v as *mut T)
+    }
+}
+
+/// System that removes every `T` component. Run it after the systems that resolve `T` intents,
+/// so that unresolved ones don't carry over. See `IntentStorage`.
+pub struct ClearIntents<T>(PhantomData<fn() -> T>);
+
+impl<T> ClearIntents<T> {
+    /// Create the system.
+    pub fn new() -> Self {
+        ClearIntents(PhantomData)
+    }
+}
+
+impl<T> Default for ClearIntents<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T> System<'a> for ClearIntents<T>
+where
+    T: 'a + StorageSpec<'a>,
+    T::Storage: 'a,
+{
+    type Dependencies = (WriteComponent<'a, T>,);
+    fn run(&'a mut self, (mut intents,): Self::Dependencies) {
+        intents.clear();
+    }
+}
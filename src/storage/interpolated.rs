@@ -0,0 +1,201 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// `ComponentStorage` that remembers each component's value as of the last
+/// `WorldInterface::flip`, alongside its current value.
+///
+/// This is for renderers that interpolate between simulation ticks: call `flip` once per tick,
+/// before the simulation systems run, and join over `&storage` and `&storage.prev()` to get both
+/// ends of the interpolation. Apart from that, it behaves like `BasicVecStorage`.
+///
+/// Components that are added (or given to a new entity) start with a previous value equal to
+/// their current one, so they don't appear to move from wherever the last occupant was.
+///
+/// ```
+/// # use ecstatic::*;
+/// let mut storage = InterpolatedStorage::default();
+/// let e = Entity::new(0);
+/// storage.set(e, Some(1.0));
+/// storage.flip();
+/// *storage.get_mut(e).unwrap() = 3.0;
+///
+/// assert_eq!(storage.prev().get(e), Some(&1.0));
+/// assert_eq!(storage.get(e), Some(&3.0));
+/// ```
+#[derive(Clone, Debug)]
+pub struct InterpolatedStorage<T>(Vec<Option<(Entity, T, T)>>);
+
+impl<T> Default for InterpolatedStorage<T> {
+    fn default() -> Self {
+        InterpolatedStorage(Vec::new())
+    }
+}
+
+impl<T> InterpolatedStorage<T> {
+    /// View of the previous values, for joining alongside the current ones.
+    pub fn prev(&self) -> Prev<'_, T> {
+        Prev(self)
+    }
+
+    /// Iterate over the entities that have the component, with its previous and current values.
+    pub fn iter_with_prev(&self) -> impl Iterator<Item = (Entity, &T, &T)> + '_ {
+        self.0.iter().flatten().map(|(e, v, prev)| (*e, prev, v))
+    }
+
+    #[inline]
+    fn slot(&self, entity: Entity) -> Option<&(Entity, T, T)> {
+        match self.0.get(entity.id()) {
+            Some(Some(slot)) if slot.0 == entity => Some(slot),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn slot_mut(&mut self, entity: Entity) -> Option<&mut (Entity, T, T)> {
+        match self.0.get_mut(entity.id()) {
+            Some(Some(slot)) if slot.0 == entity => Some(slot),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, T: 'a + Clone> ReadStorage<'a> for InterpolatedStorage<T> {
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.slot(entity).map(|(_, v, _)| v)
+    }
+}
+
+impl<'a, T: 'a + Clone> WriteStorage<'a> for InterpolatedStorage<T> {
+    #[inline]
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.slot_mut(entity).map(|(_, v, _)| v)
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
+
+impl<'a, T: 'a + Clone> ComponentStorage<'a> for InterpolatedStorage<T> {
+    type Iter = core::iter::Map<
+        core::slice::Iter<'a, Option<(Entity, T, T)>>,
+        fn(&'a Option<(Entity, T, T)>) -> Option<&'a T>,
+    >;
+    #[inline]
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.get(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.get(id)?.as_ref().map(|(e, _, _)| *e)
+    }
+    /// Replacing an entity's component keeps its previous value. (`GetComponent::insert` removes
+    /// the old component first, so it doesn't.)
+    fn set(&mut self, entity: Entity, item: Option<T>) {
+        match item {
+            Some(x) => {
+                if let Some(slot) = self.slot_mut(entity) {
+                    slot.1 = x;
+                    return;
+                }
+                if entity.id() >= self.0.len() {
+                    self.0.resize_with(entity.id() + 1, || None);
+                }
+                self.0[entity.id()] = Some((entity, x.clone(), x));
+            }
+            None => {
+                if self.slot(entity).is_some() {
+                    self.0[entity.id()] = None;
+                }
+            }
+        }
+    }
+    #[inline]
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        self.slot(entity)?;
+        self.0[entity.id()].take().map(|(_, v, _)| v)
+    }
+    #[inline]
+    fn reserve(&mut self, n: usize) {
+        self.0.reserve(n);
+    }
+    fn clear(&mut self) {
+        self.0 = Vec::new();
+    }
+    fn flip(&mut self) {
+        for (_, current, prev) in self.0.iter_mut().flatten() {
+            prev.clone_from(current);
+        }
+    }
+    #[inline]
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+    #[inline]
+    fn iter(&'a self) -> Self::Iter {
+        self.0.iter().map(|v| v.as_ref().map(|(_, v, _)| v))
+    }
+}
+
+impl<'a, T: 'a + Clone> MutableComponentStorage<'a> for InterpolatedStorage<T> {
+    type IterMut = core::iter::Map<
+        core::slice::IterMut<'a, Option<(Entity, T, T)>>,
+        fn(&'a mut Option<(Entity, T, T)>) -> Option<&'a mut T>,
+    >;
+    #[inline]
+    fn iter_mut(&'a mut self) -> Self::IterMut {
+        self.0.iter_mut().map(|v| v.as_mut().map(|(_, v, _)| v))
+    }
+    #[inline]
+    fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
+        self.get_mut(entity)
+            .map_or(core::ptr::null_mut(), |v| v as *mut T)
+    }
+}
+
+/// Joins over the previous values in an `InterpolatedStorage`. See `InterpolatedStorage::prev`.
+pub struct Prev<'a, T>(&'a InterpolatedStorage<T>);
+
+impl<'a, T> Prev<'a, T> {
+    /// The value `entity`'s component had at the last flip.
+    pub fn get(&self, entity: Entity) -> Option<&'a T> {
+        self.0.slot(entity).map(|(_, _, v)| v)
+    }
+
+    pub(crate) fn get_raw(&self, entity: Entity) -> *const T {
+        self.get(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.0 .0.len()
+    }
+
+    pub(crate) fn entity(&self, id: usize) -> Option<Entity> {
+        self.0 .0.get(id)?.as_ref().map(|(e, _, _)| *e)
+    }
+}
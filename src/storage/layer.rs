@@ -0,0 +1,399 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec::Vec;
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
+
+use crate::bitset::*;
+use crate::*;
+
+/// A set of up to 32 layers, as a bitmask. Layer `n` is bit `n`.
+///
+/// Used for collision groups, render layers and the like; see
+/// [`LayerStorage`](struct.LayerStorage.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Layers(pub u32);
+
+impl Layers {
+    /// No layers.
+    pub const NONE: Layers = Layers(0);
+    /// Every layer.
+    pub const ALL: Layers = Layers(!0);
+
+    /// Just layer `n`. Panics if `n` is 32 or more.
+    pub const fn layer(n: u32) -> Layers {
+        assert!(n < 32, "layers are numbered 0 to 31");
+        Layers(1 << n)
+    }
+
+    /// These layers and layer `n`, builder style. Panics if `n` is 32 or more.
+    pub const fn with(self, n: u32) -> Layers {
+        Layers(self.0 | Layers::layer(n).0)
+    }
+
+    /// Whether every layer in `other` is in `self`.
+    pub const fn contains(self, other: Layers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether `self` and `other` have any layers in common.
+    pub const fn intersects(self, other: Layers) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    /// Whether there are no layers.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Add the layers in `other`.
+    pub fn insert(&mut self, other: Layers) {
+        self.0 |= other.0;
+    }
+
+    /// Remove the layers in `other`.
+    pub fn remove(&mut self, other: Layers) {
+        self.0 &= !other.0;
+    }
+
+    /// The numbers of the layers, in ascending order.
+    pub fn iter(self) -> impl Iterator<Item = u32> {
+        self.0.iter().map(|n| n as u32)
+    }
+}
+
+impl BitOr for Layers {
+    type Output = Layers;
+    fn bitor(self, other: Layers) -> Layers {
+        Layers(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Layers {
+    fn bitor_assign(&mut self, other: Layers) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitAnd for Layers {
+    type Output = Layers;
+    fn bitand(self, other: Layers) -> Layers {
+        Layers(self.0 & other.0)
+    }
+}
+
+impl BitAndAssign for Layers {
+    fn bitand_assign(&mut self, other: Layers) {
+        self.0 &= other.0;
+    }
+}
+
+impl Not for Layers {
+    type Output = Layers;
+    fn not(self) -> Layers {
+        Layers(!self.0)
+    }
+}
+
+impl From<u32> for Layers {
+    fn from(bits: u32) -> Self {
+        Layers(bits)
+    }
+}
+
+impl AsRef<Layers> for Layers {
+    fn as_ref(&self) -> &Layers {
+        self
+    }
+}
+
+/// `ComponentStorage` for small, `Copy` components holding a [`Layers`](struct.Layers.html) mask,
+/// such as collision groups or render layers. Which entities have the component is kept in a
+/// bitset, like `VoidStorage`, with the components packed alongside, and
+/// [`in_layers`](#method.in_layers) gives a join filter that only visits the entities in at least
+/// one of the given layers, so collision and rendering systems can skip the rest cheaply.
+///
+/// Since `define_world!` can't pick a storage for a type from another crate, the component is one
+/// of the game's own, implementing `AsRef<Layers>`. That also lets a world have separate masks
+/// for separate purposes.
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// #[derive(Clone, Copy, Default)]
+/// pub struct Collides(Layers);
+/// impl AsRef<Layers> for Collides {
+///     fn as_ref(&self) -> &Layers {
+///         &self.0
+///     }
+/// }
+/// # #[derive(Default)]
+/// # pub struct Position(i32, i32);
+///
+/// const TERRAIN: Layers = Layers::layer(0);
+/// const MONSTERS: Layers = Layers::layer(1);
+///
+/// define_world!(
+///     #[derive(Default)]
+///     pub world {
+///         components {
+///             collision: LayerStorage<Collides>,
+///             positions: BasicVecStorage<Position>,
+///         }
+///         resources {}
+///     }
+/// );
+///
+/// let mut w = World::default();
+/// w.new_entity().with(Position(0, 0)).with(Collides(TERRAIN)).build();
+/// let orc = w.new_entity().with(Position(1, 0)).with(Collides(MONSTERS)).build();
+///
+/// let (positions, collision) = w
+///     .fetch::<(ReadComponent<Position>, ReadComponent<Collides>)>()
+///     .unwrap();
+/// let mut hit = Vec::new();
+/// (&positions, collision.in_layers(MONSTERS)).for_each(|e, _| hit.push(e));
+/// assert_eq!(hit, vec![orc]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LayerStorage<T> {
+    // Bit `id % 32` of word `id / 32` is set if entity id `id` has the component.
+    present: Vec<u32>,
+    // The generation of the entity in each set slot.
+    generations: Vec<u32>,
+    values: Vec<T>,
+}
+
+impl<T: AsRef<Layers>> LayerStorage<T> {
+    /// A join filter that only lets through the entities whose component shares a layer with
+    /// `mask`. Like `Visibility`, it contributes `()` to the join's output, and checks for
+    /// disabled entities are left to the other views in the join.
+    pub fn in_layers(&self, mask: Layers) -> InLayers<'_, T> {
+        InLayers {
+            storage: self,
+            mask,
+        }
+    }
+
+    /// `entity`'s layers, or `Layers::NONE` if it doesn't have the component.
+    #[inline]
+    pub fn layers(&self, entity: Entity) -> Layers {
+        if self.contains(entity) {
+            *self.values[entity.id()].as_ref()
+        } else {
+            Layers::NONE
+        }
+    }
+}
+
+impl<T> LayerStorage<T> {
+    #[inline]
+    fn contains(&self, entity: Entity) -> bool {
+        self.contains_id(entity.id()) && self.generations[entity.id()] == entity.generation()
+    }
+
+    #[inline]
+    fn contains_id(&self, id: usize) -> bool {
+        id / 32 < self.present.len() && self.present[id / 32].get_bit(id % 32)
+    }
+}
+
+impl<'a, T: 'a> ReadStorage<'a> for LayerStorage<T> {
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        if self.contains(entity) {
+            Some(&self.values[entity.id()])
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, T: 'a + Copy + Default> WriteStorage<'a> for LayerStorage<T> {
+    #[inline]
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        if self.contains(entity) {
+            Some(&mut self.values[entity.id()])
+        } else {
+            None
+        }
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
+
+impl<'a, T: 'a + Copy + Default> ComponentStorage<'a> for LayerStorage<T> {
+    type Iter = LayerStorageIter<'a, T>;
+    #[inline]
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.get(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        if self.contains_id(id) {
+            Entity::from_bits((u64::from(self.generations[id]) << 32) | id as u64)
+        } else {
+            None
+        }
+    }
+    fn set(&mut self, entity: Entity, item: Option<T>) {
+        match item {
+            Some(x) => {
+                if entity.id() / 32 >= self.present.len() {
+                    self.present.resize(entity.id() / 32 + 1, 0);
+                    self.generations.resize(self.present.len() * 32, 0);
+                    self.values.resize(self.present.len() * 32, T::default());
+                }
+                self.present[entity.id() / 32].set_bit(entity.id() % 32);
+                self.generations[entity.id()] = entity.generation();
+                self.values[entity.id()] = x;
+            }
+            None => {
+                if self.contains(entity) {
+                    self.present[entity.id() / 32].clear_bit(entity.id() % 32);
+                }
+            }
+        }
+    }
+    #[inline]
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        if self.contains(entity) {
+            self.present[entity.id() / 32].clear_bit(entity.id() % 32);
+            Some(self.values[entity.id()])
+        } else {
+            None
+        }
+    }
+    #[inline]
+    fn reserve(&mut self, n: usize) {
+        self.present.reserve(n.div_ceil(32));
+        self.generations.reserve(n.div_ceil(32) * 32);
+        self.values.reserve(n.div_ceil(32) * 32);
+    }
+    fn clear(&mut self) {
+        self.present = Vec::new();
+        self.generations = Vec::new();
+        self.values = Vec::new();
+    }
+    #[inline]
+    fn size(&self) -> usize {
+        self.present.len() * 32
+    }
+    #[inline]
+    fn iter(&'a self) -> Self::Iter {
+        LayerStorageIter {
+            storage: self,
+            next: 0,
+        }
+    }
+}
+
+impl<'a, T: 'a + Copy + Default> MutableComponentStorage<'a> for LayerStorage<T> {
+    type IterMut = LayerStorageIterMut<'a, T>;
+    #[inline]
+    fn iter_mut(&'a mut self) -> Self::IterMut {
+        LayerStorageIterMut {
+            present: &self.present,
+            values: self.values.iter_mut().enumerate(),
+        }
+    }
+    #[inline]
+    fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
+        self.get_mut(entity)
+            .map_or(core::ptr::null_mut(), |v| v as *mut T)
+    }
+}
+
+/// Iterator for `LayerStorage<T>`.
+pub struct LayerStorageIter<'a, T> {
+    storage: &'a LayerStorage<T>,
+    next: usize,
+}
+
+impl<'a, T> Iterator for LayerStorageIter<'a, T> {
+    type Item = Option<&'a T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.next;
+        if id >= self.storage.values.len() {
+            return None;
+        }
+        self.next += 1;
+        if self.storage.contains_id(id) {
+            Some(Some(&self.storage.values[id]))
+        } else {
+            Some(None)
+        }
+    }
+}
+
+/// Mutable iterator for `LayerStorage<T>`.
+pub struct LayerStorageIterMut<'a, T> {
+    present: &'a [u32],
+    values: core::iter::Enumerate<core::slice::IterMut<'a, T>>,
+}
+
+impl<'a, T> Iterator for LayerStorageIterMut<'a, T> {
+    type Item = Option<&'a mut T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, v) = self.values.next()?;
+        if self.present[id / 32].get_bit(id % 32) {
+            Some(Some(v))
+        } else {
+            Some(None)
+        }
+    }
+}
+
+/// Join filter for the entities in any of a set of layers. See `LayerStorage::in_layers`.
+pub struct InLayers<'a, T> {
+    storage: &'a LayerStorage<T>,
+    mask: Layers,
+}
+
+impl<T: AsRef<Layers>> InLayers<'_, T> {
+    /// Whether `entity` is in one of the layers.
+    #[inline]
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.storage.layers(entity).intersects(self.mask)
+    }
+
+    #[inline]
+    pub(crate) fn size(&self) -> usize {
+        self.storage.present.len() * 32
+    }
+
+    // The entity in slot `id`, if it's in one of the layers.
+    #[inline]
+    pub(crate) fn entity(&self, id: usize) -> Option<Entity> {
+        if self.storage.contains_id(id) && self.storage.values[id].as_ref().intersects(self.mask) {
+            Entity::from_bits((u64::from(self.storage.generations[id]) << 32) | id as u64)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// `ComponentStorage` that can hold several components of the same type for each entity, like
+/// status effects or wounds.
+///
+/// Through the `ComponentStorage` interface (including plain joins), an entity's component is the
+/// first one it was given. Use `all`/`all_mut` to join over entities that have at least one
+/// component, getting all of them as a slice:
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// #[derive(Debug, PartialEq)]
+/// pub struct Wound(u32);
+///
+/// define_world!(
+///     #[derive(Default)]
+///     pub world {
+///         components {
+///             wounds: MultiStorage<Wound>,
+///         }
+///         resources {}
+///     }
+/// );
+///
+/// struct Bleed(u32);
+/// impl<'a> System<'a> for Bleed {
+///     type Dependencies = (ReadComponent<'a, Wound>,);
+///     fn run(&'a mut self, (wounds,): Self::Dependencies) {
+///         (&wounds.all(),).for_each(|_, (w,)| {
+///             self.0 += w.iter().map(|w| w.0).sum::<u32>();
+///         });
+///     }
+/// }
+///
+/// let mut w = World::default();
+/// let e = w.new_entity().with(Wound(1)).build();
+/// <World as GetComponent<'_, Wound>>::get_mut(&w).push(e, Wound(2));
+/// w.new_entity().build();
+///
+/// let mut bleed = Bleed(0);
+/// w.run_system(&mut bleed);
+/// assert_eq!(bleed.0, 3);
+/// assert_eq!(<World as GetComponent<'_, Wound>>::get(&w).get(e), Some(&Wound(1)));
+/// ```
+#[derive(Clone, Debug)]
+pub struct MultiStorage<T>(Vec<Option<(Entity, Vec<T>)>>);
+
+impl<T> Default for MultiStorage<T> {
+    fn default() -> Self {
+        MultiStorage(Vec::new())
+    }
+}
+
+impl<T> MultiStorage<T> {
+    // The components of `entity`, if they're stored for the right generation.
+    #[inline]
+    fn slot(&self, entity: Entity) -> Option<&Vec<T>> {
+        match self.0.get(entity.id()) {
+            Some(Some((e, v))) if *e == entity => Some(v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn slot_mut(&mut self, entity: Entity) -> Option<&mut Vec<T>> {
+        match self.0.get_mut(entity.id()) {
+            Some(Some((e, v))) if *e == entity => Some(v),
+            _ => None,
+        }
+    }
+
+    /// All of the components of `entity`. Empty if it has none.
+    pub fn get_all(&self, entity: Entity) -> &[T] {
+        self.slot(entity).map_or(&[], |v| &v[..])
+    }
+
+    /// All of the components of `entity`, mutably. Empty if it has none.
+    pub fn get_all_mut(&mut self, entity: Entity) -> &mut [T] {
+        self.slot_mut(entity).map_or(&mut [], |v| &mut v[..])
+    }
+
+    /// Give `entity` another component.
+    pub fn push(&mut self, entity: Entity, item: T) {
+        match self.slot_mut(entity) {
+            Some(v) => v.push(item),
+            None => {
+                if entity.id() >= self.0.len() {
+                    self.0.resize_with(entity.id() + 1, || None);
+                }
+                self.0[entity.id()] = Some((entity, vec![item]));
+            }
+        }
+    }
+
+    /// Keep only the components of `entity` for which `f` returns `true`.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, entity: Entity, f: F) {
+        if let Some(v) = self.slot_mut(entity) {
+            v.retain(f);
+            if v.is_empty() {
+                self.0[entity.id()] = None;
+            }
+        }
+    }
+
+    /// View for joining over every entity with at least one component, yielding all of them.
+    pub fn all(&self) -> MultiView<'_, T> {
+        MultiView(self)
+    }
+
+    /// Mutable version of `all`.
+    pub fn all_mut(&mut self) -> MultiViewMut<'_, T> {
+        MultiViewMut(self)
+    }
+}
+
+impl<'a, T: 'a> ReadStorage<'a> for MultiStorage<T> {
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.slot(entity).and_then(|v| v.first())
+    }
+}
+
+impl<'a, T: 'a> WriteStorage<'a> for MultiStorage<T> {
+    #[inline]
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.slot_mut(entity).and_then(|v| v.first_mut())
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
+
+impl<'a, T: 'a> ComponentStorage<'a> for MultiStorage<T> {
+    type Iter = core::iter::Map<
+        core::slice::Iter<'a, Option<(Entity, Vec<T>)>>,
+        fn(&'a Option<(Entity, Vec<T>)>) -> Option<&'a T>,
+    >;
+    #[inline]
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.get(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.0.get(id)?.as_ref().map(|(e, _)| *e)
+    }
+    /// Replaces all of the entity's components with `item`.
+    fn set(&mut self, entity: Entity, item: Option<T>) {
+        match item {
+            Some(x) => {
+                if entity.id() >= self.0.len() {
+                    self.0.resize_with(entity.id() + 1, || None);
+                }
+                self.0[entity.id()] = Some((entity, vec![x]));
+            }
+            None => {
+                if self.slot(entity).is_some() {
+                    self.0[entity.id()] = None;
+                }
+            }
+        }
+    }
+    /// Removes and returns the entity's first component, leaving the rest.
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        let v = self.slot_mut(entity)?;
+        let first = v.remove(0);
+        if v.is_empty() {
+            self.0[entity.id()] = None;
+        }
+        Some(first)
+    }
+    #[inline]
+    fn reserve(&mut self, n: usize) {
+        self.0.reserve(n);
+    }
+    fn clear(&mut self) {
+        self.0 = Vec::new();
+    }
+    #[inline]
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+    #[inline]
+    fn iter(&'a self) -> Self::Iter {
+        self.0
+            .iter()
+            .map(|v| v.as_ref().and_then(|(_, v)| v.first()))
+    }
+}
+
+impl<'a, T: 'a> MutableComponentStorage<'a> for MultiStorage<T> {
+    type IterMut = core::iter::Map<
+        core::slice::IterMut<'a, Option<(Entity, Vec<T>)>>,
+        fn(&'a mut Option<(Entity, Vec<T>)>) -> Option<&'a mut T>,
+    >;
+    #[inline]
+    fn iter_mut(&'a mut self) -> Self::IterMut {
+        self.0
+            .iter_mut()
+            .map(|v| v.as_mut().and_then(|(_, v)| v.first_mut()))
+    }
+    #[inline]
+    fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
+        self.get_mut(entity)
+            .map_or(core::ptr::null_mut(), |v| v as *mut T)
+    }
+}
+
+/// Joins over the entities in a `MultiStorage` that have at least one component, yielding all of
+/// them. See `MultiStorage::all`.
+pub struct MultiView<'a, T>(&'a MultiStorage<T>);
+
+/// Mutable version of `MultiView`. See `MultiStorage::all_mut`.
+pub struct MultiViewMut<'a, T>(&'a mut MultiStorage<T>);
+
+impl<'a, T> MultiView<'a, T> {
+    pub(crate) fn get_raw(&self, entity: Entity) -> *const [T] {
+        match self.0.slot(entity) {
+            Some(v) if !v.is_empty() => &v[..] as *const [T],
+            _ => core::ptr::slice_from_raw_parts(core::ptr::null(), 0),
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.0 .0.len()
+    }
+
+    pub(crate) fn entity(&self, id: usize) -> Option<Entity> {
+        self.0 .0.get(id)?.as_ref().map(|(e, _)| *e)
+    }
+}
+
+impl<'a, T> MultiViewMut<'a, T> {
+    pub(crate) fn get_raw_mut(&mut self, entity: Entity) -> *mut [T] {
+        match self.0.slot_mut(entity) {
+            Some(v) if !v.is_empty() => &mut v[..] as *mut [T],
+            _ => core::ptr::slice_from_raw_parts_mut(core::ptr::null_mut(), 0),
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.0 .0.len()
+    }
+
+    pub(crate) fn entity(&self, id: usize) -> Option<Entity> {
+        self.0 .0.get(id)?.as_ref().map(|(e, _)| *e)
+    }
+}
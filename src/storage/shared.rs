@@ -0,0 +1,359 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+
+use crate::hash::{DefaultHasher, HashMap};
+use crate::*;
+
+#[derive(Clone, Debug)]
+struct PoolEntry<T> {
+    value: T,
+    refs: usize,
+    // Whether the entry is in the storage's index. Entries that have been handed out mutably
+    // aren't, since their value may have changed.
+    interned: bool,
+}
+
+/// `ComponentStorage` for data that many entities have identical copies of, like tile sprites or
+/// material definitions.
+///
+/// Each distinct value is stored once, in a pool, and entities hold a handle into the pool.
+/// Mutating an entity's component (through `get_mut` or a mutable join) gives it its own copy
+/// first, so other entities sharing the value are unaffected. Mutated values aren't deduplicated
+/// again until `compact` is called.
+///
+/// ```
+/// # use ecstatic::*;
+/// let mut storage = SharedStorage::default();
+/// let (a, b, c) = (Entity::new(0), Entity::new(1), Entity::new(2));
+/// storage.set(a, Some("grass"));
+/// storage.set(b, Some("grass"));
+/// storage.set(c, Some("water"));
+/// assert_eq!(storage.distinct(), 2);
+///
+/// *storage.get_mut(b).unwrap() = "water";
+/// assert_eq!(storage.get(a), Some(&"grass"));
+/// assert_eq!(storage.distinct(), 3);
+///
+/// storage.compact();
+/// assert_eq!(storage.distinct(), 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct SharedStorage<T> {
+    // The entity in each slot and the index of its value in `pool`.
+    slots: Vec<Option<(Entity, usize)>>,
+    // Boxed so that values don't move when the pool grows: `get_raw_mut` can add an entry while
+    // a join is still holding references to others.
+    pool: Vec<Option<Box<PoolEntry<T>>>>,
+    free: Vec<usize>,
+    // Interned pool entries, by the hash of their value.
+    index: HashMap<u64, Vec<usize>>,
+}
+
+impl<T> Default for SharedStorage<T> {
+    fn default() -> Self {
+        SharedStorage {
+            slots: Vec::new(),
+            pool: Vec::new(),
+            free: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+}
+
+fn hash_of<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<T: Eq + Hash + Clone> SharedStorage<T> {
+    /// The number of distinct values stored.
+    pub fn distinct(&self) -> usize {
+        self.pool.len() - self.free.len()
+    }
+
+    /// Deduplicate values that have been modified since they were stored.
+    pub fn compact(&mut self) {
+        let mut remap: Vec<usize> = (0..self.pool.len()).collect();
+        for (i, target) in remap.iter_mut().enumerate() {
+            let (hash, existing) = match self.pool[i] {
+                Some(ref entry) if !entry.interned => {
+                    let hash = hash_of(&entry.value);
+                    let pool = &self.pool;
+                    let existing = self.index.get(&hash).and_then(|bucket| {
+                        bucket
+                            .iter()
+                            .copied()
+                            .find(|&k| pool[k].as_ref().unwrap().value == entry.value)
+                    });
+                    (hash, existing)
+                }
+                _ => continue,
+            };
+            match existing {
+                Some(k) => {
+                    let refs = self.pool[i].take().unwrap().refs;
+                    self.pool[k].as_mut().unwrap().refs += refs;
+                    self.free.push(i);
+                    *target = k;
+                }
+                None => {
+                    self.pool[i].as_mut().unwrap().interned = true;
+                    self.index.entry(hash).or_default().push(i);
+                }
+            }
+        }
+        for slot in self.slots.iter_mut().flatten() {
+            slot.1 = remap[slot.1];
+        }
+    }
+
+    // The pool index of `entity`'s value, if it's stored for the right generation.
+    #[inline]
+    fn slot(&self, entity: Entity) -> Option<usize> {
+        match self.slots.get(entity.id()) {
+            Some(Some((e, i))) if *e == entity => Some(*i),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn value(&self, i: usize) -> &T {
+        &self.pool[i].as_ref().unwrap().value
+    }
+
+    fn alloc(&mut self, entry: PoolEntry<T>) -> usize {
+        match self.free.pop() {
+            Some(i) => {
+                self.pool[i] = Some(Box::new(entry));
+                i
+            }
+            None => {
+                self.pool.push(Some(Box::new(entry)));
+                self.pool.len() - 1
+            }
+        }
+    }
+
+    fn intern(&mut self, value: T) -> usize {
+        let hash = hash_of(&value);
+        if let Some(bucket) = self.index.get(&hash) {
+            for &i in bucket {
+                let entry = self.pool[i].as_mut().unwrap();
+                if entry.value == value {
+                    entry.refs += 1;
+                    return i;
+                }
+            }
+        }
+        let i = self.alloc(PoolEntry {
+            value,
+            refs: 1,
+            interned: true,
+        });
+        self.index.entry(hash).or_default().push(i);
+        i
+    }
+
+    fn unintern(&mut self, i: usize) {
+        let entry = self.pool[i].as_mut().unwrap();
+        if entry.interned {
+            entry.interned = false;
+            let hash = hash_of(&entry.value);
+            if let Some(bucket) = self.index.get_mut(&hash) {
+                bucket.retain(|&j| j != i);
+                if bucket.is_empty() {
+                    self.index.remove(&hash);
+                }
+            }
+        }
+    }
+
+    fn release(&mut self, i: usize) {
+        let entry = self.pool[i].as_mut().unwrap();
+        entry.refs -= 1;
+        if entry.refs == 0 {
+            self.unintern(i);
+            self.pool[i] = None;
+            self.free.push(i);
+        }
+    }
+
+    // Give the value in slot `id` a pool entry of its own, and return its index.
+    fn make_unique(&mut self, id: usize) -> usize {
+        let (entity, i) = self.slots[id].unwrap();
+        let entry = self.pool[i].as_mut().unwrap();
+        if entry.refs > 1 {
+            entry.refs -= 1;
+            let value = entry.value.clone();
+            let j = self.alloc(PoolEntry {
+                value,
+                refs: 1,
+                interned: false,
+            });
+            self.slots[id] = Some((entity, j));
+            j
+        } else {
+            self.unintern(i);
+            i
+        }
+    }
+}
+
+impl<'a, T> ReadStorage<'a> for SharedStorage<T>
+where
+    T: 'a + Eq + Hash + Clone,
+{
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.slot(entity).map(|i| self.value(i))
+    }
+}
+
+impl<'a, T> WriteStorage<'a> for SharedStorage<T>
+where
+    T: 'a + Eq + Hash + Clone,
+{
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.slot(entity)?;
+        let i = self.make_unique(entity.id());
+        Some(&mut self.pool[i].as_mut().unwrap().value)
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
+
+impl<'a, T> ComponentStorage<'a> for SharedStorage<T>
+where
+    T: 'a + Eq + Hash + Clone,
+{
+    type Iter = SharedStorageIter<'a, T>;
+    #[inline]
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.get(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.slots.get(id)?.map(|(e, _)| e)
+    }
+    fn set(&mut self, entity: Entity, item: Option<T>) {
+        match item {
+            Some(value) => {
+                if entity.id() >= self.slots.len() {
+                    self.slots.resize(entity.id() + 1, None);
+                }
+                if let Some((_, old)) = self.slots[entity.id()] {
+                    self.release(old);
+                }
+                let i = self.intern(value);
+                self.slots[entity.id()] = Some((entity, i));
+            }
+            None => {
+                if let Some(i) = self.slot(entity) {
+                    self.release(i);
+                    self.slots[entity.id()] = None;
+                }
+            }
+        }
+    }
+    /// Moves the value out of the pool if no other entity shares it, and clones it otherwise.
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        let i = self.slot(entity)?;
+        self.slots[entity.id()] = None;
+        if self.pool[i].as_ref().unwrap().refs > 1 {
+            self.pool[i].as_mut().unwrap().refs -= 1;
+            Some(self.value(i).clone())
+        } else {
+            self.unintern(i);
+            self.free.push(i);
+            self.pool[i].take().map(|entry| entry.value)
+        }
+    }
+    #[inline]
+    fn reserve(&mut self, n: usize) {
+        self.slots.reserve(n);
+    }
+    fn clear(&mut self) {
+        *self = SharedStorage::default();
+    }
+    #[inline]
+    fn size(&self) -> usize {
+        self.slots.len()
+    }
+    #[inline]
+    fn iter(&'a self) -> Self::Iter {
+        SharedStorageIter {
+            slots: self.slots.iter(),
+            pool: &self.pool,
+        }
+    }
+}
+
+impl<'a, T> MutableComponentStorage<'a> for SharedStorage<T>
+where
+    T: 'a + Eq + Hash + Clone,
+{
+    type IterMut = alloc::vec::IntoIter<Option<&'a mut T>>;
+    fn iter_mut(&'a mut self) -> Self::IterMut {
+        for id in 0..self.slots.len() {
+            if self.slots[id].is_some() {
+                self.make_unique(id);
+            }
+        }
+        let pool = self.pool.as_mut_ptr();
+        // This unsafe block should be sound, because every slot now has its own pool entry, so
+        // the returned references don't alias, and their borrows are tied to `&mut self`.
+        self.slots
+            .iter()
+            .map(|slot| slot.map(|(_, i)| unsafe { &mut (*pool.add(i)).as_mut().unwrap().value }))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+    fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
+        self.get_mut(entity)
+            .map_or(core::ptr::null_mut(), |v| v as *mut T)
+    }
+}
+
+/// Iterator for `SharedStorage<T>`.
+pub struct SharedStorageIter<'a, T> {
+    slots: core::slice::Iter<'a, Option<(Entity, usize)>>,
+    pool: &'a [Option<Box<PoolEntry<T>>>],
+}
+
+impl<'a, T> Iterator for SharedStorageIter<'a, T> {
+    type Item = Option<&'a T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let pool = self.pool;
+        self.slots
+            .next()
+            .map(|slot| slot.map(|(_, i)| &pool[i].as_ref().unwrap().value))
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec::Vec;
+
+use crate::*;
+
+#[derive(Clone, Debug)]
+enum Slots<T, const N: usize> {
+    Inline([Option<(Entity, T)>; N]),
+    Heap(Vec<Option<(Entity, T)>>),
+}
+
+/// `ComponentStorage` that keeps the components of the first `N` entity ids inline, only moving
+/// to the heap once an entity with a larger id is given the component.
+///
+/// Meant for small auxiliary worlds (menus, UI scenes) where a handful of entities exist at a
+/// time, so a heap allocation per storage is pure overhead. In `define_world!`, the extra
+/// parameter goes after the component type, e.g. `labels: SmallVecStorage<Label, 16>`.
+///
+/// ```
+/// # use ecstatic::*;
+/// let mut storage = SmallVecStorage::<u32, 8>::default();
+/// storage.set(Entity::new(3), Some(1));
+/// assert!(!storage.spilled());
+///
+/// storage.set(Entity::new(8), Some(2));
+/// assert!(storage.spilled());
+/// assert_eq!(storage.get(Entity::new(3)), Some(&1));
+/// ```
+#[derive(Clone, Debug)]
+pub struct SmallVecStorage<T, const N: usize> {
+    slots: Slots<T, N>,
+}
+
+impl<T, const N: usize> Default for SmallVecStorage<T, N> {
+    fn default() -> Self {
+        SmallVecStorage {
+            slots: Slots::Inline(core::array::from_fn(|_| None)),
+        }
+    }
+}
+
+impl<T, const N: usize> SmallVecStorage<T, N> {
+    /// Whether the storage has moved its components to the heap.
+    pub fn spilled(&self) -> bool {
+        matches!(self.slots, Slots::Heap(_))
+    }
+
+    #[inline]
+    fn as_slice(&self) -> &[Option<(Entity, T)>] {
+        match self.slots {
+            Slots::Inline(ref a) => &a[..],
+            Slots::Heap(ref v) => &v[..],
+        }
+    }
+
+    #[inline]
+    fn as_mut_slice(&mut self) -> &mut [Option<(Entity, T)>] {
+        match self.slots {
+            Slots::Inline(ref mut a) => &mut a[..],
+            Slots::Heap(ref mut v) => &mut v[..],
+        }
+    }
+
+    #[inline]
+    fn cell(&self, entity: Entity) -> Option<&T> {
+        match self.as_slice().get(entity.id()) {
+            Some(Some((e, v))) if *e == entity => Some(v),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn cell_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.as_mut_slice().get_mut(entity.id()) {
+            Some(Some((e, v))) if *e == entity => Some(v),
+            _ => None,
+        }
+    }
+
+    // Make sure there is a slot for entity id `id`, spilling to the heap if necessary.
+    fn grow(&mut self, id: usize) {
+        match self.slots {
+            Slots::Inline(ref mut a) if id >= N => {
+                let mut v = Vec::with_capacity(id + 1);
+                v.extend(a.iter_mut().map(Option::take));
+                v.resize_with(id + 1, || None);
+                self.slots = Slots::Heap(v);
+            }
+            Slots::Heap(ref mut v) if id >= v.len() => v.resize_with(id + 1, || None),
+            _ => {}
+        }
+    }
+}
+
+impl<'a, T: 'a, const N: usize> ReadStorage<'a> for SmallVecStorage<T, N> {
+    type Component = T;
+    #[inline]
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.cell(entity)
+    }
+}
+
+impl<'a, T: 'a, const N: usize> WriteStorage<'a> for SmallVecStorage<T, N> {
+    #[inline]
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.cell_mut(entity)
+    }
+    #[inline]
+    fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        let old = self.take(entity);
+        self.set(entity, Some(value));
+        old
+    }
+    #[inline]
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.take(entity)
+    }
+}
+
+impl<'a, T: 'a, const N: usize> ComponentStorage<'a> for SmallVecStorage<T, N> {
+    type Iter = core::iter::Map<
+        core::slice::Iter<'a, Option<(Entity, T)>>,
+        fn(&'a Option<(Entity, T)>) -> Option<&'a T>,
+    >;
+    #[inline]
+    fn get_raw(&self, entity: Entity) -> *const T {
+        self.cell(entity)
+            .map_or(core::ptr::null(), |v| v as *const T)
+    }
+    #[inline]
+    fn entity(&self, id: usize) -> Option<Entity> {
+        self.as_slice().get(id)?.as_ref().map(|(e, _)| *e)
+    }
+    fn set(&mut self, entity: Entity, item: Option<T>) {
+        match item {
+            Some(x) => {
+                self.grow(entity.id());
+                self.as_mut_slice()[entity.id()] = Some((entity, x));
+            }
+            None => {
+                if self.cell(entity).is_some() {
+                    self.as_mut_slice()[entity.id()] = None;
+                }
+            }
+        }
+    }
+    #[inline]
+    fn take(&mut self, entity: Entity) -> Option<T> {
+        self.cell(entity)?;
+        self.as_mut_slice()[entity.id()].take().map(|(_, v)| v)
+    }
+    #[inline]
+    fn reserve(&mut self, n: usize) {
+        if let Slots::Heap(ref mut v) = self.slots {
+            v.reserve(n);
+        }
+    }
+    fn clear(&mut self) {
+        *self = SmallVecStorage::default();
+    }
+    #[inline]
+    fn size(&self) -> usize {
+        self.as_slice().len()
+    }
+    #[inline]
+    fn iter(&'a self) -> Self::Iter {
+        self.as_slice().iter().map(|v| v.as_ref().map(|(_, v)| v))
+    }
+}
+
+impl<'a, T: 'a, const N: usize> MutableComponentStorage<'a> for SmallVecStorage<T, N> {
+    type IterMut = core::iter::Map<
+        core::slice::IterMut<'a, Option<(Entity, T)>>,
+        fn(&'a mut Option<(Entity, T)>) -> Option<&'a mut T>,
+    >;
+    #[inline]
+    fn iter_mut(&'a mut self) -> Self::IterMut {
+        self.as_mut_slice()
+            .iter_mut()
+            .map(|v| v.as_mut().map(|(_, v)| v))
+    }
+    #[inline]
+    fn get_raw_mut(&mut self, entity: Entity) -> *mut T {
+        self.cell_mut(entity)
+            .map_or(core::ptr::null_mut(), |v| v as *mut T)
+    }
+}
@@ -0,0 +1,428 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Synchronization primitives for running systems on more than one thread.
+//!
+//! Worlds are single-threaded, but the state that an executor shares between worker threads
+//! needs to be guarded. This module has the pieces that need care to get right, for use by the
+//! crate's own executors and by anyone building their own:
+//!
+//! * [`StorageLock`](struct.StorageLock.html) holds a storage (or any other value) that threads
+//!   claim for reading or writing without blocking. A claim that conflicts with one that's
+//!   already held fails, rather than waiting, since a scheduler that runs two conflicting systems
+//!   at once has a bug to report, not a lock to wait on.
+//! * [`CommandQueue`](struct.CommandQueue.html) collects changes to a world from any number of
+//!   threads, to be applied on the thread that owns the world, like a shared
+//!   [`Commands`](../command/struct.Commands.html).
+//! * [`EntityAllocator`](struct.EntityAllocator.html) hands out handles for new entities from
+//!   any thread, from a block reserved in the world up front.
+//!
+//! For state that's only occasionally shared, [`cell::RwLock`](../cell/struct.RwLock.html) is a
+//! plain blocking lock.
+//!
+//! The primitives are checked with [loom](https://docs.rs/loom), which runs the tests in
+//! `src/tests.rs` marked `#[cfg(loom)]` under every interleaving of their threads:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib loom
+//! ```
+//!
+//! This module needs the `std` feature.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! use ecstatic::sync::*;
+//! use std::sync::Arc;
+//! use std::thread;
+//!
+//! #[derive(Debug, Default, PartialEq)]
+//! pub struct Chunk(u32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             chunks: BasicVecStorage<Chunk>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let meshes = Arc::new(StorageLock::new(Vec::new()));
+//! let queue = Arc::new(CommandQueue::<World>::new());
+//!
+//! let workers: Vec<_> = (0..4)
+//!     .map(|i| {
+//!         let (meshes, queue) = (meshes.clone(), queue.clone());
+//!         thread::spawn(move || {
+//!             // Only one worker can write at a time; the others try again.
+//!             loop {
+//!                 if let Some(mut meshes) = meshes.try_write() {
+//!                     meshes.push(i);
+//!                     break;
+//!                 }
+//!                 thread::yield_now();
+//!             }
+//!             queue.push(move |w: &mut World| {
+//!                 w.new_entity().with(Chunk(i)).build();
+//!             });
+//!         })
+//!     })
+//!     .collect();
+//! for worker in workers {
+//!     worker.join().unwrap();
+//! }
+//!
+//! assert_eq!(meshes.try_read().unwrap().len(), 4);
+//! assert_eq!(queue.len(), 4);
+//! queue.apply(&mut w);
+//! assert_eq!(w.component::<Chunk>().iter().flatten().count(), 4);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+use self::shim::{AtomicIsize, AtomicU32, ConstPtr, MutPtr, Mutex, Ordering, UnsafeCell};
+use crate::{Entity, WorldInterface};
+
+// The primitives are built on these, which are loom's versions under `cfg(loom)`.
+#[cfg(loom)]
+mod shim {
+    pub(crate) use loom::cell::{ConstPtr, MutPtr, UnsafeCell};
+    pub(crate) use loom::sync::atomic::{AtomicIsize, AtomicU32, Ordering};
+    pub(crate) use loom::sync::Mutex;
+}
+
+#[cfg(not(loom))]
+mod shim {
+    pub(crate) use std::sync::atomic::{AtomicIsize, AtomicU32, Ordering};
+    pub(crate) use std::sync::Mutex;
+
+    // `core::cell::UnsafeCell` with the same interface as loom's, which tracks each access for as
+    // long as its pointer is kept.
+    #[derive(Debug, Default)]
+    pub(crate) struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+    impl<T> UnsafeCell<T> {
+        pub(crate) fn new(value: T) -> Self {
+            UnsafeCell(core::cell::UnsafeCell::new(value))
+        }
+
+        pub(crate) fn get(&self) -> ConstPtr<T> {
+            ConstPtr(self.0.get())
+        }
+
+        pub(crate) fn get_mut(&self) -> MutPtr<T> {
+            MutPtr(self.0.get())
+        }
+
+        pub(crate) fn into_inner(self) -> T {
+            self.0.into_inner()
+        }
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct ConstPtr<T>(*const T);
+
+    impl<T> ConstPtr<T> {
+        pub(crate) unsafe fn deref(&self) -> &T {
+            &*self.0
+        }
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct MutPtr<T>(*mut T);
+
+    impl<T> MutPtr<T> {
+        #[allow(clippy::mut_from_ref)]
+        pub(crate) unsafe fn deref(&self) -> &mut T {
+            &mut *self.0
+        }
+    }
+}
+
+/// A value that threads claim for reading or writing without blocking: any number of readers, or
+/// one writer. See the [module-level documentation](index.html).
+pub struct StorageLock<T> {
+    // The number of readers, or -1 while there's a writer.
+    state: AtomicIsize,
+    value: UnsafeCell<T>,
+}
+
+// The value is only reached through claims that follow the borrowing rules, as for `RwLock`.
+unsafe impl<T: Send> Send for StorageLock<T> {}
+unsafe impl<T: Send + Sync> Sync for StorageLock<T> {}
+
+impl<T> StorageLock<T> {
+    /// Wrap `value`.
+    pub fn new(value: T) -> Self {
+        StorageLock {
+            state: AtomicIsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Unwrap the value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// Claim the value for reading, unless a writer has it.
+    pub fn try_read(&self) -> Option<StorageReadGuard<'_, T>> {
+        let mut readers = self.state.load(Ordering::Relaxed);
+        loop {
+            if readers < 0 {
+                return None;
+            }
+            match self.state.compare_exchange_weak(
+                readers,
+                readers + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(StorageReadGuard {
+                        lock: self,
+                        value: self.value.get(),
+                    })
+                }
+                Err(actual) => readers = actual,
+            }
+        }
+    }
+
+    /// Claim the value for writing, unless anyone else has it.
+    pub fn try_write(&self) -> Option<StorageWriteGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, -1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        Some(StorageWriteGuard {
+            lock: self,
+            value: self.value.get_mut(),
+        })
+    }
+
+    /// Whether the value is claimed at all. This is only a snapshot, since another thread may
+    /// claim or release it straight afterwards.
+    pub fn is_claimed(&self) -> bool {
+        self.state.load(Ordering::Relaxed) != 0
+    }
+}
+
+impl<T: Default> Default for StorageLock<T> {
+    fn default() -> Self {
+        StorageLock::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for StorageLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_read() {
+            Some(value) => f.debug_tuple("StorageLock").field(&*value).finish(),
+            None => f.write_str("StorageLock(<written>)"),
+        }
+    }
+}
+
+/// A claim on a `StorageLock`'s value for reading.
+pub struct StorageReadGuard<'a, T> {
+    lock: &'a StorageLock<T>,
+    value: ConstPtr<T>,
+}
+
+impl<'a, T> Deref for StorageReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Writers are shut out until the claim is dropped.
+        unsafe { self.value.deref() }
+    }
+}
+
+impl<'a, T> Drop for StorageReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// A claim on a `StorageLock`'s value for writing.
+pub struct StorageWriteGuard<'a, T> {
+    lock: &'a StorageLock<T>,
+    value: MutPtr<T>,
+}
+
+impl<'a, T> Deref for StorageWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Everyone else is shut out until the claim is dropped.
+        unsafe { self.value.deref() }
+    }
+}
+
+impl<'a, T> DerefMut for StorageWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.value.deref() }
+    }
+}
+
+impl<'a, T> Drop for StorageWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+    }
+}
+
+type Command<W> = Box<dyn FnOnce(&mut W) + Send>;
+
+/// A queue of changes to a world of type `W` that any thread can add to. See the
+/// [module-level documentation](index.html).
+pub struct CommandQueue<W> {
+    queue: Mutex<Vec<Command<W>>>,
+    world: PhantomData<fn(&mut W)>,
+}
+
+impl<W> Default for CommandQueue<W> {
+    fn default() -> Self {
+        CommandQueue {
+            queue: Mutex::new(Vec::new()),
+            world: PhantomData,
+        }
+    }
+}
+
+impl<W> CommandQueue<W> {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a change.
+    pub fn push<F>(&self, f: F)
+    where
+        F: FnOnce(&mut W) + Send + 'static,
+    {
+        self.lock().push(Box::new(f));
+    }
+
+    /// The number of changes queued.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether there are no changes queued.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Make the queued changes to `world`, in the order they were queued, and empty the queue.
+    /// Changes queued while this runs (including by the changes themselves) are left for the
+    /// next call.
+    pub fn apply(&self, world: &mut W) {
+        let queue = core::mem::take(&mut *self.lock());
+        for command in queue {
+            command(world);
+        }
+    }
+
+    // A thread that panicked while pushing can't have left the queue half-changed.
+    fn lock(&self) -> impl DerefMut<Target = Vec<Command<W>>> + '_ {
+        self.queue
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+impl<W> fmt::Debug for CommandQueue<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandQueue")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Hands out handles for new entities from any thread. See the
+/// [module-level documentation](index.html).
+///
+/// The handles come from a block of entities reserved in the world with
+/// `WorldInterface::reserve_entity` when the allocator is created, so they're alive in the world
+/// from the start, without components, and the world won't give their slots to anything else.
+/// Components can be added once the handles are back on the world's thread, e.g. by commands
+/// pushed to a `CommandQueue`. Entities that are never handed out stay alive too; `unused` lists
+/// them, for deleting.
+#[derive(Debug)]
+pub struct EntityAllocator {
+    next: AtomicU32,
+    end: u32,
+}
+
+impl EntityAllocator {
+    /// Reserve `n` entities in `world`, and create an allocator that hands them out.
+    pub fn reserve<'a, W: WorldInterface<'a>>(world: &W, n: u32) -> Self {
+        // `define_world!` worlds reserve consecutive slots, so only the range needs keeping.
+        let mut reserved = (0..n).map(|_| world.reserve_entity());
+        let first = reserved.next().map_or(0, |e| e.id() as u32);
+        for (i, e) in (1..).zip(reserved) {
+            assert_eq!(
+                e,
+                Entity::new(first + i),
+                "reserved entities aren't consecutive"
+            );
+        }
+        EntityAllocator {
+            next: AtomicU32::new(first),
+            end: first + n,
+        }
+    }
+
+    /// The handle for a new entity, which no other call has returned. Panics if the reserved
+    /// entities have all been handed out.
+    pub fn allocate(&self) -> Entity {
+        self.allocate_many(1)
+            .next()
+            .expect("no reserved entities left")
+    }
+
+    /// Handles for `n` new entities, in consecutive slots. Panics if there aren't that many
+    /// reserved entities left, without handing any out.
+    pub fn allocate_many(&self, n: u32) -> impl Iterator<Item = Entity> {
+        let end = self.end;
+        let first = self
+            .next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| {
+                next.checked_add(n).filter(|&next| next <= end)
+            })
+            .expect("no reserved entities left");
+        (first..first + n).map(Entity::new)
+    }
+
+    /// The slot the next handle will be for.
+    pub fn next_slot(&self) -> u32 {
+        self.next.load(Ordering::Relaxed)
+    }
+
+    /// The number of reserved entities left to hand out.
+    pub fn remaining(&self) -> u32 {
+        self.end - self.next_slot()
+    }
+
+    /// The reserved entities that haven't been handed out (yet).
+    pub fn unused(&self) -> impl Iterator<Item = Entity> {
+        (self.next_slot()..self.end).map(Entity::new)
+    }
+}
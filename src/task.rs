@@ -0,0 +1,206 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Spreading a single system's work over threads.
+//!
+//! Systems run one at a time, but a heavy one (meshing terrain chunks, say) can fan its own work
+//! out. Requesting [`Tasks`](struct.Tasks.html) in a system's `Dependencies` gives it the
+//! current [`Executor`](../executor/trait.Executor.html), i.e. the one given to
+//! [`executor::set_current`](../executor/fn.set_current.html) on the thread running it (or
+//! `Inline`, which runs everything on the system's thread).
+//!
+//! [`Tasks::scope`](struct.Tasks.html#method.scope) builds a graph of tasks with
+//! [`spawn`](struct.TaskGraph.html#method.spawn) and
+//! [`spawn_after`](struct.TaskGraph.html#method.spawn_after), which runs once the closure
+//! returns. Tasks can borrow anything from outside the scope, including the system's
+//! dependencies (as long as they're `Sync`, e.g. the components of a storage), and they've all
+//! finished by the time `scope` returns, so the system carries on as normal afterwards. Tasks
+//! only run after the ones they were spawned after; the rest run in whatever order the executor
+//! picks, in parallel if it can.
+//!
+//! The dispatcher waits for the tasks along with the system, so a `Watchdog` counts their time
+//! towards the system's, and it records how many there were, in
+//! [`ScheduledSystem::tasks`](../dispatch/struct.ScheduledSystem.html#method.tasks).
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! use std::sync::Arc;
+//!
+//! #[derive(Default)]
+//! pub struct Chunk {
+//!     heights: Vec<u8>,
+//!     triangles: usize,
+//! }
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             chunks: BasicVecStorage<Chunk>,
+//!         }
+//!         resources {
+//!             total: usize,
+//!         }
+//!     }
+//! );
+//!
+//! struct MeshChunks;
+//! impl<'a> System<'a> for MeshChunks {
+//!     type Dependencies = (Tasks, WriteComponent<'a, Chunk>, WriteResource<'a, usize>);
+//!     fn run(&'a mut self, (tasks, mut chunks, mut total): Self::Dependencies) {
+//!         let mut chunks: Vec<&mut Chunk> = chunks.components_mut().collect();
+//!         tasks.for_each_mut(&mut chunks, |chunk| {
+//!             chunk.triangles = chunk.heights.windows(2).filter(|h| h[0] != h[1]).count() * 2;
+//!         });
+//!         *total = chunks.iter().map(|c| c.triangles).sum();
+//!     }
+//! }
+//!
+//! let mut w = World::default();
+//! for i in 0..16 {
+//!     w.new_entity().with(Chunk { heights: vec![0, i, i, 0], triangles: 0 }).build();
+//! }
+//! executor::set_current(Some(Arc::new(ThreadPool::new(4))));
+//! let mut dispatcher = Dispatcher::new().with(&mut w, MeshChunks);
+//! dispatcher.run(&mut w).unwrap();
+//! assert_eq!(*<World as GetResource<usize>>::get(&w), 15 * 4);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::executor::{self, Executor, Job};
+
+/// System dependency for running work in parallel on the current executor. See the
+/// [module-level documentation](index.html).
+pub struct Tasks {
+    pub(crate) executor: Arc<dyn Executor>,
+}
+
+impl Tasks {
+    /// The executor the tasks run on.
+    pub fn executor(&self) -> &Arc<dyn Executor> {
+        &self.executor
+    }
+
+    /// The number of tasks that can usefully run at once. See `Executor::parallelism`.
+    pub fn parallelism(&self) -> usize {
+        self.executor.parallelism()
+    }
+
+    /// Build a graph of tasks with `f`, then run it, returning `f`'s result once every task has
+    /// finished. If any of them panics, the panic carries on from here.
+    pub fn scope<'s, R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut TaskGraph<'s>) -> R,
+    {
+        let mut graph = TaskGraph { tasks: Vec::new() };
+        let result = f(&mut graph);
+        graph.run(&*self.executor);
+        result
+    }
+
+    /// Call `f` on every item, splitting them between as many tasks as can usefully run at once.
+    pub fn for_each_mut<T, F>(&self, items: &mut [T], f: F)
+    where
+        T: Send,
+        F: Fn(&mut T) + Sync,
+    {
+        let size = items.len().div_ceil(self.parallelism().max(1)).max(1);
+        let f = &f;
+        self.scope(|graph| {
+            for chunk in items.chunks_mut(size) {
+                graph.spawn(move || chunk.iter_mut().for_each(f));
+            }
+        });
+    }
+}
+
+impl fmt::Debug for Tasks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tasks")
+            .field("parallelism", &self.parallelism())
+            .finish()
+    }
+}
+
+/// Identifies a task in a `TaskGraph`, for others to be spawned after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TaskId(usize);
+
+/// A graph of tasks being built by `Tasks::scope`.
+pub struct TaskGraph<'s> {
+    // Each task, with the tasks it has to run after. Those always come earlier.
+    tasks: Vec<(Job<'s>, Vec<TaskId>)>,
+}
+
+impl<'s> TaskGraph<'s> {
+    /// Add a task.
+    pub fn spawn<F>(&mut self, f: F) -> TaskId
+    where
+        F: FnOnce() + Send + 's,
+    {
+        self.spawn_after(&[], f)
+    }
+
+    /// Add a task that only starts once the tasks in `after` have finished.
+    pub fn spawn_after<F>(&mut self, after: &[TaskId], f: F) -> TaskId
+    where
+        F: FnOnce() + Send + 's,
+    {
+        self.tasks.push((Box::new(f), after.to_vec()));
+        TaskId(self.tasks.len() - 1)
+    }
+
+    /// The number of tasks added so far.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Whether no tasks have been added.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    // Run the tasks in waves: each wave is every task whose predecessors ran in earlier waves.
+    fn run(self, executor: &dyn Executor) {
+        executor::record_tasks(self.tasks.len());
+        let mut waves: Vec<Vec<Job<'s>>> = Vec::new();
+        let mut wave_of = Vec::with_capacity(self.tasks.len());
+        for (job, after) in self.tasks {
+            let wave = after.iter().map(|t| wave_of[t.0] + 1).max().unwrap_or(0);
+            wave_of.push(wave);
+            if waves.len() <= wave {
+                waves.resize_with(wave + 1, Vec::new);
+            }
+            waves[wave].push(job);
+        }
+        for wave in waves {
+            executor.scope(wave);
+        }
+    }
+}
+
+impl fmt::Debug for TaskGraph<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskGraph")
+            .field("len", &self.len())
+            .finish()
+    }
+}
@@ -0,0 +1,414 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Drawing entities as characters on a grid, for terminal games like roguelikes.
+//!
+//! Entities with components holding a [`Position`](struct.Position.html) and a
+//! [`Glyph`](struct.Glyph.html) are drawn by the [`RenderGlyphs`](struct.RenderGlyphs.html) system into a
+//! [`CellBuffer`](struct.CellBuffer.html) resource: a grid of characters with colors, that
+//! doesn't know anything about terminals. Getting it onto the screen is left to the game, with
+//! whichever library it uses, by going through the buffer's rows, or just the cells that have
+//! changed since the last frame it drew, from [`changes`](struct.CellBuffer.html#method.changes).
+//! With crossterm, for example, that's a `MoveTo`, `SetForegroundColor`, `SetBackgroundColor` and
+//! `Print` for each cell; colors are plain [`Rgb`](struct.Rgb.html) values, so they convert
+//! directly to the library's own.
+//!
+//! `define_world!` can only give storages to the game's own types, so the two components are
+//! newtypes (or bigger structs) of the game's that implement `AsRef<Position>` and
+//! `AsRef<Glyph>`, and `RenderGlyphs` is told which they are. They have to be two different
+//! components, since the system reads both storages at once.
+//!
+//! Glyphs with a higher `z` are drawn over ones with a lower one, so items can lie on the floor
+//! and monsters stand on the items. Glyphs without a background color keep the color of
+//! whatever's under them. Positions outside the buffer aren't drawn.
+//!
+//! This module needs the `term` feature.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct At(Position);
+//! impl AsRef<Position> for At {
+//!     fn as_ref(&self) -> &Position {
+//!         &self.0
+//!     }
+//! }
+//!
+//! #[derive(Default)]
+//! pub struct Looks(Glyph);
+//! impl AsRef<Glyph> for Looks {
+//!     fn as_ref(&self) -> &Glyph {
+//!         &self.0
+//!     }
+//! }
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<At>,
+//!             glyphs: BasicVecStorage<Looks>,
+//!         }
+//!         resources {
+//!             screen: CellBuffer,
+//!         }
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! *<World as GetResource<CellBuffer>>::get_mut(&w) = CellBuffer::new(5, 3);
+//! for x in 0..5 {
+//!     for y in 0..3 {
+//!         let floor = Glyph::new('.').with_bg(Rgb::new(20, 20, 20));
+//!         w.new_entity().with(At(Position::new(x, y))).with(Looks(floor)).build();
+//!     }
+//! }
+//! let player = Glyph::new('@').with_fg(Rgb::YELLOW).with_z(2);
+//! w.new_entity().with(At(Position::new(1, 1))).with(Looks(player)).build();
+//! let potion = Glyph::new('!').with_fg(Rgb::RED).with_z(1);
+//! w.new_entity().with(At(Position::new(3, 1))).with(Looks(potion)).build();
+//!
+//! w.run_system(&mut RenderGlyphs::<At, Looks>::new());
+//!
+//! let screen = <World as GetResource<CellBuffer>>::get(&w);
+//! assert_eq!(screen.to_string(), ".....\n.@.!.\n.....\n");
+//! let cell = screen.get(1, 1).unwrap();
+//! assert_eq!((cell.fg, cell.bg), (Rgb::YELLOW, Rgb::new(20, 20, 20)));
+//!
+//! // Only the cells that differ from what's on the terminal need to be drawn.
+//! let blank = CellBuffer::new(5, 3);
+//! assert_eq!(screen.changes(&blank).count(), 15);
+//! assert_eq!(screen.changes(&screen.clone()).count(), 0);
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+use crate::*;
+
+/// An entity's cell on the grid, held by one of the game's components. See the [module-level
+/// documentation](index.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Position {
+    /// The column, counting from the left.
+    pub x: i32,
+    /// The row, counting from the top.
+    pub y: i32,
+}
+
+impl Position {
+    /// Create a position.
+    pub const fn new(x: i32, y: i32) -> Self {
+        Position { x, y }
+    }
+}
+
+impl From<(i32, i32)> for Position {
+    fn from((x, y): (i32, i32)) -> Self {
+        Position { x, y }
+    }
+}
+
+/// A color, as red, green and blue components.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rgb {
+    /// Red.
+    pub r: u8,
+    /// Green.
+    pub g: u8,
+    /// Blue.
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Black.
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+    /// White.
+    pub const WHITE: Rgb = Rgb::new(255, 255, 255);
+    /// Red.
+    pub const RED: Rgb = Rgb::new(255, 0, 0);
+    /// Green.
+    pub const GREEN: Rgb = Rgb::new(0, 255, 0);
+    /// Blue.
+    pub const BLUE: Rgb = Rgb::new(0, 0, 255);
+    /// Yellow.
+    pub const YELLOW: Rgb = Rgb::new(255, 255, 0);
+
+    /// Create a color.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+}
+
+/// How to draw an entity, held by one of the game's components. See the [module-level
+/// documentation](index.html).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Glyph {
+    /// The character.
+    pub ch: char,
+    /// The character's color.
+    pub fg: Rgb,
+    /// The cell's background color, or `None` to keep the one underneath.
+    pub bg: Option<Rgb>,
+    /// Glyphs with a higher `z` are drawn over ones with a lower one.
+    pub z: i32,
+}
+
+impl Glyph {
+    /// A white character, with no background, at `z` 0.
+    pub const fn new(ch: char) -> Self {
+        Glyph {
+            ch,
+            fg: Rgb::WHITE,
+            bg: None,
+            z: 0,
+        }
+    }
+
+    /// Set the character's color, builder style.
+    pub const fn with_fg(mut self, fg: Rgb) -> Self {
+        self.fg = fg;
+        self
+    }
+
+    /// Set the background color, builder style.
+    pub const fn with_bg(mut self, bg: Rgb) -> Self {
+        self.bg = Some(bg);
+        self
+    }
+
+    /// Set the drawing order, builder style.
+    pub const fn with_z(mut self, z: i32) -> Self {
+        self.z = z;
+        self
+    }
+}
+
+impl Default for Glyph {
+    fn default() -> Self {
+        Glyph::new(' ')
+    }
+}
+
+/// A single cell of a `CellBuffer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Cell {
+    /// The character.
+    pub ch: char,
+    /// The character's color.
+    pub fg: Rgb,
+    /// The background color.
+    pub bg: Rgb,
+}
+
+/// A space, white on black.
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            ch: ' ',
+            fg: Rgb::WHITE,
+            bg: Rgb::BLACK,
+        }
+    }
+}
+
+/// Resource holding a grid of colored characters, for the game to draw to the screen. See the
+/// [module-level documentation](index.html).
+///
+/// Formatting it with `Display` gives its characters, a line per row, without the colors.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CellBuffer {
+    width: u32,
+    height: u32,
+    cells: Vec<Cell>,
+}
+
+impl CellBuffer {
+    /// Create a buffer of blank cells.
+    pub fn new(width: u32, height: u32) -> Self {
+        CellBuffer {
+            width,
+            height,
+            cells: alloc::vec![Cell::default(); width as usize * height as usize],
+        }
+    }
+
+    /// The number of columns.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The number of rows.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Change the size of the buffer, e.g. when the terminal is resized, blanking every cell.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.cells.clear();
+        self.cells
+            .resize(width as usize * height as usize, Cell::default());
+    }
+
+    /// Blank every cell.
+    pub fn clear(&mut self) {
+        self.fill(Cell::default());
+    }
+
+    /// Set every cell to `cell`.
+    pub fn fill(&mut self, cell: Cell) {
+        self.cells.iter_mut().for_each(|c| *c = cell);
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        Some(y as usize * self.width as usize + x as usize)
+    }
+
+    /// The cell at column `x` and row `y`, or `None` if that's outside the buffer.
+    pub fn get(&self, x: i32, y: i32) -> Option<&Cell> {
+        self.index(x, y).map(|i| &self.cells[i])
+    }
+
+    /// Mutable access to the cell at column `x` and row `y`, or `None` if that's outside the
+    /// buffer.
+    pub fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut Cell> {
+        self.index(x, y).map(move |i| &mut self.cells[i])
+    }
+
+    /// Set the cell at column `x` and row `y`. Does nothing if that's outside the buffer.
+    pub fn set(&mut self, x: i32, y: i32, cell: Cell) {
+        if let Some(c) = self.get_mut(x, y) {
+            *c = cell;
+        }
+    }
+
+    /// Write `text` in `fg` from column `x` of row `y`, keeping the cells' backgrounds, e.g. for a
+    /// status line. Whatever doesn't fit is cut off.
+    pub fn print(&mut self, x: i32, y: i32, text: &str, fg: Rgb) {
+        for (i, ch) in text.chars().enumerate() {
+            if let Some(c) = self.get_mut(x.saturating_add(i as i32), y) {
+                c.ch = ch;
+                c.fg = fg;
+            }
+        }
+    }
+
+    /// Draw `glyph` in the cell at `position`, keeping its background if the glyph doesn't have
+    /// one. Does nothing if that's outside the buffer.
+    pub fn draw(&mut self, position: Position, glyph: &Glyph) {
+        if let Some(c) = self.get_mut(position.x, position.y) {
+            c.ch = glyph.ch;
+            c.fg = glyph.fg;
+            if let Some(bg) = glyph.bg {
+                c.bg = bg;
+            }
+        }
+    }
+
+    /// Iterate over the rows, from the top.
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> + '_ {
+        // `max(1)` since `chunks` doesn't take 0; an empty buffer has no cells anyway.
+        self.cells.chunks(self.width.max(1) as usize)
+    }
+
+    /// Iterate over the cells that differ from `previous`, e.g. the buffer as it was last drawn,
+    /// with their positions, row by row. Every cell counts as changed if `previous` is a
+    /// different size.
+    pub fn changes<'b>(
+        &'b self,
+        previous: &'b CellBuffer,
+    ) -> impl Iterator<Item = (Position, &'b Cell)> + 'b {
+        let resized = (self.width, self.height) != (previous.width, previous.height);
+        let width = self.width.max(1) as usize;
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(move |&(i, cell)| resized || previous.cells[i] != *cell)
+            .map(move |(i, cell)| (Position::new((i % width) as i32, (i / width) as i32), cell))
+    }
+}
+
+impl fmt::Display for CellBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use core::fmt::Write as _;
+        for row in self.rows() {
+            for cell in row {
+                f.write_char(cell.ch)?;
+            }
+            f.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
+
+/// System that clears the `CellBuffer` and draws every entity with both a `P` and a `G`
+/// component into it. See the [module-level documentation](index.html).
+pub struct RenderGlyphs<P, G> {
+    // The glyphs to draw, kept between runs to reuse the allocation.
+    queue: Vec<(Position, Glyph)>,
+    tag: PhantomData<fn() -> (P, G)>,
+}
+
+impl<P, G> RenderGlyphs<P, G> {
+    /// Create the system.
+    pub fn new() -> Self {
+        RenderGlyphs {
+            queue: Vec::new(),
+            tag: PhantomData,
+        }
+    }
+}
+
+impl<P, G> Default for RenderGlyphs<P, G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P, G> fmt::Debug for RenderGlyphs<P, G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RenderGlyphs").finish()
+    }
+}
+
+impl<'a, P, G> System<'a> for RenderGlyphs<P, G>
+where
+    P: 'static + AsRef<Position> + StorageSpec<'a, Component = P>,
+    G: 'static + AsRef<Glyph> + StorageSpec<'a, Component = G>,
+{
+    type Dependencies = (
+        ReadComponent<'a, P>,
+        ReadComponent<'a, G>,
+        WriteResource<'a, CellBuffer>,
+    );
+    fn run(&'a mut self, (positions, glyphs, mut buffer): Self::Dependencies) {
+        buffer.clear();
+        let queue = &mut self.queue;
+        (&positions, &glyphs).for_each(|_, (p, g)| queue.push((*p.as_ref(), *g.as_ref())));
+        // Stable, so glyphs at the same `z` are drawn in entity order.
+        queue.sort_by_key(|(_, g)| g.z);
+        for (p, g) in queue.drain(..) {
+            buffer.draw(p, &g);
+        }
+    }
+}
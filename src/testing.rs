@@ -0,0 +1,461 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers for testing systems.
+//!
+//! [`WorldTestBuilder`](struct.WorldTestBuilder.html) sets up a world from tuples of components,
+//! [`run_once`](fn.run_once.html) runs a single system against it, and the
+//! `assert_component_eq!`, `assert_no_component!` and `assert_resource_eq!` macros check the
+//! result.
+//!
+//! For systems whose results are too involved to check piece by piece, `World::snapshot` dumps
+//! the whole world as text, with entities in id order and each entity's components and the
+//! resources sorted by field name, formatted with `Debug`. [`assert_golden`](fn.assert_golden.html)
+//! compares a snapshot against one stored in a file, so that changes show up as a diff in review.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! use ecstatic::testing::*;
+//!
+//! #[derive(Debug, Default, PartialEq)]
+//! pub struct Health {
+//!     hp: u32,
+//! }
+//! #[derive(Debug, Default, PartialEq)]
+//! pub struct Poisoned;
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             health: BasicVecStorage<Health>,
+//!             poisoned: BasicVecStorage<Poisoned>,
+//!         }
+//!         resources {
+//!             deaths: u32,
+//!         }
+//!     }
+//! );
+//!
+//! struct Poison;
+//! impl<'a> System<'a> for Poison {
+//!     type Dependencies = (
+//!         WriteComponent<'a, Health>,
+//!         ReadComponent<'a, Poisoned>,
+//!         WriteResource<'a, u32>,
+//!     );
+//!     fn run(&'a mut self, (mut health, poisoned, mut deaths): Self::Dependencies) {
+//!         (&mut health, &poisoned).for_each(|_, (h, _)| {
+//!             h.hp -= 1;
+//!             if h.hp == 0 {
+//!                 *deaths += 1;
+//!             }
+//!         });
+//!     }
+//! }
+//!
+//! let (mut w, e) = WorldTestBuilder::<World>::new()
+//!     .entity((Health { hp: 4 }, Poisoned))
+//!     .entity((Health { hp: 1 }, Poisoned))
+//!     .entity((Health { hp: 9 },))
+//!     .build();
+//!
+//! run_once(&mut w, Poison);
+//!
+//! assert_component_eq!(w, e[0], Health { hp: 3 });
+//! assert_component_eq!(w, e[2], Health { hp: 9 }, "{:?} isn't poisoned", e[2]);
+//! assert_no_component!(w, e[2], Poisoned);
+//! assert_resource_eq!(w, 1u32);
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    Entity, GetComponent, GetResource, ReadStorage, RunSystem, StorageSpec, WorldInterface,
+};
+
+/// Components to give an entity, as a tuple of up to eight of them.
+pub trait ComponentList<W> {
+    /// Give `entity` every component in the list.
+    fn insert_into(self, world: &mut W, entity: Entity);
+}
+
+macro_rules! impl_component_list {
+    ($($t:ident)*) => {
+        #[allow(non_snake_case, unused_variables)]
+        impl<W, $($t,)*> ComponentList<W> for ($($t,)*)
+        where
+            $(
+                $t: for<'a> StorageSpec<'a, Component = $t>,
+                W: for<'a> GetComponent<'a, $t>,
+            )*
+        {
+            fn insert_into(self, world: &mut W, entity: Entity) {
+                let ($($t,)*) = self;
+                $(
+                    <W as GetComponent<'_, $t>>::insert(world, entity, $t);
+                )*
+            }
+        }
+    };
+}
+
+impl_component_list!();
+impl_component_list!(A);
+impl_component_list!(A B);
+impl_component_list!(A B C);
+impl_component_list!(A B C D);
+impl_component_list!(A B C D E);
+impl_component_list!(A B C D E F);
+impl_component_list!(A B C D E F G);
+impl_component_list!(A B C D E F G H);
+
+/// Builds a world for a test from lists of components.
+///
+/// Components are added directly, so the world's `constraints` aren't checked.
+pub struct WorldTestBuilder<W> {
+    world: W,
+    entities: Vec<Entity>,
+}
+
+impl<W: Default> WorldTestBuilder<W> {
+    /// Start from an empty world.
+    pub fn new() -> Self {
+        Self::from_world(W::default())
+    }
+}
+
+impl<W: Default> Default for WorldTestBuilder<W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W> WorldTestBuilder<W> {
+    /// Start from an existing world.
+    pub fn from_world(world: W) -> Self {
+        WorldTestBuilder {
+            world,
+            entities: Vec::new(),
+        }
+    }
+
+    /// Add an entity with the given components.
+    pub fn entity<C: ComponentList<W>>(mut self, components: C) -> Self
+    where
+        W: for<'a> WorldInterface<'a>,
+        for<'a> <W as WorldInterface<'a>>::ComponentSet: Default,
+    {
+        let entity = self.world.build_entity(Default::default());
+        components.insert_into(&mut self.world, entity);
+        self.entities.push(entity);
+        self
+    }
+
+    /// Add an entity for each list of components.
+    pub fn entities<C, I>(self, lists: I) -> Self
+    where
+        C: ComponentList<W>,
+        I: IntoIterator<Item = C>,
+        W: for<'a> WorldInterface<'a>,
+        for<'a> <W as WorldInterface<'a>>::ComponentSet: Default,
+    {
+        lists
+            .into_iter()
+            .fold(self, |b, components| b.entity(components))
+    }
+
+    /// Set a resource.
+    pub fn resource<R>(self, value: R) -> Self
+    where
+        W: GetResource<R>,
+    {
+        self.world.set(value);
+        self
+    }
+
+    /// Finish building, returning the world along with the entities, in the order they were
+    /// added.
+    pub fn build(self) -> (W, Vec<Entity>) {
+        (self.world, self.entities)
+    }
+}
+
+/// Run `system` against `world` once, returning its output.
+pub fn run_once<W, S>(world: &mut W, mut system: S) -> S::Output
+where
+    S: RunSystem<W, Input = ()>,
+{
+    system.run_on(world, ())
+}
+
+/// Used by `assert_component_eq!`.
+#[doc(hidden)]
+pub fn check_component<'w, W, T>(world: &'w W, entity: Entity, expected: &T) -> Result<(), String>
+where
+    T: StorageSpec<'w, Component = T> + PartialEq + Debug,
+    W: GetComponent<'w, T>,
+{
+    match world.get().get(entity) {
+        Some(found) if found == expected => Ok(()),
+        Some(found) => Err(format!(
+            "{:?}: expected {:?}, found {:?}",
+            entity, expected, found
+        )),
+        None => Err(format!(
+            "{:?}: expected {:?}, but it has no `{}`",
+            entity,
+            expected,
+            std::any::type_name::<T>()
+        )),
+    }
+}
+
+/// Used by `assert_no_component!`.
+#[doc(hidden)]
+pub fn check_no_component<'w, W, T>(world: &'w W, entity: Entity) -> Result<(), String>
+where
+    T: StorageSpec<'w, Component = T> + Debug,
+    W: GetComponent<'w, T>,
+{
+    match world.get().get(entity) {
+        Some(found) => Err(format!(
+            "{:?}: expected no component, found {:?}",
+            entity, found
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Used by `assert_resource_eq!`.
+#[doc(hidden)]
+pub fn check_resource<W, R>(world: &W, expected: &R) -> Result<(), String>
+where
+    R: PartialEq + Debug,
+    W: GetResource<R>,
+{
+    let found = world.get();
+    if *found == *expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "resource `{}`: expected {:?}, found {:?}",
+            std::any::type_name::<R>(),
+            expected,
+            *found
+        ))
+    }
+}
+
+// Field names and their values, formatted with `Debug`.
+type Fields = Vec<(&'static str, String)>;
+
+/// Builds the text of `World::snapshot`. Not meant to be used directly.
+#[doc(hidden)]
+pub struct Snapshot {
+    entities: BTreeMap<usize, (Entity, Fields)>,
+    names: BTreeMap<usize, String>,
+    resources: Fields,
+}
+
+impl Snapshot {
+    /// Start a snapshot of the given live entities.
+    pub fn new<I: IntoIterator<Item = Entity>>(entities: I) -> Self {
+        Snapshot {
+            entities: entities
+                .into_iter()
+                .map(|e| (e.id(), (e, Vec::new())))
+                .collect(),
+            names: BTreeMap::new(),
+            resources: Vec::new(),
+        }
+    }
+
+    /// Add a component of a live entity.
+    pub fn component(&mut self, entity: Entity, name: &'static str, value: String) {
+        if let Some((e, components)) = self.entities.get_mut(&entity.id()) {
+            if *e == entity {
+                components.push((name, value));
+            }
+        }
+    }
+
+    /// Name a live entity.
+    pub fn name(&mut self, entity: Entity, name: &str) {
+        if let Some((e, _)) = self.entities.get(&entity.id()) {
+            if *e == entity {
+                self.names.insert(entity.id(), String::from(name));
+            }
+        }
+    }
+
+    /// Add a resource.
+    pub fn resource(&mut self, name: &'static str, value: String) {
+        self.resources.push((name, value));
+    }
+
+    /// Render the snapshot.
+    pub fn finish(mut self) -> String {
+        let mut out = String::new();
+        for (entity, components) in self.entities.values_mut() {
+            out.push_str(&format!(
+                "entity {} (generation {})",
+                entity.id(),
+                entity.generation()
+            ));
+            if let Some(name) = self.names.get(&entity.id()) {
+                out.push_str(&format!(" `{}`", name));
+            }
+            out.push('\n');
+            components.sort();
+            for (name, value) in components.iter() {
+                out.push_str(&format!("    {}: {}\n", name, value));
+            }
+        }
+        self.resources.sort();
+        for (name, value) in self.resources.iter() {
+            out.push_str(&format!("resource {}: {}\n", name, value));
+        }
+        out
+    }
+}
+
+/// The environment variable that makes `assert_golden` rewrite golden files instead of comparing
+/// against them.
+pub const UPDATE_GOLDEN: &str = "ECSTATIC_UPDATE_GOLDEN";
+
+/// Compare `actual` against the contents of the golden file at `path`, panicking with the first
+/// line that differs if they don't match.
+///
+/// If the `ECSTATIC_UPDATE_GOLDEN` environment variable is set, the file is written with `actual`
+/// instead, creating its directory if needed; commit the result. Otherwise a missing file is an
+/// error too, so that a deleted or misnamed golden file doesn't pass unnoticed.
+pub fn assert_golden<P: AsRef<Path>>(path: P, actual: &str) {
+    let path = path.as_ref();
+    if std::env::var_os(UPDATE_GOLDEN).is_some() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .unwrap_or_else(|e| panic!("couldn't create {}: {}", dir.display(), e));
+        }
+        fs::write(path, actual)
+            .unwrap_or_else(|e| panic!("couldn't write {}: {}", path.display(), e));
+        return;
+    }
+    let expected = fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!(
+            "couldn't read {}: {}\n(set {} to create it)",
+            path.display(),
+            e,
+            UPDATE_GOLDEN
+        )
+    });
+    if let Err(message) = compare_golden(&expected, actual) {
+        panic!(
+            "{} doesn't match: {}\n(set {} to update it)",
+            path.display(),
+            message,
+            UPDATE_GOLDEN
+        );
+    }
+}
+
+// Describe the first difference between `expected` and `actual`.
+fn compare_golden(expected: &str, actual: &str) -> Result<(), String> {
+    if expected == actual {
+        return Ok(());
+    }
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(e), Some(a)) if e == a => line += 1,
+            (None, None) => return Err("they differ only in line endings".to_string()),
+            (e, a) => {
+                return Err(format!(
+                    "line {}: expected {:?}, found {:?}",
+                    line,
+                    e.unwrap_or("<end of file>"),
+                    a.unwrap_or("<end of file>")
+                ))
+            }
+        }
+    }
+}
+
+/// Assert that an entity's component of the same type as the expected value is equal to it.
+/// Extra arguments are formatted into the panic message, as with `assert_eq!`.
+///
+/// ```ignore
+/// assert_component_eq!(world, entity, Health { hp: 3 });
+/// ```
+#[macro_export]
+macro_rules! assert_component_eq {
+    ($world:expr, $entity:expr, $expected:expr $(,)?) => {
+        if let Err(message) = $crate::testing::check_component(&$world, $entity, &$expected) {
+            ::std::panic!("assertion failed: {}", message);
+        }
+    };
+    ($world:expr, $entity:expr, $expected:expr, $($arg:tt)+) => {
+        if let Err(message) = $crate::testing::check_component(&$world, $entity, &$expected) {
+            ::std::panic!("assertion failed: {}: {}", message, ::std::format_args!($($arg)+));
+        }
+    };
+}
+
+/// Assert that an entity has no component of the given type.
+///
+/// ```ignore
+/// assert_no_component!(world, entity, Poisoned);
+/// ```
+#[macro_export]
+macro_rules! assert_no_component {
+    ($world:expr, $entity:expr, $type:ty $(,)?) => {
+        if let Err(message) = $crate::testing::check_no_component::<_, $type>(&$world, $entity) {
+            ::std::panic!("assertion failed: {}", message);
+        }
+    };
+    ($world:expr, $entity:expr, $type:ty, $($arg:tt)+) => {
+        if let Err(message) = $crate::testing::check_no_component::<_, $type>(&$world, $entity) {
+            ::std::panic!("assertion failed: {}: {}", message, ::std::format_args!($($arg)+));
+        }
+    };
+}
+
+/// Assert that the world's resource of the same type as the expected value is equal to it.
+///
+/// ```ignore
+/// assert_resource_eq!(world, Turn(3));
+/// ```
+#[macro_export]
+macro_rules! assert_resource_eq {
+    ($world:expr, $expected:expr $(,)?) => {
+        if let Err(message) = $crate::testing::check_resource(&$world, &$expected) {
+            ::std::panic!("assertion failed: {}", message);
+        }
+    };
+    ($world:expr, $expected:expr, $($arg:tt)+) => {
+        if let Err(message) = $crate::testing::check_resource(&$world, &$expected) {
+            ::std::panic!("assertion failed: {}: {}", message, ::std::format_args!($($arg)+));
+        }
+    };
+}
@@ -1,6 +1,10 @@
+use std::borrow::ToOwned;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
 use crate::*;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Data {
     x: u32,
 }
@@ -12,27 +16,55 @@ impl Default for Data {
     }
 }
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct MoreData {
     y: u32,
 }
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Void {}
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Tile(&'static str);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Effect(u32);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label(&'static str);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heat(u32);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position(f32);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step(u32);
+
+impl_persist!(Data { x });
+
 define_world!(
-        #[derive(Default)]
-        pub world {
-            components {
-                test1: BasicVecStorage<Data>,
-                test2: BasicVecStorage<MoreData>,
-                test3: VoidStorage<Void>,
-            }
-            resources {
-                test_resource: String,
-            }
+    #[derive(Clone, Default)]
+    pub world {
+        components {
+            test1: BasicVecStorage<Data>,
+            test2: BasicVecStorage<MoreData>,
+            test3: VoidStorage<Void>,
+            test4: SharedStorage<Tile>,
+            test5: MultiStorage<Effect>,
+            test6: SmallVecStorage<Label, 4>,
+            test7: DoubleBufferedStorage<Heat>,
+            test8: InterpolatedStorage<Position>,
+            test9: IntentStorage<Step>,
         }
-    );
+        resources {
+            test_resource: String,
+            inputs: InputEvents<u32>,
+            board: Blackboard,
+        }
+    }
+);
 
 #[test]
 fn test_world() {
@@ -59,15 +91,11 @@ fn test_world() {
         fn run(&'a mut self, (data, mut more_data): Self::Dependencies) {
             self.total = 0;
             self.chosen = 0;
-            for item in data.iter() {
-                if let Some(d) = item {
-                    self.total += d.x;
-                }
+            for d in data.iter().flatten() {
+                self.total += d.x;
             }
-            for item in more_data.iter_mut() {
-                if let Some(d) = item {
-                    d.y *= 2;
-                }
+            for d in more_data.iter_mut().flatten() {
+                d.y *= 2;
             }
         }
     }
@@ -134,3 +162,6139 @@ fn test_join() {
     );
     assert_eq!(system.chosen, 5);
 }
+
+#[cfg(feature = "journal")]
+#[test]
+fn test_journal() {
+    let mut w = World::default();
+    <World as GetResource<Journal>>::get_mut(&w).mark_frame();
+    let a = w.new_entity().with(Data { x: 1 }).build();
+    let b = w.new_entity().with(MoreData { y: 2 }).with(Void {}).build();
+    <World as GetResource<Journal>>::get_mut(&w).mark_frame();
+    w.delete_entity(a);
+    <World as GetResource<String>>::set(&w, String::from("hello"));
+
+    let journal = <World as GetResource<Journal>>::get(&w);
+    assert_eq!(
+        journal.entries(),
+        &[
+            JournalEntry::Frame(0),
+            JournalEntry::Spawn(a),
+            JournalEntry::ComponentAdded {
+                entity: a,
+                component: std::any::type_name::<Data>(),
+            },
+            JournalEntry::Spawn(b),
+            JournalEntry::ComponentAdded {
+                entity: b,
+                component: std::any::type_name::<MoreData>(),
+            },
+            JournalEntry::ComponentAdded {
+                entity: b,
+                component: std::any::type_name::<Void>(),
+            },
+            JournalEntry::Frame(1),
+            JournalEntry::ComponentRemoved {
+                entity: a,
+                component: std::any::type_name::<Data>(),
+            },
+            JournalEntry::Despawn(a),
+            JournalEntry::ResourceSet {
+                resource: std::any::type_name::<String>(),
+            },
+        ][..]
+    );
+    assert_eq!(journal.since_frame(1).len(), 4);
+    assert!(journal.since_frame(2).is_empty());
+    drop(journal);
+
+    // Components added and removed by systems are recorded once the masks are synced.
+    struct Swap(Entity);
+    impl<'a> System<'a> for Swap {
+        type Dependencies = (WriteComponent<'a, Data>, WriteComponent<'a, MoreData>);
+        fn run(&'a mut self, (mut data, mut more): Self::Dependencies) {
+            data.set(self.0, Some(Data { x: 3 }));
+            more.set(self.0, None);
+        }
+    }
+    w.run_system(&mut Swap(b));
+    let recorded = <World as GetResource<Journal>>::get(&w).entries().len();
+    w.delete_entity(b);
+    assert_eq!(
+        &<World as GetResource<Journal>>::get(&w).entries()[recorded..],
+        &[
+            JournalEntry::ComponentAdded {
+                entity: b,
+                component: std::any::type_name::<Data>(),
+            },
+            JournalEntry::ComponentRemoved {
+                entity: b,
+                component: std::any::type_name::<MoreData>(),
+            },
+            JournalEntry::ComponentRemoved {
+                entity: b,
+                component: std::any::type_name::<Data>(),
+            },
+            JournalEntry::ComponentRemoved {
+                entity: b,
+                component: std::any::type_name::<Void>(),
+            },
+            JournalEntry::Despawn(b),
+        ][..]
+    );
+
+    // Dispatchers sync them when they apply deferred changes.
+    let c = w.new_entity().build();
+    let recorded = <World as GetResource<Journal>>::get(&w).entries().len();
+    let mut dispatcher = Dispatcher::new().with(&mut w, Swap(c));
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(
+        &<World as GetResource<Journal>>::get(&w).entries()[recorded..],
+        &[JournalEntry::ComponentAdded {
+            entity: c,
+            component: std::any::type_name::<Data>(),
+        }][..]
+    );
+}
+
+#[test]
+fn test_replay() {
+    struct Spawner;
+    impl<'a> System<'a> for Spawner {
+        type Dependencies = (ReadResource<'a, InputEvents<u32>>, WriteComponent<'a, Data>);
+        fn run(&'a mut self, (inputs, mut data): Self::Dependencies) {
+            for (i, x) in inputs.iter().enumerate() {
+                data.set(Entity::new(i as u32), Some(Data { x: *x }));
+            }
+        }
+    }
+
+    fn run_frame(w: &mut World) {
+        w.run_system(&mut Spawner);
+        let e = w.new_entity().build();
+        w.delete_entity(e);
+    }
+
+    let mut replay = Replay::new();
+    let mut w = World::default();
+    for frame in 0..3 {
+        for i in 0..frame {
+            <World as GetResource<InputEvents<u32>>>::get_mut(&w).push(i * 10);
+        }
+        replay.record(&w);
+        run_frame(&mut w);
+        <World as GetResource<InputEvents<u32>>>::get_mut(&w).clear();
+    }
+    assert_eq!(replay.len(), 3);
+    assert_eq!(replay.frame(2), Some(&[0, 10][..]));
+
+    let mut w2 = World::default();
+    replay.playback(&mut w2, run_frame);
+    let data = <World as GetComponent<'_, Data>>::get(&w);
+    let data2 = <World as GetComponent<'_, Data>>::get(&w2);
+    assert!(data.iter().eq(data2.iter()));
+
+    #[cfg(feature = "journal")]
+    assert_eq!(
+        <World as GetResource<Journal>>::get(&w).entries(),
+        <World as GetResource<Journal>>::get(&w2).entries()
+    );
+}
+
+#[test]
+fn test_undo() {
+    let mut w = World::default();
+    let mut history = UndoStack::new(2);
+    assert!(!history.undo(&mut w));
+
+    let a = w.new_entity().with(Data { x: 1 }).build();
+    history.push_checkpoint(&w);
+    let b = w.new_entity().with(Data { x: 2 }).with(Void {}).build();
+    history.push_checkpoint(&w);
+    w.delete_entity(a);
+    history.push_checkpoint(&w);
+    w.delete_entity(b);
+
+    // Capacity is 2, so the first checkpoint has been discarded.
+    assert!(history.undo(&mut w));
+    assert!(history.undo(&mut w));
+    assert!(!history.undo(&mut w));
+    assert!(history.can_redo());
+    {
+        let data = <World as GetComponent<'_, Data>>::get(&w);
+        assert_eq!(data.get(a), Some(&Data { x: 1 }));
+        assert_eq!(data.get(b), Some(&Data { x: 2 }));
+        assert!(<World as GetComponent<'_, Void>>::get(&w).get(b).is_some());
+    }
+
+    assert!(history.redo(&mut w));
+    assert!(history.redo(&mut w));
+    assert!(!history.redo(&mut w));
+    assert_eq!(<World as GetComponent<'_, Data>>::get(&w).get(b), None);
+
+    // A new checkpoint invalidates the redo history.
+    assert!(history.undo(&mut w));
+    history.push_checkpoint(&w);
+    assert!(!history.can_redo());
+
+    #[cfg(feature = "journal")]
+    {
+        assert_eq!(
+            <World as GetResource<Journal>>::get(&w).entries().last(),
+            Some(&JournalEntry::Despawn(b))
+        );
+        // Snapshots leave the journal out, and the live one survives undo and redo.
+        let entries = <World as GetResource<Journal>>::get(&w).entries().len();
+        history.clear();
+        history.push_checkpoint(&w);
+        assert_eq!(
+            <World as GetResource<Journal>>::get(&w).entries().len(),
+            entries
+        );
+        w.new_entity().build();
+        assert!(history.undo(&mut w));
+        assert!(history.redo(&mut w));
+        assert_eq!(
+            <World as GetResource<Journal>>::get(&w).entries().len(),
+            entries + 1
+        );
+    }
+}
+
+#[test]
+fn test_resource_changed() {
+    struct ReadString;
+    impl<'a> System<'a> for ReadString {
+        type Dependencies = (ReadResource<'a, String>,);
+        fn run(&'a mut self, _: Self::Dependencies) {}
+    }
+    struct WriteString;
+    impl<'a> System<'a> for WriteString {
+        type Dependencies = (WriteResource<'a, String>,);
+        fn run(&'a mut self, (mut s,): Self::Dependencies) {
+            s.push('!');
+        }
+    }
+
+    let mut w = World::default();
+    let mut changed = ResourceChanged::<String>::new();
+    let mut inputs_changed = ResourceChanged::<InputEvents<u32>>::new();
+    assert!(changed.check(&w));
+    assert!(inputs_changed.check(&w));
+
+    w.run_system(&mut ReadString);
+    assert!(!changed.check(&w));
+
+    w.run_system(&mut WriteString);
+    assert!(changed.check(&w));
+    assert!(!changed.check(&w));
+    assert!(!inputs_changed.check(&w));
+
+    <World as GetResource<String>>::set(&w, String::from("hello"));
+    assert!(changed.check(&w));
+    assert!(!inputs_changed.check(&w));
+}
+
+#[test]
+fn test_local() {
+    #[derive(Default)]
+    struct Counter {
+        runs: u32,
+        seen: u32,
+    }
+    impl<'a> System<'a> for Counter {
+        type Dependencies = (Local<'a, u32>, Local<'a, Vec<u32>>, ReadComponent<'a, Data>);
+        fn run(&'a mut self, (mut runs, mut history, data): Self::Dependencies) {
+            *runs += 1;
+            history.push(data.iter().flatten().count() as u32);
+            self.runs = *runs;
+            self.seen = history.iter().sum();
+        }
+    }
+    // Appends its name and how many times it has run to the string resource.
+    struct Tally(char);
+    impl<'a> OutputSystem<'a> for Tally {
+        type Dependencies = (Local<'a, u32>, WriteResource<'a, String>);
+        type Input = ();
+        type Output = ();
+        fn run_with(&'a mut self, _: (), (mut runs, mut s): Self::Dependencies) {
+            *runs += 1;
+            s.push(self.0);
+            s.push_str(&runs.to_string());
+        }
+    }
+
+    let mut w = World::default();
+    w.new_entity().with(Data { x: 1 }).build();
+    // State is per instance of the system, not per system type, including within a chain.
+    let mut dispatcher = Dispatcher::<World>::new()
+        .with(&mut w, Tally('a'))
+        .with(&mut w, Chain::new(Tally('b'), Tally('c')))
+        .with(&mut w, Tally('d'));
+    dispatcher.run(&mut w).unwrap();
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(
+        &*<World as GetResource<String>>::get(&w),
+        "a1b1c1d1a2b2c2d2"
+    );
+
+    // Systems run on their own start from scratch every time.
+    let mut counter = Counter::default();
+    w.run_system(&mut counter);
+    w.new_entity().with(Data { x: 2 }).build();
+    w.run_system(&mut counter);
+    assert_eq!(counter.runs, 1);
+    assert_eq!(counter.seen, 2);
+
+    // They don't have to be `'static`, either.
+    struct AddRuns<'s>(&'s mut u32);
+    impl<'a, 's> System<'a> for AddRuns<'s> {
+        type Dependencies = (Local<'a, u32>,);
+        fn run(&'a mut self, (mut runs,): Self::Dependencies) {
+            *runs += 1;
+            *self.0 += *runs;
+        }
+    }
+    let mut total = 0;
+    w.run_system(&mut AddRuns(&mut total));
+    w.run_system(&mut AddRuns(&mut total));
+    assert_eq!(total, 2);
+
+    // Locals are scratch state, and are not cloned along with the world.
+    let mut w2 = w.clone();
+    <World as GetResource<String>>::set(&w2, String::new());
+    dispatcher.run(&mut w2).unwrap();
+    assert_eq!(&*<World as GetResource<String>>::get(&w2), "a1b1c1d1");
+}
+
+#[test]
+fn test_try_system() {
+    #[derive(Debug, PartialEq)]
+    struct TooBig(u32);
+
+    struct CheckData;
+    impl<'a> TrySystem<'a> for CheckData {
+        type Dependencies = (ReadComponent<'a, Data>, Local<'a, u32>);
+        type Error = TooBig;
+        fn try_run(&'a mut self, (data, mut runs): Self::Dependencies) -> Result<(), TooBig> {
+            *runs += 1;
+            for d in data.iter().flatten() {
+                if d.x > 10 {
+                    return Err(TooBig(d.x));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut w = World::default();
+    w.new_entity().with(Data { x: 1 }).build();
+    assert_eq!(w.try_run_system(&mut CheckData), Ok(()));
+    w.new_entity().with(Data { x: 42 }).build();
+    assert_eq!(w.try_run_system(&mut CheckData), Err(TooBig(42)));
+
+    // Infallible systems can be run the same way.
+    struct Noop;
+    impl<'a> System<'a> for Noop {
+        type Dependencies = (ReadComponent<'a, Data>,);
+        fn run(&'a mut self, _: Self::Dependencies) {}
+    }
+    assert!(w.try_run_system(&mut Noop).is_ok());
+}
+
+#[test]
+fn test_dispatcher() {
+    struct Total;
+    impl<'a> OutputSystem<'a> for Total {
+        type Dependencies = (ReadComponent<'a, Data>,);
+        type Input = ();
+        type Output = u32;
+        fn run_with(&'a mut self, _: (), (data,): Self::Dependencies) -> u32 {
+            data.iter().flatten().map(|d| d.x).sum()
+        }
+    }
+    struct Check;
+    impl<'a> OutputSystem<'a> for Check {
+        type Dependencies = (WriteResource<'a, String>,);
+        type Input = u32;
+        type Output = Result<(), String>;
+        fn run_with(&'a mut self, total: u32, (mut s,): Self::Dependencies) -> Self::Output {
+            *s = total.to_string();
+            if total > 10 {
+                Err(format!("total too large: {}", total))
+            } else {
+                Ok(())
+            }
+        }
+    }
+    #[derive(Default)]
+    struct Count;
+    impl<'a> System<'a> for Count {
+        type Dependencies = (Local<'a, u32>, WriteComponent<'a, MoreData>);
+        fn run(&'a mut self, (mut runs, mut more_data): Self::Dependencies) {
+            *runs += 1;
+            more_data.set(Entity::new(0), Some(MoreData { y: *runs }));
+        }
+    }
+
+    let mut w = World::default();
+    let e = w.new_entity().with(Data { x: 4 }).build();
+    let mut dispatcher = Dispatcher::new()
+        .with(&mut w, Chain::new(Total, Check))
+        .with(&mut w, Count);
+
+    assert!(dispatcher.run(&mut w).is_ok());
+    assert!(dispatcher.run(&mut w).is_ok());
+    assert_eq!(&*<World as GetResource<String>>::get(&w), "4");
+    assert_eq!(
+        <World as GetComponent<'_, MoreData>>::get(&w).get(e),
+        Some(&MoreData { y: 2 })
+    );
+
+    // A failing system stops the rest of the dispatcher from running.
+    w.new_entity().with(Data { x: 7 }).build();
+    let err = dispatcher.run(&mut w).unwrap_err();
+    assert_eq!(err.error().to_string(), "total too large: 11");
+    assert!(err.system().contains("Chain"));
+    assert_eq!(&*<World as GetResource<String>>::get(&w), "11");
+    assert_eq!(
+        <World as GetComponent<'_, MoreData>>::get(&w).get(e),
+        Some(&MoreData { y: 2 })
+    );
+}
+
+#[test]
+fn test_async_system() {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    // Completes after being polled `polls` times.
+    struct Delay {
+        polls: u32,
+        commands: Option<Commands<World>>,
+    }
+    impl Future for Delay {
+        type Output = Commands<World>;
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Commands<World>> {
+            self.polls -= 1;
+            if self.polls == 0 {
+                Poll::Ready(self.commands.take().unwrap())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    struct Load;
+    impl<'a> AsyncSystem<'a, World> for Load {
+        type Dependencies = (ReadComponent<'a, Data>, Local<'a, u32>);
+        type Future = Delay;
+        fn run(&'a mut self, (data, mut starts): Self::Dependencies) -> Delay {
+            *starts += 1;
+            let total: u32 = data.iter().flatten().map(|d| d.x).sum();
+            let starts = *starts;
+            let mut commands = Commands::new();
+            commands.push(move |w: &mut World| {
+                w.new_entity().with(MoreData { y: total * starts }).build();
+            });
+            Delay {
+                polls: 3,
+                commands: Some(commands),
+            }
+        }
+    }
+
+    let mut w = World::default();
+    w.new_entity().with(Data { x: 5 }).build();
+    let mut dispatcher = Dispatcher::new().with_async(&mut w, Load);
+    let count = |w: &World| {
+        <World as GetComponent<'_, MoreData>>::get(w)
+            .iter()
+            .flatten()
+            .map(|m| m.y)
+            .collect::<Vec<_>>()
+    };
+
+    dispatcher.run(&mut w).unwrap();
+    dispatcher.run(&mut w).unwrap();
+    assert!(count(&w).is_empty());
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(count(&w), vec![5]);
+    // The next run starts the system again.
+    for _ in 0..3 {
+        dispatcher.run(&mut w).unwrap();
+    }
+    assert_eq!(count(&w), vec![5, 10]);
+}
+
+#[test]
+fn test_dispatcher_enable_disable() {
+    struct Append(char);
+    impl<'a> System<'a> for Append {
+        type Dependencies = (WriteResource<'a, String>,);
+        fn run(&'a mut self, (mut s,): Self::Dependencies) {
+            s.push(self.0);
+        }
+    }
+
+    let mut w = World::default();
+    let mut dispatcher = Dispatcher::new();
+    dispatcher
+        .add(&mut w, Append('a'))
+        .label("a")
+        .label("letters");
+    dispatcher.add(&mut w, Append('b')).label("letters");
+    dispatcher.add(&mut w, Append('c'));
+
+    dispatcher.run(&mut w).unwrap();
+    assert!(dispatcher.set_enabled("a", false));
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(dispatcher.is_enabled("a"), Some(false));
+    assert_eq!(dispatcher.is_enabled("letters"), Some(true));
+    assert!(dispatcher.set_enabled("letters", false));
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(dispatcher.is_enabled("letters"), Some(false));
+    assert!(!dispatcher.set_enabled("nonexistent", false));
+    assert_eq!(dispatcher.is_enabled("nonexistent"), None);
+    dispatcher.set_enabled("a", true);
+    dispatcher.run(&mut w).unwrap();
+
+    assert_eq!(&*<World as GetResource<String>>::get(&w), "abcbccac");
+}
+
+#[test]
+fn test_dispatcher_ordering() {
+    struct Append(char);
+    impl<'a> System<'a> for Append {
+        type Dependencies = (WriteResource<'a, String>,);
+        fn run(&'a mut self, (mut s,): Self::Dependencies) {
+            s.push(self.0);
+        }
+    }
+
+    let mut w = World::default();
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(&mut w, Append('d')).label("d").after("c");
+    dispatcher.add(&mut w, Append('a')).label("first");
+    dispatcher
+        .add(&mut w, Append('b'))
+        .label("first")
+        .after("a");
+    dispatcher.add(&mut w, Append('c')).label("c");
+    dispatcher.add(&mut w, Append('x')).label("a");
+    dispatcher
+        .add(&mut w, Append('e'))
+        .label("e")
+        .before("first");
+    dispatcher.configure_set("first").before("c");
+    dispatcher.build().unwrap();
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(&*<World as GetResource<String>>::get(&w), "xeabcd");
+
+    dispatcher
+        .add(&mut w, Append('f'))
+        .label("f")
+        .before("e")
+        .after("d");
+    match dispatcher.build() {
+        Err(ScheduleError::Cycle(systems)) => assert_eq!(systems.len(), 5),
+        Ok(()) => panic!("cycle not detected"),
+    }
+}
+
+#[test]
+fn test_define_schedule() {
+    struct Counter;
+    impl<'a> System<'a> for Counter {
+        type Dependencies = (Local<'a, u32>, WriteResource<'a, String>);
+        fn run(&'a mut self, (mut count, mut s): Self::Dependencies) {
+            *count += 1;
+            s.push_str(&count.to_string());
+        }
+    }
+
+    struct Fail;
+    impl<'a> TrySystem<'a> for Fail {
+        type Dependencies = (ReadComponent<'a, Data>, Local<'a, u32>);
+        type Error = String;
+        fn try_run(&'a mut self, _: Self::Dependencies) -> Result<(), String> {
+            Err("failed".to_owned())
+        }
+    }
+
+    struct Scale;
+    impl<'a> System<'a> for Scale {
+        type Dependencies = (ReadResource<'a, String>, WriteComponent<'a, MoreData>);
+        fn run(&'a mut self, (s, mut md): Self::Dependencies) {
+            (&mut md,).for_each(|_, (md,)| md.y *= s.len() as u32);
+        }
+    }
+
+    define_schedule!(
+        schedule TestSchedule {
+            counter: Counter,
+            fail: Fail,
+            scale: Scale,
+        }
+    );
+
+    assert_eq!(TestSchedule::SYSTEMS, &["counter", "fail", "scale"]);
+    assert_eq!(TestSchedule::stages(), &[vec![0, 1], vec![2]][..]);
+    const STAGE_OF: [usize; 3] = TestSchedule::stage_of::<World>();
+    assert_eq!(STAGE_OF, [0, 0, 1]);
+
+    let mut w = World::default();
+    let e = w.new_entity().with(MoreData { y: 3 }).build();
+    let mut schedule = TestSchedule {
+        counter: Counter,
+        fail: Fail,
+        scale: Scale,
+    };
+    let err = schedule.run(&mut w).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("system {} failed: failed", std::any::type_name::<Fail>())
+    );
+    assert_eq!(&*<World as GetResource<String>>::get(&w), "1");
+    assert_eq!(
+        <World as GetComponent<'_, MoreData>>::get(&w).get(e),
+        Some(&MoreData { y: 3 })
+    );
+}
+
+#[test]
+fn test_restricted_write() {
+    struct AddMore;
+    impl<'a> System<'a> for AddMore {
+        type Dependencies = (
+            RestrictedWriteComponent<'a, Data>,
+            ReadComponent<'a, MoreData>,
+        );
+        fn run(&'a mut self, (mut data, more_data): Self::Dependencies) {
+            (&mut data, &more_data).for_each(|_, (d, md)| d.x += md.y);
+        }
+    }
+
+    struct Double;
+    impl<'a> System<'a> for Double {
+        type Dependencies = (RestrictedWriteComponent<'a, Data>, ReadComponent<'a, Void>);
+        fn run(&'a mut self, (mut data, void): Self::Dependencies) {
+            (&mut data, &void).for_each(|_, (d, _)| d.x *= 2);
+        }
+    }
+
+    define_schedule!(
+        schedule Conservative {
+            add_more: AddMore,
+            double: Double,
+        }
+    );
+    define_schedule!(
+        schedule Parallel {
+            add_more: AddMore,
+            double: Double,
+        }
+        disjoint {
+            double, add_more;
+        }
+    );
+
+    assert_eq!(Conservative::stages(), &[vec![0], vec![1]][..]);
+    assert_eq!(Parallel::stages(), &[vec![0, 1]][..]);
+    assert_eq!(Conservative::stage_of::<World>(), [0, 1]);
+    assert_eq!(Parallel::stage_of::<World>(), [0, 0]);
+
+    let mut w = World::default();
+    let a = w
+        .new_entity()
+        .with(Data { x: 1 })
+        .with(MoreData { y: 2 })
+        .build();
+    let b = w.new_entity().with(Data { x: 3 }).with(Void {}).build();
+    let mut schedule = Parallel {
+        add_more: AddMore,
+        double: Double,
+    };
+    schedule.run(&mut w).unwrap();
+    let data = <World as GetComponent<'_, Data>>::get(&w);
+    assert_eq!(data.get(a), Some(&Data { x: 3 }));
+    assert_eq!(data.get(b), Some(&Data { x: 6 }));
+}
+
+#[test]
+fn test_entity_niche() {
+    assert_eq!(
+        std::mem::size_of::<Option<Entity>>(),
+        std::mem::size_of::<u64>()
+    );
+
+    let mut w = World::default();
+    let a = w.new_entity().build();
+    w.delete_entity(a);
+    let b = w.new_entity().build();
+    assert_eq!(a.id(), b.id());
+    assert_eq!(b.generation(), a.generation() + 1);
+    assert_ne!(a.to_bits(), b.to_bits());
+    assert_eq!(Entity::from_bits(b.to_bits()), Some(b));
+}
+
+#[test]
+fn test_stale_entity() {
+    let mut w = World::default();
+    let a = w.new_entity().with(Data { x: 1 }).with(Void {}).build();
+    w.delete_entity(a);
+    let b = w.new_entity().with(Data { x: 2 }).with(Void {}).build();
+    assert_eq!(a.id(), b.id());
+    assert!(!w.is_alive(a));
+    assert!(w.is_alive(b));
+
+    {
+        let mut data = <World as GetComponent<'_, Data>>::get_mut(&w);
+        assert_eq!(data.get(a), None);
+        assert_eq!(data.get_mut(a), None);
+        assert_eq!(data.get(b), Some(&Data { x: 2 }));
+        data.set(a, None);
+        assert_eq!(data.get(b), Some(&Data { x: 2 }));
+        let void = <World as GetComponent<'_, Void>>::get(&w);
+        assert!(void.get(a).is_none());
+        assert!(void.get(b).is_some());
+    }
+
+    struct Visit(Vec<Entity>);
+    impl<'a> System<'a> for Visit {
+        type Dependencies = (ReadComponent<'a, Data>, ReadComponent<'a, Void>);
+        fn run(&'a mut self, (data, void): Self::Dependencies) {
+            (&data, &void).for_each(|e, _| self.0.push(e));
+        }
+    }
+    let mut visit = Visit(vec![]);
+    w.run_system(&mut visit);
+    assert_eq!(visit.0, vec![b]);
+
+    // Deleting a stale handle does nothing.
+    w.delete_entity(a);
+    assert!(w.is_alive(b));
+    let c = w.new_entity().build();
+    assert_ne!(c.id(), b.id());
+}
+
+#[test]
+fn test_component_masks() {
+    let mut w = World::default();
+    let a = w.new_entity().with(Data { x: 1 }).with(Void {}).build();
+    let b = w.new_entity().with(MoreData { y: 1 }).build();
+    assert_eq!(
+        w.component_masks().components(a.id()).collect::<Vec<_>>(),
+        vec![0, 2]
+    );
+    assert_eq!(
+        w.component_masks().components(b.id()).collect::<Vec<_>>(),
+        vec![1]
+    );
+    assert!(w.component_masks().is_synced(0));
+
+    // Adding a component behind the world's back still gets cleaned up on deletion.
+    <World as GetComponent<'_, Data>>::get_mut(&w).set(b, Some(Data { x: 2 }));
+    assert!(!w.component_masks().is_synced(0));
+    w.delete_entity(b);
+    assert_eq!(w.component_masks().components(b.id()).count(), 0);
+    assert_eq!(
+        <World as GetComponent<'_, Data>>::get(&w).entity(b.id()),
+        None
+    );
+    // The masks were rebuilt, so later deletions can rely on them again.
+    assert!(w.component_masks().is_synced(0));
+
+    // So does removing one.
+    <World as GetComponent<'_, Data>>::get_mut(&w).set(a, None);
+    let c = w.clone_entity(a).unwrap();
+    assert_eq!(
+        w.component_masks().components(a.id()).collect::<Vec<_>>(),
+        vec![2]
+    );
+    assert_eq!(
+        w.component_masks().components(c.id()).collect::<Vec<_>>(),
+        vec![2]
+    );
+    assert!(<World as GetComponent<'_, Void>>::get(&w).get(c).is_some());
+
+    w.delete_entity(a);
+    assert_eq!(
+        <World as GetComponent<'_, Void>>::get(&w).entity(a.id()),
+        None
+    );
+    assert_eq!(w.clone_entity(a), None);
+}
+
+#[test]
+fn test_with_capacity() {
+    let mut w = World::with_capacity(100);
+    let entities = (0..100)
+        .map(|i| w.new_entity().with(Data { x: i }).with(Void {}).build())
+        .collect::<Vec<_>>();
+    w.reserve(50);
+    assert!(entities.iter().all(|&e| w.is_alive(e)));
+    assert_eq!(
+        <World as GetComponent<'_, Data>>::get(&w).get(entities[99]),
+        Some(&Data { x: 99 })
+    );
+    assert!(<World as GetComponent<'_, Void>>::get(&w)
+        .get(entities[99])
+        .is_some());
+}
+
+#[test]
+fn test_shared_storage() {
+    struct Flood;
+    impl<'a> System<'a> for Flood {
+        type Dependencies = (ReadComponent<'a, Data>, WriteComponent<'a, Tile>);
+        fn run(&'a mut self, (data, mut tiles): Self::Dependencies) {
+            (&data, &mut tiles).for_each(|_, (d, t)| {
+                if d.x > 1 {
+                    t.0 = "water";
+                }
+            });
+        }
+    }
+
+    let mut w = World::default();
+    let a = w
+        .new_entity()
+        .with(Data { x: 1 })
+        .with(Tile("grass"))
+        .build();
+    let b = w
+        .new_entity()
+        .with(Data { x: 2 })
+        .with(Tile("grass"))
+        .build();
+    let c = w.new_entity().with(Tile("grass")).build();
+    assert_eq!(<World as GetComponent<'_, Tile>>::get(&w).distinct(), 1);
+
+    w.run_system(&mut Flood);
+    {
+        let tiles = <World as GetComponent<'_, Tile>>::get(&w);
+        assert_eq!(tiles.get(a), Some(&Tile("grass")));
+        assert_eq!(tiles.get(b), Some(&Tile("water")));
+        assert_eq!(tiles.get(c), Some(&Tile("grass")));
+        // `a` was also handed out mutably by the join, so it has its own copy now.
+        assert_eq!(tiles.distinct(), 3);
+    }
+
+    <World as GetComponent<'_, Tile>>::get_mut(&w).compact();
+    assert_eq!(<World as GetComponent<'_, Tile>>::get(&w).distinct(), 2);
+    w.delete_entity(b);
+    assert_eq!(<World as GetComponent<'_, Tile>>::get(&w).distinct(), 1);
+    let iterated = <World as GetComponent<'_, Tile>>::get(&w)
+        .iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
+    assert_eq!(iterated, vec![Tile("grass"), Tile("grass")]);
+}
+
+#[test]
+fn test_multi_storage() {
+    struct Tick;
+    impl<'a> System<'a> for Tick {
+        type Dependencies = (WriteComponent<'a, Effect>, WriteComponent<'a, Data>);
+        fn run(&'a mut self, (mut effects, mut data): Self::Dependencies) {
+            (&mut effects.all_mut(), &mut data).for_each(|_, (effects, d)| {
+                for effect in effects.iter_mut() {
+                    d.x += effect.0;
+                    effect.0 -= 1;
+                }
+            });
+            let expired = (0..effects.size())
+                .filter_map(|id| effects.entity(id))
+                .collect::<Vec<_>>();
+            for e in expired {
+                effects.retain(e, |effect| effect.0 > 0);
+            }
+        }
+    }
+
+    let mut w = World::default();
+    let a = w.new_entity().with(Data { x: 0 }).with(Effect(1)).build();
+    let b = w.new_entity().with(Data { x: 0 }).build();
+    <World as GetComponent<'_, Effect>>::get_mut(&w).push(a, Effect(2));
+    <World as GetComponent<'_, Effect>>::get_mut(&w).push(b, Effect(1));
+
+    w.run_system(&mut Tick);
+    {
+        let effects = <World as GetComponent<'_, Effect>>::get(&w);
+        assert_eq!(effects.get_all(a), &[Effect(1)]);
+        assert_eq!(effects.get(a), Some(&Effect(1)));
+        assert!(effects.get_all(b).is_empty());
+        assert_eq!(effects.entity(b.id()), None);
+    }
+    w.run_system(&mut Tick);
+    let data = <World as GetComponent<'_, Data>>::get(&w);
+    assert_eq!(data.get(a), Some(&Data { x: 4 }));
+    assert_eq!(data.get(b), Some(&Data { x: 1 }));
+    assert_eq!(
+        <World as GetComponent<'_, Effect>>::get(&w).entity(a.id()),
+        None
+    );
+}
+
+#[test]
+fn test_clear() {
+    let mut w = World::default();
+    let a = w.new_entity().with(Data { x: 1 }).with(Void {}).build();
+    let b = w.new_entity().with(Tile("grass")).with(Effect(2)).build();
+    w.clear();
+    assert!(!w.is_alive(a));
+    assert!(!w.is_alive(b));
+    assert_eq!(<World as GetComponent<'_, Data>>::get(&w).size(), 0);
+    assert_eq!(<World as GetComponent<'_, Tile>>::get(&w).distinct(), 0);
+    assert_eq!(w.component_masks().components(a.id()).count(), 0);
+
+    // Slots are reused, but the old handles stay dead.
+    let c = w.new_entity().with(Data { x: 3 }).build();
+    assert!(w.is_alive(c));
+    assert!(!w.is_alive(a) && !w.is_alive(b));
+    assert_eq!(<World as GetComponent<'_, Data>>::get(&w).get(a), None);
+    assert_eq!(
+        <World as GetComponent<'_, Data>>::get(&w).get(c),
+        Some(&Data { x: 3 })
+    );
+}
+
+#[test]
+fn test_small_vec_storage() {
+    let mut w = World::default();
+    let entities = (0..6)
+        .map(|i| w.new_entity().with(Data { x: i }).build())
+        .collect::<Vec<_>>();
+    for &e in &entities[..3] {
+        w.new_entity().build();
+        <World as GetComponent<'_, Label>>::get_mut(&w).set(e, Some(Label("menu")));
+    }
+    assert!(!<World as GetComponent<'_, Label>>::get(&w).spilled());
+
+    <World as GetComponent<'_, Label>>::get_mut(&w).set(entities[5], Some(Label("back")));
+    let labels = <World as GetComponent<'_, Label>>::get(&w);
+    assert!(labels.spilled());
+    assert_eq!(labels.get(entities[0]), Some(&Label("menu")));
+    assert_eq!(labels.get(entities[4]), None);
+    assert_eq!(labels.get(entities[5]), Some(&Label("back")));
+    assert_eq!(labels.iter().flatten().count(), 4);
+}
+
+#[test]
+fn test_btree_map_storage() {
+    let mut a = BTreeMapStorage::default();
+    let mut b = BTreeMapStorage::default();
+    let ids = [5, 0, 9, 3];
+    for &id in &ids {
+        a.set(Entity::new(id), Some(id));
+    }
+    for &id in ids.iter().rev() {
+        b.set(Entity::new(id), Some(id));
+    }
+    b.set(Entity::new(9), None);
+    b.set(Entity::new(9), Some(9));
+
+    let expected = vec![0, 3, 5, 9];
+    assert_eq!(a.entries().map(|(_, &v)| v).collect::<Vec<_>>(), expected);
+    assert_eq!(b.entries().map(|(_, &v)| v).collect::<Vec<_>>(), expected);
+    assert_eq!(a.size(), 10);
+    assert_eq!(
+        a.iter()
+            .enumerate()
+            .filter_map(|(i, v)| v.map(|_| i as u32))
+            .collect::<Vec<_>>(),
+        expected
+    );
+    for v in b.iter_mut().flatten() {
+        *v *= 2;
+    }
+    assert_eq!(b.get(Entity::new(9)), Some(&18));
+    assert_eq!(b.get(Entity::new(9).next_generation()), None);
+}
+
+#[test]
+fn test_join_order() {
+    struct Visit(Vec<u32>);
+    impl<'a> System<'a> for Visit {
+        type Dependencies = (ReadComponent<'a, Data>, ReadComponent<'a, Void>);
+        fn run(&'a mut self, (data, void): Self::Dependencies) {
+            (&data, &void).for_each(|_, (d, _)| self.0.push(d.x));
+        }
+    }
+
+    let mut w = World::default();
+    let entities = (0..8)
+        .map(|i| w.new_entity().with(Data { x: i }).build())
+        .collect::<Vec<_>>();
+    // Add the second component in a scrambled order, and recycle some ids.
+    for &i in &[6, 1, 4, 3] {
+        <World as GetComponent<'_, Void>>::get_mut(&w).set(entities[i], Some(Void {}));
+    }
+    w.delete_entity(entities[2]);
+    w.delete_entity(entities[0]);
+    let a = w.new_entity().with(Data { x: 10 }).with(Void {}).build();
+    let b = w.new_entity().with(Data { x: 11 }).with(Void {}).build();
+    assert_eq!((a.id(), b.id()), (0, 2));
+
+    let mut visit = Visit(vec![]);
+    w.run_system(&mut visit);
+    assert_eq!(visit.0, vec![10, 1, 11, 3, 4, 6]);
+}
+
+#[test]
+fn test_move_and_swap_components() {
+    let mut w = World::default();
+    let a = w.new_entity().with(Data { x: 1 }).with(Void {}).build();
+    let b = w.new_entity().with(Data { x: 2 }).build();
+    let c = w.new_entity().build();
+
+    w.swap_components::<Data>(a, b);
+    w.swap_components::<Void>(a, b);
+    {
+        let data = <World as GetComponent<'_, Data>>::get(&w);
+        assert_eq!(data.get(a), Some(&Data { x: 2 }));
+        assert_eq!(data.get(b), Some(&Data { x: 1 }));
+    }
+    assert!(<World as GetComponent<'_, Void>>::get(&w).get(a).is_none());
+    assert!(<World as GetComponent<'_, Void>>::get(&w).get(b).is_some());
+
+    assert!(w.move_component::<Data>(a, c));
+    assert!(!w.move_component::<Data>(a, c));
+    assert_eq!(<World as GetComponent<'_, Data>>::get(&w).get(a), None);
+    assert_eq!(
+        <World as GetComponent<'_, Data>>::get(&w).get(c),
+        Some(&Data { x: 2 })
+    );
+    assert_eq!(
+        w.component_masks().components(c.id()).collect::<Vec<_>>(),
+        vec![0]
+    );
+    assert_eq!(w.component_masks().components(a.id()).count(), 0);
+
+    // Moving to a dead entity leaves the component where it is.
+    w.delete_entity(a);
+    assert!(!w.move_component::<Data>(c, a));
+    assert!(<World as GetComponent<'_, Data>>::get(&w).get(c).is_some());
+}
+
+#[test]
+fn test_default_take() {
+    #[allow(dead_code)]
+    mod untakeable_world {
+        use crate::*;
+
+        // A storage that relies on the default `take`.
+        #[derive(Debug, Default)]
+        pub struct Untakeable<T>(BasicVecStorage<T>);
+        impl<'a, T: 'a> ReadStorage<'a> for Untakeable<T> {
+            type Component = T;
+            fn get(&self, entity: Entity) -> Option<&T> {
+                self.0.get(entity)
+            }
+        }
+        impl<'a, T: 'a> ComponentStorage<'a> for Untakeable<T> {
+            type Iter = <BasicVecStorage<T> as ComponentStorage<'a>>::Iter;
+            fn get_raw(&self, entity: Entity) -> *const T {
+                self.0.get_raw(entity)
+            }
+            fn entity(&self, id: usize) -> Option<Entity> {
+                self.0.entity(id)
+            }
+            fn set(&mut self, entity: Entity, item: Option<T>) {
+                self.0.set(entity, item)
+            }
+            fn size(&self) -> usize {
+                self.0.size()
+            }
+            fn iter(&'a self) -> Self::Iter {
+                self.0.iter()
+            }
+        }
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Name(pub &'static str);
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    names: Untakeable<Name>,
+                }
+                resources {}
+            }
+        );
+    }
+    use untakeable_world::*;
+
+    let mut w = World::default();
+    let a = w.new_entity().with(Name("a")).build();
+    let b = w.new_entity().build();
+
+    // Nothing can be moved out, so nothing moves.
+    assert!(!w.move_component::<Name>(a, b));
+    assert_eq!(
+        <World as GetComponent<'_, Name>>::get(&w).get(a),
+        Some(&Name("a"))
+    );
+    assert_eq!(<World as GetComponent<'_, Name>>::get(&w).get(b), None);
+
+    // But replacing and deleting still work.
+    assert_eq!(
+        <World as GetComponent<'_, Name>>::insert(&mut w, a, Name("c")),
+        None
+    );
+    assert_eq!(
+        <World as GetComponent<'_, Name>>::get(&w).get(a),
+        Some(&Name("c"))
+    );
+    w.delete_entity(a);
+    assert_eq!(
+        <World as GetComponent<'_, Name>>::get(&w).entity(a.id()),
+        None
+    );
+}
+
+#[test]
+fn test_constraints() {
+    #[allow(dead_code)]
+    mod constrained_world {
+        use crate::*;
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Pos {
+            pub x: i32,
+        }
+        impl_persist!(Pos { x });
+        impl Migrate for Pos {}
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Vel {
+            pub dx: i32,
+        }
+        impl_persist!(Vel { dx });
+        impl Migrate for Vel {}
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    positions: BasicVecStorage<Pos>,
+                    velocities: BasicVecStorage<Vel>,
+                }
+                resources {}
+                constraints {
+                    velocities requires positions;
+                }
+            }
+        );
+    }
+    use constrained_world::*;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut w = World::default();
+    let a = w
+        .new_entity()
+        .with(Pos { x: 0 })
+        .with(Vel { dx: 1 })
+        .build();
+    let b = w.new_entity().build();
+
+    // Inserting a component needs the ones it requires to be there already.
+    assert!(catch_unwind(AssertUnwindSafe(|| {
+        <World as GetComponent<'_, Vel>>::insert(&mut w, b, Vel { dx: 2 });
+    }))
+    .is_err());
+    assert_eq!(<World as GetComponent<'_, Vel>>::get(&w).get(b), None);
+    <World as GetComponent<'_, Pos>>::insert(&mut w, b, Pos { x: 3 });
+    <World as GetComponent<'_, Vel>>::insert(&mut w, b, Vel { dx: 2 });
+
+    // Removing a required component panics and leaves it in place.
+    assert!(catch_unwind(AssertUnwindSafe(|| {
+        <World as GetComponent<'_, Pos>>::remove(&mut w, a);
+    }))
+    .is_err());
+    assert_eq!(
+        <World as GetComponent<'_, Pos>>::get(&w).get(a),
+        Some(&Pos { x: 0 })
+    );
+    assert_eq!(
+        <World as GetComponent<'_, Vel>>::remove(&mut w, a),
+        Some(Vel { dx: 1 })
+    );
+    assert_eq!(
+        <World as GetComponent<'_, Pos>>::remove(&mut w, a),
+        Some(Pos { x: 0 })
+    );
+
+    // Loading a save that breaks the constraints fails, and changes nothing.
+    let saved = w.save(1);
+    <World as GetComponent<'_, Pos>>::get_mut(&w).set(b, None);
+    let broken = w.save(1);
+    let mut restored = World::default();
+    assert!(restored.load(&broken).is_err());
+    assert!(restored.load_entity(&w.save_entity(b, 1).unwrap()).is_err());
+    assert!(!restored.is_alive(a) && !restored.is_alive(b));
+    restored.load(&saved).unwrap();
+    assert_eq!(
+        <World as GetComponent<'_, Vel>>::get(&restored).get(b),
+        Some(&Vel { dx: 2 })
+    );
+}
+
+#[test]
+fn test_builder_with_default_and_with_if() {
+    let mut w = World::default();
+    let spawn = |w: &mut World, elite: bool| {
+        w.new_entity()
+            .with(Data { x: 1 })
+            .with_default::<MoreData>()
+            .with_if(elite, Void {})
+            .build()
+    };
+    let a = spawn(&mut w, true);
+    let b = spawn(&mut w, false);
+    assert_eq!(
+        <World as GetComponent<'_, MoreData>>::get(&w).get(a),
+        Some(&MoreData::default())
+    );
+    assert!(<World as GetComponent<'_, Void>>::get(&w).get(a).is_some());
+    assert!(<World as GetComponent<'_, Void>>::get(&w).get(b).is_none());
+}
+
+#[test]
+fn test_spawn_batch() {
+    let mut w = World::default();
+    let template = ComponentSet {
+        test1: Some(Data { x: 0 }),
+        test3: Some(Void {}),
+        ..Default::default()
+    };
+    let entities = w.spawn_batch(&template, 5, |i, c| {
+        c.test1.as_mut().unwrap().x = i as u32 * 10;
+        if i % 2 == 1 {
+            c.test3 = None;
+        }
+    });
+    assert_eq!(entities.len(), 5);
+    assert!(entities.iter().all(|&e| w.is_alive(e)));
+    let data = <World as GetComponent<'_, Data>>::get(&w);
+    let void = <World as GetComponent<'_, Void>>::get(&w);
+    for (i, &e) in entities.iter().enumerate() {
+        assert_eq!(data.get(e), Some(&Data { x: i as u32 * 10 }));
+        assert_eq!(void.get(e).is_some(), i % 2 == 0);
+        assert_eq!(w.component_masks().contains(e.id(), 2), i % 2 == 0);
+    }
+}
+
+#[test]
+fn test_component_iteration() {
+    struct Double(Vec<(Entity, u32)>);
+    impl<'a> System<'a> for Double {
+        type Dependencies = (ReadComponent<'a, Data>, WriteComponent<'a, MoreData>);
+        fn run(&'a mut self, (data, mut more_data): Self::Dependencies) {
+            for (e, d) in more_data.iter_mut_with_entities() {
+                d.y *= 2;
+                self.0.push((e, d.y));
+            }
+            for d in more_data.components_mut() {
+                d.y += data.components().map(|d| d.x).sum::<u32>();
+            }
+        }
+    }
+
+    let mut w = World::default();
+    let a = w
+        .new_entity()
+        .with(Data { x: 1 })
+        .with(MoreData { y: 5 })
+        .build();
+    w.new_entity().with(Data { x: 2 }).build();
+    let c = w.new_entity().with(MoreData { y: 7 }).build();
+
+    let mut system = Double(vec![]);
+    w.run_system(&mut system);
+    assert_eq!(system.0, vec![(a, 10), (c, 14)]);
+    let more_data = <World as GetComponent<'_, MoreData>>::get(&w);
+    assert_eq!(more_data.get(c), Some(&MoreData { y: 17 }));
+}
+
+#[test]
+fn test_for_each_in() {
+    struct Initiative(Vec<Entity>, Vec<u32>);
+    impl<'a> System<'a> for Initiative {
+        type Dependencies = (ReadComponent<'a, Data>, WriteComponent<'a, MoreData>);
+        fn run(&'a mut self, (data, mut more_data): Self::Dependencies) {
+            let order = &self.0;
+            let visited = &mut self.1;
+            (&data, &mut more_data).for_each_in(order, |_, (d, m)| {
+                m.y += 1;
+                visited.push(d.x);
+            });
+        }
+    }
+
+    let mut w = World::default();
+    let a = w
+        .new_entity()
+        .with(Data { x: 1 })
+        .with(MoreData { y: 0 })
+        .build();
+    let b = w.new_entity().with(Data { x: 2 }).build();
+    let c = w
+        .new_entity()
+        .with(Data { x: 3 })
+        .with(MoreData { y: 0 })
+        .build();
+    let d = w
+        .new_entity()
+        .with(Data { x: 4 })
+        .with(MoreData { y: 0 })
+        .build();
+    w.delete_entity(d);
+
+    let mut system = Initiative(vec![c, b, a, d, c], vec![]);
+    w.run_system(&mut system);
+    assert_eq!(system.1, vec![3, 1]);
+    assert_eq!(
+        <World as GetComponent<'_, MoreData>>::get(&w).get(c),
+        Some(&MoreData { y: 1 })
+    );
+}
+
+#[test]
+fn test_single() {
+    struct FindVoid(Option<Entity>, Option<Entity>);
+    impl<'a> System<'a> for FindVoid {
+        type Dependencies = (ReadComponent<'a, Void>, WriteComponent<'a, Data>);
+        fn run(&'a mut self, (void, mut data): Self::Dependencies) {
+            self.0 = (&void, &data).single().map(|(e, _)| e);
+            self.1 = (&data,).single().map(|(e, _)| e);
+            let (_, (_, d)) = (&void, &mut data).expect_one();
+            d.x += 1;
+        }
+    }
+
+    let mut w = World::default();
+    w.new_entity().with(Data { x: 1 }).build();
+    let b = w.new_entity().with(Data { x: 2 }).with(Void {}).build();
+    w.new_entity().with(Void {}).build();
+
+    let mut system = FindVoid(None, None);
+    w.run_system(&mut system);
+    assert_eq!(system.0, Some(b));
+    assert_eq!(system.1, None);
+    assert_eq!(
+        <World as GetComponent<'_, Data>>::get(&w).get(b),
+        Some(&Data { x: 3 })
+    );
+}
+
+#[test]
+#[should_panic(expected = "found 2")]
+fn test_expect_one_panics() {
+    let mut w = World::default();
+    w.new_entity().with(Data { x: 1 }).build();
+    w.new_entity().with(Data { x: 2 }).build();
+    let (data,): (ReadComponent<Data>,) = ComponentProvider::fetch(&w);
+    (&data,).expect_one();
+}
+
+#[test]
+fn test_join_aggregates() {
+    let mut w = World::default();
+    w.new_entity().with(Data { x: 5 }).build();
+    let b = w.new_entity().with(Data { x: 2 }).with(Void {}).build();
+    let c = w.new_entity().with(Data { x: 9 }).with(Void {}).build();
+    let d = w.new_entity().with(Data { x: 2 }).build();
+
+    let (data, void): (ReadComponent<Data>, ReadComponent<Void>) = ComponentProvider::fetch(&w);
+    assert_eq!((&data,).iter().map(|(_, (d,))| d.x).sum::<u32>(), 18);
+    assert_eq!((&data, &void).iter().count(), 2);
+    assert_eq!((&data,).min_by_key(|_, (d,)| d.x).map(|(e, _)| e), Some(b));
+    assert_eq!((&data,).max_by_key(|_, (d,)| d.x).map(|(e, _)| e), Some(c));
+    assert_eq!(
+        (&data, &void).min_by_key(|_, (d, _)| d.x).map(|(e, _)| e),
+        Some(b)
+    );
+    // Ties go to the lowest id for `min_by_key` and the highest for `max_by_key`.
+    assert_eq!((&data,).max_by_key(|_, _| 0).map(|(e, _)| e), Some(d));
+    assert_eq!((&void,).min_by_key(|_, _| 0).map(|(e, _)| e), Some(b));
+}
+
+#[test]
+fn test_join_iter_mut() {
+    let mut w = World::default();
+    for x in 0..4 {
+        w.new_entity().with(Data { x }).build();
+    }
+    let (mut data,): (WriteComponent<Data>,) = ComponentProvider::fetch(&w);
+    let refs = (&mut data,).iter().map(|(_, (d,))| d).collect::<Vec<_>>();
+    for d in refs {
+        d.x *= 10;
+    }
+    assert_eq!((&data,).iter().map(|(_, (d,))| d.x).sum::<u32>(), 60);
+}
+
+#[test]
+fn test_group_by() {
+    let mut w = World::default();
+    let a = w.new_entity().with(Data { x: 1 }).build();
+    let b = w.new_entity().with(Data { x: 2 }).with(Void {}).build();
+    let c = w.new_entity().with(Data { x: 3 }).build();
+    let d = w.new_entity().with(Data { x: 4 }).with(Void {}).build();
+
+    let (data, void): (ReadComponent<Data>, ReadComponent<Void>) = ComponentProvider::fetch(&w);
+    let groups = (&data,).group_by(|_, (d,)| d.x % 2 == 0);
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[&false], vec![a, c]);
+    assert_eq!(groups[&true], vec![b, d]);
+
+    let groups = (&data, &void).group_by(|_, (d, _)| d.x > 2);
+    assert_eq!(groups[&false], vec![b]);
+    assert_eq!(groups[&true], vec![d]);
+}
+
+#[test]
+fn test_double_buffered_storage() {
+    struct Spread;
+    impl<'a> System<'a> for Spread {
+        type Dependencies = (WriteComponent<'a, Heat>,);
+        fn run(&'a mut self, (mut heat,): Self::Dependencies) {
+            // Each cell takes the heat of the cell before it, as of the start of the frame.
+            let entities = heat
+                .iter_with_entities()
+                .map(|(e, _)| e)
+                .collect::<Vec<_>>();
+            let (current, mut next) = heat.split_mut();
+            for pair in entities.windows(2) {
+                next.get_mut(pair[1]).unwrap().0 = current.get(pair[0]).unwrap().0;
+            }
+        }
+    }
+
+    let mut w = World::default();
+    let cells = (0..3)
+        .map(|i| {
+            w.new_entity()
+                .with(Heat(if i == 0 { 5 } else { 0 }))
+                .build()
+        })
+        .collect::<Vec<_>>();
+    let heat = |w: &World| {
+        let storage = <World as GetComponent<'_, Heat>>::get(w);
+        cells
+            .iter()
+            .map(|&e| storage.get(e).unwrap().0)
+            .collect::<Vec<_>>()
+    };
+
+    w.run_system(&mut Spread);
+    assert_eq!(heat(&w), vec![5, 0, 0]);
+    w.flip();
+    assert_eq!(heat(&w), vec![5, 5, 0]);
+    w.run_system(&mut Spread);
+    w.flip();
+    assert_eq!(heat(&w), vec![5, 5, 5]);
+
+    // Writes through a join go to the next buffer too.
+    let (mut storage,): (WriteComponent<Heat>,) = ComponentProvider::fetch(&w);
+    (&mut storage,).for_each(|_, (h,)| h.0 += 1);
+    assert_eq!(storage.get(cells[0]), Some(&Heat(5)));
+    assert_eq!(storage.get_next(cells[0]), Some(&Heat(6)));
+}
+
+#[test]
+fn test_interpolated_storage() {
+    struct Move;
+    impl<'a> System<'a> for Move {
+        type Dependencies = (WriteComponent<'a, Position>,);
+        fn run(&'a mut self, (mut pos,): Self::Dependencies) {
+            (&mut pos,).for_each(|_, (p,)| p.0 += 2.0);
+        }
+    }
+
+    let mut w = World::default();
+    let a = w.new_entity().with(Position(0.0)).build();
+    let lerp = |w: &World, t: f32| {
+        let (pos,): (ReadComponent<Position>,) = ComponentProvider::fetch(w);
+        (&pos, &pos.prev())
+            .iter()
+            .map(|(_, (cur, prev))| prev.0 + (cur.0 - prev.0) * t)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(lerp(&w, 0.5), vec![0.0]);
+
+    w.flip();
+    w.run_system(&mut Move);
+    assert_eq!(lerp(&w, 0.5), vec![1.0]);
+
+    // A new entity starts out stationary, while `a` keeps its previous position.
+    let b = w.new_entity().with(Position(10.0)).build();
+    {
+        let (mut pos,): (WriteComponent<Position>,) = ComponentProvider::fetch(&w);
+        pos.set(a, Some(Position(4.0)));
+    }
+    assert_eq!(lerp(&w, 0.5), vec![2.0, 10.0]);
+
+    w.flip();
+    assert_eq!(lerp(&w, 0.5), vec![4.0, 10.0]);
+    w.delete_entity(b);
+    let (pos,): (ReadComponent<Position>,) = ComponentProvider::fetch(&w);
+    assert_eq!(pos.prev().get(b), None);
+}
+
+#[test]
+fn test_dirty_region() {
+    let mut dirty = DirtyRegion2D::new(40, 3);
+    assert!(dirty.is_clean());
+
+    dirty.mark(39, 2);
+    dirty.mark(40, 0);
+    dirty.mark_rect(38, 0, 5, 2);
+    assert_eq!(
+        dirty.iter().collect::<Vec<_>>(),
+        vec![(38, 0), (39, 0), (38, 1), (39, 1), (39, 2)]
+    );
+    assert!(dirty.is_dirty(39, 2));
+    assert!(!dirty.is_dirty(40, 0));
+
+    dirty.clear();
+    assert!(dirty.is_clean());
+    dirty.mark_all();
+    assert_eq!(dirty.iter().count(), 120);
+}
+
+#[test]
+fn test_take_entity() {
+    let mut w = World::default();
+    let a = w
+        .new_entity()
+        .with(Data { x: 1 })
+        .with(Void {})
+        .with(Effect(1))
+        .build();
+    <World as GetComponent<'_, Effect>>::get_mut(&w).push(a, Effect(2));
+
+    let components = w.take_entity(a).unwrap();
+    assert!(!w.is_alive(a));
+    assert!(w.take_entity(a).is_none());
+    assert_eq!(components.test1, Some(Data { x: 1 }));
+    assert!(components.test3.is_some());
+    assert!(components.test2.is_none());
+    // Only the first effect comes along, but none are left behind.
+    assert_eq!(components.test5, Some(Effect(1)));
+    let b = w.build_entity(components);
+    assert_eq!(
+        <World as GetComponent<'_, Effect>>::get(&w).get(b),
+        Some(&Effect(1))
+    );
+}
+
+#[test]
+fn test_chunks() {
+    let mut unloaded = Vec::new();
+    let mut chunks = Chunks::new((
+        |(x, _)| {
+            let mut w = World::default();
+            w.new_entity().with(Data { x: x as u32 }).build();
+            w
+        },
+        |coord, mut w: World| unloaded.push((coord, w.take_entity(Entity::new(0)).is_some())),
+    ));
+
+    chunks.stream((2, 0), 0);
+    assert!(chunks.is_loaded((2, 0)));
+    let e = chunks
+        .get_mut((2, 0))
+        .unwrap()
+        .new_entity()
+        .with(Data { x: 7 })
+        .build();
+    assert_eq!(chunks.migrate((0, 0), e, (3, 0)), None);
+    let moved = chunks.migrate((2, 0), e, (3, 0)).unwrap();
+    assert!(!chunks.get((2, 0)).unwrap().is_alive(e));
+    let data = chunks
+        .iter()
+        .map(|(c, w)| {
+            let d = <World as GetComponent<'_, Data>>::get(w);
+            (c, d.iter().flatten().map(|d| d.x).collect::<Vec<_>>())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(data, vec![((2, 0), vec![2]), ((3, 0), vec![3, 7])]);
+    assert!(chunks.get((3, 0)).unwrap().is_alive(moved));
+
+    chunks.stream((3, 0), 0);
+    assert!(chunks.unload((3, 0)));
+    assert!(!chunks.unload((3, 0)));
+    assert!(chunks.is_empty());
+    drop(chunks);
+    assert_eq!(unloaded, vec![((2, 0), true), ((3, 0), true)]);
+}
+
+#[test]
+fn test_visibility() {
+    let mut w = World::default();
+    let a = w.new_entity().with(Data { x: 1 }).build();
+    let b = w.new_entity().with(Data { x: 8 }).with(Void {}).build();
+    let c = w.new_entity().with(Data { x: 2 }).with(Void {}).build();
+
+    let mut visibility = Visibility::new();
+    {
+        let (data,): (ReadComponent<Data>,) = ComponentProvider::fetch(&w);
+        visibility.update(&*data, |d| d.x < 5);
+    }
+    assert_eq!(visibility.iter().collect::<Vec<_>>(), vec![a, c]);
+
+    let (data, void): (ReadComponent<Data>, ReadComponent<Void>) = ComponentProvider::fetch(&w);
+    let seen = (&visibility, &void)
+        .iter()
+        .map(|(e, _)| e)
+        .collect::<Vec<_>>();
+    assert_eq!(seen, vec![c]);
+    let seen = (&data, &visibility)
+        .iter()
+        .map(|(_, (d, _))| d.x)
+        .collect::<Vec<_>>();
+    assert_eq!(seen, vec![1, 2]);
+
+    visibility.remove(c);
+    visibility.insert(b);
+    visibility.insert(b);
+    assert_eq!(visibility.len(), 2);
+    assert_eq!(
+        (&void, &visibility)
+            .iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>(),
+        vec![b]
+    );
+
+    // Handles of deleted entities don't match their successors.
+    drop((data, void));
+    w.delete_entity(b);
+    let d = w.new_entity().with(Void {}).build();
+    assert_eq!(d.id(), b.id());
+    let (void,): (ReadComponent<Void>,) = ComponentProvider::fetch(&w);
+    assert_eq!((&visibility, &void).iter().count(), 0);
+}
+
+#[test]
+fn test_path_solver() {
+    // An open 5x5 room.
+    let room = |(x, y): (i32, i32)| {
+        if (0..5).contains(&x) && (0..5).contains(&y) {
+            Some(1)
+        } else {
+            None
+        }
+    };
+    let solver = PathSolver::<_, ()>::new(room);
+    assert_eq!(solver.solve((2, 2), (2, 2)), Path::Found(vec![]));
+    assert_eq!(
+        solver.solve((0, 0), (0, 4)).steps().map(|s| s.len()),
+        Some(4)
+    );
+    assert_eq!(solver.solve((0, 0), (5, 5)), Path::Unreachable);
+
+    let solver = PathSolver::<_, ()>::new(room).diagonal(true);
+    assert_eq!(
+        solver.solve((0, 0), (3, 3)),
+        Path::Found(vec![(1, 1), (2, 2), (3, 3)])
+    );
+
+    // An unbounded grid: only the search limit stops an unreachable search.
+    let solver = PathSolver::<_, ()>::new(|t: (i32, i32)| if t == (9, 9) { None } else { Some(1) })
+        .max_expanded(100);
+    assert_eq!(solver.solve((0, 0), (9, 9)), Path::Unreachable);
+    assert!(solver.solve((0, 0), (3, 4)).steps().is_some());
+
+    let mut requests = PathRequests::new();
+    let (a, b) = (Entity::new(0), Entity::new(1));
+    requests.submit(a, (0, 0), (1, 1));
+    requests.submit(b, (0, 0), (2, 2));
+    requests.submit(a, (1, 1), (0, 0));
+    requests.cancel(a);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests.pop().map(|r| r.entity), Some(b));
+
+    // Searching at the edges of the grid, or with costs that add up past `u32::MAX`, doesn't
+    // overflow.
+    let solver = PathSolver::<_, ()>::new(|_| Some(u32::MAX / 2)).max_expanded(100);
+    assert_eq!(
+        solver.solve((i32::MAX, i32::MIN), (i32::MAX - 2, i32::MIN)),
+        Path::Found(vec![(i32::MAX - 1, i32::MIN), (i32::MAX - 2, i32::MIN)])
+    );
+    assert_eq!(
+        solver.solve((i32::MAX, 0), (i32::MAX - 3, 0)),
+        Path::Unreachable
+    );
+
+    #[allow(dead_code)]
+    mod path_world {
+        use crate::*;
+
+        #[derive(Debug, Default)]
+        pub struct Route(pub Option<Path>);
+        impl From<Path> for Route {
+            fn from(path: Path) -> Self {
+                Route(Some(path))
+            }
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    routes: BasicVecStorage<Route>,
+                }
+                resources {
+                    path_requests: PathRequests,
+                }
+            }
+        );
+    }
+    use path_world::*;
+
+    // Requests for entities deleted in the meantime are dropped.
+    let mut w = World::default();
+    let (a, b) = (w.new_entity().build(), w.new_entity().build());
+    {
+        let mut requests = <World as GetResource<PathRequests>>::get_mut(&w);
+        requests.submit(a, (0, 0), (1, 0));
+        requests.submit(b, (0, 0), (0, 1));
+    }
+    w.delete_entity(a);
+    let c = w.new_entity().build();
+    assert_eq!(c.id(), a.id());
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.add(&mut w, PathSolver::<_, Route>::new(room));
+    dispatcher.run(&mut w).unwrap();
+    assert!(<World as GetResource<PathRequests>>::get(&w).is_empty());
+    let routes = <World as GetComponent<'_, Route>>::get(&w);
+    assert!(routes.get(c).is_none());
+    assert_eq!(routes.get(b).unwrap().0, Some(Path::Found(vec![(0, 1)])));
+}
+
+#[test]
+fn test_intents() {
+    // Every actor with an even `x` wants to step forward.
+    struct Decide;
+    impl<'a> System<'a> for Decide {
+        type Dependencies = (ReadComponent<'a, Data>, WriteComponent<'a, Step>);
+        fn run(&'a mut self, (data, mut steps): Self::Dependencies) {
+            for (e, d) in data.iter_with_entities() {
+                if d.x % 2 == 0 {
+                    steps.set(e, Some(Step(d.x / 2)));
+                }
+            }
+        }
+    }
+
+    // Only resolves steps for actors that also have `MoreData`.
+    struct Resolve;
+    impl<'a> System<'a> for Resolve {
+        type Dependencies = (WriteComponent<'a, Step>, WriteComponent<'a, MoreData>);
+        fn run(&'a mut self, (mut steps, mut more): Self::Dependencies) {
+            (&steps, &mut more).for_each(|_, (s, m)| m.y += s.0);
+            steps.clear();
+        }
+    }
+
+    let mut w = World::default();
+    let a = w.new_entity().with(Data { x: 2 }).build();
+    let b = w
+        .new_entity()
+        .with(Data { x: 4 })
+        .with(MoreData { y: 0 })
+        .build();
+    w.new_entity().with(Data { x: 3 }).build();
+
+    w.run_system(&mut Decide);
+    assert_eq!(<World as GetComponent<'_, Step>>::get(&w).pending(), 2);
+    w.run_system(&mut Resolve);
+    assert_eq!(
+        <World as GetComponent<'_, MoreData>>::get(&w).get(b),
+        Some(&MoreData { y: 2 })
+    );
+
+    w.run_system(&mut Decide);
+    let mut resolved = Vec::new();
+    <World as GetComponent<'_, Step>>::get_mut(&w).resolve(|e, s| resolved.push((e, s)));
+    assert_eq!(resolved, vec![(a, Step(1)), (b, Step(2))]);
+
+    w.run_system(&mut Decide);
+    w.run_system(&mut ClearIntents::<Step>::new());
+    assert_eq!(<World as GetComponent<'_, Step>>::get(&w).pending(), 0);
+    w.delete_entity(a);
+    assert!(!w.is_alive(a));
+}
+
+#[test]
+fn test_blackboard() {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Target(Entity);
+
+    struct Spot;
+    impl<'a> System<'a> for Spot {
+        type Dependencies = (ReadComponent<'a, Data>, WriteResource<'a, Blackboard>);
+        fn run(&'a mut self, (data, mut board): Self::Dependencies) {
+            if let Some((e, _)) = (&data,).max_by_key(|_, (d,)| d.x) {
+                board.set(Target(e));
+            }
+        }
+    }
+
+    let mut w = World::default();
+    w.new_entity().with(Data { x: 1 }).build();
+    let big = w.new_entity().with(Data { x: 9 }).build();
+
+    let seen = <World as GetResource<Blackboard>>::get(&w).tick();
+    w.run_system(&mut Spot);
+    {
+        let mut board = <World as GetResource<Blackboard>>::get_mut(&w);
+        assert!(board.changed_since::<Target>(seen));
+        assert_eq!(board.get::<Target>(), Some(&Target(big)));
+        assert_eq!(board.get::<Data>(), None);
+
+        let seen = board.tick();
+        assert!(!board.changed_since::<Target>(seen));
+        board.get_mut::<Target>().unwrap();
+        assert!(board.changed_since::<Target>(seen));
+
+        board.register::<Data>("data");
+        board.register::<(u32, String)>("pair");
+        board.set(Data { x: 7 });
+        board.set((3u32, "three".to_string()));
+        assert_eq!(board.set(Data { x: 8 }), Some(Data { x: 7 }));
+    }
+
+    let saved = <World as GetResource<Blackboard>>::get(&w).save();
+    assert_eq!(
+        saved,
+        Value::Map(vec![
+            (
+                "data".to_string(),
+                Value::Map(vec![("x".to_string(), Value::UInt(8))])
+            ),
+            (
+                "pair".to_string(),
+                Value::List(vec![Value::UInt(3), Value::Str("three".to_string())])
+            ),
+        ])
+    );
+
+    let mut restored = Blackboard::new();
+    restored.register::<Data>("data");
+    restored.register::<(u32, String)>("pair");
+    restored.load(&saved).unwrap();
+    assert_eq!(restored.get::<Data>(), Some(&Data { x: 8 }));
+    assert_eq!(
+        restored.get::<(u32, String)>(),
+        Some(&(3, "three".to_string()))
+    );
+    assert!(!restored.contains::<Target>());
+
+    let bad = Value::Map(vec![("data".to_string(), Value::Map(vec![]))]);
+    assert_eq!(
+        restored.load(&bad),
+        Err(PersistError::MissingField("x".to_string()))
+    );
+    assert_eq!(u8::load(&Value::UInt(300)), Err(PersistError::OutOfRange));
+    assert_eq!(restored.remove::<Data>(), Some(Data { x: 8 }));
+    let copy = w.clone();
+    assert_eq!(
+        <World as GetResource<Blackboard>>::get(&copy).get::<Target>(),
+        Some(&Target(big))
+    );
+}
+
+#[cfg(feature = "bt")]
+#[test]
+fn test_behavior_tree() {
+    use crate::bt::*;
+
+    // The world is only used for this test, so not every generated method is.
+    #[allow(dead_code)]
+    mod behavior_world {
+        use std::vec::Vec;
+
+        use crate::bt::*;
+        use crate::*;
+
+        #[derive(Clone, Default)]
+        pub struct Brain(pub BehaviorTree<World>);
+        impl AsMut<BehaviorTree<World>> for Brain {
+            fn as_mut(&mut self) -> &mut BehaviorTree<World> {
+                &mut self.0
+            }
+        }
+
+        #[derive(Default)]
+        pub struct Counter(pub u32);
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    brains: BasicVecStorage<Brain>,
+                    counters: BasicVecStorage<Counter>,
+                }
+                resources {
+                    log: Vec<(Entity, &'static str)>,
+                }
+            }
+        );
+    }
+    use behavior_world::*;
+
+    fn log(ctx: &Context<World>, message: &'static str) {
+        ctx.resource_mut::<Vec<(Entity, &'static str)>>()
+            .push((ctx.entity(), message));
+    }
+
+    type Count = (RestrictedWriteComponent<'static, Counter>,);
+    type Compare = (ReadComponent<'static, Counter>,);
+    type Log = (WriteResource<'static, Vec<(Entity, &'static str)>>,);
+
+    // Counts up to 3 (one step per tick), then reports whether any other entity has more.
+    let tree = BehaviorTree::new(Behavior::sequence(vec![
+        Behavior::action::<Count, _>(|ctx: &Context<World>| {
+            let mut data = ctx.get_mut::<Counter>().unwrap();
+            if data.0 < 3 {
+                data.0 += 1;
+                Status::Running
+            } else {
+                Status::Success
+            }
+        }),
+        Behavior::selector(vec![
+            Behavior::sequence(vec![
+                Behavior::condition::<Compare, _>(|ctx: &Context<World>| {
+                    let mine = ctx.get::<Counter>().unwrap().0;
+                    let all = ctx.component::<Counter>();
+                    (&all,).iter().all(|(_, (d,))| d.0 <= mine)
+                })
+                .invert(),
+                Behavior::action::<Log, _>(|ctx: &Context<World>| {
+                    log(ctx, "behind");
+                    Status::Success
+                }),
+            ]),
+            Behavior::action::<Log, _>(|ctx: &Context<World>| {
+                log(ctx, "ahead");
+                Status::Failure
+            }),
+        ]),
+    ]));
+
+    let mut w = World::default();
+    let a = w
+        .new_entity()
+        .with(Brain(tree.clone()))
+        .with(Counter(0))
+        .build();
+    let b = w.new_entity().with(Brain(tree)).with(Counter(1)).build();
+    w.new_entity().with(Counter(10)).build();
+    let mut runner = BehaviorRunner::<Brain, (WriteComponent<Counter>, Group<Log>)>::new();
+
+    runner.run_on(&mut w, ());
+    runner.run_on(&mut w, ());
+    assert!(<World as GetResource<Vec<(Entity, &'static str)>>>::get(&w).is_empty());
+    runner.run_on(&mut w, ());
+    assert_eq!(
+        *<World as GetResource<Vec<(Entity, &'static str)>>>::get(&w),
+        vec![(b, "behind")]
+    );
+    {
+        let brains = <World as GetComponent<'_, Brain>>::get(&w);
+        assert_eq!(brains.get(a).unwrap().0.status(), Some(Status::Running));
+        assert_eq!(brains.get(b).unwrap().0.status(), Some(Status::Success));
+    }
+
+    <World as GetComponent<'_, Counter>>::get_mut(&w)
+        .get_mut(b)
+        .unwrap()
+        .0 = 20;
+    runner.run_on(&mut w, ());
+    assert_eq!(
+        *<World as GetResource<Vec<(Entity, &'static str)>>>::get(&w),
+        vec![(b, "behind"), (a, "behind"), (b, "ahead")]
+    );
+    {
+        let brains = <World as GetComponent<'_, Brain>>::get(&w);
+        assert_eq!(brains.get(b).unwrap().0.status(), Some(Status::Failure));
+    }
+
+    // Leaves can only use what they declare, and the runner has to cover it.
+    let sneaky = Behavior::action::<Compare, _>(|ctx: &Context<World>| {
+        log(ctx, "sneaky");
+        Status::Success
+    });
+    let c = w
+        .new_entity()
+        .with(Brain(BehaviorTree::new(sneaky)))
+        .build();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        runner.run_on(&mut w, ());
+    }));
+    assert!(result.is_err());
+    w.delete_entity(c);
+    w.new_entity()
+        .with(Brain(BehaviorTree::new(Behavior::action::<Log, _>(
+            |_: &Context<World>| Status::Success,
+        ))))
+        .build();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        BehaviorRunner::<Brain, (WriteComponent<Counter>,)>::new().run_on(&mut w, ());
+    }));
+    assert!(result.is_err());
+    let mut access = Access::new();
+    <BehaviorRunner<Brain, (WriteComponent<Counter>, Group<Log>)> as DependencyAccess>::record(
+        &mut access,
+    );
+    assert_eq!(access.writes().len(), 3);
+}
+
+// A world whose components and resources can all be saved. Not every generated method is
+// used.
+#[allow(dead_code)]
+mod saved_world {
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+
+    use crate::*;
+
+    // Version 1 saved armor as a bare number; version 2 added the material.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct Armor {
+        pub value: u32,
+        pub material: String,
+    }
+    impl_persist!(Armor { value, material });
+    impl Migrate for Armor {
+        fn migrate(version: u32, value: &Value) -> Result<Self, PersistError> {
+            if version < 2 {
+                Ok(Armor {
+                    value: u32::load(value)?,
+                    material: "leather".to_string(),
+                })
+            } else {
+                Armor::load(value)
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct Gold(pub u32);
+    impl Persist for Gold {
+        fn save(&self) -> Value {
+            self.0.save()
+        }
+        fn load(value: &Value) -> Result<Self, PersistError> {
+            u32::load(value).map(Gold)
+        }
+    }
+    impl Migrate for Gold {}
+
+    define_world!(
+        #[derive(Default)]
+        pub world {
+            components {
+                gold: BasicVecStorage<Gold>,
+                armor: BasicVecStorage<Armor>,
+            }
+            resources {
+                turn: u64,
+                history: Vec<Armor>,
+            }
+        }
+    );
+}
+
+#[test]
+fn test_validate() {
+    let mut w = World::default();
+    let a = w.new_entity().with(Data { x: 1 }).with(Void {}).build();
+    let b = w
+        .new_entity()
+        .with(Data { x: 2 })
+        .with(MoreData { y: 3 })
+        .build();
+    let c = w.new_entity().with(Effect(1)).build();
+    w.delete_entity(a);
+    w.take_entity(c);
+    w.new_entity().with(Void {}).build();
+    let report = w.validate();
+    assert!(report.is_ok(), "{}", report);
+
+    // Removing a component through the storage itself leaves the mask behind.
+    w.get_resources().test2.borrow_mut().set(b, None);
+    // And writing to a deleted entity's slot leaves a component on a dead entity.
+    w.get_resources()
+        .test1
+        .borrow_mut()
+        .set(c, Some(Data { x: 4 }));
+    let report = w.validate();
+    assert_eq!(
+        report.problems(),
+        &[
+            IntegrityProblem::DeadComponent {
+                component: std::any::type_name::<Data>(),
+                entity: c,
+            },
+            IntegrityProblem::MaskMismatch {
+                component: std::any::type_name::<MoreData>(),
+                slot: b.id(),
+                masked: true,
+            },
+        ]
+    );
+    assert!(report.to_string().starts_with("2 problem(s) found:"));
+
+    // Clearing puts everything right again.
+    w.clear();
+    assert!(w.validate().is_ok());
+}
+
+#[test]
+fn test_world_test_builder() {
+    use crate::testing::*;
+
+    struct Grow;
+    impl<'a> System<'a> for Grow {
+        type Dependencies = (
+            WriteComponent<'a, Data>,
+            ReadComponent<'a, MoreData>,
+            WriteResource<'a, String>,
+        );
+        fn run(&'a mut self, (mut data, more, mut log): Self::Dependencies) {
+            (&mut data, &more).for_each(|_, (d, m)| {
+                d.x += m.y;
+                log.push('+');
+            });
+        }
+    }
+
+    let (mut w, e) = WorldTestBuilder::<World>::new()
+        .entity((Data { x: 1 }, MoreData { y: 2 }))
+        .entity((Data { x: 5 },))
+        .entities((0..3).map(|y| (MoreData { y },)))
+        .entity(())
+        .resource(String::from(">"))
+        .build();
+    assert_eq!(e.len(), 6);
+    assert!(w.validate().is_ok());
+
+    run_once(&mut w, Grow);
+    assert_component_eq!(w, e[0], Data { x: 3 });
+    assert_component_eq!(w, e[1], Data { x: 5 }, "{:?} has no MoreData", e[1]);
+    assert_component_eq!(w, e[3], MoreData { y: 1 });
+    assert_no_component!(w, e[2], Data);
+    assert_no_component!(w, e[5], MoreData);
+    assert_resource_eq!(w, String::from(">+"));
+
+    assert_eq!(
+        check_component(&w, e[0], &Data { x: 4 }),
+        Err(format!(
+            "{:?}: expected Data {{ x: 4 }}, found Data {{ x: 3 }}",
+            e[0]
+        ))
+    );
+    assert!(check_component(&w, e[5], &Data { x: 4 })
+        .unwrap_err()
+        .ends_with("has no `ecstatic::tests::Data`"));
+}
+
+#[test]
+fn test_bench_hooks() {
+    let mut w = World::default();
+    let entities = (0..10)
+        .map(|i| w.new_entity().with(Data { x: i }).build())
+        .collect::<Vec<_>>();
+    for &e in entities.iter().step_by(2) {
+        <World as GetComponent<'_, MoreData>>::insert(&mut w, e, MoreData { y: 1 });
+    }
+    {
+        let (data, more): (ReadComponent<Data>, ReadComponent<MoreData>) =
+            ComponentProvider::fetch(&w);
+        assert_eq!(bench::join_count((&data, &more)), 5);
+        assert_eq!(bench::join_fold((&data,), 0, |sum, _, (d,)| sum + d.x), 45);
+        assert_eq!(bench::storage_iter(&*more), 5);
+    }
+
+    let mut storage = BasicVecStorage::<Data>::default();
+    assert_eq!(
+        bench::storage_insert(&mut storage, &entities, |e| Data { x: e.id() as u32 }),
+        0
+    );
+    assert_eq!(
+        bench::storage_insert(&mut storage, &entities[..3], |_| Data { x: 0 }),
+        3
+    );
+    assert_eq!(bench::storage_remove(&mut storage, &entities[5..]), 5);
+    assert_eq!(bench::storage_remove(&mut storage, &entities), 5);
+
+    let last = bench::entity_churn(&mut w, 4, 3).unwrap();
+    assert!(!w.is_alive(last));
+    assert_eq!((last.id(), last.generation()), (13, 3));
+    assert!(entities.iter().all(|&e| w.is_alive(e)));
+    assert!(w.validate().is_ok());
+}
+
+#[test]
+fn test_snapshot() {
+    use crate::testing::*;
+
+    let mut w = World::default();
+    let a = w
+        .new_entity()
+        .with(MoreData { y: 2 })
+        .with(Data { x: 1 })
+        .build();
+    let b = w.new_entity().with(Label("b")).build();
+    w.new_entity().build();
+    w.delete_entity(a);
+    w.new_entity().with(Effect(3)).build();
+    <World as GetResource<String>>::set(&w, String::from("hi"));
+    assert!(w.is_alive(b));
+
+    let snapshot = w.snapshot();
+    let expected = "\
+entity 0 (generation 2)
+    test5: Effect(3)
+entity 1 (generation 1)
+    test6: Label(\"b\")
+entity 2 (generation 1)
+resource board: Blackboard { slots: 0, tick: 0, registered: [] }
+resource inputs: InputEvents { events: [] }
+resource test_resource: \"hi\"
+";
+    assert_eq!(snapshot, expected);
+
+    let dir = std::env::temp_dir().join(format!("ecstatic-golden-{}", std::process::id()));
+    let path = dir.join("world.txt");
+    // A missing file fails, rather than being written.
+    let result = std::panic::catch_unwind(|| assert_golden(&path, &snapshot));
+    let message = *result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains(UPDATE_GOLDEN), "{}", message);
+    assert!(!path.exists());
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(&path, expected).unwrap();
+    assert_golden(&path, &snapshot);
+
+    w.delete_entity(b);
+    let changed = w.snapshot();
+    let result = std::panic::catch_unwind(|| assert_golden(&path, &changed));
+    std::fs::remove_dir_all(&dir).unwrap();
+    let message = *result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains(
+        "line 3: expected \"entity 1 (generation 1)\", found \"entity 2 (generation 1)\""
+    ));
+}
+
+#[cfg(feature = "quickcheck")]
+#[test]
+fn test_arbitrary_world() {
+    use crate::arbitrary::*;
+
+    #[allow(dead_code)]
+    mod generated_world {
+        use crate::arbitrary::*;
+        use crate::*;
+
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct Pos(pub i8, pub i8);
+        impl Arbitrary for Pos {
+            fn arbitrary(g: &mut Gen) -> Self {
+                Pos(i8::arbitrary(g), i8::arbitrary(g))
+            }
+        }
+
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct Vel(pub i8);
+        impl Arbitrary for Vel {
+            fn arbitrary(g: &mut Gen) -> Self {
+                Vel(i8::arbitrary(g))
+            }
+        }
+
+        define_world!(
+            #[derive(Clone, Default)]
+            pub world {
+                components {
+                    positions: BasicVecStorage<Pos>,
+                    velocities: BasicVecStorage<Vel>,
+                }
+                resources {}
+                constraints {
+                    velocities requires positions;
+                }
+            }
+        );
+    }
+    use generated_world::*;
+
+    let mut g = Gen::from_size_and_seed(50, 7);
+    let w: World = arbitrary_world(&mut g);
+    let entities = w.live_entities();
+    assert!(!entities.is_empty() && entities.len() <= 50);
+    assert!(w.validate().is_ok());
+    let positions = <World as GetComponent<'_, Pos>>::get(&w);
+    let velocities = <World as GetComponent<'_, Vel>>::get(&w);
+    for &e in entities.iter() {
+        assert!(velocities.get(e).is_none() || positions.get(e).is_some());
+    }
+
+    // The same seed gives the same world.
+    let again: World = arbitrary_world(&mut Gen::from_size_and_seed(50, 7));
+    assert_eq!(again.live_entities(), entities);
+    for &e in entities.iter() {
+        assert_eq!(
+            <World as GetComponent<'_, Pos>>::get(&again).get(e),
+            positions.get(e)
+        );
+    }
+
+    // Each shrink deletes one entity.
+    let shrunk = ArbitraryWorld(w.clone()).shrink().collect::<Vec<_>>();
+    assert_eq!(shrunk.len(), entities.len());
+    for (smaller, &e) in shrunk.iter().zip(entities.iter()) {
+        assert!(!smaller.0.is_alive(e));
+        assert_eq!(smaller.0.live_entities().len(), entities.len() - 1);
+    }
+}
+
+#[test]
+fn test_save_versions() {
+    use self::saved_world::*;
+
+    let mut w = World::default();
+    let a = w.new_entity().with(Gold(1)).build();
+    let b = w.new_entity().with(Gold(2)).build();
+    let c = w
+        .new_entity()
+        .with(Armor {
+            value: 3,
+            material: "iron".to_string(),
+        })
+        .build();
+    w.delete_entity(b);
+    <World as GetResource<u64>>::set(&w, 12);
+
+    let saved = w.save(2);
+    let mut restored = World::default();
+    restored.load(&saved).unwrap();
+    assert!(restored.is_alive(a) && !restored.is_alive(b) && restored.is_alive(c));
+    assert_eq!(restored.save(2), saved);
+    assert_eq!(*<World as GetResource<u64>>::get(&restored), 12);
+    {
+        let (gold, armor): (ReadComponent<Gold>, ReadComponent<Armor>) =
+            ComponentProvider::fetch(&restored);
+        assert_eq!((&gold,).iter().count(), 1);
+        assert_eq!(armor.get(c).unwrap().material, "iron");
+    }
+    // The deleted slot is reused, with a new generation.
+    let d = restored.new_entity().build();
+    assert_eq!(d.id(), b.id());
+    assert!(d != b);
+
+    // Single entities can be saved and loaded too.
+    let saved_c = restored.save_entity(c, 2).unwrap();
+    let e = restored.load_entity(&saved_c).unwrap();
+    assert_eq!(
+        <World as GetComponent<'_, Armor>>::get(&restored).get(e),
+        <World as GetComponent<'_, Armor>>::get(&restored).get(c)
+    );
+    assert_eq!(
+        <World as GetComponent<'_, Gold>>::get(&restored).get(e),
+        None
+    );
+    assert_eq!(restored.save_entity(b, 2), None);
+
+    // A version 1 save, from before armor had a material.
+    let old = Value::Map(vec![
+        ("version".to_string(), Value::UInt(1)),
+        ("entities".to_string(), vec![a].save()),
+        ("free".to_string(), Value::List(vec![])),
+        (
+            "components".to_string(),
+            Value::Map(vec![(
+                "armor".to_string(),
+                Value::List(vec![Value::List(vec![a.save(), Value::UInt(5)])]),
+            )]),
+        ),
+        (
+            "resources".to_string(),
+            Value::Map(vec![(
+                "history".to_string(),
+                Value::List(vec![Value::UInt(1), Value::UInt(2)]),
+            )]),
+        ),
+    ]);
+    restored.load(&old).unwrap();
+    assert!(restored.is_alive(a) && !restored.is_alive(c));
+    assert_eq!(
+        <World as GetComponent<'_, Armor>>::get(&restored).get(a),
+        Some(&Armor {
+            value: 5,
+            material: "leather".to_string(),
+        })
+    );
+    assert_eq!(
+        <World as GetResource<Vec<Armor>>>::get(&restored)[1].value,
+        2
+    );
+    // Resources missing from the save are left alone.
+    assert_eq!(*<World as GetResource<u64>>::get(&restored), 12);
+
+    // Failed loads don't change anything.
+    let before = restored.save(2);
+    let bad = Value::Map(vec![
+        ("version".to_string(), Value::UInt(2)),
+        ("entities".to_string(), Value::List(vec![])),
+        ("free".to_string(), Value::List(vec![])),
+        (
+            "components".to_string(),
+            Value::Map(vec![(
+                "gold".to_string(),
+                Value::List(vec![Value::List(vec![a.save(), Gold(1).save()])]),
+            )]),
+        ),
+        ("resources".to_string(), Value::Map(vec![])),
+    ]);
+    assert!(restored.load(&bad).is_err());
+    assert_eq!(restored.save(2), before);
+}
+
+#[test]
+fn test_binary_saves() {
+    use self::saved_world::*;
+
+    let mut w = World::default();
+    let mut entities = (0..100u32)
+        .map(|i| {
+            w.new_entity()
+                .with(Gold(i * 1000))
+                .with_if(
+                    i % 3 == 0,
+                    Armor {
+                        value: i,
+                        material: "bone".to_string(),
+                    },
+                )
+                .build()
+        })
+        .collect::<Vec<_>>();
+    for e in entities.drain(10..20) {
+        w.delete_entity(e);
+    }
+    w.disable(entities[30]);
+    <World as GetResource<u64>>::set(&w, 7);
+
+    let bytes = w.save_binary(3);
+    let mut text = Vec::new();
+    encode_value(&w.save(3), &mut text);
+    assert!(bytes.len() < text.len() / 2);
+
+    let mut restored = World::default();
+    restored.load_binary(&bytes).unwrap();
+    assert_eq!(restored.save(3), w.save(3));
+    assert_eq!(decode_world(&bytes), Ok(w.save(3)));
+    assert!(restored.is_disabled(entities[30]));
+    assert_eq!(restored.disabled().len(), 1);
+
+    // Saves from before disabled entities were recorded load with everything enabled.
+    let mut old = w.save(3);
+    if let Value::Map(fields) = &mut old {
+        fields.retain(|(name, _)| name != "disabled");
+    }
+    let mut from_old = World::default();
+    from_old.load(&old).unwrap();
+    assert!(from_old.disabled().is_empty());
+    assert!(from_old.is_alive(entities[30]));
+
+    // Truncated or corrupted saves are rejected without touching the world.
+    for n in 0..bytes.len() {
+        assert!(restored.load_binary(&bytes[..n]).is_err());
+    }
+    let mut corrupt = bytes.clone();
+    corrupt[0] = b'X';
+    assert_eq!(
+        restored.load_binary(&corrupt),
+        Err(PersistError::Malformed(
+            "not a binary world save".to_string()
+        ))
+    );
+    assert_eq!(restored.save(3), w.save(3));
+
+    // Deeply nested values are rejected rather than overflowing the stack.
+    let deep = |n: usize| {
+        let mut bytes = [7, 1].repeat(n);
+        bytes.push(0);
+        bytes
+    };
+    assert!(crate::binary::decode_value(&mut &deep(128)[..]).is_ok());
+    for n in [129, 1_000_000] {
+        assert_eq!(
+            crate::binary::decode_value(&mut &deep(n)[..]),
+            Err(PersistError::Malformed("nested too deeply".to_string()))
+        );
+    }
+}
+
+#[test]
+fn test_incremental_save() {
+    use self::saved_world::*;
+
+    let mut w = World::default();
+    let a = w.new_entity().with(Gold(5)).build();
+    let b = w
+        .new_entity()
+        .with(Gold(6))
+        .with(Armor {
+            value: 1,
+            material: "cloth".to_string(),
+        })
+        .build();
+    w.disable(b);
+    let expected = w.save_binary(2);
+
+    let mut save = w.save_incremental(2);
+    assert_eq!(save.remaining(), 4);
+    let mut out = Vec::new();
+    assert!(!save.step(&mut out).unwrap());
+    // Changes made while the save is in progress don't show up in it.
+    w.delete_entity(a);
+    <World as GetComponent<'_, Gold>>::get_mut(&w)
+        .get_mut(b)
+        .unwrap()
+        .0 = 100;
+    assert!(!save.step(&mut out).unwrap());
+    w.new_entity().with(Gold(7)).build();
+    save.finish(&mut out).unwrap();
+    assert!(save.is_done());
+    assert_eq!(out, expected);
+
+    let mut restored = World::default();
+    restored.load_binary(&out).unwrap();
+    assert!(restored.is_alive(a));
+    assert_eq!(
+        <World as GetComponent<'_, Gold>>::get(&restored).get(b),
+        Some(&Gold(6))
+    );
+    assert!(restored.is_disabled(b));
+}
+
+#[test]
+fn test_save_reserved_entities() {
+    use self::saved_world::*;
+
+    // Entities reserved since the world was last changed are saved as alive, without components.
+    let mut w = World::default();
+    let a = w.new_entity().with(Gold(1)).build();
+    let r = w.reserve_entity();
+    assert!(w.debug_entity(r).is_some());
+    let snapshot = w.snapshot();
+    assert!(snapshot.contains(&format!("entity {} (generation 1)", r.id())));
+    let saved = w.save(1);
+    let mut incremental = Vec::new();
+    w.save_incremental(1).finish(&mut incremental).unwrap();
+    assert_eq!(incremental, w.save_binary(1));
+
+    let mut restored = World::default();
+    restored.load(&saved).unwrap();
+    assert!(restored.is_alive(a) && restored.is_alive(r));
+    assert!(restored.component::<Gold>().get(r).is_none());
+    // The reserved slot isn't given to anything else.
+    let b = restored.new_entity().build();
+    assert!(b != a && b != r);
+    assert!(restored.validate().is_ok());
+
+    let mut restored = World::default();
+    restored.load_binary(&incremental).unwrap();
+    assert!(restored.is_alive(r));
+    assert_eq!(restored.save(1), saved);
+}
+
+#[test]
+fn test_compressed_saves() {
+    use self::saved_world::*;
+
+    let mut w = World::default();
+    let mut entities = Vec::new();
+    for i in 0..200 {
+        entities.push(w.new_entity().with(Gold(i % 3)).build());
+    }
+    let plain = w.save_binary(2);
+
+    let mut out = Vec::new();
+    w.save_to(2, &mut out, &Uncompressed).unwrap();
+    assert_eq!(out, plain);
+
+    // An incremental save can be written through the same adapter.
+    let mut streamed = Vec::new();
+    {
+        let mut writer = Uncompressed.compress(&mut streamed);
+        w.save_incremental(2).finish(&mut writer).unwrap();
+        writer.finish().unwrap();
+    }
+    assert_eq!(streamed, plain);
+
+    let mut restored = World::default();
+    restored.load_from(&mut &out[..], &Uncompressed).unwrap();
+    assert_eq!(restored.save_binary(2), plain);
+
+    #[cfg(feature = "deflate")]
+    {
+        let mut out = Vec::new();
+        w.save_to(2, &mut out, &Deflate::default()).unwrap();
+        assert!(out.len() < plain.len());
+
+        let mut restored = World::default();
+        restored
+            .load_from(&mut &out[..], &Deflate::default())
+            .unwrap();
+        assert_eq!(restored.save_binary(2), plain);
+
+        // Reading it back without decompressing fails instead of loading garbage.
+        assert!(restored.load_from(&mut &out[..], &Uncompressed).is_err());
+    }
+}
+
+#[test]
+fn test_world_cell() {
+    use crate::cell::{Ref, RefMut, WorldCell};
+
+    let cell = WorldCell::new(vec![1, 2, 3]);
+    {
+        let all = cell.borrow();
+        let again = Ref::clone(&all);
+        let first = Ref::map(again, |v| &v[0]);
+        assert_eq!(*first, 1);
+        assert!(Ref::filter_map(all, |v| v.get(5)).is_err());
+    }
+    {
+        let last = RefMut::filter_map(cell.borrow_mut(), |v| v.last_mut());
+        *last.unwrap() = 4;
+    }
+    let missing = RefMut::filter_map(cell.borrow_mut(), |v| v.get_mut(5));
+    let mut all = missing.unwrap_err();
+    all.push(5);
+    drop(all);
+    // Every borrow above has been released.
+    assert_eq!(cell.replace(Vec::new()), vec![1, 2, 4, 5]);
+    assert!(cell.borrow_mut().is_empty());
+}
+
+// Conflicting borrows are caught in every build, with or without `single_thread`.
+#[test]
+#[should_panic(expected = "already")]
+fn test_world_cell_conflict() {
+    let cell = crate::cell::WorldCell::new(0);
+    let _read = cell.borrow();
+    let _write = cell.borrow_mut();
+}
+
+#[test]
+fn test_rw_lock() {
+    use crate::cell::RwLock;
+    use std::sync::Arc;
+
+    let lock = Arc::new(RwLock::new(vec![1]));
+    {
+        let read = lock.read();
+        assert_eq!(*read, vec![1]);
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_write().is_none());
+    }
+    lock.write().push(2);
+
+    // A panic while the lock is held doesn't poison it.
+    let other = Arc::clone(&lock);
+    let result = std::thread::spawn(move || {
+        let mut v = other.write();
+        v.push(3);
+        panic!("while holding the lock");
+    })
+    .join();
+    assert!(result.is_err());
+    assert_eq!(*lock.read(), vec![1, 2, 3]);
+    let lock = Arc::try_unwrap(lock).unwrap();
+    assert_eq!(lock.into_inner(), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_dependency_groups() {
+    type Reads<'a> = (
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+        ReadComponent<'a, Data>,
+    );
+
+    // 12 * 3 + 1 dependencies, more than a flat tuple can have.
+    struct Mega;
+    impl<'a> System<'a> for Mega {
+        type Dependencies = (
+            Group<Reads<'a>>,
+            Group<(Group<Reads<'a>>, Group<Reads<'a>>)>,
+            WriteResource<'a, String>,
+        );
+        fn run(
+            &'a mut self,
+            (Group(first), Group((Group(second), _)), mut out): Self::Dependencies,
+        ) {
+            (&first.0,).for_each(|_, (d,)| out.push_str(&d.x.to_string()));
+            (&second.11,).for_each(|_, (d,)| out.push_str(&d.x.to_string()));
+        }
+    }
+
+    let mut w = World::default();
+    w.new_entity().with(Data { x: 4 }).build();
+    w.run_system(&mut Mega);
+    assert_eq!(*<World as GetResource<String>>::get(&w), "44");
+
+    let access = Access::of::<Mega>();
+    assert_eq!(
+        access.reads(),
+        &[AccessKey::Component(std::any::TypeId::of::<Data>())]
+    );
+    assert_eq!(
+        access.writes(),
+        &[AccessKey::Resource(std::any::TypeId::of::<String>())]
+    );
+}
+
+#[test]
+fn test_access_ignores_declaration_order() {
+    struct Forward;
+    impl<'a> System<'a> for Forward {
+        type Dependencies = (
+            ReadComponent<'a, Data>,
+            ReadComponent<'a, MoreData>,
+            WriteResource<'a, String>,
+            WriteResource<'a, u32>,
+        );
+        fn run(&'a mut self, _: Self::Dependencies) {}
+    }
+    struct Backward;
+    impl<'a> System<'a> for Backward {
+        type Dependencies = (
+            WriteResource<'a, u32>,
+            ReadComponent<'a, MoreData>,
+            WriteResource<'a, String>,
+            ReadComponent<'a, Data>,
+        );
+        fn run(&'a mut self, _: Self::Dependencies) {}
+    }
+
+    assert_eq!(Access::of::<Forward>(), Access::of::<Backward>());
+}
+
+#[test]
+fn test_read_write_storage() {
+    fn bump<'a, S: WriteStorage<'a, Component = u32>>(storage: &mut S, e: Entity) -> Option<u32> {
+        let old = storage.insert(e, 1)?;
+        *storage.get_mut(e).unwrap() += old;
+        storage.get(e).copied()
+    }
+
+    let e = Entity::new(3);
+    let mut basic = BasicVecStorage::default();
+    assert_eq!(bump(&mut basic, e), None);
+    assert_eq!(bump(&mut basic, e), Some(2));
+    assert_eq!(basic.remove(e), Some(2));
+    assert_eq!(basic.remove(e), None);
+
+    // A component left behind by an earlier generation is replaced, not returned.
+    let mut tree = BTreeMapStorage::default();
+    tree.insert(e, 7);
+    let next = e.next_generation();
+    assert_eq!(tree.insert(next, 5), None);
+    assert_eq!(tree.get(e), None);
+    assert_eq!(tree.remove(e), None);
+    assert_eq!(tree.remove(next), Some(5));
+}
+
+#[test]
+fn test_storage_conformance() {
+    use crate::storage::conformance::*;
+
+    // `VoidStorage` always hands back the default value, so every value has to compare equal.
+    #[derive(Debug, Default)]
+    struct Marker;
+    impl From<u32> for Marker {
+        fn from(_: u32) -> Self {
+            Marker
+        }
+    }
+    impl PartialEq for Marker {
+        fn eq(&self, _: &Marker) -> bool {
+            true
+        }
+    }
+
+    assert_mutable_storage_conformance::<BasicVecStorage<u32>>();
+    assert_mutable_storage_conformance::<BTreeMapStorage<u32>>();
+    assert_mutable_storage_conformance::<SmallVecStorage<u32, 4>>();
+    assert_mutable_storage_conformance::<ArenaStorage<u32>>();
+    assert_mutable_storage_conformance::<DoubleBufferedStorage<u32>>();
+    assert_mutable_storage_conformance::<IntentStorage<u32>>();
+    assert_mutable_storage_conformance::<MultiStorage<u32>>();
+    assert_mutable_storage_conformance::<InterpolatedStorage<u32>>();
+    assert_mutable_storage_conformance::<SharedStorage<u32>>();
+    assert_mutable_storage_conformance::<LayerStorage<Layers>>();
+    assert_mutable_storage_conformance::<DenseVecStorage<u32>>();
+    assert_storage_conformance::<VoidStorage<Marker>>();
+}
+
+#[test]
+fn test_system_setup() {
+    struct Greeter {
+        setups: usize,
+    }
+    impl<'a> System<'a> for Greeter {
+        type Dependencies = (WriteResource<'a, String>,);
+        fn run(&'a mut self, (mut s,): Self::Dependencies) {
+            s.push('!');
+        }
+        fn setup(&mut self, (mut s,): Self::Dependencies) {
+            self.setups += 1;
+            *s = "hello".to_owned();
+        }
+    }
+    struct Shout;
+    impl<'a> TrySystem<'a> for Shout {
+        type Dependencies = (WriteResource<'a, String>, Deferred<'a, World>);
+        type Error = std::fmt::Error;
+        fn try_run(&'a mut self, (mut s, _): Self::Dependencies) -> Result<(), Self::Error> {
+            *s = s.to_uppercase();
+            Ok(())
+        }
+        fn setup(&mut self, (mut s, deferred): Self::Dependencies) {
+            s.push_str(", world");
+            // Spawning through `Deferred` happens before `add` returns.
+            deferred.push(|w: &mut World| {
+                w.new_entity().with(Data { x: 42 }).build();
+            });
+        }
+    }
+
+    // Systems are set up as soon as they're added, before anything runs.
+    let mut w = World::default();
+    let mut dispatcher = Dispatcher::new().with(&mut w, Greeter { setups: 0 });
+    assert_eq!(*<World as GetResource<String>>::get(&w), "hello");
+    dispatcher.run(&mut w).unwrap();
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(*<World as GetResource<String>>::get(&w), "hello!!");
+
+    dispatcher.add(&mut w, Shout);
+    assert_eq!(*<World as GetResource<String>>::get(&w), "hello!!, world");
+    let data = <World as GetComponent<'_, Data>>::get(&w);
+    assert_eq!(
+        data.iter().flatten().map(|d| d.x).collect::<Vec<_>>(),
+        vec![42]
+    );
+    drop(data);
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(*<World as GetResource<String>>::get(&w), "HELLO!!, WORLD!");
+
+    // Systems can also be set up without a dispatcher.
+    let mut greeter = Greeter { setups: 0 };
+    greeter.setup_on(&mut w);
+    assert_eq!(greeter.setups, 1);
+    assert_eq!(*<World as GetResource<String>>::get(&w), "hello");
+}
+
+#[test]
+fn test_plugins() {
+    struct Append(char);
+    impl<'a> System<'a> for Append {
+        type Dependencies = (WriteResource<'a, String>,);
+        fn run(&'a mut self, (mut s,): Self::Dependencies) {
+            s.push(self.0);
+        }
+    }
+
+    define_schedule!(
+        schedule Pair {
+            first: Append,
+            second: Append,
+        }
+    );
+
+    struct Base;
+    impl Plugin<World> for Base {
+        fn build(&self, app: &mut EcsApp<World>) {
+            app.insert_resource("base:".to_owned());
+            app.add_system(Append('b')).label("base");
+        }
+    }
+
+    struct Extra(char);
+    impl Plugin<World> for Extra {
+        fn build(&self, app: &mut EcsApp<World>) {
+            app.add_plugin(Base);
+            app.add_system(Pair {
+                first: Append(self.0),
+                second: Append(self.0),
+            })
+            .before("base");
+        }
+    }
+
+    let mut app = EcsApp::<World>::default();
+    app.add_plugin(Extra('x'));
+    // `Extra` has already been built, and so has its dependency.
+    app.add_plugin(Extra('y')).add_plugin(Base);
+    assert!(app.has_plugin::<Base>());
+    assert_eq!(app.dispatcher().systems().count(), 2);
+
+    app.run().unwrap();
+    app.run().unwrap();
+    assert_eq!(
+        *<World as GetResource<String>>::get(app.world()),
+        "base:xxbxxb"
+    );
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn test_watch() {
+    use crate::persist::PersistError;
+    use crate::watch::*;
+    use std::time::{Duration, SystemTime};
+
+    #[allow(dead_code)]
+    mod watched_world {
+        use crate::*;
+        use alloc::string::String;
+        use alloc::vec::Vec;
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Tuning {
+            pub speed: f32,
+            pub names: Vec<String>,
+        }
+        impl_persist!(Tuning { speed, names });
+
+        #[derive(Debug, Default)]
+        pub struct Unused;
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    unused: VoidStorage<Unused>,
+                }
+                resources {
+                    tuning: Tuning,
+                }
+            }
+        );
+    }
+    use watched_world::*;
+
+    // Pins the modification time, so the test doesn't depend on the filesystem's resolution.
+    let path = std::env::temp_dir().join(format!("ecstatic-watch-{}.json", std::process::id()));
+    let write = |text: &str, secs: u64| {
+        std::fs::write(&path, text).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    };
+    let tuning = |w: &World| {
+        let t = <World as GetResource<Tuning>>::get(w);
+        (t.speed, t.names.clone())
+    };
+
+    let w = World::default();
+    let mut watcher = ResourceWatcher::new();
+    watcher.watch::<Tuning>(&path);
+    write(
+        r#"{"speed": 1.5, "names": ["a\"b", "\u00e9\ud83d\ude00"]}"#,
+        1000,
+    );
+    assert_eq!(watcher.poll(&w), vec![Ok("Tuning")]);
+    assert_eq!(
+        tuning(&w),
+        (1.5, vec!["a\"b".to_string(), "é😀".to_string()])
+    );
+    assert!(watcher.poll(&w).is_empty());
+
+    write(r#"{"speed": 2, "names": []}"#, 2000);
+    assert_eq!(watcher.poll(&w), vec![Ok("Tuning")]);
+    assert_eq!(tuning(&w), (2.0, vec![]));
+
+    // A broken file is reported once, and leaves the resource alone.
+    write(r#"{"speed": 3,, "names": []}"#, 3000);
+    let results = watcher.poll(&w);
+    assert_eq!(results.len(), 1);
+    let error = results[0].clone().unwrap_err();
+    assert_eq!(error.resource, "Tuning");
+    assert_eq!(
+        error.error,
+        PersistError::Malformed("expected a key at line 1, column 13".to_string())
+    );
+    assert_eq!(tuning(&w), (2.0, vec![]));
+    assert!(watcher.poll(&w).is_empty());
+
+    write(r#"{"speed": 3}"#, 4000);
+    assert_eq!(
+        watcher.poll(&w)[0].clone().unwrap_err().error,
+        PersistError::MissingField("names".to_string())
+    );
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        watcher.poll(&w)[..],
+        [Err(WatchError {
+            error: PersistError::Io(_),
+            ..
+        })]
+    ));
+    assert!(watcher.poll(&w).is_empty());
+    assert_eq!(tuning(&w), (2.0, vec![]));
+
+    for bad in [
+        "01",
+        "-",
+        "1.",
+        "[1,]",
+        "{\"a\":1,\"a\":2}",
+        "\"\\x\"",
+        "tru",
+        "1 2",
+        "1e",
+        "-.5",
+    ] {
+        assert!(
+            matches!(parse_json(bad), Err(PersistError::Malformed(_))),
+            "{}",
+            bad
+        );
+    }
+    assert_eq!(
+        parse_json("18446744073709551616"),
+        Err(PersistError::OutOfRange)
+    );
+    assert_eq!(parse_json("-0.5e1"), Ok(Value::Float(-5.0)));
+
+    // Nesting is limited, rather than overflowing the stack.
+    let deep = |n: usize| format!("{}{}", "[".repeat(n), "]".repeat(n));
+    assert!(parse_json(&deep(128)).is_ok());
+    assert_eq!(
+        parse_json(&deep(129)),
+        Err(PersistError::Malformed(
+            "nested too deeply at line 1, column 129".to_string()
+        ))
+    );
+    assert!(matches!(
+        parse_json(&"{\"a\":".repeat(100_000)),
+        Err(PersistError::Malformed(_))
+    ));
+
+    #[cfg(feature = "ron")]
+    {
+        let path = path.with_extension("ron");
+        std::fs::write(&path, r#"Tuning(speed: 4.0, names: ["c", "d"])"#).unwrap();
+        watcher.watch_with::<Tuning>(&path, parse_ron);
+        assert_eq!(watcher.poll(&w), vec![Ok("Tuning")]);
+        assert_eq!(tuning(&w), (4.0, vec!["c".to_string(), "d".to_string()]));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            parse_ron("Some('x')"),
+            Ok(Value::List(vec![Value::Str("x".to_string())]))
+        );
+        assert!(matches!(
+            parse_ron("{1: 2}"),
+            Err(PersistError::Malformed(_))
+        ));
+        assert!(matches!(
+            parse_ron(&deep(100_000)),
+            Err(PersistError::Malformed(_))
+        ));
+    }
+}
+
+#[test]
+fn test_interner() {
+    let mut interner: Interner = Interner::new();
+    let a = interner.intern("orc");
+    let b = interner.intern(&String::from("orc"));
+    let c = interner.intern("troll");
+    assert!(Interned::ptr_eq(&a, &b));
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(interner.len(), 2);
+    assert_eq!(interner.get("troll"), Some(c.clone()));
+    assert_eq!(interner.get("ogre"), None);
+
+    // A loaded handle has its own copy, which interning swaps for the shared one.
+    let loaded = Interned::<str>::load(&a.save()).unwrap();
+    assert_eq!(loaded, a);
+    assert!(!Interned::ptr_eq(&loaded, &a));
+    assert!(Interned::ptr_eq(&interner.intern(&loaded), &a));
+
+    drop(c);
+    assert_eq!(interner.collect_garbage(), 1);
+    assert_eq!(interner.get("troll"), None);
+    drop((a, b));
+    assert_eq!(interner.collect_garbage(), 1);
+    assert!(interner.is_empty());
+
+    let mut bytes: Interner<[u8]> = Interner::new();
+    let x = bytes.intern(&[1, 2, 3][..]);
+    assert!(Interned::ptr_eq(&x, &bytes.intern(&[1, 2, 3])));
+}
+
+#[test]
+fn test_entity_ref() {
+    let mut w = World::default();
+    let e = w.new_entity().with(Data { x: 1 }).build();
+    let other = w.new_entity().with(Data { x: 2 }).build();
+
+    {
+        let r = w.entity(e);
+        assert_eq!(r.id(), e);
+        assert!(r.is_alive());
+        assert_eq!(*r.get::<Data>().unwrap(), Data { x: 1 });
+        assert!(!r.has::<MoreData>());
+        // Handles can read different components at once.
+        let _data = r.get::<Data>().unwrap();
+        assert!(r.get::<Tile>().is_none());
+    }
+
+    let mut m = w.entity_mut(e);
+    assert_eq!(m.insert(MoreData { y: 3 }), None);
+    assert_eq!(m.insert(MoreData { y: 4 }), Some(MoreData { y: 3 }));
+    m.get_mut::<Data>().unwrap().x += 10;
+    assert!(m.get_mut::<Tile>().is_none());
+    assert_eq!(m.remove::<Data>(), Some(Data { x: 11 }));
+    assert_eq!(m.remove::<Data>(), None);
+    assert!(m.as_ref().has::<MoreData>());
+    m.despawn();
+
+    assert!(!w.entity(e).is_alive());
+    assert!(w.entity(e).get::<MoreData>().is_none());
+    assert_eq!(w.entity_mut(e).insert(Data { x: 5 }), None);
+    assert!(!w.entity(e).has::<Data>());
+    assert_eq!(w.entity(other).get::<Data>().unwrap().x, 2);
+    // Changes through a handle keep the masks and journal up to date.
+    assert!(w.validate().is_ok());
+}
+
+#[test]
+fn test_component_accessors() {
+    let mut w = World::default();
+    let e = w.new_entity().with(Data { x: 1 }).build();
+    w.component_mut::<Data>().get_mut(e).unwrap().x = 7;
+    assert_eq!(w.component::<Data>().get(e), Some(&Data { x: 7 }));
+    assert!(w.component::<MoreData>().get(e).is_none());
+    // Different storages can be borrowed at once.
+    let _data = w.component::<Data>();
+    let _tiles = w.component_mut::<Tile>();
+}
+
+#[test]
+fn test_fetch() {
+    let mut w = World::default();
+    let e = w
+        .new_entity()
+        .with(Data { x: 2 })
+        .with(MoreData { y: 3 })
+        .build();
+    {
+        let (data, mut more, mut resource) = w
+            .fetch::<(
+                ReadComponent<Data>,
+                WriteComponent<MoreData>,
+                WriteResource<String>,
+            )>()
+            .unwrap();
+        (&data, &mut more).for_each(|_, (d, m)| m.y *= d.x);
+        resource.push_str("fetched");
+    }
+    assert_eq!(w.component::<MoreData>().get(e), Some(&MoreData { y: 6 }));
+    assert_eq!(*<World as GetResource<String>>::get(&w), "fetched");
+
+    // Shared borrows of the same storage don't conflict.
+    assert!(w
+        .fetch::<(ReadComponent<Data>, ReadComponent<Data>)>()
+        .is_ok());
+
+    let conflict = w
+        .fetch::<(
+            ReadComponent<Data>,
+            WriteComponent<MoreData>,
+            ReadComponent<MoreData>,
+        )>()
+        .err()
+        .unwrap();
+    assert_eq!(
+        conflict.key,
+        AccessKey::Component(core::any::TypeId::of::<MoreData>())
+    );
+    assert!(conflict.to_string().contains("MoreData"), "{}", conflict);
+    // Nothing was left borrowed.
+    assert!(w.fetch::<(WriteComponent<MoreData>,)>().is_ok());
+
+    let conflict = w
+        .fetch::<(WriteResource<String>, Group<(ReadResource<String>,)>)>()
+        .err()
+        .unwrap();
+    assert_eq!(
+        conflict.to_string(),
+        format!(
+            "resource `{}` is borrowed mutably by one dependency while another uses it",
+            core::any::type_name::<String>()
+        )
+    );
+
+    // Borrows made outside the fetch are checked too, instead of panicking.
+    {
+        let _data = w.component::<Data>();
+        let conflict = w
+            .fetch::<(ReadComponent<MoreData>, WriteComponent<Data>)>()
+            .err()
+            .unwrap();
+        assert_eq!(
+            conflict.key,
+            AccessKey::Component(core::any::TypeId::of::<Data>())
+        );
+        assert!(conflict.already_borrowed);
+        assert!(w
+            .fetch::<(ReadComponent<Data>, WriteComponent<MoreData>)>()
+            .is_ok());
+        assert!(w.fetch::<(WriteComponent<MoreData>,)>().is_ok());
+    }
+    {
+        let _resource = <World as GetResource<String>>::get_mut(&w);
+        let conflict = w.fetch::<(ReadResource<String>,)>().err().unwrap();
+        assert_eq!(
+            conflict.to_string(),
+            format!(
+                "resource `{}` is already borrowed",
+                core::any::type_name::<String>()
+            )
+        );
+        let _journal = <World as GetResource<Journal>>::get(&w);
+        assert!(w.fetch::<(WriteResource<Journal>,)>().is_err());
+        assert!(w.fetch::<(ReadResource<Journal>,)>().is_ok());
+    }
+    assert!(w
+        .fetch::<(WriteComponent<Data>, WriteResource<String>)>()
+        .is_ok());
+}
+
+#[test]
+fn test_resource_override() {
+    let mut w = World::default();
+    <World as GetResource<String>>::set(&w, "sunny".to_string());
+    let seen = w.with_resource_override("rain".to_string(), |w| {
+        let outer = <World as GetResource<String>>::get(w).clone();
+        let inner = w.with_resource_override("snow".to_string(), |w| {
+            <World as GetResource<String>>::get_mut(w).push('!');
+            <World as GetResource<String>>::get(w).clone()
+        });
+        (outer, inner, <World as GetResource<String>>::get(w).clone())
+    });
+    assert_eq!(
+        seen,
+        ("rain".to_string(), "snow!".to_string(), "rain".to_string())
+    );
+    assert_eq!(*<World as GetResource<String>>::get(&w), "sunny");
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        w.with_resource_override("hail".to_string(), |_| panic!("simulation failed"))
+    }));
+    assert!(result.is_err());
+    assert_eq!(*<World as GetResource<String>>::get(&w), "sunny");
+}
+
+#[test]
+fn test_frame_info() {
+    use core::time::Duration;
+
+    #[allow(dead_code)]
+    mod timed_world {
+        use crate::*;
+        use alloc::vec::Vec;
+
+        #[derive(Debug, Default)]
+        pub struct Unused;
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    unused: VoidStorage<Unused>,
+                }
+                resources {
+                    frame: FrameInfo,
+                    seen: Vec<(u64, u32)>,
+                }
+            }
+        );
+    }
+    use timed_world::*;
+
+    struct Record;
+    impl<'a> System<'a> for Record {
+        type Dependencies = (
+            ReadResource<'a, FrameInfo>,
+            WriteResource<'a, Vec<(u64, u32)>>,
+        );
+        fn run(&'a mut self, (frame, mut seen): Self::Dependencies) {
+            seen.push((frame.tick(), frame.delta().as_millis() as u32));
+        }
+    }
+
+    let mut app = EcsApp::new(World::default());
+    app.add_system(Record);
+    app.run_frame(Duration::from_millis(16)).unwrap();
+    app.run_frame(Duration::from_millis(20)).unwrap();
+    // Running without starting a frame leaves the frame info alone.
+    app.run().unwrap();
+    let frame = *<World as GetResource<FrameInfo>>::get(app.world());
+    assert_eq!(frame.tick(), 2);
+    assert_eq!(frame.elapsed(), Duration::from_millis(36));
+    assert!((frame.delta_secs() - 0.02).abs() < 1e-6);
+    assert_eq!(
+        *<World as GetResource<Vec<(u64, u32)>>>::get(app.world()),
+        vec![(1, 16), (2, 20), (2, 20)]
+    );
+
+    let mut clock = FrameClock::new();
+    assert_eq!(clock.delta(), Duration::ZERO);
+    std::thread::sleep(Duration::from_millis(2));
+    assert!(clock.delta() >= Duration::from_millis(2));
+}
+
+#[test]
+fn test_timers() {
+    use core::time::Duration;
+
+    #[allow(dead_code)]
+    mod timer_world {
+        use crate::*;
+
+        #[derive(Default)]
+        pub struct Burning(pub Timer);
+        impl AsMut<Timer> for Burning {
+            fn as_mut(&mut self) -> &mut Timer {
+                &mut self.0
+            }
+        }
+
+        #[derive(Default)]
+        pub struct Attack(pub Timer);
+        impl AsMut<Timer> for Attack {
+            fn as_mut(&mut self) -> &mut Timer {
+                &mut self.0
+            }
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    burning: BasicVecStorage<Burning>,
+                    attacks: BasicVecStorage<Attack>,
+                }
+                resources {
+                    frame: FrameInfo,
+                    burnt_out: Expired<Burning>,
+                    attacks_ready: Expired<Attack>,
+                }
+            }
+        );
+    }
+    use timer_world::*;
+
+    let ms = Duration::from_millis;
+    let mut timer = Timer::time(ms(100)).repeating();
+    assert_eq!(timer.tick(1, ms(40)), 0);
+    assert_eq!(timer.tick(1, ms(60)), 1);
+    assert_eq!(timer.remaining(), Span::Time(ms(100)));
+    assert_eq!(timer.tick(1, ms(250)), 2);
+    assert_eq!(timer.remaining(), Span::Time(ms(50)));
+    let mut once = Timer::turns(2);
+    assert_eq!(once.tick(5, ms(0)), 1);
+    assert!(once.is_finished());
+    assert_eq!(once.tick(5, ms(0)), 0);
+    once.restart();
+    assert_eq!(once.remaining(), Span::Turns(2));
+
+    let mut w = World::default();
+    let a = w.new_entity().with(Burning(Timer::turns(1))).build();
+    let b = w
+        .new_entity()
+        .with(Burning(Timer::time(ms(30)).repeating()))
+        .with(Attack(Timer::cooldown(Span::Turns(2))))
+        .build();
+    let mut dispatcher = Dispatcher::new()
+        .with(&mut w, TickTimers::<Burning>::new())
+        .with(&mut w, TickTimers::<Attack>::new());
+
+    dispatcher.run_frame(&mut w, ms(20)).unwrap();
+    assert_eq!(
+        <World as GetResource<Expired<Burning>>>::get(&w)
+            .iter()
+            .copied()
+            .collect::<Vec<_>>(),
+        vec![a]
+    );
+    // A ready cooldown doesn't expire again until it's used.
+    assert!(w.component::<Attack>().get(b).unwrap().0.ready());
+    assert!(<World as GetResource<Expired<Attack>>>::get(&w).is_empty());
+    w.component_mut::<Attack>().get_mut(b).unwrap().0.restart();
+
+    dispatcher.run_frame(&mut w, ms(20)).unwrap();
+    assert_eq!(
+        <World as GetResource<Expired<Burning>>>::get(&w)
+            .iter()
+            .copied()
+            .collect::<Vec<_>>(),
+        vec![b]
+    );
+    assert!(!w.component::<Attack>().get(b).unwrap().0.ready());
+    // Running again without starting a frame changes nothing.
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(<World as GetResource<Expired<Burning>>>::get(&w).len(), 1);
+
+    dispatcher.run_frame(&mut w, ms(20)).unwrap();
+    assert!(<World as GetResource<Expired<Burning>>>::get(&w).contains(b));
+    assert_eq!(
+        w.component::<Burning>().get(b).unwrap().0.remaining(),
+        Span::Time(ms(30))
+    );
+    assert!(<World as GetResource<Expired<Attack>>>::get(&w).contains(b));
+    assert!(w.component::<Attack>().get(b).unwrap().0.ready());
+
+    // Timer systems that weren't there for the earlier frames don't count them.
+    let remaining = |w: &World| w.component::<Burning>().get(b).unwrap().0.remaining();
+    let mut late = TickTimers::<Burning>::new();
+    w.run_system(&mut late);
+    assert_eq!(remaining(&w), Span::Time(ms(30)));
+    <World as GetResource<FrameInfo>>::get_mut(&w).advance(ms(20));
+    w.run_system(&mut late);
+    assert_eq!(remaining(&w), Span::Time(ms(10)));
+    let mut later = Dispatcher::new().with(&mut w, TickTimers::<Burning>::new());
+    later.run_frame(&mut w, ms(5)).unwrap();
+    assert_eq!(remaining(&w), Span::Time(ms(5)));
+}
+
+#[test]
+fn test_lifetimes() {
+    #[allow(dead_code)]
+    mod lifetime_world {
+        use crate::*;
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Particle(pub u8);
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    lifetimes: BasicVecStorage<Lifetime>,
+                    particles: BasicVecStorage<Particle>,
+                }
+                resources {}
+            }
+        );
+    }
+    use lifetime_world::*;
+
+    let mut w = World::default();
+    let short = w.new_entity().with(Lifetime(1)).with(Particle(1)).build();
+    let long = w.new_entity().with(Lifetime(3)).build();
+    let doomed = w.new_entity().with(Lifetime(0)).build();
+    let forever = w.new_entity().with(Particle(2)).build();
+
+    let mut dispatcher = Dispatcher::new().with(&mut w, DespawnExpired::<Lifetime>::new());
+    dispatcher.run(&mut w).unwrap();
+    assert!(!w.is_alive(short));
+    assert!(!w.is_alive(doomed));
+    assert!(<World as GetComponent<'_, Particle>>::get(&w)
+        .get(short)
+        .is_none());
+    assert_eq!(*w.component::<Lifetime>().get(long).unwrap(), Lifetime(2));
+
+    dispatcher.run(&mut w).unwrap();
+    assert!(w.is_alive(long));
+    dispatcher.run(&mut w).unwrap();
+    assert!(!w.is_alive(long));
+    assert!(w.is_alive(forever));
+    assert!(w.validate().is_ok());
+}
+
+#[test]
+fn test_field_initializers() {
+    #[allow(dead_code)]
+    mod initialized_world {
+        use crate::*;
+        use alloc::string::String;
+
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct Tag(pub u8);
+
+        // Deliberately has no `Default`.
+        #[derive(Clone, Debug, PartialEq)]
+        pub struct Seed(pub u64);
+
+        define_world!(
+            /// The world's attributes, apart from a derived `Default`, apply to `Resources` too.
+            #[derive(Clone, core::default::Default, Debug)]
+            pub world {
+                components {
+                    tags: BasicVecStorage<Tag> = BasicVecStorage::default(),
+                }
+                resources {
+                    seed: Seed = Seed(42),
+                    name: String = String::from("overworld"),
+                    turn: u64,
+                }
+            }
+        );
+    }
+    use initialized_world::*;
+
+    let w = World::default();
+    assert_eq!(*<World as GetResource<Seed>>::get(&w), Seed(42));
+    assert_eq!(&*<World as GetResource<String>>::get(&w), "overworld");
+    assert_eq!(*<World as GetResource<u64>>::get(&w), 0);
+    assert!(w.validate().is_ok());
+
+    let copy = w.clone();
+    <World as GetResource<Seed>>::set(&w, Seed(1));
+    assert_eq!(*<World as GetResource<Seed>>::get(&copy), Seed(42));
+    assert!(format!("{:?}", copy).contains("Seed(42)"));
+    fn derived<T: Clone + core::fmt::Debug + Default>() {}
+    derived::<Resources>();
+}
+
+#[cfg(feature = "resource-accessors")]
+#[test]
+fn test_resource_accessors() {
+    #[allow(dead_code)]
+    mod accessor_world {
+        use crate::*;
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Unused;
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    unused: VoidStorage<Unused>,
+                }
+                resources {
+                    gold: u32,
+                    turn: u64 = 1,
+                }
+            }
+        );
+    }
+    use accessor_world::*;
+
+    let w = World::default();
+    assert_eq!(*w.resource_turn(), 1);
+    *w.resource_gold_mut() += 5;
+    *w.resource_turn_mut() += 1;
+    assert_eq!(*w.resource_gold(), 5);
+    assert_eq!(*<World as GetResource<u64>>::get(&w), 2);
+    // Borrowing mutably counts as a change, as it does through `GetResource::get_mut`.
+    assert!(<World as GetResource<u32>>::change_tick(&w) > 0);
+}
+
+#[test]
+fn test_debug_names() {
+    #[allow(dead_code)]
+    mod named_world {
+        use crate::*;
+
+        #[derive(Debug, Default)]
+        pub struct Position(pub i32, pub i32);
+        #[derive(Debug, Default)]
+        pub struct Velocity(pub i32, pub i32);
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    positions: BasicVecStorage<Position>,
+                    velocities: BasicVecStorage<Velocity>,
+                }
+                resources {}
+                constraints {
+                    velocities requires positions;
+                }
+            }
+        );
+    }
+    use named_world::*;
+
+    let mut w = World::default();
+    let boss = w
+        .new_entity()
+        .named("boss_dragon")
+        .with(Position(1, 2))
+        .build();
+    let minion = w.new_entity().with(Position(0, 0)).build();
+    w.debug_names_mut().set(minion, "minion");
+    let err = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        w.new_entity().named("ghost").with(Velocity(1, 0)).build();
+    }))
+    .unwrap_err();
+    let message = err.downcast_ref::<String>().unwrap();
+
+    if cfg!(feature = "debug-names") {
+        assert_eq!(w.debug_names().get(boss), Some("boss_dragon"));
+        assert_eq!(
+            w.debug_names().describe(boss).to_string(),
+            "`boss_dragon` (entity 0, generation 1)"
+        );
+        assert!(message.ends_with("(building `ghost`)"), "{}", message);
+        assert!(w
+            .snapshot()
+            .starts_with("entity 0 (generation 1) `boss_dragon`\n"));
+    } else {
+        assert_eq!(w.debug_names().get(boss), None);
+        assert_eq!(
+            w.debug_names().describe(boss).to_string(),
+            "entity 0 (generation 1)"
+        );
+        assert!(!message.contains("ghost"));
+        assert_eq!(core::mem::size_of::<DebugNames>(), 0);
+    }
+
+    // Names don't outlive their entities.
+    w.delete_entity(boss);
+    let reused = w.new_entity().with(Position(3, 3)).build();
+    assert_eq!(reused.id(), boss.id());
+    assert_eq!(w.debug_names().get(reused), None);
+    assert_eq!(w.debug_names().get(boss), None);
+    w.clear();
+    assert_eq!(w.debug_names().iter().count(), 0);
+}
+
+#[test]
+fn test_debug_origins() {
+    #[allow(dead_code)]
+    mod origin_world {
+        use crate::*;
+
+        #[derive(Clone, Debug, Default)]
+        pub struct Health(pub u32);
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    health: BasicVecStorage<Health>,
+                }
+                resources {
+                    frame: FrameInfo,
+                }
+            }
+        );
+    }
+    use origin_world::*;
+
+    let mut w = World::default();
+    let early = w.new_entity().with(Health(1)).build();
+    let early_line = line!() - 1;
+    let mut dispatcher = Dispatcher::new();
+    dispatcher.run_frame(&mut w, Default::default()).unwrap();
+    dispatcher.run_frame(&mut w, Default::default()).unwrap();
+    let template = ComponentSet {
+        health: Some(Health(2)),
+    };
+    let batch = w.spawn_batch(&template, 2, |_, _| {});
+    let batch_line = line!() - 1;
+
+    if cfg!(feature = "debug-origins") {
+        let origin = w.debug_origins().get(early).unwrap();
+        assert_eq!(origin.location().file(), file!());
+        assert_eq!(origin.location().line(), early_line);
+        assert_eq!(origin.tick(), 0);
+        for &e in batch.iter() {
+            let origin = w.debug_origins().get(e).unwrap();
+            assert_eq!(origin.location().line(), batch_line);
+            assert_eq!(origin.tick(), 2);
+        }
+        let description = w.debug_entity(early).unwrap().to_string();
+        assert!(description.starts_with("entity 0 (generation 1), spawned at "));
+        assert!(description.contains(&format!("{}:{}:", file!(), early_line)));
+        assert!(description.ends_with(" in frame 0"));
+    } else {
+        assert_eq!(w.debug_origins().get(early), None);
+        assert_eq!(
+            w.debug_entity(early).unwrap().to_string(),
+            "entity 0 (generation 1)"
+        );
+    }
+
+    w.delete_entity(early);
+    assert!(w.debug_entity(early).is_none());
+    assert_eq!(w.debug_origins().get(early), None);
+}
+
+#[test]
+fn test_disabled_entities() {
+    #[allow(dead_code)]
+    mod stasis_world {
+        use crate::*;
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Position(pub i32);
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Velocity(pub i32);
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Fuse(pub Lifetime);
+        impl AsMut<Lifetime> for Fuse {
+            fn as_mut(&mut self) -> &mut Lifetime {
+                &mut self.0
+            }
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    positions: BasicVecStorage<Position>,
+                    velocities: BasicVecStorage<Velocity>,
+                    fuses: BasicVecStorage<Fuse>,
+                }
+                resources {}
+            }
+        );
+    }
+    use stasis_world::*;
+
+    struct Move;
+    impl<'a> System<'a> for Move {
+        type Dependencies = (ReadComponent<'a, Velocity>, WriteComponent<'a, Position>);
+        fn run(&'a mut self, (velocities, mut positions): Self::Dependencies) {
+            (&velocities, &mut positions).for_each(|_, (v, p)| p.0 += v.0);
+        }
+    }
+
+    let mut w = World::default();
+    let a = w.new_entity().with(Position(0)).with(Velocity(1)).build();
+    let b = w
+        .new_entity()
+        .with(Position(0))
+        .with(Velocity(1))
+        .with(Fuse(Lifetime(1)))
+        .build();
+    w.disable(b);
+    assert!(w.is_disabled(b));
+    let mut dispatcher = Dispatcher::new()
+        .with(&mut w, Move)
+        .with(&mut w, DespawnExpired::<Fuse>::new());
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(w.component::<Position>().get(a), Some(&Position(1)));
+    // Frozen in place, and not aged either.
+    assert_eq!(w.component::<Position>().get(b), Some(&Position(0)));
+    assert!(w.is_alive(b));
+
+    {
+        let (velocities, positions) = w
+            .fetch::<(ReadComponent<Velocity>, ReadComponent<Position>)>()
+            .unwrap();
+        assert_eq!((&velocities, &positions).iter().count(), 1);
+        assert_eq!((&positions,).including_disabled().iter().count(), 2);
+        assert_eq!((&positions,).single().map(|(e, _)| e), Some(a));
+        let mut listed = Vec::new();
+        (&positions,).for_each_in(&[b, a], |e, _| listed.push(e));
+        assert_eq!(listed, vec![a]);
+        listed.clear();
+        (&positions,)
+            .including_disabled()
+            .for_each_in(&[b, a], |e, _| listed.push(e));
+        assert_eq!(listed, vec![b, a]);
+    }
+
+    w.enable(b);
+    dispatcher.run(&mut w).unwrap();
+    assert!(!w.is_alive(b));
+
+    // Deleting a disabled entity forgets it; its slot's next occupant starts out enabled.
+    let c = w.new_entity().with(Position(5)).build();
+    w.disable(c);
+    w.delete_entity(c);
+    let d = w.new_entity().with(Position(6)).build();
+    assert_eq!(d.id(), c.id());
+    assert!(!w.is_disabled(d));
+    assert!(w.disabled().is_empty());
+    // Dead entities can't be disabled.
+    w.disable(c);
+    assert!(w.disabled().is_empty());
+}
+
+#[test]
+fn test_layer_masks() {
+    #[allow(dead_code)]
+    mod layer_world {
+        use crate::*;
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Position(pub i32);
+        #[derive(Clone, Copy, Debug, Default, PartialEq)]
+        pub struct Collides(pub Layers);
+        impl AsRef<Layers> for Collides {
+            fn as_ref(&self) -> &Layers {
+                &self.0
+            }
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    positions: BasicVecStorage<Position>,
+                    collision: LayerStorage<Collides>,
+                }
+                resources {}
+            }
+        );
+    }
+    use layer_world::*;
+
+    const WALLS: Layers = Layers::layer(0);
+    const PLAYER: Layers = Layers::layer(1);
+    const ENEMIES: Layers = Layers::layer(31);
+
+    assert_eq!(PLAYER | ENEMIES, Layers::NONE.with(1).with(31));
+    assert!((PLAYER | ENEMIES).contains(ENEMIES));
+    assert!(!PLAYER.intersects(ENEMIES));
+    assert_eq!((WALLS | ENEMIES).iter().collect::<Vec<_>>(), vec![0, 31]);
+    let mut mask = Layers::ALL;
+    mask.remove(WALLS);
+    assert_eq!(mask & (WALLS | PLAYER), PLAYER);
+
+    let mut w = World::default();
+    let wall = w
+        .new_entity()
+        .with(Position(0))
+        .with(Collides(WALLS))
+        .build();
+    let player = w
+        .new_entity()
+        .with(Position(1))
+        .with(Collides(PLAYER))
+        .build();
+    let ghost = w.new_entity().with(Position(2)).build();
+    let mut last = ghost;
+    for i in 0..40 {
+        last = w
+            .new_entity()
+            .with(Position(3 + i))
+            .with(Collides(ENEMIES | WALLS))
+            .build();
+    }
+    w.disable(player);
+
+    {
+        let (positions, collision) = w
+            .fetch::<(ReadComponent<Position>, ReadComponent<Collides>)>()
+            .unwrap();
+        assert_eq!(collision.layers(player), PLAYER);
+        assert_eq!(collision.layers(ghost), Layers::NONE);
+
+        // As the filter or as the view that drives the join, in either position.
+        let walls: Vec<_> = (&positions, collision.in_layers(WALLS))
+            .iter()
+            .map(|(e, _)| e)
+            .collect();
+        assert_eq!(walls.len(), 41);
+        assert_eq!((walls[0], walls[40]), (wall, last));
+        assert_eq!(
+            (collision.in_layers(ENEMIES), &positions).iter().count(),
+            40
+        );
+        // The filter leaves disabled entities to the views from the world.
+        assert_eq!((&positions, collision.in_layers(PLAYER)).iter().count(), 0);
+        assert_eq!(
+            (&positions, collision.in_layers(PLAYER))
+                .including_disabled()
+                .single()
+                .map(|(e, _)| e),
+            Some(player)
+        );
+        assert_eq!(
+            (&collision, collision.in_layers(Layers::NONE))
+                .iter()
+                .count(),
+            0
+        );
+    }
+
+    // Changing an entity's layers moves it between filters.
+    w.enable(player);
+    w.component_mut::<Collides>().get_mut(player).unwrap().0 = ENEMIES;
+    w.delete_entity(wall);
+    let (positions, collision) = w
+        .fetch::<(ReadComponent<Position>, ReadComponent<Collides>)>()
+        .unwrap();
+    assert_eq!((&positions, collision.in_layers(PLAYER)).iter().count(), 0);
+    assert_eq!(
+        (&positions, collision.in_layers(ENEMIES)).iter().count(),
+        41
+    );
+    assert_eq!((&positions, collision.in_layers(WALLS)).iter().count(), 40);
+}
+
+#[test]
+fn test_sorted_index() {
+    #[allow(dead_code)]
+    mod sorted_world {
+        use crate::*;
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Depth(pub i32);
+        impl SortKey for Depth {
+            type Key = i32;
+            fn sort_key(&self) -> i32 {
+                self.0
+            }
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    depths: BasicVecStorage<Depth>,
+                }
+                resources {
+                    order: SortedIndex<Depth>,
+                }
+            }
+        );
+    }
+    use sorted_world::*;
+
+    let mut w = World::default();
+    let a = w.new_entity().with(Depth(3)).build();
+    let b = w.new_entity().with(Depth(1)).build();
+    let c = w.new_entity().with(Depth(3)).build();
+    let d = w.new_entity().with(Depth(2)).build();
+    let mut dispatcher = Dispatcher::new().with(&mut w, UpdateSortedIndex::<Depth>::new());
+    dispatcher.run(&mut w).unwrap();
+    // Ties are broken by id.
+    {
+        let order = <World as GetResource<SortedIndex<Depth>>>::get(&w);
+        assert_eq!(order.iter().collect::<Vec<_>>(), vec![b, d, a, c]);
+        assert_eq!(order.iter().next_back(), Some(c));
+        assert_eq!(order.key(d), Some(&2));
+        assert_eq!(order.len(), 4);
+    }
+
+    // Changed keys move, removed components and deleted entities drop out, and a new entity in a
+    // reused slot is filed under its own key.
+    w.component_mut::<Depth>().get_mut(a).unwrap().0 = 0;
+    w.entity_mut(b).remove::<Depth>();
+    w.delete_entity(c);
+    let e = w.new_entity().with(Depth(5)).build();
+    assert_eq!(e.id(), c.id());
+    dispatcher.run(&mut w).unwrap();
+    {
+        let order = <World as GetResource<SortedIndex<Depth>>>::get(&w);
+        assert_eq!(order.iter().collect::<Vec<_>>(), vec![a, d, e]);
+        assert!(!order.contains(b));
+        assert!(!order.contains(c));
+        assert_eq!(order.key(e), Some(&5));
+    }
+
+    // The index can also be kept up to date by hand.
+    let mut manual = SortedIndex::<Depth>::new();
+    manual.insert(d, 7);
+    manual.insert(a, 9);
+    manual.insert(d, 10);
+    assert_eq!(manual.iter().collect::<Vec<_>>(), vec![a, d]);
+    manual.remove(a);
+    manual.remove(c);
+    assert_eq!(manual.iter().collect::<Vec<_>>(), vec![d]);
+    manual.update(&*w.component::<Depth>());
+    assert_eq!(manual.iter().collect::<Vec<_>>(), vec![a, d, e]);
+    manual.clear();
+    assert!(manual.is_empty());
+}
+
+#[test]
+fn test_cached_values() {
+    #[allow(dead_code)]
+    mod cached_world {
+        use crate::*;
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Cost(pub u32);
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Bystander;
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Multiplier(pub u32);
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Unrelated(pub u32);
+
+        pub struct TotalCost;
+        impl Derive<World> for TotalCost {
+            type Output = u32;
+            type Inputs = (ComponentInput<Cost>, ResourceInput<Multiplier>);
+            fn derive(w: &World) -> u32 {
+                let costs = w.component::<Cost>();
+                let total: u32 = costs.iter().flatten().map(|c| c.0).sum();
+                total * <World as GetResource<Multiplier>>::get(w).0
+            }
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    costs: BasicVecStorage<Cost>,
+                    bystanders: VoidStorage<Bystander>,
+                }
+                resources {
+                    multiplier: Multiplier = Multiplier(1),
+                    unrelated: Unrelated,
+                    total: Cached<u32, TotalCost>,
+                }
+            }
+        );
+    }
+    use cached_world::*;
+    fn total(w: &World) -> cell::Ref<'_, Cached<u32, TotalCost>> {
+        <World as GetResource<Cached<u32, TotalCost>>>::get(w)
+    }
+    fn total_mut(w: &World) -> cell::RefMut<'_, Cached<u32, TotalCost>> {
+        <World as GetResource<Cached<u32, TotalCost>>>::get_mut(w)
+    }
+
+    let mut w = World::default();
+    assert_eq!(<World as GetComponent<Cost>>::change_tick(&w), 0);
+    assert_eq!(total(&w).last(), None);
+    assert_eq!(*total_mut(&w).get(&w), 0);
+    let a = w.new_entity().with(Cost(2)).build();
+    let b = w.new_entity().with(Cost(3)).build();
+    assert_ne!(<World as GetComponent<Cost>>::change_tick(&w), 0);
+    assert_eq!(*total_mut(&w).get(&w), 5);
+    assert_eq!(total(&w).computed(), 2);
+
+    // Reading the inputs, or changing anything else, doesn't make the value stale.
+    let _ = w.fetch::<(ReadComponent<Cost>, ReadResource<Multiplier>)>();
+    w.set(Unrelated(7));
+    w.new_entity().with(Bystander).build();
+    assert_eq!(*total_mut(&w).get(&w), 5);
+    assert_eq!(total(&w).computed(), 2);
+
+    // Every kind of change to an input does.
+    let expect = |w: &mut World, expected: u32| {
+        let computed = total(w).computed();
+        assert_eq!(*total_mut(w).get(w), expected);
+        assert_eq!(total(w).computed(), computed + 1);
+    };
+    w.set(Multiplier(2));
+    expect(&mut w, 10);
+    w.component_mut::<Cost>().get_mut(a).unwrap().0 = 4;
+    expect(&mut w, 14);
+    {
+        let (mut costs,) = w.fetch::<(WriteComponent<Cost>,)>().unwrap();
+        costs.get_mut(b).unwrap().0 = 1;
+    }
+    expect(&mut w, 10);
+    w.entity_mut(b).remove::<Cost>();
+    expect(&mut w, 8);
+    w.entity_mut(b).insert(Cost(6));
+    expect(&mut w, 20);
+    w.delete_entity(a);
+    expect(&mut w, 12);
+    w.take_entity(b);
+    expect(&mut w, 0);
+    w.new_entity().with(Cost(1)).build();
+    expect(&mut w, 2);
+    w.clear();
+    expect(&mut w, 0);
+
+    total_mut(&w).invalidate();
+    expect(&mut w, 0);
+
+    // Hand-written providers that don't track changes don't have to implement `change_tick`.
+    struct Costs(cell::WorldCell<BasicVecStorage<Cost>>);
+    impl<'a> GetComponent<'a, Cost> for Costs {
+        fn get(&self) -> cell::Ref<'_, BasicVecStorage<Cost>> {
+            self.0.borrow()
+        }
+        fn get_mut(&self) -> cell::RefMut<'_, BasicVecStorage<Cost>> {
+            self.0.borrow_mut()
+        }
+        fn insert(&mut self, entity: Entity, value: Cost) -> Option<Cost> {
+            let storage = self.0.get_mut();
+            let old = storage.take(entity);
+            storage.set(entity, Some(value));
+            old
+        }
+        fn remove(&mut self, entity: Entity) -> Option<Cost> {
+            self.0.get_mut().take(entity)
+        }
+    }
+    let costs = Costs(Default::default());
+    costs.get_mut();
+    assert_eq!(
+        <ComponentInput<Cost> as ChangeInputs<Costs>>::last_changed(&costs),
+        0
+    );
+}
+
+#[test]
+fn test_computed_components() {
+    #[allow(dead_code)]
+    mod computed_world {
+        use crate::*;
+        use std::vec::Vec;
+
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Tiles(pub Vec<(i32, i32)>);
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Bounds(pub (i32, i32), pub (i32, i32));
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Hp(pub u32);
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Bonus(pub u32);
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Effective(pub u32);
+
+        impl Computed<World> for Bounds {
+            type Source = Tiles;
+            type Inputs = ();
+            fn compute(w: &World, e: Entity) -> Option<Self> {
+                let tiles = w.component::<Tiles>();
+                let tiles = &tiles.get(e)?.0;
+                let min = tiles
+                    .iter()
+                    .copied()
+                    .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1)))?;
+                let max = tiles
+                    .iter()
+                    .copied()
+                    .reduce(|a, b| (a.0.max(b.0), a.1.max(b.1)))?;
+                Some(Bounds(min, max))
+            }
+        }
+
+        impl Computed<World> for Effective {
+            type Source = Hp;
+            type Inputs = (ResourceInput<Bonus>,);
+            fn compute(w: &World, e: Entity) -> Option<Self> {
+                let hp = w.component::<Hp>().get(e)?.0;
+                Some(Effective(hp + <World as GetResource<Bonus>>::get(w).0))
+            }
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    tiles: BasicVecStorage<Tiles>,
+                    bounds: BasicVecStorage<Bounds>,
+                    hp: BasicVecStorage<Hp>,
+                    effective: BasicVecStorage<Effective>,
+                }
+                resources {
+                    bonus: Bonus,
+                }
+            }
+        );
+    }
+    use computed_world::*;
+
+    let mut w = World::default();
+    let big = w
+        .new_entity()
+        .with(Tiles(vec![(0, 0), (2, 1), (1, 3)]))
+        .with(Hp(10))
+        .build();
+    let empty = w.new_entity().with(Tiles(vec![])).build();
+    let mut bounds = UpdateComputed::<Bounds>::new();
+    let mut effective = UpdateComputed::<Effective>::new();
+    let mut update = |w: &mut World| {
+        bounds.run_on(w, ());
+        effective.run_on(w, ());
+        (bounds.passes(), effective.passes())
+    };
+
+    assert_eq!(update(&mut w), (1, 1));
+    assert_eq!(
+        w.component::<Bounds>().get(big),
+        Some(&Bounds((0, 0), (2, 3)))
+    );
+    // `compute` returned `None`.
+    assert_eq!(w.component::<Bounds>().get(empty), None);
+    assert_eq!(w.component::<Effective>().get(big), Some(&Effective(10)));
+    assert!(w.entity(big).has::<Bounds>());
+
+    // Nothing changed, so nothing is recomputed.
+    assert_eq!(update(&mut w), (1, 1));
+
+    // Only the components whose inputs changed are.
+    w.set(Bonus(5));
+    assert_eq!(update(&mut w), (1, 2));
+    assert_eq!(w.component::<Effective>().get(big), Some(&Effective(15)));
+    w.component_mut::<Tiles>()
+        .get_mut(empty)
+        .unwrap()
+        .0
+        .push((4, 4));
+    assert_eq!(update(&mut w), (2, 2));
+    assert_eq!(
+        w.component::<Bounds>().get(empty),
+        Some(&Bounds((4, 4), (4, 4)))
+    );
+
+    // Losing the source loses the computed component.
+    w.entity_mut(big).remove::<Tiles>();
+    assert_eq!(update(&mut w), (3, 2));
+    assert!(!w.entity(big).has::<Bounds>());
+    assert!(w.entity(empty).has::<Bounds>());
+
+    effective.invalidate();
+    assert_eq!(effective.passes(), 2);
+    effective.run_on(&mut w, ());
+    assert_eq!(effective.passes(), 3);
+
+    // Systems that could write to an input but don't leave everything alone.
+    struct Heal(Option<Entity>);
+    impl<'a> System<'a> for Heal {
+        type Dependencies = (WriteComponent<'a, Hp>, WriteComponent<'a, Tiles>);
+        fn run(&'a mut self, (mut hp, tiles): Self::Dependencies) {
+            if let Some(hp) = self.0.and_then(|e| hp.get_mut(e)) {
+                hp.0 += 1;
+            }
+            assert_eq!(tiles.components().count(), 1);
+        }
+    }
+    let mut update = |w: &mut World| {
+        bounds.run_on(w, ());
+        effective.run_on(w, ());
+        (bounds.passes(), effective.passes())
+    };
+    w.run_system(&mut Heal(None));
+    assert_eq!(update(&mut w), (3, 3));
+    w.run_system(&mut Heal(Some(big)));
+    assert_eq!(update(&mut w), (3, 4));
+    assert_eq!(w.component::<Effective>().get(big), Some(&Effective(16)));
+}
+
+#[cfg(feature = "frame-arena")]
+#[test]
+fn test_frame_arena() {
+    use core::fmt::Write;
+
+    #[allow(dead_code)]
+    mod arena_world {
+        use crate::*;
+        use std::string::String;
+
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct Score(pub u32);
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct Best(pub Option<Entity>, pub String);
+
+        define_world!(
+            #[derive(Clone, Default)]
+            pub world {
+                components {
+                    scores: BasicVecStorage<Score>,
+                }
+                resources {
+                    arena: FrameArena = FrameArena::with_capacity(1024),
+                    best: Best,
+                }
+            }
+        );
+    }
+    use arena_world::*;
+
+    struct PickBest;
+    impl<'a> System<'a> for PickBest {
+        type Dependencies = (
+            ReadComponent<'a, Score>,
+            ReadResource<'a, FrameArena>,
+            WriteResource<'a, Best>,
+        );
+        fn run(&'a mut self, (scores, arena, mut best): Self::Dependencies) {
+            let mut ranked = arena.vec_with_capacity(scores.size());
+            (&scores,).for_each(|e, (s,)| ranked.push((s.0, e)));
+            ranked.sort_by_key(|&(s, e)| (core::cmp::Reverse(s), e.id()));
+            let mut label = arena.string();
+            write!(label, "{} candidates", ranked.len()).unwrap();
+            let counter = arena.alloc(0u32);
+            *counter += 1;
+            *best = Best(
+                ranked.first().map(|&(_, e)| e),
+                String::from(label.as_str()),
+            );
+            assert_eq!(&*arena.alloc_str("scratch"), "scratch");
+        }
+    }
+
+    let mut w = World::default();
+    w.new_entity().with(Score(3)).build();
+    let top = w.new_entity().with(Score(9)).build();
+    for i in 0..100 {
+        w.new_entity().with(Score(i % 5)).build();
+    }
+    let mut dispatcher = Dispatcher::new()
+        .with(&mut w, ResetFrameArena)
+        .with(&mut w, PickBest);
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(
+        *<World as GetResource<Best>>::get(&w),
+        Best(Some(top), String::from("102 candidates"))
+    );
+
+    // Resetting every frame keeps the arena from growing.
+    let arena = |w: &World| <World as GetResource<FrameArena>>::get(w).allocated_bytes();
+    let used = arena(&w);
+    assert!(used >= 1024);
+    for _ in 0..10 {
+        dispatcher.run(&mut w).unwrap();
+    }
+    assert_eq!(arena(&w), used);
+    let debug = format!("{:?}", *<World as GetResource<FrameArena>>::get(&w));
+    assert!(debug.starts_with("FrameArena"));
+
+    let copy = w.clone();
+    assert!(arena(&copy) < used);
+    <World as GetResource<FrameArena>>::get_mut(&w).reset();
+}
+
+#[test]
+fn test_dense_storage_chunks() {
+    #[allow(dead_code)]
+    mod dense_world {
+        use crate::*;
+
+        #[derive(Clone, Copy, Debug, Default, PartialEq)]
+        pub struct Light(pub f32);
+        #[derive(Debug, Default, PartialEq)]
+        pub struct Torch;
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    lights: DenseVecStorage<Light>,
+                    torches: VoidStorage<Torch>,
+                }
+                resources {}
+            }
+        );
+    }
+    use dense_world::*;
+
+    let mut w = World::default();
+    let entities: Vec<_> = (0..20)
+        .map(|i| w.new_entity().with(Light(i as f32)).build())
+        .collect();
+    // Deleting and respawning in the middle keeps the components in id order.
+    w.delete_entity(entities[3]);
+    w.delete_entity(entities[10]);
+    let respawned = w.new_entity().with(Light(100.0)).with(Torch).build();
+    assert_eq!(respawned.id(), entities[10].id());
+    w.entity_mut(entities[0]).remove::<Light>();
+
+    {
+        let (lights,) = w.fetch::<(ReadComponent<Light>,)>().unwrap();
+        let ids: Vec<_> = lights.entities().iter().map(|e| e.id()).collect();
+        assert_eq!(ids, (1..20).filter(|&i| i != 3).collect::<Vec<_>>());
+        assert_eq!(lights.as_slice().len(), 18);
+        assert_eq!(lights.get(respawned), Some(&Light(100.0)));
+        assert_eq!(lights.get(entities[10]), None);
+        assert_eq!(
+            lights
+                .iter_chunks(8)
+                .map(|(e, _)| e.len())
+                .collect::<Vec<_>>(),
+            vec![8, 8, 2]
+        );
+    }
+
+    // Batch processing sees the same components, in the same order, as a join.
+    {
+        let (mut lights,) = w.fetch::<(WriteComponent<Light>,)>().unwrap();
+        for (es, ls) in lights.iter_chunks_mut(4) {
+            assert_eq!(es.len(), ls.len());
+            for l in ls.iter_mut() {
+                l.0 *= 0.5;
+            }
+        }
+    }
+    let (lights, torches) = w
+        .fetch::<(ReadComponent<Light>, ReadComponent<Torch>)>()
+        .unwrap();
+    let joined: Vec<_> = (&lights,).iter().map(|(e, (l,))| (e, *l)).collect();
+    let chunked: Vec<_> = lights
+        .iter_chunks(5)
+        .flat_map(|(es, ls)| es.iter().copied().zip(ls.iter().copied()))
+        .collect();
+    assert_eq!(joined, chunked);
+    assert_eq!(joined[0], (entities[1], Light(0.5)));
+    assert_eq!(
+        (&lights, &torches).single().map(|(e, (l, _))| (e, *l)),
+        Some((respawned, Light(50.0)))
+    );
+}
+
+#[test]
+fn test_dense_storage_slices() {
+    let mut storage = DenseVecStorage::<[f32; 2]>::default();
+    assert!(storage.is_empty());
+    for id in [7, 2, 5] {
+        storage.set(Entity::new(id), Some([id as f32, 0.0]));
+    }
+    assert_eq!(storage.len(), 3);
+
+    // Fill the components from an "uploaded" buffer, keyed by the entity list.
+    let buffer: Vec<[f32; 2]> = storage
+        .as_slice()
+        .iter()
+        .map(|p| [p[0], p[0] * 2.0])
+        .collect();
+    let (entities, positions) = storage.as_mut_slices();
+    assert_eq!(entities, &[Entity::new(2), Entity::new(5), Entity::new(7)]);
+    positions.copy_from_slice(&buffer);
+    assert_eq!(storage.get(Entity::new(5)), Some(&[5.0, 10.0]));
+
+    storage.as_mut_slice()[0][1] = -1.0;
+    let (entities, positions) = storage.as_slices();
+    assert_eq!(entities.len(), positions.len());
+    assert_eq!(storage.get(Entity::new(2)), Some(&[2.0, -1.0]));
+}
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn test_dense_storage_bytes() {
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    #[repr(C)]
+    struct Instance {
+        position: [f32; 2],
+        tint: u32,
+    }
+    unsafe impl bytemuck::Zeroable for Instance {}
+    unsafe impl bytemuck::Pod for Instance {}
+
+    let mut storage = DenseVecStorage::<Instance>::default();
+    assert!(storage.as_bytes().is_empty());
+    for id in [3, 1] {
+        let instance = Instance {
+            position: [id as f32, 0.0],
+            tint: id,
+        };
+        storage.set(Entity::new(id), Some(instance));
+    }
+    let bytes = storage.as_bytes();
+    assert_eq!(bytes.len(), 2 * core::mem::size_of::<Instance>());
+    let instances: &[Instance] = bytemuck::cast_slice(bytes);
+    assert_eq!(instances[0].tint, 1);
+    assert_eq!(instances[1].tint, 3);
+
+    // Write the second instance's tint through the bytes.
+    let tint = core::mem::size_of::<Instance>() + 8;
+    storage.as_bytes_mut()[tint..tint + 4].copy_from_slice(&7u32.to_ne_bytes());
+    assert_eq!(storage.get(Entity::new(3)).unwrap().tint, 7);
+}
+
+#[cfg(feature = "access-stats")]
+#[test]
+fn test_access_stats() {
+    #[allow(dead_code)]
+    mod stats_world {
+        use crate::*;
+
+        #[derive(Debug, Default)]
+        pub struct Body(pub i32);
+        #[derive(Debug, Default)]
+        pub struct Mind(pub i32);
+        #[derive(Debug, Default)]
+        pub struct Clock(pub u32);
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    bodies: BasicVecStorage<Body>,
+                    minds: BasicVecStorage<Mind>,
+                }
+                resources {
+                    clock: Clock,
+                }
+            }
+        );
+    }
+    use stats_world::*;
+
+    struct Tick;
+    impl<'a> System<'a> for Tick {
+        type Dependencies = (WriteResource<'a, Clock>,);
+        fn run(&'a mut self, (mut clock,): Self::Dependencies) {
+            clock.0 += 1;
+        }
+    }
+
+    struct Heal;
+    impl<'a> System<'a> for Heal {
+        type Dependencies = (
+            ReadResource<'a, Clock>,
+            RestrictedWriteComponent<'a, Body>,
+            Group<(ReadComponent<'a, Mind>,)>,
+        );
+        fn run(&'a mut self, _: Self::Dependencies) {}
+    }
+
+    struct Think;
+    impl<'a> System<'a> for Think {
+        type Dependencies = (ReadComponent<'a, Body>, WriteComponent<'a, Mind>);
+        fn run(&'a mut self, _: Self::Dependencies) {}
+    }
+
+    let mut w = World::default();
+    let mut dispatcher = Dispatcher::new()
+        .with(&mut w, Tick)
+        .with(&mut w, Heal)
+        .with(&mut w, Think);
+    dispatcher.run(&mut w).unwrap();
+    dispatcher.run(&mut w).unwrap();
+    // Fetching outside a system isn't counted.
+    w.fetch::<(WriteComponent<Body>,)>().unwrap();
+
+    let body = AccessedType::component::<Body>();
+    let mind = AccessedType::component::<Mind>();
+    let clock = AccessedType::resource::<Clock>();
+    let matrix = w.access_stats().matrix();
+    assert_eq!(matrix.systems().len(), 3);
+    assert!(matrix.systems()[1].ends_with("Heal"));
+    assert_eq!(matrix.runs(1), 3);
+    assert_eq!(matrix.columns(), &[body, mind, clock]);
+    let writes = AccessCount {
+        reads: 0,
+        writes: 3,
+        // Not during `setup`.
+        mutations: 2,
+    };
+    assert_eq!(matrix.count::<Tick>(clock), writes);
+    assert_eq!(matrix.count::<Heal>(clock).reads, 3);
+    assert_eq!(matrix.count::<Heal>(body).writes, 3);
+    assert_eq!(matrix.count::<Heal>(mind).reads, 3);
+    assert_eq!(matrix.count::<Think>(clock).total(), 0);
+    // `Think` never borrows `Mind` mutably.
+    assert_eq!(
+        matrix.get(2, 1),
+        AccessCount {
+            mutations: 0,
+            ..writes
+        }
+    );
+    assert_eq!(matrix.contention(), vec![(body, 1), (mind, 1), (clock, 1)]);
+    let report = format!("{}", matrix);
+    assert!(report.contains("Heal (3 runs)"));
+    assert!(report.contains("    resource `"));
+    assert!(report.contains("Clock`: 3 writes"));
+
+    w.access_stats().clear();
+    assert_eq!(w.access_stats().matrix(), AccessMatrix::default());
+}
+
+#[test]
+fn test_schedule_to_dot() {
+    struct Label;
+    impl<'a> System<'a> for Label {
+        type Dependencies = (ReadComponent<'a, Data>, WriteResource<'a, String>);
+        fn run(&'a mut self, _: Self::Dependencies) {}
+    }
+
+    struct Grow;
+    impl<'a> System<'a> for Grow {
+        type Dependencies = (RestrictedWriteComponent<'a, Data>, ReadComponent<'a, Void>);
+        fn run(&'a mut self, _: Self::Dependencies) {}
+    }
+
+    struct Shrink;
+    impl<'a> System<'a> for Shrink {
+        type Dependencies = (
+            RestrictedWriteComponent<'a, Data>,
+            ReadComponent<'a, MoreData>,
+        );
+        fn run(&'a mut self, _: Self::Dependencies) {}
+    }
+
+    define_schedule!(
+        schedule Resize {
+            label: Label,
+            grow: Grow,
+            shrink: Shrink,
+        }
+        disjoint {
+            grow, shrink;
+        }
+    );
+
+    assert_eq!(Resize::stages(), &[vec![0], vec![1, 2]][..]);
+    assert_eq!(Resize::stage_of::<World>(), [0, 1, 1]);
+    let dot = Resize::to_dot();
+    assert!(dot.starts_with("digraph \"Resize\" {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("subgraph cluster_stage1 {\n        label=\"stage 1\";\n"));
+    assert!(dot.contains("        system2 [label=\"shrink\", shape=box];\n"));
+    let data = format!(
+        "    data0 [label=\"{}\", shape=ellipse];\n",
+        std::any::type_name::<Data>()
+    );
+    assert!(dot.contains(&data));
+    assert!(dot.contains(&format!(
+        "    data1 [label=\"{}\", shape=diamond];\n",
+        std::any::type_name::<String>().replace('"', "\\\"")
+    )));
+    assert!(dot.contains("    data0 -> system0;\n"));
+    assert!(dot.contains("    system0 -> data1;\n"));
+    assert!(dot.contains("    system1 -> data0 [style=dashed, label=\"joined\"];\n"));
+    // `label` reads what `grow` and `shrink` write, but they're disjoint from each other.
+    let before = |a, b| format!("    system{} -> system{} [style=bold, color=red", a, b);
+    assert!(dot.contains(&before(0, 1)));
+    assert!(dot.contains(&before(0, 2)));
+    assert!(!dot.contains(&before(1, 2)));
+}
+
+#[cfg(feature = "lints")]
+#[test]
+fn test_lints() {
+    #[allow(dead_code)]
+    mod lint_world {
+        use crate::*;
+
+        #[derive(Debug, Default)]
+        pub struct Hp(pub i32);
+        #[derive(Debug, Default)]
+        pub struct Armor(pub i32);
+        #[derive(Debug, Default)]
+        pub struct Poison;
+        #[derive(Debug, Default)]
+        pub struct Flying;
+        #[derive(Debug, Default)]
+        pub struct Turn(pub u32);
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    hp: BasicVecStorage<Hp>,
+                    armor: BasicVecStorage<Armor>,
+                    poison: VoidStorage<Poison>,
+                    flying: VoidStorage<Flying>,
+                }
+                resources {
+                    turn: Turn,
+                }
+            }
+        );
+    }
+    use lint_world::*;
+
+    // Mutates everything it writes, each in a different way.
+    struct Regenerate(Option<Entity>);
+    impl<'a> System<'a> for Regenerate {
+        type Dependencies = (
+            RestrictedWriteComponent<'a, Hp>,
+            WriteComponent<'a, Armor>,
+            WriteResource<'a, Turn>,
+        );
+        fn run(&'a mut self, (mut hp, mut armor, mut turn): Self::Dependencies) {
+            (&mut hp,).for_each(|_, (hp,)| hp.0 += 1);
+            if let Some(e) = self.0 {
+                armor.get_mut(e).unwrap().0 += 1;
+            }
+            WriteResource::get_mut(&mut turn).0 += 1;
+        }
+    }
+
+    // Only looks.
+    struct Inspect;
+    impl<'a> System<'a> for Inspect {
+        type Dependencies = (WriteComponent<'a, Hp>, WriteResource<'a, Turn>);
+        fn run(&'a mut self, (hp, turn): Self::Dependencies) {
+            assert!(hp.components().count() <= 1);
+            assert!(turn.0 > 0);
+        }
+    }
+
+    let mut w = World::default();
+    let e = w.new_entity().with(Hp(1)).with(Armor(0)).build();
+    // Added and taken away again, so it's been used.
+    w.entity_mut(e).insert(Poison);
+    w.entity_mut(e).remove::<Poison>();
+
+    let mut dispatcher = Dispatcher::new()
+        .with(&mut w, Regenerate(Some(e)))
+        .with(&mut w, Inspect);
+    dispatcher.run(&mut w).unwrap();
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(w.component::<Hp>().get(e).unwrap().0, 3);
+
+    let inspect = std::any::type_name::<Inspect>();
+    let lints = w.lint();
+    assert_eq!(
+        lints,
+        vec![
+            Lint::UnusedWrite {
+                system: inspect,
+                accessed: AccessedType::component::<Hp>(),
+                runs: 3,
+            },
+            Lint::UnusedWrite {
+                system: inspect,
+                accessed: AccessedType::resource::<Turn>(),
+                runs: 3,
+            },
+            Lint::UnusedComponent {
+                component: std::any::type_name::<Flying>(),
+            },
+        ]
+    );
+    assert_eq!(
+        lints[2].to_string(),
+        format!(
+            "component `{}` has never been attached to any entity",
+            std::any::type_name::<Flying>()
+        )
+    );
+    assert!(lints[0].to_string().starts_with(&format!(
+        "system `{}` requested write access to component",
+        inspect
+    )));
+
+    // Only counting from here on, and nothing has run since.
+    w.access_stats().clear();
+    w.new_entity().with(Flying).build();
+    assert_eq!(w.lint(), vec![]);
+}
+
+#[test]
+fn test_watchdog() {
+    use core::time::Duration;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[allow(dead_code)]
+    mod watched_world {
+        use crate::*;
+
+        #[derive(Debug, Default)]
+        pub struct Unused;
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    unused: VoidStorage<Unused>,
+                }
+                resources {
+                    frame: FrameInfo,
+                }
+            }
+        );
+    }
+    use watched_world::*;
+
+    struct Slow;
+    impl<'a> System<'a> for Slow {
+        type Dependencies = (ReadResource<'a, FrameInfo>,);
+        fn run(&'a mut self, _: Self::Dependencies) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    struct Pathfind;
+    impl<'a> System<'a> for Pathfind {
+        type Dependencies = (ReadResource<'a, FrameInfo>,);
+        fn run(&'a mut self, _: Self::Dependencies) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    let overruns = Rc::new(RefCell::new(Vec::new()));
+    let log = overruns.clone();
+    let mut w = World::default();
+    let mut dispatcher = Dispatcher::new().with_watchdog(
+        Watchdog::new(Duration::from_millis(1)).on_overrun(move |o| log.borrow_mut().push(*o)),
+    );
+    dispatcher.add(&mut w, Slow);
+    dispatcher
+        .add(&mut w, Pathfind)
+        .budget(Duration::from_secs(60));
+
+    dispatcher
+        .run_frame(&mut w, Duration::from_millis(16))
+        .unwrap();
+    dispatcher
+        .run_frame(&mut w, Duration::from_millis(16))
+        .unwrap();
+    // Without a frame, the tick just counts runs.
+    dispatcher.run(&mut w).unwrap();
+    {
+        let overruns = overruns.borrow();
+        let ticks: Vec<_> = overruns.iter().map(|o| o.tick).collect();
+        assert_eq!(ticks, vec![1, 2, 3]);
+        assert!(overruns
+            .iter()
+            .all(|o| o.system == std::any::type_name::<Slow>()
+                && o.budget == Duration::from_millis(1)
+                && o.elapsed >= Duration::from_millis(10)));
+        assert!(overruns[0]
+            .to_string()
+            .starts_with(&format!("system {} took", std::any::type_name::<Slow>())));
+    }
+    assert_eq!(dispatcher.watchdog().unwrap().overruns(), 3);
+
+    dispatcher.set_watchdog(Some(Watchdog::new(Duration::from_millis(1)).panicking()));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| dispatcher.run(&mut w)));
+    assert!(result.is_err());
+
+    // Without a watchdog, nothing is timed.
+    assert!(dispatcher.set_watchdog(None).is_some());
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(overruns.borrow().len(), 3);
+}
+
+#[test]
+fn test_poison() {
+    #[allow(dead_code)]
+    mod poison_world {
+        use crate::*;
+
+        #[derive(Debug, Default)]
+        pub struct Hp(pub u32);
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    hp: BasicVecStorage<Hp>,
+                }
+                resources {}
+            }
+        );
+    }
+    use poison_world::*;
+
+    struct Drain(u32);
+    impl<'a> System<'a> for Drain {
+        type Dependencies = (WriteComponent<'a, Hp>,);
+        fn run(&'a mut self, (mut hp,): Self::Dependencies) {
+            let amount = self.0;
+            (&mut hp,).for_each(|_, (h,)| {
+                h.0 =
+                    h.0.checked_sub(amount)
+                        .unwrap_or_else(|| panic!("{} is too much", amount));
+            });
+        }
+    }
+
+    // Asks for the same storage twice, so fetching its dependencies panics.
+    struct Conflicted;
+    impl<'a> System<'a> for Conflicted {
+        type Dependencies = (ReadComponent<'a, Hp>, WriteComponent<'a, Hp>);
+        fn run(&'a mut self, _: Self::Dependencies) {}
+    }
+
+    let mut w = World::default();
+    w.new_entity().with(Hp(10)).build();
+    w.new_entity().with(Hp(3)).build();
+    w.run_system(&mut Drain(1));
+    assert!(!w.is_poisoned());
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        w.run_system(&mut Drain(5));
+    }));
+    assert!(result.is_err());
+    assert!(w.is_poisoned());
+    assert_eq!(w.poison().system(), Some(std::any::type_name::<Drain>()));
+    assert_eq!(w.poison().message().as_deref(), Some("5 is too much"));
+    // The first entity was drained before the panic, and the storage isn't borrowed any more.
+    let hp: Vec<_> = w.component::<Hp>().iter().flatten().map(|h| h.0).collect();
+    assert_eq!(hp, vec![4, 2]);
+    w.component_mut::<Hp>().clear();
+
+    // Later panics don't replace the first.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        w.run_system(&mut Conflicted);
+    }));
+    assert!(result.is_err());
+    assert_eq!(w.poison().system(), Some(std::any::type_name::<Drain>()));
+
+    w.poison().clear();
+    assert!(!w.is_poisoned());
+    assert_eq!(w.poison().message(), None);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        w.run_system(&mut Conflicted);
+    }));
+    assert!(result.is_err());
+    assert_eq!(
+        w.poison().system(),
+        Some(std::any::type_name::<Conflicted>())
+    );
+
+    // Systems still run on a poisoned world.
+    let mut dispatcher = Dispatcher::new().with(&mut w, Drain(0));
+    dispatcher.run(&mut w).unwrap();
+}
+
+#[test]
+fn test_crash_dump() {
+    #[allow(dead_code)]
+    mod crash_world {
+        use crate::*;
+
+        #[derive(Debug, Default)]
+        pub struct Hp(pub u32);
+        #[derive(Debug, Default)]
+        pub struct Turn(pub u32);
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    hp: BasicVecStorage<Hp>,
+                }
+                resources {
+                    turn: Turn,
+                }
+            }
+        );
+    }
+    use crash_world::*;
+
+    struct Overkill;
+    impl<'a> System<'a> for Overkill {
+        type Dependencies = (WriteComponent<'a, Hp>, ReadResource<'a, Turn>);
+        fn run(&'a mut self, (mut hp, turn): Self::Dependencies) {
+            (&mut hp,).for_each(|_, (h,)| {
+                h.0 = h.0.checked_sub(turn.0).expect("hp went negative");
+            });
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("ecstatic-crash-test-{}", std::process::id()));
+    let reporter = CrashReporter::new(&dir, Uncompressed).journal_tail(2);
+    CrashReporter::install_hook();
+
+    let mut w = World::default();
+    w.new_entity().with(Hp(2)).build();
+    w.new_entity().with(Hp(7)).build();
+    <World as GetResource<Turn>>::set(&w, Turn(1));
+
+    // Nothing is written unless there's a panic.
+    reporter.catch(&mut w, |w| w.run_system(&mut Overkill));
+    assert_eq!(reporter.last_dump(), None);
+
+    <World as GetResource<Turn>>::set(&w, Turn(5));
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        reporter.catch(&mut w, |w| w.run_system(&mut Overkill))
+    }));
+    assert!(result.is_err());
+    let path = reporter.last_dump().unwrap();
+    assert!(path.starts_with(&dir));
+    let dump = reporter.read(&path).unwrap();
+    assert!(dump.starts_with("# panic\n"));
+    assert!(dump.contains("hp went negative"));
+    assert!(dump.contains(&format!(
+        "\n# system\n{}\n",
+        std::any::type_name::<Overkill>()
+    )));
+    assert!(dump.contains("Hp(1)"));
+    assert!(dump.contains("Turn(5)"));
+    #[cfg(feature = "journal")]
+    {
+        let journal = dump.split("# journal\n").nth(1).unwrap();
+        let journal: Vec<_> = journal.split("\n\n").next().unwrap().lines().collect();
+        assert_eq!(journal.len(), 2);
+        assert!(journal[1].starts_with("ResourceSet"));
+    }
+
+    // A second crash doesn't overwrite the first dump.
+    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        reporter.catch(&mut w, |w| w.run_system(&mut Overkill))
+    }));
+    assert_ne!(reporter.last_dump().unwrap(), path);
+    assert!(path.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(not(loom))]
+#[test]
+fn test_sync() {
+    use crate::sync::*;
+    use std::sync::Arc;
+
+    let lock = StorageLock::new(vec![1]);
+    {
+        let read = lock.try_read().unwrap();
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_write().is_none());
+        assert_eq!(*read, vec![1]);
+    }
+    assert!(!lock.is_claimed());
+    {
+        let mut write = lock.try_write().unwrap();
+        write.push(2);
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        assert!(lock.is_claimed());
+    }
+    assert_eq!(format!("{:?}", lock), "StorageLock([1, 2])");
+    assert_eq!(lock.into_inner(), vec![1, 2]);
+
+    let queue = Arc::new(CommandQueue::<World>::new());
+    let mut w = World::default();
+    for _ in 0..3 {
+        w.new_entity().build();
+    }
+    let allocator = Arc::new(EntityAllocator::reserve(&w, 7));
+    let workers: Vec<_> = (0..4)
+        .map(|i| {
+            let (queue, allocator) = (queue.clone(), allocator.clone());
+            std::thread::spawn(move || {
+                let e = allocator.allocate();
+                queue.push(move |w: &mut World| {
+                    w.entity_mut(e).insert(Data { x: e.id() as u32 });
+                });
+                queue.push(move |w: &mut World| {
+                    w.new_entity().with(Data { x: 100 + i }).build();
+                });
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    assert_eq!(queue.len(), 8);
+    queue.apply(&mut w);
+    assert!(queue.is_empty());
+    let mut applied: Vec<_> = w
+        .component::<Data>()
+        .iter()
+        .flatten()
+        .map(|d| d.x)
+        .collect();
+    applied.sort_unstable();
+    assert_eq!(applied, vec![3, 4, 5, 6, 100, 101, 102, 103]);
+
+    // Reserved entities are alive whether or not they've been handed out, and the world doesn't
+    // reuse their slots.
+    let more: Vec<_> = allocator.allocate_many(2).collect();
+    assert_eq!(more, vec![Entity::new(7), Entity::new(8)]);
+    assert_eq!(allocator.next_slot(), 9);
+    assert_eq!(allocator.remaining(), 1);
+    assert!(more.iter().all(|&e| w.is_alive(e)));
+    assert_eq!(allocator.unused().collect::<Vec<_>>(), vec![Entity::new(9)]);
+    assert!(w.new_entity().build().id() > 9);
+
+    // Running out doesn't use up the last one.
+    let caught = std::panic::catch_unwind(|| allocator.allocate_many(2).count());
+    assert!(caught.is_err());
+    assert_eq!(allocator.allocate(), Entity::new(9));
+    assert!(std::panic::catch_unwind(|| allocator.allocate()).is_err());
+    assert_eq!(allocator.remaining(), 0);
+}
+
+#[cfg(loom)]
+#[test]
+fn test_loom_storage_lock() {
+    use crate::sync::*;
+    use loom::sync::Arc;
+
+    loom::model(|| {
+        let lock = Arc::new(StorageLock::new(0));
+        let writers: Vec<_> = (0..2)
+            .map(|_| {
+                let lock = lock.clone();
+                loom::thread::spawn(move || match lock.try_write() {
+                    Some(mut value) => {
+                        *value += 1;
+                        1
+                    }
+                    None => 0,
+                })
+            })
+            .collect();
+        let seen = lock.try_read().map(|value| *value);
+        let wrote: usize = writers.into_iter().map(|t| t.join().unwrap()).sum();
+        assert!(seen.unwrap_or(0) <= wrote);
+        assert_eq!(*lock.try_read().unwrap(), wrote);
+        assert!(!lock.is_claimed());
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn test_loom_command_queue() {
+    use crate::sync::*;
+    use loom::sync::Arc;
+
+    loom::model(|| {
+        let queue = Arc::new(CommandQueue::<Vec<u32>>::new());
+        let other = queue.clone();
+        let pusher = loom::thread::spawn(move || other.push(|w: &mut Vec<u32>| w.push(1)));
+        queue.push(|w: &mut Vec<u32>| w.push(2));
+        let mut applied = Vec::new();
+        queue.apply(&mut applied);
+        pusher.join().unwrap();
+        queue.apply(&mut applied);
+        applied.sort_unstable();
+        assert_eq!(applied, vec![1, 2]);
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn test_loom_entity_allocator() {
+    use crate::sync::*;
+    use loom::sync::Arc;
+
+    loom::model(|| {
+        let allocator = Arc::new(EntityAllocator::reserve(&World::default(), 2));
+        let other = allocator.clone();
+        let t = loom::thread::spawn(move || other.allocate());
+        let mine = allocator.allocate();
+        let theirs = t.join().unwrap();
+        assert_ne!(mine, theirs);
+        assert_eq!(allocator.next_slot(), 2);
+    });
+}
+
+#[test]
+fn test_executors() {
+    use std::boxed::Box;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+
+    fn check(executor: &dyn Executor) {
+        // Scoped jobs can borrow, and are all done by the time `scope` returns.
+        let mut sums = [0u64; 16];
+        executor.scope(
+            sums.iter_mut()
+                .enumerate()
+                .map(|(i, sum)| -> Job<'_> { Box::new(move || *sum = (0..=i as u64).sum()) })
+                .collect(),
+        );
+        assert_eq!(sums[15], 120);
+
+        // Scopes nest, even more deeply than there are threads.
+        let count = AtomicUsize::new(0);
+        let count = &count;
+        executor.scope(
+            (0..8)
+                .map(|_| -> Job<'_> {
+                    Box::new(move || {
+                        executor.join(
+                            Box::new(move || {
+                                count.fetch_add(1, Ordering::Relaxed);
+                            }),
+                            Box::new(move || {
+                                count.fetch_add(1, Ordering::Relaxed);
+                            }),
+                        )
+                    })
+                })
+                .collect(),
+        );
+        assert_eq!(count.load(Ordering::Relaxed), 16);
+
+        // A panicking job doesn't stop the others, and the panic comes out of the scope.
+        let done = AtomicUsize::new(0);
+        let done = &done;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            executor.scope(
+                (0..4)
+                    .map(|i| -> Job<'_> {
+                        Box::new(move || {
+                            if i == 1 {
+                                panic!("job failed");
+                            }
+                            done.fetch_add(1, Ordering::Relaxed);
+                        })
+                    })
+                    .collect(),
+            )
+        }));
+        assert!(result.is_err());
+        assert_eq!(done.load(Ordering::Relaxed), 3);
+
+        let (send, receive) = mpsc::channel();
+        executor.spawn(Box::new(move || send.send(7).unwrap()));
+        assert_eq!(receive.recv().unwrap(), 7);
+    }
+
+    check(&Inline);
+    let pool = ThreadPool::new(2);
+    assert_eq!(pool.parallelism(), 2);
+    check(&pool);
+    #[cfg(feature = "rayon")]
+    check(&Rayon);
+
+    // Jobs spawned on a pool finish before it's dropped.
+    let spawned = Arc::new(AtomicUsize::new(0));
+    for _ in 0..10 {
+        let spawned = spawned.clone();
+        pool.spawn(Box::new(move || {
+            spawned.fetch_add(1, Ordering::Relaxed);
+        }));
+    }
+    drop(pool);
+    assert_eq!(spawned.load(Ordering::Relaxed), 10);
+
+    // Systems see the executor set on the thread that runs them.
+    #[allow(dead_code)]
+    mod pooled_world {
+        use crate::*;
+
+        #[derive(Debug, Default)]
+        pub struct Unused;
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    unused: VoidStorage<Unused>,
+                }
+                resources {
+                    parallelism: usize,
+                }
+            }
+        );
+    }
+    use pooled_world::*;
+
+    struct Measure;
+    impl<'a> System<'a> for Measure {
+        type Dependencies = (WriteResource<'a, usize>,);
+        fn run(&'a mut self, (mut parallelism,): Self::Dependencies) {
+            *parallelism = executor::current().parallelism();
+        }
+    }
+
+    let mut w = World::default();
+    let mut dispatcher = Dispatcher::new().with(&mut w, Measure);
+    assert!(executor::set_current(Some(Arc::new(ThreadPool::new(3)))).is_none());
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(*<World as GetResource<usize>>::get(&w), 3);
+    let pool = executor::set_current(None).unwrap();
+    assert_eq!(pool.parallelism(), 3);
+    assert_eq!(executor::current().parallelism(), 1);
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(*<World as GetResource<usize>>::get(&w), 1);
+}
+
+#[test]
+fn test_tasks() {
+    use std::sync::{Arc, Mutex};
+    use std::vec::Vec;
+
+    #[allow(dead_code)]
+    mod meshing_world {
+        use crate::*;
+        use std::vec::Vec;
+
+        #[derive(Debug, Default)]
+        pub struct Chunk {
+            pub voxels: Vec<u32>,
+            pub mesh: Vec<u32>,
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    chunks: BasicVecStorage<Chunk>,
+                }
+                resources {
+                    order: Vec<&'static str>,
+                }
+            }
+        );
+    }
+    use meshing_world::*;
+
+    struct MeshChunks;
+    impl<'a> System<'a> for MeshChunks {
+        type Dependencies = (
+            Tasks,
+            WriteComponent<'a, Chunk>,
+            WriteResource<'a, Vec<&'static str>>,
+        );
+        fn run(&'a mut self, (tasks, mut chunks, mut order): Self::Dependencies) {
+            let mut chunks: Vec<&mut Chunk> = chunks.components_mut().collect();
+            tasks.for_each_mut(&mut chunks, |chunk| {
+                chunk.mesh = chunk.voxels.iter().map(|v| v * 2).collect();
+            });
+
+            // Later tasks see what the ones they're spawned after did.
+            let log = Mutex::new(Vec::new());
+            let log = &log;
+            tasks.scope(|graph| {
+                let a = graph.spawn(move || log.lock().unwrap().push("a"));
+                let b = graph.spawn(move || log.lock().unwrap().push("b"));
+                let c = graph.spawn_after(&[a, b], move || log.lock().unwrap().push("c"));
+                graph.spawn_after(&[c], move || log.lock().unwrap().push("d"));
+                assert_eq!(graph.len(), 4);
+            });
+            *order = log.lock().unwrap().clone();
+        }
+    }
+
+    let mut w = World::default();
+    for i in 0..10 {
+        w.new_entity()
+            .with(Chunk {
+                voxels: vec![i; 4],
+                mesh: Vec::new(),
+            })
+            .build();
+    }
+    let mut dispatcher = Dispatcher::new().with(&mut w, MeshChunks);
+    executor::set_current(Some(Arc::new(ThreadPool::new(4))));
+    dispatcher.run(&mut w).unwrap();
+
+    let meshes: Vec<_> = w
+        .component::<Chunk>()
+        .iter()
+        .flatten()
+        .map(|c| c.mesh[0])
+        .collect();
+    assert_eq!(meshes, (0..10).map(|i| i * 2).collect::<Vec<_>>());
+    let order = <World as GetResource<Vec<&'static str>>>::get(&w).clone();
+    assert_eq!(order.len(), 4);
+    assert_eq!(&order[2..], &["c", "d"]);
+
+    // Four tasks for the chunks, one per thread, and four in the graph.
+    assert_eq!(dispatcher.systems().next().unwrap().tasks(), 8);
+
+    // Without an executor, the tasks run inline, as one for the chunks.
+    executor::set_current(None);
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(dispatcher.systems().next().unwrap().tasks(), 5);
+}
+
+#[test]
+fn test_reserve_entity() {
+    let mut w = World::default();
+    let a = w.new_entity().with(Data { x: 1 }).build();
+    w.delete_entity(a);
+
+    // Reserved entities are alive straight away, and never reuse a freed slot.
+    let b = w.reserve_entity();
+    let c = w.reserve_entity();
+    assert_ne!(b.id(), a.id());
+    assert_ne!(b, c);
+    assert!(w.is_alive(b) && w.is_alive(c));
+    assert!(!w.is_alive(Entity::new(c.id() as u32 + 1)));
+    assert!(<World as GetComponent<'_, Data>>::get(&w).get(b).is_none());
+
+    // Building another entity first doesn't give it a reserved slot.
+    let d = w.new_entity().with(Data { x: 4 }).build();
+    assert!(d != b && d != c);
+    assert!(w.is_alive(b) && w.is_alive(c) && w.is_alive(d));
+
+    // Components arrive later, e.g. from a command.
+    let mut commands = Commands::new();
+    let e = w.reserve_entity();
+    commands.push(move |w: &mut World| {
+        <World as GetComponent<'_, Data>>::insert(w, e, Data { x: 5 });
+    });
+    commands.apply(&mut w);
+    assert_eq!(
+        <World as GetComponent<'_, Data>>::get(&w).get(e).unwrap().x,
+        5
+    );
+    assert!(w.validate().is_ok());
+
+    w.delete_entity(c);
+    assert!(!w.is_alive(c));
+    assert!(w.is_alive(b));
+
+    // Reservations are cleared along with everything else.
+    let f = w.reserve_entity();
+    w.clear();
+    assert!(!w.is_alive(f) && !w.is_alive(b));
+}
+
+#[test]
+fn test_deferred_resources() {
+    struct Reader;
+    impl<'a> System<'a> for Reader {
+        type Dependencies = (ReadResource<'a, String>,);
+        fn run(&'a mut self, (s,): Self::Dependencies) {
+            assert_eq!(*s, "");
+        }
+    }
+
+    struct Appender;
+    impl<'a> System<'a> for Appender {
+        type Dependencies = (ReadResource<'a, String>, Deferred<'a, World>);
+        fn run(&'a mut self, (s, deferred): Self::Dependencies) {
+            assert_eq!(*s, "");
+            deferred.mutate_resource(|s: &mut String| s.push('a'));
+        }
+    }
+
+    define_schedule!(
+        schedule Readers {
+            appender: Appender,
+            reader: Reader,
+        }
+    );
+
+    // Deferring the write doesn't conflict with reading, and it isn't applied until the end of
+    // the stage.
+    assert_eq!(Readers::stages(), &[vec![0, 1]][..]);
+
+    let mut w = World::default();
+    let mut readers = Readers {
+        appender: Appender,
+        reader: Reader,
+    };
+    readers.run(&mut w).unwrap();
+    assert_eq!(*<World as GetResource<String>>::get(&w), "a");
+
+    // A dispatcher applies the changes once all its systems have run.
+    w.set(String::new());
+    let mut dispatcher = Dispatcher::new()
+        .with(&mut w, Appender)
+        .with(&mut w, Appender)
+        .with(&mut w, Reader);
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(*<World as GetResource<String>>::get(&w), "aa");
+
+    // Running a system directly leaves the changes queued until they're applied.
+    w.set(String::new());
+    w.run_system(&mut Appender);
+    assert_eq!(w.deferred().len(), 1);
+    assert_eq!(*<World as GetResource<String>>::get(&w), "");
+    w.apply_deferred();
+    assert_eq!(*<World as GetResource<String>>::get(&w), "a");
+    assert!(w.deferred().is_empty());
+
+    let mut commands = Commands::<World>::new();
+    commands.insert_resource(String::from("b"));
+    commands.mutate_resource(|s: &mut String| s.push('c'));
+    commands.apply(&mut w);
+    assert_eq!(*<World as GetResource<String>>::get(&w), "bc");
+}
+
+#[test]
+fn test_observers() {
+    use std::vec::Vec;
+
+    #[allow(dead_code)]
+    mod observed_world {
+        use crate::*;
+        use std::vec::Vec;
+
+        #[derive(Debug, Default)]
+        pub struct Unused;
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub struct Hit(pub u32);
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub struct Killed;
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    unused: VoidStorage<Unused>,
+                }
+                resources {
+                    hits: Events<Hit>,
+                    kills: Events<Killed>,
+                    log: Vec<&'static str>,
+                    damage: u32,
+                }
+            }
+        );
+    }
+    use observed_world::*;
+
+    struct Attack(Vec<u32>);
+    impl<'a> System<'a> for Attack {
+        type Dependencies = (
+            WriteResource<'a, Events<Hit>>,
+            WriteResource<'a, Vec<&'static str>>,
+        );
+        fn run(&'a mut self, (mut hits, mut log): Self::Dependencies) {
+            log.push("attack");
+            hits.extend(self.0.drain(..).map(Hit));
+        }
+    }
+
+    struct Later;
+    impl<'a> System<'a> for Later {
+        type Dependencies = (WriteResource<'a, Vec<&'static str>>,);
+        fn run(&'a mut self, (mut log,): Self::Dependencies) {
+            log.push("later");
+        }
+    }
+
+    // Sends events of its own, which are observed in the same round.
+    struct TakeDamage(usize);
+    impl<'a> Observer<'a, Hit> for TakeDamage {
+        type Dependencies = (
+            WriteResource<'a, Vec<&'static str>>,
+            WriteResource<'a, Events<Killed>>,
+            Deferred<'a, World>,
+        );
+        fn observe(&'a mut self, hits: &[Hit], (mut log, mut kills, deferred): Self::Dependencies) {
+            self.0 += 1;
+            log.push("hit");
+            let total: u32 = hits.iter().map(|h| h.0).sum();
+            deferred.mutate_resource(move |damage: &mut u32| *damage += total);
+            if total >= 10 {
+                kills.send(Killed);
+            }
+        }
+    }
+
+    struct Score;
+    impl<'a> Observer<'a, Killed> for Score {
+        type Dependencies = (WriteResource<'a, Vec<&'static str>>,);
+        fn observe(&'a mut self, kills: &[Killed], (mut log,): Self::Dependencies) {
+            assert_eq!(kills, &[Killed]);
+            log.push("kill");
+        }
+    }
+
+    let mut w = World::default();
+    let mut dispatcher = Dispatcher::new()
+        .with(&mut w, Attack(vec![3, 8]))
+        .with(&mut w, Later)
+        .with_observer(Score);
+    dispatcher.add_observer(TakeDamage(0));
+
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(
+        *<World as GetResource<Vec<&'static str>>>::get(&w),
+        vec!["attack", "hit", "kill", "later"]
+    );
+    assert_eq!(*<World as GetResource<u32>>::get(&w), 11);
+    assert!(<World as GetResource<Events<Hit>>>::get(&w).is_empty());
+
+    // Nothing is observed without events, and events sent between runs are observed first.
+    <World as GetResource<Vec<&'static str>>>::get_mut(&w).clear();
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(
+        *<World as GetResource<Vec<&'static str>>>::get(&w),
+        vec!["attack", "later"]
+    );
+    <World as GetResource<Vec<&'static str>>>::get_mut(&w).clear();
+    <World as GetResource<Events<Hit>>>::get_mut(&w).send(Hit(1));
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(
+        *<World as GetResource<Vec<&'static str>>>::get(&w),
+        vec!["hit", "attack", "later"]
+    );
+    assert_eq!(*<World as GetResource<u32>>::get(&w), 12);
+
+    // Every observer of an event type sees every event.
+    struct Bonus;
+    impl<'a> Observer<'a, Hit> for Bonus {
+        type Dependencies = (WriteResource<'a, u32>,);
+        fn observe(&'a mut self, hits: &[Hit], (mut damage,): Self::Dependencies) {
+            *damage += 100 * hits.len() as u32;
+        }
+    }
+    let mut w = World::default();
+    let mut dispatcher = Dispatcher::new()
+        .with_observer(TakeDamage(0))
+        .with_observer(Bonus);
+    <World as GetResource<Events<Hit>>>::get_mut(&w).send(Hit(1));
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(*<World as GetResource<u32>>::get(&w), 101);
+}
+
+#[test]
+fn test_entity_events() {
+    use std::vec::Vec;
+
+    #[allow(dead_code)]
+    mod targeted_world {
+        use crate::*;
+
+        #[derive(Debug, Default)]
+        pub struct Health(pub u32);
+
+        #[derive(Debug, Default)]
+        pub struct Shield;
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub struct Damage(pub u32);
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    health: BasicVecStorage<Health>,
+                    shields: VoidStorage<Shield>,
+                }
+                resources {
+                    damage: EntityEvents<Damage>,
+                }
+            }
+        );
+    }
+    use targeted_world::*;
+
+    let mut w = World::default();
+    let a = w.new_entity().with(Health(10)).build();
+    let b = w.new_entity().with(Health(10)).with(Shield).build();
+    let c = w.new_entity().with(Health(10)).build();
+    let d = w.new_entity().with(Health(10)).build();
+    w.disable(d);
+
+    // Sent out of order; visited in id order, each entity with its events in the order sent.
+    w.send_to(c, Damage(1));
+    w.send_to(a, Damage(2));
+    w.send_to(c, Damage(3));
+    w.send_to(b, Damage(4));
+    w.send_to(d, Damage(5));
+    {
+        let events = <World as GetResource<EntityEvents<Damage>>>::get(&w);
+        assert_eq!(events.len(), 5);
+        assert_eq!(events.targets(), &[a, b, c, d]);
+        assert_eq!(events.get(c), &[Damage(1), Damage(3)]);
+        assert_eq!(events.get(Entity::new(9)), &[]);
+        assert_eq!(
+            events.iter().map(|(_, e)| e.len()).collect::<Vec<_>>(),
+            vec![1, 1, 2, 1]
+        );
+    }
+
+    struct ApplyDamage;
+    impl<'a> System<'a> for ApplyDamage {
+        type Dependencies = (
+            ReadResource<'a, EntityEvents<Damage>>,
+            WriteComponent<'a, Health>,
+        );
+        fn run(&'a mut self, (damage, mut health): Self::Dependencies) {
+            let mut visited = Vec::new();
+            damage.for_each_matching((&mut health,), |e, hits, (h,)| {
+                visited.push(e);
+                h.0 -= hits.iter().map(|d| d.0).sum::<u32>();
+            });
+            assert_eq!(visited.len(), 3);
+        }
+    }
+    w.run_system(&mut ApplyDamage);
+    let health: Vec<_> = w
+        .component::<Health>()
+        .iter()
+        .flatten()
+        .map(|h| h.0)
+        .collect();
+    assert_eq!(health, vec![8, 6, 6, 10]);
+
+    // Joins narrow it down further.
+    let mut shielded = Vec::new();
+    {
+        let events = <World as GetResource<EntityEvents<Damage>>>::get(&w);
+        let (shields,): (ReadComponent<Shield>,) = ComponentProvider::fetch(&w);
+        events.for_each_matching((&shields,), |e, hits, _| shielded.push((e, hits.len())));
+    }
+    assert_eq!(shielded, vec![(b, 1)]);
+
+    // Events for an entity that's since been deleted don't go to the one that reuses its slot.
+    <World as GetResource<EntityEvents<Damage>>>::get_mut(&w).clear();
+    w.send_to(a, Damage(1));
+    w.delete_entity(a);
+    let e = w.new_entity().with(Health(10)).build();
+    assert_eq!(e.id(), a.id());
+    w.send_to(e, Damage(2));
+
+    struct CollectDamage(Vec<Damage>);
+    impl<'a> System<'a> for CollectDamage {
+        type Dependencies = (
+            ReadResource<'a, EntityEvents<Damage>>,
+            ReadComponent<'a, Health>,
+        );
+        fn run(&'a mut self, (damage, health): Self::Dependencies) {
+            damage.for_each_matching((&health,), |_, hits, _| self.0.extend_from_slice(hits));
+        }
+    }
+    let mut collect = CollectDamage(Vec::new());
+    w.run_system(&mut collect);
+    assert_eq!(collect.0, vec![Damage(2)]);
+}
+
+#[cfg(feature = "input")]
+#[test]
+fn test_input() {
+    use std::string::{String, ToString};
+    use std::vec;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    enum Key {
+        Space,
+        Left,
+        Right,
+        Stick,
+    }
+
+    let mut state = InputState::default();
+    state.apply(&InputEvent::Pressed(Key::Space));
+    // Key repeat doesn't count as another press.
+    state.end_frame();
+    state.apply(&InputEvent::Pressed(Key::Space));
+    assert!(state.is_down(&Key::Space));
+    assert!(!state.just_pressed(&Key::Space));
+    state.end_frame();
+    state.apply(&InputEvent::Released(Key::Space));
+    assert!(!state.is_down(&Key::Space));
+    assert!(state.just_released(&Key::Space));
+
+    let mut actions = ActionMap::new();
+    actions
+        .bind("jump", Key::Space)
+        .bind("move", Key::Right)
+        .bind_scaled("move", Key::Left, -1.0)
+        .bind("move", Key::Stick);
+    assert_eq!(actions.actions().collect::<Vec<_>>(), vec!["jump", "move"]);
+
+    state.end_frame();
+    state.apply(&InputEvent::Pressed(Key::Space));
+    state.apply(&InputEvent::Pressed(Key::Left));
+    state.apply(&InputEvent::Axis(Key::Stick, -0.5));
+    assert!(actions.just_activated(&state, "jump"));
+    assert_eq!(actions.value(&state, "jump"), 1.0);
+    // Values are clamped, but still count as active when they cancel out.
+    assert_eq!(actions.value(&state, "move"), -1.0);
+    state.apply(&InputEvent::Pressed(Key::Right));
+    state.apply(&InputEvent::Axis(Key::Stick, 0.0));
+    assert_eq!(actions.value(&state, "move"), 0.0);
+    assert!(actions.is_active(&state, "move"));
+    assert!(!actions.is_active(&state, "crouch"));
+
+    state.release_all();
+    assert!(!actions.is_active(&state, "move"));
+    assert!(actions.just_deactivated(&state, "jump"));
+
+    // Bindings round trip through `Value`, with scaled ones written out in full.
+    let mut named = ActionMap::new();
+    named
+        .bind("move", "d".to_string())
+        .bind_scaled("move", "a".to_string(), -1.0);
+    let value = named.save();
+    let bindings = value.field("move").unwrap().as_list().unwrap();
+    assert_eq!(bindings[0], Value::Str("d".to_string()));
+    assert_eq!(bindings[1].field("scale"), Ok(&Value::Float(-1.0)));
+    assert_eq!(ActionMap::load(&value), Ok(named));
+    let partial = Value::Map(vec![(
+        "fire".to_string(),
+        Value::List(vec![Value::Map(vec![(
+            "input".to_string(),
+            Value::Str("x".to_string()),
+        )])]),
+    )]);
+    let partial = ActionMap::<String>::load(&partial).unwrap();
+    assert_eq!(partial.bindings("fire")[0].scale, 1.0);
+    assert!(ActionMap::<String>::load(&Value::List(vec![])).is_err());
+}
+
+#[cfg(feature = "term")]
+#[test]
+fn test_term() {
+    use crate::term::Position;
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    #[allow(dead_code)]
+    mod term_world {
+        use crate::*;
+
+        #[derive(Default)]
+        pub struct At(pub Position);
+        impl AsRef<Position> for At {
+            fn as_ref(&self) -> &Position {
+                &self.0
+            }
+        }
+
+        #[derive(Default)]
+        pub struct Looks(pub Glyph);
+        impl AsRef<Glyph> for Looks {
+            fn as_ref(&self) -> &Glyph {
+                &self.0
+            }
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    positions: BasicVecStorage<At>,
+                    glyphs: BasicVecStorage<Looks>,
+                }
+                resources {
+                    screen: CellBuffer,
+                }
+            }
+        );
+    }
+    use term_world::{At, Looks, World};
+
+    let mut w = World::default();
+    *<World as GetResource<CellBuffer>>::get_mut(&w) = CellBuffer::new(4, 2);
+    let red = Rgb::new(128, 0, 0);
+    w.new_entity()
+        .with(At(Position::new(0, 0)))
+        .with(Looks(Glyph::new('#').with_bg(red)))
+        .build();
+    // Drawn over the wall, and over the item created after it, keeping the wall's background.
+    let goblin = w
+        .new_entity()
+        .with(At(Position::new(0, 0)))
+        .with(Looks(Glyph::new('g').with_z(1)))
+        .build();
+    w.new_entity()
+        .with(At(Position::new(0, 0)))
+        .with(Looks(Glyph::new('%')))
+        .build();
+    // Off the edge, or without a glyph: not drawn.
+    w.new_entity()
+        .with(At(Position::new(4, 0)))
+        .with(Looks(Glyph::new('x')))
+        .build();
+    w.new_entity()
+        .with(At(Position::new(-1, 1)))
+        .with(Looks(Glyph::new('x')))
+        .build();
+    w.new_entity().with(At(Position::new(1, 1))).build();
+
+    let mut render = RenderGlyphs::<At, Looks>::new();
+    w.run_system(&mut render);
+    let before = <World as GetResource<CellBuffer>>::get(&w).clone();
+    assert_eq!(before.to_string(), "g   \n    \n");
+    assert_eq!(
+        before.get(0, 0),
+        Some(&Cell {
+            ch: 'g',
+            fg: Rgb::WHITE,
+            bg: red,
+        })
+    );
+    assert_eq!(before.get(4, 0), None);
+
+    // Moving the goblin changes two cells, and the last frame's glyphs are cleared.
+    w.component_mut::<At>()
+        .insert(goblin, At(Position::new(2, 1)));
+    w.run_system(&mut render);
+    let mut screen = <World as GetResource<CellBuffer>>::get_mut(&w);
+    assert_eq!(screen.to_string(), "%   \n  g \n");
+    let changes: Vec<_> = screen.changes(&before).map(|(p, c)| (p, c.ch)).collect();
+    assert_eq!(
+        changes,
+        vec![(Position::new(0, 0), '%'), (Position::new(2, 1), 'g')]
+    );
+
+    screen.print(1, 0, "HP: 7", Rgb::GREEN);
+    assert_eq!(screen.to_string(), "%HP:\n  g \n");
+    assert_eq!(screen.get(1, 0).unwrap().fg, Rgb::GREEN);
+    screen.resize(2, 1);
+    assert_eq!(screen.to_string(), "  \n");
+    assert_eq!(screen.changes(&before).count(), 2);
+}
+
+#[test]
+fn test_viewport() {
+    use std::vec::Vec;
+
+    #[allow(dead_code)]
+    mod viewport_world {
+        use crate::*;
+
+        #[derive(Default)]
+        pub struct At(pub i32, pub i32);
+        impl GridPosition for At {
+            fn grid_position(&self) -> (i32, i32) {
+                (self.0, self.1)
+            }
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    positions: BasicVecStorage<At>,
+                }
+                resources {
+                    viewport: Viewport2D,
+                }
+            }
+        );
+    }
+    use viewport_world::{At, World};
+
+    // Without bounds, the viewport scrolls anywhere.
+    let mut v = Viewport2D::new(5, 3);
+    v.center_on((0, 0));
+    assert_eq!(v.offset(), (-2, -1));
+    assert_eq!(v.center(), (0, 0));
+    assert_eq!(v.world_to_screen((-2, -1)), Some((0, 0)));
+    assert_eq!(v.world_to_screen((2, 1)), Some((4, 2)));
+    assert_eq!(v.world_to_screen((3, 1)), None);
+    assert_eq!(v.screen_to_world((4, 2)), (2, 1));
+    assert_eq!(v.screen_to_world((-1, 0)), (-3, -1));
+    let tiles: Vec<_> = v.tiles().collect();
+    assert_eq!(tiles.len(), 15);
+    assert_eq!((tiles[0], tiles[5], tiles[14]), ((-2, -1), (-2, 0), (2, 1)));
+
+    // With them, it stops at the edges, and is centered on maps smaller than it.
+    v.set_bounds(Some((20, 2)));
+    assert_eq!(v.offset(), (0, 0));
+    v.scroll(100, 100);
+    assert_eq!(v.offset(), (15, 0));
+    v.set_bounds(Some((20, 10)));
+    v.resize(7, 3);
+    assert_eq!(v.offset(), (13, 0));
+    v.resize(9, 20);
+    assert_eq!(v.offset(), (11, -5));
+
+    let mut w = World::default();
+    let player = w.new_entity().with(At(10, 10)).build();
+    let near = w.new_entity().with(At(12, 9)).build();
+    w.new_entity().with(At(30, 10)).build();
+    let disabled = w.new_entity().with(At(10, 11)).build();
+    w.disable(disabled);
+    *<World as GetResource<Viewport2D>>::get_mut(&w) = Viewport2D::new(5, 5);
+
+    // Nothing to follow yet.
+    let mut follow = FollowCamera::<At>::new();
+    w.run_system(&mut follow);
+    assert_eq!(<World as GetResource<Viewport2D>>::get(&w).offset(), (0, 0));
+    <World as GetResource<Viewport2D>>::get_mut(&w).follow(player);
+    w.run_system(&mut follow);
+    assert_eq!(<World as GetResource<Viewport2D>>::get(&w).offset(), (8, 8));
+
+    let mut seen = Vec::new();
+    {
+        let (positions, viewport): (ReadComponent<At>, ReadResource<Viewport2D>) =
+            ComponentProvider::fetch(&w);
+        (&positions, viewport.on_screen(&*positions)).for_each(|e, _| seen.push(e));
+        // Disabled entities are left out by the positions, not the filter.
+        assert!(viewport.on_screen(&*positions).contains(disabled));
+    }
+    assert_eq!(seen, vec![player, near]);
+
+    // A followed entity that's gone leaves the viewport where it was.
+    w.delete_entity(player);
+    w.component_mut::<At>().get_mut(near).unwrap().0 = 40;
+    let mut v = <World as GetResource<Viewport2D>>::get(&w).clone();
+    assert!(!v.update(&*w.component::<At>()));
+    assert_eq!(v.offset(), (8, 8));
+    assert_eq!(v.following(), Some(player));
+
+    // Far away positions don't overflow.
+    let mut v = Viewport2D::new(10, 10);
+    v.center_on((i32::MIN, i32::MAX));
+    assert_eq!(v.offset(), (i32::MIN, i32::MAX - 5));
+    assert_eq!(v.world_to_screen((i32::MAX, i32::MAX)), None);
+    assert_eq!(v.world_to_screen((i32::MIN + 1, i32::MAX)), Some((1, 5)));
+    assert_eq!(v.screen_to_world((-1, 9)), (i32::MIN, i32::MAX));
+    assert_eq!(v.tiles().count(), 60);
+    v.scroll(-1, 10);
+    assert_eq!(v.offset(), (i32::MIN, i32::MAX));
+    assert_eq!(v.center(), (i32::MIN + 5, i32::MAX));
+    v.set_bounds(Some((u32::MAX, u32::MAX)));
+    assert_eq!(v.offset(), (0, i32::MAX));
+    let mut v = Viewport2D::new(u32::MAX, 10).with_bounds(1, 10);
+    assert_eq!(v.offset(), (-(i32::MAX), 0));
+    v.resize(u32::MAX, 10);
+}
+
+#[test]
+fn test_tween() {
+    use core::time::Duration;
+    use std::vec::Vec;
+
+    #[allow(dead_code)]
+    mod tween_world {
+        use crate::*;
+
+        #[derive(Default)]
+        pub struct Sprite {
+            pub offset: (f32, f32),
+        }
+
+        #[derive(Default)]
+        pub struct Lunge(pub Tween<(f32, f32)>);
+        impl AsMut<Tween<(f32, f32)>> for Lunge {
+            fn as_mut(&mut self) -> &mut Tween<(f32, f32)> {
+                &mut self.0
+            }
+        }
+
+        define_world!(
+            #[derive(Default)]
+            pub world {
+                components {
+                    sprites: BasicVecStorage<Sprite>,
+                    lunges: BasicVecStorage<Lunge>,
+                }
+                resources {
+                    frame: FrameInfo,
+                    finished: Events<TweenFinished<Lunge>>,
+                }
+            }
+        );
+    }
+    use tween_world::{Lunge, Sprite, World};
+
+    for easing in [
+        Easing::Linear,
+        Easing::QuadIn,
+        Easing::QuadOut,
+        Easing::QuadInOut,
+        Easing::CubicIn,
+        Easing::CubicOut,
+        Easing::CubicInOut,
+        Easing::BackOut,
+    ] {
+        assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+        assert!((easing.apply(1.0) - 1.0).abs() < 1e-6, "{:?}", easing);
+        assert_eq!(easing.apply(2.0), easing.apply(1.0));
+    }
+    assert_eq!(Easing::QuadInOut.apply(0.25), 0.125);
+    assert!(Easing::BackOut.apply(0.8) > 1.0);
+    assert_eq!([0.0, 10.0].lerp(&[1.0, 20.0], 0.5), [0.5, 15.0]);
+
+    let mut w = World::default();
+    // There and back again, over two turns each way.
+    let lunge = Tween::turns((0.0, 0.0), (1.0, -2.0), 2);
+    let attacker = w
+        .new_entity()
+        .with(Sprite::default())
+        .with(Lunge(lunge))
+        .build();
+    // A tween without anything to animate is left alone.
+    let loner = w
+        .new_entity()
+        .with(Lunge(Tween::turns((0.0, 0.0), (1.0, 1.0), 1)))
+        .build();
+    w.new_entity().with(Sprite { offset: (5.0, 5.0) }).build();
+    w.new_entity()
+        .with(Sprite { offset: (5.0, 5.0) })
+        .with(Lunge::default())
+        .build();
+
+    let run = RunTweens::<Lunge, _, _>::new(|s: &mut Sprite| &mut s.offset);
+    let mut dispatcher = Dispatcher::new().with(&mut w, run);
+    let offsets = |w: &World| -> Vec<(f32, f32)> {
+        w.component::<Sprite>()
+            .iter()
+            .flatten()
+            .map(|s| s.offset)
+            .collect()
+    };
+    let finished = |w: &World| -> Vec<Entity> {
+        <World as GetResource<Events<TweenFinished<Lunge>>>>::get_mut(w)
+            .drain()
+            .map(|f| f.entity)
+            .collect()
+    };
+
+    dispatcher.run_frame(&mut w, Duration::ZERO).unwrap();
+    assert_eq!(offsets(&w), vec![(0.5, -1.0), (5.0, 5.0), (5.0, 5.0)]);
+    assert!(finished(&w).is_empty());
+    // Running again in the same frame doesn't advance anything.
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(offsets(&w)[0], (0.5, -1.0));
+
+    dispatcher.run_frame(&mut w, Duration::ZERO).unwrap();
+    assert_eq!(offsets(&w)[0], (1.0, -2.0));
+    assert_eq!(finished(&w), vec![attacker]);
+    assert!(!w.component::<Lunge>().get(loner).unwrap().0.is_finished());
+
+    // Finished tweens stay put, and report once.
+    w.component_mut::<Sprite>()
+        .insert(attacker, Sprite { offset: (9.0, 9.0) });
+    dispatcher.run_frame(&mut w, Duration::ZERO).unwrap();
+    assert_eq!(offsets(&w)[0], (9.0, 9.0));
+    assert!(finished(&w).is_empty());
+
+    if let Some(lunge) = w.component_mut::<Lunge>().get_mut(attacker) {
+        lunge.0.reverse();
+    }
+    dispatcher.run_frame(&mut w, Duration::ZERO).unwrap();
+    dispatcher.run_frame(&mut w, Duration::ZERO).unwrap();
+    assert_eq!(offsets(&w)[0], (0.0, 0.0));
+    assert_eq!(finished(&w), vec![attacker]);
+    assert_eq!(offsets(&w)[1], (5.0, 5.0));
+
+    // Timed tweens follow the frame's delta.
+    let mut fade = Tween::time(0.0f64, 1.0, Duration::from_secs(2)).with_easing(Easing::QuadIn);
+    fade.tick(1, Duration::from_secs(1));
+    assert_eq!((fade.progress(), fade.value()), (0.5, 0.25));
+    fade.tick(1, Duration::from_secs(5));
+    assert!(fade.is_finished());
+    assert_eq!(fade.value(), 1.0);
+
+    // Frames from before the system was added don't count, but ones it missed later do, for as
+    // long as they took.
+    let mut w = World::default();
+    let advance = |w: &World, delta: Duration| {
+        <World as GetResource<FrameInfo>>::get_mut(w).advance(delta);
+    };
+    for _ in 0..3 {
+        advance(&w, Duration::from_secs(1));
+    }
+    let slide = Tween::time((0.0, 0.0), (8.0, 0.0), Duration::from_secs(8));
+    w.new_entity()
+        .with(Sprite::default())
+        .with(Lunge(slide))
+        .build();
+    let run = RunTweens::<Lunge, _, _>::new(|s: &mut Sprite| &mut s.offset);
+    let mut dispatcher = Dispatcher::new().with(&mut w, run);
+    dispatcher.run(&mut w).unwrap();
+    assert_eq!(offsets(&w), vec![(0.0, 0.0)]);
+    advance(&w, Duration::from_secs(1));
+    advance(&w, Duration::from_secs(2));
+    dispatcher
+        .run_frame(&mut w, Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(offsets(&w), vec![(4.0, 0.0)]);
+
+    // Without a dispatcher, the first run only notes the frame.
+    let mut run = RunTweens::<Lunge, _, _>::new(|s: &mut Sprite| &mut s.offset);
+    w.run_system(&mut run);
+    assert_eq!(offsets(&w), vec![(4.0, 0.0)]);
+    advance(&w, Duration::from_secs(2));
+    w.run_system(&mut run);
+    assert_eq!(offsets(&w), vec![(6.0, 0.0)]);
+}
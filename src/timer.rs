@@ -0,0 +1,319 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timers and cooldowns.
+//!
+//! A [`Timer`](struct.Timer.html) counts down a length of time or a number of turns. Timers are
+//! wrapped in components of the game's own, like `Poisoned(Timer)` and `Stunned(Timer)`, so that
+//! an entity can have several independent ones; the components implement `AsMut<Timer>`. The
+//! [`TickTimers<C>`](struct.TickTimers.html) system counts the timer in every `C` down once per
+//! frame, using the world's [`FrameInfo`](../frame/struct.FrameInfo.html): timers measured in time
+//! by the frame's `delta`, and timers measured in turns by one. It lists the entities whose timers
+//! ran out in the [`Expired<C>`](struct.Expired.html) resource, for the systems that run after it
+//! to act on.
+//!
+//! A cooldown (see [`Timer::cooldown`](struct.Timer.html#method.cooldown)) is a timer that starts
+//! out finished, and is restarted when the thing it limits is used.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Poisoned(Timer);
+//! impl AsMut<Timer> for Poisoned {
+//!     fn as_mut(&mut self) -> &mut Timer {
+//!         &mut self.0
+//!     }
+//! }
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             poison: BasicVecStorage<Poisoned>,
+//!         }
+//!         resources {
+//!             frame: FrameInfo,
+//!             cured: Expired<Poisoned>,
+//!         }
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let e = w.new_entity().with(Poisoned(Timer::turns(3))).build();
+//! let mut dispatcher = Dispatcher::new().with(&mut w, TickTimers::<Poisoned>::new());
+//! for turn in 1..=3 {
+//!     dispatcher.run_frame(&mut w, Default::default()).unwrap();
+//!     let cured = <World as GetResource<Expired<Poisoned>>>::get(&w);
+//!     assert_eq!(cured.contains(e), turn == 3);
+//! }
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use core::time::Duration;
+
+use crate::*;
+
+/// An amount of time or a number of turns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Span {
+    /// Time, counted down by each frame's `delta`.
+    Time(Duration),
+    /// Turns, counted down by one each frame.
+    Turns(u64),
+}
+
+impl Span {
+    /// Whether nothing is left.
+    pub fn is_zero(self) -> bool {
+        match self {
+            Span::Time(d) => d.is_zero(),
+            Span::Turns(n) => n == 0,
+        }
+    }
+}
+
+/// Counts down to zero. See the [module-level documentation](index.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Timer {
+    length: Span,
+    remaining: Span,
+    repeating: bool,
+}
+
+impl Timer {
+    /// A timer that runs out after `length`.
+    pub fn new(length: Span) -> Self {
+        Timer {
+            length,
+            remaining: length,
+            repeating: false,
+        }
+    }
+
+    /// A timer that runs out after `length` of time.
+    pub fn time(length: Duration) -> Self {
+        Timer::new(Span::Time(length))
+    }
+
+    /// A timer that runs out after `n` turns.
+    pub fn turns(n: u64) -> Self {
+        Timer::new(Span::Turns(n))
+    }
+
+    /// A cooldown of `length`, for limiting how often something can happen: it's ready straight
+    /// away, and is `restart`ed when the thing happens.
+    pub fn cooldown(length: Span) -> Self {
+        let mut timer = Timer::new(length);
+        timer.finish();
+        timer
+    }
+
+    /// Make the timer start again each time it runs out, builder style.
+    pub fn repeating(mut self) -> Self {
+        self.repeating = true;
+        self
+    }
+
+    /// How long the timer runs for.
+    pub fn length(&self) -> Span {
+        self.length
+    }
+
+    /// How much is left before the timer runs out.
+    pub fn remaining(&self) -> Span {
+        self.remaining
+    }
+
+    /// Whether the timer has run out. Repeating timers never have.
+    pub fn is_finished(&self) -> bool {
+        self.remaining.is_zero()
+    }
+
+    /// Whether a cooldown has run out, so the thing it limits can happen again.
+    pub fn ready(&self) -> bool {
+        self.is_finished()
+    }
+
+    /// Start the timer from the beginning.
+    pub fn restart(&mut self) {
+        self.remaining = self.length;
+    }
+
+    /// Run the timer out without it counting as expiring.
+    pub fn finish(&mut self) {
+        self.remaining = match self.length {
+            Span::Time(_) => Span::Time(Duration::ZERO),
+            Span::Turns(_) => Span::Turns(0),
+        };
+    }
+
+    /// Count the timer down by `turns` if it's measured in turns, or by `elapsed` if it's
+    /// measured in time, and return the number of times it ran out. A finished timer doesn't run
+    /// out again until it's restarted; a repeating one can run out several times at once if it's
+    /// counted down by more than its length.
+    pub fn tick(&mut self, turns: u64, elapsed: Duration) -> u32 {
+        if self.is_finished() {
+            return 0;
+        }
+        // How far past zero the timer went, if it ran out.
+        let over = match self.remaining {
+            Span::Time(left) => {
+                self.remaining = Span::Time(left.saturating_sub(elapsed));
+                elapsed.checked_sub(left).map(Span::Time)
+            }
+            Span::Turns(left) => {
+                self.remaining = Span::Turns(left.saturating_sub(turns));
+                turns.checked_sub(left).map(Span::Turns)
+            }
+        };
+        let over = match over {
+            Some(over) => over,
+            None => return 0,
+        };
+        if !self.repeating {
+            return 1;
+        }
+        // Carry whatever was left over into the next round, so repeating timers don't drift. The
+        // length can't be zero, since the timer wasn't already finished.
+        let (rounds, rest) = match (self.length, over) {
+            (Span::Time(length), Span::Time(over)) => {
+                let (length, over) = (length.as_nanos(), over.as_nanos());
+                let rest = Duration::from_nanos((length - over % length) as u64);
+                (over / length, Span::Time(rest))
+            }
+            (Span::Turns(length), Span::Turns(over)) => (
+                u128::from(over / length),
+                Span::Turns(length - over % length),
+            ),
+            _ => unreachable!("a timer's length and remaining span are the same kind"),
+        };
+        self.remaining = rest;
+        1 + rounds.min(u128::from(u32::MAX - 1)) as u32
+    }
+}
+
+/// A finished timer of no length, which is what `ComponentSet`s default to.
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::turns(0)
+    }
+}
+
+/// Resource listing the entities whose timer in their `C` component ran out during the last run
+/// of `TickTimers<C>`.
+pub struct Expired<C> {
+    entities: Vec<Entity>,
+    tag: PhantomData<fn() -> C>,
+}
+
+impl<C> Default for Expired<C> {
+    fn default() -> Self {
+        Expired {
+            entities: Vec::new(),
+            tag: PhantomData,
+        }
+    }
+}
+
+impl<C> fmt::Debug for Expired<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.entities.iter()).finish()
+    }
+}
+
+impl<C> Expired<C> {
+    /// The entities, in id order. An entity whose repeating timer ran out several times is
+    /// listed once for each.
+    pub fn iter(&self) -> core::slice::Iter<'_, Entity> {
+        self.entities.iter()
+    }
+
+    /// Whether `entity`'s timer ran out.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains(&entity)
+    }
+
+    /// The number of times timers ran out.
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Whether no timers ran out.
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+/// System that counts down the timer in every `C` component at the start of each frame, and lists
+/// the ones that ran out in `Expired<C>`. Running it again without starting a new frame (with
+/// `Dispatcher::run_frame`) does nothing, and leaves `Expired<C>` as it was.
+///
+/// Frames that went by before the system was added to a dispatcher, or before it first ran if it
+/// never was, aren't counted: then it only notes the current frame.
+pub struct TickTimers<C> {
+    // The frame the timers were last counted down in, or `None` before the system has seen one.
+    last_tick: Option<u64>,
+    tag: PhantomData<fn() -> C>,
+}
+
+impl<C> TickTimers<C> {
+    /// Create the system.
+    pub fn new() -> Self {
+        TickTimers {
+            last_tick: None,
+            tag: PhantomData,
+        }
+    }
+}
+
+impl<C> Default for TickTimers<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, C> System<'a> for TickTimers<C>
+where
+    C: 'static + AsMut<Timer> + StorageSpec<'a, Component = C>,
+    C::Storage: MutableComponentStorage<'a> + 'a,
+{
+    type Dependencies = (
+        ReadResource<'a, FrameInfo>,
+        WriteComponent<'a, C>,
+        WriteResource<'a, Expired<C>>,
+    );
+    fn run(&'a mut self, (frame, mut timers, mut expired): Self::Dependencies) {
+        let turns = match self.last_tick.replace(frame.tick()) {
+            Some(last) => frame.tick().saturating_sub(last),
+            None => 0,
+        };
+        if turns == 0 {
+            return;
+        }
+        expired.entities.clear();
+        (&mut timers,).for_each(|e, (timer,)| {
+            for _ in 0..timer.as_mut().tick(turns, frame.delta()) {
+                expired.entities.push(e);
+            }
+        });
+    }
+    fn setup(&mut self, (frame, _, _): Self::Dependencies) {
+        self.last_tick = Some(frame.tick());
+    }
+}
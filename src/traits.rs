@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use alloc::vec::Vec;
+
 use crate::*;
 
 /// Trait that allows us to convert flat tuple types to nested tuple types (e.g.,
@@ -20,7 +22,46 @@ use crate::*;
 /// Also provides an associated function to convert a nested tuple **by value** to the equivalent
 /// flat tuple.
 ///
-/// This trait is provided for tuples up to length 32.
+/// This trait is provided for tuples up to length 32. A system that needs more dependencies than
+/// that can put some of them in a `Group`, which takes up a single entry but holds a tuple of its
+/// own:
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// # #[derive(Default)]
+/// # pub struct Position(u32);
+/// # #[derive(Default)]
+/// # pub struct Velocity(u32);
+/// # define_world!(
+/// #     #[derive(Default)]
+/// #     pub world {
+/// #         components {
+/// #             position: BasicVecStorage<Position>,
+/// #             velocity: BasicVecStorage<Velocity>,
+/// #         }
+/// #         resources {
+/// #             frame: u64,
+/// #         }
+/// #     }
+/// # );
+/// struct Physics;
+/// impl<'a> System<'a> for Physics {
+///     type Dependencies = (
+///         Group<(WriteComponent<'a, Position>, ReadComponent<'a, Velocity>)>,
+///         ReadResource<'a, u64>,
+///     );
+///     fn run(&'a mut self, (Group((mut position, velocity)), frame): Self::Dependencies) {
+///         (&mut position, &velocity).for_each(|_, (p, v)| p.0 += v.0 * *frame as u32);
+///     }
+/// }
+///
+/// let mut w = World::default();
+/// w.new_entity().with(Position(1)).with(Velocity(2)).build();
+/// <World as GetResource<u64>>::set(&w, 3);
+/// w.run_system(&mut Physics);
+/// assert_eq!(<World as GetComponent<'_, Position>>::get(&w).get(Entity::new(0)).unwrap().0, 7);
+/// ```
 pub trait Nest: private::Sealed {
     /// Equivalent nested tuple type.
     type Nested;
@@ -51,13 +92,9 @@ macro_rules! unnest {
 impl Nest for () {
     type Nested = ();
     #[inline]
-    fn flatten(_v: ()) -> () {
-        ()
-    }
+    fn flatten(_v: ()) {}
     #[inline]
-    fn nest(self) -> () {
-        ()
-    }
+    fn nest(self) {}
 }
 
 macro_rules! nest {
@@ -132,12 +169,22 @@ impl_nested!(
 );
 
 /// Internal version of `ComponentProvider` that is implemented for nested tuples.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't provide a system's dependencies",
+    note = "every component and resource a system depends on must be declared in the world's \
+            `define_world!`"
+)]
 pub trait ComponentProviderRec<'a, T> {
     /// Get the components.
     fn fetch(&'a self) -> T;
 }
 
 /// Component provider for flat tuples.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't provide `{T}`",
+    note = "every component and resource in `{T}` must be declared in the world's \
+            `define_world!`, and there can be at most 32 of them (see `Group`)"
+)]
 pub trait ComponentProvider<'a, T: Nest> {
     /// Get the components.
     fn fetch(&'a self) -> T;
@@ -162,9 +209,12 @@ where
 {
     #[inline]
     fn fetch(&'a self) -> (ReadComponent<'a, H>, T) {
+        self.access_stats()
+            .record(AccessedType::component::<H>(), Usage::Read);
         (
             ReadComponent {
                 storage: <Self as GetComponent<'a, H>>::get(self),
+                disabled: Some(self.disabled()),
             },
             <Self as ComponentProviderRec<T>>::fetch(self),
         )
@@ -178,6 +228,8 @@ where
 {
     #[inline]
     fn fetch(&'a self) -> (ReadResource<'a, H>, T) {
+        self.access_stats()
+            .record(AccessedType::resource::<H>(), Usage::Read);
         (
             ReadResource {
                 resource: <Self as GetResource<H>>::get(self),
@@ -194,9 +246,13 @@ where
 {
     #[inline]
     fn fetch(&'a self) -> (WriteResource<'a, H>, T) {
+        let probe = self
+            .access_stats()
+            .record(AccessedType::resource::<H>(), Usage::Write);
         (
             WriteResource {
                 resource: <Self as GetResource<H>>::get_mut(self),
+                probe,
             },
             <Self as ComponentProviderRec<T>>::fetch(self),
         )
@@ -211,30 +267,126 @@ where
 {
     #[inline]
     fn fetch(&'a self) -> (WriteComponent<'a, H>, T) {
+        let probe = self
+            .access_stats()
+            .record(AccessedType::component::<H>(), Usage::Write);
+        let (storage, stamp) = <Self as GetComponent<'a, H>>::get_mut_unstamped(self);
         (
             WriteComponent {
-                storage: <Self as GetComponent<'a, H>>::get_mut(self),
+                storage,
+                probe,
+                stamp,
+                disabled: Some(self.disabled()),
             },
             <Self as ComponentProviderRec<T>>::fetch(self),
         )
     }
 }
 
-impl<'a, WD> ComponentProviderRec<'a, ()> for WD {
+impl<'a, H, T, WD> ComponentProviderRec<'a, (RestrictedWriteComponent<'a, H>, T)> for WD
+where
+    H: 'a + StorageSpec<'a>,
+    H::Storage: ComponentStorage<'a>,
+    WD: WorldInterface<'a> + ComponentProviderRec<'a, T> + GetComponent<'a, H>,
+{
     #[inline]
-    fn fetch(&'a self) -> () {
-        ()
+    fn fetch(&'a self) -> (RestrictedWriteComponent<'a, H>, T) {
+        let probe = self
+            .access_stats()
+            .record(AccessedType::component::<H>(), Usage::RestrictedWrite);
+        let (storage, stamp) = <Self as GetComponent<'a, H>>::get_mut_unstamped(self);
+        (
+            RestrictedWriteComponent {
+                storage,
+                probe,
+                stamp,
+                disabled: Some(self.disabled()),
+            },
+            <Self as ComponentProviderRec<T>>::fetch(self),
+        )
+    }
+}
+
+impl<'a, H, T, WD> ComponentProviderRec<'a, (Local<'a, H>, T)> for WD
+where
+    H: 'static + Default,
+    WD: WorldInterface<'a> + ComponentProviderRec<'a, T>,
+{
+    #[inline]
+    fn fetch(&'a self) -> (Local<'a, H>, T) {
+        (
+            self.locals().fetch(),
+            <Self as ComponentProviderRec<T>>::fetch(self),
+        )
+    }
+}
+
+impl<'a, T, WD> ComponentProviderRec<'a, (Deferred<'a, WD>, T)> for WD
+where
+    WD: WorldInterface<'a> + ComponentProviderRec<'a, T>,
+{
+    #[inline]
+    fn fetch(&'a self) -> (Deferred<'a, WD>, T) {
+        (
+            Deferred {
+                commands: self.deferred(),
+            },
+            <Self as ComponentProviderRec<T>>::fetch(self),
+        )
     }
 }
 
+impl<'a, T, WD> ComponentProviderRec<'a, (Tasks, T)> for WD
+where
+    WD: WorldInterface<'a> + ComponentProviderRec<'a, T>,
+{
+    #[inline]
+    fn fetch(&'a self) -> (Tasks, T) {
+        (
+            Tasks {
+                executor: crate::executor::current(),
+            },
+            <Self as ComponentProviderRec<T>>::fetch(self),
+        )
+    }
+}
+
+/// A group of dependencies, taking up a single entry in a system's dependency tuple. Since a
+/// group can hold as many dependencies as a flat tuple can, including other groups, grouping them
+/// lets a system have more dependencies than `Nest` is implemented for. See `Nest`.
+#[derive(Debug)]
+pub struct Group<T>(pub T);
+
+impl<'a, G, T, WD> ComponentProviderRec<'a, (Group<G>, T)> for WD
+where
+    G: Nest,
+    WD: WorldInterface<'a> + ComponentProviderRec<'a, G::Nested> + ComponentProviderRec<'a, T>,
+{
+    #[inline]
+    fn fetch(&'a self) -> (Group<G>, T) {
+        (
+            Group(<Self as ComponentProvider<'a, G>>::fetch(self)),
+            <Self as ComponentProviderRec<T>>::fetch(self),
+        )
+    }
+}
+
+impl<'a, WD> ComponentProviderRec<'a, ()> for WD {
+    #[inline]
+    fn fetch(&'a self) {}
+}
+
 impl<'a, WD, T> ComponentProviderRec<'a, ReadComponent<'a, T>> for WD
 where
     T: 'a + StorageSpec<'a>,
     WD: WorldInterface<'a> + GetComponent<'a, T>,
 {
-    fn fetch(&'a self) -> ReadComponent<T> {
+    fn fetch(&'a self) -> ReadComponent<'a, T> {
+        self.access_stats()
+            .record(AccessedType::component::<T>(), Usage::Read);
         ReadComponent {
             storage: <Self as GetComponent<'a, T>>::get(self),
+            disabled: Some(self.disabled()),
         }
     }
 }
@@ -244,9 +396,16 @@ where
     T: 'a + StorageSpec<'a>,
     WD: WorldInterface<'a> + GetComponent<'a, T>,
 {
-    fn fetch(&'a self) -> WriteComponent<T> {
+    fn fetch(&'a self) -> WriteComponent<'a, T> {
+        let probe = self
+            .access_stats()
+            .record(AccessedType::component::<T>(), Usage::Write);
+        let (storage, stamp) = <Self as GetComponent<'a, T>>::get_mut_unstamped(self);
         WriteComponent {
-            storage: <Self as GetComponent<'a, T>>::get_mut(self),
+            storage,
+            probe,
+            stamp,
+            disabled: Some(self.disabled()),
         }
     }
 }
@@ -257,10 +416,85 @@ pub trait System<'a> {
     type Dependencies: Nest; // +IntoTypeList;
     /// Run the system.
     fn run(&'a mut self, dependencies: Self::Dependencies);
+    /// Prepare the world for the system, e.g. by giving the resources it writes their initial
+    /// values. `Dispatcher::add` calls this once, when the system is added. Changes queued with a
+    /// `Deferred` dependency are applied as soon as it returns, so it can also spawn entities and
+    /// insert components. The default implementation does nothing.
+    fn setup(&mut self, _dependencies: Self::Dependencies) {}
+}
+
+/// Trait for systems that can fail.
+///
+/// Every `System` is also a `TrySystem` that never fails.
+pub trait TrySystem<'a> {
+    /// The components and resources this system needs to run.
+    type Dependencies: Nest;
+    /// The error returned when the system fails.
+    type Error;
+    /// Run the system.
+    fn try_run(&'a mut self, dependencies: Self::Dependencies) -> Result<(), Self::Error>;
+    /// Prepare the world for the system. See `System::setup`.
+    fn setup(&mut self, _dependencies: Self::Dependencies) {}
+}
+
+impl<'a, S> TrySystem<'a> for S
+where
+    S: System<'a>,
+{
+    type Dependencies = S::Dependencies;
+    type Error = core::convert::Infallible;
+    #[inline]
+    fn try_run(&'a mut self, dependencies: Self::Dependencies) -> Result<(), Self::Error> {
+        self.run(dependencies);
+        Ok(())
+    }
+    #[inline]
+    fn setup(&mut self, dependencies: Self::Dependencies) {
+        <Self as System<'a>>::setup(self, dependencies)
+    }
+}
+
+/// Trait for systems that take an input and/or produce an output, so that they can be composed
+/// with [`Chain`](../dispatch/struct.Chain.html).
+///
+/// Every `TrySystem` is also an `OutputSystem` with an input of `()` whose output is its
+/// `Result`.
+pub trait OutputSystem<'a> {
+    /// The components and resources this system needs to run.
+    type Dependencies: Nest;
+    /// Extra input passed to the system when it runs.
+    type Input;
+    /// The value returned by the system.
+    type Output;
+    /// Run the system.
+    fn run_with(&'a mut self, input: Self::Input, dependencies: Self::Dependencies)
+        -> Self::Output;
+    /// Prepare the world for the system. See `System::setup`.
+    fn setup(&mut self, _dependencies: Self::Dependencies) {}
+}
+
+impl<'a, S> OutputSystem<'a> for S
+where
+    S: TrySystem<'a>,
+{
+    type Dependencies = S::Dependencies;
+    type Input = ();
+    type Output = Result<(), S::Error>;
+    #[inline]
+    fn run_with(&'a mut self, _input: (), dependencies: Self::Dependencies) -> Self::Output {
+        self.try_run(dependencies)
+    }
+    #[inline]
+    fn setup(&mut self, dependencies: Self::Dependencies) {
+        <Self as TrySystem<'a>>::setup(self, dependencies)
+    }
 }
+
 /// Output of `PureFunctionalSystem` for one component.
+#[derive(Default)]
 pub enum SystemOutput<T> {
     /// Ignore the component (neither update nor delete it).
+    #[default]
     Ignore,
     /// Delete the component if it exists.
     Delete,
@@ -268,12 +502,6 @@ pub enum SystemOutput<T> {
     Update(T),
 }
 
-impl<T> Default for SystemOutput<T> {
-    fn default() -> Self {
-        SystemOutput::Ignore
-    }
-}
-
 /// For systems that don't cause side effects or need to reason about entities or components
 /// globally, it is highly recommended that you implement `PureFunctionalSystem`, which the
 /// library will be able to automatically parallelize.
@@ -286,6 +514,25 @@ pub trait PureFunctionalSystem {
     fn process(&self, data: &Self::Inputs) -> <Self::Outputs as SystemOutputTuple>::OutputTuple;
 }
 
+/// Trait for systems that start work which completes asynchronously (loading assets, waiting on
+/// the network, ...).
+///
+/// `run` borrows its dependencies like any other system, but the future it returns must not
+/// borrow anything from the world. Instead, the future resolves to a set of
+/// [`Commands`](../command/struct.Commands.html) that are applied to the world once it completes.
+///
+/// Async systems are driven by a [`Dispatcher`](../dispatch/struct.Dispatcher.html) (see
+/// `Dispatcher::add_async`), which polls the in-flight future once per run and only calls `run`
+/// again after the previous future has completed.
+pub trait AsyncSystem<'a, W> {
+    /// The components and resources this system needs to run.
+    type Dependencies: Nest;
+    /// The future returned by `run`.
+    type Future: core::future::Future<Output = Commands<W>> + 'static;
+    /// Start the system.
+    fn run(&'a mut self, dependencies: Self::Dependencies) -> Self::Future;
+}
+
 /// Interface to the `World` struct generated via the `define_world!` macro.
 pub trait WorldInterface<'a>
 where
@@ -301,10 +548,191 @@ where
     fn new_entity(&'a mut self) -> Self::EntityBuilder;
     /// Consume an `EntityBuilder` and store its components. Under normal circumstances, this
     /// should only be called by `EntityBuilder::build()`.
+    ///
+    /// Panics if the components don't satisfy the world's `constraints`.
     fn build_entity(&mut self, c: Self::ComponentSet) -> Entity;
-    /// Delete an entity.
+    /// Build `n` entities from copies of `template`, letting `f` adjust each copy (given its
+    /// index in the batch) first. Each component storage is only borrowed once for the whole
+    /// batch.
+    ///
+    /// Panics if any of the entities don't satisfy the world's `constraints`, in which case none
+    /// of them are built.
+    fn spawn_batch<F>(&mut self, template: &Self::ComponentSet, n: usize, f: F) -> Vec<Entity>
+    where
+        Self::ComponentSet: Clone,
+        F: FnMut(usize, &mut Self::ComponentSet);
+    /// Delete an entity. Does nothing if the entity has already been deleted.
     fn delete_entity(&mut self, e: Entity);
-    /// Run a system.
+    /// Delete an entity, returning its components instead of dropping them, e.g. to build it
+    /// again in another world. Returns `None` if the entity has already been deleted.
+    ///
+    /// Only the first of a `MultiStorage` component's values is returned.
+    fn take_entity(&mut self, e: Entity) -> Option<Self::ComponentSet>;
+    /// Whether the entity exists, i.e., it has been built (or reserved) and not deleted since.
+    fn is_alive(&self, e: Entity) -> bool;
+    /// Reserve a new entity without any components, returning its handle straight away. It's
+    /// alive from now on, and its components can be added later on, e.g. by `Commands`.
+    ///
+    /// This only needs a shared borrow of the world, so the handle can be handed to an async
+    /// task or put in a network message before the entity is built, without any risk of it being
+    /// given to another entity in the meantime. The world adds the entity to its own bookkeeping
+    /// (and journal) the next time it's changed.
+    fn reserve_entity(&self) -> Entity;
+    /// Get a handle for reading the entity's components. See the
+    /// [`entity_ref`](../entity_ref/index.html) module.
+    fn entity(&self, e: Entity) -> EntityRef<'_, Self> {
+        EntityRef::new(self, e)
+    }
+    /// Get a handle for changing the entity's components. See the
+    /// [`entity_ref`](../entity_ref/index.html) module.
+    fn entity_mut(&mut self, e: Entity) -> EntityMut<'_, Self> {
+        EntityMut::new(self, e)
+    }
+    /// Move `from`'s `T` component to `to`, replacing any `T` that `to` already had. `T` doesn't
+    /// need to be `Clone`.
+    ///
+    /// Returns whether a component was moved; nothing happens if `from` doesn't have one or `to`
+    /// isn't alive.
+    fn move_component<T>(&mut self, from: Entity, to: Entity) -> bool
+    where
+        T: StorageSpec<'a>,
+        Self: GetComponent<'a, T> + Sized,
+    {
+        if from == to || !self.is_alive(to) {
+            return false;
+        }
+        match <Self as GetComponent<'a, T>>::remove(self, from) {
+            Some(value) => {
+                <Self as GetComponent<'a, T>>::insert(self, to, value);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Exchange the `T` components of `a` and `b`. If only one of them has one, it's moved to the
+    /// other. Does nothing unless both entities are alive.
+    fn swap_components<T>(&mut self, a: Entity, b: Entity)
+    where
+        T: StorageSpec<'a>,
+        Self: GetComponent<'a, T> + Sized,
+    {
+        if a == b || !self.is_alive(a) || !self.is_alive(b) {
+            return;
+        }
+        let from_a = <Self as GetComponent<'a, T>>::remove(self, a);
+        let from_b = <Self as GetComponent<'a, T>>::remove(self, b);
+        if let Some(value) = from_a {
+            <Self as GetComponent<'a, T>>::insert(self, b, value);
+        }
+        if let Some(value) = from_b {
+            <Self as GetComponent<'a, T>>::insert(self, a, value);
+        }
+    }
+    /// Replace the resource of type `T` with `value` while `f` runs, then put the original back,
+    /// even if `f` panics. Any changes `f` makes to the replacement are discarded. Overrides can
+    /// be nested, e.g. to simulate a turn as if it were raining without touching the real
+    /// weather.
+    fn with_resource_override<T, R, F>(&mut self, value: T, f: F) -> R
+    where
+        Self: GetResource<T>,
+        F: FnOnce(&mut Self) -> R,
+    {
+        let original = core::mem::replace(&mut *<Self as GetResource<T>>::get_mut(self), value);
+        let guard = RestoreResource {
+            world: self,
+            original: Some(original),
+        };
+        f(&mut *guard.world)
+    }
+    /// Delete every entity, clearing every component storage in one go rather than entity by
+    /// entity. Handles to the deleted entities stay invalid.
+    fn clear(&mut self);
+    /// Finish a frame for double-buffered storages (see `DoubleBufferedStorage`), making the
+    /// values written during the frame the ones that are read during the next, and record the
+    /// previous values of components in an `InterpolatedStorage`.
+    fn flip(&mut self);
+    /// Reserve space for at least `n` more entities, in the entity metadata and in every
+    /// component storage.
+    fn reserve(&mut self, n: usize);
+    /// Create an empty world with space for `n` entities.
+    fn with_capacity(n: usize) -> Self
+    where
+        Self: Default + Sized,
+    {
+        let mut world = Self::default();
+        world.reserve(n);
+        world
+    }
+    /// Get the storage for systems' `Local`s.
+    fn locals(&self) -> &Locals;
+    /// Send an event to `entity`, in the world's `EntityEvents<E>` resource. See the
+    /// [`observer`](../observer/index.html) module.
+    fn send_to<E>(&self, entity: Entity, event: E)
+    where
+        Self: GetResource<EntityEvents<E>>,
+    {
+        <Self as GetResource<EntityEvents<E>>>::get_mut(self).send(entity, event);
+    }
+    /// Get the buffer that systems' `Deferred` dependencies queue changes in. See the
+    /// [`command`](../command/index.html) module.
+    fn deferred(&self) -> &DeferredCommands<Self>;
+    /// Apply the changes queued by systems' `Deferred` dependencies. `Dispatcher::run` does this
+    /// once all its systems have run, and schedules at the end of each stage, but systems run
+    /// with `run_system` and the like don't have theirs applied.
+    ///
+    /// The component masks are synced first, so that the components systems added and removed
+    /// are recorded in the journal before the changes they queued.
+    fn apply_deferred(&mut self) {
+        self.sync_masks();
+        let mut commands = self.deferred().take();
+        commands.apply(self);
+    }
+    /// Get the record of which components each entity has. Storages that have been borrowed
+    /// mutably since the masks were last synced may not agree with it.
+    fn component_masks(&self) -> &ComponentMasks;
+    /// Bring the component masks up to date with the storages that have been borrowed mutably,
+    /// e.g. by systems with a `WriteComponent`, recording the components that were added or
+    /// removed through them in the journal. Changes made through the world sync the masks first.
+    fn sync_masks(&mut self);
+    /// Get the set of disabled entities. See the [`disabled`](../disabled/index.html) module.
+    fn disabled(&self) -> &Disabled;
+    /// Get the set of disabled entities mutably.
+    fn disabled_mut(&mut self) -> &mut Disabled;
+    /// Disable `entity`, so that joins skip it until it's enabled again. Its components are
+    /// kept. Does nothing if the entity isn't alive.
+    fn disable(&mut self, entity: Entity) {
+        if self.is_alive(entity) {
+            self.disabled_mut().insert(entity);
+        }
+    }
+    /// Enable a disabled entity.
+    fn enable(&mut self, entity: Entity) {
+        self.disabled_mut().remove(entity);
+    }
+    /// Whether `entity` is disabled.
+    fn is_disabled(&self, entity: Entity) -> bool {
+        self.disabled().contains(entity)
+    }
+    /// Get the entities' debug names. See the [`names`](../names/index.html) module.
+    fn debug_names(&self) -> &DebugNames;
+    /// Get the entities' debug names mutably, e.g. to name an entity after it's been built.
+    fn debug_names_mut(&mut self) -> &mut DebugNames;
+    /// Get the record of where and when each entity was created. See the
+    /// [`origins`](../origins/index.html) module.
+    fn debug_origins(&self) -> &DebugOrigins;
+    /// Get the record of where and when each entity was created mutably.
+    fn debug_origins_mut(&mut self) -> &mut DebugOrigins;
+    /// Get the counts of which components and resources each system uses. See the
+    /// [`access_stats`](../access_stats/index.html) module.
+    fn access_stats(&self) -> &AccessStats;
+    /// Get the record of which system panicked, if any. See the
+    /// [`poison`](../poison/index.html) module.
+    fn poison(&self) -> &Poison;
+    /// Whether a system has panicked while running on the world.
+    fn is_poisoned(&self) -> bool {
+        self.poison().is_poisoned()
+    }
+    /// Run a system. If it panics, the world is marked as poisoned before the panic carries on.
     fn run_system<'b, S, T /*, U, V*/>(&'a mut self, system: &'b mut S)
     where
         S: System<'b, Dependencies = T>,
@@ -313,16 +741,171 @@ where
         //Self::AvailableTypes: typelist::ConsumeMultiple<U, V>,
         Self: ComponentProviderRec<'a, T::Nested>,
     {
-        system.run(<Self as ComponentProvider<'a, T>>::fetch(self));
+        let world: &'a Self = self;
+        let _instance = world.locals().ad_hoc();
+        world
+            .poison()
+            .guard::<S, _, _>(move || system.run(world.fetch_for::<S, T>()))
+    }
+    /// Run a system that can fail, returning its error (if any).
+    fn try_run_system<'b, S, T>(&'a mut self, system: &'b mut S) -> Result<(), S::Error>
+    where
+        S: TrySystem<'b, Dependencies = T>,
+        T: Nest,
+        Self: ComponentProviderRec<'a, T::Nested>,
+    {
+        let world: &'a Self = self;
+        let _instance = world.locals().ad_hoc();
+        world
+            .poison()
+            .guard::<S, _, _>(move || system.try_run(world.fetch_for::<S, T>()))
+    }
+    /// Run a system with the given input, returning its output.
+    fn run_output_system<'b, S, T>(&'a mut self, system: &'b mut S, input: S::Input) -> S::Output
+    where
+        S: OutputSystem<'b, Dependencies = T>,
+        T: Nest,
+        Self: ComponentProviderRec<'a, T::Nested>,
+    {
+        let world: &'a Self = self;
+        let _instance = world.locals().ad_hoc();
+        world
+            .poison()
+            .guard::<S, _, _>(move || system.run_with(input, world.fetch_for::<S, T>()))
+    }
+    /// Fetch the dependencies `T` on behalf of the system `S`. This is what the `run_*` methods
+    /// use to get the arguments they pass to systems; you should only need to call it if you are
+    /// implementing a new way of running systems.
+    fn fetch_for<S, T>(&'a self) -> T
+    where
+        T: Nest,
+        Self: ComponentProviderRec<'a, T::Nested>,
+    {
+        // Left by dropping the guard, so a conflicting borrow panicking in `fetch` doesn't leave
+        // `S`'s locals and access counts in use.
+        let _fetching = Fetching {
+            previous: self.locals().enter(),
+            counted: self.access_stats().enter::<S>(),
+            locals: self.locals(),
+            access_stats: self.access_stats(),
+        };
+        <Self as ComponentProvider<'a, T>>::fetch(self)
+    }
+}
+
+// Undoes `enter` on the world's locals and access stats when dropped, for
+// `WorldInterface::fetch_for`.
+struct Fetching<'w> {
+    locals: &'w Locals,
+    previous: Option<(SystemId, usize)>,
+    access_stats: &'w AccessStats,
+    counted: Option<usize>,
+}
+
+impl<'w> Drop for Fetching<'w> {
+    fn drop(&mut self) {
+        self.access_stats.leave(self.counted);
+        self.locals.leave(self.previous);
+    }
+}
+
+// Puts a resource back when dropped, for `WorldInterface::with_resource_override`.
+struct RestoreResource<'w, W: GetResource<T>, T> {
+    world: &'w mut W,
+    original: Option<T>,
+}
+
+impl<'w, W: GetResource<T>, T> Drop for RestoreResource<'w, W, T> {
+    fn drop(&mut self) {
+        if let Some(original) = self.original.take() {
+            <W as GetResource<T>>::set(self.world, original);
+        }
     }
 }
 
 /// Trait implemented by `EntityBuilder` types.
+///
+/// Builders keep track of which components they've been given in their type, and only implement
+/// `BuildWith<T>` until `T` has been provided, so giving an entity the same component twice is a
+/// compile-time error:
+///
+/// ```compile_fail
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::*;
+/// # #[derive(Default)]
+/// # pub struct Health(u32);
+/// # define_world!(
+/// #     #[derive(Default)]
+/// #     pub world {
+/// #         components {
+/// #             health: BasicVecStorage<Health>,
+/// #         }
+/// #         resources {}
+/// #     }
+/// # );
+/// let mut w = World::default();
+/// w.new_entity().with(Health(3)).with(Health(5)).build();
+/// ```
+#[diagnostic::on_unimplemented(
+    message = "`{T}` can't be added to this entity",
+    label = "`{T}` is not a component of this world",
+    note = "add a storage for `{T}` to the `components` block of the world's `define_world!`"
+)]
 pub trait BuildWith<T> {
+    /// The builder after `T` has been provided.
+    type Output;
+    /// Set the component of type `T`, or leave it unset if `data` is `None`. Either way, `T`
+    /// counts as provided.
+    fn with_option(self, data: Option<T>) -> Self::Output;
     /// Set the component of type `T`.
-    fn with(self, data: T) -> Self;
+    fn with(self, data: T) -> Self::Output
+    where
+        Self: Sized,
+    {
+        self.with_option(Some(data))
+    }
 }
 
+/// Error returned when an entity's components don't satisfy the world's `constraints`. See
+/// [`define_world!`](../macro.define_world.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingComponent {
+    /// The type name of the missing component.
+    pub component: &'static str,
+    /// The type name of the component that requires it.
+    pub required_by: &'static str,
+}
+
+impl MissingComponent {
+    #[doc(hidden)]
+    pub fn type_name_of<T>(_: &Option<T>) -> &'static str {
+        core::any::type_name::<T>()
+    }
+
+    #[doc(hidden)]
+    pub fn type_name_of_field<S, T>(_: fn(&S) -> &Option<T>) -> &'static str {
+        core::any::type_name::<T>()
+    }
+}
+
+impl core::fmt::Display for MissingComponent {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "an entity with a `{}` must also have a `{}`",
+            self.required_by, self.component
+        )
+    }
+}
+
+impl core::error::Error for MissingComponent {}
+
+/// `EntityBuilder` state marker for a component that has been provided.
+pub enum Provided {}
+
+/// `EntityBuilder` state marker for a component that hasn't been provided yet.
+pub enum Unprovided {}
+
 /// Get the `Resources` struct from a world generically.
 pub trait ResourceProvider {
     /// The `Resources` struct type.
@@ -332,21 +915,63 @@ pub trait ResourceProvider {
 }
 
 /// Indicates that the implementor stores components of type `T`.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no `{T}` component",
+    label = "`{T}` is not a component of `{Self}`",
+    note = "add a storage for `{T}` to the `components` block of the world's `define_world!`"
+)]
 pub trait GetComponent<'a, T: StorageSpec<'a>> {
     /// Get the storage.
-    fn get(&self) -> std::cell::Ref<T::Storage>;
+    fn get(&self) -> crate::cell::Ref<'_, T::Storage>;
     /// Get the storage mutably.
-    fn get_mut(&self) -> std::cell::RefMut<T::Storage>;
+    fn get_mut(&self) -> crate::cell::RefMut<'_, T::Storage>;
+    /// Get the storage mutably without marking it as changed, along with a `ChangeStamp` that
+    /// marks it once it's written to. This is how `WriteComponent`s are fetched.
+    ///
+    /// The default marks the storage as changed straight away, with `get_mut`.
+    fn get_mut_unstamped(&self) -> (crate::cell::RefMut<'_, T::Storage>, ChangeStamp<'_>) {
+        (self.get_mut(), ChangeStamp::default())
+    }
+    /// Give a live entity the component, returning the one it replaces, if any. Does nothing if
+    /// the entity isn't alive.
+    ///
+    /// Unlike setting it through the storage, this keeps the world's component masks and journal
+    /// up to date, and panics if the entity lacks a component that the world's `constraints` say
+    /// this one requires.
+    fn insert(&mut self, entity: Entity, value: T::Component) -> Option<T::Component>;
+    /// Remove the entity's component and return it, if it has one. If the storage can't move
+    /// components out (see `ComponentStorage::take`), it's left in place and `None` is returned.
+    ///
+    /// Panics, leaving the component in place, if another of the entity's components requires it
+    /// according to the world's `constraints`.
+    fn remove(&mut self, entity: Entity) -> Option<T::Component>;
+    /// The world change tick at which the storage was last borrowed mutably (or written to, through
+    /// a `WriteComponent`) or had components added or removed by the world. See the [`change`](../change/index.html) module.
+    ///
+    /// The default returns 0, as if the storage had never changed, for implementations that
+    /// don't track changes. `Cached` values derived from it then aren't recomputed when it
+    /// changes, only when they're invalidated or another input changes.
+    fn change_tick(&self) -> u64 {
+        0
+    }
 }
 
 /// Indicates that the implementor stores a resource of type `T`.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no `{T}` resource",
+    label = "`{T}` is not a resource of `{Self}`",
+    note = "add `{T}` to the `resources` block of the world's `define_world!`"
+)]
 pub trait GetResource<T> {
     /// Get the resource.
-    fn get(&self) -> std::cell::Ref<T>;
-    /// Get the resource mutably.
-    fn get_mut(&self) -> std::cell::RefMut<T>;
+    fn get(&self) -> crate::cell::Ref<'_, T>;
+    /// Get the resource mutably. This marks the resource as changed.
+    fn get_mut(&self) -> crate::cell::RefMut<'_, T>;
     /// Set the resource.
     fn set(&self, t: T);
+    /// The world change tick at which the resource was last borrowed mutably. See the
+    /// [`change`](../change/index.html) module.
+    fn change_tick(&self) -> u64;
 }
 
 mod private {
@@ -0,0 +1,412 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Animating a field of a component from one value to another.
+//!
+//! A [`Tween<V>`](struct.Tween.html) goes from a start value to an end value over a `Span` of time
+//! or turns, following an [`Easing`](enum.Easing.html) curve. Like timers, tweens are wrapped in
+//! components of the game's own, like `Slide(Tween<(f32, f32)>)`, which implement
+//! `AsMut<Tween<V>>`, so that an entity can have several animations going at once.
+//!
+//! The [`RunTweens<C, T, V>`](struct.RunTweens.html) system advances the tween in every `C`
+//! component once per frame, using the world's [`FrameInfo`](../frame/struct.FrameInfo.html), and
+//! writes its current value into a field of the same entity's `T` component, picked out by a
+//! function given to the system. When a tween reaches its end, the system sends a
+//! [`TweenFinished<C>`](struct.TweenFinished.html) event to the `Events<TweenFinished<C>>`
+//! resource, for an observer to remove the component, chain another animation, or start the
+//! attack the lunge was for. Finished tweens leave their field alone, so other systems can take
+//! it over.
+//!
+//! Values implement [`Lerp`](trait.Lerp.html), which is implemented for `f32`, `f64`, pairs and
+//! triples of them, and arrays of `f32`.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # use std::time::Duration;
+//! #[derive(Default)]
+//! pub struct Panel {
+//!     x: f32,
+//! }
+//!
+//! #[derive(Default)]
+//! pub struct SlideIn(Tween<f32>);
+//! impl AsMut<Tween<f32>> for SlideIn {
+//!     fn as_mut(&mut self) -> &mut Tween<f32> {
+//!         &mut self.0
+//!     }
+//! }
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             panels: BasicVecStorage<Panel>,
+//!             slides: BasicVecStorage<SlideIn>,
+//!         }
+//!         resources {
+//!             frame: FrameInfo,
+//!             finished: Events<TweenFinished<SlideIn>>,
+//!         }
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let slide = Tween::time(-100.0, 0.0, Duration::from_millis(400))
+//!     .with_easing(Easing::CubicOut);
+//! let panel = w.new_entity().with(Panel { x: -100.0 }).with(SlideIn(slide)).build();
+//! let mut dispatcher = Dispatcher::new().with(
+//!     &mut w,
+//!     RunTweens::<SlideIn, _, _>::new(|p: &mut Panel| &mut p.x),
+//! );
+//!
+//! dispatcher.run_frame(&mut w, Duration::from_millis(200)).unwrap();
+//! // Halfway through, but eased out, so well over halfway there.
+//! assert_eq!(w.component::<Panel>().get(panel).unwrap().x, -12.5);
+//! dispatcher.run_frame(&mut w, Duration::from_millis(200)).unwrap();
+//! assert_eq!(w.component::<Panel>().get(panel).unwrap().x, 0.0);
+//!
+//! let finished = <World as GetResource<Events<TweenFinished<SlideIn>>>>::get(&w);
+//! assert_eq!(finished.iter().map(|f| f.entity).collect::<Vec<_>>(), vec![panel]);
+//! ```
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::time::Duration;
+
+use crate::*;
+
+/// Values that can be interpolated between. See the [module-level documentation](index.html).
+pub trait Lerp: Clone {
+    /// The value `t` of the way from `self` to `to`, where `t` is usually between 0 and 1 (but
+    /// can be outside that for easings that overshoot).
+    fn lerp(&self, to: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Lerp for f64 {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        self + (to - self) * f64::from(t)
+    }
+}
+
+impl<A: Lerp, B: Lerp> Lerp for (A, B) {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        (self.0.lerp(&to.0, t), self.1.lerp(&to.1, t))
+    }
+}
+
+impl<A: Lerp, B: Lerp, C: Lerp> Lerp for (A, B, C) {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        (
+            self.0.lerp(&to.0, t),
+            self.1.lerp(&to.1, t),
+            self.2.lerp(&to.2, t),
+        )
+    }
+}
+
+impl<const N: usize> Lerp for [f32; N] {
+    fn lerp(&self, to: &Self, t: f32) -> Self {
+        let mut out = *self;
+        for (x, y) in out.iter_mut().zip(to) {
+            *x = x.lerp(y, t);
+        }
+        out
+    }
+}
+
+/// How a tween's progress maps to how far its value is from the start to the end.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Easing {
+    /// At a constant speed.
+    #[default]
+    Linear,
+    /// Speeding up from the start.
+    QuadIn,
+    /// Slowing down towards the end.
+    QuadOut,
+    /// Speeding up, then slowing down.
+    QuadInOut,
+    /// Like `QuadIn`, but more sharply.
+    CubicIn,
+    /// Like `QuadOut`, but more sharply.
+    CubicOut,
+    /// Like `QuadInOut`, but more sharply.
+    CubicInOut,
+    /// Overshooting the end a little before settling back on it, e.g. for a panel that bounces
+    /// into place.
+    BackOut,
+}
+
+impl Easing {
+    /// How far along the value is when the tween is `t` of the way through, with `t` clamped to
+    /// between 0 and 1. Always 0 at the start and 1 at the end.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut if t < 0.5 => 2.0 * t * t,
+            Easing::QuadInOut => 1.0 - 2.0 * (1.0 - t) * (1.0 - t),
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t) * (1.0 - t) * (1.0 - t),
+            Easing::CubicInOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::CubicInOut => 1.0 - 4.0 * (1.0 - t) * (1.0 - t) * (1.0 - t),
+            Easing::BackOut => {
+                const C1: f32 = 1.70158;
+                const C3: f32 = C1 + 1.0;
+                let u = t - 1.0;
+                1.0 + C3 * u * u * u + C1 * u * u
+            }
+        }
+    }
+}
+
+/// Animates a value from `start` to `end`. See the [module-level documentation](index.html).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tween<V> {
+    start: V,
+    end: V,
+    easing: Easing,
+    timer: Timer,
+    // Whether the end has been reached and reported.
+    done: bool,
+}
+
+impl<V> Tween<V> {
+    /// A tween from `start` to `end` over `length`, at a constant speed.
+    pub fn new(start: V, end: V, length: Span) -> Self {
+        Tween {
+            start,
+            end,
+            easing: Easing::Linear,
+            timer: Timer::new(length),
+            done: false,
+        }
+    }
+
+    /// A tween from `start` to `end` over `length` of time.
+    pub fn time(start: V, end: V, length: Duration) -> Self {
+        Tween::new(start, end, Span::Time(length))
+    }
+
+    /// A tween from `start` to `end` over `n` turns.
+    pub fn turns(start: V, end: V, n: u64) -> Self {
+        Tween::new(start, end, Span::Turns(n))
+    }
+
+    /// Follow `easing` instead of going at a constant speed, builder style.
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// The value the tween starts from.
+    pub fn start(&self) -> &V {
+        &self.start
+    }
+
+    /// The value the tween ends at.
+    pub fn end(&self) -> &V {
+        &self.end
+    }
+
+    /// The easing curve the tween follows.
+    pub fn easing(&self) -> Easing {
+        self.easing
+    }
+
+    /// How long the tween runs for.
+    pub fn length(&self) -> Span {
+        self.timer.length()
+    }
+
+    /// How far through the tween is, from 0 at the start to 1 at the end, before easing.
+    pub fn progress(&self) -> f32 {
+        match (self.timer.length(), self.timer.remaining()) {
+            (Span::Time(length), Span::Time(left)) if !length.is_zero() => {
+                1.0 - (left.as_secs_f64() / length.as_secs_f64()) as f32
+            }
+            (Span::Turns(length), Span::Turns(left)) if length != 0 => {
+                1.0 - (left as f64 / length as f64) as f32
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Whether the tween has reached its end.
+    pub fn is_finished(&self) -> bool {
+        self.timer.is_finished()
+    }
+
+    /// Start the tween from the beginning.
+    pub fn restart(&mut self) {
+        self.timer.restart();
+        self.done = false;
+    }
+
+    /// Swap the start and end, and start the tween from the beginning, e.g. to slide a panel
+    /// back out the way it came in.
+    pub fn reverse(&mut self) {
+        core::mem::swap(&mut self.start, &mut self.end);
+        self.restart();
+    }
+
+    /// Advance the tween by `turns` if it's measured in turns, or by `elapsed` if it's measured
+    /// in time.
+    pub fn tick(&mut self, turns: u64, elapsed: Duration) {
+        self.timer.tick(turns, elapsed);
+    }
+}
+
+impl<V: Lerp> Tween<V> {
+    /// The tween's current value.
+    pub fn value(&self) -> V {
+        self.start
+            .lerp(&self.end, self.easing.apply(self.progress()))
+    }
+}
+
+/// A finished tween of default values, which is what `ComponentSet`s default to.
+impl<V: Default> Default for Tween<V> {
+    fn default() -> Self {
+        Tween {
+            done: true,
+            ..Tween::turns(V::default(), V::default(), 0)
+        }
+    }
+}
+
+/// Event sent when the tween in an entity's `C` component reaches its end. See the
+/// [module-level documentation](index.html).
+pub struct TweenFinished<C> {
+    /// The entity whose tween finished.
+    pub entity: Entity,
+    tag: PhantomData<fn() -> C>,
+}
+
+impl<C> TweenFinished<C> {
+    /// An event for `entity`'s tween.
+    pub fn new(entity: Entity) -> Self {
+        TweenFinished {
+            entity,
+            tag: PhantomData,
+        }
+    }
+}
+
+impl<C> Clone for TweenFinished<C> {
+    fn clone(&self) -> Self {
+        TweenFinished::new(self.entity)
+    }
+}
+
+impl<C> fmt::Debug for TweenFinished<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TweenFinished")
+            .field("entity", &self.entity)
+            .finish()
+    }
+}
+
+/// System that advances the tween in every `C` component at the start of each frame, and writes
+/// its value into the field of the entity's `T` component picked out by the function it was
+/// created with. Tweens that reach their end send a `TweenFinished<C>` event. Running it again
+/// without starting a new frame (with `Dispatcher::run_frame`) does nothing, and frames it missed
+/// are made up for, with the time they took between them.
+///
+/// Frames that went by before the system was added to a dispatcher, or before it first ran if it
+/// never was, aren't counted: then it only notes the current frame.
+pub struct RunTweens<C, T, V> {
+    field: fn(&mut T) -> &mut V,
+    // The frame the tweens were last advanced in, or `None` before the system has seen one, and
+    // the world's `FrameInfo::elapsed` as of that frame.
+    last_tick: Option<u64>,
+    last_elapsed: Duration,
+    tag: PhantomData<fn() -> C>,
+}
+
+impl<C, T, V> RunTweens<C, T, V> {
+    /// Create the system, animating the field of `T` that `field` returns.
+    pub fn new(field: fn(&mut T) -> &mut V) -> Self {
+        RunTweens {
+            field,
+            last_tick: None,
+            last_elapsed: Duration::ZERO,
+            tag: PhantomData,
+        }
+    }
+}
+
+impl<C, T, V> fmt::Debug for RunTweens<C, T, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunTweens")
+            .field("last_tick", &self.last_tick)
+            .field("last_elapsed", &self.last_elapsed)
+            .finish()
+    }
+}
+
+impl<'a, C, T, V> System<'a> for RunTweens<C, T, V>
+where
+    C: 'static + AsMut<Tween<V>> + StorageSpec<'a, Component = C>,
+    C::Storage: MutableComponentStorage<'a> + 'a,
+    T: 'static + StorageSpec<'a, Component = T>,
+    T::Storage: MutableComponentStorage<'a> + 'a,
+    V: 'static + Lerp,
+{
+    type Dependencies = (
+        ReadResource<'a, FrameInfo>,
+        WriteComponent<'a, C>,
+        WriteComponent<'a, T>,
+        WriteResource<'a, Events<TweenFinished<C>>>,
+    );
+    fn run(&'a mut self, (frame, mut tweens, mut targets, mut finished): Self::Dependencies) {
+        let turns = match self.last_tick.replace(frame.tick()) {
+            Some(last) => frame.tick().saturating_sub(last),
+            None => 0,
+        };
+        let elapsed = frame.elapsed().saturating_sub(self.last_elapsed);
+        self.last_elapsed = frame.elapsed();
+        if turns == 0 {
+            return;
+        }
+        let field = self.field;
+        (&mut tweens, &mut targets).for_each(|e, (tween, target)| {
+            let tween = tween.as_mut();
+            if tween.done {
+                return;
+            }
+            tween.tick(turns, elapsed);
+            *field(target) = tween.value();
+            if tween.is_finished() {
+                tween.done = true;
+                finished.send(TweenFinished::new(e));
+            }
+        });
+    }
+    fn setup(&mut self, (frame, _, _, _): Self::Dependencies) {
+        self.last_tick = Some(frame.tick());
+        self.last_elapsed = frame.elapsed();
+    }
+}
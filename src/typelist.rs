@@ -164,8 +164,58 @@
 //! ```
 //!
 //!
+//! # Set operations
+//!
+//! A few traits treat `TypeList`s as sets, for expressing constraints between lists. Like
+//! `Consume`, they take an index type parameter that the compiler has to infer.
+//!
+//! - [`Contains<T, I>`](trait.Contains.html): `T` is in the list.
+//! - [`Subset<L, I>`](trait.Subset.html): every element of the list is in `L`.
+//! - [`Unique<I>`](trait.Unique.html): no type appears in the list more than once.
+//! - [`Disjoint<L, I>`](trait.Disjoint.html): no type is in both the list and `L`.
+//! - [`Permutation<L, I>`](trait.Permutation.html): the list has the same elements as `L`, in
+//!   any order.
+//!
+//! For example, two systems can safely run at the same time if one's writes are disjoint from the
+//! other's reads:
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::typelist::*;
+//! fn run_together<W1, R2, I>()
+//! where
+//!     W1: Disjoint<R2, I>,
+//! {
+//!     // ...
+//! }
+//! run_together::<tlist![u32, f64], tlist![String], _>();
+//! ```
+//!
+//! ```compile_fail
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::typelist::*;
+//! # fn run_together<W1, R2, I>()
+//! # where
+//! #     W1: Disjoint<R2, I>,
+//! # {
+//! # }
+//! // The first system writes a `u32` that the second reads.
+//! run_together::<tlist![u32, f64], tlist![String, u32], _>();
+//! ```
+//!
+//! None of these care about the order of either list, so `tlist![A, B]` and `tlist![B, A]` are
+//! interchangeable in constraints built from them. There's no way to sort a list into a canonical
+//! order instead: that would need an ordering on types, and stable Rust can't compare `TypeId`s
+//! or any other per-type key while type checking.
+//!
+//! There are no `Union` or `Intersect` traits. Building either list means deciding, for each
+//! element, whether it's also in the other list, and the type system can only prove that two types
+//! are the same, not that they differ. Constraints that would be written with them can usually be
+//! written with the traits above instead (a union being unique is `Disjoint`, and one list
+//! containing the intersection of two others follows from `Subset`).
+//!
 //! [1]: https://beachape.com/blog/2017/03/12/gentle-intro-to-type-level-recursion-in-Rust-from-zero-to-frunk-hlist-sculpting/
-use std::marker::PhantomData;
+use core::marker::PhantomData;
 
 mod private {
     pub trait Sealed {}
@@ -220,6 +270,7 @@ where
 /// Removes all instances of `T`, leaving `Self::Remainder`. `INDEX` must be inferred.
 ///
 /// See the [module-level documentation](index.html#examples) for examples.
+#[diagnostic::on_unimplemented(message = "`{T}` is not an element of the type list")]
 pub trait Consume<T, INDEX>: private::Sealed {
     /// The `TypeList` with all instances of `T` removed.
     type Remainder: TypeList;
@@ -262,6 +313,120 @@ where
         >>::Remainder;
 }
 
+/// `T` is an element of the list. `INDEX` must be inferred.
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::typelist::*;
+/// fn check<L: Contains<T, I>, T, I>() {}
+/// check::<tlist![u32, f64], f64, _>();
+/// ```
+///
+/// ```compile_fail
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::typelist::*;
+/// fn check<L: Contains<T, I>, T, I>() {}
+/// check::<tlist![u32, f64], String, _>();
+/// ```
+#[diagnostic::on_unimplemented(message = "`{T}` is not an element of the type list")]
+pub trait Contains<T, INDEX>: private::Sealed {}
+
+impl<T, TAIL> Contains<T, Found> for TypeCons<T, TAIL> {}
+
+impl<HEAD, TAIL, T, TINDEX> Contains<T, NotFound<TINDEX>> for TypeCons<HEAD, TAIL> where
+    TAIL: Contains<T, TINDEX>
+{
+}
+
+/// Every element of the list is also in `SUPER`. `INDICES` must be inferred.
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::typelist::*;
+/// fn check<L: Subset<S, I>, S, I>() {}
+/// check::<tlist![String, u32], tlist![u32, f64, String], _>();
+/// check::<Nil, tlist![u32], _>();
+/// ```
+///
+/// ```compile_fail
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::typelist::*;
+/// fn check<L: Subset<S, I>, S, I>() {}
+/// check::<tlist![String, u8], tlist![u32, f64, String], _>();
+/// ```
+pub trait Subset<SUPER, INDICES>: private::Sealed {}
+
+impl<SUPER: TypeList> Subset<SUPER, Nil> for Nil {}
+
+impl<HEAD, TAIL, SUPER, IHEAD, ITAIL> Subset<SUPER, TypeCons<IHEAD, ITAIL>> for TypeCons<HEAD, TAIL>
+where
+    SUPER: Contains<HEAD, IHEAD>,
+    TAIL: Subset<SUPER, ITAIL>,
+{
+}
+
+/// No type appears in the list more than once. `INDICES` must be inferred.
+///
+/// Like the `T != U` example in the [module-level documentation](index.html#examples), a
+/// duplicate makes `INDICES` ambiguous, so the error is that type annotations are needed.
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::typelist::*;
+/// fn check<L: Unique<I>, I>() {}
+/// check::<tlist![u32, f64, String], _>();
+/// ```
+///
+/// ```compile_fail
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::typelist::*;
+/// fn check<L: Unique<I>, I>() {}
+/// check::<tlist![u32, f64, u32], _>();
+/// ```
+pub trait Unique<INDICES>: private::Sealed {}
+
+impl<L, INDICES> Unique<INDICES> for L where
+    L: private::Sealed + ConsumeMultiple<L, INDICES, Remainder = Nil>
+{
+}
+
+/// No type is in both the list and `OTHER`, and neither has duplicates. `INDICES` must be
+/// inferred.
+///
+/// See the [module-level documentation](index.html#set-operations) for an example.
+pub trait Disjoint<OTHER, INDICES>: private::Sealed {}
+
+impl<L, OTHER, INDICES> Disjoint<OTHER, INDICES> for L
+where
+    L: Append<OTHER>,
+    OTHER: TypeList,
+    <L as Append<OTHER>>::Output: Unique<INDICES>,
+{
+}
+
+/// The list has exactly the same elements as `OTHER`, possibly in a different order. `INDICES`
+/// must be inferred.
+///
+/// ```
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::typelist::*;
+/// fn check<L: Permutation<R, I>, R, I>() {}
+/// check::<tlist![u32, f64, String], tlist![String, u32, f64], _>();
+/// ```
+///
+/// ```compile_fail
+/// # #[macro_use] extern crate ecstatic;
+/// # use ecstatic::typelist::*;
+/// fn check<L: Permutation<R, I>, R, I>() {}
+/// check::<tlist![u32, f64, String], tlist![String, u32], _>();
+/// ```
+pub trait Permutation<OTHER, INDICES>: private::Sealed {}
+
+impl<L, OTHER, INDICES> Permutation<OTHER, INDICES> for L where
+    L: private::Sealed + ConsumeMultiple<OTHER, INDICES, Remainder = Nil>
+{
+}
+
 /// Easy conversion into `TypeList`.
 pub trait IntoTypeList: private::Sealed {
     /// The `TypeList` that is equivalent to this type.
@@ -0,0 +1,161 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Undo/redo built on whole-world snapshots.
+//!
+//! [`UndoStack`](struct.UndoStack.html) keeps a bounded history of clones of a `World`, so the
+//! world must be `Clone`; add `Clone` to the derives in `define_world!` (which requires every
+//! component and resource type to be `Clone` as well).
+//!
+//! The world's [`Journal`](../journal/struct.Journal.html) is append-only, so it is carried over
+//! when a snapshot is restored rather than being rolled back with the rest of the world, and
+//! snapshots are taken without it, so that they don't grow with the length of the log.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Clone, Debug, Default, PartialEq)]
+//! pub struct Position(i32, i32);
+//!
+//! define_world!(
+//!     #[derive(Clone, Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!         }
+//!         resources {}
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let mut history = UndoStack::new(16);
+//! let player = w.new_entity().with(Position(0, 0)).build();
+//!
+//! history.push_checkpoint(&w);
+//! <World as GetComponent<'_, Position>>::get_mut(&w).set(player, Some(Position(1, 0)));
+//!
+//! assert!(history.undo(&mut w));
+//! assert_eq!(
+//!     <World as GetComponent<'_, Position>>::get(&w).get(player),
+//!     Some(&Position(0, 0))
+//! );
+//! assert!(history.redo(&mut w));
+//! assert_eq!(
+//!     <World as GetComponent<'_, Position>>::get(&w).get(player),
+//!     Some(&Position(1, 0))
+//! );
+//! ```
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// Bounded undo/redo history of world snapshots.
+#[derive(Clone, Debug)]
+pub struct UndoStack<W> {
+    undo: VecDeque<W>,
+    redo: Vec<W>,
+    capacity: usize,
+}
+
+impl<W> UndoStack<W>
+where
+    W: Clone + GetResource<Journal>,
+{
+    /// Create an empty history that retains at most `capacity` checkpoints. Once full, pushing a
+    /// new checkpoint discards the oldest one.
+    pub fn new(capacity: usize) -> Self {
+        UndoStack {
+            undo: VecDeque::with_capacity(capacity),
+            redo: Vec::new(),
+            capacity,
+        }
+    }
+
+    /// Snapshot `world`. This discards any redo history.
+    pub fn push_checkpoint(&mut self, world: &W) {
+        self.redo.clear();
+        if self.capacity == 0 {
+            return;
+        }
+        if self.undo.len() == self.capacity {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(Self::snapshot(world));
+    }
+
+    /// Restore `world` to the most recent checkpoint, saving its current state for `redo`.
+    /// Returns `false` (leaving `world` untouched) if there is nothing to undo.
+    pub fn undo(&mut self, world: &mut W) -> bool {
+        match self.undo.pop_back() {
+            Some(snapshot) => {
+                self.redo.push(Self::restore(world, snapshot));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapply the most recently undone state. Returns `false` (leaving `world` untouched) if
+    /// there is nothing to redo.
+    pub fn redo(&mut self, world: &mut W) -> bool {
+        match self.redo.pop() {
+            Some(snapshot) => {
+                let previous = Self::restore(world, snapshot);
+                if self.undo.len() == self.capacity {
+                    self.undo.pop_front();
+                }
+                self.undo.push_back(previous);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether `undo` would do anything.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Whether `redo` would do anything.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Discard all checkpoints.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    // Clone `world`, leaving out its journal, which would only be thrown away on restore.
+    fn snapshot(world: &W) -> W {
+        let journal = core::mem::take(&mut *<W as GetResource<Journal>>::get_mut(world));
+        let snapshot = world.clone();
+        <W as GetResource<Journal>>::set(world, journal);
+        snapshot
+    }
+
+    // Replace `world` with `snapshot`, moving the live journal over, and return the old world
+    // without it.
+    fn restore(world: &mut W, snapshot: W) -> W {
+        let previous = core::mem::replace(world, snapshot);
+        let journal = core::mem::take(&mut *<W as GetResource<Journal>>::get_mut(&previous));
+        <W as GetResource<Journal>>::set(world, journal);
+        previous
+    }
+}
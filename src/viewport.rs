@@ -0,0 +1,377 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The part of a tile map that's on screen, and converting between map and screen coordinates.
+//!
+//! A [`Viewport2D`](struct.Viewport2D.html) resource is a rectangle of tiles, `width` by `height`,
+//! whose top left corner is at its `offset` on the map. Screen coordinates count from that
+//! corner, so [`world_to_screen`](struct.Viewport2D.html#method.world_to_screen) and
+//! [`screen_to_world`](struct.Viewport2D.html#method.screen_to_world) are a subtraction and an
+//! addition, but having them in one place means the off-by-ones only need getting right once.
+//! Given the size of the map, the viewport stops scrolling at its edges.
+//!
+//! The viewport can follow an entity, staying centered on it as it moves (within the map's
+//! edges). Positions are components that implement [`GridPosition`](trait.GridPosition.html);
+//! calling [`update`](struct.Viewport2D.html#method.update) with their storage, or running the
+//! [`FollowCamera`](struct.FollowCamera.html) system, recenters the viewport.
+//!
+//! Drawing just what's on screen is a join with the filter from
+//! [`on_screen`](struct.Viewport2D.html#method.on_screen), which, like `Visibility`, contributes
+//! `()` to the join's output.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! #[derive(Default)]
+//! pub struct Position(i32, i32);
+//! impl GridPosition for Position {
+//!     fn grid_position(&self) -> (i32, i32) {
+//!         (self.0, self.1)
+//!     }
+//! }
+//!
+//! #[derive(Default)]
+//! pub struct Sprite(char);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!             sprites: BasicVecStorage<Sprite>,
+//!         }
+//!         resources {
+//!             viewport: Viewport2D,
+//!         }
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! let player = w.new_entity().with(Position(60, 4)).with(Sprite('@')).build();
+//! w.new_entity().with(Position(62, 5)).with(Sprite('g')).build();
+//! w.new_entity().with(Position(5, 5)).with(Sprite('o')).build();
+//! {
+//!     // An 80x25 screen onto a 100x50 map.
+//!     let mut viewport = <World as GetResource<Viewport2D>>::get_mut(&w);
+//!     *viewport = Viewport2D::new(80, 25).with_bounds(100, 50);
+//!     viewport.follow(player);
+//! }
+//! w.run_system(&mut FollowCamera::<Position>::new());
+//!
+//! let (positions, sprites, viewport): (
+//!     ReadComponent<Position>,
+//!     ReadComponent<Sprite>,
+//!     ReadResource<Viewport2D>,
+//! ) = ComponentProvider::fetch(&w);
+//! // Centered horizontally, but stopped at the top edge of the map.
+//! assert_eq!(viewport.offset(), (20, 0));
+//!
+//! let mut drawn = Vec::new();
+//! (&positions, &sprites, viewport.on_screen(&*positions)).for_each(|_, (p, s, _)| {
+//!     drawn.push((viewport.world_to_screen((p.0, p.1)).unwrap(), s.0));
+//! });
+//! assert_eq!(drawn, vec![((40, 4), '@'), ((42, 5), 'g')]);
+//! ```
+
+use core::marker::PhantomData;
+
+use crate::*;
+
+/// Components holding a position on a tile map, for `Viewport2D`. See the [module-level
+/// documentation](index.html).
+pub trait GridPosition {
+    /// The position's column and row.
+    fn grid_position(&self) -> (i32, i32);
+}
+
+impl GridPosition for (i32, i32) {
+    fn grid_position(&self) -> (i32, i32) {
+        *self
+    }
+}
+
+#[cfg(feature = "term")]
+impl GridPosition for crate::term::Position {
+    fn grid_position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+}
+
+/// Resource holding the part of the map that's on screen. See the [module-level
+/// documentation](index.html).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Viewport2D {
+    offset: (i32, i32),
+    width: u32,
+    height: u32,
+    bounds: Option<(u32, u32)>,
+    follow: Option<Entity>,
+}
+
+impl Viewport2D {
+    /// Create a viewport `width` tiles wide and `height` tiles high, showing the map from
+    /// `(0, 0)`.
+    pub fn new(width: u32, height: u32) -> Self {
+        Viewport2D {
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    /// Keep the viewport within a map `width` tiles wide and `height` tiles high, starting at
+    /// `(0, 0)`, builder style. See `set_bounds`.
+    pub fn with_bounds(mut self, width: u32, height: u32) -> Self {
+        self.set_bounds(Some((width, height)));
+        self
+    }
+
+    /// Keep the viewport within a map of the given width and height, starting at `(0, 0)`, or
+    /// let it scroll anywhere if `None`. Along an axis where the map is smaller than the
+    /// viewport, it's centered instead.
+    pub fn set_bounds(&mut self, bounds: Option<(u32, u32)>) {
+        self.bounds = bounds;
+        self.set_offset(self.offset);
+    }
+
+    /// The map size the viewport is kept within, if any.
+    pub fn bounds(&self) -> Option<(u32, u32)> {
+        self.bounds
+    }
+
+    /// The number of columns on screen.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The number of rows on screen.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Change the size of the viewport, e.g. when the window is resized, keeping the same tile
+    /// in the center.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let center = self.center();
+        self.width = width;
+        self.height = height;
+        self.center_on(center);
+    }
+
+    /// The map position of the top left tile on screen.
+    pub fn offset(&self) -> (i32, i32) {
+        self.offset
+    }
+
+    /// Scroll so that the top left tile on screen is the one at `offset`, or as close as the
+    /// bounds allow.
+    pub fn set_offset(&mut self, offset: (i32, i32)) {
+        self.offset = match self.bounds {
+            Some((w, h)) => (
+                clamp_axis(offset.0, self.width, w),
+                clamp_axis(offset.1, self.height, h),
+            ),
+            None => offset,
+        };
+    }
+
+    /// Scroll by `dx` columns and `dy` rows, stopping at the edges of the `i32` range.
+    pub fn scroll(&mut self, dx: i32, dy: i32) {
+        self.set_offset((
+            self.offset.0.saturating_add(dx),
+            self.offset.1.saturating_add(dy),
+        ));
+    }
+
+    /// The map position of the tile in the center of the screen (rounding towards the bottom right
+    /// when the size is even).
+    pub fn center(&self) -> (i32, i32) {
+        (
+            self.offset.0.saturating_add((self.width / 2) as i32),
+            self.offset.1.saturating_add((self.height / 2) as i32),
+        )
+    }
+
+    /// Scroll so that `position` is in the center of the screen, or as close as the bounds
+    /// allow.
+    pub fn center_on(&mut self, position: (i32, i32)) {
+        self.set_offset((
+            position.0.saturating_sub((self.width / 2) as i32),
+            position.1.saturating_sub((self.height / 2) as i32),
+        ));
+    }
+
+    /// Keep `entity` in the center of the screen whenever `update` is called.
+    pub fn follow(&mut self, entity: Entity) {
+        self.follow = Some(entity);
+    }
+
+    /// Stop following an entity.
+    pub fn unfollow(&mut self) {
+        self.follow = None;
+    }
+
+    /// The entity being followed, if any.
+    pub fn following(&self) -> Option<Entity> {
+        self.follow
+    }
+
+    /// Center the viewport on the entity being followed, if it has a position in `positions`,
+    /// returning whether it did.
+    pub fn update<'a, S>(&mut self, positions: &S) -> bool
+    where
+        S: ReadStorage<'a>,
+        S::Component: GridPosition,
+    {
+        match self.follow.and_then(|e| positions.get(e)) {
+            Some(p) => {
+                self.center_on(p.grid_position());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the tile at `position` is on screen.
+    pub fn contains(&self, position: (i32, i32)) -> bool {
+        self.world_to_screen(position).is_some()
+    }
+
+    /// Where the tile at `position` on the map is on screen, or `None` if it's off screen.
+    pub fn world_to_screen(&self, position: (i32, i32)) -> Option<(i32, i32)> {
+        let x = position.0.checked_sub(self.offset.0)?;
+        let y = position.1.checked_sub(self.offset.1)?;
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        Some((x, y))
+    }
+
+    /// The map position of the tile at `screen` on the screen, e.g. under the mouse. Positions
+    /// off the screen are converted as if it carried on, up to the edges of the `i32` range.
+    pub fn screen_to_world(&self, screen: (i32, i32)) -> (i32, i32) {
+        (
+            screen.0.saturating_add(self.offset.0),
+            screen.1.saturating_add(self.offset.1),
+        )
+    }
+
+    /// Iterate over the map positions of the tiles on screen, row by row from the top left, e.g.
+    /// to draw the map's terrain. Tiles past the edges of the `i32` range are left out.
+    pub fn tiles(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        let (ox, oy) = self.offset;
+        let (width, height) = (axis_len(self.width), axis_len(self.height));
+        (0..height)
+            .filter_map(move |y| oy.checked_add(y))
+            .flat_map(move |y| {
+                (0..width)
+                    .map_while(move |x| ox.checked_add(x))
+                    .map(move |x| (x, y))
+            })
+    }
+
+    /// A join filter that only lets through the entities whose component in `positions` is on
+    /// screen. Like `Visibility`, it contributes `()` to the join's output, and checks for
+    /// disabled entities are left to the other views in the join.
+    pub fn on_screen<'v, S>(&'v self, positions: &'v S) -> OnScreen<'v, S> {
+        OnScreen {
+            viewport: self,
+            positions,
+        }
+    }
+}
+
+// The offset along one axis that keeps a viewport `size` tiles long within a map `bound` tiles
+// long, centering it if the map is shorter.
+fn clamp_axis(offset: i32, size: u32, bound: u32) -> i32 {
+    if bound <= size {
+        -axis_len((size - bound) / 2)
+    } else {
+        offset.clamp(0, axis_len(bound - size))
+    }
+}
+
+// A length along one axis as an `i32`, saturating at `i32::MAX`.
+fn axis_len(len: u32) -> i32 {
+    len.min(i32::MAX as u32) as i32
+}
+
+/// Join filter for the entities that are on screen. See `Viewport2D::on_screen`.
+#[derive(Debug)]
+pub struct OnScreen<'v, S> {
+    viewport: &'v Viewport2D,
+    positions: &'v S,
+}
+
+impl<'a, S> OnScreen<'_, S>
+where
+    S: ComponentStorage<'a>,
+    S::Component: GridPosition,
+{
+    /// Whether `entity` has a position, and it's on screen.
+    #[inline]
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.positions
+            .get(entity)
+            .is_some_and(|p| self.viewport.contains(p.grid_position()))
+    }
+
+    #[inline]
+    pub(crate) fn size(&self) -> usize {
+        self.positions.size()
+    }
+
+    // The entity in slot `id`, if it's on screen.
+    #[inline]
+    pub(crate) fn entity(&self, id: usize) -> Option<Entity> {
+        self.positions.entity(id).filter(|&e| self.contains(e))
+    }
+}
+
+/// System that centers the `Viewport2D` on the entity it's following, using the positions in
+/// the `P` components. See the [module-level documentation](index.html).
+pub struct FollowCamera<P> {
+    tag: PhantomData<fn() -> P>,
+}
+
+impl<P> FollowCamera<P> {
+    /// Create the system.
+    pub fn new() -> Self {
+        FollowCamera { tag: PhantomData }
+    }
+}
+
+impl<P> Default for FollowCamera<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P> core::fmt::Debug for FollowCamera<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FollowCamera").finish()
+    }
+}
+
+impl<'a, P> System<'a> for FollowCamera<P>
+where
+    P: 'static + GridPosition + StorageSpec<'a, Component = P>,
+{
+    type Dependencies = (ReadComponent<'a, P>, WriteResource<'a, Viewport2D>);
+    fn run(&'a mut self, (positions, mut viewport): Self::Dependencies) {
+        viewport.update(&*positions);
+    }
+}
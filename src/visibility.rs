@@ -0,0 +1,148 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The set of entities the player can see, as a join filter.
+//!
+//! [`Visibility`](struct.Visibility.html) is meant to be kept as a resource and recomputed once per
+//! turn (or whenever the player moves) by calling `update` with the storage of positions and a
+//! field-of-view test. After that, it can be joined like a component storage that every visible
+//! entity has, yielding `()`, so drawing just the visible entities, or having AI react only to
+//! what the player can see, is a single join.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! # #[derive(Default)]
+//! # pub struct Sprite(char);
+//! #[derive(Default)]
+//! pub struct Position(i32, i32);
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             positions: BasicVecStorage<Position>,
+//!             sprites: BasicVecStorage<Sprite>,
+//!         }
+//!         resources {
+//!             visibility: Visibility,
+//!         }
+//!     }
+//! );
+//!
+//! let mut w = World::default();
+//! w.new_entity().with(Position(1, 1)).with(Sprite('g')).build();
+//! w.new_entity().with(Position(9, 9)).with(Sprite('o')).build();
+//!
+//! let (positions, sprites, mut visibility): (
+//!     ReadComponent<Position>,
+//!     ReadComponent<Sprite>,
+//!     WriteResource<Visibility>,
+//! ) = ComponentProvider::fetch(&w);
+//! visibility.update(&*positions, |p| p.0.abs() <= 5 && p.1.abs() <= 5);
+//!
+//! let mut drawn = Vec::new();
+//! (&sprites, &*visibility).for_each(|_, (s, _)| drawn.push(s.0));
+//! assert_eq!(drawn, vec!['g']);
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::*;
+
+/// Set of visible entities. See the [module documentation](index.html).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Visibility {
+    // Indexed by entity id.
+    visible: Vec<Option<Entity>>,
+    count: usize,
+}
+
+impl Visibility {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Recompute the set: an entity is visible if it has a position in `positions` and
+    /// `in_view` returns `true` for it.
+    pub fn update<'a, S, F>(&mut self, positions: &S, mut in_view: F)
+    where
+        S: ComponentStorage<'a>,
+        F: FnMut(&S::Component) -> bool,
+    {
+        self.clear();
+        for id in 0..positions.size() {
+            if let Some(e) = positions.entity(id) {
+                if positions.get(e).is_some_and(&mut in_view) {
+                    self.insert(e);
+                }
+            }
+        }
+    }
+
+    /// Mark `entity` as visible.
+    pub fn insert(&mut self, entity: Entity) {
+        if entity.id() >= self.visible.len() {
+            self.visible.resize(entity.id() + 1, None);
+        }
+        if self.visible[entity.id()].replace(entity).is_none() {
+            self.count += 1;
+        }
+    }
+
+    /// Mark `entity` as not visible.
+    pub fn remove(&mut self, entity: Entity) {
+        if self.contains(entity) {
+            self.visible[entity.id()] = None;
+            self.count -= 1;
+        }
+    }
+
+    /// Whether `entity` is visible.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.visible.get(entity.id()) == Some(&Some(entity))
+    }
+
+    /// The number of visible entities.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Whether nothing is visible.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Iterate over the visible entities, in ascending id order.
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.visible.iter().flatten().copied()
+    }
+
+    /// Mark every entity as not visible.
+    pub fn clear(&mut self) {
+        self.visible.clear();
+        self.count = 0;
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.visible.len()
+    }
+
+    pub(crate) fn entity(&self, id: usize) -> Option<Entity> {
+        self.visible.get(id).copied().flatten()
+    }
+}
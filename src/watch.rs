@@ -0,0 +1,578 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reloading resources from files while the game runs.
+//!
+//! A [`ResourceWatcher`](struct.ResourceWatcher.html) binds resource types to files. Each call to
+//! `poll` checks whether any of the files have changed since it last looked, and if one has,
+//! parses it into a [`Value`](../persist/enum.Value.html), loads the resource from that with
+//! `Persist::load`, and sets it in the world. Calling `poll` once a frame, between runs of the
+//! dispatcher, lets designers edit balance tables and see the effect without restarting.
+//!
+//! Files are JSON by default, read by [`parse_json`](fn.parse_json.html). With the `ron` feature,
+//! [`parse_ron`](fn.parse_ron.html) reads RON instead. Other formats (TOML, a game's own) can be
+//! used by passing `watch_with` a function that parses the text into a `Value`, so this crate
+//! doesn't need to depend on a parser for each of them.
+//!
+//! A file that can't be read, parsed or loaded leaves the resource as it was. The error is
+//! reported once, and the file is tried again the next time it changes.
+//!
+//! This module is only available with the `watch` feature.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate ecstatic;
+//! # use ecstatic::*;
+//! use ecstatic::watch::*;
+//!
+//! #[derive(Debug, Default, PartialEq)]
+//! pub struct Balance {
+//!     spawn_rate: f64,
+//!     max_goblins: u32,
+//! }
+//! impl_persist!(Balance { spawn_rate, max_goblins });
+//! # #[derive(Default)]
+//! # pub struct Goblin;
+//!
+//! define_world!(
+//!     #[derive(Default)]
+//!     pub world {
+//!         components {
+//!             goblins: VoidStorage<Goblin>,
+//!         }
+//!         resources {
+//!             balance: Balance,
+//!         }
+//!     }
+//! );
+//!
+//! let path = std::env::temp_dir().join(format!("balance-{}.json", std::process::id()));
+//! std::fs::write(&path, r#"{ "spawn_rate": 0.5, "max_goblins": 12 }"#).unwrap();
+//!
+//! let world = World::default();
+//! let mut watcher = ResourceWatcher::new();
+//! watcher.watch::<Balance>(&path);
+//!
+//! // The first poll loads every file.
+//! assert_eq!(watcher.poll(&world), vec![Ok("Balance")]);
+//! assert_eq!(
+//!     *<World as GetResource<Balance>>::get(&world),
+//!     Balance { spawn_rate: 0.5, max_goblins: 12 }
+//! );
+//!
+//! // After that, only files that have changed are reloaded.
+//! assert!(watcher.poll(&world).is_empty());
+//! # std::fs::remove_file(&path).unwrap();
+//! ```
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::persist::{Persist, PersistError, Value};
+use crate::GetResource;
+
+/// Parses a file's text into a `Value`.
+pub type Parser = fn(&str) -> Result<Value, PersistError>;
+
+// What the file looked like the last time it was checked: its modification time and length, or
+// why they couldn't be read.
+type Stamp = Result<(SystemTime, u64), io::ErrorKind>;
+
+struct Watch<W> {
+    path: PathBuf,
+    resource: &'static str,
+    parse: Parser,
+    reload: fn(&W, &Value) -> Result<(), PersistError>,
+    // `None` until the first poll.
+    stamp: Option<Stamp>,
+}
+
+/// Resources bound to the files they're reloaded from. See the
+/// [module-level documentation](index.html).
+pub struct ResourceWatcher<W> {
+    watches: Vec<Watch<W>>,
+}
+
+impl<W> Default for ResourceWatcher<W> {
+    fn default() -> Self {
+        ResourceWatcher::new()
+    }
+}
+
+impl<W> ResourceWatcher<W> {
+    /// Create a watcher with no files.
+    pub fn new() -> Self {
+        ResourceWatcher {
+            watches: Vec::new(),
+        }
+    }
+
+    /// Reload the resource of type `T` from the JSON file at `path` whenever it changes.
+    pub fn watch<T>(&mut self, path: impl AsRef<Path>) -> &mut Self
+    where
+        T: Persist,
+        W: GetResource<T>,
+    {
+        self.watch_with::<T>(path, parse_json)
+    }
+
+    /// Reload the resource of type `T` from the file at `path` whenever it changes, using `parse`
+    /// to read it.
+    pub fn watch_with<T>(&mut self, path: impl AsRef<Path>, parse: Parser) -> &mut Self
+    where
+        T: Persist,
+        W: GetResource<T>,
+    {
+        self.watches.push(Watch {
+            path: path.as_ref().to_path_buf(),
+            resource: short_name::<T>(),
+            parse,
+            reload: reload::<W, T>,
+            stamp: None,
+        });
+        self
+    }
+
+    /// The files being watched, in the order they were added.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.watches.iter().map(|w| w.path.as_path())
+    }
+
+    /// Reload every resource whose file has changed since the last poll, or every resource if
+    /// this is the first. Returns one entry for each changed file, in the order they were added:
+    /// the name of the resource that was reloaded, or why it couldn't be.
+    pub fn poll(&mut self, world: &W) -> Vec<Result<&'static str, WatchError>> {
+        let mut results = Vec::new();
+        for watch in self.watches.iter_mut() {
+            let stamp = fs::metadata(&watch.path)
+                .and_then(|m| Ok((m.modified()?, m.len())))
+                .map_err(|e| e.kind());
+            if watch.stamp.as_ref() == Some(&stamp) {
+                continue;
+            }
+            watch.stamp = Some(stamp);
+            let result = fs::read_to_string(&watch.path)
+                .map_err(|e| PersistError::Io(e.to_string()))
+                .and_then(|text| (watch.parse)(&text))
+                .and_then(|value| (watch.reload)(world, &value));
+            results.push(match result {
+                Ok(()) => Ok(watch.resource),
+                Err(error) => Err(WatchError {
+                    resource: watch.resource,
+                    path: watch.path.clone(),
+                    error,
+                }),
+            });
+        }
+        results
+    }
+}
+
+fn reload<W: GetResource<T>, T: Persist>(world: &W, value: &Value) -> Result<(), PersistError> {
+    world.set(T::load(value)?);
+    Ok(())
+}
+
+// The type's name without its path, which is what a designer editing the file will recognize.
+fn short_name<T>() -> &'static str {
+    let name = core::any::type_name::<T>();
+    let base = name.split('<').next().unwrap_or(name);
+    match base.rfind("::") {
+        Some(i) => &name[i + 2..],
+        None => name,
+    }
+}
+
+/// Why a watched resource couldn't be reloaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchError {
+    /// The name of the resource's type.
+    pub resource: &'static str,
+    /// The file it's bound to.
+    pub path: PathBuf,
+    /// What went wrong.
+    pub error: PersistError,
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "couldn't reload `{}` from {}: {}",
+            self.resource,
+            self.path.display(),
+            self.error
+        )
+    }
+}
+
+impl Error for WatchError {}
+
+/// Parse JSON text into a `Value`.
+///
+/// `null` becomes `Unit`, whole numbers become `UInt`, or `Int` if they're negative, and other
+/// numbers become `Float`. Objects become maps, with their keys in the order they're written, and
+/// may not repeat a key. Arrays and objects may be nested at most 128 deep, so that a malformed
+/// file can't overflow the stack.
+///
+/// ```
+/// # use ecstatic::persist::Value;
+/// # use ecstatic::watch::parse_json;
+/// assert_eq!(
+///     parse_json(r#"{ "hp": 10, "drops": [-1, 2.5, null] }"#),
+///     Ok(Value::Map(vec![
+///         ("hp".to_string(), Value::UInt(10)),
+///         (
+///             "drops".to_string(),
+///             Value::List(vec![Value::Int(-1), Value::Float(2.5), Value::Unit])
+///         ),
+///     ]))
+/// );
+/// ```
+pub fn parse_json(text: &str) -> Result<Value, PersistError> {
+    let mut parser = JsonParser {
+        text,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+// How deeply arrays and objects may be nested.
+const MAX_DEPTH: usize = 128;
+
+struct JsonParser<'t> {
+    text: &'t str,
+    // Byte offset of the next character.
+    pos: usize,
+    // The number of arrays and objects the next character is inside.
+    depth: usize,
+}
+
+impl<'t> JsonParser<'t> {
+    fn error(&self, what: &str) -> PersistError {
+        let before = &self.text[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        PersistError::Malformed(format!("{} at line {}, column {}", what, line, column))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), PersistError> {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", c as char)))
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, PersistError> {
+        if self.text[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, PersistError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.keyword("null", Value::Unit),
+            Some(b't') => self.keyword("true", Value::Bool(true)),
+            Some(b'f') => self.keyword("false", Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::Str),
+            Some(b'[') => self.nested(Self::list),
+            Some(b'{') => self.nested(Self::map),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    // Parse an array or object with `f`, one level deeper.
+    fn nested(
+        &mut self,
+        f: fn(&mut Self) -> Result<Value, PersistError>,
+    ) -> Result<Value, PersistError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.depth += 1;
+        let value = f(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn list(&mut self) -> Result<Value, PersistError> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::List(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::List(items));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn map(&mut self) -> Result<Value, PersistError> {
+        self.pos += 1;
+        let mut entries: Vec<(String, Value)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::Map(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key_pos = self.pos;
+            let key = self.string()?;
+            if entries.iter().any(|(k, _)| *k == key) {
+                self.pos = key_pos;
+                return Err(self.error(&format!("duplicate key {:?}", key)));
+            }
+            self.expect(b':')?;
+            let value = self.value()?;
+            entries.push((key, value));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Map(entries));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, PersistError> {
+        let start = self.pos;
+        let negative = self.peek() == Some(b'-');
+        if negative {
+            self.pos += 1;
+        }
+        let int_start = self.pos;
+        let int_len = self.digits();
+        let mut valid = int_len == 1 || (int_len > 1 && self.text.as_bytes()[int_start] != b'0');
+        let mut float = false;
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            float = true;
+            valid &= self.digits() > 0;
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            float = true;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            valid &= self.digits() > 0;
+        }
+        if !valid {
+            self.pos = start;
+            return Err(self.error("malformed number"));
+        }
+        let s = &self.text[start..self.pos];
+        // Anything that gets this far is a valid float, so an integer can only fail to parse by
+        // overflowing.
+        if float {
+            Ok(Value::Float(s.parse().unwrap()))
+        } else if negative {
+            s.parse()
+                .map(Value::Int)
+                .map_err(|_| PersistError::OutOfRange)
+        } else {
+            s.parse()
+                .map(Value::UInt)
+                .map_err(|_| PersistError::OutOfRange)
+        }
+    }
+
+    // Skip over a run of digits, returning how many there were.
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn string(&mut self) -> Result<String, PersistError> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let end = rest
+                .find(|c: char| c == '"' || c == '\\' || c < ' ')
+                .ok_or_else(|| self.error("unterminated string"))?;
+            s.push_str(&rest[..end]);
+            self.pos += end;
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    s.push(self.escape()?);
+                }
+                _ => return Err(self.error("control character in string")),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, PersistError> {
+        let c = match self.peek() {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                let high = self.hex4()?;
+                let code = if (0xd800..0xdc00).contains(&high) {
+                    // A surrogate pair, for a character outside the basic multilingual plane.
+                    if !self.text[self.pos..].starts_with("\\u") {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+                    self.pos += 2;
+                    let low = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+                    0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+                } else {
+                    high
+                };
+                return char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate"));
+            }
+            _ => return Err(self.error("unknown escape")),
+        };
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn hex4(&mut self) -> Result<u32, PersistError> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .filter(|d| d.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("expected four hex digits"))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+}
+
+/// Parse RON text into a `Value`. Only available with the `ron` feature.
+///
+/// Structs and maps become maps, with their keys sorted, and their names, if they have one, are
+/// dropped. Map keys must be strings. Whole numbers become `UInt`, or `Int` if they're negative,
+/// and other numbers become `Float`. A `char` becomes a one-character string, `None` becomes
+/// `Unit`, and `Some(x)` becomes a list holding `x`, as `Option`'s `Persist` implementation
+/// expects. `ron` limits how deeply values can be nested.
+///
+/// ```
+/// # use ecstatic::persist::Value;
+/// # use ecstatic::watch::parse_ron;
+/// assert_eq!(
+///     parse_ron("Spawner(rate: 0.5, boss: Some(\"ogre\"), waves: [1, -2])"),
+///     Ok(Value::Map(vec![
+///         (
+///             "boss".to_string(),
+///             Value::List(vec![Value::Str("ogre".to_string())])
+///         ),
+///         ("rate".to_string(), Value::Float(0.5)),
+///         (
+///             "waves".to_string(),
+///             Value::List(vec![Value::UInt(1), Value::Int(-2)])
+///         ),
+///     ]))
+/// );
+/// ```
+#[cfg(feature = "ron")]
+pub fn parse_ron(text: &str) -> Result<Value, PersistError> {
+    let value: ron::Value =
+        ron::from_str(text).map_err(|e| PersistError::Malformed(e.to_string()))?;
+    from_ron(value)
+}
+
+#[cfg(feature = "ron")]
+fn from_ron(value: ron::Value) -> Result<Value, PersistError> {
+    use ron::value::Number;
+    Ok(match value {
+        ron::Value::Unit | ron::Value::Option(None) => Value::Unit,
+        ron::Value::Bool(b) => Value::Bool(b),
+        ron::Value::Char(c) => Value::Str(c.to_string()),
+        ron::Value::String(s) => Value::Str(s),
+        ron::Value::Number(Number::Integer(i)) if i < 0 => Value::Int(i),
+        ron::Value::Number(Number::Integer(i)) => Value::UInt(i as u64),
+        ron::Value::Number(Number::Float(f)) => Value::Float(f.get()),
+        ron::Value::Option(Some(x)) => Value::List(vec![from_ron(*x)?]),
+        ron::Value::Seq(items) => {
+            Value::List(items.into_iter().map(from_ron).collect::<Result<_, _>>()?)
+        }
+        ron::Value::Map(map) => Value::Map(
+            map.into_iter()
+                .map(|(k, v)| match k {
+                    ron::Value::String(k) => Ok((k, from_ron(v)?)),
+                    k => Err(PersistError::Malformed(format!(
+                        "map key {:?} isn't a string",
+                        k
+                    ))),
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+    })
+}